@@ -0,0 +1,65 @@
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+const DAY: u64 = 24 * 60 * 60;
+/// Upper bounds of the histogram buckets; the last bucket is open-ended.
+const BUCKETS: [(u64, &str); 3] = [(7 * DAY, "<1w"), (30 * DAY, "1w-1m"), (365 * DAY, "1m-1y")];
+
+/// Age distribution of a set of files, by modification time.
+#[derive(Debug, Serialize)]
+pub struct AgeStats {
+    /// Ages in seconds.
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+    /// Counts for `<1w`, `1w-1m`, `1m-1y` and `>1y`.
+    pub histogram: [usize; 4],
+}
+
+impl AgeStats {
+    /// Returns `None` if no file has a readable modification time.
+    pub fn of(files: &[PathBuf]) -> Option<Self> {
+        let now = SystemTime::now();
+        let mut ages = files.iter()
+            .filter_map(|e| fs::symlink_metadata(e).and_then(|m| m.modified()).ok())
+            .map(|e| now.duration_since(e).unwrap_or_default().as_secs())
+            .collect::<Vec<u64>>();
+        if ages.is_empty() {
+            return None;
+        }
+        ages.sort_unstable();
+        let mut histogram = [0; 4];
+        for age in &ages {
+            let bucket = BUCKETS.iter().position(|(limit, _)| age < limit).unwrap_or(3);
+            histogram[bucket] += 1;
+        }
+        Some(Self { min: ages[0], median: ages[ages.len() / 2], max: ages[ages.len() - 1], histogram })
+    }
+}
+
+/// Renders an age in its largest whole unit, e.g. `3d` or `5mo`.
+fn short_age(secs: u64) -> String {
+    let days = secs / DAY;
+    match secs {
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s if s < DAY => format!("{}h", s / (60 * 60)),
+        _ if days < 7 => format!("{}d", days),
+        _ if days < 60 => format!("{}w", days / 7),
+        _ if days < 365 => format!("{}mo", days / 30),
+        _ => format!("{}y", days / 365),
+    }
+}
+
+impl fmt::Display for AgeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let labels = BUCKETS.iter().map(|(_, e)| *e).chain(Some(">1y"));
+        let histogram = labels.zip(self.histogram)
+            .map(|(label, n)| format!("{}: {}", label, n))
+            .collect::<Vec<String>>();
+        write!(f, "min {}, median {}, max {} ({})", short_age(self.min), short_age(self.median),
+               short_age(self.max), histogram.join(", "))
+    }
+}
@@ -0,0 +1,537 @@
+use crate::error::{CliError, ErrorCode, EXIT_FAILED};
+use crate::history::RunRecord;
+use crate::interrupt::{interrupted, start_removing, EXIT_INTERRUPTED};
+use crate::remove::{remove_empty_dirs, remove_files, RemoveOptions};
+use crate::scan::{scan_dir, Expected, ExpectedSet, Mismatch, Scan, ScanOptions, UnsafePath};
+use crate::torrent::{check_geometry, geometry_error, is_local_path, overview, parse_torrent};
+use crate::paths::{aliases, PathRenderer, PathStyle};
+use crate::output::set_quiet;
+use crate::progress::ProgressMode;
+use crate::prompt::{confirm, interactive, EXIT_DECLINED};
+use crate::say;
+use crate::summary::RunStatus;
+use crate::warnings::{warn, Warning, WarningCode};
+use crate::{absolute_path, apply_folding, apply_renames, check_content, check_threshold,
+            check_torrent, held_back, print_in_use, print_unknown_age, print_unreadable,
+            log_paths, new_record, asked_entries, print_aliases, print_changes, print_empty_dirs, print_entries,
+            print_kind_counts, print_largest, print_kept, print_skipped, print_space_freed,
+            private_protections, request_recheck, save_record, scan_options, set_aside_collisions,
+            sort_changes, top, torrent_listing, unlisted_dirs, unsafe_path_warnings};
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use indicatif::{BinaryBytes, MultiProgress, ProgressBar, ProgressStyle};
+use inquire::Confirm;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use term_painter::Color::Red;
+use term_painter::ToStyle;
+
+pub struct BatchEntry {
+    pub torrent: PathBuf,
+    pub dir: PathBuf,
+}
+
+/// Reads `<torrent><TAB><dir>` lines, skipping blank lines and `#` comments.
+pub fn read_manifest(path: &Path) -> anyhow::Result<Vec<BatchEntry>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read manifest {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut entries = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (torrent, dir) = line.split_once('\t').ok_or_else(||
+            anyhow!("{}:{}: expected `<torrent><TAB><dir>`", path.display(), i + 1))?;
+        entries.push(BatchEntry {
+            torrent: match is_local_path(Path::new(torrent)) {
+                true => absolute_path(base.join(torrent))?,
+                false => PathBuf::from(torrent),
+            },
+            dir: absolute_path(base.join(dir))?,
+        });
+    }
+    Ok(entries)
+}
+
+struct Plan {
+    record: RunRecord,
+    scan: Scan,
+    missing: Vec<PathBuf>,
+    missing_size: u64,
+    mismatched: Vec<Mismatch>,
+    /// Whether all files of the torrent sit at the top level.
+    flat: bool,
+    /// Those of [`check_content`], printed with the plan.
+    warnings: Vec<Warning>,
+    /// [`overview`] of the torrent.
+    overview: String,
+    /// Inconsistencies in the torrent's piece geometry.
+    geometry: Vec<String>,
+    /// Why the labels of the torrent in the `--client` keep it from being cleaned.
+    held: Option<String>,
+    /// Files of the torrent left out for their paths.
+    unsafe_paths: Vec<UnsafePath>,
+}
+
+fn plan(entry: &BatchEntry, command: &str, matches: &ArgMatches, options: &ScanOptions,
+        progress: &ProgressBar) -> anyhow::Result<Plan> {
+    if !entry.dir.is_dir() {
+        return Err(CliError::new(ErrorCode::DirNotFound,
+            format!("Directory not found: {}", entry.dir.display())).with_path(&entry.dir).into());
+    }
+    progress.set_message("Parsing...");
+    let metainfo = parse_torrent(progress, &entry.torrent)?;
+    let options = &ScanOptions { private: private_protections(matches, &metainfo)?,
+                                 ..options.clone() };
+    let torrent = metainfo.meta;
+    let geometry = check_geometry(&torrent.info);
+    if matches.get_flag("strict-torrent") && !geometry.is_empty() {
+        return Err(geometry_error(&geometry).with_path(&entry.torrent).into());
+    }
+    let mut expected = Expected::from_torrent_with(&torrent, matches.get_flag("keep-padding"))?;
+    apply_renames(matches, &mut expected)?;
+    apply_folding(matches, &mut expected);
+    // The checks of clean and diff, as the entry would be planned alone
+    let warnings = check_content(matches, &entry.dir, &expected, command == "clean")?;
+    check_torrent(matches, &expected, command == "clean")?;
+    progress.set_message("Scanning...");
+    let mut scan = scan_dir(&entry.dir, &expected, options, progress)?;
+    let paths = PathRenderer::new(&entry.dir, PathStyle::Absolute);
+    print_unreadable(&scan.unreadable, &paths);
+    print_unknown_age(&scan, &paths);
+    print_in_use(&scan, &paths);
+    set_aside_collisions(matches, &entry.dir, &expected, &mut scan, &paths);
+    let (mut missing, missing_size) = expected.missing(&entry.dir);
+    let mut record = new_record(command, &torrent, &entry.dir);
+    let held = match command {
+        "clean" => held_back(matches, &record)?,
+        _ => None,
+    };
+    if command == "clean" && held.is_none() {
+        check_threshold(matches, &scan, expected.file_count() - missing.len())
+            .map_err(|e| e.with_path(&entry.dir))?;
+    }
+    let mut mismatched = expected.mismatched(&entry.dir);
+    sort_changes(matches, &entry.dir, &expected, &mut missing, &mut mismatched);
+    record.extras = scan.removal_count();
+    record.extras_size = scan.rm_size;
+    record.missing = missing.len();
+    Ok(Plan { record, scan, missing, missing_size, mismatched, flat: expected.is_flat(), warnings,
+              overview: overview(&torrent.info), geometry, held,
+              unsafe_paths: expected.unsafe_paths().to_vec() })
+}
+
+fn execute(plan: &Plan, options: &ScanOptions, revalidate: bool, paths: &PathRenderer,
+           progress: &ProgressBar) -> anyhow::Result<usize> {
+    let files = remove_files(&plan.scan.old_files, revalidate.then_some(&plan.scan), paths, None,
+                             None, RemoveOptions::default(), progress)?;
+    let mut count = match interrupted() {
+        true => removed_files(&plan.scan).count(),
+        false => plan.scan.old_files.len() - files.changed.len() - files.failed.len(),
+    };
+    // Directories still holding a file that failed stay, and the others go as usual
+    let mut error = files.error(paths);
+    if options.include_empty_dir {
+        let removal = remove_empty_dirs(&plan.scan, paths, None, RemoveOptions::default(),
+                                        progress);
+        error = error.or_else(|| removal.error(paths));
+        count += removal.removed;
+    }
+    match error {
+        Some(err) => Err(err.into()),
+        None => Ok(count),
+    }
+}
+
+/// The extras of `scan` that no longer exist, which after Ctrl-C are the ones removed.
+fn removed_files(scan: &Scan) -> impl Iterator<Item = &PathBuf> {
+    scan.old_files.iter().filter(|e| fs::symlink_metadata(e).is_err())
+}
+
+/// Runs `f` over `items` on up to `jobs` threads, returning the results in item order.
+fn run_parallel<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(usize, &T) -> R + Sync) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results = items.iter().map(|_| Mutex::new(None)).collect::<Vec<Mutex<Option<R>>>>();
+    thread::scope(|s| {
+        for _ in 0..jobs.min(items.len()) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else { break };
+                let result = f(i, item);
+                *results[i].lock().expect("not poisoned") = Some(result);
+            });
+        }
+    });
+    results.into_iter()
+        .map(|e| e.into_inner().expect("not poisoned").expect("every item processed"))
+        .collect()
+}
+
+fn job_bar(mode: ProgressMode, multi: &MultiProgress, prefix: String) -> anyhow::Result<ProgressBar> {
+    let bar = mode.add(multi, ProgressBar::new_spinner());
+    bar.set_style(ProgressStyle::default_spinner()
+        .tick_chars("|/-\\ ")
+        .template("{spinner:.green} {prefix} [{elapsed_precise}] {msg}")?);
+    bar.set_prefix(prefix);
+    bar.enable_steady_tick(Duration::from_millis(100));
+    Ok(bar)
+}
+
+fn label(entry: &BatchEntry) -> String {
+    format!("{} -> {}", entry.torrent.file_name().unwrap_or_default().to_string_lossy(),
+            entry.dir.display())
+}
+
+/// What happens to the remaining entries once one fails, set by `--on-error`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OnError {
+    Continue,
+    Abort,
+    Prompt,
+}
+
+impl OnError {
+    fn parse(policy: &str) -> Self {
+        match policy {
+            "abort" => Self::Abort,
+            "prompt" => Self::Prompt,
+            _ => Self::Continue,
+        }
+    }
+
+    /// Whether to go on with the remaining entries after `failed` failed with `err`.
+    fn keep_going(self, failed: &str, err: &anyhow::Error) -> bool {
+        match self {
+            Self::Continue => true,
+            Self::Abort => false,
+            // Without a terminal to ask on, the remaining entries are not risked
+            Self::Prompt => interactive() && Confirm::new(format!("{} failed: {:#}. Continue \
+                    with the remaining entries?", failed, err).as_str())
+                .with_default(true).prompt().unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EntryStatus {
+    Ok,
+    Failed,
+    /// Not processed because of `--on-error` or a declined confirmation.
+    Skipped,
+}
+
+/// One entry of the `--output json` document of a batch run.
+#[derive(Serialize)]
+struct EntryReport {
+    torrent: PathBuf,
+    dir: PathBuf,
+    status: EntryStatus,
+    error: Option<String>,
+    held_back: Option<String>,
+    extras: usize,
+    extras_size: u64,
+    missing: usize,
+    removed: usize,
+    removed_size: u64,
+}
+
+impl EntryReport {
+    fn new(entry: &BatchEntry) -> Self {
+        Self { torrent: entry.torrent.clone(), dir: entry.dir.clone(), status: EntryStatus::Skipped,
+               error: None, held_back: None, extras: 0, extras_size: 0, missing: 0, removed: 0,
+               removed_size: 0 }
+    }
+
+    fn update(&mut self, record: &RunRecord) {
+        self.extras = record.extras;
+        self.extras_size = record.extras_size;
+        self.missing = record.missing;
+        self.removed = record.removed;
+        self.removed_size = record.removed_size;
+    }
+
+    fn fail(&mut self, err: &anyhow::Error) {
+        self.status = EntryStatus::Failed;
+        self.error = Some(format!("{:#}", err));
+    }
+}
+
+#[derive(Serialize)]
+struct BatchReport<'a> {
+    command: &'a str,
+    status: RunStatus,
+    exit_code: i32,
+    entries: &'a [EntryReport],
+}
+
+pub fn run_batch(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let manifest = absolute_path(matches.get_one::<PathBuf>("manifest").expect("required"))?;
+    let entries = read_manifest(&manifest)?;
+    let jobs = *matches.get_one::<u16>("jobs").expect("default") as usize;
+    let clean = matches.get_flag("clean");
+    let revalidate = !matches.get_flag("no-revalidate");
+    let no_confirm = matches.get_flag("no-confirm");
+    let on_error = OnError::parse(matches.get_one::<String>("on-error").expect("default"));
+    let json = matches.get_one::<String>("output").is_some_and(|e| e == "json");
+    set_quiet(json || matches.get_flag("quiet"));
+    let mut options = scan_options(matches)?;
+    // A torrent file may well sit in the directory of another entry, if not its own
+    options.protected.extend(entries.iter().map(|e| e.torrent.clone()));
+    let aliases = aliases(&entries.iter().map(|e| e.dir.as_path()).collect::<Vec<&Path>>());
+    let top = top(matches);
+    let total = entries.len();
+
+    // Jobs sharing a directory would each see the other torrent's files as extras
+    if clean {
+        let mut seen = HashMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let canonical = entry.dir.canonicalize().unwrap_or_else(|_| entry.dir.clone());
+            if let Some(first) = seen.insert(canonical, i) {
+                return Err(anyhow!("Entries {} and {} both clean {}; a directory can only be \
+                    cleaned by one torrent per batch", first + 1, i + 1, entry.dir.display()));
+            }
+        }
+    }
+
+    let mode = ProgressMode::from_matches(matches);
+    let multi = mode.multi();
+    let command = if clean { "clean" } else { "diff" };
+    let plans = run_parallel(&entries, jobs, |i, entry| {
+        let bar = job_bar(mode, &multi, format!("[{}/{}]", i + 1, total))?;
+        let result = plan(entry, command, matches, &options, &bar);
+        bar.finish_and_clear();
+        multi.remove(&bar);
+        result
+    });
+    say!("Scanned {} torrents.\n", total);
+
+    let mut reports = entries.iter().map(EntryReport::new).collect::<Vec<EntryReport>>();
+    let mut failures = Vec::new();
+    let mut pending = Vec::new();
+    // Files of the pending plans their listings left out
+    let mut unlisted = 0;
+    let mut stopped = false;
+    for (i, (entry, result)) in entries.iter().zip(plans).enumerate() {
+        say!("[{}/{}] {}", i + 1, total, label(entry));
+        let plan = match result {
+            Ok(plan) => plan,
+            Err(err) => {
+                say!("{} {:#}\n", Red.paint("Failed:"), err);
+                reports[i].fail(&err);
+                let last = i + 1 == total && (!clean || pending.is_empty());
+                stopped = !last && !on_error.keep_going(&label(entry), &err);
+                failures.push((i, err));
+                if stopped {
+                    say!("Stopping; the remaining entries are skipped.");
+                    break;
+                }
+                continue;
+            }
+        };
+        reports[i].update(&plan.record);
+        say!("{}", plan.overview);
+        for problem in &plan.geometry {
+            say!("{}", warn(WarningCode::Geometry,
+                            format!("suspicious torrent geometry: {}", problem)));
+        }
+        for warning in unsafe_path_warnings(&plan.unsafe_paths, &entry.torrent) {
+            say!("{}", warning);
+        }
+        for warning in &plan.warnings {
+            say!("{}", warning);
+        }
+        let mut listing = torrent_listing(matches, &entry.dir, plan.flat);
+        listing.paths = listing.paths.with_alias(&aliases[i]);
+        let usage = plan.scan.disk_usage();
+        listing.hardlinked = usage.hardlinked.clone();
+        if clean {
+            if plan.scan.removal_count() == 0 {
+                say!("No matching entries found.");
+            } else if plan.scan.old_files.is_empty() {
+                print_empty_dirs(&plan.scan, &listing);
+                unlisted += unlisted_dirs(&plan.scan, &listing);
+                print_kept(&plan.scan, &listing);
+            } else {
+                print_entries(&plan.scan.old_files, &listing);
+                print_empty_dirs(&plan.scan, &listing);
+                unlisted += listing.unlisted(plan.scan.old_files.len())
+                    + unlisted_dirs(&plan.scan, &listing);
+                print_aliases(&plan.scan, &listing);
+                print_kept(&plan.scan, &listing);
+                say!("Remove files: {} ({})", Red.paint(BinaryBytes(plan.scan.rm_size)),
+                         plan.scan.old_files.len());
+                print_space_freed(&usage);
+                print_kind_counts(&plan.scan.old_files);
+                print_largest(matches, &plan.scan.old_files, top, &listing)?;
+            }
+        } else if print_changes(&plan.scan, &plan.missing, plan.missing_size,
+                                    &plan.mismatched, &listing) {
+            print_largest(matches, &plan.scan.old_files, top, &listing)?;
+        } else {
+            say!("No matching entries found.");
+        }
+        print_skipped(matches, &plan.scan);
+        if let Some(reason) = &plan.held {
+            say!("Skipped: {} is not cleaned, as {}.", plan.record.torrent, reason);
+        }
+        say!();
+        if let Some(reason) = plan.held {
+            reports[i].held_back = Some(reason);
+            save_record(matches, &plan.record);
+        } else if clean && (!plan.scan.old_files.is_empty() || options.include_empty_dir) {
+            pending.push((i, plan));
+        } else {
+            reports[i].status = EntryStatus::Ok;
+            save_record(matches, &plan.record);
+        }
+    }
+
+    let mut declined = false;
+    if !pending.is_empty() && !stopped {
+        let count = pending.iter().map(|(_, plan)| plan.scan.old_files.len()).sum::<usize>();
+        let dirs = pending.iter()
+            .map(|(_, plan)| plan.scan.empty_dirs.len() + plan.scan.emptied_dirs.len())
+            .sum::<usize>();
+        let size = pending.iter().map(|(_, plan)| plan.scan.rm_size).sum::<u64>();
+        say!("Remove files in total: {} ({}) across {} torrents",
+                 Red.paint(BinaryBytes(size)), count, pending.len());
+        if dirs > 0 {
+            say!("Remove directories in total: {}", Red.paint(dirs));
+        }
+        let question = format!("Delete {}?", asked_entries(count, dirs, unlisted));
+        if !no_confirm && count + dirs > 0 && !confirm(matches, &question, true) {
+            declined = true;
+        }
+    }
+    let stop = AtomicBool::new(stopped || declined);
+    if !stop.load(Ordering::Relaxed) {
+        start_removing();
+    }
+
+    let run = |(i, plan): &(usize, Plan)| -> Option<anyhow::Result<usize>> {
+        if stop.load(Ordering::Relaxed) || interrupted() {
+            return None;
+        }
+        let result = (|| {
+            let bar = mode.add(&multi, ProgressBar::new(plan.scan.old_files.len() as u64));
+            bar.set_style(ProgressStyle::default_bar()
+                .template("{prefix} [{wide_bar:.cyan/blue}] {pos}/{len}\n{msg}")?);
+            bar.set_prefix(format!("[{}/{}]", i + 1, total));
+            let entry = &entries[*i];
+            let paths = log_paths(matches, &entry.dir).with_alias(&aliases[*i]);
+            let result = execute(plan, &options, revalidate, &paths, &bar);
+            bar.finish_and_clear();
+            multi.remove(&bar);
+            result
+        })();
+        if result.is_err() && on_error == OnError::Abort {
+            stop.store(true, Ordering::Relaxed);
+        }
+        Some(result)
+    };
+    // Asking after each failure needs the entries removed one at a time
+    let results = match on_error {
+        OnError::Prompt => {
+            let mut results = Vec::new();
+            for (n, item) in pending.iter().enumerate() {
+                let result = run(item);
+                if let Some(Err(err)) = result.as_ref().filter(|_| n + 1 < pending.len()) {
+                    let failed = format!("[{}/{}]", item.0 + 1, total);
+                    if !on_error.keep_going(&failed, err) {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+                results.push(result);
+            }
+            results
+        }
+        _ => run_parallel(&pending, jobs, |_, item| run(item)),
+    };
+    for ((i, plan), result) in pending.iter_mut().zip(results) {
+        let i = *i;
+        let Some(result) = result else {
+            save_record(matches, &plan.record);
+            continue;
+        };
+        match &result {
+            Ok(count) => {
+                plan.record.removed = *count;
+                plan.record.removed_size = match interrupted() {
+                    true => removed_files(&plan.scan).filter_map(|e| plan.scan.stamps.get(e))
+                        .map(|e| e.size).sum(),
+                    false => plan.scan.rm_size,
+                };
+            }
+            Err(_) => plan.record.failures = 1,
+        }
+        save_record(matches, &plan.record);
+        reports[i].update(&plan.record);
+        match result {
+            Ok(count) => {
+                reports[i].status = EntryStatus::Ok;
+                say!("[{}/{}] {} entries removed.", i + 1, total, count);
+                request_recheck(matches, &plan.record);
+            }
+            Err(err) => {
+                say!("[{}/{}] {} {:#}", i + 1, total, Red.paint("Failed:"), err);
+                reports[i].fail(&err);
+                failures.push((i, err));
+            }
+        }
+    }
+    let skipped = reports.iter().filter(|e| matches!(e.status, EntryStatus::Skipped)).count();
+
+    if json {
+        let status = match (failures.is_empty(), declined || interrupted()) {
+            (false, _) => RunStatus::Failed,
+            (true, true) => RunStatus::Aborted,
+            (true, false) => RunStatus::Ok,
+        };
+        let exit_code: i32 = match () {
+            _ if interrupted() => EXIT_INTERRUPTED.into(),
+            _ if !failures.is_empty() => EXIT_FAILED.into(),
+            _ if declined => EXIT_DECLINED.into(),
+            _ => 0,
+        };
+        println!("{}", serde_json::to_string(&BatchReport { command, status, exit_code, entries: &reports })?);
+        return Ok(ExitCode::from(exit_code as u8));
+    }
+    if interrupted() {
+        let removed = reports.iter().map(|e| e.removed).sum::<usize>();
+        return Err(CliError::new(ErrorCode::Interrupted, format!(
+            "Interrupted: {} entries removed, {} batch entries not cleaned; run batch again for \
+             the rest", removed, skipped)).into());
+    }
+    if !failures.is_empty() {
+        failures.sort_by_key(|(i, _)| *i);
+        println!();
+        println!("Failures:");
+        let mut error = CliError::new(ErrorCode::BatchFailed,
+            format!("{} of {} batch entries failed", failures.len(), total));
+        for (i, err) in &failures {
+            println!("  [{}/{}] {}: {:#}", i + 1, total, label(&entries[*i]), err);
+            error = error.with_entry(&entries[*i].dir, format!("{:#}", err));
+        }
+        if skipped > 0 {
+            println!("{} entries were skipped.", skipped);
+        }
+        return Err(error.into());
+    }
+
+    match declined {
+        true => return Ok(ExitCode::from(EXIT_DECLINED)),
+        false if skipped > 0 => println!("{} entries were skipped.", skipped),
+        false => println!("Operation completed successfully."),
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,98 @@
+use crate::kind::{extension, FileKind};
+use crate::scan::Scan;
+use crate::{path_colored, say, Listing};
+use indicatif::BinaryBytes;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use term_painter::ToStyle;
+
+/// What `--summary` totals the extra files by.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Dir(PathBuf),
+    /// The lowercased extension, if the name has one.
+    Ext(Option<String>),
+}
+
+/// A line of a `--summary` table: the extra files it totals, and their size.
+struct Total {
+    key: Key,
+    count: usize,
+    size: u64,
+    /// The type of the first file counted, which colors an extension.
+    kind: FileKind,
+}
+
+/// Totals the extra files of `scan` by `keys`, largest first and then by key so the order is
+/// stable.
+fn totals<'a>(scan: &'a Scan, keys: impl Fn(&'a Path) -> Vec<Key>) -> Vec<Total> {
+    let mut totals = HashMap::<Key, Total>::new();
+    for file in &scan.old_files {
+        let size = scan.stamps.get(file).map_or(0, |e| e.size);
+        for key in keys(file) {
+            let total = totals.entry(key.clone())
+                .or_insert(Total { key, count: 0, size: 0, kind: FileKind::of(file) });
+            total.count += 1;
+            total.size += size;
+        }
+    }
+    let mut totals = totals.into_values().collect::<Vec<Total>>();
+    totals.sort_by_cached_key(|e| (Reverse(e.size), e.key.label()));
+    totals
+}
+
+impl Key {
+    fn label(&self) -> String {
+        match self {
+            Key::Dir(path) => path.to_string_lossy().into_owned(),
+            Key::Ext(Some(ext)) => format!(".{}", ext),
+            Key::Ext(None) => "(no extension)".to_string(),
+        }
+    }
+}
+
+/// Prints the first `top` of `totals` under `title`, and what the others add up to as `more` of
+/// them if they are disjoint.
+fn print_table(title: &str, totals: &[Total], top: usize, more: Option<&str>, listing: &Listing) {
+    if totals.is_empty() {
+        return;
+    }
+    let shown = &totals[..top.min(totals.len())];
+    let width = shown.iter().map(|e| e.count.to_string().len()).max().unwrap_or(0);
+    say!();
+    say!("{}", title);
+    for total in shown {
+        let (size, count) = (BinaryBytes(total.size).to_string(), total.count);
+        match &total.key {
+            Key::Dir(path) => say!("{:>10}  ({:>width$})  {}", size, count,
+                                   path_colored(path, listing)),
+            key => say!("{:>10}  ({:>width$})  {}", size, count,
+                        total.kind.color().paint(key.label())),
+        }
+    }
+    let rest = &totals[shown.len()..];
+    if let Some(more) = more.filter(|_| !rest.is_empty()) {
+        let size = BinaryBytes(rest.iter().map(|e| e.size).sum::<u64>()).to_string();
+        say!("{:>10}  ({:>width$})  {} more {}", size, rest.iter().map(|e| e.count).sum::<usize>(),
+             rest.len(), more);
+    }
+}
+
+/// Prints where the extras of `scan` take their space: the `top` directories below `dir`
+/// holding the most of them by size, each counting everything beneath it, and their bytes and
+/// count per extension.
+pub fn print_breakdown(dir: &Path, scan: &Scan, top: usize, listing: &Listing) {
+    if top == 0 || scan.old_files.is_empty() {
+        return;
+    }
+    let dirs = totals(scan, |file| file.ancestors().skip(1).take_while(|e| *e != dir)
+        .filter(|e| e.starts_with(dir))
+        .map(|e| Key::Dir(e.to_path_buf()))
+        .collect());
+    // A directory also counts those inside it, so the others do not add up
+    print_table(&format!("Top {} directories by extras:", top.min(dirs.len())), &dirs, top, None,
+                listing);
+    let exts = totals(scan, |file| vec![Key::Ext(extension(file))]);
+    print_table("Extras by extension:", &exts, top, Some("extensions"), listing);
+}
@@ -0,0 +1,901 @@
+use clap::{arg, command, value_parser, Arg, ArgAction, ArgMatches, Command};
+use clap::error::ErrorKind;
+use crate::client::ClientKind;
+use crate::config::Config;
+use crate::kind::parse_ext;
+use crate::warnings::{warn, WarningCode};
+use clap_complete::Shell;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+fn torrent_arg() -> Arg {
+    Arg::new("file")
+        .help("Specify the .torrent file, - to read it from stdin or an http(s) URL to download it from, or the magnet URI, .magnet file or info hash of a torrent, whose metadata is fetched from peers unless its .torrent file was parsed before")
+        .required(true)
+        .value_parser(value_parser!(PathBuf))
+}
+
+/// `--manifest` with the positional arguments of diff and clean, of which only the directory is
+/// given along with it; clap then reads it as the first one.
+fn manifest_args() -> [Arg; 5] {
+    [arg!(--manifest <PATH> "Expect the files of a list written by list --output tsv or json instead of a torrent's; give the directory alone")
+         .required(false)
+         .value_parser(value_parser!(PathBuf)),
+     arg!(--"torrents-dir" <PATH> "Expect the files of every .torrent in PATH, e.g. qBittorrent's BT_backup, each under its name; give the downloads directory alone")
+         .required(false)
+         .value_parser(value_parser!(PathBuf))
+         .conflicts_with("manifest"),
+     arg!(--"transmission-config" <PATH> "Expect the files of every torrent Transmission keeps in PATH, e.g. ~/.config/transmission-daemon, under the paths its resume files record for those renamed in it; give the download directory alone")
+         .required(false)
+         .value_parser(value_parser!(PathBuf))
+         .conflicts_with_all(["manifest", "torrents-dir"]),
+     torrent_arg().required(false)
+         .required_unless_present_any(["manifest", "torrents-dir", "transmission-config"]),
+     dir_arg().required(false)
+         .required_unless_present_any(["manifest", "torrents-dir", "transmission-config"])]
+}
+
+/// Torrents sharing the directory with the first, e.g. cross-seeded ones. Its conflicts with the
+/// other sources are declared here, as some subcommands take [`manifest_args`] without it.
+fn also_arg() -> Arg {
+    arg!(--also <FILE> "Also expect the files of this torrent, given like the first; for cross-seeded torrents sharing the directory (repeatable)")
+        .required(false)
+        .action(ArgAction::Append)
+        .value_parser(value_parser!(PathBuf))
+        .conflicts_with_all(["manifest", "torrents-dir", "transmission-config"])
+}
+
+/// Hashing the extras that may be missing files of the torrent under another path.
+fn verify_renames_arg() -> Arg {
+    arg!(--"verify-renames" "Only take an extra of the size of a missing file for that file moved if the pieces wholly inside it match the torrent's piece hashes")
+        .required(false)
+        .action(ArgAction::SetTrue)
+}
+
+/// Hashing the extras that may be copies of files of the torrent.
+fn verify_duplicates_arg() -> Arg {
+    arg!(--"verify-duplicates" "Tag the extras whose content is identical to a file of the torrent that is present as its duplicate, safe to remove, hashing those of the same size as one")
+        .required(false)
+        .action(ArgAction::SetTrue)
+}
+
+/// Where the extras take their space, after the largest ones.
+fn summary_arg() -> Arg {
+    arg!(--summary "After the largest extras, also list the --top directories holding the most extras by size and the extras' size and count per extension")
+        .required(false)
+        .action(ArgAction::SetTrue)
+}
+
+/// How a removal is confirmed when `--no-confirm` does not skip it.
+fn prompt_args() -> [Arg; 2] {
+    [arg!(--"assume-no" "Answer no to the confirmation: list what would be removed, remove nothing and exit with 1")
+         .required(false)
+         .conflicts_with("no-confirm")
+         .action(ArgAction::SetTrue),
+     arg!(--"prompt-timeout" <DURATION> "Take no answer to the confirmation within DURATION (e.g. 30s) for no")
+         .required(false)
+         .conflicts_with_all(["no-confirm", "assume-no"])
+         .value_parser(humantime::parse_duration)]
+}
+
+/// The limits a removal must stay within unless `--force-threshold` is given.
+fn threshold_args() -> [Arg; 3] {
+    [arg!(--"max-delete-ratio" <RATIO> "Refuse to remove when more than this share of the files found, from 0 to 1, would go")
+         .required(false)
+         .value_parser(parse_ratio)
+         .default_value("0.5"),
+     arg!(--"max-delete-size" <SIZE> "Refuse to remove when more than SIZE (e.g. 100G) would go")
+         .required(false)
+         .value_parser(parse_size),
+     arg!(--"force-threshold" "Remove even beyond --max-delete-ratio or --max-delete-size, or when none of the torrent's files are in the directory")
+         .required(false)
+         .action(ArgAction::SetTrue)]
+}
+
+/// The labels and categories in the `--client` that decide which torrents are cleaned.
+fn label_args() -> [Arg; 3] {
+    [arg!(--"skip-label" <NAME> "Report but never clean the torrents carrying this label (tag in qBittorrent) in the --client; repeatable")
+         .required(false)
+         .action(ArgAction::Append),
+     arg!(--"skip-category" <NAME> "Report but never clean the torrents in this qBittorrent category; repeatable")
+         .required(false)
+         .action(ArgAction::Append),
+     arg!(--"only-label" <NAME> "Report but never clean the torrents carrying none of the labels given with this option; repeatable")
+         .required(false)
+         .action(ArgAction::Append)]
+}
+
+fn parse_ratio(ratio: &str) -> Result<f64, String> {
+    match ratio.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        _ => Err(format!("expected a number from 0 to 1: {}", ratio)),
+    }
+}
+
+/// Parses a `--map` rename, `OLD=NEW` with both plain names.
+fn parse_rename(rename: &str) -> Result<(String, String), String> {
+    let plain = |e: &str| !e.is_empty() && e != "." && e != ".." && !e.contains(['/', '\\']);
+    match rename.split_once('=') {
+        Some((old, new)) if plain(old) && plain(new) => Ok((old.to_string(), new.to_string())),
+        _ => Err(format!("expected OLD=NEW with two top-level names: {}", rename)),
+    }
+}
+
+/// The directory of diff and clean, which may be given again for a torrent spread over several.
+fn several_dirs(dir: Arg) -> Arg {
+    dir.help("Specify the directory storing torrent contents; repeat it for contents spread over several, e.g. across disks, of which any may hold each file")
+        .num_args(1..)
+        .action(ArgAction::Append)
+}
+
+/// With several directories, which copy of a file of the torrent in more than one is kept.
+fn dedupe_arg() -> Arg {
+    arg!(--"dedupe-across-roots" "With several directories, count the copies of a file of the torrent in all but the first directory holding it as extras")
+        .required(false)
+        .action(ArgAction::SetTrue)
+}
+
+fn dir_arg() -> Arg {
+    Arg::new("dir")
+        .help("Specify the directory storing torrent contents")
+        .required(true)
+        .value_parser(value_parser!(PathBuf))
+}
+
+/// Listing every entry to remove in a file, and how.
+fn report_args() -> [Arg; 2] {
+    [arg!(--report <PATH> "Write every entry planned for removal to PATH, one path relative to the directory per line, however many the listings show")
+         .required(false)
+         .value_parser(value_parser!(PathBuf)),
+     arg!(--"report-format" <FORMAT> "Format of --report: paths, or ndjson for a JSON object per entry with its action, path, size and kind, and a last one with the totals and the info hash")
+         .required(false)
+         .requires("report")
+         .value_parser(["paths", "ndjson"])
+         .default_value("paths")]
+}
+
+/// The exit codes of `clean`, which tell a script what became of the run.
+const CLEAN_EXIT_STATUS: &str = "\
+Exit status:
+  0    Removed what was planned, or found nothing to remove
+  1    Removed nothing: the confirmation was declined
+  2    Failed with an error, before removing anything or because entries could not be removed
+  3    With --deny-warnings, went through but printed warnings
+  130  Interrupted";
+
+/// The exit codes of `diff`, the same as `diff(1)`'s for errors.
+const DIFF_EXIT_STATUS: &str = "\
+Exit status:
+  0    Compared; with --check, nothing to remove and the torrent is complete
+  8    With --check, there are entries to remove
+  9    With --check, nothing to remove but entries outside the torrent's scope were found
+  10   With --check, nothing to remove but files of the torrent are missing or differ in size
+  2    Failed: an error, such as an unreadable torrent or directory
+  3    With --deny-warnings, compared but printed warnings";
+
+/// The exit codes of `verify`.
+const VERIFY_EXIT_STATUS: &str = "\
+Exit status:
+  0    Every file of the torrent is intact
+  1    Files are corrupt, missing, of another size or unreadable, even once removed
+  2    Failed with an error, such as an unreadable torrent or a v2-only one
+  3    With --deny-warnings, every file is intact but warnings were printed";
+
+/// Parses a byte count with an optional binary unit, e.g. `1500`, `64K`, `1.5G` or `2GiB`.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let split = size.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number = number.parse::<f64>().map_err(|_| format!("invalid size: {}", size))?;
+    let shift = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("unknown size unit: {}", unit)),
+    };
+    Ok((number * (1u64 << shift) as f64) as u64)
+}
+
+pub fn build_cli() -> Command {
+    command!()
+        .arg_required_else_help(true)
+        .arg(arg!(--schema "Print the JSON Schema of the JSON documents this tool emits and exit")
+            .required(false)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--config <PATH> "Read default options from this TOML file instead of config.toml in the user's config directory")
+            .required(false)
+            .global(true)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"print-config" "Print the options in effect, with whether the command line, the config file or their default set them, and exit")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(-s --surface "Take other files in the root directory into account")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(-d --"empty-dir" "Include empty directories")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"no-scope-notice" "Do not report entries outside the torrent's scope")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(-q --quiet "Print only errors and the outcome: diff prints nothing unless there are entries to remove, clean only how many it removed")
+            .required(false)
+            .global(true)
+            .conflicts_with("verbose")
+            .action(ArgAction::SetTrue))
+        .arg(arg!(-v --verbose "Explain the run on stderr: -v adds each entry removed, -vv why each entry found is planned for removal or left alone")
+            .required(false)
+            .global(true)
+            .action(ArgAction::Count))
+        .arg(arg!(--"log-file" <PATH> "Append a timestamped log of the run to PATH, with everything -vv explains whatever the verbosity")
+            .required(false)
+            .global(true)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--color <WHEN> "Color the output: auto does when stdout is a terminal and NO_COLOR is not set, or when CLICOLOR_FORCE is; uncolored listings tag entries with their type")
+            .required(false)
+            .global(true)
+            .value_parser(["auto", "always", "never"])
+            .default_value("auto"))
+        .arg(arg!(--"no-autodetect" "Scan the directory as given, holding the torrent's files directly without its root folder, even if it holds none of the torrent's top-level entries while the torrent's directory inside it, or the one it is inside, does")
+            .visible_alias("flat")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--unsorted "List entries in the order they are found instead of sorting them by path")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--sort <KEY> "Order of the extras and empty directories, and of the missing and wrong-size files of diff: by path, by the size the summary counts smallest first, or by modification time oldest first, entries alike by path; removal goes deepest first whatever the order")
+            .required(false)
+            .global(true)
+            .conflicts_with("unsorted")
+            .value_parser(["path", "size", "mtime"])
+            .default_value("path"))
+        .arg(arg!(--reverse "Turn the --sort order around")
+            .required(false)
+            .global(true)
+            .conflicts_with("unsorted")
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"prune-dir" <GLOB> "Do not descend into directories matching GLOB by name, or by relative path if it has a /; their contents are never read, counted or removed (repeatable)")
+            .required(false)
+            .global(true)
+            .action(ArgAction::Append))
+        .arg(arg!(--exclude <PATTERN> "Never list or remove entries matching this gitignore-style glob: by name without a /, by relative path with one, directories only with a trailing /, everything inside a matching directory included (repeatable)")
+            .required(false)
+            .global(true)
+            .action(ArgAction::Append))
+        .arg(arg!(--"no-ignore-file" "Do not read the .torrentcleanerignore patterns at the root of the directory, which otherwise exclude entries along with --exclude")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"max-depth" <N> "Walk at most N levels below the directory; a deeper directory not part of the torrent is an extra as a whole unless it holds an entry that is left alone, and one of the torrent's is left unexamined. 1 only looks at the top-level entries")
+            .required(false)
+            .global(true)
+            .value_parser(value_parser!(u16).range(1..)))
+        .arg(arg!(--"one-file-system" "Stay on the file system of the directory: directories on another one, such as mount points, are never read, counted or removed")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--threads <N> "Walk the directory with N threads; defaults to the number of logical CPUs, and 1 suits spinning disks where parallel reads are slower")
+            .required(false)
+            .global(true)
+            .value_parser(value_parser!(u16).range(1..)))
+        .arg(arg!(--"keep-from" <FILE> "Never list or remove the paths in FILE, one per line relative to the directory, nor anything inside them; blank lines and # comments are ignored")
+            .required(false)
+            .global(true)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"include-snapshots" "Scan snapshot and recycle areas (.zfs, .snapshots, .snapshot, #snapshot, .recycle, #recycle, @Recycle) like other directories instead of leaving them unread; nothing inside them is removed either way")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"touch-moved" "Give files moved by clean --stage or --move-to, restore or a duplicate merge the current time instead of keeping their modification time")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--bwlimit <RATE> "Copy at most RATE bytes per second (e.g. 20M) when moving files to another file system")
+            .required(false)
+            .global(true)
+            .value_parser(parse_size))
+        .arg(arg!(--"no-revalidate" "Remove or stage extras without first checking that their size and modification time are still those the scan found")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"clean-droppings" "Also remove partial and resume files torrent clients left for files no longer in the torrent (.!qB, .part, .resume, ...)")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"remove-incomplete" "Also remove the downloads in progress of files of the torrent: their partial files (.!qB, .part, ...) and those in qBittorrent's .unwanted folders")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"remove-mismatched" "Also remove files of the torrent whose size differs from the one it declares, so the client downloads them again")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"min-size" <SIZE> "Leave extra files smaller than SIZE (e.g. 100M, 1.5G or plain bytes) alone")
+            .required(false)
+            .global(true)
+            .value_parser(parse_size))
+        .arg(arg!(--"max-size" <SIZE> "Leave extra files larger than SIZE (e.g. 100M, 1.5G or plain bytes) alone")
+            .required(false)
+            .global(true)
+            .value_parser(parse_size))
+        .arg(arg!(--"older-than" <DURATION> "Leave extra files alone that were modified less than DURATION (e.g. 7d, 24h, 30m) ago, such as ones another tool is still writing")
+            .required(false)
+            .global(true)
+            .value_parser(humantime::parse_duration))
+        .arg(arg!(--"newer-than" <DURATION> "Leave extra files alone that were modified more than DURATION (e.g. 7d, 24h, 30m) ago")
+            .required(false)
+            .global(true)
+            .value_parser(humantime::parse_duration))
+        .arg(arg!(--"skip-hardlinked" "Leave extra files alone that have further links outside the removal, such as into a seeding pool, since removing them frees no space")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"skip-errors" "Go on past entries that cannot be read, such as directories without permission, leaving them alone, instead of failing")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--symlinks <POLICY> "What to do with symbolic links: keep them, remove them like other extras (the link, never its target), or follow them to what they point to")
+            .required(false)
+            .global(true)
+            .value_parser(["keep", "remove", "follow"])
+            .default_value("remove"))
+        .arg(arg!(--junk <MODE> "What to do with junk, the files systems and sync tools leave around (.DS_Store, Thumbs.db, desktop.ini, .stfolder, ...) and everything inside such directories: keep it, remove it like other extras, or remove only junk")
+            .required(false)
+            .global(true)
+            .value_parser(["keep", "remove", "only"])
+            .default_value("remove"))
+        .arg(arg!(--"junk-pattern" <GLOB> "Also count files and directories named like GLOB as junk, in any directory (repeatable)")
+            .required(false)
+            .global(true)
+            .action(ArgAction::Append))
+        .arg(arg!(--map <RENAME> "Expect the files under the torrent's top-level folder OLD under NEW instead, given as OLD=NEW, for folders renamed on disk (repeatable)")
+            .required(false)
+            .global(true)
+            .action(ArgAction::Append)
+            .value_parser(parse_rename))
+        .arg(arg!(--"deletable-ext" <EXT> "Only ever remove extra files with these extensions (repeatable or comma-separated; e.g. rar,nfo,sample.mkv), leaving every other extra for manual review")
+            .required(false)
+            .global(true)
+            .action(ArgAction::Append)
+            .value_delimiter(',')
+            .value_parser(parse_ext))
+        .arg(arg!(--"keep-padding" "Expect the padding files of the torrent (.pad/...) on disk, for clients that write them")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"no-private-protections" "Treat the files the tracker of a private torrent may require, *.nfo, *.sfv and proof/ unless the config file's [private] tables say otherwise, like other extras instead of leaving them alone")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"force-empty-expected" "Plan even if the torrent describes no files once padding is left out, with --surface every entry is then extra")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"only-owned" "Only remove entries owned by the current user (Unix only)")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--owner <USER> "Only remove entries owned by USER, a name or numeric id (Unix only)")
+            .required(false)
+            .global(true)
+            .conflicts_with("only-owned"))
+        .arg(arg!(--"highlight-ext" <EXT> "Make entries with these extensions stand out in listings (repeatable or comma-separated)")
+            .required(false)
+            .global(true)
+            .action(ArgAction::Append)
+            .value_delimiter(',')
+            .value_parser(parse_ext))
+        .arg(arg!(--top <N> "Number of largest extras listed before confirming (0 to disable)")
+            .required(false)
+            .global(true)
+            .value_parser(value_parser!(usize))
+            .default_value("5"))
+        .arg(arg!(--limit <N> "Entries shown per listing section; counts, sizes and the confirmation still cover all of them (0 for all; 100 unless given for a torrent without subdirectories)")
+            .required(false)
+            .global(true)
+            .value_parser(value_parser!(usize))
+            .default_value("200"))
+        .arg(arg!(--"show-all" "List every entry, as --limit 0 does")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"limit-json" "Cut the entry lists of diff --output json to --limit as well, setting its truncated field when any was cut")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--paths <STYLE> "Show paths relative to the directory or absolute [default: relative in listings, absolute in messages]")
+            .required(false)
+            .global(true)
+            .value_parser(["relative", "absolute"]))
+        .arg(arg!(--"no-progress" "Do not show progress bars or status lines")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--background "Run at the lowest CPU and I/O priority to yield to other programs")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"strict-torrent" "Treat an inconsistent piece length, piece count or total size in the torrent as an error")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"allow-unsafe-paths" "Remove or create entries even though the torrent has paths leading outside of the directory, which are left out")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"save-torrent" <PATH> "Write the torrent's info dictionary as a .torrent file to PATH, e.g. when it was named by a magnet URI")
+            .required(false)
+            .global(true)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--timeout <DURATION> "Give up downloading a torrent given by an http:// or https:// URL, or a request to the --client, after this long")
+            .required(false)
+            .global(true)
+            .value_parser(humantime::parse_duration)
+            .default_value("60s"))
+        .arg(arg!(--"metadata-timeout" <DURATION> "Give up fetching the metadata of a torrent named by a magnet URI or info hash from peers after this long; 0 to use cached metadata alone")
+            .required(false)
+            .global(true)
+            .value_parser(humantime::parse_duration)
+            .default_value("60s"))
+        .arg(arg!(--"no-dht" "Find peers to fetch metadata from through the magnet URI's trackers and x.pe peers alone, not the DHT")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"v1-only" "Read a torrent of a newer meta version than supported by its v1 part alone, if it has one")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"ignore-case" "Match the torrent's paths against the directory ignoring case, as on NTFS and APFS volumes")
+             .required(false)
+             .global(true)
+             .action(ArgAction::SetTrue))
+        .arg(arg!(--"deny-warnings" "Exit with 3 if the run printed any warning, even if it went through otherwise")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"normalize-unicode" <WHEN> "Match decomposed names on disk, as macOS stores them, against the torrent's precomposed ones; auto does on macOS")
+            .required(false)
+            .global(true)
+            .value_parser(["auto", "always", "never"])
+            .default_value("auto"))
+        .arg(arg!(--"no-history" "Do not record this run in the run history")
+            .required(false)
+            .global(true)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"history-keep" <N> "Maximum number of runs kept in the run history")
+            .required(false)
+            .global(true)
+            .value_parser(value_parser!(usize))
+            .default_value("1000"))
+        .arg(arg!(--client <KIND> "The BitTorrent client seeding the torrents, whose Web API --recheck-after and the label options talk to")
+            .required(false)
+            .global(true)
+            .value_parser(ClientKind::NAMES))
+        .arg(arg!(--"client-url" <URL> "Address of the client's Web API, by default where the client listens on localhost out of the box")
+            .required(false)
+            .global(true))
+        .arg(arg!(--"client-user" <NAME> "User name to log in to the client's Web API with")
+            .required(false)
+            .global(true))
+        .arg(arg!(--"client-password" <PASSWORD> "Password to log in to the client's Web API with; better kept in the config file than given on the command line")
+            .required(false)
+            .global(true))
+        .arg(arg!(--output <FORMAT> "Output format; in json mode errors are reported as a JSON object on stdout, and tsv only applies to list")
+            .required(false)
+            .global(true)
+            .value_parser(["human", "json", "tsv"])
+            .default_value("human"))
+        .subcommand(Command::new("clean")
+            .about("Remove entries in the directory that are not part of the torrent")
+            .after_help(CLEAN_EXIT_STATUS)
+            .arg(arg!(-f --"no-confirm" "Skip confirmation before deleting files")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .args(prompt_args())
+            .arg(arg!(--"allow-empty-target" "Clean even if the directory looks like an unmounted mount point")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"link-to" <DIR> "Symlink the entries that would be removed into DIR for review instead of removing them, pruning the links of earlier reviews that are no longer extras")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)))
+            .arg(arg!(--"summary-json" "Print only a one-line JSON summary of the run; requires --no-confirm")
+                .required(false)
+                .requires("no-confirm")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"only-duplicates-of-kept" "Only remove extras whose content is identical to a file of the torrent that is present")
+                .required(false)
+                .visible_alias("only-duplicates")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--force "Allow --only-duplicates-of-kept together with --no-confirm, --surface in a directory not named after the torrent, cleaning while the torrent looks like it is still downloading, and clearing the read-only attribute of files that cannot be removed otherwise (Windows)")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"confirm-each" "Ask about each file in turn, deleting the accepted ones as you go")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["no-confirm", "link-to", "stage", "move-to", "assume-no",
+                                     "prompt-timeout"]))
+            .arg(arg!(--interactive "Instead of confirming the removal as a whole, pick the files to remove from a list of them all, each selected to begin with")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["no-confirm", "link-to", "confirm-each", "assume-no",
+                                     "prompt-timeout"]))
+            .arg(arg!(--"backup-manifest" <PATH> "Append the path, size, modification time and BLAKE3 hash of each file to PATH before deleting it")
+                .required(false)
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["link-to", "stage", "move-to"]))
+            .arg(arg!(--"manifest-hash-max-size" <SIZE> "Do not hash files larger than SIZE (e.g. 500M) for --backup-manifest")
+                .required(false)
+                .requires("backup-manifest")
+                .value_parser(parse_size))
+            .arg(arg!(--journal <PATH> "Record the entries about to be removed and each removal in PATH rather than in a new file of the cache directory")
+                .required(false)
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["link-to", "stage", "move-to", "confirm-each", "resume"]))
+            .arg(arg!(--resume <JOURNAL> "Instead of scanning, remove what the run recorded in JOURNAL left, if it still exists, is unchanged and is still not part of the torrent")
+                .required(false)
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["link-to", "stage", "move-to", "confirm-each",
+                                     "only-duplicates-of-kept"]))
+            .arg(arg!(--stage "Move files into <dir>/.torrent-cleaner-trash/<timestamp>/ instead of deleting them; see purge")
+                .required(false)
+                .conflicts_with("link-to")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"move-to" <PATH> "Move files into PATH/<dir name>/, keeping their paths under the directory, instead of deleting them")
+                .required(false)
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with_all(["link-to", "stage"]))
+            .arg(arg!(--trash "Move files and, with --empty-dir, emptied directories to the system trash or recycle bin instead of deleting them, failing for those it cannot take, such as on network file systems")
+                .required(false)
+                .conflicts_with_all(["link-to", "stage", "move-to"])
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"pre-delete-hook" <CMD> "Run CMD with the entries about to be removed as JSON Lines on stdin; a non-zero exit aborts the run")
+                .required(false)
+                .conflicts_with("link-to"))
+            .arg(arg!(--"filter-hook" <CMD> "Run CMD for each file with its path as argument and its JSON entry on stdin; a non-zero exit keeps the file")
+                .required(false)
+                .conflicts_with("link-to"))
+            .arg(arg!(--"hook-timeout" <DURATION> "Kill a hook running longer than this; a timed-out hook counts as a non-zero exit")
+                .required(false)
+                .value_parser(humantime::parse_duration)
+                .default_value("30s"))
+            .arg(arg!(--"fix-renames" "Move each extra that is probably a missing file of the torrent under another path back to that path instead of removing it; ambiguous matches are only reported")
+                .required(false)
+                .conflicts_with("link-to")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--relink "Hardlink each extra that is probably a missing file of the torrent to that file's path, keeping both; ambiguous matches are only reported")
+                .required(false)
+                .conflicts_with_all(["link-to", "fix-renames"])
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"relink-move" "With --relink, remove the extra once linked")
+                .required(false)
+                .requires("relink")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"relink-copy" "With --relink, copy the extra where it cannot be hardlinked, e.g. across devices, instead of only reporting it")
+                .required(false)
+                .requires("relink")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--verify "Also hash the files of the torrent against its pieces, like verify, and list those that are corrupt or missing")
+                .required(false)
+                .conflicts_with("link-to")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"delete-corrupt" "With --verify, remove the corrupt files together with the extras so that the client downloads them again")
+                .required(false)
+                .requires("verify")
+                .conflicts_with("only-duplicates-of-kept")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"duplicate-roots" <ACTION> "With --surface, what to do with the probable duplicate roots next to the directory: ask about each, merge the files the directory lacks out of them and delete them, delete them, or keep them")
+                .required(false)
+                .value_parser(["ask", "merge", "delete", "keep"])
+                .default_value("ask"))
+            .arg(arg!(--"recheck-after" "Once the clean went through, ask the --client to recheck the torrent's data and print its answer")
+                .required(false)
+                .conflicts_with("link-to")
+                .action(ArgAction::SetTrue))
+            .args(label_args())
+            .arg(verify_renames_arg())
+            .arg(verify_duplicates_arg())
+            .arg(summary_arg())
+            .args(report_args())
+            .arg(dedupe_arg())
+            .args(threshold_args())
+            .arg(also_arg())
+            .args(manifest_args())
+            .mut_arg("dir", several_dirs))
+        .subcommand(Command::new("diff")
+            .about("Compare directory content changes without removing anything")
+            .after_help(DIFF_EXIT_STATUS)
+            .arg(arg!(--"summary-json" "Print only a one-line JSON summary of the run")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--check "Exit with 8 if there are entries to remove, 10 if not but files of the torrent are missing or differ in size, 9 if only entries outside the torrent's scope were found, 0 otherwise")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"since-last" "Show how the extras changed since the previous recorded run")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"map-report" <PATH> "Write a JSON report mapping each torrent file to its file on disk")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)))
+            .arg(arg!(--tree "Show the changes as a tree under the directory with their sizes, folding a directory whose changes are all of one sort into a line with their count")
+                .required(false)
+                .conflicts_with("group-dirs")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"group-dirs" "List a directory whose entries are all to be removed once, with their count and total size, and only the entries of directories that also keep something one by one")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"group-sort" <ORDER> "Order of the --group-dirs listing: largest first, or by path")
+                .required(false)
+                .requires("group-dirs")
+                .value_parser(["size", "path"])
+                .default_value("size"))
+            .arg(arg!(--watch "Keep comparing the directory after the first run, printing the counts and the entries that changed, until Ctrl-C")
+                .required(false)
+                .conflicts_with_all(["summary-json", "output"])
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--interval <DURATION> "How often --watch scans the directory again")
+                .required(false)
+                .requires("watch")
+                .value_parser(humantime::parse_duration)
+                .default_value("30s"))
+            .arg(verify_renames_arg())
+            .arg(verify_duplicates_arg())
+            .arg(summary_arg())
+            .args(report_args())
+            .arg(dedupe_arg())
+            .arg(also_arg())
+            .args(manifest_args())
+            .mut_arg("dir", several_dirs))
+        .subcommand(Command::new("stats")
+            .about("Show how much of the torrent is in the directory and how much else lies there, without hashing or removing anything")
+            .arg(also_arg())
+            .args(manifest_args()))
+        .subcommand(Command::new("touch")
+            .about("Create each missing file of the torrent as an empty placeholder, with the directories it needs, leaving existing files untouched")
+            .arg(arg!(--allocate "Give each placeholder the length the torrent declares, as a sparse file where the file system supports it")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--"dry-run" "Only list the files and directories that would be created")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(also_arg())
+            .args(manifest_args()))
+        .subcommand(Command::new("missing")
+            .about("List the files of the torrent missing from the directory, by path with their size and a total; exits with 1 if there are any")
+            .arg(also_arg())
+            .args(manifest_args()))
+        .subcommand(Command::new("verify")
+            .about("Hash the files of the torrent against its pieces and list those that are corrupt or missing; exits with 1 unless all are intact")
+            .after_help(VERIFY_EXIT_STATUS)
+            .arg(arg!(--"delete-corrupt" "Remove the corrupt files afterwards, after confirmation, so that the client downloads them again")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(-f --"no-confirm" "Skip confirmation before deleting the corrupt files")
+                .required(false)
+                .requires("delete-corrupt")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--files <GLOB> "Verify only the files of the torrent named like GLOB, or with a path like it if it holds a /, hashing just the pieces they have a part in (repeatable)")
+                .required(false)
+                .action(ArgAction::Append))
+            .args(prompt_args())
+            .arg(torrent_arg())
+            .arg(dir_arg()))
+        .subcommand(Command::new("snapshot")
+            .about("Record the plan of a dry run and the options behind it, to check later with compare-snapshot")
+            .arg(arg!(--out <PATH> "File to write the snapshot to")
+                .required(true)
+                .value_parser(value_parser!(PathBuf)))
+            .arg(arg!(--"include-paths" "Also record the absolute paths of the torrent and the directory, which are left out so snapshots can be shared")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(torrent_arg())
+            .arg(dir_arg()))
+        .subcommand(Command::new("compare-snapshot")
+            .about("Plan again with the current options and report how the plan differs from a snapshot; exits with 1 if it does")
+            .arg(Arg::new("snapshot")
+                .help("Snapshot written by the snapshot subcommand")
+                .required(true)
+                .value_parser(value_parser!(PathBuf)))
+            .arg(torrent_arg())
+            .arg(dir_arg()))
+        .subcommand(Command::new("plan")
+            .about("Record what clean would remove in a plan file, to review and carry out later with apply")
+            .arg(arg!(--out <PATH> "File to write the plan to")
+                .required(true)
+                .value_parser(value_parser!(PathBuf)))
+            .arg(torrent_arg())
+            .arg(dir_arg()))
+        .subcommand(Command::new("apply")
+            .about("Remove the entries of a plan file, leaving alone those that changed since or became part of the torrent")
+            .arg(Arg::new("plan")
+                .help("Plan written by the plan subcommand")
+                .required(true)
+                .value_parser(value_parser!(PathBuf)))
+            .arg(arg!(-f --"no-confirm" "Skip confirmation before deleting files")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .args(prompt_args())
+            .arg(torrent_arg())
+            .arg(dir_arg()))
+        .subcommand(Command::new("info")
+            .about("Show information about the torrent")
+            .arg(arg!(--"piece-layers" <PATH> "Bencoded piece layers of a v2 torrent whose .torrent file lacks them, checked against the files' pieces roots")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)))
+            .arg(torrent_arg()))
+        .subcommand(Command::new("list")
+            .about("List the files described by the torrent")
+            .arg(torrent_arg()))
+        .subcommand(Command::new("purge")
+            .about("Permanently delete files staged by clean --stage")
+            .arg(arg!(--"older-than" <DURATION> "Only purge runs staged longer ago than this, e.g. 7d or 12h")
+                .required_unless_present("all")
+                .value_parser(humantime::parse_duration))
+            .arg(arg!(--all "Purge all staged runs")
+                .required(false)
+                .conflicts_with("older-than")
+                .action(ArgAction::SetTrue))
+            .arg(arg!(-f --"no-confirm" "Skip confirmation before deleting files")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .args(prompt_args())
+            .arg(dir_arg()))
+        .subcommand(Command::new("restore")
+            .about("Move files staged by clean --stage back into the directory")
+            .arg(arg!(--run <NAME> "Staged run to restore, as listed by purge [default: the latest]")
+                .required(false))
+            .arg(arg!(--include <GLOB> "Only restore files matching GLOB by name, or by relative path if it has a / (repeatable)")
+                .required(false)
+                .action(ArgAction::Append))
+            .arg(arg!(--overwrite "Replace files that already exist in the directory")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(dir_arg()))
+        .subcommand(Command::new("batch")
+            .about("Compare or clean every torrent/directory pair listed in a manifest file")
+            .arg(arg!(--clean "Remove extra entries instead of only comparing")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(-f --"no-confirm" "Skip confirmation before deleting files")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .args(prompt_args())
+            .arg(arg!(--"allow-empty-target" "Clean even if the directory looks like an unmounted mount point")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(--force "Clean the torrents that look like they are still downloading as well")
+                .required(false)
+                .action(ArgAction::SetTrue))
+            .arg(arg!(-j --jobs <N> "Number of torrents processed concurrently")
+                .required(false)
+                .value_parser(value_parser!(u16).range(1..))
+                .default_value("1"))
+            .arg(arg!(--"on-error" <POLICY> "What to do with the remaining entries once one fails: continue, abort, or prompt for each failure")
+                .required(false)
+                .value_parser(["continue", "abort", "prompt"])
+                .default_value("continue"))
+            .arg(arg!(--"recheck-after" "With --clean, ask the --client to recheck the data of each torrent cleaned and print its answer")
+                .required(false)
+                .requires("clean")
+                .action(ArgAction::SetTrue))
+            .args(label_args())
+            .args(threshold_args())
+            .arg(Arg::new("manifest")
+                .help("File listing one `<torrent><TAB><dir>` pair per line; relative paths are \
+                    resolved against the manifest's directory")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))))
+        .subcommand(Command::new("history")
+            .about("List previously recorded runs")
+            .arg(arg!(--dir <DIR> "Only show runs against this directory")
+                .required(false)
+                .value_parser(value_parser!(PathBuf)))
+            .arg(arg!(--torrent <TORRENT> "Only show runs of the torrent with this name or info-hash (prefix)")
+                .required(false)))
+        .subcommand(Command::new("completions")
+            .about("Generate shell completions")
+            .arg(Arg::new("shell")
+                .required(true)
+                .value_parser(value_parser!(Shell))))
+}
+
+/// The grammar before subcommands were introduced: `[OPTIONS] <file> <dir> [diff]`.
+fn legacy_cli() -> Command {
+    command!()
+        .arg(arg!(-s --surface).action(ArgAction::SetTrue))
+        .arg(arg!(-f --"no-confirm").action(ArgAction::SetTrue))
+        .arg(arg!(-d --"empty-dir").action(ArgAction::SetTrue))
+        .arg(arg!(--"link-to" <DIR>).value_parser(value_parser!(OsString)))
+        .arg(arg!(--output <FORMAT>).value_parser(value_parser!(OsString)))
+        .arg(Arg::new("file").required(true).value_parser(value_parser!(OsString)))
+        .arg(Arg::new("dir").required(true).value_parser(value_parser!(OsString)))
+        .subcommand(Command::new("diff"))
+}
+
+/// Parses the command line with the defaults of the config file, translating the deprecated
+/// grammar into the equivalent subcommand invocation with a warning.
+pub fn parse_args() -> ArgMatches {
+    let args = std::env::args_os().collect::<Vec<OsString>>();
+    let config = Config::load(&build_cli(), &args)
+        .unwrap_or_else(|e| build_cli().error(ErrorKind::InvalidValue, format!("{:#}", e)).exit());
+    let err = match config.matches(build_cli(), &args) {
+        Ok(matches) => return checked(matches),
+        Err(err) => err,
+    };
+    if matches!(err.kind(), ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+        | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand) {
+        err.exit();
+    }
+
+    let Ok(legacy) = legacy_cli().try_get_matches_from(&args) else { err.exit() };
+    // A mistyped invocation of a new subcommand is not a legacy invocation
+    let file = legacy.get_one::<OsString>("file").expect("required");
+    if build_cli().get_subcommands().any(|e| *e.get_name() == *file) {
+        err.exit();
+    }
+    let diff = legacy.subcommand_matches("diff").is_some();
+    let mut new_args = vec![args[0].clone(), OsString::from(if diff { "diff" } else { "clean" })];
+    for flag in ["surface", "empty-dir"] {
+        if legacy.get_flag(flag) {
+            new_args.push(format!("--{}", flag).into());
+        }
+    }
+    if let Some(output) = legacy.get_one::<OsString>("output") {
+        new_args.extend(["--output".into(), output.clone()]);
+    }
+    // `-f` used to be accepted and ignored by diff
+    if !diff && legacy.get_flag("no-confirm") {
+        new_args.push("--no-confirm".into());
+    }
+    if let Some(link_to) = legacy.get_one::<OsString>("link-to") {
+        new_args.extend(["--link-to".into(), link_to.clone()]);
+    }
+    let dir = legacy.get_one::<OsString>("dir").expect("required");
+    if file.to_string_lossy().starts_with('-') || dir.to_string_lossy().starts_with('-') {
+        new_args.push("--".into());
+    }
+    new_args.extend([file.clone(), dir.clone()]);
+
+    let suggestion = new_args[1..].iter().map(|e| e.to_string_lossy().into_owned())
+        .collect::<Vec<String>>().join(" ");
+    eprintln!("{}", warn(WarningCode::Deprecated, format!(
+        "this invocation is deprecated and will be removed in the next release; use `{} {}` \
+         instead.", env!("CARGO_BIN_NAME"), suggestion)));
+    checked(config.matches(build_cli(), &new_args).unwrap_or_else(|e| e.exit()))
+}
+
+/// Checks the requirements clap cannot, as the config file may be what gives the options
+/// involved, and the defaults it sets do not count for `requires`.
+fn checked(matches: ArgMatches) -> ArgMatches {
+    let sub = matches.subcommand().map_or(&matches, |e| e.1);
+    if sub.get_one::<String>("client").is_none() {
+        let needing = ["client-url", "client-user", "client-password"].into_iter()
+            .find(|e| sub.get_one::<String>(e).is_some())
+            .or_else(|| (sub.try_get_one::<bool>("recheck-after").ok().flatten() == Some(&true))
+                .then_some("recheck-after"))
+            .or_else(|| ["skip-label", "skip-category", "only-label"].into_iter()
+                .find(|e| sub.try_get_many::<String>(e).ok().flatten().is_some()));
+        if let Some(id) = needing {
+            build_cli().error(ErrorKind::MissingRequiredArgument, format!(
+                "--{} needs the --client it is for, given here or in the config file", id)).exit();
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_cli;
+
+    #[test]
+    fn arg_graph_is_consistent() {
+        build_cli().debug_assert();
+    }
+}
@@ -0,0 +1,311 @@
+use crate::download::timeout;
+use anyhow::{anyhow, bail, Context};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::ArgMatches;
+use serde_json::{json, Value};
+use std::sync::{Mutex, MutexGuard};
+
+/// The BitTorrent clients whose Web API `--client` talks to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientKind {
+    QBittorrent,
+    Transmission,
+    Deluge,
+}
+
+impl ClientKind {
+    /// The values of `--client`.
+    pub const NAMES: [&'static str; 3] = ["qbittorrent", "transmission", "deluge"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "qbittorrent" => Self::QBittorrent,
+            "transmission" => Self::Transmission,
+            "deluge" => Self::Deluge,
+            _ => unreachable!("value parser"),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::QBittorrent => "qBittorrent",
+            Self::Transmission => "Transmission",
+            Self::Deluge => "Deluge",
+        }
+    }
+
+    /// Where the Web API of the client listens unless configured otherwise.
+    fn default_url(self) -> &'static str {
+        match self {
+            Self::QBittorrent => "http://localhost:8080",
+            Self::Transmission => "http://localhost:9091/transmission/rpc",
+            Self::Deluge => "http://localhost:8112/json",
+        }
+    }
+}
+
+/// A torrent as the client lists it.
+#[derive(Debug)]
+pub struct ClientTorrent {
+    pub name: String,
+    /// The tags of qBittorrent, the labels of Transmission, or the label of Deluge.
+    pub labels: Vec<String>,
+    /// The category of qBittorrent, which the others lack.
+    pub category: Option<String>,
+}
+
+/// `--skip-label`, `--skip-category` and `--only-label`: the labels and categories in the client
+/// that keep a torrent from being cleaned, or that it needs to be. Names match ignoring ASCII
+/// case, as clients differ in whether they keep it.
+pub struct LabelFilter {
+    skip_labels: Vec<String>,
+    skip_categories: Vec<String>,
+    only_labels: Vec<String>,
+}
+
+impl LabelFilter {
+    /// The filter given for this run, if any.
+    pub fn from_matches(matches: &ArgMatches) -> Option<Self> {
+        let values = |id: &str| matches.try_get_many::<String>(id).ok().flatten()
+            .map_or(Vec::new(), |e| e.cloned().collect());
+        let filter = Self { skip_labels: values("skip-label"),
+                            skip_categories: values("skip-category"),
+                            only_labels: values("only-label") };
+        (!filter.skip_labels.is_empty() || !filter.skip_categories.is_empty()
+            || !filter.only_labels.is_empty()).then_some(filter)
+    }
+
+    /// Why `torrent` is not to be cleaned, if it is not; a torrent the client does not have
+    /// carries no labels.
+    pub fn held_back(&self, torrent: Option<&ClientTorrent>) -> Option<String> {
+        let has = |names: &[String], name: &str| names.iter().any(|e| e.eq_ignore_ascii_case(name));
+        let labels = torrent.map_or(&[][..], |e| &e.labels[..]);
+        if let Some(label) = labels.iter().find(|e| has(&self.skip_labels, e)) {
+            return Some(format!("it carries the label {} (--skip-label)", label));
+        }
+        let category = torrent.and_then(|e| e.category.as_deref());
+        if let Some(category) = category.filter(|e| has(&self.skip_categories, e)) {
+            return Some(format!("it is in the category {} (--skip-category)", category));
+        }
+        if !self.only_labels.is_empty() && !labels.iter().any(|e| has(&self.only_labels, e)) {
+            return Some(format!("it carries none of the labels {} (--only-label)",
+                                self.only_labels.join(", ")));
+        }
+        None
+    }
+}
+
+/// The Web API of the client given by `--client`, at `--client-url` or its default address, logged
+/// in to with `--client-user` and `--client-password` on the first request that needs it.
+pub struct Client {
+    kind: ClientKind,
+    url: String,
+    user: Option<String>,
+    password: Option<String>,
+    agent: ureq::Agent,
+    /// The session cookie of qBittorrent and Deluge, or the `X-Transmission-Session-Id` of
+    /// Transmission, once the client gave it.
+    session: Mutex<Option<String>>,
+}
+
+impl Client {
+    /// The client configured for this run, if any.
+    pub fn from_matches(matches: &ArgMatches) -> Option<Self> {
+        let kind = ClientKind::from_name(matches.get_one::<String>("client")?);
+        let url = matches.get_one::<String>("client-url").map_or(kind.default_url(), |e| e);
+        let agent = ureq::AgentBuilder::new()
+            .timeout(timeout())
+            .user_agent(concat!("torrent-cleaner/", env!("CARGO_PKG_VERSION")))
+            .build();
+        Some(Self {
+            kind,
+            url: url.trim_end_matches('/').to_string(),
+            user: matches.get_one::<String>("client-user").cloned(),
+            password: matches.get_one::<String>("client-password").cloned(),
+            agent,
+            session: Mutex::new(None),
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.kind.name()
+    }
+
+    /// The torrent of `info_hash` as the client lists it, or `None` if the client has no such
+    /// torrent.
+    pub fn torrent(&self, info_hash: &str) -> anyhow::Result<Option<ClientTorrent>> {
+        let torrent = match self.kind {
+            ClientKind::QBittorrent => {
+                let url = format!("{}/api/v2/torrents/info", self.url);
+                let request = self.qbittorrent_request(self.agent.get(&url))?
+                    .query("hashes", info_hash);
+                let reply = self.json(request.call().map_err(|e| self.error(e))?)?;
+                reply.get(0).cloned()
+            }
+            ClientKind::Transmission => {
+                let reply = self.transmission("torrent-get",
+                    json!({ "ids": [info_hash], "fields": ["name", "labels"] }))?;
+                reply["torrents"].get(0).cloned()
+            }
+            ClientKind::Deluge => {
+                // The label is the Label plugin's, missing when it is not enabled
+                let reply = self.deluge("core.get_torrent_status",
+                                        json!([info_hash, ["name", "label"]]))?;
+                // An unknown torrent has no status rather than an error
+                reply.as_object().is_some_and(|e| !e.is_empty()).then_some(reply)
+            }
+        };
+        let text = |value: &Value| value.as_str().unwrap_or_default().to_string();
+        Ok(torrent.map(|e| ClientTorrent {
+            name: text(&e["name"]),
+            labels: match self.kind {
+                ClientKind::QBittorrent => text(&e["tags"]).split(',').map(str::trim)
+                    .filter(|e| !e.is_empty()).map(str::to_string).collect(),
+                ClientKind::Transmission => e["labels"].as_array()
+                    .map_or(Vec::new(), |e| e.iter().map(text).collect()),
+                ClientKind::Deluge => Some(text(&e["label"])).filter(|e| !e.is_empty())
+                    .into_iter().collect(),
+            },
+            category: Some(text(&e["category"])).filter(|e| !e.is_empty()),
+        }))
+    }
+
+    /// Asks the client to recheck the data of the torrent of `info_hash`, returning its answer.
+    pub fn recheck(&self, info_hash: &str) -> anyhow::Result<String> {
+        // None of the APIs fail a recheck of a torrent they do not have
+        if self.torrent(info_hash)?.is_none() {
+            bail!("{} at {} has no torrent with info hash {}", self.name(), self.url, info_hash);
+        }
+        match self.kind {
+            ClientKind::QBittorrent => {
+                let url = format!("{}/api/v2/torrents/recheck", self.url);
+                let response = self.qbittorrent_request(self.agent.post(&url))?
+                    .send_form(&[("hashes", info_hash)])
+                    .map_err(|e| self.error(e))?;
+                Ok(format!("HTTP {} {}", response.status(), response.status_text()))
+            }
+            ClientKind::Transmission => {
+                self.transmission("torrent-verify", json!({ "ids": [info_hash] }))?;
+                Ok("result: success".to_string())
+            }
+            ClientKind::Deluge => {
+                let reply = self.deluge("core.force_recheck", json!([[info_hash]]))?;
+                Ok(format!("result: {}", reply))
+            }
+        }
+    }
+
+    fn error(&self, error: ureq::Error) -> anyhow::Error {
+        match error {
+            ureq::Error::Status(401 | 403, _) => anyhow!("{} at {} refused access; check \
+                --client-user and --client-password", self.name(), self.url),
+            ureq::Error::Status(status, _) => anyhow!("{} at {} answered with HTTP status {}",
+                                                      self.name(), self.url, status),
+            ureq::Error::Transport(e) => anyhow!("Could not reach {} at {}: {}", self.name(),
+                                                 self.url, e),
+        }
+    }
+
+    fn session(&self) -> MutexGuard<'_, Option<String>> {
+        self.session.lock().expect("not poisoned")
+    }
+
+    fn json(&self, response: ureq::Response) -> anyhow::Result<Value> {
+        let body = response.into_string()
+            .with_context(|| format!("Failed to read the answer of {}", self.name()))?;
+        serde_json::from_str(&body)
+            .with_context(|| format!("{} at {} did not answer with JSON", self.name(), self.url))
+    }
+
+    /// Keeps the cookie the client set for the session, without its attributes.
+    fn keep_session(&self, response: &ureq::Response) {
+        if let Some(cookie) = response.header("set-cookie") {
+            let cookie = cookie.split(';').next().unwrap_or_default().trim();
+            *self.session() = Some(cookie.to_string());
+        }
+    }
+
+    /// `request` with the session cookie, logging in first when there is a user name to log in
+    /// with; without one, the client is expected to let requests from this host through.
+    fn qbittorrent_request(&self, request: ureq::Request) -> anyhow::Result<ureq::Request> {
+        if self.session().is_none() {
+            if let Some(user) = &self.user {
+                let url = format!("{}/api/v2/auth/login", self.url);
+                let response = self.agent.post(&url)
+                    .set("Referer", &self.url)
+                    .send_form(&[("username", user),
+                                 ("password", self.password.as_deref().unwrap_or_default())])
+                    .map_err(|e| self.error(e))?;
+                self.keep_session(&response);
+                if response.into_string().unwrap_or_default().trim() != "Ok." {
+                    bail!("qBittorrent at {} rejected the login of {}", self.url, user);
+                }
+            }
+        }
+        let request = request.set("Referer", &self.url);
+        Ok(match self.session().as_deref() {
+            Some(cookie) => request.set("Cookie", cookie),
+            None => request,
+        })
+    }
+
+    /// Calls `method` of the Transmission RPC, taking the session id of the first answer that
+    /// asks for one, and returns the `arguments` of the reply.
+    fn transmission(&self, method: &str, arguments: Value) -> anyhow::Result<Value> {
+        let body = json!({ "method": method, "arguments": arguments }).to_string();
+        for _ in 0..2 {
+            let mut request = self.agent.post(&self.url).set("Content-Type", "application/json");
+            if let Some(id) = self.session().as_deref() {
+                request = request.set("X-Transmission-Session-Id", id);
+            }
+            if let Some(user) = &self.user {
+                let credentials = format!("{}:{}", user, self.password.as_deref().unwrap_or_default());
+                request = request.set("Authorization",
+                                      &format!("Basic {}", BASE64_STANDARD.encode(credentials)));
+            }
+            match request.send_string(&body) {
+                Err(ureq::Error::Status(409, response)) => {
+                    *self.session() = response.header("X-Transmission-Session-Id")
+                        .map(str::to_string);
+                }
+                response => {
+                    let mut reply = self.json(response.map_err(|e| self.error(e))?)?;
+                    if reply["result"] != "success" {
+                        bail!("Transmission answered {} with: {}", method, reply["result"]);
+                    }
+                    return Ok(reply["arguments"].take());
+                }
+            }
+        }
+        Err(anyhow!("Transmission at {} keeps asking for a new session id", self.url))
+    }
+
+    /// Calls `method` of the Deluge Web JSON-RPC, logging in first, and returns the `result` of
+    /// the reply.
+    fn deluge(&self, method: &str, params: Value) -> anyhow::Result<Value> {
+        if self.session().is_none() {
+            // deluge is the password of a fresh Deluge Web
+            let password = self.password.as_deref().unwrap_or("deluge");
+            if self.deluge_call("auth.login", json!([password]))? != Value::Bool(true) {
+                bail!("Deluge at {} rejected the password", self.url);
+            }
+        }
+        self.deluge_call(method, params)
+    }
+
+    fn deluge_call(&self, method: &str, params: Value) -> anyhow::Result<Value> {
+        let mut request = self.agent.post(&self.url).set("Content-Type", "application/json");
+        if let Some(cookie) = self.session().as_deref() {
+            request = request.set("Cookie", cookie);
+        }
+        let response = request
+            .send_string(&json!({ "method": method, "params": params, "id": 1 }).to_string())
+            .map_err(|e| self.error(e))?;
+        self.keep_session(&response);
+        let mut reply = self.json(response)?;
+        if !reply["error"].is_null() {
+            bail!("Deluge answered {} with: {}", method, reply["error"]["message"]);
+        }
+        Ok(reply["result"].take())
+    }
+}
@@ -0,0 +1,547 @@
+use crate::scan::Exclude;
+use anyhow::{anyhow, bail, Context};
+use clap::builder::ArgPredicate;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use directories::ProjectDirs;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use toml::de::{DeTable, DeValue};
+
+/// Options the config file cannot set, as they say which config file to read or what to do with
+/// it.
+const NOT_CONFIGURABLE: [&str; 4] = ["help", "version", "config", "print-config"];
+
+/// The options of this run whose value the config file gave, see [`from_config`].
+static FROM_CONFIG: OnceLock<HashSet<String>> = OnceLock::new();
+
+/// What is left alone on private torrents without a `[private]` table saying otherwise: the
+/// release information, checksums and proof pictures trackers require to be kept.
+pub const PRIVATE_PATTERNS: [&str; 3] = ["*.nfo", "*.sfv", "proof/"];
+
+/// The `protect` lists of the `[private]` tables, by the tracker host of `[private."<host>"]`.
+static PRIVATE: OnceLock<HashMap<Option<String>, Vec<String>>> = OnceLock::new();
+
+/// A value of the config file.
+#[derive(Clone, Debug)]
+enum Value {
+    Bool(bool),
+    /// An integer or float, as written without `_` separators.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+}
+
+/// A `key = value` line of the config file, at the top or under the table of a subcommand.
+struct Setting {
+    table: Option<String>,
+    /// The long name of the option, with `_` read as `-`.
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+/// Defaults for the options of the command line, read from a TOML file: `key = value` lines
+/// named after the long options, which apply to every subcommand that has the option, followed
+/// by `[<subcommand>]` tables with options for that subcommand alone. Options given on the
+/// command line win over the file; `--no-<flag>` turns off a flag it turns on (`--<flag>` for
+/// flags named `--no-<flag>`). `[private]` and `[private."<host>"]` tables instead take the
+/// `protect` list of what to leave alone on private torrents, see [`private_patterns`].
+#[derive(Default)]
+pub struct Config {
+    /// The file read, if any.
+    pub path: Option<PathBuf>,
+    settings: Vec<Setting>,
+}
+
+/// `config.toml` in the user's config directory: `~/.config/torrent-cleaner` on Linux,
+/// `~/Library/Application Support/torrent-cleaner` on macOS and
+/// `%APPDATA%\torrent-cleaner\config` on Windows.
+pub fn default_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "torrent-cleaner").map(|e| e.config_dir().join("config.toml"))
+}
+
+/// Whether the config file gave the value `id` has in this run, rather than the option's own
+/// default, where a check of [`ValueSource::DefaultValue`] needs to tell them apart.
+pub fn from_config(id: &str) -> bool {
+    FROM_CONFIG.get().is_some_and(|e| e.contains(id))
+}
+
+/// What is left alone on a private torrent of the tracker at `host`: the `protect` list of its
+/// `[private."<host>"]` table, or of the one for the closest domain it is in, else that of
+/// `[private]`, else [`PRIVATE_PATTERNS`].
+pub fn private_patterns(host: Option<&str>) -> Vec<String> {
+    let tables = PRIVATE.get();
+    let tracker = tables.into_iter().flatten()
+        .filter_map(|(key, patterns)| Some((key.as_deref()?, patterns)))
+        .filter(|(key, _)| host.is_some_and(|e| e == *key || e.ends_with(&format!(".{}", key))))
+        .max_by_key(|(key, _)| key.len());
+    match tracker.map(|(_, e)| e).or_else(|| tables?.get(&None)) {
+        Some(patterns) => patterns.clone(),
+        None => PRIVATE_PATTERNS.iter().map(|e| e.to_string()).collect(),
+    }
+}
+
+/// Whether `table` is `[private]` or a `[private."<host>"]` table rather than a subcommand's.
+fn is_private_table(table: &str) -> bool {
+    table == "private" || table.starts_with("private.")
+}
+
+/// The `protect` lists of the settings of `[private]` tables, the only key they take.
+fn private_tables(settings: Vec<Setting>) -> anyhow::Result<HashMap<Option<String>, Vec<String>>> {
+    let mut tables = HashMap::new();
+    for setting in settings {
+        let table = setting.table.expect("private table");
+        let host = table.split_once('.').map(|(_, host)| host.to_ascii_lowercase());
+        let patterns = match (&*setting.key, setting.value) {
+            ("protect", Value::Array(values)) => values.into_iter()
+                .map(|e| match e {
+                    Value::String(pattern) => Ok(pattern),
+                    _ => Err(anyhow!("`protect` takes strings")),
+                })
+                .collect::<anyhow::Result<Vec<String>>>(),
+            ("protect", Value::String(pattern)) => Ok(vec![pattern]),
+            ("protect", _) => Err(anyhow!("`protect` takes an array of patterns")),
+            (key, _) => Err(anyhow!("unknown key `{}` in [{}], which only takes `protect`", key,
+                                    table)),
+        };
+        let patterns = patterns.and_then(|e| Exclude::new(&e).map(|_| e))
+            .with_context(|| format!("line {}", setting.line))?;
+        tables.insert(host, patterns);
+    }
+    Ok(tables)
+}
+
+/// The value of `--config`, looked up before parsing since the file changes what is parsed.
+fn config_arg(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1).take_while(|e| *e != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|e| e.strip_prefix("--config=")) {
+            return Some(path.into());
+        }
+    }
+    None
+}
+
+/// The negation of the flag named `long`.
+fn negation(long: &str) -> String {
+    match long.strip_prefix("no-") {
+        Some(flag) => flag.to_string(),
+        None => format!("no-{}", long),
+    }
+}
+
+/// Adds a hidden negation of each flag of `cmd` whose name is not already taken, by `cmd` or by
+/// the options in `taken`. The flag is off when its negation is given, whatever its default;
+/// the one given last wins when both are.
+fn negate(mut cmd: Command, taken: &HashSet<String>) -> Command {
+    let flags = cmd.get_arguments()
+        .filter(|e| matches!(e.get_action(), ArgAction::SetTrue))
+        .filter_map(|e| {
+            Some((e.get_id().to_string(), e.get_long()?.to_string(), e.is_global_set()))
+        })
+        .collect::<Vec<(String, String, bool)>>();
+    for (id, long, global) in flags {
+        let negation = negation(&long);
+        if taken.contains(&negation) || cmd.get_arguments()
+            .any(|e| e.get_long() == Some(&negation) || e.get_id() == negation.as_str()) {
+            continue;
+        }
+        cmd = cmd
+            .mut_arg(&id, |e| e.overrides_with(&negation)
+                .default_value_if(&negation, ArgPredicate::Equals("true".into()), Some("false")))
+            .arg(Arg::new(&negation)
+                .long(&negation)
+                .hide(true)
+                .global(global)
+                .overrides_with(&id)
+                .action(ArgAction::SetTrue));
+    }
+    cmd
+}
+
+/// [`negate`] for `cli` and each of its subcommands.
+fn negations(cli: Command) -> Command {
+    let taken = cli.get_arguments().filter_map(|e| e.get_long().map(String::from))
+        .collect::<HashSet<String>>();
+    let mut cli = negate(cli, &HashSet::new());
+    for name in subcommand_names(&cli) {
+        cli = cli.mut_subcommand(name, |e| negate(e, &taken));
+    }
+    cli
+}
+
+/// Whether the negation of the flag `id` turned it off on the command line.
+fn negated(cmd: &Command, matches: &ArgMatches, id: &str) -> bool {
+    let negation = negation(id);
+    cmd.get_arguments().any(|e| e.get_id() == negation.as_str() && e.is_hide_set())
+        && matches.value_source(&negation) == Some(ValueSource::CommandLine)
+}
+
+fn subcommand_names(cli: &Command) -> Vec<String> {
+    cli.get_subcommands().map(|e| e.get_name().to_string()).collect()
+}
+
+/// Makes every argument optional when `--print-config` is given, so it can be asked of any
+/// subcommand without the arguments it needs to run.
+fn relaxed(mut cli: Command) -> Command {
+    for name in subcommand_names(&cli) {
+        cli = cli.mut_subcommand(name, |mut sub| {
+            let ids = sub.get_arguments().map(|e| e.get_id().to_string()).collect::<Vec<String>>();
+            for id in ids {
+                sub = sub.mut_arg(id, |e| {
+                    e.required(false).required_unless_present("print-config")
+                });
+            }
+            sub
+        });
+    }
+    cli
+}
+
+fn configurable(arg: &Arg, key: &str) -> bool {
+    arg.get_long() == Some(key) && !arg.is_hide_set()
+        && !NOT_CONFIGURABLE.contains(&arg.get_id().as_str())
+}
+
+/// A scalar value as the command line would spell it.
+fn scalar(key: &str, value: &Value) -> anyhow::Result<String> {
+    match value {
+        Value::Number(number) => Ok(number.clone()),
+        Value::String(string) => Ok(string.clone()),
+        Value::Bool(_) => bail!("`{}` takes a value, not true or false", key),
+        Value::Array(_) => bail!("`{}` takes a single value, not an array", key),
+    }
+}
+
+/// `arg` with the value of `setting` as its default, checked by the option's own parser.
+fn with_default(arg: Arg, setting: &Setting) -> anyhow::Result<Arg> {
+    let key = &setting.key;
+    let values = match (arg.get_action(), &setting.value) {
+        (ArgAction::SetTrue, Value::Bool(on)) => return Ok(arg.default_value(on.to_string())),
+        (ArgAction::SetTrue, _) => bail!("`{}` must be true or false", key),
+        (ArgAction::Count, Value::Number(count)) if count.parse::<u8>().is_ok() => {
+            return Ok(arg.default_value(count.clone()));
+        }
+        (ArgAction::Count, _) => bail!("`{}` must be a count, such as 2", key),
+        (ArgAction::Append, Value::Array(values)) => values.iter().map(|e| scalar(key, e))
+            .collect::<anyhow::Result<Vec<String>>>()?,
+        (_, value) => vec![scalar(key, value)?],
+    };
+    // The option alone, without the conflicts and requirements naming the others
+    let mut check = Arg::new(arg.get_id().clone())
+        .long(key)
+        .action(ArgAction::Append)
+        .value_parser(arg.get_value_parser().clone());
+    if let Some(delimiter) = arg.get_value_delimiter() {
+        check = check.value_delimiter(delimiter);
+    }
+    if let Some(names) = arg.get_value_names() {
+        check = check.value_names(names.to_vec());
+    }
+    let args = values.iter().flat_map(|e| [format!("--{}", key), e.clone()]);
+    Command::new("config").no_binary_name(true).arg(check).try_get_matches_from(args)
+        .map_err(|e| {
+            let message = e.to_string();
+            let first = message.lines().next().unwrap_or_default();
+            anyhow!("`{}`: {}", key, first.trim_start_matches("error: "))
+        })?;
+    Ok(arg.default_values(values))
+}
+
+impl Config {
+    /// Reads the file given by `--config` in `args`, or the one at [`default_path`] if it
+    /// exists, and checks that its settings are options of `cli` with valid values.
+    pub fn load(cli: &Command, args: &[OsString]) -> anyhow::Result<Self> {
+        let path = match config_arg(args) {
+            Some(path) => path,
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let (private, settings) = parse(&text)
+            .with_context(|| format!("Invalid config file {}", path.display()))?
+            .into_iter()
+            .partition(|e| e.table.as_deref().is_some_and(is_private_table));
+        let private = private_tables(private)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        PRIVATE.set(private).ok();
+        let config = Self { path: Some(path), settings };
+        config.apply(cli.clone(), &HashSet::new())?;
+        Ok(config)
+    }
+
+    /// `cli` with the settings as defaults, except those whose index is in `skip`.
+    fn apply(&self, cli: Command, skip: &HashSet<usize>) -> anyhow::Result<Command> {
+        let mut cli = negations(cli);
+        for (index, setting) in self.settings.iter().enumerate() {
+            if !skip.contains(&index) {
+                cli = apply_setting(cli, setting).with_context(|| {
+                    let path = self.path.clone().unwrap_or_default();
+                    format!("Invalid config file {}: line {}", path.display(), setting.line)
+                })?;
+            }
+        }
+        Ok(cli)
+    }
+
+    /// Parses `args` with the settings as defaults for `cli`. Settings conflicting with an
+    /// option given on the command line are left out, so the command line wins. With
+    /// `--print-config`, prints the options in effect and exits, as `--help` does.
+    pub fn matches(&self, cli: Command, args: &[OsString]) -> Result<ArgMatches, clap::Error> {
+        let print = args.iter().skip(1).take_while(|e| *e != "--").any(|e| e == "--print-config");
+        let cli = if print { relaxed(cli) } else { cli };
+        let mut skip = HashSet::new();
+        loop {
+            let mut configured = self.apply(cli.clone(), &skip).expect("checked by load");
+            let matches = configured.try_get_matches_from_mut(args)?;
+            configured.build();
+            let (cmd, scope, sub) = match matches.subcommand() {
+                Some((name, sub)) => {
+                    (configured.find_subcommand(name).expect("matched"), Some(name), sub)
+                }
+                None => (&configured, None, &matches),
+            };
+            let overridden = self.overridden(cmd, scope, sub, &skip)?;
+            if !overridden.is_empty() {
+                skip.extend(overridden);
+                continue;
+            }
+            let from_config = self.in_effect(cmd, scope, sub, &skip);
+            if print {
+                self.print(cmd, sub, &from_config);
+                std::process::exit(0);
+            }
+            FROM_CONFIG.set(from_config).expect("parsed once");
+            return Ok(matches);
+        }
+    }
+
+    /// The settings for `cmd`, the subcommand `scope` or the top command, that are not in
+    /// `skip`, with the option each sets.
+    fn settings_for<'a>(&'a self, cmd: &'a Command, scope: Option<&'a str>,
+                        skip: &'a HashSet<usize>) -> impl Iterator<Item = (usize, &'a Arg)> + 'a {
+        self.settings.iter().enumerate()
+            .filter(move |(index, e)| !skip.contains(index)
+                && (e.table.is_none() || e.table.as_deref() == scope))
+            .filter_map(|(index, e)| {
+                Some((index, cmd.get_arguments().find(|arg| configurable(arg, &e.key))?))
+            })
+    }
+
+    /// The settings giving a default to an option that conflicts with one given on the command
+    /// line. Fails if two settings in effect conflict with each other.
+    fn overridden(&self, cmd: &Command, scope: Option<&str>, matches: &ArgMatches,
+                  skip: &HashSet<usize>) -> Result<HashSet<usize>, clap::Error> {
+        let source = |arg: &Arg| matches.value_source(arg.get_id().as_str());
+        let conflict = |a: &Arg, b: &Arg| {
+            cmd.get_arg_conflicts_with(a).iter().any(|e| e.get_id() == b.get_id())
+                || cmd.get_arg_conflicts_with(b).iter().any(|e| e.get_id() == a.get_id())
+        };
+        let explicit = cmd.get_arguments()
+            .filter(|e| source(e) == Some(ValueSource::CommandLine))
+            .collect::<Vec<&Arg>>();
+        let defaults = self.settings_for(cmd, scope, skip)
+            .filter(|(_, arg)| source(arg) == Some(ValueSource::DefaultValue))
+            .collect::<Vec<(usize, &Arg)>>();
+        let mut overridden = HashSet::new();
+        for &(index, arg) in &defaults {
+            if explicit.iter().any(|e| conflict(arg, e)) {
+                overridden.insert(index);
+            }
+        }
+        for &(index, arg) in &defaults {
+            let other = defaults.iter()
+                .find(|(other, e)| !overridden.contains(&index) && !overridden.contains(other)
+                    && conflict(arg, e));
+            if let Some((_, other)) = other {
+                let path = self.path.clone().unwrap_or_default();
+                return Err(cmd.clone().error(ErrorKind::ArgumentConflict, format!(
+                    "`{}` and `{}` of config file {} cannot be used together",
+                    arg.get_id(), other.get_id(), path.display())));
+            }
+        }
+        Ok(overridden)
+    }
+
+    /// The options whose value in `matches` comes from the config file.
+    fn in_effect(&self, cmd: &Command, scope: Option<&str>, matches: &ArgMatches,
+                 skip: &HashSet<usize>) -> HashSet<String> {
+        let source = |id: &str| matches.value_source(id);
+        self.settings_for(cmd, scope, skip)
+            .map(|(_, arg)| arg.get_id().as_str())
+            .filter(|id| source(id) == Some(ValueSource::DefaultValue))
+            .filter(|id| !negated(cmd, matches, id))
+            .map(String::from)
+            .collect()
+    }
+
+    /// Prints the options of `cmd` in effect in `matches` as a config file would set them, with
+    /// where each value comes from.
+    fn print(&self, cmd: &Command, matches: &ArgMatches, from_config: &HashSet<String>) {
+        match &self.path {
+            Some(path) => println!("# Config file: {}", path.display()),
+            None => println!("# Config file: none, {} does not exist",
+                             default_path().unwrap_or_default().display()),
+        }
+        let mut args = cmd.get_arguments()
+            .filter(|e| e.get_long().is_some_and(|long| configurable(e, long)))
+            .collect::<Vec<&Arg>>();
+        args.sort_by_key(|e| e.get_id());
+        for arg in args {
+            let id = arg.get_id().as_str();
+            let value = match arg.get_action() {
+                ArgAction::SetTrue => matches.get_flag(id).to_string(),
+                ArgAction::Count => matches.get_count(id).to_string(),
+                action => {
+                    let Some(values) = matches.get_raw(id) else { continue };
+                    let values = values.map(|e| toml_scalar(&e.to_string_lossy()))
+                        .collect::<Vec<String>>();
+                    match action {
+                        ArgAction::Append => format!("[{}]", values.join(", ")),
+                        _ => values.join(" "),
+                    }
+                }
+            };
+            let explicit = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+            let source = if explicit(id) || negated(cmd, matches, id) {
+                "command line"
+            } else if from_config.contains(id) {
+                "config file"
+            } else {
+                "default"
+            };
+            println!("{} = {}  # {}", id, value, source);
+        }
+    }
+}
+
+/// `value` as a TOML number if it reads as one, else as a string.
+fn toml_scalar(value: &str) -> String {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => value.to_string(),
+        _ => serde_json::to_string(value).expect("serializable"),
+    }
+}
+
+/// Sets the default of the option named by `setting` in `cli`: in every (sub)command that has
+/// it for a setting at the top, in its subcommand alone for one in a table.
+fn apply_setting(mut cli: Command, setting: &Setting) -> anyhow::Result<Command> {
+    let key = &setting.key;
+    let Some(table) = &setting.table else {
+        let mut found = false;
+        let arg = cli.get_arguments().find(|e| configurable(e, key)).cloned();
+        if let Some(arg) = arg {
+            let arg = with_default(arg, setting)?;
+            cli = cli.mut_arg(arg.get_id().clone(), |_| arg);
+            found = true;
+        }
+        for name in subcommand_names(&cli) {
+            let sub = cli.find_subcommand(&name).expect("listed");
+            let arg = sub.get_arguments().find(|e| configurable(e, key)).cloned();
+            if let Some(arg) = arg {
+                let arg = with_default(arg, setting)?;
+                cli = cli.mut_subcommand(name, |sub| sub.mut_arg(arg.get_id().clone(), |_| arg));
+                found = true;
+            }
+        }
+        return match found {
+            true => Ok(cli),
+            false => Err(anyhow!("unknown key `{}`", key)),
+        };
+    };
+    let sub = cli.find_subcommand(table)
+        .ok_or_else(|| anyhow!("unknown table [{}], which is not a subcommand", table))?;
+    let local = sub.get_arguments().find(|e| configurable(e, key)).cloned();
+    let global = cli.get_arguments().find(|e| e.is_global_set() && configurable(e, key)).cloned();
+    match (local, global) {
+        (Some(arg), _) => {
+            let arg = with_default(arg, setting)?;
+            Ok(cli.mut_subcommand(table, |sub| sub.mut_arg(arg.get_id().clone(), |_| arg)))
+        }
+        (None, Some(arg)) => {
+            // Given to the subcommand alone, it is not propagated there from the top
+            let arg = with_default(arg, setting)?;
+            Ok(cli.mut_subcommand(table, |sub| sub.arg(arg)))
+        }
+        (None, None) => Err(anyhow!("unknown key `{}` in [{}]", key, table)),
+    }
+}
+
+/// The line of the byte offset `pos` in `text`, counted from 1.
+fn line_of(text: &str, pos: usize) -> usize {
+    text.get(..pos).unwrap_or(text).matches('\n').count() + 1
+}
+
+/// A value of the config file as the command line would take it: numbers are kept as written,
+/// in decimal, and dates and tables are refused.
+fn value(value: &DeValue) -> anyhow::Result<Value> {
+    Ok(match value {
+        DeValue::Boolean(on) => Value::Bool(*on),
+        DeValue::String(string) => Value::String(string.to_string()),
+        DeValue::Integer(integer) => {
+            let number = i128::from_str_radix(integer.as_str(), integer.radix())
+                .map_err(|_| anyhow!("invalid integer {}", integer))?;
+            Value::Number(number.to_string())
+        }
+        DeValue::Float(float) if float.as_str().parse::<f64>().is_ok_and(f64::is_finite) => {
+            Value::Number(float.as_str().to_string())
+        }
+        DeValue::Float(float) => bail!("expected a finite number, found {}", float.as_str()),
+        DeValue::Array(values) => Value::Array(values.iter().map(|e| self::value(e.get_ref()))
+            .collect::<anyhow::Result<Vec<Value>>>()?),
+        DeValue::Datetime(_) => bail!("expected a value, found a date"),
+        DeValue::Table(_) => bail!("expected a value, found a table"),
+    })
+}
+
+/// The settings of `entries`, the top of the file or the table `table`, in the order written.
+fn table_settings(text: &str, table: Option<&str>, entries: &DeTable,
+                  settings: &mut Vec<Setting>) -> anyhow::Result<()> {
+    for (key, entry) in entries {
+        let line = line_of(text, key.span().start);
+        match (table, entry.get_ref()) {
+            (None, DeValue::Table(entries)) => {
+                table_settings(text, Some(key.get_ref()), entries, settings)?;
+            }
+            // Only `[private."<host>"]` tables are nested
+            (Some("private"), DeValue::Table(entries)) => {
+                let host = format!("private.{}", key.get_ref());
+                table_settings(text, Some(&host), entries, settings)?;
+            }
+            (Some(table), DeValue::Table(_)) => {
+                bail!("line {}: [{}] cannot hold the table {}", line, table, key.get_ref());
+            }
+            (_, entry) => {
+                let key = key.get_ref().replace('_', "-");
+                let value = value(entry).with_context(|| format!("line {}", line))?;
+                if settings.iter().any(|e| e.table.as_deref() == table && e.key == key) {
+                    bail!("line {}: `{}` is set twice", line, key);
+                }
+                settings.push(Setting { table: table.map(String::from), key, value, line });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the settings of a TOML config file: `key = value` lines at the top and in `[table]`s.
+fn parse(text: &str) -> anyhow::Result<Vec<Setting>> {
+    let document = DeTable::parse(text).map_err(|e| {
+        let line = e.span().map_or(1, |span| line_of(text, span.start));
+        anyhow!("line {}: {}", line, e.message())
+    })?;
+    let mut settings = Vec::new();
+    table_settings(text, None, document.get_ref(), &mut settings)?;
+    Ok(settings)
+}
@@ -0,0 +1,100 @@
+use crate::scan::ExpectedSet;
+use crate::truncate_message;
+use indicatif::ProgressBar;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+
+/// Bytes read from each end of a file for the quick comparison.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+/// An extra whose content is identical to a file of the torrent that is present on disk.
+pub struct Duplicate {
+    pub extra: PathBuf,
+    pub kept: PathBuf,
+    pub size: u64,
+}
+
+/// Hash of the first and last [`SAMPLE_SIZE`] bytes, enough to tell most different files apart.
+fn quick_hash(path: &Path, size: u64) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut buf = Vec::new();
+    file.by_ref().take(SAMPLE_SIZE).read_to_end(&mut buf)?;
+    if size > SAMPLE_SIZE {
+        file.seek(SeekFrom::Start(size.saturating_sub(SAMPLE_SIZE).max(SAMPLE_SIZE)))?;
+        file.take(SAMPLE_SIZE).read_to_end(&mut buf)?;
+    }
+    Ok(xxh3_64(&buf))
+}
+
+fn full_hash(path: &Path) -> io::Result<u128> {
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hasher.digest128());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+/// Finds the `extras` under `dir` that duplicate a present torrent file, comparing a quick hash
+/// first and confirming with a hash of the whole content. Empty files are never matched.
+pub fn find_duplicates_of_kept(dir: &Path, expected: &impl ExpectedSet, extras: &[PathBuf],
+                               progress: &ProgressBar) -> anyhow::Result<Vec<Duplicate>> {
+    let mut kept_by_size = HashMap::<u64, Vec<PathBuf>>::new();
+    for (path, size) in expected.files() {
+        let kept = expected.locate(dir, path);
+        if size > 0 && fs::metadata(&kept).is_ok_and(|m| m.is_file() && m.len() == size) {
+            kept_by_size.entry(size).or_default().push(kept);
+        }
+    }
+
+    let mut quick = HashMap::<PathBuf, u64>::new();
+    let mut full = HashMap::<PathBuf, u128>::new();
+    let mut cached_quick = |path: &Path, size: u64| -> io::Result<u64> {
+        if let Some(hash) = quick.get(path) {
+            return Ok(*hash);
+        }
+        let hash = quick_hash(path, size)?;
+        quick.insert(path.to_path_buf(), hash);
+        Ok(hash)
+    };
+    let mut duplicates = Vec::new();
+    for extra in extras {
+        let Ok(meta) = fs::symlink_metadata(extra) else { continue };
+        let Some(candidates) = kept_by_size.get(&meta.len()).filter(|_| meta.is_file()) else {
+            continue;
+        };
+        progress.set_message(truncate_message(format!("Hashing: {}", extra.display())));
+        let extra_quick = cached_quick(extra, meta.len())?;
+        let mut extra_hash = None;
+        for kept in candidates {
+            if cached_quick(kept, meta.len())? != extra_quick {
+                continue;
+            }
+            let kept_full = match full.get(kept) {
+                Some(hash) => *hash,
+                None => {
+                    let hash = full_hash(kept)?;
+                    full.insert(kept.clone(), hash);
+                    hash
+                }
+            };
+            let extra_full = match extra_hash {
+                Some(hash) => hash,
+                None => *extra_hash.insert(full_hash(extra)?),
+            };
+            if kept_full == extra_full {
+                duplicates.push(Duplicate { extra: extra.clone(), kept: kept.clone(), size: meta.len() });
+                break;
+            }
+        }
+        progress.inc(1);
+    }
+    Ok(duplicates)
+}
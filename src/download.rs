@@ -0,0 +1,79 @@
+use crate::error::{CliError, ErrorCode};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Torrent files are small; a larger body is rather an error page or the wrong link.
+const MAX_SIZE: u64 = 50 * 1024 * 1024;
+
+static TIMEOUT_SECS: AtomicU64 = AtomicU64::new(60);
+
+/// How long downloading a torrent URL may take, as given by `--timeout`.
+pub fn set_timeout(timeout: Duration) {
+    TIMEOUT_SECS.store(timeout.as_secs().max(1), Ordering::Relaxed);
+}
+
+/// The `--timeout` of network requests, downloads and the Web API of the client alike.
+pub fn timeout() -> Duration {
+    Duration::from_secs(TIMEOUT_SECS.load(Ordering::Relaxed))
+}
+
+/// The URL `input` is if it is an `http://` or `https://` one rather than an existing file.
+pub fn torrent_url(input: &Path) -> Option<&str> {
+    let text = input.to_str()?;
+    let scheme = text.split_once("://")?.0;
+    (["http", "https"].iter().any(|e| scheme.eq_ignore_ascii_case(e)) && !input.exists())
+        .then_some(text)
+}
+
+fn error(url: &str, message: String) -> CliError {
+    CliError::new(ErrorCode::TorrentUnreadable, message).with_path(url)
+}
+
+/// Whether `error`, from a request or reading its body, is the timeout running out.
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+}
+
+/// Downloads `url` into memory, following redirects.
+pub fn download(url: &str) -> anyhow::Result<Vec<u8>> {
+    let timeout = timeout();
+    let timed_out = || error(url, format!("Timed out after {}s downloading {}; raise it with \
+        --timeout", timeout.as_secs(), url));
+    let too_large = || error(url, format!("{} is larger than {} MiB, too large for a torrent file",
+                                          url, MAX_SIZE >> 20));
+    let agent = ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .user_agent(concat!("torrent-cleaner/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let response = match agent.get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, _)) => {
+            return Err(error(url, format!("Failed to download {}: the server answered with HTTP \
+                status {}", url, status)).into());
+        }
+        Err(ureq::Error::Transport(e)) => {
+            let source = std::error::Error::source(&e).and_then(|e| e.downcast_ref::<io::Error>());
+            if source.is_some_and(is_timeout) {
+                return Err(timed_out().into());
+            }
+            return Err(error(url, format!("Failed to download {}: {}", url, e)).into());
+        }
+    };
+    if response.header("Content-Length").and_then(|e| e.parse::<u64>().ok())
+        .is_some_and(|e| e > MAX_SIZE) {
+        return Err(too_large().into());
+    }
+    // One byte past the limit tells a body over it from one just at it
+    let mut body = Vec::new();
+    let read = response.into_reader().take(MAX_SIZE + 1).read_to_end(&mut body);
+    read.map_err(|e| match is_timeout(&e) {
+        true => timed_out(),
+        false => error(url, format!("Failed to download {}: {}", url, e)),
+    })?;
+    if body.len() as u64 > MAX_SIZE {
+        return Err(too_large().into());
+    }
+    Ok(body)
+}
@@ -0,0 +1,124 @@
+use crate::scan::ExpectedSet;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// File name suffixes torrent clients give their partial and bookkeeping files, with the client.
+/// Without the suffix, the name is that of the torrent file it belonged to, if any.
+const SUFFIXES: &[(&str, &str)] = &[
+    (".!qB", "qBittorrent"),
+    // libtorrent's part files, `.<info hash>.parts`
+    (".parts", "qBittorrent"),
+    (".part", "Transmission"),
+    (".resume", "Transmission"),
+    (".libtorrent_resume", "rTorrent"),
+    (".rtorrent", "rTorrent"),
+    (".!ut", "µTorrent"),
+    (".bc!", "BitComet"),
+];
+
+/// The folder qBittorrent moves the files of a torrent that are not downloaded into, next to
+/// where they belong.
+const UNWANTED: &str = ".unwanted";
+
+/// The client `path` looks like a dropping of, with the path it has without the suffix.
+fn strip(path: &Path) -> Option<(&'static str, PathBuf)> {
+    let name = path.file_name()?.to_str()?;
+    SUFFIXES.iter().find_map(|(suffix, client)| {
+        let stem = name.len().checked_sub(suffix.len()).filter(|e| *e > 0)?;
+        name.get(stem..)?.eq_ignore_ascii_case(suffix)
+            .then(|| (*client, path.with_file_name(&name[..stem])))
+    })
+}
+
+/// The client whose partial or bookkeeping file `path` looks like, going by its name alone.
+///
+/// ```
+/// use std::path::Path;
+/// use torrent_cleaner::droppings::client_of;
+///
+/// assert_eq!(client_of(Path::new("Season1/ep2.mkv.!qB")), Some("qBittorrent"));
+/// assert_eq!(client_of(Path::new(".7b5e81a362842d40.parts")), Some("qBittorrent"));
+/// assert_eq!(client_of(Path::new("Season1/ep2.mkv.part")), Some("Transmission"));
+/// assert_eq!(client_of(Path::new("Show.7b5e81a362842d40.resume")), Some("Transmission"));
+/// assert_eq!(client_of(Path::new("7B5E81A3.torrent.libtorrent_resume")), Some("rTorrent"));
+/// assert_eq!(client_of(Path::new("7B5E81A3.torrent.rtorrent")), Some("rTorrent"));
+/// assert_eq!(client_of(Path::new("Season1/ep2.mkv.!ut")), Some("µTorrent"));
+/// assert_eq!(client_of(Path::new("Season1/ep2.mkv.bc!")), Some("BitComet"));
+/// assert_eq!(client_of(Path::new("Season1/ep2.mkv")), None);
+/// assert_eq!(client_of(Path::new(".part")), None);
+/// ```
+pub fn client_of(path: &Path) -> Option<&'static str> {
+    strip(path).map(|(client, _)| client)
+}
+
+/// Whether `path`, relative to the content directory, is a client dropping that no longer
+/// belongs to a file of the torrent.
+pub fn is_orphaned(path: &Path, expected: &impl ExpectedSet) -> bool {
+    strip(path).is_some_and(|(_, file)| expected.file_size(&file).is_none())
+}
+
+/// The file `path` becomes once its download completes, with the client downloading it: the path
+/// without the client's suffix, taken out of a `.unwanted` folder.
+///
+/// ```
+/// use std::path::{Path, PathBuf};
+/// use torrent_cleaner::droppings::download_of;
+///
+/// assert_eq!(download_of(Path::new("Season1/ep2.mkv.!qB")),
+///            Some(("qBittorrent", PathBuf::from("Season1/ep2.mkv"))));
+/// assert_eq!(download_of(Path::new("Season1/.unwanted/ep2.mkv")),
+///            Some(("qBittorrent", PathBuf::from("Season1/ep2.mkv"))));
+/// assert_eq!(download_of(Path::new(".unwanted/ep2.mkv.part")),
+///            Some(("Transmission", PathBuf::from("ep2.mkv"))));
+/// assert_eq!(download_of(Path::new("Season1/ep2.mkv")), None);
+/// ```
+pub fn download_of(path: &Path) -> Option<(&'static str, PathBuf)> {
+    let unwanted = path.parent().filter(|e| e.file_name() == Some(OsStr::new(UNWANTED)));
+    let moved = match unwanted {
+        Some(folder) => folder.with_file_name(path.file_name()?),
+        None => path.to_path_buf(),
+    };
+    match strip(&moved) {
+        Some(download) => Some(download),
+        None => unwanted.map(|_| ("qBittorrent", moved)),
+    }
+}
+
+/// Whether `path`, relative to the content directory, is the download in progress of a file of
+/// the torrent.
+pub fn is_in_progress(path: &Path, expected: &impl ExpectedSet) -> bool {
+    download_of(path).is_some_and(|(_, file)| expected.file_size(&file).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{client_of, is_in_progress, is_orphaned};
+    use crate::scan::Expected;
+    use std::path::Path;
+
+    #[test]
+    fn droppings_are_told_by_their_client_and_file() {
+        let expected = Expected::from_files([(vec!["Season1".into(), "ep1.mkv".into()], 3)])
+            .expect("file set");
+        // Path, the client, and whether it is orphaned or the download of a file of the torrent
+        let cases = [
+            ("Season1/ep1.mkv.resume", Some("Transmission"), false, true),
+            ("Season1/ep2.mkv.resume", Some("Transmission"), true, false),
+            ("Season1/ep1.mkv.part", Some("Transmission"), false, true),
+            ("Season1/ep2.mkv.part", Some("Transmission"), true, false),
+            ("Season1/ep1.mkv.!qB", Some("qBittorrent"), false, true),
+            ("Season1/ep2.mkv.!qB", Some("qBittorrent"), true, false),
+            ("Season1/EP2.MKV.!QB", Some("qBittorrent"), true, false),
+            ("Show.torrent.libtorrent_resume", Some("rTorrent"), true, false),
+            ("Season1/ep1.mkv", None, false, false),
+            ("Season1/ep2.nfo", None, false, false),
+            (".part", None, false, false),
+        ];
+        for (path, client, orphaned, in_progress) in cases {
+            let path = Path::new(path);
+            assert_eq!(client_of(path), client, "{}", path.display());
+            assert_eq!(is_orphaned(path, &expected), orphaned, "{}", path.display());
+            assert_eq!(is_in_progress(path, &expected), in_progress, "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::scan::ExpectedSet;
+use crate::stage::{move_file, MoveOptions};
+use anyhow::Context;
+use indicatif::ProgressBar;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// A sibling of the content directory named like a client-made copy of it, e.g. `Show (1)`.
+pub struct DuplicateRoot {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Expected files present in it with the expected size.
+    pub present: usize,
+}
+
+/// Whether `name` is `base` followed by a ` (N)` or `_N` suffix.
+fn is_duplicate_name(name: &str, base: &str) -> bool {
+    let Some(suffix) = name.strip_prefix(base) else { return false };
+    let digits = suffix.strip_prefix(" (").and_then(|e| e.strip_suffix(')'))
+        .or_else(|| suffix.strip_prefix('_'));
+    digits.is_some_and(|e| !e.is_empty() && e.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Expected files found under `root` with the size the torrent declares.
+pub fn present_files(root: &Path, expected: &impl ExpectedSet) -> usize {
+    expected.matching(root).0
+}
+
+/// Lists the duplicate roots next to `dir`.
+pub fn find_duplicate_roots(dir: &Path, expected: &impl ExpectedSet) -> Vec<DuplicateRoot> {
+    let (Some(parent), Some(base)) = (dir.parent(), dir.file_name()) else { return Vec::new() };
+    let base = base.to_string_lossy();
+    let Ok(entries) = fs::read_dir(parent) else { return Vec::new() };
+    let mut roots = entries.flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .filter(|e| is_duplicate_name(&e.file_name().to_string_lossy(), &base))
+        .map(|e| {
+            let path = e.path();
+            let size = WalkDir::new(&path).into_iter().flatten()
+                .filter(|e| e.file_type().is_file())
+                .filter_map(|e| e.metadata().ok())
+                .map(|e| e.len())
+                .sum();
+            let present = present_files(&path, expected);
+            DuplicateRoot { path, size, present }
+        })
+        .collect::<Vec<DuplicateRoot>>();
+    roots.sort_by(|a, b| a.path.cmp(&b.path));
+    roots
+}
+
+/// Moves the expected files that `dir` lacks from `duplicate` into `dir`, returning how many
+/// were moved.
+pub fn merge_into(duplicate: &Path, dir: &Path, expected: &impl ExpectedSet, options: MoveOptions)
+                  -> anyhow::Result<usize> {
+    let mut count = 0;
+    for (path, size) in expected.files() {
+        let target = expected.locate(dir, path);
+        let source = expected.locate(duplicate, path);
+        if target.exists() || !fs::metadata(&source).is_ok_and(|m| m.is_file() && m.len() == size) {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        move_file(&source, &target, options, &ProgressBar::hidden())
+            .with_context(|| format!("Failed to move {} into {}", source.display(), dir.display()))?;
+        count += 1;
+    }
+    Ok(count)
+}
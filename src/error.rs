@@ -0,0 +1,127 @@
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Exit code of a run that failed with an error, which sets it apart from the 1 of a removal
+/// declined or of differences found, as `diff(1)` does.
+pub const EXIT_FAILED: u8 = 2;
+
+/// Machine-readable error categories reported in JSON output mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The .torrent file could not be opened or read.
+    TorrentUnreadable,
+    /// The .torrent file is not valid bencoded metainfo.
+    TorrentInvalid,
+    /// The torrent is valid but not of a supported kind.
+    TorrentUnsupported,
+    /// The torrent has paths that could lead outside of the directory, without
+    /// `--allow-unsafe-paths`.
+    UnsafePaths,
+    /// The target directory does not exist or is not a directory.
+    DirNotFound,
+    /// The target directory looks like a mount point with nothing mounted.
+    TargetUnmounted,
+    /// The torrent looks like it is still downloading into the target directory.
+    TargetActive,
+    /// The target directory could not be traversed.
+    ScanFailed,
+    /// One or more entries could not be removed.
+    RemoveFailed,
+    /// One or more entries of a batch run failed.
+    BatchFailed,
+    /// A `--pre-delete-hook` rejected the removal or timed out.
+    HookRejected,
+    /// Files were left in place because their destination lacked the space for them.
+    InsufficientSpace,
+    /// The torrent describes no files once padding is left out, so everything would be extra.
+    EmptyExpected,
+    /// The removal exceeds `--max-delete-ratio` or `--max-delete-size`, or none of the torrent's
+    /// files were found.
+    ThresholdExceeded,
+    /// Ctrl-C stopped the removals before all entries were removed.
+    Interrupted,
+    /// The `--client` could not tell the labels of the torrent that `--skip-label`,
+    /// `--skip-category` or `--only-label` need.
+    ClientFailed,
+    /// The SFTP connection to a remote directory could not be made, or was lost during the run.
+    RemoteFailed,
+    /// Anything not covered above.
+    Internal,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntryError {
+    pub path: String,
+    pub message: String,
+}
+
+/// An error carrying a machine-readable [`ErrorCode`], optionally attached to a path
+/// and to the per-entry failures that caused it.
+#[derive(Debug)]
+pub struct CliError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub path: Option<PathBuf>,
+    pub entries: Vec<EntryError>,
+}
+
+impl CliError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), path: None, entries: Vec::new() }
+    }
+
+    pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn with_entry(mut self, path: impl AsRef<Path>, message: impl fmt::Display) -> Self {
+        self.entries.push(EntryError {
+            path: path.as_ref().to_string_lossy().into_owned(),
+            message: message.to_string(),
+        });
+        self
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    code: ErrorCode,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    errors: &'a [EntryError],
+}
+
+#[derive(Serialize)]
+struct ErrorDocument<'a> {
+    error: ErrorBody<'a>,
+}
+
+/// Renders an error as the single-line JSON object emitted in JSON output mode.
+pub fn error_json(err: &anyhow::Error) -> String {
+    let cli = err.chain().find_map(|e| e.downcast_ref::<CliError>());
+    // Keep the full context chain in the message so nothing is lost compared to human output
+    let message = err.chain().map(|e| e.to_string()).collect::<Vec<String>>().join(": ");
+    let body = match cli {
+        Some(cli) => ErrorBody {
+            code: cli.code,
+            message,
+            path: cli.path.as_ref().map(|p| p.to_string_lossy().into_owned()),
+            errors: &cli.entries,
+        },
+        None => ErrorBody { code: ErrorCode::Internal, message, path: None, errors: &[] },
+    };
+    serde_json::to_string(&ErrorDocument { error: body }).expect("serializable")
+}
@@ -0,0 +1,84 @@
+use crate::output::logging;
+use crate::scan::{ExpectedSet, Scan, ScanOptions, SkipReason, IGNORE_FILE};
+use crate::verbose;
+use indicatif::BinaryBytes;
+use std::fs;
+use std::path::Path;
+
+/// Says how the scan of `dir` went with `-v`, and with `-vv` what it made of each entry: the
+/// torrent's files it found, and why each other entry is planned for removal or left alone.
+pub fn explain_scan(dir: &Path, expected: &impl ExpectedSet, scan: &Scan, options: &ScanOptions) {
+    verbose!(1, "Scanned {}: {} extra files ({}), {} empty directories, {} entries left alone",
+             dir.display(), scan.old_files.len(), BinaryBytes(scan.rm_size),
+             scan.empty_dirs.len() + scan.emptied_dirs.len(), scan.skipped.len());
+    if !logging(2) {
+        return;
+    }
+    let rel = |path: &Path| path.strip_prefix(dir).unwrap_or(path).display().to_string();
+    let scope = |path: &Path| match path.strip_prefix(dir).ok().and_then(|e| e.iter().next()) {
+        Some(first) if expected.is_surface(first) => {
+            format!("under the torrent's top-level entry {}", first.to_string_lossy())
+        }
+        _ => "outside the torrent's top-level entries, with --surface".to_string(),
+    };
+    let junk = |path: &Path| match options.junk.matches(path.strip_prefix(dir).unwrap_or(path)) {
+        true => ", junk",
+        false => "",
+    };
+
+    let mut files = expected.files().collect::<Vec<(&Path, u64)>>();
+    files.sort();
+    for (path, size) in files {
+        let disk = expected.locate(dir, path);
+        match fs::metadata(&disk) {
+            Ok(meta) if meta.len() == size => verbose!(2, "match    {}: file of the torrent",
+                                                       rel(&disk)),
+            Ok(meta) => verbose!(2, "mismatch {}: {} bytes where the torrent has {}", rel(&disk),
+                                 meta.len(), size),
+            Err(_) => verbose!(2, "missing  {}: file of the torrent", path.display()),
+        }
+    }
+    for file in &scan.old_files {
+        match expected.file_size(file.strip_prefix(dir).unwrap_or(file)) {
+            Some(_) => verbose!(2, "extra    {}: file of the torrent with another size, with \
+                                    --remove-mismatched", rel(file)),
+            None => verbose!(2, "extra    {}: not a file of the torrent, {}{}", rel(file),
+                             scope(file), junk(file)),
+        }
+    }
+    for empty in &scan.empty_dirs {
+        verbose!(2, "extra    {}/: empty directory, {}{}", rel(empty), scope(empty), junk(empty));
+    }
+    for emptied in &scan.emptied_dirs {
+        verbose!(2, "extra    {}/: left empty by the removals, {}{}", rel(emptied), scope(emptied),
+                 junk(emptied));
+    }
+    for skipped in &scan.skipped {
+        let first = skipped.path.strip_prefix(dir).ok().and_then(|e| e.iter().next());
+        match (skipped.reason, first) {
+            (SkipReason::OutOfScope, Some(first)) => {
+                verbose!(2, "skipped  {}: outside scope, the torrent has no top-level entry {}",
+                         rel(&skipped.path), first.to_string_lossy())
+            }
+            (SkipReason::Excluded, _) => {
+                let path = skipped.path.strip_prefix(dir).unwrap_or(&skipped.path);
+                let is_dir = fs::symlink_metadata(&skipped.path).is_ok_and(|e| e.is_dir());
+                match options.exclude.matched(path, is_dir) {
+                    _ if path == Path::new(IGNORE_FILE) => {
+                        verbose!(2, "skipped  {}: excluded, the ignore file itself", rel(path))
+                    }
+                    Some(pattern) => verbose!(2, "skipped  {}: excluded by {}", rel(path), pattern),
+                    None => verbose!(2, "skipped  {}: excluded", rel(path)),
+                }
+            }
+            (reason, _) => verbose!(2, "skipped  {}: {}", rel(&skipped.path), reason.describe()),
+        }
+    }
+    for kept in &scan.kept_dirs {
+        match &kept.blocker {
+            Some(blocker) => verbose!(2, "kept     {}/: {} ({})", rel(&kept.path),
+                                      kept.reason.describe(), rel(blocker)),
+            None => verbose!(2, "kept     {}/: {}", rel(&kept.path), kept.reason.describe()),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::scan::Scan;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How `diff --group-dirs` orders its lines.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum GroupOrder {
+    /// Largest first.
+    Size,
+    Path,
+}
+
+/// A line of `diff --group-dirs`.
+pub enum Group {
+    /// A directory holding nothing but entries to remove, with how many there are and their size.
+    Dir { path: PathBuf, entries: usize, size: u64 },
+    /// An entry to remove in a directory that also keeps something.
+    Entry { path: PathBuf, size: u64 },
+}
+
+impl Group {
+    pub fn path(&self) -> &Path {
+        match self {
+            Group::Dir { path, .. } | Group::Entry { path, .. } => path,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        match self {
+            Group::Dir { size, .. } | Group::Entry { size, .. } => *size,
+        }
+    }
+}
+
+/// Folds the entries `scan` removes into the outermost directories below `dir` that hold
+/// nothing else, listing the others one by one.
+pub fn group_dirs(dir: &Path, scan: &Scan, order: GroupOrder) -> Vec<Group> {
+    let removed = scan.old_files.iter().chain(&scan.empty_dirs).chain(&scan.emptied_dirs)
+        .collect::<HashSet<&PathBuf>>();
+    // The removals below each directory, itself included, with their size
+    let mut below = HashMap::<&Path, (usize, u64)>::new();
+    for &entry in &removed {
+        let size = scan.stamps.get(entry).map_or(0, |e| e.size);
+        for parent in entry.ancestors().take_while(|e| *e != dir) {
+            let total = below.entry(parent).or_default();
+            total.0 += 1;
+            total.1 += size;
+        }
+    }
+    let mut children = HashMap::<&Path, Vec<&Path>>::new();
+    for &path in below.keys() {
+        if let Some(parent) = path.parent() {
+            children.entry(parent).or_default().push(path);
+        }
+    }
+
+    let mut groups = Vec::new();
+    let mut pending = vec![dir];
+    let mut pure = HashMap::new();
+    while let Some(parent) = pending.pop() {
+        for &path in children.get(parent).into_iter().flatten() {
+            let (entries, size) = below[path];
+            let is_dir = path.is_dir() && !path.is_symlink();
+            // A removal that is a directory as a whole, or an empty one, is listed as itself
+            if !is_dir || !children.contains_key(path) {
+                groups.push(Group::Entry { path: path.to_owned(), size });
+            } else if holds_only(path, &removed, &mut pure) {
+                groups.push(Group::Dir { path: path.to_owned(), entries, size });
+            } else {
+                pending.push(path);
+            }
+        }
+    }
+    match order {
+        GroupOrder::Size => groups.sort_by(|a, b| b.size().cmp(&a.size())
+            .then_with(|| a.path().cmp(b.path()))),
+        GroupOrder::Path => groups.sort_by(|a, b| a.path().cmp(b.path())),
+    }
+    groups
+}
+
+/// Whether everything inside the directory at `path` is one of `entries`, directories holding
+/// nothing else aside. One that cannot be listed may hold anything.
+pub fn holds_only(path: &Path, entries: &HashSet<&PathBuf>, known: &mut HashMap<PathBuf, bool>)
+                  -> bool {
+    if let Some(&pure) = known.get(path) {
+        return pure;
+    }
+    let pure = fs::read_dir(path).is_ok_and(|mut listed| listed.all(|entry| {
+        let Ok(entry) = entry else { return false };
+        let path = entry.path();
+        entries.contains(&path)
+            || entry.file_type().is_ok_and(|e| e.is_dir()) && holds_only(&path, entries, known)
+    }));
+    known.insert(path.to_owned(), pure);
+    pure
+}
@@ -0,0 +1,97 @@
+use anyhow::{anyhow, Context};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Summary of a single diff or clean run, stored one JSON object per line.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub command: String,
+    pub info_hash: String,
+    pub torrent: String,
+    pub dir: PathBuf,
+    pub extras: usize,
+    pub extras_size: u64,
+    pub missing: usize,
+    pub removed: usize,
+    pub removed_size: u64,
+    pub failures: usize,
+}
+
+impl RunRecord {
+    pub fn new(command: &str, info_hash: String, torrent: String, dir: &Path) -> Self {
+        Self {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            command: command.to_string(),
+            info_hash,
+            torrent,
+            dir: dir.to_path_buf(),
+            extras: 0,
+            extras_size: 0,
+            missing: 0,
+            removed: 0,
+            removed_size: 0,
+            failures: 0,
+        }
+    }
+
+    pub fn time(&self) -> String {
+        humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(self.timestamp))
+            .to_string()
+    }
+
+    pub fn date(&self) -> String {
+        self.time()[..10].to_string()
+    }
+}
+
+pub fn history_file() -> anyhow::Result<PathBuf> {
+    let dirs = ProjectDirs::from("", "", "torrent-cleaner")
+        .ok_or(anyhow!("No home directory available for the run history"))?;
+    Ok(dirs.data_dir().join("history.jsonl"))
+}
+
+/// Reads all records, oldest first. Lines that fail to parse are skipped.
+pub fn load_history(file: &Path) -> anyhow::Result<Vec<RunRecord>> {
+    match fs::read_to_string(file) {
+        Ok(content) => Ok(content.lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read run history {}", file.display())),
+    }
+}
+
+/// The most recent record for the same torrent and directory.
+pub fn last_run<'a>(records: &'a [RunRecord], info_hash: &str, dir: &Path) -> Option<&'a RunRecord> {
+    records.iter().rev().find(|e| e.info_hash == info_hash && e.dir == dir)
+}
+
+/// Appends `record`, dropping the oldest records beyond `keep`.
+pub fn record_run(file: &Path, record: &RunRecord, keep: usize) -> anyhow::Result<()> {
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    let mut out = OpenOptions::new().create(true).append(true).open(file)
+        .with_context(|| format!("Failed to open run history {}", file.display()))?;
+    writeln!(out, "{}", serde_json::to_string(record)?)?;
+    drop(out);
+
+    let records = load_history(file)?;
+    if records.len() > keep {
+        let mut content = String::new();
+        for record in &records[records.len() - keep..] {
+            content.push_str(&serde_json::to_string(record)?);
+            content.push('\n');
+        }
+        fs::write(file, content)
+            .with_context(|| format!("Failed to write run history {}", file.display()))?;
+    }
+    Ok(())
+}
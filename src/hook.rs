@@ -0,0 +1,161 @@
+use crate::error::{CliError, ErrorCode};
+use crate::report::entry_id;
+use crate::scan::Stamp;
+use crate::truncate_message;
+use crate::warnings::{warn, Warning, WarningCode};
+use anyhow::Context;
+use indicatif::ProgressBar;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `--filter-hook` runs once per file, so above this many it warns that the run will be slow.
+const FILTER_HOOK_WARN: usize = 10_000;
+
+/// One line of the JSON Lines input of a hook.
+#[derive(Serialize)]
+struct HookEntry {
+    /// See [`entry_id`].
+    id: String,
+    /// Path relative to the content directory, `/`-separated.
+    path: String,
+    size: u64,
+    kind: &'static str,
+}
+
+/// Runs `--pre-delete-hook` and `--filter-hook` commands through the shell, with `TC_TORRENT_NAME`,
+/// `TC_INFOHASH` and `TC_DIR` set and their output sent to stderr.
+pub struct Hooks {
+    root: PathBuf,
+    info_hash: String,
+    env: Vec<(&'static str, String)>,
+    timeout: Duration,
+}
+
+impl Hooks {
+    pub fn new(name: &str, info_hash: &str, root: &Path, timeout: Duration) -> Self {
+        let env = vec![
+            ("TC_TORRENT_NAME", name.to_string()),
+            ("TC_INFOHASH", info_hash.to_string()),
+            ("TC_DIR", root.to_string_lossy().into_owned()),
+        ];
+        Self { root: root.to_path_buf(), info_hash: info_hash.to_string(), env, timeout }
+    }
+
+    fn entry_line(&self, entry: &Path) -> String {
+        let meta = fs::symlink_metadata(entry).ok();
+        let rel = entry.strip_prefix(&self.root).unwrap_or(entry);
+        let line = HookEntry {
+            id: entry_id(&self.info_hash, rel),
+            path: rel.iter().map(|e| e.to_string_lossy()).collect::<Vec<_>>().join("/"),
+            size: Stamp::read(entry).map_or(0, |e| e.size),
+            kind: match meta.is_some_and(|m| m.is_dir()) {
+                true => "dir",
+                false => "file",
+            },
+        };
+        serde_json::to_string(&line).expect("serializable")
+    }
+
+    #[cfg(windows)]
+    fn command(&self, cmd: &str, args: &[&Path]) -> Command {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(cmd).args(args);
+        command
+    }
+
+    #[cfg(not(windows))]
+    fn command(&self, cmd: &str, args: &[&Path]) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(format!("{} \"$@\"", cmd)).arg("sh").args(args);
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        command
+    }
+
+    /// Kills the hook together with anything it started.
+    fn kill(child: &mut Child) {
+        #[cfg(unix)]
+        // SAFETY: the child leads its own process group, see `command`
+        unsafe {
+            libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    /// Runs `cmd` with `input` on its stdin, returning whether it succeeded, or `None` if it was
+    /// killed for running longer than the timeout.
+    fn run(&self, cmd: &str, args: &[&Path], input: String) -> anyhow::Result<Option<bool>> {
+        let mut child = self.command(cmd, args)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::piped())
+            .stdout(io::stderr())
+            .spawn()
+            .with_context(|| format!("Failed to run hook: {}", cmd))?;
+        let mut stdin = child.stdin.take().expect("piped");
+        // Written from a thread so that a hook which does not read its input cannot block the run
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let deadline = Instant::now() + self.timeout;
+        let mut wait = Duration::from_millis(1);
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(Some(status.success()));
+            }
+            if Instant::now() >= deadline {
+                Self::kill(&mut child);
+                return Ok(None);
+            }
+            thread::sleep(wait);
+            wait = (wait * 2).min(Duration::from_millis(50));
+        }
+    }
+
+    /// Runs `cmd` once with every entry about to be removed; any failure vetoes the removal.
+    pub fn pre_delete<'a>(&self, cmd: &str, entries: impl Iterator<Item = &'a PathBuf>)
+                          -> anyhow::Result<()> {
+        let input = entries.map(|e| self.entry_line(e) + "\n").collect::<String>();
+        let message = match self.run(cmd, &[], input)? {
+            Some(true) => return Ok(()),
+            Some(false) => "The pre-delete hook rejected the removal".to_string(),
+            None => format!("The pre-delete hook timed out after {}",
+                            humantime::format_duration(self.timeout)),
+        };
+        Err(CliError::new(ErrorCode::HookRejected, message).into())
+    }
+
+    /// Runs `cmd` once per file, with its path as the argument and its entry on stdin, returning
+    /// the files it exits non-zero for or times out on.
+    pub fn filter(&self, cmd: &str, files: &[PathBuf], progress: &ProgressBar)
+                  -> anyhow::Result<HashSet<PathBuf>> {
+        if files.len() > FILTER_HOOK_WARN {
+            eprintln!("{}", warn(WarningCode::Hook,
+                                 format!("--filter-hook runs once for each of the {} files",
+                                         files.len())));
+        }
+        let mut vetoed = HashSet::new();
+        for file in files {
+            progress.set_message(truncate_message(format!("Filtering: {}", file.display())));
+            match self.run(cmd, &[file], self.entry_line(file) + "\n")? {
+                Some(true) => {}
+                Some(false) => {
+                    vetoed.insert(file.clone());
+                }
+                None => {
+                    let warning = Warning::new(WarningCode::Hook, format!(
+                        "the filter hook timed out on {}; keeping it", file.display()))
+                        .at(file).record();
+                    progress.suspend(|| eprintln!("{}", warning));
+                    vetoed.insert(file.clone());
+                }
+            }
+            progress.inc(1);
+        }
+        Ok(vetoed)
+    }
+}
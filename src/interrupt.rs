@@ -0,0 +1,118 @@
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Exit status after Ctrl-C, the one shells give a process killed by SIGINT.
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+/// Set once Ctrl-C is to let the run wrap up rather than exit at once.
+static DEFERRED: AtomicBool = AtomicBool::new(false);
+static CLEAR_LINE: AtomicBool = AtomicBool::new(false);
+
+/// Handles Ctrl-C from now on. Until [`start_removing`] or [`start_watching`] it exits at once;
+/// afterwards the first one only sets [`interrupted`], so that the removal in progress finishes
+/// and the run can report what was done, and a second one exits at once.
+pub fn handle_ctrl_c() {
+    CLEAR_LINE.store(io::stderr().is_terminal(), Ordering::Relaxed);
+    install_handler();
+}
+
+/// Marks the start of the removals, which Ctrl-C lets finish their current entry.
+pub fn start_removing() {
+    DEFERRED.store(true, Ordering::Relaxed);
+}
+
+/// Marks the start of `diff --watch`, which Ctrl-C stops with the last comparison.
+pub fn start_watching() {
+    DEFERRED.store(true, Ordering::Relaxed);
+}
+
+/// Whether Ctrl-C was pressed since the removals or the watch started; no further entry is
+/// removed then.
+pub fn interrupted() -> bool {
+    INTERRUPTS.load(Ordering::Relaxed) > 0
+}
+
+/// What the handler does with a Ctrl-C; only async-signal-safe calls may happen here.
+fn on_interrupt() {
+    let count = INTERRUPTS.fetch_add(1, Ordering::Relaxed) + 1;
+    if !DEFERRED.load(Ordering::Relaxed) || count > 1 {
+        if CLEAR_LINE.load(Ordering::Relaxed) {
+            // Wipe the progress bar's line so the prompt starts on a clean one
+            write_stderr(b"\r\x1b[2K");
+        }
+        exit_now();
+    }
+}
+
+#[cfg(unix)]
+fn install_handler() {
+    extern "C" fn handler(_: libc::c_int) {
+        on_interrupt();
+    }
+
+    // SAFETY: the handler only touches atomics and makes async-signal-safe calls
+    unsafe {
+        let mut action = std::mem::zeroed::<libc::sigaction>();
+        action.sa_sigaction = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
+}
+
+#[cfg(unix)]
+fn write_stderr(bytes: &[u8]) {
+    // SAFETY: write(2) on a valid buffer is async-signal-safe
+    unsafe { libc::write(libc::STDERR_FILENO, bytes.as_ptr().cast(), bytes.len()) };
+}
+
+#[cfg(unix)]
+fn exit_now() -> ! {
+    // SAFETY: _exit skips the atexit handlers and buffers that a signal handler must not run
+    unsafe { libc::_exit(EXIT_INTERRUPTED.into()) }
+}
+
+#[cfg(windows)]
+fn install_handler() {
+    use windows_sys::Win32::Foundation::BOOL;
+    use windows_sys::Win32::System::Console::{SetConsoleCtrlHandler, CTRL_BREAK_EVENT,
+                                              CTRL_C_EVENT};
+
+    unsafe extern "system" fn handler(kind: u32) -> BOOL {
+        match kind {
+            CTRL_C_EVENT | CTRL_BREAK_EVENT => {
+                on_interrupt();
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    // SAFETY: registers a handler that lives as long as the process
+    unsafe { SetConsoleCtrlHandler(Some(handler), 1) };
+}
+
+#[cfg(windows)]
+fn write_stderr(bytes: &[u8]) {
+    use std::io::Write;
+
+    // The handler runs on a thread of its own rather than interrupting one
+    let _ = io::stderr().write_all(bytes);
+}
+
+#[cfg(windows)]
+fn exit_now() -> ! {
+    std::process::exit(EXIT_INTERRUPTED.into())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn install_handler() {}
+
+#[cfg(not(any(unix, windows)))]
+fn write_stderr(_: &[u8]) {}
+
+#[cfg(not(any(unix, windows)))]
+fn exit_now() -> ! {
+    std::process::exit(EXIT_INTERRUPTED.into())
+}
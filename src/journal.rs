@@ -0,0 +1,260 @@
+use crate::scan::{ExpectedSet, Scan, Stamp};
+use crate::stage::run_name;
+use crate::warnings::{Warning, WarningCode};
+use anyhow::{anyhow, Context};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Version of the journal format; journals of other versions are refused.
+pub const JOURNAL_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum EntryKind {
+    File,
+    Dir,
+}
+
+/// One line of the journal. Paths are relative to the content directory, `/`-separated.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum JournalLine {
+    /// The first line: which torrent and directory the run cleans.
+    Start { version: u32, info_hash: String, dir: PathBuf, timestamp: u64 },
+    /// An entry the run is about to remove, written for all of them before the first removal.
+    Planned { path: String, kind: EntryKind, size: u64, modified: Option<SystemTime> },
+    Removed { path: String },
+    Failed { path: String, error: String },
+    /// The last line of a run that got through all its entries without a failure.
+    Finished,
+}
+
+/// Append-only record of a clean run, from which `--resume` picks up an interrupted one.
+pub struct Journal {
+    file: Option<File>,
+    path: PathBuf,
+    root: PathBuf,
+}
+
+fn relative(root: &Path, entry: &Path) -> String {
+    let rel = entry.strip_prefix(root).unwrap_or(entry);
+    rel.iter().map(|e| e.to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+fn journals_dir() -> anyhow::Result<PathBuf> {
+    let dirs = ProjectDirs::from("", "", "torrent-cleaner")
+        .ok_or(anyhow!("No home directory available for the journal"))?;
+    Ok(dirs.cache_dir().join("journals"))
+}
+
+/// Where a run keeps its journal unless `--journal` is given:
+/// `<cache dir>/journals/<info hash>-<time>.jsonl`.
+pub fn default_path(info_hash: &str) -> anyhow::Result<PathBuf> {
+    Ok(journals_dir()?.join(format!("{}-{}.jsonl", info_hash, run_name(SystemTime::now()))))
+}
+
+impl Journal {
+    /// Starts a new journal at `path` listing everything `scan` plans to remove.
+    pub fn create(path: &Path, info_hash: &str, dir: &Path, scan: &Scan) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent().filter(|e| !e.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        let mut file = OpenOptions::new().write(true).create_new(true).open(path)
+            .with_context(|| format!("Failed to create journal {}; an existing one is continued \
+                with --resume", path.display()))?;
+        let mut lines = vec![JournalLine::Start {
+            version: JOURNAL_VERSION,
+            info_hash: info_hash.to_string(),
+            dir: dir.to_path_buf(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        }];
+        for file in &scan.old_files {
+            let stamp = scan.stamps.get(file);
+            lines.push(JournalLine::Planned {
+                path: relative(dir, file),
+                kind: EntryKind::File,
+                size: stamp.map_or(0, |e| e.size),
+                modified: stamp.and_then(|e| e.modified),
+            });
+        }
+        for empty in scan.empty_dirs.iter().chain(&scan.emptied_dirs) {
+            lines.push(JournalLine::Planned {
+                path: relative(dir, empty), kind: EntryKind::Dir, size: 0, modified: None,
+            });
+        }
+        let mut content = String::new();
+        for line in &lines {
+            content += &serde_json::to_string(line)?;
+            content.push('\n');
+        }
+        file.write_all(content.as_bytes()).and_then(|_| file.flush())
+            .with_context(|| format!("Failed to write journal {}", path.display()))?;
+        Ok(Self { file: Some(file), path: path.to_path_buf(), root: dir.to_path_buf() })
+    }
+
+    /// Reopens the journal of `recorded` to append the outcome of the resumed run.
+    pub fn append(recorded: &Recorded) -> anyhow::Result<Self> {
+        let file = OpenOptions::new().append(true).open(&recorded.path)
+            .with_context(|| format!("Failed to open journal {}", recorded.path.display()))?;
+        Ok(Self { file: Some(file), path: recorded.path.clone(), root: recorded.dir.clone() })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn removed(&mut self, entry: &Path) {
+        self.write(JournalLine::Removed { path: relative(&self.root, entry) });
+    }
+
+    pub fn failed(&mut self, entry: &Path, error: &str) {
+        self.write(JournalLine::Failed { path: relative(&self.root, entry), error: error.to_string() });
+    }
+
+    /// Marks the run as finished. A journal in the cache directory is then deleted, as nothing is
+    /// left to resume; one given with `--journal` is kept as a record of what was removed.
+    pub fn finished(&mut self) {
+        self.write(JournalLine::Finished);
+        if self.file.is_some() && journals_dir().is_ok_and(|e| self.path.parent() == Some(&e)) {
+            self.file = None;
+            if let Err(e) = fs::remove_file(&self.path) {
+                eprintln!("{}", Warning::new(WarningCode::Journal,
+                    format!("failed to delete the finished journal {}: {}", self.path.display(), e))
+                    .at(&self.path).record());
+            }
+        }
+    }
+
+    /// Appends `line`, flushed so that it survives an interrupted run. The removals go on if the
+    /// journal cannot be written; it is then left as it was, with a warning.
+    fn write(&mut self, line: JournalLine) {
+        let Some(file) = &mut self.file else { return };
+        let result = serde_json::to_string(&line).map_err(std::io::Error::from)
+            .and_then(|e| writeln!(file, "{}", e))
+            .and_then(|_| file.flush());
+        if let Err(e) = result {
+            eprintln!("{}", Warning::new(WarningCode::Journal,
+                format!("failed to write journal {}, which is no longer updated: {}",
+                        self.path.display(), e))
+                .at(&self.path).record());
+            self.file = None;
+        }
+    }
+}
+
+/// What a journal says about its run.
+pub struct Recorded {
+    pub path: PathBuf,
+    pub dir: PathBuf,
+    /// Entries planned and not removed yet, in the order they were planned.
+    pending: Vec<(PathBuf, EntryKind, Stamp)>,
+    pub removed: usize,
+    pub finished: bool,
+}
+
+/// Reads the journal at `path`, refusing one written for another torrent or directory.
+pub fn read_journal(path: &Path, info_hash: &str, dir: &Path) -> anyhow::Result<Recorded> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read journal {}", path.display()))?;
+    let mut lines = content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let invalid = |n: usize| move |e: serde_json::Error|
+        anyhow!("Invalid journal {}, line {}: {}", path.display(), n + 1, e);
+    match lines.next().map(|(n, line)| serde_json::from_str(line).map_err(invalid(n))).transpose()? {
+        Some(JournalLine::Start { version, info_hash: journal_hash, dir: journal_dir, .. }) => {
+            if version != JOURNAL_VERSION {
+                return Err(anyhow!("Journal {} has version {}, but this version of the tool \
+                    reads version {}", path.display(), version, JOURNAL_VERSION));
+            }
+            if journal_hash != info_hash {
+                return Err(anyhow!("Journal {} was written for the torrent {}, not {}",
+                                   path.display(), journal_hash, info_hash));
+            }
+            if journal_dir != dir {
+                return Err(anyhow!("Journal {} was written for {}, not {}", path.display(),
+                                   journal_dir.display(), dir.display()));
+            }
+        }
+        _ => return Err(anyhow!("Invalid journal {}: it does not start with the run it records",
+                                path.display())),
+    }
+    let mut recorded = Recorded {
+        path: path.to_path_buf(), dir: dir.to_path_buf(), pending: Vec::new(), removed: 0,
+        finished: false,
+    };
+    let mut planned = HashMap::new();
+    let mut removed = HashSet::new();
+    for (n, line) in lines {
+        // The last line may be cut short by the interruption the journal is resumed from
+        let Ok(line) = serde_json::from_str(line).map_err(invalid(n)) else { continue };
+        match line {
+            JournalLine::Planned { path, kind, size, modified } => {
+                let entry = path.split('/').fold(dir.to_path_buf(), |e, part| e.join(part));
+                planned.insert(path, recorded.pending.len());
+                recorded.pending.push((entry, kind, Stamp { size, modified }));
+            }
+            JournalLine::Removed { path } => {
+                removed.insert(path);
+            }
+            JournalLine::Finished => recorded.finished = true,
+            JournalLine::Start { .. } | JournalLine::Failed { .. } => {}
+        }
+    }
+    let done = removed.iter().filter_map(|e| planned.get(e)).copied().collect::<HashSet<usize>>();
+    recorded.removed = done.len();
+    recorded.pending = recorded.pending.into_iter().enumerate()
+        .filter(|(i, _)| !done.contains(i))
+        .map(|(_, e)| e)
+        .collect();
+    Ok(recorded)
+}
+
+impl Recorded {
+    /// How many planned entries are not removed yet.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The pending entries that still exist and are still not part of `expected`, as a scan to
+    /// remove them with; files are checked against their stamp again before removal.
+    pub fn scan(&self, expected: &impl ExpectedSet) -> Scan {
+        let mut scan = Scan {
+            old_files: Vec::new(),
+            empty_dirs: Vec::new(),
+            emptied_dirs: Vec::new(),
+            kept_dirs: Vec::new(),
+            rm_size: 0,
+            skipped: Vec::new(),
+            aliases: Vec::new(),
+            stamps: HashMap::new(),
+            unreadable: Vec::new(),
+        };
+        for (entry, kind, stamp) in &self.pending {
+            let rel = entry.strip_prefix(&self.dir).unwrap_or(entry);
+            let Ok(meta) = fs::symlink_metadata(entry) else { continue };
+            if expected.file_size(rel).is_some() || expected.contains_dir(rel) {
+                continue;
+            }
+            match kind {
+                EntryKind::File if !meta.is_dir() => {
+                    scan.old_files.push(entry.clone());
+                    scan.stamps.insert(entry.clone(), *stamp);
+                    scan.rm_size += stamp.size;
+                }
+                EntryKind::Dir if meta.is_dir() => {
+                    match fs::read_dir(entry).is_ok_and(|mut e| e.next().is_none()) {
+                        true => scan.empty_dirs.push(entry.clone()),
+                        false => scan.emptied_dirs.push(entry.clone()),
+                    }
+                }
+                _ => {}
+            }
+        }
+        scan
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::{anyhow, Context};
+use globset::{GlobBuilder, GlobMatcher};
+use std::path::Path;
+
+/// Names operating systems, file managers and sync tools leave in the directories they touch.
+/// Directories among them count as junk along with all they hold.
+const NAMES: &[&str] = &[
+    ".DS_Store",
+    "._*",
+    ".AppleDouble",
+    ".Spotlight-V100",
+    ".Trashes",
+    ".fseventsd",
+    ".TemporaryItems",
+    "Thumbs.db",
+    "ehthumbs.db",
+    "desktop.ini",
+    "$RECYCLE.BIN",
+    "System Volume Information",
+    ".directory",
+    ".Trash-*",
+    ".stfolder",
+    ".stversions",
+    ".stignore",
+    "@eaDir",
+    ".sync",
+];
+
+/// What becomes of junk, the entries named like [`Junk`] matches.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JunkMode {
+    /// Never removed, nor are the directories holding it.
+    Keep,
+    /// Removed like other extras.
+    #[default]
+    Remove,
+    /// The only extras removed; everything else is left alone.
+    Only,
+}
+
+/// The built-in junk names and those added by `--junk-pattern`, matched case-insensitively
+/// against the name of an entry and of every directory holding it.
+///
+/// ```
+/// use std::path::Path;
+/// use torrent_cleaner::junk::{Junk, JunkMode};
+///
+/// let junk = Junk::new(JunkMode::Keep, &["*.nfo".to_string()]).unwrap();
+/// assert!(junk.matches(Path::new("Season1/Thumbs.db")));
+/// assert!(junk.matches(Path::new("Season1/.DS_Store")));
+/// assert!(junk.matches(Path::new(".stfolder/index")));
+/// assert!(junk.matches(Path::new("Show.NFO")));
+/// assert!(!junk.matches(Path::new("Season1/ep2.mkv")));
+/// ```
+#[derive(Clone)]
+pub struct Junk {
+    pub mode: JunkMode,
+    names: Vec<GlobMatcher>,
+}
+
+impl Default for Junk {
+    fn default() -> Self {
+        Self::new(JunkMode::default(), &[]).expect("valid built-in names")
+    }
+}
+
+fn matcher(pattern: &str) -> Result<GlobMatcher, globset::Error> {
+    Ok(GlobBuilder::new(pattern).literal_separator(true).case_insensitive(true).build()?
+        .compile_matcher())
+}
+
+impl Junk {
+    /// The built-in names extended with `patterns`, globs matched against names.
+    pub fn new<'a>(mode: JunkMode, patterns: impl IntoIterator<Item = &'a String>)
+                   -> anyhow::Result<Self> {
+        let mut names = NAMES.iter().copied().map(matcher)
+            .collect::<Result<Vec<GlobMatcher>, globset::Error>>()?;
+        for pattern in patterns {
+            if pattern.contains('/') {
+                return Err(anyhow!("--junk-pattern matches names, not paths: {}", pattern));
+            }
+            names.push(matcher(pattern)
+                .with_context(|| format!("Invalid --junk-pattern: {}", pattern))?);
+        }
+        Ok(Self { mode, names })
+    }
+
+    /// Whether the entry at `path`, relative to the scanned directory, is junk or lies inside a
+    /// junk directory.
+    pub fn matches(&self, path: &Path) -> bool {
+        path.iter().any(|name| self.names.iter().any(|e| e.is_match(name)))
+    }
+}
@@ -0,0 +1,141 @@
+use crate::output::color_enabled;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use term_painter::Color::{self, Blue, BrightBlack, BrightBlue, BrightGreen, Cyan, Magenta, NotSet, Yellow};
+use term_painter::{Style, ToStyle};
+
+/// Coarse file type derived from the extension, used to color and count listing entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FileKind {
+    Video,
+    Audio,
+    Archive,
+    Subtitle,
+    Image,
+    Junk,
+    Other,
+}
+
+const KINDS: &[(FileKind, &[&str])] = &[
+    (FileKind::Video, &["mkv", "mp4", "avi", "m4v", "mov", "wmv", "ts", "m2ts", "webm", "mpg",
+        "mpeg", "flv", "vob", "iso"]),
+    (FileKind::Audio, &["mp3", "flac", "aac", "m4a", "ogg", "opus", "wav", "wma", "ape", "ac3",
+        "dts", "mka"]),
+    (FileKind::Archive, &["zip", "rar", "7z", "tar", "gz", "bz2", "xz", "zst", "r00", "001"]),
+    (FileKind::Subtitle, &["srt", "ass", "ssa", "sub", "idx", "vtt", "sup"]),
+    (FileKind::Image, &["jpg", "jpeg", "png", "gif", "bmp", "webp", "tif", "tiff"]),
+    (FileKind::Junk, &["nfo", "txt", "url", "lnk", "sfv", "md5", "sha1", "nzb", "db", "ini", "log",
+        "htm", "html", "exe", "parts", "ds_store"]),
+];
+
+impl FileKind {
+    pub fn of(path: &Path) -> Self {
+        let Some(ext) = extension(path) else { return FileKind::Other };
+        KINDS.iter()
+            .find(|(_, exts)| exts.contains(&ext.as_str()))
+            .map_or(FileKind::Other, |(kind, _)| *kind)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FileKind::Video => "video",
+            FileKind::Audio => "audio",
+            FileKind::Archive => "archive",
+            FileKind::Subtitle => "subtitle",
+            FileKind::Image => "image",
+            FileKind::Junk => "junk",
+            FileKind::Other => "other",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            FileKind::Video => Magenta,
+            FileKind::Audio => Cyan,
+            FileKind::Archive => Yellow,
+            FileKind::Subtitle => BrightGreen,
+            FileKind::Image => BrightBlue,
+            FileKind::Junk => BrightBlack,
+            FileKind::Other => NotSet,
+        }
+    }
+}
+
+/// Lowercased extension; dotfiles such as `.DS_Store` count as their own extension.
+pub fn extension(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_string_lossy();
+    let ext = match path.extension() {
+        Some(ext) => ext.to_string_lossy(),
+        None => name.strip_prefix('.')?.into(),
+    };
+    Some(ext.to_lowercase())
+}
+
+/// Normalizes `--highlight-ext` and `--deletable-ext` values to lowercase without the leading dot.
+pub fn parse_ext(ext: &str) -> Result<String, String> {
+    let ext = ext.trim().trim_start_matches('.');
+    match ext.is_empty() {
+        true => Err("extension must not be empty".to_string()),
+        false => Ok(ext.to_lowercase()),
+    }
+}
+
+/// Style of a file entry: its type's color, bold and underlined when highlighted.
+pub fn kind_style(path: &Path, highlight: &[String]) -> Style {
+    let style = FileKind::of(path).color().to_style();
+    match is_highlighted(path, highlight) {
+        true => style.bold().underline(),
+        false => style,
+    }
+}
+
+pub fn is_highlighted(path: &Path, highlight: &[String]) -> bool {
+    extension(path).is_some_and(|e| highlight.contains(&e))
+}
+
+/// A listed path, colored by type (directories in blue) or tagged with it in no-color mode.
+pub struct KindPath<'a> {
+    pub path: &'a Path,
+    /// The path as rendered for output.
+    pub shown: String,
+    pub highlight: &'a [String],
+}
+
+impl fmt::Display for KindPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path;
+        if path.is_dir() {
+            return Blue.paint(&self.shown).fmt(f);
+        }
+        if color_enabled() {
+            return kind_style(path, self.highlight).paint(&self.shown).fmt(f);
+        }
+        let mark = if is_highlighted(path, self.highlight) { "*" } else { "" };
+        write!(f, "{} [{}{}]", self.shown, FileKind::of(path).name(), mark)
+    }
+}
+
+/// Counts files per type, in [`FileKind`] order.
+pub fn count_kinds<'a>(files: impl IntoIterator<Item = &'a PathBuf>) -> KindCounts {
+    let mut counts = Vec::<(FileKind, usize)>::new();
+    for file in files {
+        let kind = FileKind::of(file);
+        match counts.iter_mut().find(|(e, _)| *e == kind) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((kind, 1)),
+        }
+    }
+    counts.sort();
+    KindCounts(counts)
+}
+
+pub struct KindCounts(pub Vec<(FileKind, usize)>);
+
+impl fmt::Display for KindCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = self.0.iter()
+            .map(|(kind, n)| format!("{} {}", n, kind.name()))
+            .collect::<Vec<String>>();
+        f.write_str(&counts.join(", "))
+    }
+}
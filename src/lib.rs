@@ -0,0 +1,221 @@
+//! The scanner behind `torrent-cleaner`, for tools that want to compare a directory against a
+//! torrent without the command-line interface.
+//!
+//! Build with `default-features = false` to leave out the `cli` feature and its terminal
+//! dependencies; nothing in this API depends on them. The `tokio` feature adds the async variants
+//! in [`nonblocking`].
+//!
+//! ```no_run
+//! use librqbit_core::torrent_metainfo::torrent_from_bytes_ext;
+//! use librqbit_buffers::ByteBufOwned;
+//! use std::path::Path;
+//! use torrent_cleaner::{Scanner, TorrentFileSet};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let bytes = ByteBufOwned::from(std::fs::read("show.torrent")?);
+//! let torrent = torrent_from_bytes_ext::<ByteBufOwned>(bytes.as_ref())?.meta;
+//! let files = TorrentFileSet::from_metainfo(&torrent)?;
+//! let report = Scanner::new(Path::new("/downloads/Show")).scan(&files)?;
+//! for extra in report.extras() {
+//!     println!("extra: {}", extra.display());
+//! }
+//! println!("{} bytes would be freed", report.plan().size);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod droppings;
+pub mod error;
+pub mod junk;
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
+pub mod owner;
+pub mod scan;
+
+use crate::error::{CliError, ErrorCode};
+use crate::scan::{scan_dir, sort_deepest_first, Expected, ExpectedSet, Progress, Scan,
+                  ScanOptions};
+use librqbit_buffers::ByteBufOwned;
+use librqbit_core::torrent_metainfo::TorrentMetaV1;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The files a torrent consists of, with their sizes; a single-file torrent's is the one file
+/// named after it. Other sources of expected files
+/// implement [`ExpectedSet`] themselves and are scanned the same way.
+pub struct TorrentFileSet {
+    expected: Expected,
+}
+
+impl TorrentFileSet {
+    pub fn from_metainfo(torrent: &TorrentMetaV1<ByteBufOwned>) -> anyhow::Result<Self> {
+        Ok(Self { expected: Expected::from_torrent(torrent)? })
+    }
+}
+
+impl ExpectedSet for TorrentFileSet {
+    fn file_size(&self, path: &Path) -> Option<u64> {
+        self.expected.file_size(path)
+    }
+
+    fn contains_dir(&self, path: &Path) -> bool {
+        self.expected.contains_dir(path)
+    }
+
+    fn is_surface(&self, name: &OsStr) -> bool {
+        self.expected.is_surface(name)
+    }
+
+    fn files(&self) -> impl Iterator<Item = (&Path, u64)> {
+        self.expected.files()
+    }
+
+    fn file_count(&self) -> usize {
+        self.expected.file_count()
+    }
+
+    fn is_flat(&self) -> bool {
+        self.expected.is_flat()
+    }
+
+    fn locate(&self, dir: &Path, path: &Path) -> PathBuf {
+        self.expected.locate(dir, path)
+    }
+}
+
+/// Compares one content directory against a [`TorrentFileSet`] or any other [`ExpectedSet`].
+///
+/// ```
+/// use std::fs;
+/// use torrent_cleaner::scan::{Expected, ScanOptions};
+/// use torrent_cleaner::Scanner;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let dir = std::env::temp_dir().join(format!("torrent-cleaner-doc-{}", std::process::id()));
+/// fs::create_dir_all(dir.join("Show/Extras"))?;
+/// fs::write(dir.join("Show/ep1.mkv"), b"video")?;
+/// fs::write(dir.join("Show/sample.txt"), b"extra")?;
+///
+/// let expected = Expected::from_files([
+///     (vec!["Show".into(), "ep1.mkv".into()], 5),
+///     (vec!["Show".into(), "ep2.mkv".into()], 7),
+/// ])?;
+/// let options = ScanOptions { include_empty_dir: true, ..ScanOptions::default() };
+/// let report = Scanner::new(&dir).with_options(options).scan(&expected)?;
+/// assert_eq!(report.extras(), [dir.join("Show/sample.txt")]);
+/// assert_eq!(report.missing(), [dir.join("Show/ep2.mkv")]);
+/// assert_eq!(report.missing_size(), 7);
+///
+/// let plan = report.plan();
+/// assert_eq!(plan.dirs, [dir.join("Show/Extras")]);
+/// assert_eq!(plan.size, 5);
+/// assert_eq!(plan.execute()?, 2);
+/// assert!(dir.join("Show/ep1.mkv").exists() && !dir.join("Show/Extras").exists());
+/// # fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Scanner {
+    dir: PathBuf,
+    options: ScanOptions,
+}
+
+impl Scanner {
+    /// Scans `dir` with the default [`ScanOptions`].
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf(), options: ScanOptions::default() }
+    }
+
+    pub fn with_options(self, options: ScanOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    /// Walks the directory; nothing is modified.
+    pub fn scan(&self, files: &impl ExpectedSet) -> anyhow::Result<ScanReport> {
+        self.scan_with(files, &())
+    }
+
+    fn scan_with(&self, files: &impl ExpectedSet, progress: &impl Progress)
+                 -> anyhow::Result<ScanReport> {
+        let scan = scan_dir(&self.dir, files, &self.options, progress)?;
+        let (missing, missing_size) = files.missing(&self.dir);
+        Ok(ScanReport { scan: Box::new(scan), missing, missing_size })
+    }
+}
+
+/// Result of [`Scanner::scan`]; all paths are absolute, i.e. joined to the scanned directory.
+pub struct ScanReport {
+    scan: Box<Scan>,
+    missing: Vec<PathBuf>,
+    missing_size: u64,
+}
+
+/// What cleaning would delete: the extra files first, then the directories left empty.
+#[derive(Clone)]
+pub struct Plan {
+    pub files: Vec<PathBuf>,
+    /// Empty only with [`ScanOptions::include_empty_dir`].
+    pub dirs: Vec<PathBuf>,
+    /// Bytes freed, counting files with several paths once.
+    pub size: u64,
+}
+
+impl ScanReport {
+    /// Files not part of the torrent.
+    pub fn extras(&self) -> &[PathBuf] {
+        &self.scan.old_files
+    }
+
+    /// Files of the torrent that do not exist.
+    pub fn missing(&self) -> &[PathBuf] {
+        &self.missing
+    }
+
+    pub fn missing_size(&self) -> u64 {
+        self.missing_size
+    }
+
+    pub fn plan(&self) -> Plan {
+        let mut dirs = [&self.scan.empty_dirs[..], &self.scan.emptied_dirs[..]].concat();
+        // Children before their parents, so the directories can be removed in order
+        dirs.sort_by(|a, b| b.cmp(a));
+        Plan { files: self.scan.old_files.clone(), dirs, size: self.scan.rm_size }
+    }
+
+    /// The full scan, including the entries left alone and why.
+    pub fn details(&self) -> &Scan {
+        &self.scan
+    }
+}
+
+fn remove_error(kind: &str, path: &Path, err: std::io::Error) -> CliError {
+    CliError::new(ErrorCode::RemoveFailed, format!("Failed to remove {}: {}", kind, path.display()))
+        .with_path(path).with_entry(path, err)
+}
+
+impl Plan {
+    /// Deletes the files deepest first, then the directories, stopping at the first failure;
+    /// returns how many entries were removed. Directories are only removed while empty, except
+    /// those that are extras as a whole below [`ScanOptions::max_depth`]; one that is no longer
+    /// empty, having gained an entry since the scan, is left alone along with its parents.
+    pub fn execute(&self) -> anyhow::Result<usize> {
+        let mut files = self.files.clone();
+        sort_deepest_first(&mut files);
+        for file in &files {
+            match fs::symlink_metadata(file).is_ok_and(|e| e.is_dir()) {
+                true => fs::remove_dir_all(file),
+                false => fs::remove_file(file),
+            }.map_err(|e| remove_error("file", file, e))?;
+        }
+        let mut kept = 0;
+        for dir in &self.dirs {
+            match fs::remove_dir(dir) {
+                Err(e) if e.kind() == std::io::ErrorKind::DirectoryNotEmpty => kept += 1,
+                result => result.map_err(|e| remove_error("directory", dir, e))?,
+            }
+        }
+        Ok(self.files.len() + self.dirs.len() - kept)
+    }
+}
@@ -1,4 +1,3 @@
-
 //! Features
 //! - Check the file list of files
 //! - Option to take surface into account
@@ -6,292 +5,3398 @@
 
 extern crate core;
 
+pub mod age;
+pub mod batch;
+pub mod breakdown;
+pub mod cli;
+pub mod client;
+pub mod config;
+pub mod dedup;
+pub mod download;
+pub mod duplicate;
+pub mod explain;
+pub mod group;
+pub mod history;
+pub mod hook;
+pub mod interrupt;
+pub mod journal;
+pub mod kind;
+pub mod manifest;
+pub mod metadata;
+pub mod mount;
+pub mod output;
+pub mod paths;
+pub mod peers;
+pub mod plan;
+pub mod priority;
+pub mod progress;
+pub mod prompt;
+pub mod remote;
+pub mod remove;
+pub mod rename;
+pub mod report;
+pub mod review;
+pub mod roots;
+pub mod schema;
+pub mod snapshot;
+pub mod stage;
+pub mod stats;
+pub mod summary;
 pub mod torrent;
+pub mod touch;
+pub mod tree;
+pub mod verify;
+pub mod warnings;
+pub mod watch;
+
+use torrent_cleaner::{droppings, error, junk, owner, scan};
+use crate::age::AgeStats;
+use crate::batch::run_batch;
+use crate::breakdown::print_breakdown;
+use crate::cli::{build_cli, parse_args};
+use crate::client::{Client, LabelFilter};
+use crate::config::{from_config, private_patterns};
+use crate::dedup::{find_duplicates_of_kept, Duplicate};
+use crate::download::set_timeout;
+use crate::droppings::{client_of, download_of};
+use crate::duplicate::{find_duplicate_roots, merge_into, present_files};
+use crate::error::{error_json, CliError, ErrorCode, EXIT_FAILED};
+use crate::explain::explain_scan;
+use crate::group::{group_dirs, Group, GroupOrder};
+use crate::touch::Placeholders;
 
-use crate::torrent::parse_torrent;
+use crate::tree::{print_tree, Glyphs};
+use crate::watch::{watch, Watched};
+use crate::history::{history_file, last_run, load_history, record_run, RunRecord};
+use crate::hook::Hooks;
+use crate::interrupt::{handle_ctrl_c, interrupted, start_removing, EXIT_INTERRUPTED};
+use crate::journal::{default_path, read_journal, Journal, Recorded};
+use crate::junk::{Junk, JunkMode};
+use crate::kind::{count_kinds, FileKind, KindPath};
+use crate::manifest::BackupManifest;
+use crate::mount::{looks_active, looks_unmounted, sibling_trees};
+use crate::output::{open_log, set_color, set_quiet, set_to_stderr, set_verbosity};
+use crate::owner::{current_uid, lookup_user};
+use crate::paths::{PathRenderer, PathStyle};
+use crate::plan::SavedPlan;
+use crate::priority::lower_priority;
+use crate::progress::{finish, ProgressMode};
+use crate::prompt::{confirm, interactive, select, EXIT_DECLINED};
+use crate::remote::{remote_dir, RemoteDir};
+use crate::remove::{remove_empty_dirs, remove_files, FileRemoval, RemoveOptions};
+use crate::rename::{find_renames, move_back, relink, Pieces, Renames};
+use crate::report::{write_removal_list, write_report, DiffReport, MapReport, ReportAction,
+                    ReportRow};
+use crate::review::link_extras;
+use crate::roots::run_roots;
+use crate::scan::{collisions, file_list, file_list_tsv, parse_file_list, parse_keep_list, scan_dir,
+                  sort_paths, unsafe_paths, windows_conflict, Collision, Deletable, DirKeptReason,
+                  DiskUsage, Exclude, Expected, ExpectedSet, Folding, KeptDir, Mismatch, Prune, Scan,
+                  ScanOptions, SkipReason, Skipped, SortKey, SortOrder, Stamp, Symlinks, Unreadable,
+                  UnsafePath};
+use crate::snapshot::{expected_digest, PlanOptions, Snapshot};
+use crate::stage::{is_no_space, move_files_to, stage_files, staged_runs, MoveOptions};
+use crate::stats::Stats;
+use crate::summary::{KeptDirEntry, KindTotal, RunStatus, Summary};
+use crate::verify::{bytes_to_hash, verify, FileCheck, Verification};
+use crate::warnings::{denied, print_warnings, set_deny, warn, Warning, WarningCode, EXIT_WARNED};
+use crate::torrent::{check_geometry, check_layers, geometry_error, load_piece_layers,
+                     is_local_path, overview, parse_torrent, piece_count, read_transmission_resume,
+                     set_v1_only, LayerCheck, Metainfo};
 use anyhow::{anyhow, Context};
-use clap::{arg, command, value_parser, Arg, ArgAction, Command};
-use indicatif::{BinaryBytes, ProgressBar, ProgressStyle};
-use inquire::Confirm;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use globset::GlobBuilder;
+use indicatif::{BinaryBytes, HumanCount, ProgressBar, ProgressStyle};
+use inquire::Select;
+use librqbit_buffers::ByteBufOwned;
+use librqbit_core::torrent_metainfo::TorrentMetaV1;
 use path_clean::PathClean;
-use std::collections::{HashMap, HashSet};
-use std::ffi::OsString;
-use std::path::{Display, Path, PathBuf};
-use std::time::Duration;
-use std::{env, fs, io};
-use term_painter::Color::{Blue, Green, NotSet, Red};
-use term_painter::{Painted, ToStyle};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use std::{env, io};
+use term_painter::Color::{Green, Red, Yellow};
+use term_painter::ToStyle;
 use unicode_truncate::UnicodeTruncateStr;
-use walkdir::WalkDir;
-
-fn main() -> anyhow::Result<()> {
-    let matches = command!()
-        .arg_required_else_help(true)
-        .arg(arg!(-s --surface "Take other files in the root directory into account")
-            .required(false)
-            .action(ArgAction::SetTrue))
-        .arg(arg!(-f --"no-confirm" "Skip confirmation before deleting files")
-            .required(false)
-            .action(ArgAction::SetTrue))
-        .arg(arg!(-d --"empty-dir" "Include empty directories")
-            .required(false)
-            .action(ArgAction::SetTrue))
-        .arg(Arg::new("file")
-            .help("Specify the .torrent file; must be a multi-file torrent")
-            .required(true)
-            .value_parser(value_parser!(PathBuf)))
-        .arg(Arg::new("dir")
-            .help("Specify the directory storing torrent contents")
-            .required(true)
-            .value_parser(value_parser!(PathBuf)))
-        .subcommand_required(false)
-        .subcommand(Command::new("diff")
-            .about("Compare directory content changes instead"))
-        .get_matches();
-
-    let path = absolute_path(matches.get_one::<PathBuf>("file").expect("required"))?;
-    let dir = absolute_path(matches.get_one::<PathBuf>("dir").expect("required"))?;
-    let include_sur = matches.get_flag("surface");
-    let no_confirm = matches.get_flag("no-confirm");
-    let include_empty_dir = matches.get_flag("empty-dir");
 
-    let spinner = ProgressBar::new_spinner();
+/// What the private tracker of the run's torrent may require to be kept, see
+/// [`private_protections`].
+static PRIVATE: OnceLock<Exclude> = OnceLock::new();
+
+fn main() -> ExitCode {
+    let matches = parse_args();
+    set_color(matches.get_one::<String>("color").expect("default"));
+
+    let result = run(&matches);
+    print_warnings();
+    match result {
+        Ok(code) if code == ExitCode::SUCCESS && denied() => {
+            verbose!(1, "Finished with warnings");
+            eprintln!("Error: warnings were printed and --deny-warnings is given");
+            ExitCode::from(EXIT_WARNED)
+        }
+        Ok(code) => {
+            verbose!(1, "Finished");
+            code
+        }
+        Err(err) => {
+            verbose!(1, "Failed: {:#}", err);
+            if matches.get_one::<String>("output").is_some_and(|e| e == "json") {
+                println!("{}", error_json(&err));
+            } else {
+                eprintln!("Error: {:?}", err);
+            }
+            match err.chain().find_map(|e| e.downcast_ref::<CliError>()) {
+                Some(cli) if cli.code == ErrorCode::EmptyExpected => {
+                    ExitCode::from(EXIT_EMPTY_EXPECTED)
+                }
+                Some(cli) if cli.code == ErrorCode::Interrupted => ExitCode::from(EXIT_INTERRUPTED),
+                _ => ExitCode::from(EXIT_FAILED),
+            }
+        }
+    }
+}
+
+fn run(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    handle_ctrl_c();
+    set_verbosity(matches.get_count("verbose"));
+    if let Some(path) = matches.get_one::<PathBuf>("log-file") {
+        open_log(path)?;
+    }
+    verbose!(1, "torrent-cleaner {}: {}", env!("CARGO_PKG_VERSION"),
+             env::args_os().skip(1).map(|e| e.to_string_lossy().into_owned())
+                 .collect::<Vec<String>>().join(" "));
+    set_quiet(matches.get_flag("quiet"));
+    set_v1_only(matches.get_flag("v1-only"));
+    set_timeout(*matches.get_one::<Duration>("timeout").expect("default"));
+    peers::set_timeout(*matches.get_one::<Duration>("metadata-timeout").expect("default"));
+    peers::set_no_dht(matches.get_flag("no-dht"));
+    set_deny(matches.get_flag("deny-warnings"));
+    if matches.get_flag("background") {
+        for warning in lower_priority() {
+            eprintln!("{}", warn(WarningCode::Priority, warning));
+        }
+    }
+    if matches.get_flag("schema") {
+        println!("{}", serde_json::to_string_pretty(&schema::schema())?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    let result = match matches.subcommand() {
+        Some(("clean", sub)) => return run_clean(sub),
+        Some(("diff", sub)) => return run_diff(sub),
+        Some(("stats", sub)) => run_stats(sub),
+        Some(("touch", sub)) => run_touch(sub),
+        Some(("missing", sub)) => return run_missing(sub),
+        Some(("verify", sub)) => return run_verify(sub),
+        Some(("info", sub)) => run_info(sub),
+        Some(("list", sub)) => run_list(sub),
+        Some(("purge", sub)) => return run_purge(sub),
+        Some(("restore", sub)) => run_restore(sub),
+        Some(("batch", sub)) => return run_batch(sub),
+        Some(("history", sub)) => run_history(sub),
+        Some(("snapshot", sub)) => run_snapshot(sub),
+        Some(("compare-snapshot", sub)) => return run_compare_snapshot(sub),
+        Some(("plan", sub)) => run_plan(sub),
+        Some(("apply", sub)) => return run_apply(sub),
+        Some(("completions", sub)) => {
+            let shell = *sub.get_one::<clap_complete::Shell>("shell").expect("required");
+            clap_complete::generate(shell, &mut build_cli(), env!("CARGO_BIN_NAME"),
+                &mut io::stdout());
+            Ok(())
+        }
+        _ => build_cli().error(clap::error::ErrorKind::MissingSubcommand,
+            "a subcommand is required unless --schema is given").exit(),
+    };
+    result.map(|()| ExitCode::SUCCESS)
+}
+
+fn load_torrent(matches: &ArgMatches) -> anyhow::Result<TorrentMetaV1<ByteBufOwned>> {
+    let metainfo = load_metainfo(matches)?;
+    PRIVATE.set(private_protections(matches, &metainfo)?).ok();
+    Ok(metainfo.meta)
+}
+
+/// What to leave alone for the tracker of a private torrent, after a notice naming it so that
+/// whose rules apply is known: nothing for a public torrent or with `--no-private-protections`.
+pub fn private_protections(matches: &ArgMatches, torrent: &Metainfo)
+                           -> anyhow::Result<Exclude> {
+    if !torrent.is_private() || matches.get_flag("no-private-protections") {
+        return Ok(Exclude::default());
+    }
+    let host = torrent.announce_host();
+    let patterns = private_patterns(host.as_deref());
+    let tracker = host.map_or("a tracker it does not name".to_string(),
+                              |e| format!("tracker {}", e));
+    match patterns.is_empty() {
+        true => say!("Private torrent of {}.\n", tracker),
+        false => say!("Private torrent of {}: leaving {} alone (--no-private-protections to treat \
+                       them like other extras).\n", tracker, patterns.join(", ")),
+    }
+    Exclude::new(&patterns)
+}
+
+fn load_metainfo(matches: &ArgMatches) -> anyhow::Result<Metainfo> {
+    load_metainfo_from(matches, matches.get_one::<PathBuf>("file").expect("required"))
+}
+
+fn load_metainfo_from(matches: &ArgMatches, input: &PathBuf) -> anyhow::Result<Metainfo> {
+    let path = match is_local_path(input) {
+        true => absolute_path(input)?,
+        false => input.clone(),
+    };
+
+    let spinner = ProgressMode::from_matches(matches).bar(ProgressBar::new_spinner());
     spinner.set_style(ProgressStyle::default_spinner()
         .tick_chars("|/-\\")
         .template("{spinner:.green} [{elapsed_precise}] {msg}")?);
     spinner.set_message("Parsing...");
     spinner.enable_steady_tick(Duration::from_millis(100));
 
-    let result = parse_torrent(&spinner, path);
+    let result = parse_torrent(&spinner, &path);
     spinner.finish_and_clear();
     drop(spinner);
     let torrent = result?;
-    println!("Parsing completed.\n");
-
-    let mut files = HashMap::new();
-    let mut dirs = HashSet::new();
-    let mut surface_files = HashSet::new();
-    if let Some(vec) = torrent.info.files {
-        for f in vec.iter() {
-            let segs = f.path.iter().map(|e| e.to_string()).collect::<Vec<String>>();
-            files.insert(PathBuf::from_iter(&segs).into_boxed_path(), f.length);
-            surface_files.insert(OsString::from(
-                f.path.first().ok_or(anyhow!("Empty path"))?.to_string()));
-            dirs.extend(list_recursive_dirs(segs))
-        }
-    } else {
-        return Err(anyhow!("Not a valid multi-file torrent"));
-    }
-
-    let mut old_files = Vec::new();
-    let mut empty_dirs = Vec::new();
-    let mut rm_size: u64 = 0;
-    for entry in WalkDir::new(&dir) {
-        let entry = entry.context("Failed to read directory contents")?;
-        if entry.depth() == 0 { continue; } // skip root
-        let path = entry.path().strip_prefix(&dir).with_context(||
-            format!("Failed to strip directory contents of {:?}", &dir))?;
-        if (include_sur || surface_files.contains(path.components().next().expect("Not empty")
-            .as_os_str())) && !files.contains_key(path) {
-            let meta = entry.metadata()?;
-            if meta.is_file() {
-                rm_size += meta.len();
-            }
-
-            if meta.is_dir() {
-                if include_empty_dir && check_dir_kind_of_empty(entry.path()) {
-                    empty_dirs.push(entry.path().to_owned());
-                }
-            } else {
-                old_files.push(entry.path().to_owned());
-            }
+    let problems = check_geometry(&torrent.meta.info);
+    if matches.get_flag("strict-torrent") && !problems.is_empty() {
+        return Err(geometry_error(&problems).with_path(&path).into());
+    }
+    for problem in &problems {
+        eprintln!("{}", warn(WarningCode::Geometry,
+                             format!("suspicious torrent geometry: {}", problem)));
+    }
+    for warning in unsafe_path_warnings(&unsafe_paths(&torrent.meta), &path) {
+        eprintln!("{}", warning);
+    }
+    if let Some(file) = matches.get_one::<PathBuf>("save-torrent") {
+        save_torrent(file, &torrent.info_bytes)?;
+    }
+    say!("Parsing completed.");
+    say!("{}\n", overview(&torrent.meta.info));
+    Ok(torrent)
+}
+
+/// Writes the info dict as a .torrent file of its own, e.g. to keep the metadata a magnet URI was
+/// resolved to from the cache; trackers and other fields outside the info dict are left out.
+fn save_torrent(file: &Path, info_bytes: &[u8]) -> anyhow::Result<()> {
+    let mut buf = b"d4:info".to_vec();
+    buf.extend_from_slice(info_bytes);
+    buf.push(b'e');
+    fs::write(file, buf).with_context(|| format!("Failed to write torrent {}", file.display()))?;
+    say!("Torrent written to {}", file.display());
+    Ok(())
+}
+
+/// Refuses to clean a directory that looks unmounted unless `--allow-empty-target` is given;
+/// other commands only warn.
+pub fn check_target(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet, clean: bool)
+                    -> anyhow::Result<()> {
+    if let Some(warning) = unmounted_target(matches, dir, expected, clean)? {
+        say!("{}", warning);
+    }
+    Ok(())
+}
+
+fn unmounted_target(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet, clean: bool)
+                    -> Result<Option<Warning>, CliError> {
+    let Some(reason) = looks_unmounted(dir, expected) else { return Ok(None) };
+    if clean && !matches.get_flag("allow-empty-target") {
+        return Err(CliError::new(ErrorCode::TargetUnmounted, format!("Target looks unmounted: {}; \
+            pass --allow-empty-target to clean it anyway", reason)).with_path(dir));
+    }
+    Ok(Some(Warning::new(WarningCode::Unmounted, format!("target looks unmounted: {}", reason))
+        .at(dir).record()))
+}
+
+/// Refuses to clean a directory the torrent looks like it is still downloading into, where files
+/// may be half written or about to appear, unless `--force` is given; diff only warns.
+fn active_target(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet, clean: bool)
+                 -> Result<Option<Warning>, CliError> {
+    let Some(reason) = looks_active(dir, expected) else { return Ok(None) };
+    if clean && !matches.get_flag("force") {
+        return Err(CliError::new(ErrorCode::TargetActive, format!("The torrent looks like it is \
+            still downloading: {}; wait for it to finish, or pass --force to clean anyway",
+            reason)).with_path(dir));
+    }
+    Ok(Some(Warning::new(WarningCode::Active,
+                         format!("the torrent looks like it is still downloading: {}", reason))
+        .at(dir).record()))
+}
+
+/// The checks of `dir` before planning a clean or a diff of it, for `clean`, its several
+/// directories and `batch` alike: see [`check_target`] and [`active_target`]. The warnings are
+/// returned for the caller to print, so that batch jobs planned in parallel print theirs in
+/// order.
+pub fn check_content(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet, clean: bool)
+                 -> Result<Vec<Warning>, CliError> {
+    Ok(unmounted_target(matches, dir, expected, clean)?.into_iter()
+        .chain(active_target(matches, dir, expected, clean)?)
+        .collect())
+}
+
+/// The checks of the torrent before planning a clean or a diff against it, for `clean`, its
+/// several directories and `batch` alike: see [`check_expected`], and for a clean
+/// [`check_paths`].
+pub fn check_torrent(matches: &ArgMatches, expected: &Expected, clean: bool)
+                     -> Result<(), CliError> {
+    check_expected(matches, expected)?;
+    match clean {
+        true => check_paths(matches, expected),
+        false => Ok(()),
+    }
+}
+
+/// Exit code of diff and clean when the torrent describes no files to compare against.
+const EXIT_EMPTY_EXPECTED: u8 = 11;
+
+/// Refuses to plan against a torrent that describes no files once padding is left out, where
+/// everything in the directory would be extra, unless `--force-empty-expected` is given.
+pub fn check_expected(matches: &ArgMatches, expected: &impl ExpectedSet) -> Result<(), CliError> {
+    check_empty(matches, expected.file_count() == 0)
+}
+
+/// The warnings for the files of the torrent at `origin` left out for their paths, recorded.
+pub fn unsafe_path_warnings(found: &[UnsafePath], origin: &Path) -> Vec<Warning> {
+    found.iter().map(|e| Warning::new(WarningCode::UnsafePath, format!("{}; leaving it out", e))
+        .at(origin).record()).collect()
+}
+
+/// Refuses to remove or create entries for a torrent with paths that could lead outside of the
+/// directory, crafted or broken as it must be, unless `--allow-unsafe-paths` is given; the files
+/// of those paths are left out either way.
+pub fn check_paths(matches: &ArgMatches, expected: &Expected) -> Result<(), CliError> {
+    let found = expected.unsafe_paths();
+    if found.is_empty() || matches.get_flag("allow-unsafe-paths") {
+        return Ok(());
+    }
+    let more = match found.len() {
+        1 => String::new(),
+        n => format!(" (and {} more)", n - 1),
+    };
+    Err(CliError::new(ErrorCode::UnsafePaths, format!("Torrent has a path leading outside of the \
+        directory: {}{}; pass --allow-unsafe-paths to go on without those files", found[0], more)))
+}
+
+pub fn check_empty(matches: &ArgMatches, empty: bool) -> Result<(), CliError> {
+    match empty && !matches.get_flag("force-empty-expected") {
+        true => Err(CliError::new(ErrorCode::EmptyExpected, "Torrent describes no payload files; \
+            nothing will be cleaned. Pass --force-empty-expected to treat every entry as extra")),
+        false => Ok(()),
+    }
+}
+
+/// Warns when `dir` looks like a library holding the torrent's content among other trees rather
+/// than the content directory itself, and refuses `clean --surface` on a directory not named
+/// after the torrent without `--force`. `names` are those of the torrents, of which it may be
+/// named after any; there are none for `--manifest`.
+pub fn check_scope(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet,
+                   names: &[String], clean: bool) -> anyhow::Result<()> {
+    let names = names.iter().filter(|e| !e.is_empty()).collect::<Vec<&String>>();
+    let Some(name) = names.iter().find(|e| dir.join(e).is_dir()).or(names.first()) else {
+        return Ok(());
+    };
+    let folding = folding(matches);
+    let named = |name: &str| dir.file_name()
+        .is_some_and(|e| folding.fold(Path::new(e)) == folding.fold(Path::new(name)));
+    if names.iter().any(|name| named(name)) {
+        return Ok(());
+    }
+    let surface = matches.get_flag("surface");
+    let trees = sibling_trees(dir, expected);
+    if !trees.is_empty() {
+        let first = trees[0].file_name().unwrap_or_default().to_string_lossy();
+        say!("{}", Warning::new(WarningCode::SiblingTrees, format!(
+            "{} holds {} top-level directories outside the torrent, e.g. {}; {} remove them with \
+             everything inside.", dir.display(), trees.len(), first,
+            if surface { "--surface plans to" } else { "--surface would" })).at(dir).record());
+        let content = dir.join(name);
+        match content.is_dir() {
+            true => say!("Did you mean {}?", content.display()),
+            false => say!("Pass the torrent's content directory, usually named {}.", name),
         }
     }
+    if clean && surface && !matches.get_flag("force") {
+        return Err(anyhow!("{} is not named after the torrent ({}); add --force to clean it with \
+            --surface", dir.display(), name));
+    }
+    Ok(())
+}
 
-    fn path_colored(path: &Path) -> Painted<Display> {
-        match path.is_dir() {
-            true => Blue.paint(path.display()),
-            false => NotSet.paint(path.display()),
+/// The directory to scan for `dir` as given: the torrent's directory inside it, or the one it is
+/// inside when the torrent's paths start with its name, if `dir` holds none of the torrent's
+/// top-level entries but that directory does. Never guessed with `--surface`, which widens what is
+/// removed, with `--no-autodetect`, for `--manifest` or for a client's torrents. Fails when files
+/// of the torrent are both in `dir` and in the torrent's directory inside it, as either could be
+/// meant.
+fn detect_root(matches: &ArgMatches, dir: PathBuf, expected: &impl ExpectedSet,
+               record: &mut RunRecord, sources: &mut Sources) -> anyhow::Result<PathBuf> {
+    if matches.get_flag("no-autodetect") || matches.get_flag("surface")
+        || ["manifest", "torrents-dir", "transmission-config"].into_iter()
+            .any(|e| matches.try_get_one::<PathBuf>(e).is_ok_and(|e| e.is_some())) {
+        return Ok(dir);
+    }
+    let holds_torrent = |dir: &Path| fs::read_dir(dir)
+        .is_ok_and(|entries| entries.flatten().any(|e| expected.is_surface(&e.file_name())));
+    let names = sources.names.iter()
+        .filter(|e| !e.is_empty() && *e != "." && *e != ".." && !e.contains(['/', '\\']))
+        .collect::<Vec<&String>>();
+    if holds_torrent(&dir) {
+        let holds_file = |dir: &Path| expected.files()
+            .any(|(path, _)| fs::symlink_metadata(dir.join(path)).is_ok());
+        let nested = names.iter().map(|e| dir.join(e))
+            .find(|e| !expected.is_surface(e.file_name().unwrap_or_default()) && e.is_dir()
+                  && holds_file(e));
+        if let Some(nested) = nested.filter(|_| holds_file(&dir)) {
+            return Err(anyhow!("Files of the torrent are both directly in {} and in {}, so the \
+                layout is ambiguous; pass --flat to scan the former as given, or the latter as \
+                the directory", dir.display(), nested.display()));
         }
+        return Ok(dir);
+    }
+    let inside = names.iter().map(|e| dir.join(e)).find(|e| e.is_dir() && holds_torrent(e));
+    let outside = || dir.parent().filter(|parent| dir.file_name().is_some_and(|name| {
+        names.iter().any(|e| OsStr::new(e) == name) && expected.is_surface(name)
+    }) && holds_torrent(parent)).map(Path::to_path_buf);
+    let Some(root) = inside.or_else(outside) else {
+        return Ok(dir);
+    };
+    say!("{} holds none of the torrent's top-level entries; scanning {} instead (--flat to scan \
+          it as given).\n", dir.display(), root.display());
+    record.dir = root.clone();
+    sources.origins = std::mem::take(&mut sources.origins).into_iter()
+        .map(|(path, names)| (root.join(path.strip_prefix(&dir).unwrap_or(&path)), names))
+        .collect();
+    Ok(root)
+}
+
+/// Reports sibling copies of `dir` such as `Show (1)`; when cleaning with `--surface`, offers to
+/// merge the files `dir` lacks from each copy and delete it, or does what `--duplicate-roots`
+/// says without asking.
+fn handle_duplicates(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet, clean: bool)
+                     -> anyhow::Result<()> {
+    let roots = find_duplicate_roots(dir, expected);
+    if roots.is_empty() {
+        return Ok(());
+    }
+    let total = expected.file_count();
+    let present = present_files(dir, expected);
+    for root in &roots {
+        say!("{} {} ({}, {}/{} expected files; {} has {}/{})", Red.paint("Probable duplicate root:"),
+                 root.path.display(), BinaryBytes(root.size), root.present, total, dir.display(),
+                 present, total);
+    }
+    if !clean || !matches.get_flag("surface") {
+        say!("Rerun clean with --surface to merge or delete them.\n");
+        return Ok(());
+    }
+    let action = matches.get_one::<String>("duplicate-roots").expect("default").as_str();
+    if action == "keep" {
+        say!("Duplicate roots are left alone with --duplicate-roots keep.\n");
+        return Ok(());
+    }
+    if action == "ask" && matches.get_flag("no-confirm") {
+        say!("Duplicate roots are left alone with --no-confirm.\n");
+        return Ok(());
+    }
+    if action == "ask" && (matches.get_flag("assume-no") || !interactive()) {
+        say!("Duplicate roots are left alone {}.\n", match matches.get_flag("assume-no") {
+            true => "with --assume-no",
+            false => "without a terminal to ask on",
+        });
+        return Ok(());
     }
 
-    // Compare directory
-    if matches.subcommand_matches("diff").is_some() {
-        let mut new_files = Vec::new();
-        let mut new_size: u64 = 0;
-        for entry in files.iter() {
-            let path = dir.join(entry.0);
-            if !path.exists() {
-                new_files.push(path);
-                new_size += entry.1;
+    for root in roots {
+        if root.present > present {
+            say!("{} holds more of the torrent than {}; consider cleaning it instead.",
+                     root.path.display(), dir.display());
+            continue;
+        }
+        let merge = match action {
+            "merge" => true,
+            "delete" => false,
+            _ => {
+                let choices = vec!["Merge missing files, then delete it", "Delete it", "Keep it"];
+                let choice = Select::new(&format!("What to do with {}?", root.path.display()),
+                                         choices).raw_prompt();
+                match choice.map(|e| e.index) {
+                    Ok(0) => true,
+                    Ok(1) => false,
+                    _ => continue,
+                }
             }
+        };
+        if merge {
+            let count = merge_into(&root.path, dir, expected, move_options(matches))?;
+            say!("Moved {} files into {}.", count, dir.display());
         }
+        fs::remove_dir_all(&root.path).map_err(|e| CliError::new(ErrorCode::RemoveFailed,
+            format!("Failed to remove directory: {}", root.path.display()))
+            .with_path(&root.path).with_entry(&root.path, e))?;
+        say!("Removed {}.", root.path.display());
+    }
+    say!();
+    Ok(())
+}
 
-        if new_files.is_empty() && old_files.is_empty() && empty_dirs.is_empty() {
-            println!("No matching entries found.");
-            return Ok(());
+fn expected(matches: &ArgMatches, torrent: &TorrentMetaV1<ByteBufOwned>)
+            -> anyhow::Result<Expected> {
+    Expected::from_torrent_with(torrent, matches.get_flag("keep-padding"))
+}
+
+/// The spinner of the directory walk: the entries visited and the size of the extras found so
+/// far, over the directory being read.
+struct ScanProgress<'a> {
+    bar: ProgressBar,
+    root: &'a Path,
+    size: AtomicU64,
+}
+
+impl scan::Progress for ScanProgress<'_> {
+    fn inc(&self, delta: u64) {
+        self.bar.inc(delta);
+    }
+
+    fn found(&self, _: &Path, size: u64) {
+        let size = self.size.fetch_add(size, Ordering::Relaxed) + size;
+        self.bar.set_prefix(BinaryBytes(size).to_string());
+    }
+
+    fn entering(&self, dir: &Path) {
+        let dir = dir.strip_prefix(self.root).unwrap_or(dir);
+        self.bar.set_message(truncate_message(dir.display().to_string()));
+    }
+}
+
+pub fn scan(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet) -> anyhow::Result<Scan> {
+    let spinner = ProgressMode::from_matches(matches).bar(ProgressBar::new_spinner());
+    spinner.set_style(ProgressStyle::default_spinner()
+        .tick_chars("|/-\\")
+        .template("{spinner:.green} [{elapsed_precise}] Scanning... {pos} entries, {prefix} in \
+                   extras\n{msg}")?);
+    spinner.set_prefix(BinaryBytes(0).to_string());
+    spinner.enable_steady_tick(Duration::from_millis(100));
+
+    let progress = ScanProgress { bar: spinner, root: dir, size: AtomicU64::new(0) };
+    let mut options = scan_options(matches)?;
+    // Loaded here rather than by the scan, for the explanation to name its patterns
+    options.load_ignore_file(dir)?;
+    let mut result = scan_dir(dir, expected, &options, &progress);
+    progress.bar.finish_and_clear();
+    if let Ok(scan) = &mut result {
+        let paths = PathRenderer::new(dir, path_style(matches).unwrap_or(PathStyle::Relative));
+        print_unreadable(&scan.unreadable, &paths);
+        print_unknown_age(scan, &paths);
+        print_in_use(scan, &paths);
+        print_snapshots(scan, &paths);
+        set_aside_collisions(matches, dir, expected, scan, &paths);
+        explain_scan(dir, expected, scan, &options);
+    }
+    result
+}
+
+/// Warns about the entries `--skip-errors` went past, before the results they are missing from.
+pub fn print_unreadable(unreadable: &[Unreadable], paths: &PathRenderer) {
+    if unreadable.is_empty() {
+        return;
+    }
+    // Each entry is recorded as a warning of its own, under a heading that is not one
+    eprintln!("{}", Warning::new(WarningCode::Unreadable, format!(
+        "{} could not be read and {} left alone:", match unreadable.len() {
+            1 => "1 entry".to_string(),
+            n => format!("{} entries", n),
+        }, if unreadable.len() == 1 { "is" } else { "are" })));
+    for entry in unreadable {
+        Warning::new(WarningCode::Unreadable, format!("could not be read: {}", entry.error))
+            .at(&entry.path).record();
+        eprintln!("    {}: {}", paths.render(&entry.path), entry.error);
+    }
+}
+
+/// Warns about the files of the torrent that cannot all exist on disk as spelled, and leaves alone
+/// the extras that may be one of them spelled otherwise unless `clean --force` is given.
+pub fn set_aside_collisions(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet,
+                            scan: &mut Scan, paths: &PathRenderer) {
+    let collisions = collisions(expected);
+    if collisions.is_empty() {
+        return;
+    }
+    eprintln!("{}", warn(WarningCode::Colliding, format!(
+        "the torrent declares {} paths that cannot all exist on disk as spelled:",
+        collisions.iter().flat_map(Collision::paths).collect::<HashSet<&Path>>().len())));
+    for collision in &collisions {
+        match collision {
+            Collision::Alike(alike) => eprintln!("    {}  (one file ignoring case or the \
+                characters Windows forbids)", alike.iter().map(|e| paths.render(&dir.join(e)))
+                .collect::<Vec<String>>().join(" = ")),
+            Collision::FileAsDir { file, inside } => eprintln!("    {}  (a file, yet {} is inside \
+                it)", paths.render(&dir.join(file)), paths.render(&dir.join(inside))),
         }
+    }
+    let colliding = scan.colliding(dir, &collisions);
+    if colliding.is_empty() || matches.try_get_one::<bool>("force").ok().flatten() == Some(&true) {
+        return;
+    }
+    eprintln!("{}", warn(WarningCode::Colliding, format!(
+        "{} extras may be these files spelled otherwise and are left alone; clean --force removes \
+         them.", colliding.len())));
+    scan.exclude(&colliding, SkipReason::Colliding);
+}
+
+/// Tells which extras were left alone for being files of the run itself.
+pub fn print_in_use(scan: &Scan, paths: &PathRenderer) {
+    for skipped in scan.skipped(SkipReason::InUse) {
+        verbose!(1, "Left alone: {} (used by this run)", paths.render(&skipped.path));
+    }
+}
+
+/// Warns about the extras `--older-than` or `--newer-than` left alone for want of a modification
+/// time.
+pub fn print_unknown_age(scan: &Scan, paths: &PathRenderer) {
+    for skipped in scan.skipped(SkipReason::UnknownAge) {
+        eprintln!("{}", Warning::new(WarningCode::UnknownAge, format!(
+            "the modification time of {} cannot be read; leaving it alone",
+            paths.render(&skipped.path))).at(&skipped.path).record());
+    }
+}
+
+/// Names the snapshot and recycle areas left unread, on a line of its own.
+pub fn print_snapshots(scan: &Scan, paths: &PathRenderer) {
+    let areas = scan.skipped(SkipReason::Snapshot).map(|e| paths.render(&e.path))
+        .collect::<Vec<String>>();
+    if !areas.is_empty() {
+        say!("Left snapshot and recycle areas unread: {} (--include-snapshots to scan them).",
+             areas.join(", "));
+    }
+}
+
+fn target_dir(matches: &ArgMatches) -> anyhow::Result<PathBuf> {
+    check_dir(matches.get_one::<PathBuf>("dir").expect("required"))
+}
 
-        println!("File changes:");
+/// The directory of diff and clean, which is their only positional argument with `--manifest`,
+/// `--torrents-dir` or `--transmission-config`.
+fn content_dir(matches: &ArgMatches) -> anyhow::Result<PathBuf> {
+    let Some(option) = ["manifest", "torrents-dir", "transmission-config"].into_iter()
+        .find(|e| matches.contains_id(e)) else {
+        return target_dir(matches);
+    };
+    let option = format!("--{}", option);
+    match (matches.get_one::<PathBuf>("file"), matches.get_one::<PathBuf>("dir")) {
+        (Some(dir), None) => check_dir(dir),
+        (None, _) => build_cli().error(clap::error::ErrorKind::MissingRequiredArgument,
+            format!("the directory is required with {}", option)).exit(),
+        (Some(_), Some(_)) => build_cli().error(clap::error::ErrorKind::ArgumentConflict,
+            format!("{} replaces the torrent; give only the directory", option)).exit(),
+    }
+}
+
+/// Every directory of diff and clean, which take several for a torrent spread over them.
+fn content_dirs(matches: &ArgMatches) -> anyhow::Result<Vec<PathBuf>> {
+    let mut dirs = matches.get_many::<PathBuf>("dir").into_iter().flatten().collect::<Vec<_>>();
+    if matches.contains_id("manifest") {
+        dirs.splice(0..0, matches.get_one::<PathBuf>("file"));
+    }
+    match dirs.len() < 2 || matches.contains_id("torrents-dir") {
+        true => Ok(vec![content_dir(matches)?]),
+        false => dirs.into_iter().map(check_dir).collect(),
+    }
+}
+
+/// The torrents diff and clean compare against; none for `--manifest`.
+#[derive(Default)]
+pub struct Sources {
+    pub names: Vec<String>,
+    /// With `--also`, the names of the torrents expecting each file, by its path in the directory.
+    origins: HashMap<PathBuf, String>,
+    /// The v1 piece hashes of the first torrent, for `clean --verify` and `--verify-renames`.
+    pieces: Option<Pieces>,
+}
 
-        for entry in &old_files {
-            println!("{}  {}", Red.paint("-f"), path_colored(entry));
+/// The torrent's files together with those of the `--also` torrents, a file only having to be
+/// expected by one of them. Sizes they disagree on are warned about, keeping the first one's.
+fn load_torrents(matches: &ArgMatches, command: &str, dir: &Path)
+                 -> anyhow::Result<(Expected, RunRecord, Sources)> {
+    let torrent = load_torrent(matches)?;
+    let mut expected = expected(matches, &torrent)?;
+    let mut record = new_record(command, &torrent, dir);
+    let mut names = vec![record.torrent.clone()];
+    let pieces = Pieces::of(&torrent.info);
+    let Some(also) = matches.get_many::<PathBuf>("also") else {
+        return Ok((expected, record, Sources { names, origins: HashMap::new(), pieces }));
+    };
+    let mut origins = HashMap::<PathBuf, Vec<String>>::new();
+    let add = |origins: &mut HashMap<PathBuf, Vec<String>>, expected: &Expected, name: &String| {
+        for (path, _) in expected.files() {
+            origins.entry(dir.join(path)).or_default().push(name.clone());
         }
+    };
+    add(&mut origins, &expected, &names[0]);
+    for input in also {
+        let torrent = load_metainfo_from(matches, input)?.meta;
+        let name = torrent.info.name.as_ref().map(|e| e.to_string()).unwrap_or_default();
+        let other = self::expected(matches, &torrent)?;
+        add(&mut origins, &other, &name);
+        for (path, size) in expected.merge(other) {
+            let kept = expected.file_size(&path).unwrap_or_default();
+            eprintln!("{}", Warning::new(WarningCode::SizeConflict, format!(
+                "{} is {} in {} but {} in {}; expecting the former", path.display(),
+                BinaryBytes(kept), origins[&dir.join(&path)][0], BinaryBytes(size), name))
+                .at(&dir.join(&path)).record());
+        }
+        names.push(name);
+    }
+    say!("Combined: {} files, {} from {} torrents\n", expected.file_count(),
+         BinaryBytes(expected.total_size()), names.len());
+    record.torrent = names.join(" + ");
+    let origins = origins.into_iter().map(|(path, names)| (path, names.join(", "))).collect();
+    Ok((expected, record, Sources { names, origins, pieces }))
+}
+
+/// The files of every .torrent in the `--torrents-dir` of a client, each under the torrent's name
+/// in the downloads directory. Torrents that fail to load are warned about and left out. With
+/// the `resume` directory of Transmission, see [`transmission_expected`].
+fn load_session(matches: &ArgMatches, command: &str, dir: &Path, session: &Path,
+                resume: Option<&Path>) -> anyhow::Result<(Expected, RunRecord, Sources)> {
+    let unreadable = |e: std::io::Error| CliError::new(ErrorCode::TorrentUnreadable,
+        format!("Failed to read torrents directory {}: {}", session.display(), e)).with_path(session);
+    let mut files = fs::read_dir(session).map_err(unreadable)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<PathBuf>, _>>().map_err(unreadable)?;
+    files.retain(|e| e.extension().is_some_and(|e| e.eq_ignore_ascii_case("torrent")));
+    files.sort();
 
-        for entry in &empty_dirs {
-            println!("{}  {}", Red.paint("-d"), path_colored(entry));
+    let spinner = ProgressMode::from_matches(matches).bar(ProgressBar::new(files.len() as u64));
+    spinner.set_style(ProgressStyle::default_bar()
+        .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos}/{len} {msg}")?);
+    spinner.set_message("Parsing...");
+    let mut expected = Expected::from_files([])?;
+    let mut names = Vec::new();
+    let mut origins = HashMap::<PathBuf, String>::new();
+    let mut skipped = 0;
+    for file in &files {
+        let result = parse_torrent(&ProgressBar::hidden(), file).and_then(|e| {
+            let keep_padding = matches.get_flag("keep-padding");
+            let expected = match resume {
+                Some(resume) => transmission_expected(&e.meta, keep_padding, resume, file)?,
+                None => (Expected::from_torrent_in_parent(&e.meta, keep_padding)?, None),
+            };
+            Ok((e.meta, expected))
+        });
+        spinner.inc(1);
+        let (torrent, (other, resume_warning)) = match result {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                let warning = Warning::new(WarningCode::TorrentSkipped,
+                                           format!("skipped {}: {:#}", file.display(), err))
+                    .at(file).record();
+                spinner.suspend(|| eprintln!("{}", warning));
+                skipped += 1;
+                continue;
+            }
+        };
+        if let Some(warning) = resume_warning {
+            spinner.suspend(|| eprintln!("{}", warning.record()));
+        }
+        for warning in unsafe_path_warnings(other.unsafe_paths(), file) {
+            spinner.suspend(|| eprintln!("{}", warning));
         }
+        let name = torrent.info.name.as_ref().map(|e| e.to_string()).unwrap_or_default();
+        let paths = other.files().map(|(path, _)| path.to_path_buf()).collect::<Vec<PathBuf>>();
+        for (path, size) in expected.merge(other) {
+            let kept = expected.file_size(&path).unwrap_or_default();
+            let warning = Warning::new(WarningCode::SizeConflict, format!(
+                "{} is {} in {} but {} in {}; expecting the former", path.display(),
+                BinaryBytes(kept), origins[&dir.join(&path)], BinaryBytes(size), file.display()))
+                .at(&dir.join(&path)).record();
+            spinner.suspend(|| eprintln!("{}", warning));
+        }
+        for path in paths {
+            origins.entry(dir.join(path)).or_insert_with(|| name.clone());
+        }
+        names.push(name);
+    }
+    spinner.finish_and_clear();
+    say!("Torrents directory: {} torrents loaded{}, {} files, {}\n", names.len(),
+         match skipped {
+             0 => String::new(),
+             n => format!(" ({} skipped)", n),
+         },
+         expected.file_count(), BinaryBytes(expected.total_size()));
+    let name = session.file_name().map_or(String::new(), |e| e.to_string_lossy().into_owned());
+    let record = RunRecord::new(command, expected_digest(&expected), name, dir);
+    // The directory holds the torrents side by side, so no torrent names its scope
+    Ok((expected, record, Sources::default()))
+}
+
+/// The files of the torrent loaded from `file` as Transmission downloaded them, under the paths
+/// its `.resume` file of the same name in `resume` records for the files and the torrent renamed
+/// in Transmission. Without a resume file that can be read and fits the torrent, the torrent's
+/// own paths are expected instead, along with a warning saying why.
+fn transmission_expected(torrent: &TorrentMetaV1<ByteBufOwned>, keep_padding: bool, resume: &Path,
+                         file: &Path) -> anyhow::Result<(Expected, Option<Warning>)> {
+    let mut name = file.file_stem().unwrap_or_default().to_os_string();
+    name.push(".resume");
+    let resume = resume.join(name);
+    let renamed = read_transmission_resume(&resume).and_then(|e| {
+        Expected::from_torrent_renamed(torrent, keep_padding, e.name.as_deref(), &e.files)
+            .with_context(|| format!("{} does not fit the torrent", resume.display()))
+    });
+    match renamed {
+        Ok(expected) => Ok((expected, None)),
+        Err(err) => Ok((Expected::from_torrent_in_parent(torrent, keep_padding)?,
+                        Some(Warning::new(WarningCode::Resume, format!(
+                            "{:#}; expecting the files of {} under the torrent's own paths", err,
+                            file.display())).at(&resume)))),
+    }
+}
 
-        for entry in new_files.iter() {
-            println!("{}   {}", Green.paint("+"), path_colored(entry));
+/// How `--ignore-case` and `--normalize-unicode` have paths matched.
+pub fn folding(matches: &ArgMatches) -> Folding {
+    let unicode = match matches.get_one::<String>("normalize-unicode").map(String::as_str) {
+        Some("always") => true,
+        Some("never") => false,
+        _ => cfg!(target_os = "macos"),
+    };
+    Folding { case: matches.get_flag("ignore-case"), unicode }
+}
+
+/// Expects the top-level folders `--map` renames under their new names, warning about those the
+/// torrent does not have.
+pub fn apply_renames(matches: &ArgMatches, expected: &mut Expected) -> anyhow::Result<()> {
+    let renames = matches.get_many::<(String, String)>("map")
+        .map_or(Vec::new(), |e| e.cloned().collect());
+    for (i, (old, _)) in renames.iter().enumerate() {
+        if renames[..i].iter().any(|e| e.0 == *old) {
+            return Err(anyhow!("--map renames {} more than once", old));
         }
+    }
+    for old in expected.rename_top_level(&renames) {
+        eprintln!("{}", warn(WarningCode::Map, format!(
+            "--map {}: the torrent has no top-level entry of that name", old)));
+    }
+    Ok(())
+}
+
+/// Matches the expected files as [`folding`] has them, warning about those that then fold alike.
+pub fn apply_folding(matches: &ArgMatches, expected: &mut Expected) {
+    for (first, other) in expected.fold(folding(matches)) {
+        eprintln!("{}", warn(WarningCode::FoldedAlike, format!(
+            "{} and {} differ only by case or Unicode normalization, so both are the same file on \
+             disk", first.display(), other.display())));
+    }
+}
 
-        println!();
-        println!("New files: {} ({})", Green.paint(BinaryBytes(new_size)), new_files.len());
-        println!("Remove entries: {} ({})", Red.paint(BinaryBytes(rm_size)),
-                 old_files.len() + empty_dirs.len());
-    } else { // Delete files
-        let files = old_files;
+/// The expected files of diff and clean, renamed by `--map` and matched as [`folding`] has them.
+pub fn load_expected(matches: &ArgMatches, command: &str, dir: &Path)
+                     -> anyhow::Result<(Expected, RunRecord, Sources)> {
+    let (mut expected, record, mut sources) = load_files(matches, command, dir)?;
+    apply_renames(matches, &mut expected)?;
+    if let (Some(pieces), Some(renames)) = (&mut sources.pieces, matches.get_many("map")) {
+        pieces.rename_top_level(&renames.cloned().collect::<Vec<(String, String)>>());
+    }
+    apply_folding(matches, &mut expected);
+    Ok((expected, record, sources))
+}
 
-        let progress = if files.is_empty() {
-            println!("No matching entries found.");
-            if !include_empty_dir {
-                println!("Aborted.");
-                return Ok(())
-            }
+/// The files `--manifest` lists, those of the torrents in `--torrents-dir` or
+/// `--transmission-config`, or else the torrents', with a record of the run against them.
+fn load_files(matches: &ArgMatches, command: &str, dir: &Path)
+              -> anyhow::Result<(Expected, RunRecord, Sources)> {
+    if let Some(session) = matches.get_one::<PathBuf>("torrents-dir") {
+        return load_session(matches, command, dir, session, None);
+    }
+    if let Some(config) = matches.get_one::<PathBuf>("transmission-config") {
+        let (expected, mut record, sources) = load_session(matches, command, dir,
+                                                           &config.join("torrents"),
+                                                           Some(&config.join("resume")))?;
+        record.torrent = config.file_name()
+            .map_or(String::new(), |e| e.to_string_lossy().into_owned());
+        return Ok((expected, record, sources));
+    }
+    let Some(path) = matches.get_one::<PathBuf>("manifest") else {
+        return load_torrents(matches, command, dir);
+    };
+    let content = fs::read_to_string(path).map_err(|e| CliError::new(ErrorCode::TorrentUnreadable,
+        format!("Failed to read manifest {}: {}", path.display(), e)).with_path(path))?;
+    let expected = parse_file_list(&content).and_then(Expected::from_files)
+        .with_context(|| format!("Invalid manifest {}", path.display()))?;
+    say!("Manifest: {} files, {}\n", expected.file_count(), BinaryBytes(expected.total_size()));
+    let name = path.file_stem().map_or(String::new(), |e| e.to_string_lossy().into_owned());
+    let record = RunRecord::new(command, expected_digest(&expected), name, dir);
+    Ok((expected, record, Sources::default()))
+}
 
-            let progress = ProgressBar::no_length();
-            progress.set_style(ProgressStyle::default_spinner()
-                .tick_chars("|/-\\|/-\\ ")
-                .template("{prefix} [{elapsed_precise}] {spinner:.green}\n{msg}")?);
-            progress.enable_steady_tick(Duration::from_millis(50));
-            progress
-        } else {
-            println!("Existed files found:");
-            for entry in &files {
-                println!("{}  {}", Red.paint(match entry.is_dir() {
-                    true => "-d",
-                    false => "-f",
-                }), path_colored(entry));
-            }
-
-            println!();
-            println!("Remove files: {} ({})", Red.paint(BinaryBytes(rm_size)), files.len());
-
-            if !no_confirm {
-                match Confirm::new(format!("Delete the above {} files?", files.len()).as_str())
-                    .with_default(true).prompt() {
-                    Ok(true) => {
-                        println!("Confirmed.");
-                    }
-                    _ => {
-                        println!("Aborted.");
-                        return Ok(());
-                    }
-                }
-            }
+fn check_dir(input: &PathBuf) -> anyhow::Result<PathBuf> {
+    if RemoteDir::parse(input).is_some() {
+        return Err(CliError::new(ErrorCode::DirNotFound, format!("{} is a remote directory, which \
+            only diff and clean support", input.display())).with_path(input).into());
+    }
+    let dir = absolute_path(input)?;
+    if dir.is_file() {
+        let parent = dir.parent().unwrap_or(Path::new("/"));
+        return Err(CliError::new(ErrorCode::DirNotFound, format!("{} is a file, not a directory; \
+            for a single-file torrent pass the directory holding it: {}", dir.display(),
+            parent.display())).with_path(&dir).into());
+    }
+    if !dir.is_dir() {
+        return Err(CliError::new(ErrorCode::DirNotFound,
+            format!("Directory not found: {}", dir.display())).with_path(&dir).into());
+    }
+    Ok(dir)
+}
 
-            let progress = ProgressBar::new(files.len() as u64);
-            progress.set_style(ProgressStyle::default_bar()
-                .template("{prefix} [{wide_bar:.cyan/blue}] {pos}/{len} ({percent}%)\n{msg}")?);
-            progress.set_prefix("Processing");
+pub fn new_record(command: &str, torrent: &TorrentMetaV1<ByteBufOwned>, dir: &Path) -> RunRecord {
+    let name = torrent.info.name.as_ref().map(|e| e.to_string()).unwrap_or_default();
+    RunRecord::new(command, torrent.info_hash.as_string(), name, dir)
+}
 
-            for entry in &files {
-                fs::remove_file(entry)?;
-                progress.set_message(truncate_message(
-                    format!("Removed file: {}", entry.to_string_lossy())));
-                progress.inc(1);
-            }
+/// Appends the run to the history unless disabled; failures are only warned about.
+pub fn save_record(matches: &ArgMatches, record: &RunRecord) {
+    if matches.get_flag("no-history") {
+        return;
+    }
+    let keep = *matches.get_one::<usize>("history-keep").expect("default");
+    if let Err(err) = history_file().and_then(|file| record_run(&file, record, keep)) {
+        eprintln!("{}", warn(WarningCode::History,
+                             format!("failed to record run history: {:#}", err)));
+    }
+}
 
-            progress
-        };
+/// Every file the run reads or writes: those given on the command line and the history and
+/// config files it keeps in the user's directories. The scan never plans to remove them.
+pub fn own_files(matches: &ArgMatches) -> HashSet<PathBuf> {
+    let mut files = matches.ids()
+        .filter_map(|id| matches.try_get_many::<PathBuf>(id.as_str()).ok().flatten())
+        .flatten()
+        // The directories given are scanned, or moved or linked into, not files of the run
+        .filter(|e| e.as_path() != Path::new("-") && !e.is_dir())
+        .cloned()
+        .collect::<HashSet<PathBuf>>();
+    files.extend(history_file().ok());
+    files.extend(config::default_path());
+    files
+}
 
-        let mut count = files.len();
-        if include_empty_dir {
-            progress.set_prefix("Clearing dirs");
-            let vec = find_empty_dirs(dir);
-            let mut empty_dirs = vec.iter().filter(|e| !dirs.contains(*e))
-                .collect::<Vec<&PathBuf>>();
-            empty_dirs.sort();
-            empty_dirs.reverse();
-            for entry in &empty_dirs {
-                fs::remove_dir_all(entry)?;
-                progress.set_message(truncate_message(
-                    format!("Removed directory: {}", entry.to_string_lossy())));
-            }
-            count += empty_dirs.len();
+/// The order of `--sort` and `--reverse`.
+pub fn sort_order(matches: &ArgMatches) -> SortOrder {
+    SortOrder {
+        key: match matches.get_one::<String>("sort").map(String::as_str) {
+            Some("size") => SortKey::Size,
+            Some("mtime") => SortKey::Mtime,
+            _ => SortKey::Path,
+        },
+        reverse: matches.get_flag("reverse"),
+    }
+}
+
+pub fn scan_options(matches: &ArgMatches) -> anyhow::Result<ScanOptions> {
+    Ok(ScanOptions {
+        include_surface: matches.get_flag("surface"),
+        include_empty_dir: matches.get_flag("empty-dir"),
+        prune: Prune::new(matches.get_many::<String>("prune-dir").into_iter().flatten())?,
+        exclude: Exclude::new(matches.get_many::<String>("exclude").into_iter().flatten())?,
+        keep: match matches.get_one::<PathBuf>("keep-from") {
+            Some(file) => fs::read_to_string(file).map_err(anyhow::Error::from)
+                .and_then(|e| parse_keep_list(&e))
+                .with_context(|| format!("Invalid keep list {}", file.display()))?,
+            None => HashSet::new(),
+        },
+        sorted: !matches.get_flag("unsorted"),
+        order: sort_order(matches),
+        owner: match matches.get_one::<String>("owner") {
+            Some(user) => Some(lookup_user(user)?),
+            None if matches.get_flag("only-owned") => Some(current_uid()?),
+            None => None,
+        },
+        clean_droppings: matches.get_flag("clean-droppings"),
+        remove_incomplete: matches.get_flag("remove-incomplete"),
+        include_snapshots: matches.get_flag("include-snapshots"),
+        private: PRIVATE.get().cloned().unwrap_or_default(),
+        remove_mismatched: matches.get_flag("remove-mismatched"),
+        deletable: matches.get_many::<String>("deletable-ext").map(Deletable::new),
+        symlinks: match matches.get_one::<String>("symlinks").map(String::as_str) {
+            Some("keep") => Symlinks::Keep,
+            Some("follow") => Symlinks::Follow,
+            _ => Symlinks::Remove,
+        },
+        skip_errors: matches.get_flag("skip-errors"),
+        skip_hardlinked: matches.get_flag("skip-hardlinked"),
+        max_depth: matches.get_one::<u16>("max-depth").map(|e| *e as usize),
+        one_file_system: matches.get_flag("one-file-system"),
+        min_size: matches.get_one::<u64>("min-size").copied(),
+        max_size: matches.get_one::<u64>("max-size").copied(),
+        older_than: matches.get_one::<Duration>("older-than").copied(),
+        newer_than: matches.get_one::<Duration>("newer-than").copied(),
+        protected: own_files(matches),
+        ignore_file: !matches.get_flag("no-ignore-file"),
+        junk: Junk::new(match matches.get_one::<String>("junk").map(String::as_str) {
+            Some("keep") => JunkMode::Keep,
+            Some("only") => JunkMode::Only,
+            _ => JunkMode::Remove,
+        }, matches.get_many::<String>("junk-pattern").into_iter().flatten())?,
+        threads: match matches.get_one::<u16>("threads") {
+            Some(threads) => *threads as usize,
+            None => std::thread::available_parallelism().map_or(1, |e| e.get()),
+        },
+    })
+}
+
+pub fn top(matches: &ArgMatches) -> usize {
+    *matches.get_one::<usize>("top").expect("default")
+}
+
+/// How entries under a scanned directory are shown in listings.
+pub struct Listing {
+    pub paths: PathRenderer,
+    pub highlight: Vec<String>,
+    /// Entries shown per listing section; 0 shows all.
+    pub limit: usize,
+    /// Files of the torrent `clean --delete-corrupt` removes with the extras, tagged as corrupt.
+    pub corrupt: HashSet<PathBuf>,
+    /// The torrents expecting each file, shown next to missing ones when there are several.
+    pub origins: HashMap<PathBuf, String>,
+    /// What counts as junk, tagged as such in the listings.
+    pub junk: Junk,
+    /// Extra files with links outside the removal, tagged as hardlinked.
+    pub hardlinked: HashSet<PathBuf>,
+    /// Extras identical to a present file of the torrent, with `--verify-duplicates`, tagged
+    /// with that file.
+    pub duplicates: HashMap<PathBuf, PathBuf>,
+    /// List the removals by directory, in this order, rather than one by one.
+    pub group: Option<GroupOrder>,
+    /// Show the changes as a tree drawn with these connectors.
+    pub tree: Option<Glyphs>,
+    /// The torrent's size of each missing file, shown in the tree.
+    pub missing_sizes: HashMap<PathBuf, u64>,
+    /// The command takes `--report`, which listings cut short point to.
+    pub report: bool,
+}
+
+impl Listing {
+    /// How many of `total` entries a listing section leaves out.
+    pub fn unlisted(&self, total: usize) -> usize {
+        match self.limit {
+            0 => 0,
+            limit => total.saturating_sub(limit),
         }
+    }
+}
 
-        progress.set_prefix("Done");
-        progress.set_message(format!("{} entries removed.", count));
-        progress.finish();
+/// Listings default to paths relative to `dir`.
+pub fn listing(matches: &ArgMatches, dir: &Path) -> Listing {
+    Listing {
+        paths: PathRenderer::new(dir, path_style(matches).unwrap_or(PathStyle::Relative)),
+        highlight: matches.get_many::<String>("highlight-ext")
+            .map_or(Vec::new(), |e| e.cloned().collect()),
+        limit: match matches.get_flag("show-all") {
+            true => 0,
+            false => *matches.get_one::<usize>("limit").expect("default"),
+        },
+        corrupt: HashSet::new(),
+        origins: HashMap::new(),
+        // An invalid --junk-pattern already failed the scan options
+        junk: Junk::new(JunkMode::Remove, matches.get_many::<String>("junk-pattern")
+            .into_iter().flatten()).unwrap_or_default(),
+        hardlinked: HashSet::new(),
+        duplicates: HashMap::new(),
+        group: None,
+        tree: None,
+        missing_sizes: HashMap::new(),
+        report: matches.try_get_one::<PathBuf>("report").is_ok(),
     }
+}
 
-    println!("Operation completed successfully.");
-    Ok(())
+/// How `clean` removes what it removes in place.
+pub fn remove_options(matches: &ArgMatches) -> RemoveOptions {
+    RemoveOptions { force: matches.get_flag("force"), trash: matches.get_flag("trash") }
 }
 
-/// Source: https://stackoverflow.com/a/54817755
-pub fn absolute_path(path: impl AsRef<Path>) -> io::Result<PathBuf> {
-    let path = path.as_ref();
+/// `--limit` of the listings of a flat torrent when none is given, as they tend to list every
+/// file at the top level.
+const FLAT_LIMIT: usize = 100;
 
-    let absolute_path = if path.is_absolute() {
-        path.to_path_buf()
-    } else {
-        env::current_dir()?.join(path)
-    }.clean();
+/// The listing for a torrent's directory; that of a flat torrent shows [`FLAT_LIMIT`] entries
+/// per section unless `--limit` is given.
+pub fn torrent_listing(matches: &ArgMatches, dir: &Path, flat: bool) -> Listing {
+    let mut listing = listing(matches, dir);
+    if flat && matches.value_source("limit") == Some(ValueSource::DefaultValue)
+        && !from_config("limit") && !matches.get_flag("show-all") {
+        listing.limit = FLAT_LIMIT;
+    }
+    listing
+}
 
-    Ok(absolute_path)
+pub fn move_options(matches: &ArgMatches) -> MoveOptions {
+    MoveOptions {
+        touch: matches.get_flag("touch-moved"),
+        bwlimit: matches.get_one::<u64>("bwlimit").copied(),
+    }
 }
 
-// Credit: Copilot
-fn check_dir_kind_of_empty<P: AsRef<Path>>(path: P) -> bool {
-    if let Ok(entries) = fs::read_dir(&path) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_dir() {
-                // Recursively check the subdirectory
-                if !check_dir_kind_of_empty(&path) {
-                    return false;
-                }
-            } else {
-                // If there's any file, the directory is not empty
-                return false;
-            }
+/// Writes `--report`, if given, with the entries `scan` plans to remove and, in `ndjson`, those
+/// `--deletable-ext` holds back and the `missing` files as well, and which of the removals are
+/// `corrupt` files of the torrent.
+fn write_planned_report(matches: &ArgMatches, dir: &Path, scan: &Scan,
+                        corrupt: &HashSet<PathBuf>, missing: Vec<ReportRow>, info_hash: &str)
+                        -> anyhow::Result<()> {
+    let Some(file) = matches.get_one::<PathBuf>("report") else {
+        return Ok(());
+    };
+    match matches.get_one::<String>("report-format").map(String::as_str) {
+        Some("ndjson") => {
+            let rows = ReportRow::planned(dir, scan, corrupt, ReportAction::WouldRemove)
+                .chain(ReportRow::held_back(dir, scan))
+                .chain(missing);
+            write_report(file, rows, info_hash)?;
         }
+        _ => write_removal_list(file, dir, scan)?,
     }
-    // If we loop through all entries and find only empty directories, return true
-    true
+    say!("Report of the entries to remove written to {}.", file.display());
+    Ok(())
 }
 
-// Credit: Copilot
-fn find_empty_dirs<P: AsRef<Path>>(path: P) -> Vec<PathBuf> {
-    let mut empty_dirs = Vec::new();
-    if let Ok(entries) = fs::read_dir(&path) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_dir() {
-                if check_dir_kind_of_empty(&path) {
-                    empty_dirs.push(path.clone());
-                }
-                empty_dirs.extend(find_empty_dirs(path));
-            }
+/// Writes the `--report-format ndjson` report again once `clean` is done, with what became of
+/// each entry. The one written before anything was removed stays if this fails.
+fn write_outcome_report(matches: &ArgMatches, dir: &Path, scan: &Scan,
+                        corrupt: &HashSet<PathBuf>, failed: &[(PathBuf, String)], moved: bool,
+                        info_hash: &str) {
+    let Some(file) = matches.get_one::<PathBuf>("report")
+        .filter(|_| matches.get_one::<String>("report-format").is_some_and(|e| e == "ndjson"))
+    else {
+        return;
+    };
+    let rows = ReportRow::outcome(dir, scan, corrupt, failed, moved)
+        .chain(ReportRow::held_back(dir, scan));
+    match write_report(file, rows, info_hash) {
+        Ok(()) => say!("Report of the entries removed written to {}.", file.display()),
+        Err(err) => eprintln!("{}", Warning::new(WarningCode::Report, format!("{:#}", err))
+            .at(file).record()),
+    }
+}
+
+/// The entries an error of removing them names, with its message.
+fn failed_entries(err: &anyhow::Error) -> Vec<(PathBuf, String)> {
+    err.downcast_ref::<CliError>().map_or(Vec::new(), |e| e.entries.iter()
+        .map(|entry| (PathBuf::from(&entry.path), entry.message.clone())).collect())
+}
+
+/// Progress and log messages default to absolute paths.
+pub fn log_paths(matches: &ArgMatches, dir: &Path) -> PathRenderer {
+    PathRenderer::new(dir, path_style(matches).unwrap_or(PathStyle::Absolute))
+}
+
+fn path_style(matches: &ArgMatches) -> Option<PathStyle> {
+    matches.get_one::<String>("paths").map(|e| PathStyle::parse(e))
+}
+
+pub fn path_colored<'a>(path: &'a Path, listing: &'a Listing) -> KindPath<'a> {
+    KindPath { path, shown: listing.paths.render(path), highlight: &listing.highlight }
+}
+
+/// The first `--limit` of `entries`.
+pub fn limited<'a, T>(entries: &'a [T], listing: &Listing) -> &'a [T] {
+    match listing.limit {
+        0 => entries,
+        limit => &entries[..limit.min(entries.len())],
+    }
+}
+
+/// Tells how many of `total` entries a listing section left out.
+pub fn print_more(total: usize, listing: &Listing) {
+    match listing.unlisted(total) {
+        0 => {}
+        n if listing.report => say!("... and {} more (use --show-all to list everything, or \
+                                     --report to write them to a file)", HumanCount(n as u64)),
+        n => say!("... and {} more (use --show-all to list everything)", HumanCount(n as u64)),
+    }
+}
+
+/// The files and directories a confirmation asks about, making clear it covers those the listing
+/// left out.
+pub fn asked_entries(files: usize, dirs: usize, unlisted: usize) -> String {
+    let count = |n: usize| HumanCount(n as u64);
+    let entries = match (files, dirs) {
+        (files, 0) => format!("{} files", count(files)),
+        (0, dirs) => format!("{} directories", count(dirs)),
+        (files, dirs) => format!("{} files and {} directories", count(files), count(dirs)),
+    };
+    match unlisted {
+        0 => format!("the above {}", entries),
+        n => format!("all {} ({} not listed above)", entries, count(n)),
+    }
+}
+
+/// Marks the entries of a listing that are junk by name, as `(system)` to tell them from the
+/// `[junk]` type of their extension, those that free nothing being hardlinked, the corrupt files
+/// of the torrent and the duplicates of its present ones.
+pub fn tags(entry: &Path, listing: &Listing) -> String {
+    let rel = entry.strip_prefix(listing.paths.root()).unwrap_or(entry);
+    let mut tags = String::new();
+    if listing.junk.matches(rel) {
+        tags += "  (system)";
+    }
+    if listing.hardlinked.contains(entry) {
+        tags += "  (hardlinked)";
+    }
+    if listing.corrupt.contains(entry) {
+        tags += "  (corrupt)";
+    }
+    if let Some(kept) = listing.duplicates.get(entry) {
+        tags += &format!("  (duplicate of {})", listing.paths.render(kept));
+    }
+    tags
+}
+
+pub fn print_entries(entries: &[PathBuf], listing: &Listing) {
+    for entry in limited(entries, listing) {
+        say!("{}  {}{}", Red.paint(match entry.is_dir() {
+            _ if entry.is_symlink() => "-l",
+            true => "-d",
+            false => "-f",
+        }), path_colored(entry, listing), tags(entry, listing));
+    }
+    print_more(entries.len(), listing);
+}
+
+/// Lists the directories removed for being empty, marked `-d`, then those left empty by the
+/// removal, marked `-D`.
+pub fn print_empty_dirs(scan: &Scan, listing: &Listing) {
+    for (dirs, mark) in [(&scan.empty_dirs, "-d"), (&scan.emptied_dirs, "-D")] {
+        for entry in limited(dirs, listing) {
+            say!("{}  {}{}", Red.paint(mark), path_colored(entry, listing), tags(entry, listing));
         }
+        print_more(dirs.len(), listing);
+    }
+}
+
+/// How many of the empty directories `scan` removes a listing left out.
+pub fn unlisted_dirs(scan: &Scan, listing: &Listing) -> usize {
+    listing.unlisted(scan.empty_dirs.len()) + listing.unlisted(scan.emptied_dirs.len())
+}
+
+/// Prints what removing the extras frees on disk, less than their size for sparse files and
+/// nothing for those hardlinked elsewhere.
+pub fn print_space_freed(usage: &DiskUsage) {
+    match usage.hardlinked.len() {
+        0 => say!("Space freed: {} allocated on disk", BinaryBytes(usage.freed)),
+        n => say!("Space freed: {} of {} allocated on disk; {} hardlinked files free nothing",
+                  BinaryBytes(usage.freed), BinaryBytes(usage.allocated), n),
+    }
+}
+
+/// Prints the per-type counts of `files`, if any.
+pub fn print_kind_counts(files: &[PathBuf]) {
+    if !files.is_empty() {
+        say!("By type: {}", count_kinds(files));
     }
-    empty_dirs
 }
 
-fn list_recursive_dirs<I: IntoIterator<Item = impl AsRef<Path>>>(iter: I) -> Vec<PathBuf> {
-    let mut paths = Vec::new();
-    let mut head = PathBuf::new();
-    for path in iter.into_iter() {
-        head.push(path);
-        paths.push(head.clone());
+/// Prints how many entries were left alone for each reason, with their total size.
+pub fn print_skipped(matches: &ArgMatches, scan: &Scan) {
+    let mut totals = scan.skip_totals();
+    let scope_notice = !matches.get_flag("no-scope-notice");
+    if !scope_notice {
+        totals.remove(&SkipReason::OutOfScope);
+    }
+    if totals.is_empty() {
+        return;
+    }
+    say!("Skipped:");
+    for (reason, (count, size)) in &totals {
+        let size = match reason {
+            SkipReason::Pruned | SkipReason::OtherFileSystem => "-".to_string(),
+            _ => BinaryBytes(*size).to_string(),
+        };
+        say!("    {:<26} {:>6}  {:>10}", reason.describe(), count, size);
+    }
+    if totals.contains_key(&SkipReason::OutOfScope) {
+        say!("Rerun with --surface to examine the entries outside the torrent's scope.");
     }
-    paths
+    if totals.contains_key(&SkipReason::Dropping) {
+        say!("Rerun with --clean-droppings to remove the client droppings as well.");
+    }
+}
+
+/// Lists the extras kept because someone else owns them, they are client droppings, downloads in
+/// progress or junk, or `--deletable-ext` holds them back.
+pub fn print_kept(scan: &Scan, listing: &Listing) {
+    let not_owned = scan.skipped(SkipReason::NotOwned).map(|e| &e.path).collect::<Vec<_>>();
+    if !not_owned.is_empty() {
+        say!("Owned by others, skipped ({}):", not_owned.len());
+        for entry in limited(&not_owned, listing) {
+            say!("    {}", path_colored(entry, listing));
+        }
+        print_more(not_owned.len(), listing);
+    }
+    let droppings = scan.skipped(SkipReason::Dropping).collect::<Vec<_>>();
+    if !droppings.is_empty() {
+        say!("Client droppings, skipped ({}, {}):", droppings.len(),
+             BinaryBytes(droppings.iter().map(|e| e.size).sum()));
+        for entry in limited(&droppings, listing) {
+            say!("    {}  ({})", path_colored(&entry.path, listing),
+                 client_of(&entry.path).unwrap_or_default());
+        }
+        print_more(droppings.len(), listing);
+    }
+    let downloads = scan.skipped(SkipReason::InProgress).collect::<Vec<_>>();
+    if !downloads.is_empty() {
+        say!("Downloads in progress, skipped ({}, {}):", downloads.len(),
+             BinaryBytes(downloads.iter().map(|e| e.size).sum()));
+        for entry in limited(&downloads, listing) {
+            let (client, file) = download_of(&entry.path).expect("in progress");
+            say!("    {}  ({}, for {})", path_colored(&entry.path, listing), client,
+                 listing.paths.render(&file));
+        }
+        print_more(downloads.len(), listing);
+    }
+    let junk = scan.skipped(SkipReason::Junk).collect::<Vec<_>>();
+    if !junk.is_empty() {
+        say!("System junk, kept ({}, {}):", junk.len(), BinaryBytes(junk.iter().map(|e| e.size).sum()));
+        for entry in limited(&junk, listing) {
+            say!("    {}", path_colored(&entry.path, listing));
+        }
+        print_more(junk.len(), listing);
+    }
+    let review = scan.skipped(SkipReason::NotDeletable).collect::<Vec<_>>();
+    if !review.is_empty() {
+        say!("Held back by --deletable-ext, requiring manual review ({}, {}):", review.len(),
+             BinaryBytes(review.iter().map(|e| e.size).sum()));
+        for entry in limited(&review, listing) {
+            say!("    {}", path_colored(&entry.path, listing));
+        }
+        print_more(review.len(), listing);
+    }
+}
+
+/// Lists the directories `--empty-dir` kept, with what keeps each.
+/// Lists the files of the torrent that cannot exist on Windows, which are therefore missing.
+fn print_windows_conflicts(dir: &Path, expected: &impl ExpectedSet, listing: &Listing) {
+    let mut conflicts = expected.files().map(|(path, _)| path)
+        .filter(|e| windows_conflict(e).is_some())
+        .map(Path::to_path_buf)
+        .collect::<Vec<PathBuf>>();
+    if conflicts.is_empty() {
+        return;
+    }
+    sort_paths(&mut conflicts);
+    say!("Files that cannot exist on Windows ({}):", conflicts.len());
+    for path in limited(&conflicts, listing) {
+        let why = windows_conflict(path).unwrap_or_default();
+        say!("    {}  {}", path_colored(&dir.join(path), listing), why);
+    }
+    print_more(conflicts.len(), listing);
+}
+
+pub fn print_kept_dirs(kept: &[KeptDir], listing: &Listing) {
+    if kept.is_empty() {
+        return;
+    }
+    say!("Directories kept ({}):", kept.len());
+    for entry in limited(kept, listing) {
+        let why = match (&entry.error, &entry.blocker) {
+            (Some(error), _) => format!("{}: {}", entry.reason.describe(), error),
+            (None, Some(blocker)) => format!("{}: {}", entry.reason.describe(),
+                                             listing.paths.render(blocker)),
+            (None, None) => entry.reason.describe().to_string(),
+        };
+        say!("    {}  {}", path_colored(&entry.path, listing), why);
+    }
+    print_more(kept.len(), listing);
+}
+
+/// Notes the extras that are further paths to a file listed before them.
+pub fn print_aliases(scan: &Scan, listing: &Listing) {
+    if scan.aliases.is_empty() {
+        return;
+    }
+    say!("{} entries are further links to a file already listed; its size is counted once:",
+         scan.aliases.len());
+    for (alias, first) in limited(&scan.aliases, listing) {
+        say!("    {}  = {}", path_colored(alias, listing), listing.paths.render(first));
+    }
+    print_more(scan.aliases.len(), listing);
+}
+
+/// Lists the extras that are probably missing files of the torrent under another path.
+fn print_renames(renames: &Renames, listing: &Listing) {
+    if !renames.found.is_empty() {
+        say!("Probable renames ({}):", renames.found.len());
+        for rename in limited(&renames.found, listing) {
+            say!("    {}  -> {}  ({}{})", path_colored(&rename.from, listing),
+                 listing.paths.render(&rename.to), BinaryBytes(rename.size),
+                 if rename.verified { ", pieces match" } else { "" });
+        }
+        print_more(renames.found.len(), listing);
+        say!();
+    }
+    if !renames.ambiguous.is_empty() {
+        say!("Ambiguous renames, never moved back ({}):", renames.ambiguous.len());
+        for ambiguous in limited(&renames.ambiguous, listing) {
+            say!("    {}  could be any of:", listing.paths.render(&ambiguous.to));
+            for candidate in &ambiguous.candidates {
+                say!("        {}", path_colored(candidate, listing));
+            }
+        }
+        print_more(renames.ambiguous.len(), listing);
+        say!();
+    }
+}
+
+/// Moves the probable renames back to where the torrent has them, keeping those that fail to move
+/// out of the removal too.
+fn fix_renames(renames: &Renames, scan: &mut Scan, listing: &Listing) {
+    let (mut moved, mut kept) = (HashSet::new(), HashSet::new());
+    for rename in &renames.found {
+        match move_back(rename) {
+            Ok(()) => moved.insert(rename.from.clone()),
+            Err(err) => {
+                eprintln!("{}", Warning::new(WarningCode::Rename, format!(
+                    "failed to move {} back to {}: {}; leaving it in place",
+                    listing.paths.render(&rename.from), listing.paths.render(&rename.to), err))
+                    .at(&rename.from).record());
+                kept.insert(rename.from.clone())
+            }
+        };
+    }
+    if !moved.is_empty() {
+        say!("Moved {} files back to where the torrent has them.\n", moved.len());
+    }
+    scan.moved_away(&moved, SkipReason::Renamed);
+    scan.exclude(&kept, SkipReason::Renamed);
+}
+
+/// Hardlinks the probable renames to where the torrent has them, or moves or copies them there
+/// as `--relink-move` and `--relink-copy` say. Extras left in place are kept out of the removal.
+fn relink_files(matches: &ArgMatches, renames: &Renames, scan: &mut Scan, listing: &Listing)
+                -> KindTotal {
+    let (take, copy) = (matches.get_flag("relink-move"), matches.get_flag("relink-copy"));
+    let (mut moved, mut kept, mut linked) = (HashSet::new(), HashSet::new(), HashSet::new());
+    let mut relinked = KindTotal::default();
+    for rename in &renames.found {
+        match relink(rename, take, copy) {
+            Ok(()) => {
+                relinked.count += 1;
+                relinked.size += rename.size;
+                match take {
+                    true => moved.insert(rename.from.clone()),
+                    false => linked.insert(rename.from.clone()),
+                };
+            }
+            Err(err) => {
+                eprintln!("{}", Warning::new(WarningCode::Relink, format!(
+                    "failed to link {} to {}: {}; {}", listing.paths.render(&rename.from),
+                    listing.paths.render(&rename.to), err, match copy {
+                        true => "leaving it in place",
+                        false => "use --relink-copy to copy it instead",
+                    }))
+                    .at(&rename.from).record());
+                kept.insert(rename.from.clone());
+            }
+        }
+    }
+    if relinked.count > 0 {
+        say!("Relinked {} files into the torrent, sparing a download of {}.\n", relinked.count,
+             BinaryBytes(relinked.size));
+    }
+    scan.moved_away(&moved, SkipReason::Renamed);
+    scan.exclude(&kept, SkipReason::Renamed);
+    scan.exclude(&linked, SkipReason::Relinked);
+    relinked
+}
+
+/// The extras of `scan` that are probably missing files of the torrent under another path, their
+/// content checked with `--verify-renames`.
+fn renames(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet, scan: &Scan,
+           sources: &Sources) -> Renames {
+    let pieces = sources.pieces.as_ref().filter(|_| matches.get_flag("verify-renames"));
+    if matches.get_flag("verify-renames") && pieces.is_none() {
+        eprintln!("{}", warn(WarningCode::Rename, "--verify-renames: the piece hashes are not \
+                                                    available; matching by size only"));
+    }
+    find_renames(dir, expected, scan, pieces)
+}
+
+/// Prints the torrent's totals next to what was found on disk, so that a plan against the wrong
+/// directory stands out. The `corrupt` files of the torrent among those `scan` removes are not
+/// counted as extras.
+pub fn print_reconciliation(dir: &Path, expected: &impl ExpectedSet, scan: &Scan,
+                            corrupt: &HashSet<PathBuf>) {
+    let (matching, matching_size) = expected.matching(dir);
+    let corrupt_size = corrupt.iter().filter_map(|e| scan.stamps.get(e)).map(|e| e.size)
+        .sum::<u64>();
+    say!("Torrent expects:  {} in {} files", BinaryBytes(expected.total_size()),
+         expected.file_count());
+    say!("Found matching:   {} in {} files", BinaryBytes(matching_size), matching);
+    say!("Flagged as extra: {} in {} entries", BinaryBytes(scan.rm_size - corrupt_size),
+         scan.removal_count() - corrupt.len());
+    let present = expected.file_count() - expected.missing(dir).0.len();
+    let extras = scan.old_files.len() - corrupt.len();
+    say!("Delete ratio:     {:.3} ({} of {} files found)", delete_ratio(extras, present), extras,
+         extras + present);
+}
+
+/// Share of the files found that are extra, from 0 to 1, given the extra files and the files of
+/// the torrent that are present.
+pub fn delete_ratio(extras: usize, present: usize) -> f64 {
+    match extras + present {
+        0 => 0.0,
+        total => extras as f64 / total as f64,
+    }
+}
+
+/// Refuses a removal that looks like the torrent and the directory do not belong together: one
+/// of more than `--max-delete-ratio` of the files found or more than `--max-delete-size`, or one
+/// in a directory holding none of the torrent's files, unless `--force-threshold` is given.
+pub fn check_threshold(matches: &ArgMatches, scan: &Scan, present: usize) -> Result<(), CliError> {
+    if matches.get_flag("force-threshold") {
+        return Ok(());
+    }
+    let extras = scan.old_files.len();
+    let ratio = delete_ratio(extras, present);
+    let max_ratio = *matches.get_one::<f64>("max-delete-ratio").expect("default");
+    let max_size = matches.get_one::<u64>("max-delete-size").copied();
+    // Nothing planned is nothing to refuse, while empty directories count like files
+    let reason = if present == 0 && scan.removal_count() > 0 {
+        "none of the torrent's files are in the directory, which rarely holds the content of \
+         another torrent by chance".to_string()
+    } else if ratio > max_ratio {
+        format!("{} of the {} files found ({:.1}%) would be removed, more than --max-delete-ratio \
+                 {}", extras, extras + present, ratio * 100.0, max_ratio)
+    } else if let Some(max) = max_size.filter(|max| scan.rm_size > *max) {
+        format!("{} would be removed, more than --max-delete-size {}", BinaryBytes(scan.rm_size),
+                BinaryBytes(max))
+    } else {
+        return Ok(());
+    };
+    Err(CliError::new(ErrorCode::ThresholdExceeded, format!("Refusing to remove: {}; check that \
+        the torrent belongs to the directory, and pass --force-threshold if it does", reason)))
+}
+
+/// Removals of more entries than this show their progress while the listing is prepared.
+const LARGE_PLAN: usize = 10_000;
+
+/// Prints the `top` largest of `files` with their size and modification time. Reading every
+/// file shows a progress bar on a removal larger than [`LARGE_PLAN`], which would otherwise leave
+/// a long pause after the scan.
+pub fn print_largest(matches: &ArgMatches, files: &[PathBuf], top: usize, listing: &Listing)
+                     -> anyhow::Result<()> {
+    if top == 0 || files.is_empty() {
+        return Ok(());
+    }
+    let progress = match files.len() > LARGE_PLAN {
+        true => ProgressMode::from_matches(matches).bar(ProgressBar::new(files.len() as u64)),
+        false => ProgressBar::hidden(),
+    };
+    progress.set_style(ProgressStyle::default_bar()
+        .template("{prefix} [{wide_bar:.cyan/blue}] {pos}/{len} ({percent}%)")?);
+    progress.set_prefix("Preparing listing");
+    // Only the `top` are kept however many files there are, the first ones on equal sizes
+    let mut heap = BinaryHeap::with_capacity(top + 1);
+    for (i, file) in files.iter().enumerate() {
+        progress.inc(1);
+        if let Some(stamp) = Stamp::read(file) {
+            heap.push(Reverse((stamp.size, Reverse(i))));
+            if heap.len() > top {
+                heap.pop();
+            }
+        }
+    }
+    progress.finish_and_clear();
+    let mut largest = heap.into_iter().map(|Reverse((size, Reverse(i)))| (Reverse(size), i))
+        .collect::<Vec<_>>();
+    largest.sort();
+    let largest = largest.into_iter()
+        .filter_map(|(_, i)| Stamp::read(&files[i]).map(|m| (&files[i], m)))
+        .collect::<Vec<_>>();
+    print_top(&largest, listing);
+    Ok(())
+}
+
+/// Prints the `largest` extras, largest first, with their size and modification time.
+pub fn print_top(largest: &[(&PathBuf, Stamp)], listing: &Listing) {
+    say!();
+    say!("Top {} largest:", largest.len());
+    for (entry, stamp) in largest {
+        let mtime = stamp.modified.map_or("-".to_string(),
+            |e| humantime::format_rfc3339_seconds(e).to_string());
+        say!("{:>10}  {}  {}", BinaryBytes(stamp.size).to_string(), mtime,
+                 path_colored(entry, listing));
+    }
+}
+
+/// Prints the diff listing and summary; returns `false` without printing if there is nothing
+/// to report.
+pub fn print_changes(scan: &Scan, new_files: &[PathBuf], new_size: u64, mismatched: &[Mismatch],
+                     listing: &Listing) -> bool {
+    let (old_files, empty_dirs) = (&scan.old_files, &scan.empty_dirs);
+    if new_files.is_empty() && old_files.is_empty() && empty_dirs.is_empty() && mismatched.is_empty()
+        && !scan.skipped.iter().any(|e| matches!(e.reason, SkipReason::NotOwned | SkipReason::Dropping
+                                                 | SkipReason::InProgress | SkipReason::Junk)) {
+        return false;
+    }
+
+    say!("File changes:");
+
+    if let Some(glyphs) = listing.tree {
+        print_tree(scan, new_files, mismatched, listing, glyphs);
+    } else {
+        match listing.group {
+            Some(order) => print_groups(scan, order, listing),
+            None => {
+                for entry in limited(old_files, listing) {
+                    say!("{}  {}{}", Red.paint(removal_mark(entry)), path_colored(entry, listing),
+                         tags(entry, listing));
+                }
+                print_more(old_files.len(), listing);
+
+                print_empty_dirs(scan, listing);
+            }
+        }
+
+        for entry in limited(new_files, listing) {
+            match listing.origins.get(entry) {
+                Some(origin) => say!("{}   {}  ({})", Green.paint("+"),
+                                     path_colored(entry, listing), origin),
+                None => say!("{}   {}", Green.paint("+"), path_colored(entry, listing)),
+            }
+        }
+        print_more(new_files.len(), listing);
+
+        for entry in limited(mismatched, listing) {
+            say!("{}   {} (got {}, expected {})", Yellow.paint("~"),
+                 path_colored(&entry.path, listing), BinaryBytes(entry.size),
+                 BinaryBytes(entry.expected));
+        }
+        print_more(mismatched.len(), listing);
+    }
+    print_aliases(scan, listing);
+    print_kept(scan, listing);
+
+    say!();
+    say!("New files: {} ({})", Green.paint(BinaryBytes(new_size)), new_files.len());
+    if !mismatched.is_empty() {
+        let delta = mismatched.iter().map(Mismatch::delta).sum::<i64>();
+        say!("Wrong size: {} files, {}{} against the torrent", mismatched.len(),
+             if delta < 0 { "-" } else { "+" }, Yellow.paint(BinaryBytes(delta.unsigned_abs())));
+    }
+    say!("Remove entries: {} ({})", Red.paint(BinaryBytes(scan.rm_size)),
+             scan.removal_count());
+    print_space_freed(&scan.disk_usage());
+    print_kind_counts(old_files);
+    if let Some(ages) = AgeStats::of(old_files) {
+        say!("Age: {}", ages);
+    }
+    true
+}
+
+/// Puts the files of `expected` missing from `dir` and those of another size in the `--sort`
+/// order; a missing file has the size the torrent gives it, and no modification time.
+pub fn sort_changes(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet,
+                    missing: &mut [PathBuf], mismatched: &mut [Mismatch]) {
+    let order = sort_order(matches);
+    let sizes = match order.key {
+        SortKey::Size => expected.files().map(|(path, size)| (expected.locate(dir, path), size))
+            .collect::<HashMap<PathBuf, u64>>(),
+        _ => HashMap::new(),
+    };
+    order.sort(missing, |e| e, |e| sizes.get(e).map(|&size| Stamp { size, modified: None }));
+    order.sort(mismatched, |e| &e.path, |e| Some(Stamp {
+        size: e.size,
+        modified: fs::symlink_metadata(&e.path).ok().and_then(|e| e.modified().ok()),
+    }));
+}
+
+fn removal_mark(entry: &Path) -> &'static str {
+    match entry.is_dir() {
+        _ if entry.is_symlink() => "-l",
+        true => "-d",
+        false => "-f",
+    }
+}
+
+/// Lists the removals of `scan` as `--group-dirs` does: a directory whose entries are all removed
+/// once, marked `-g` with their count, size and share of the total, and the removals in
+/// directories that keep something one by one with their size.
+fn print_groups(scan: &Scan, order: GroupOrder, listing: &Listing) {
+    let groups = group_dirs(listing.paths.root(), scan, order);
+    let total = groups.iter().map(Group::size).sum::<u64>().max(1);
+    for group in limited(&groups, listing) {
+        let share = group.size() as f64 * 100.0 / total as f64;
+        match group {
+            Group::Dir { path, entries, size } => {
+                say!("{}  {}{}  ({} entr{}, {}, {:.1}%)", Red.paint("-g"),
+                     path_colored(path, listing), MAIN_SEPARATOR, entries,
+                     if *entries == 1 { "y" } else { "ies" }, BinaryBytes(*size), share);
+            }
+            Group::Entry { path, size } => {
+                let mark = match scan.emptied_dirs.contains(path) {
+                    true => "-D",
+                    false => removal_mark(path),
+                };
+                say!("{}  {}{}  ({}, {:.1}%)", Red.paint(mark), path_colored(path, listing),
+                     tags(path, listing), BinaryBytes(*size), share);
+            }
+        }
+    }
+    print_more(groups.len(), listing);
+}
+
+/// `diff --check` exit code when there are entries to remove.
+const CHECK_EXTRAS: u8 = 8;
+/// `diff --check` exit code when nothing is to be removed but entries outside the torrent's scope
+/// were left unexamined.
+const CHECK_OUT_OF_SCOPE: u8 = 9;
+/// `diff --check` exit code when nothing is to be removed but files of the torrent are missing or
+/// have another size.
+const CHECK_INCOMPLETE: u8 = 10;
+
+/// Says how a directory with nothing to remove compares to the torrent; returns whether all of
+/// its files are present with the expected size.
+fn print_nothing_to_remove(dir: &Path, expected: &impl ExpectedSet) -> bool {
+    let (matching, matching_size) = expected.matching(dir);
+    let total = expected.file_count();
+    if matching == total {
+        say!("{}", Green.paint(format!("Directory is clean: all {} files match ({})",
+                                       HumanCount(total as u64), BinaryBytes(matching_size))));
+        return true;
+    }
+    let missing = expected.missing(dir).0.len();
+    // Rounded down, so that a directory lacking a few bytes never reads as 100%
+    let present = match expected.total_size() {
+        0 => 100.0,
+        size => (matching_size as f64 * 1000.0 / size as f64).floor() / 10.0,
+    };
+    say!("{}", Yellow.paint(format!("Nothing to remove, but the directory does not match the \
+        torrent: {} of {} files missing, {} with another size, {:.1}% of its size present.",
+        HumanCount(missing as u64), HumanCount(total as u64),
+        HumanCount((total - matching - missing) as u64), present)));
+    false
+}
+
+// Compare directory
+fn run_diff(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let start = Instant::now();
+    let summary_json = matches.get_flag("summary-json");
+    let quiet = matches.get_flag("quiet");
+    let json = matches.get_one::<String>("output").is_some_and(|e| e == "json");
+    set_quiet(summary_json || quiet);
+    set_to_stderr(json);
+    if let Some(remote) = remote_dir(matches)? {
+        return remote::run(matches, "diff", remote);
+    }
+    let mut dirs = content_dirs(matches)?;
+    if dirs.len() > 1 {
+        return run_roots(matches, &dirs, "diff");
+    }
+    let dir = dirs.remove(0);
+    let (expected, mut record, mut sources) = load_expected(matches, "diff", &dir)?;
+    let dir = detect_root(matches, dir, &expected, &mut record, &mut sources)?;
+    for warning in check_content(matches, &dir, &expected, false)? {
+        say!("{}", warning);
+    }
+    check_scope(matches, &dir, &expected, &sources.names, false)?;
+    if let Err(err) = check_expected(matches, &expected) {
+        // Still show what is there, everything being outside a torrent without files
+        let options = ScanOptions { include_surface: true, ..scan_options(matches)? };
+        let scan = scan_dir(&dir, &expected, &options, &())?;
+        let (entries, listing) = ([&scan.old_files[..], &scan.empty_dirs[..]].concat(),
+                                  listing(matches, &dir));
+        say!("Directory contents: {} in {} entries", BinaryBytes(scan.rm_size), entries.len());
+        for entry in limited(&entries, &listing) {
+            say!("    {}", path_colored(entry, &listing));
+        }
+        print_more(entries.len(), &listing);
+        say!();
+        return Err(err.into());
+    }
+    handle_duplicates(matches, &dir, &expected, false)?;
+    let mut scan = scan(matches, &dir, &expected)?;
+    let mut actionable = !scan.old_files.is_empty() || !scan.empty_dirs.is_empty();
+    if quiet && actionable && !summary_json {
+        set_quiet(false);
+    }
+    let (mut new_files, mut new_size) = expected.missing(&dir);
+    let mut mismatched = expected.mismatched(&dir);
+    sort_changes(matches, &dir, &expected, &mut new_files, &mut mismatched);
+    record.extras = scan.removal_count();
+    record.extras_size = scan.rm_size;
+    record.missing = new_files.len();
+
+    print_reconciliation(&dir, &expected, &scan, &HashSet::new());
+    say!();
+    let renames = renames(matches, &dir, &expected, &scan, &sources);
+    let mut listing = torrent_listing(matches, &dir, expected.is_flat());
+    listing.origins = sources.origins;
+    listing.hardlinked = scan.disk_usage().hardlinked;
+    if matches.get_flag("tree") {
+        listing.tree = Some(Glyphs::detect());
+        let missing = new_files.iter().collect::<HashSet<&PathBuf>>();
+        listing.missing_sizes = expected.files()
+            .map(|(path, size)| (expected.locate(&dir, path), size))
+            .filter(|(path, _)| missing.contains(path))
+            .collect();
+    }
+    if matches.get_flag("group-dirs") {
+        listing.group = Some(match matches.get_one::<String>("group-sort").map(String::as_str) {
+            Some("path") => GroupOrder::Path,
+            _ => GroupOrder::Size,
+        });
+    }
+    let duplicates = tag_duplicates(matches, &dir, &expected, &scan, &mut listing)?;
+    let mut consistent = false;
+    print_renames(&renames, &listing);
+    if print_changes(&scan, &new_files, new_size, &mismatched, &listing) {
+        print_largest(matches, &scan.old_files, top(matches), &listing)?;
+        if matches.get_flag("summary") {
+            print_breakdown(&dir, &scan, top(matches), &listing);
+        }
+        print_duplicate_total(&duplicates, &scan);
+        if !actionable {
+            say!();
+            consistent = print_nothing_to_remove(&dir, &expected);
+        }
+    } else {
+        consistent = print_nothing_to_remove(&dir, &expected);
+    }
+    print_kept_dirs(&scan.kept_dirs, &listing);
+    if cfg!(windows) {
+        print_windows_conflicts(&dir, &expected, &listing);
+    }
+    if matches.get_flag("since-last") {
+        print_delta(&record)?;
+    }
+    write_planned_report(matches, &dir, &scan, &HashSet::new(),
+                         ReportRow::missing(&dir, &expected, &new_files), &record.info_hash)?;
+    if let Some(file) = matches.get_one::<PathBuf>("map-report") {
+        MapReport::new(record.info_hash.clone(), record.torrent.clone(), &dir, &expected).write(file)?;
+        say!("Map report written to {}.", file.display());
+    }
+    if matches.get_flag("watch") {
+        let interval = *matches.get_one::<Duration>("interval").expect("default");
+        let last = Watched::new(scan, new_files, new_size, mismatched.len());
+        let last = watch(matches, &dir, &expected, &listing, interval, last)?;
+        (scan, new_files, new_size) = (last.scan, last.missing, last.missing_size);
+        record.extras = scan.removal_count();
+        record.extras_size = scan.rm_size;
+        record.missing = new_files.len();
+        // The exit code of --check follows the last comparison
+        actionable = !scan.old_files.is_empty() || !scan.empty_dirs.is_empty();
+        consistent = !actionable && expected.matching(&dir).0 == expected.file_count();
+    }
+    save_record(matches, &record);
+    print_skipped(matches, &scan);
+    say!("Operation completed successfully.");
+    let code = match matches.get_flag("check") {
+        true if actionable => CHECK_EXTRAS,
+        true if !consistent => CHECK_INCOMPLETE,
+        true if scan.skipped(SkipReason::OutOfScope).next().is_some() => CHECK_OUT_OF_SCOPE,
+        _ => 0,
+    };
+    if json || summary_json {
+        let summary = Summary::new("diff", &dir, &expected, &scan, &new_files, new_size, start);
+        if json {
+            let summary = summary.finished(RunStatus::Ok, start);
+            // The status of --check, or that of --deny-warnings without one
+            let exit_code = if code == 0 { summary.exit_code } else { code.into() };
+            let summary = Summary { exit_code, ..summary };
+            let mut report = DiffReport::new(record.info_hash.clone(), record.torrent.clone(),
+                                             &dir, &expected, &scan, &new_files, summary)
+                .with_duplicates(&dir, &duplicates);
+            if matches.get_flag("limit-json") {
+                report = report.limited(torrent_listing(matches, &dir, expected.is_flat()).limit);
+            }
+            println!("{}", serde_json::to_string(&report)?);
+        } else {
+            summary.print(RunStatus::Ok, start);
+        }
+    }
+    Ok(ExitCode::from(code))
+}
+
+fn print_delta(record: &RunRecord) -> anyhow::Result<()> {
+    let history = load_history(&history_file()?)?;
+    let Some(last) = last_run(&history, &record.info_hash, &record.dir) else {
+        say!("No previous run recorded for this torrent and directory.");
+        return Ok(());
+    };
+    let count = record.extras as i64 - last.extras as i64;
+    let size = record.extras_size as i64 - last.extras_size as i64;
+    if count == 0 && size == 0 {
+        say!("Extras unchanged since {}.", last.date());
+    } else if count >= 0 && size >= 0 {
+        say!("Extras grew by {} files / {} since {}.", count, BinaryBytes(size as u64),
+                 last.date());
+    } else if count <= 0 && size <= 0 {
+        say!("Extras shrank by {} files / {} since {}.", -count, BinaryBytes(-size as u64),
+                 last.date());
+    } else {
+        say!("Extras changed by {:+} files / {}{} since {}.", count,
+                 if size < 0 { "-" } else { "+" }, BinaryBytes(size.unsigned_abs()), last.date());
+    }
+    Ok(())
+}
+
+
+fn run_clean(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let start = Instant::now();
+    let summary_json = matches.get_flag("summary-json");
+    let quiet = matches.get_flag("quiet");
+    set_quiet(summary_json || quiet);
+    if let Some(remote) = remote_dir(matches)? {
+        return remote::run(matches, "clean", remote);
+    }
+    let mut dirs = content_dirs(matches)?;
+    if dirs.len() > 1 {
+        return run_roots(matches, &dirs, "clean");
+    }
+    let dir = dirs.remove(0);
+    let options = scan_options(matches)?;
+    let mut include_empty_dir = options.include_empty_dir;
+    let no_confirm = matches.get_flag("no-confirm");
+    let confirm_each = matches.get_flag("confirm-each");
+    let link_to = matches.get_one::<PathBuf>("link-to").map(absolute_path).transpose()?;
+    let stage = matches.get_flag("stage");
+    let move_to = matches.get_one::<PathBuf>("move-to").map(absolute_path).transpose()?;
+    let (expected, mut record, mut sources) = load_expected(matches, "clean", &dir)?;
+    let dir = detect_root(matches, dir, &expected, &mut record, &mut sources)?;
+    if let Some(to) = &move_to {
+        if to.starts_with(&dir) || to.join(dir.file_name().unwrap_or_default()) == dir {
+            return Err(anyhow!("Destination directory must not be inside the scanned directory \
+                or its parent: {}", to.display()));
+        }
+    }
+    let mut manifest = matches.get_one::<PathBuf>("backup-manifest")
+        .map(|e| BackupManifest::open(e, &dir,
+            matches.get_one::<u64>("manifest-hash-max-size").copied()))
+        .transpose()?;
+    for warning in check_content(matches, &dir, &expected, true)? {
+        say!("{}", warning);
+    }
+    check_scope(matches, &dir, &expected, &sources.names, true)?;
+    check_torrent(matches, &expected, true)?;
+    let held = held_back(matches, &record)?;
+    handle_duplicates(matches, &dir, &expected, held.is_none())?;
+    let resume = matches.get_one::<PathBuf>("resume")
+        .map(|e| read_journal(&absolute_path(e)?, &record.info_hash, &dir)).transpose()?;
+    let mut scan = match &resume {
+        Some(recorded) => {
+            if recorded.finished {
+                say!("The run recorded in {} completed; there is nothing to resume.",
+                     recorded.path.display());
+                return Ok(ExitCode::SUCCESS);
+            }
+            let scan = recorded.scan(&expected);
+            say!("Resuming {}: {} entries were removed before, {} of the {} left are still there \
+                  and not part of the torrent.\n", recorded.path.display(), recorded.removed,
+                 scan.removal_count(), recorded.pending());
+            // The directories were planned by a run with --empty-dir
+            include_empty_dir |= !scan.empty_dirs.is_empty() || !scan.emptied_dirs.is_empty();
+            scan
+        }
+        None => scan(matches, &dir, &expected)?,
+    };
+    let duplicates = match matches.get_flag("only-duplicates-of-kept") {
+        true => Some(restrict_to_duplicates(matches, &dir, &expected, &mut scan)?),
+        false => None,
+    };
+    let hooks = Hooks::new(&record.torrent, &record.info_hash, &dir,
+                           *matches.get_one::<Duration>("hook-timeout").expect("default"));
+    if let Some(cmd) = matches.get_one::<String>("filter-hook") {
+        filter_by_hook(matches, &hooks, cmd, &mut scan)?;
+    }
+    let renames = renames(matches, &dir, &expected, &scan, &sources);
+    let rename_listing = torrent_listing(matches, &dir, expected.is_flat());
+    print_renames(&renames, &rename_listing);
+    let mut relinked = KindTotal::default();
+    // Files moved back or linked into the torrent are worth a recheck even if nothing is removed
+    let relocated = held.is_none() && !renames.found.is_empty()
+        && (matches.get_flag("fix-renames") || matches.get_flag("relink"));
+    if held.is_some() {
+        // Left where they are with the rest of a torrent that is not cleaned
+    } else if matches.get_flag("fix-renames") {
+        fix_renames(&renames, &mut scan, &rename_listing);
+    } else if matches.get_flag("relink") {
+        relinked = relink_files(matches, &renames, &mut scan, &rename_listing);
+    } else if !renames.found.is_empty() {
+        say!("Use --fix-renames to move them back, or --relink to link them there, instead of \
+              removing them.\n");
+    }
+    let verification = match matches.get_flag("verify") {
+        true => {
+            let verification = hash_files(matches, &dir, sources.pieces.as_ref())?;
+            print_verification(&verification, &torrent_listing(matches, &dir, expected.is_flat()));
+            say!();
+            Some(verification)
+        }
+        false => None,
+    };
+    // Taken before removing anything so the per-type sizes can still be read
+    let mut summary = summary_json.then(|| {
+        let (missing, missing_size) = expected.missing(&dir);
+        Summary { relinked: relinked.clone(), held_back: held.clone(),
+                  ..Summary::new("clean", &dir, &expected, &scan, &missing, missing_size, start) }
+    });
+    // The corrupt files go with the extras so that the client downloads them again, under the
+    // same confirmation and revalidated the same way
+    let mut corrupt = HashSet::new();
+    if let Some(verification) = &verification {
+        let files = verification.with(|e| *e == FileCheck::Corrupt);
+        if let Some(summary) = &mut summary {
+            summary.corrupt = KindTotal { count: files.len(),
+                                          size: files.iter().map(|e| e.size).sum() };
+        }
+        if matches.get_flag("delete-corrupt") {
+            let deletable = matches.get_many::<String>("deletable-ext").map(Deletable::new);
+            for file in files {
+                if deletable.as_ref().is_some_and(|e| !e.allows(&file.path)) {
+                    scan.skipped.push(Skipped { path: file.path.clone(), size: file.size,
+                                                reason: SkipReason::NotDeletable });
+                    continue;
+                }
+                let Ok(meta) = fs::symlink_metadata(&file.path) else {
+                    continue;
+                };
+                scan.rm_size += meta.len();
+                scan.stamps.insert(file.path.clone(), Stamp::of(&meta));
+                scan.old_files.push(file.path.clone());
+                corrupt.insert(file.path.clone());
+            }
+        }
+    }
+    // After the corrupt files joined the extras, as they are removed all the same
+    if link_to.is_none() && held.is_none() {
+        check_threshold(matches, &scan, expected.file_count() - expected.missing(&dir).0.len())?;
+    }
+    if matches.get_flag("interactive") && !scan.old_files.is_empty() && held.is_none() {
+        if !interactive() {
+            say!("Aborted: there is no terminal to select the files on.");
+            return Ok(ExitCode::from(EXIT_DECLINED));
+        }
+        if !select_removals(&mut scan, &torrent_listing(matches, &dir, expected.is_flat())) {
+            say!("Aborted.");
+            return Ok(ExitCode::from(EXIT_DECLINED));
+        }
+        corrupt.retain(|e| scan.old_files.contains(e));
+    }
+    let rm_size = scan.rm_size;
+    record.extras = scan.removal_count();
+    record.extras_size = rm_size;
+    let report = |status: RunStatus, removed: usize| if let Some(summary) = &summary {
+        Summary { removed, removed_size: if removed > 0 { rm_size } else { 0 }, ..summary.clone() }
+            .print(status, start);
+    };
+    // From here on Ctrl-C lets the removal in progress finish and reports what was removed
+    let pre_delete = || {
+        if let Some(cmd) = matches.get_one::<String>("pre-delete-hook") {
+            hooks.pre_delete(cmd, scan.old_files.iter().chain(&scan.empty_dirs)
+                .chain(&scan.emptied_dirs)).inspect_err(|_| report(RunStatus::Aborted, 0))?;
+        }
+        start_removing();
+        anyhow::Ok(())
+    };
+    // Reported as any other, but stopped short of the question
+    let skip_held = |record: &RunRecord| held.as_ref().map(|reason| {
+        say!("Skipped: {} is not cleaned, as {}.", record.torrent, reason);
+        save_record(matches, record);
+        report(RunStatus::Ok, 0);
+        ExitCode::SUCCESS
+    });
+
+    if let Some(link_dir) = link_to { // Link files for review
+        if link_dir.starts_with(&dir) {
+            return Err(anyhow!("Review directory must not be inside the scanned directory: {}",
+                link_dir.display()));
+        }
+
+        let mut entries = scan.old_files.clone();
+        entries.extend(scan.empty_dirs.iter().cloned());
+        if entries.is_empty() {
+            say!("No matching entries found.");
+        } else {
+            say!("Existed entries found:");
+            print_entries(&entries, &torrent_listing(matches, &dir, expected.is_flat()));
+            say!();
+        }
+
+        let summary = link_extras(&dir, &link_dir, &entries)?;
+        say!("Linked {} entries into {} ({} created, {} refreshed, {} stale links pruned).",
+                 summary.total(), link_dir.display(), summary.created, summary.refreshed,
+                 summary.pruned);
+        print_skipped(matches, &scan);
+        say!("Operation completed successfully.");
+        report(RunStatus::Ok, 0);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Delete files
+    let files = &scan.old_files;
+    let revalidate = (!matches.get_flag("no-revalidate")).then_some(&scan);
+    let mut staged = None;
+    let mut changed = Vec::new();
+    let mut failed = Vec::new();
+    let mut accepted = None;
+    let mut journal = None;
+
+    let dirs = scan.empty_dirs.len() + scan.emptied_dirs.len();
+    let progress = if files.is_empty() {
+        let listing = torrent_listing(matches, &dir, expected.is_flat());
+        match dirs {
+            0 => {
+                print_nothing_to_remove(&dir, &expected);
+            }
+            _ => {
+                say!("Empty directories found:");
+                print_empty_dirs(&scan, &listing);
+            }
+        }
+        print_kept(&scan, &listing);
+        if let Some(code) = skip_held(&record) {
+            return Ok(code);
+        }
+        if !include_empty_dir {
+            save_record(matches, &record);
+            print_skipped(matches, &scan);
+            if relocated {
+                request_recheck(matches, &record);
+            }
+            say!("Aborted.");
+            report(RunStatus::Ok, 0);
+            return Ok(ExitCode::SUCCESS)
+        }
+        if dirs > 0 {
+            say!();
+            say!("Remove directories: {}", Red.paint(dirs));
+            say!();
+            write_planned_report(matches, &dir, &scan, &corrupt, Vec::new(), &record.info_hash)?;
+            let question = format!("Delete {}?",
+                                   asked_entries(0, dirs, unlisted_dirs(&scan, &listing)));
+            if !no_confirm && !confirm(matches, &question, true) {
+                report(RunStatus::Aborted, 0);
+                return Ok(ExitCode::from(EXIT_DECLINED));
+            }
+        }
+        pre_delete()?;
+        journal = open_journal(matches, &record, &dir, &scan, resume.as_ref())?;
+
+        let progress = ProgressMode::from_matches(matches).bar(ProgressBar::no_length());
+        progress.set_style(ProgressStyle::default_spinner()
+            .tick_chars("|/-\\|/-\\ ")
+            .template("{prefix} [{elapsed_precise}] {spinner:.green}\n{msg}")?);
+        progress.enable_steady_tick(Duration::from_millis(50));
+        progress
+    } else {
+        say!("Existed files found:");
+        let usage = scan.disk_usage();
+        let mut listing = torrent_listing(matches, &dir, expected.is_flat());
+        listing.hardlinked = usage.hardlinked.clone();
+        listing.corrupt = corrupt.clone();
+        // --only-duplicates-of-kept lists each next to its file already
+        let tagged = match &duplicates {
+            Some(_) => Vec::new(),
+            None => tag_duplicates(matches, &dir, &expected, &scan, &mut listing)?,
+        };
+        match &duplicates {
+            Some(duplicates) => print_duplicates(duplicates, &listing),
+            None => print_entries(files, &listing),
+        }
+        print_empty_dirs(&scan, &listing);
+
+        print_aliases(&scan, &listing);
+        print_kept(&scan, &listing);
+        say!();
+        say!("Remove files: {} ({})", Red.paint(BinaryBytes(rm_size)), files.len());
+        if !corrupt.is_empty() {
+            let size = corrupt.iter().filter_map(|e| scan.stamps.get(e)).map(|e| e.size).sum();
+            say!("  of which corrupt, to download again: {} ({})", Red.paint(BinaryBytes(size)),
+                 corrupt.len());
+        }
+        if !tagged.is_empty() {
+            let size = tagged.iter().map(|e| e.size).sum();
+            say!("  of which duplicates of files of the torrent: {} ({})", BinaryBytes(size),
+                 tagged.len());
+        }
+        if dirs > 0 {
+            say!("Remove directories: {}", Red.paint(dirs));
+        }
+        print_space_freed(&usage);
+        print_kind_counts(files);
+        say!();
+        print_reconciliation(&dir, &expected, &scan, &corrupt);
+        print_largest(matches, files, top(matches), &listing)?;
+        if matches.get_flag("summary") {
+            print_breakdown(&dir, &scan, top(matches), &listing);
+        }
+        write_planned_report(matches, &dir, &scan, &corrupt, Vec::new(), &record.info_hash)?;
+        if let Some(code) = skip_held(&record) {
+            return Ok(code);
+        }
+
+        if confirm_each {
+            if !interactive() {
+                say!("Aborted: there is no terminal to ask about each file on.");
+                return Ok(ExitCode::from(EXIT_DECLINED));
+            }
+            pre_delete()?;
+            let paths = log_paths(matches, &dir);
+            let mut decisions = Decisions::default();
+            let result = confirm_each_file(files, revalidate, &listing, &paths, manifest.as_mut(),
+                                           remove_options(matches), &mut decisions);
+            let unasked = files.len() - decisions.accepted.len() - decisions.declined
+                - decisions.changed.len() - decisions.failed.len();
+            say!("{} accepted, {} declined{}.", decisions.accepted.len(), decisions.declined,
+                 match unasked {
+                     0 => String::new(),
+                     n => format!(", {} not asked", n),
+                 });
+            record.removed = decisions.accepted.len();
+            record.removed_size = decisions.accepted_size;
+            if let Err(err) = result {
+                record.failures = 1;
+                save_record(matches, &record);
+                return Err(err);
+            }
+            accepted = Some(decisions.accepted);
+            changed = decisions.changed;
+            failed = decisions.failed;
+        } else if !no_confirm && !matches.get_flag("interactive") {
+            let (unlisted, unlisted_dirs) = (listing.unlisted(files.len()),
+                                             unlisted_dirs(&scan, &listing));
+            // Moving takes the files only; the directories are deleted either way
+            let question = match &move_to {
+                Some(to) if dirs > 0 => format!("Move {} to {}, and delete {}?",
+                    asked_entries(files.len(), 0, unlisted), to.display(),
+                    asked_entries(0, dirs, unlisted_dirs)),
+                Some(to) => format!("Move {} to {}?", asked_entries(files.len(), 0, unlisted),
+                                    to.display()),
+                None => format!("Delete {}?",
+                                asked_entries(files.len(), dirs, unlisted + unlisted_dirs)),
+            };
+            if !confirm(matches, &question, true) {
+                report(RunStatus::Aborted, 0);
+                return Ok(ExitCode::from(EXIT_DECLINED));
+            }
+        }
+        if !confirm_each {
+            pre_delete()?;
+        }
+
+        let length = accepted.as_ref().map_or(files.len(), |e: &Vec<PathBuf>| e.len());
+        let progress = ProgressMode::from_matches(matches).bar(ProgressBar::new(length as u64));
+        progress.set_style(ProgressStyle::default_bar()
+            .template("{prefix} [{wide_bar:.cyan/blue}] {pos}/{len} ({percent}%)\n{msg}")?);
+        progress.set_prefix("Processing");
+
+        let paths = log_paths(matches, &dir);
+        let result = if let Some(accepted) = &accepted {
+            progress.set_position(accepted.len() as u64);
+            Ok(())
+        } else if stage {
+            stage_files(&dir, files, revalidate, &paths, move_options(matches), &progress)
+                .map(|run| staged = Some(run))
+        } else if let Some(to) = &move_to {
+            move_files_to(&dir, files, to, revalidate, &paths, move_options(matches), &progress)
+                .map(|moved| staged = Some(moved))
+        } else {
+            journal = open_journal(matches, &record, &dir, &scan, resume.as_ref())?;
+            remove_files(files, revalidate, &paths, manifest.as_mut(), journal.as_mut(),
+                         remove_options(matches), &progress)
+                .map(|e| (changed, failed) = (e.changed, e.failed))
+        };
+        if let Err(err) = result {
+            progress.finish_and_clear();
+            write_outcome_report(matches, &dir, &scan, &corrupt, &failed_entries(&err),
+                                 staged.is_some(), &record.info_hash);
+            record.failures = 1;
+            save_record(matches, &record);
+            report(RunStatus::Failed, 0);
+            return Err(err);
+        }
+        progress
+    };
+
+    let (mut count, mut rm_size) = match &accepted {
+        Some(accepted) => (accepted.len(), record.removed_size),
+        None => (files.len(), rm_size),
+    };
+    if let Some(staged) = &mut staged {
+        changed = std::mem::take(&mut staged.changed);
+    }
+    let no_space = staged.as_ref().map_or(&[][..], |e| &e.no_space[..]);
+    let no_space_size = no_space.iter()
+        .map(|e| fs::symlink_metadata(e).map_or(0, |m| m.len()))
+        .sum::<u64>();
+    // The sizes the scan counted, which is what the removal was planned with
+    let scanned_size = |files: &mut dyn Iterator<Item = &PathBuf>| files
+        .filter(|e| !scan.aliases.iter().any(|(alias, _)| alias == *e))
+        .filter_map(|e| scan.stamps.get(e))
+        .map(|e| e.size)
+        .sum::<u64>();
+    let changed_size = scanned_size(&mut changed.iter());
+    let failed_size = scanned_size(&mut failed.iter().map(|(path, _)| path));
+    if accepted.is_none() {
+        count -= no_space.len() + changed.len() + failed.len();
+        rm_size = rm_size.saturating_sub(no_space_size + changed_size + failed_size);
+    }
+    let moved = files.len() - no_space.len() - changed.len();
+    // After Ctrl-C, whatever no longer exists was removed, staged or moved
+    let gone = interrupted().then(|| {
+        let gone = files.iter().filter(|e| fs::symlink_metadata(e).is_err()).collect::<Vec<_>>();
+        (gone.len(), scanned_size(&mut gone.into_iter()))
+    });
+    scan.exclude(&changed.iter().cloned().collect(), SkipReason::Changed);
+    if include_empty_dir && gone.is_none() {
+        progress.set_prefix("Clearing dirs");
+        let paths = log_paths(matches, &dir);
+        let removal = remove_empty_dirs(&scan, &paths, journal.as_mut(), remove_options(matches),
+                                        &progress);
+        count += removal.removed;
+        let error = removal.error(&paths);
+        scan.kept_dirs.extend(removal.kept);
+        if let Some(err) = error {
+            progress.finish_and_clear();
+            let failed = scan.kept_dirs.iter().filter(|e| e.reason == DirKeptReason::RemoveFailed)
+                .map(|e| (e.path.clone(), err.message.clone())).chain(failed.iter().cloned())
+                .collect::<Vec<_>>();
+            write_outcome_report(matches, &dir, &scan, &corrupt, &failed, staged.is_some(),
+                                 &record.info_hash);
+            print_kept_dirs(&scan.kept_dirs, &torrent_listing(matches, &dir, expected.is_flat()));
+            record.removed = count;
+            record.removed_size = rm_size;
+            record.failures = 1;
+            save_record(matches, &record);
+            if let Some(summary) = &summary {
+                Summary { removed: count, removed_size: rm_size,
+                          dirs_kept: KeptDirEntry::list(&dir, &scan.kept_dirs), ..summary.clone() }
+                    .print(RunStatus::Failed, start);
+            }
+            return Err(err.into());
+        }
+    }
+    if interrupted() {
+        progress.finish_and_clear();
+        let (removed, removed_size) = gone.unwrap_or((count, rm_size));
+        record.removed = removed;
+        record.removed_size = removed_size;
+        record.failures = failed.len();
+        save_record(matches, &record);
+        if let Some(summary) = &summary {
+            let summary = Summary { removed, removed_size, exit_code: EXIT_INTERRUPTED.into(),
+                                    ..summary.finished(RunStatus::Aborted, start) };
+            println!("{}", serde_json::to_string(&summary)?);
+        }
+        let rest = match &journal {
+            Some(journal) => format!("continue with --resume {}", journal.path().display()),
+            None => "run clean again for the rest".to_string(),
+        };
+        return Err(CliError::new(ErrorCode::Interrupted, format!(
+            "Interrupted: {} entries removed ({}), {} left; {}", removed,
+            BinaryBytes(removed_size), record.extras.saturating_sub(removed), rest)).into());
+    }
+    if let Some(journal) = journal.as_mut().filter(|_| failed.is_empty()) {
+        journal.finished();
+    }
+    record.removed = count;
+    record.removed_size = rm_size;
+    record.failures = no_space.len() + failed.len();
+    save_record(matches, &record);
+
+    progress.set_prefix("Done");
+    finish(&progress, match matches.get_flag("trash") {
+        true => format!("{} entries moved to the trash, from where they can be restored.", count),
+        false => format!("{} entries removed.", count),
+    });
+    if let Some(staged) = &staged {
+        match move_to {
+            Some(_) => say!("{} files ({}) were moved to {}.", moved, BinaryBytes(staged.size),
+                            staged.run.display()),
+            None => say!("{} files were staged in {}; use `purge` to delete them permanently.",
+                         moved, log_paths(matches, &dir).render(&staged.run)),
+        }
+    }
+
+    let no_space = no_space.to_vec();
+    write_outcome_report(matches, &dir, &scan, &corrupt, &failed, staged.is_some(),
+                         &record.info_hash);
+    print_kept_dirs(&scan.kept_dirs, &torrent_listing(matches, &dir, expected.is_flat()));
+    print_skipped(matches, &scan);
+    if let Some(summary) = &summary {
+        let mut summary = Summary {
+            removed: count,
+            removed_size: rm_size,
+            dirs_kept: KeptDirEntry::list(&dir, &scan.kept_dirs),
+            no_space: KindTotal { count: no_space.len(), size: no_space_size },
+            ..summary.clone()
+        };
+        if !changed.is_empty() {
+            summary.skipped.insert(SkipReason::Changed,
+                                   KindTotal { count: changed.len(), size: changed_size });
+        }
+        let status = match () {
+            _ if !failed.is_empty() => RunStatus::Failed,
+            _ if !no_space.is_empty() => RunStatus::Incomplete,
+            _ => RunStatus::Ok,
+        };
+        summary.print(status, start);
+    }
+    let removal = FileRemoval { changed: Vec::new(), failed };
+    if let Some(err) = removal.error(&log_paths(matches, &dir)) {
+        say!("Failed to remove ({}):", removal.failed.len());
+        let listing = torrent_listing(matches, &dir, expected.is_flat());
+        for (path, error) in limited(&removal.failed, &listing) {
+            say!("    {}  {}", path_colored(path, &listing), error);
+        }
+        print_more(removal.failed.len(), &listing);
+        return Err(err.into());
+    }
+    if !no_space.is_empty() {
+        let mut err = CliError::new(ErrorCode::InsufficientSpace,
+            format!("{} files ({}) were left in place: insufficient space at destination",
+                    no_space.len(), BinaryBytes(no_space_size)));
+        for file in &no_space {
+            err = err.with_entry(file, "insufficient space at destination");
+        }
+        return Err(err.into());
+    }
+    if quiet && !summary_json {
+        println!("{} entries removed ({}).", count, BinaryBytes(rm_size));
+    }
+    request_recheck(matches, &record);
+    say!("Operation completed successfully.");
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Why the labels or category of the torrent of `record` in the `--client` keep it from being
+/// cleaned under `--skip-label`, `--skip-category` and `--only-label`, if they do. A torrent the
+/// client does not have carries no labels, which is worth a warning.
+pub fn held_back(matches: &ArgMatches, record: &RunRecord) -> Result<Option<String>, CliError> {
+    let Some(filter) = LabelFilter::from_matches(matches) else {
+        return Ok(None);
+    };
+    let client = Client::from_matches(matches).expect("the label options require --client");
+    let torrent = client.torrent(&record.info_hash).map_err(|e| CliError::new(
+        ErrorCode::ClientFailed, format!("Could not read the labels of {} from {}: {:#}",
+                                         record.torrent, client.name(), e)))?;
+    if torrent.is_none() {
+        eprintln!("{}", warn(WarningCode::Client, format!(
+            "{} has no torrent with info hash {}, so {} counts as carrying no labels",
+            client.name(), record.info_hash, record.torrent)));
+    }
+    Ok(filter.held_back(torrent.as_ref()))
+}
+
+/// With `--recheck-after`, asks the `--client` to recheck the data of the torrent of `record`
+/// and prints its answer. The clean is done by then, so a client that cannot be reached or
+/// refuses only makes a warning.
+pub fn request_recheck(matches: &ArgMatches, record: &RunRecord) {
+    if !matches.get_flag("recheck-after") {
+        return;
+    }
+    let client = Client::from_matches(matches).expect("--recheck-after requires --client");
+    match client.recheck(&record.info_hash) {
+        Ok(answer) => say!("Recheck of {} requested from {}: {}", record.torrent, client.name(),
+                           answer),
+        Err(err) => eprintln!("{}", warn(WarningCode::Client, format!(
+            "Could not request a recheck of {}: {:#}", record.torrent, err))),
+    }
+}
+
+/// The journal the removals of `scan` are recorded in: the resumed one, the one given with
+/// `--journal`, or a new one in the cache directory, which is left out with a warning if it
+/// cannot be created.
+fn open_journal(matches: &ArgMatches, record: &RunRecord, dir: &Path, scan: &Scan,
+                resume: Option<&Recorded>) -> anyhow::Result<Option<Journal>> {
+    if let Some(recorded) = resume {
+        return Journal::append(recorded).map(Some);
+    }
+    if let Some(path) = matches.get_one::<PathBuf>("journal") {
+        return Journal::create(&absolute_path(path)?, &record.info_hash, dir, scan).map(Some);
+    }
+    match default_path(&record.info_hash)
+        .and_then(|path| Journal::create(&path, &record.info_hash, dir, scan)) {
+        Ok(journal) => Ok(Some(journal)),
+        Err(err) => {
+            eprintln!("{}", warn(WarningCode::Journal,
+                                 format!("no journal is kept for this run: {:#}", err)));
+            Ok(None)
+        }
+    }
+}
+
+/// Takes the files `--filter-hook` vetoes out of `scan`.
+fn filter_by_hook(matches: &ArgMatches, hooks: &Hooks, cmd: &str, scan: &mut Scan)
+                  -> anyhow::Result<()> {
+    let progress = ProgressMode::from_matches(matches)
+        .bar(ProgressBar::new(scan.old_files.len() as u64));
+    progress.set_style(ProgressStyle::default_bar()
+        .template("{prefix} [{wide_bar:.cyan/blue}] {pos}/{len}\n{msg}")?);
+    progress.set_prefix("Filtering");
+    let vetoed = hooks.filter(cmd, &scan.old_files, &progress);
+    progress.finish_and_clear();
+    scan.exclude(&vetoed?, SkipReason::Hook);
+    Ok(())
+}
+
+/// Files accepted and declined so far by `--confirm-each`.
+#[derive(Default)]
+struct Decisions {
+    accepted: Vec<PathBuf>,
+    accepted_size: u64,
+    declined: usize,
+    /// Accepted, but left alone because they changed since the scan.
+    changed: Vec<PathBuf>,
+    /// Accepted, but could not be removed.
+    failed: Vec<(PathBuf, String)>,
+}
+
+/// Asks about each of `files` in turn, removing the accepted ones right away so that quitting
+/// leaves everything answered so far done.
+fn confirm_each_file(files: &[PathBuf], revalidate: Option<&Scan>, listing: &Listing,
+                     paths: &PathRenderer, mut manifest: Option<&mut BackupManifest>,
+                     options: RemoveOptions, decisions: &mut Decisions) -> anyhow::Result<()> {
+    let mut all = false;
+    for entry in files {
+        if interrupted() {
+            break;
+        }
+        let meta = fs::symlink_metadata(entry).ok();
+        let size = meta.as_ref().map_or(0, |m| m.len());
+        if !all {
+            let mtime = meta.and_then(|m| m.modified().ok()).map_or("-".to_string(),
+                |e| humantime::format_rfc3339_seconds(e).to_string());
+            say!();
+            say!("{}  {}  {}  {}", BinaryBytes(size).to_string(), mtime, FileKind::of(entry).name(),
+                 path_colored(entry, listing));
+            let choices = vec!["Yes", "No", "All remaining", "Quit"];
+            match Select::new("Delete this file?", choices).raw_prompt().map(|e| e.index) {
+                Ok(0) => {}
+                Ok(1) => {
+                    decisions.declined += 1;
+                    continue;
+                }
+                Ok(2) => all = true,
+                _ => return Ok(()),
+            }
+        }
+        let removal = remove_files(std::slice::from_ref(entry), revalidate, paths,
+                                   manifest.as_deref_mut(), None, options, &ProgressBar::hidden())?;
+        if !removal.changed.is_empty() || !removal.failed.is_empty() {
+            decisions.changed.extend(removal.changed);
+            decisions.failed.extend(removal.failed);
+            continue;
+        }
+        decisions.accepted.push(entry.clone());
+        decisions.accepted_size += size;
+    }
+    Ok(())
+}
+
+/// Has `--interactive` pick the extra files of `scan` to remove, each shown with its size and
+/// long paths cut at the start to keep the name in view, and leaves the others out as
+/// deselected. Returns false if the selection was cancelled or left nothing to remove.
+fn select_removals(scan: &mut Scan, listing: &Listing) -> bool {
+    let width = term_size::dimensions().map_or(usize::MAX, |(width, _)| width);
+    let options = scan.old_files.iter()
+        .map(|entry| {
+            let size = BinaryBytes(scan.stamps.get(entry).map_or(0, |e| e.size)).to_string();
+            let path = listing.paths.render(entry);
+            // Room left by the checkbox, the size and the spacing around it
+            let room = width.saturating_sub(size.len() + 12);
+            match path.unicode_truncate_start(room).0.len() < path.len() {
+                true => format!("...{}  ({})",
+                                path.unicode_truncate_start(room.saturating_sub(3)).0, size),
+                false => format!("{}  ({})", path, size),
+            }
+        })
+        .collect();
+    let Some(selected) = select("Remove the selected files?", options) else {
+        return false;
+    };
+    if selected.is_empty() {
+        return false;
+    }
+    let selected = selected.into_iter().collect::<HashSet<usize>>();
+    let deselected = scan.old_files.iter().enumerate()
+        .filter(|(i, _)| !selected.contains(i))
+        .map(|(_, e)| e.clone())
+        .collect::<HashSet<PathBuf>>();
+    scan.exclude(&deselected, SkipReason::Deselected);
+    true
+}
+
+/// The `extras` identical to a present file of the torrent, hashed behind a progress bar.
+fn hash_duplicates(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet,
+                   extras: &[PathBuf]) -> anyhow::Result<Vec<Duplicate>> {
+    let spinner = ProgressMode::from_matches(matches).bar(ProgressBar::new(extras.len() as u64));
+    spinner.set_style(ProgressStyle::default_bar()
+        .template("Hashing [{wide_bar:.cyan/blue}] {pos}/{len}\n{msg}")?);
+    let result = find_duplicates_of_kept(dir, expected, extras, &spinner);
+    spinner.finish_and_clear();
+    result
+}
+
+/// Tags the extras of `listing` identical to a present file of the torrent with
+/// `--verify-duplicates`; returns them.
+fn tag_duplicates(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet, scan: &Scan,
+                  listing: &mut Listing) -> anyhow::Result<Vec<Duplicate>> {
+    if !matches.get_flag("verify-duplicates") {
+        return Ok(Vec::new());
+    }
+    let duplicates = hash_duplicates(matches, dir, expected, &scan.old_files)?;
+    listing.duplicates = duplicates.iter().map(|e| (e.extra.clone(), e.kept.clone())).collect();
+    Ok(duplicates)
+}
+
+/// Narrows the extras of `scan` down to exact duplicates of files the torrent keeps.
+fn restrict_to_duplicates(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet,
+                          scan: &mut Scan) -> anyhow::Result<Vec<Duplicate>> {
+    if matches.get_flag("no-confirm") && !matches.get_flag("force") {
+        return Err(anyhow!("--only-duplicates-of-kept hashes files before deleting them; \
+            add --force to combine it with --no-confirm"));
+    }
+    let duplicates = hash_duplicates(matches, dir, expected, &scan.old_files)?;
+
+    let others = scan.old_files.len() - duplicates.len();
+    if others > 0 {
+        say!("{} extras are not duplicates of kept files and are left alone.\n", others);
+    }
+    scan.old_files = duplicates.iter().map(|e| e.extra.clone()).collect();
+    scan.rm_size = duplicates.iter().map(|e| e.size).sum();
+    Ok(duplicates)
+}
+
+/// Says how many of the extras of `scan` `--verify-duplicates` found to be copies of present
+/// files of the torrent.
+fn print_duplicate_total(duplicates: &[Duplicate], scan: &Scan) {
+    if duplicates.is_empty() {
+        return;
+    }
+    let size = duplicates.iter().map(|e| e.size).sum::<u64>();
+    say!();
+    say!("Duplicates of files of the torrent, safe to remove: {} in {} of the {} extra files",
+         BinaryBytes(size), duplicates.len(), scan.old_files.len());
+}
+
+fn print_duplicates(duplicates: &[Duplicate], listing: &Listing) {
+    for duplicate in limited(duplicates, listing) {
+        say!("{}  {}  = {}", Red.paint("-f"), path_colored(&duplicate.extra, listing),
+             listing.paths.render(&duplicate.kept));
+    }
+    print_more(duplicates.len(), listing);
+}
+
+/// Describes how finely the files of a v2 or hybrid torrent can be verified, from the piece
+/// layers of the .torrent file and those of `--piece-layers`.
+fn print_layers(matches: &ArgMatches, metainfo: &Metainfo) -> anyhow::Result<()> {
+    let external = matches.get_one::<PathBuf>("piece-layers");
+    if metainfo.v2_files.is_empty() {
+        if external.is_some() {
+            eprintln!("{}", warn(WarningCode::PieceLayers,
+                                 "not a v2 torrent; ignoring --piece-layers"));
+        }
+        return Ok(());
+    }
+    let mut layers = metainfo.piece_layers.clone().unwrap_or_default();
+    let source = match (external, &metainfo.piece_layers) {
+        (Some(file), _) => {
+            layers.extend(load_piece_layers(file)?);
+            format!("from {}", file.display())
+        }
+        (None, Some(_)) => "embedded".to_string(),
+        (None, None) => "none".to_string(),
+    };
+    let checks = check_layers(&metainfo.v2_files, metainfo.meta.info.piece_length as u64, &layers);
+    let count = |check| checks.iter().filter(|(_, e)| *e == check).count();
+    say!("Piece layers: {}", source);
+    say!("Verifiable: {} files by piece, {} in a single piece, {} only as a whole",
+         count(LayerCheck::ByPiece), count(LayerCheck::SinglePiece),
+         count(LayerCheck::Missing) + count(LayerCheck::Mismatch));
+    for (file, check) in &checks {
+        let problem = match check {
+            LayerCheck::Missing => "has no piece layer",
+            LayerCheck::Mismatch => "has a piece layer that does not match its pieces root",
+            _ => continue,
+        };
+        eprintln!("{}", warn(WarningCode::PieceLayers, format!(
+            "{} {}; it can only be verified as a whole", file.name(), problem)));
+    }
+    Ok(())
+}
+
+// Show how much of the torrent is there
+fn run_stats(matches: &ArgMatches) -> anyhow::Result<()> {
+    let json = matches.get_one::<String>("output").is_some_and(|e| e == "json");
+    set_to_stderr(json);
+    let dir = content_dir(matches)?;
+    let (expected, mut record, mut sources) = load_expected(matches, "stats", &dir)?;
+    let dir = detect_root(matches, dir, &expected, &mut record, &mut sources)?;
+    check_target(matches, &dir, &expected, false)?;
+    check_scope(matches, &dir, &expected, &sources.names, false)?;
+    let stats = Stats::new(&dir, &expected, &scan(matches, &dir, &expected)?);
+    match json {
+        true => println!("{}", serde_json::to_string(&stats)?),
+        false => stats.print(),
+    }
+    Ok(())
+}
+
+// Create the missing files of the torrent as placeholders
+fn run_touch(matches: &ArgMatches) -> anyhow::Result<()> {
+    let dry_run = matches.get_flag("dry-run");
+    let allocate = matches.get_flag("allocate");
+    let dir = content_dir(matches)?;
+    let (expected, mut record, mut sources) = load_expected(matches, "touch", &dir)?;
+    let dir = detect_root(matches, dir, &expected, &mut record, &mut sources)?;
+    check_target(matches, &dir, &expected, false)?;
+    check_paths(matches, &expected)?;
+    let placeholders = Placeholders::find(&dir, &expected);
+    if placeholders.files.is_empty() {
+        say!("No files of the torrent are missing.");
+        return Ok(());
+    }
+
+    let listing = torrent_listing(matches, &dir, expected.is_flat());
+    say!("{}", if dry_run { "Placeholders to create:" } else { "Creating placeholders:" });
+    for entry in limited(&placeholders.dirs, &listing) {
+        say!("{}  {}", Green.paint("+d"), path_colored(entry, &listing));
+    }
+    print_more(placeholders.dirs.len(), &listing);
+    for (entry, size) in limited(&placeholders.files, &listing) {
+        say!("{}   {} ({})", Green.paint("+"), path_colored(entry, &listing), BinaryBytes(*size));
+    }
+    print_more(placeholders.files.len(), &listing);
+    say!();
+    let allocated = |size| match allocate {
+        true => format!(", allocating {}", BinaryBytes(size)),
+        false => String::new(),
+    };
+    if dry_run {
+        say!("Would create {} directories and {} files{}.", placeholders.dirs.len(),
+             placeholders.files.len(), allocated(placeholders.size()));
+        return Ok(());
+    }
+
+    let creation = placeholders.create(allocate);
+    say!("Created {} directories and {} files{}.", creation.dirs, creation.files,
+         allocated(creation.size));
+    if creation.failed.is_empty() {
+        return Ok(());
+    }
+    say!("Failed to create ({}):", creation.failed.len());
+    for (path, error) in limited(&creation.failed, &listing) {
+        say!("    {}  {}", path_colored(path, &listing), error);
+    }
+    print_more(creation.failed.len(), &listing);
+    Err(anyhow!("{} of {} placeholders could not be created", creation.failed.len(),
+                placeholders.dirs.len() + placeholders.files.len()))
+}
+
+// List what is left to download, without the extras
+fn run_missing(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let dir = content_dir(matches)?;
+    let (expected, mut record, mut sources) = load_expected(matches, "missing", &dir)?;
+    let dir = detect_root(matches, dir, &expected, &mut record, &mut sources)?;
+    check_target(matches, &dir, &expected, false)?;
+    let (missing, size) = expected.missing(&dir);
+    if missing.is_empty() {
+        say!("{}", Green.paint("No files of the torrent are missing."));
+        return Ok(ExitCode::SUCCESS);
+    }
+    let listing = torrent_listing(matches, &dir, expected.is_flat());
+    say!("Missing files:");
+    for entry in limited(&missing, &listing) {
+        let file_size = entry.strip_prefix(&dir).ok().and_then(|e| expected.file_size(e));
+        say!("{}   {} ({})", Green.paint("+"), path_colored(entry, &listing),
+             BinaryBytes(file_size.unwrap_or(0)));
+    }
+    print_more(missing.len(), &listing);
+    say!();
+    say!("Missing: {} in {} of {} files", Green.paint(BinaryBytes(size)), missing.len(),
+         expected.file_count());
+    Ok(ExitCode::FAILURE)
+}
+
+/// The files of the torrent `verify --files` selects, by their index in `pieces`: those whose
+/// name matches a pattern without a `/`, and whose path does one with a `/`. Fails for a pattern
+/// that selects none.
+fn selected_files(matches: &ArgMatches, pieces: &Pieces)
+                  -> anyhow::Result<Option<HashSet<usize>>> {
+    let Some(patterns) = matches.try_get_many::<String>("files").ok().flatten() else {
+        return Ok(None);
+    };
+    let mut selected = HashSet::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern.trim_matches('/')).literal_separator(true).build()
+            .with_context(|| format!("Invalid --files pattern: {}", pattern))?
+            .compile_matcher();
+        let by_path = pattern.trim_matches('/').contains('/');
+        let found = pieces.files().iter().enumerate()
+            .filter(|(_, e)| !e.padding && match by_path {
+                true => glob.is_match(&e.path),
+                false => e.path.file_name().is_some_and(|name| glob.is_match(name)),
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        if found.is_empty() {
+            return Err(anyhow!("--files {} matches no file of the torrent", pattern));
+        }
+        selected.extend(found);
+    }
+    Ok(Some(selected))
+}
+
+/// Hashes the files of the torrent under `dir` against its `pieces` with a progress bar of the
+/// bytes hashed, for `verify` and `clean --verify`; only the pieces of the files of `--files`, if
+/// given.
+fn hash_files(matches: &ArgMatches, dir: &Path, pieces: Option<&Pieces>)
+              -> anyhow::Result<Verification> {
+    let Some(pieces) = pieces else {
+        return Err(CliError::new(ErrorCode::TorrentUnsupported,
+            "There are no v1 piece hashes to verify against: a --manifest has none, and v2-only \
+             torrents cannot be verified yet").into());
+    };
+    let only = selected_files(matches, pieces)?;
+    let total = bytes_to_hash(pieces, only.as_ref());
+    match &only {
+        Some(only) => say!("Verifying {} selected files: {} in pieces of {}", only.len(),
+                           BinaryBytes(total), BinaryBytes(pieces.piece_length())),
+        None => say!("Verifying {} in {} pieces of {}", BinaryBytes(pieces.total()),
+                     pieces.count(), BinaryBytes(pieces.piece_length())),
+    }
+    let progress = ProgressMode::from_matches(matches).bar(ProgressBar::new(total));
+    progress.set_style(ProgressStyle::default_bar()
+        .template("{prefix} [{wide_bar:.cyan/blue}] {binary_bytes}/{binary_total_bytes} \
+                   ({percent}%, {binary_bytes_per_sec}, {eta})")?);
+    progress.set_prefix("Hashing");
+    let verification = verify(dir, pieces, only.as_ref(), &progress);
+    progress.finish_and_clear();
+    Ok(verification)
+}
+
+/// Lists the files `verification` did not find intact by what is wrong with them, and how much
+/// was verified. Returns how many files are intact.
+fn print_verification(verification: &Verification, listing: &Listing) -> usize {
+    let sections = [
+        ("Corrupt files", verification.with(|e| *e == FileCheck::Corrupt)),
+        ("Missing files", verification.with(|e| *e == FileCheck::Missing)),
+        ("Files of another size", verification.with(|e| matches!(e, FileCheck::SizeDiffers(_)))),
+        ("Unreadable files", verification.with(|e| matches!(e, FileCheck::Unreadable(_)))),
+        ("Not verified, sharing all their pieces with files missing, resized or unreadable",
+         verification.with(|e| *e == FileCheck::Unchecked)),
+    ];
+    for (title, files) in &sections {
+        if files.is_empty() {
+            continue;
+        }
+        say!("{} ({}):", title, files.len());
+        for file in limited(files, listing) {
+            let detail = match &file.check {
+                FileCheck::SizeDiffers(size) => format!("{} instead of {}", BinaryBytes(*size),
+                                                        BinaryBytes(file.size)),
+                FileCheck::Unreadable(error) => error.clone(),
+                _ => BinaryBytes(file.size).to_string(),
+            };
+            say!("    {}  ({})", path_colored(&file.path, listing), detail);
+        }
+        print_more(files.len(), listing);
+        say!();
+    }
+    let intact = verification.with(|e| *e == FileCheck::Intact).len();
+    say!("Verified: {} of {} ({:.1}%), {} of {} pieces matching; {} of {} files intact",
+         BinaryBytes(verification.verified), BinaryBytes(verification.total),
+         verification.percent(),
+         verification.pieces - verification.bad_pieces - verification.unchecked_pieces,
+         verification.pieces, intact, verification.files.len());
+    intact
+}
+
+/// Lists how the pieces of each file of `verify --files` fared, and the files around it that kept
+/// some of them from being hashed.
+fn print_piece_counts(verification: &Verification, listing: &Listing) {
+    say!("Pieces per file:");
+    for file in limited(&verification.files, listing) {
+        let counts = file.pieces;
+        say!("    {}  {} verified, {} failed, {} unverifiable", path_colored(&file.path, listing),
+             counts.verified, counts.failed, counts.unverifiable);
+        for (blocker, check) in &file.blockers {
+            let why = match check {
+                FileCheck::Missing => "missing",
+                FileCheck::SizeDiffers(_) => "of another size",
+                _ => "unreadable",
+            };
+            say!("        unverifiable where it shares a piece with {}, which is {}",
+                 path_colored(blocker, listing), why);
+        }
+    }
+    print_more(verification.files.len(), listing);
+    say!();
+}
+
+// Hash the files of the torrent against its pieces
+fn run_verify(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let dir = target_dir(matches)?;
+    let torrent = load_torrent(matches)?;
+    let expected = expected(matches, &torrent)?;
+    let mut record = new_record("verify", &torrent, &dir);
+    let mut sources = Sources { names: vec![record.torrent.clone()], ..Sources::default() };
+    let dir = detect_root(matches, dir, &expected, &mut record, &mut sources)?;
+    check_target(matches, &dir, &expected, false)?;
+    if matches.get_flag("delete-corrupt") {
+        check_paths(matches, &expected)?;
+    }
+    let verification = hash_files(matches, &dir, Pieces::of(&torrent.info).as_ref())?;
+    let listing = listing(matches, &dir);
+    if matches.contains_id("files") {
+        print_piece_counts(&verification, &listing);
+    }
+    let intact = print_verification(&verification, &listing);
+    let corrupt = verification.with(|e| *e == FileCheck::Corrupt);
+    if intact == verification.files.len() {
+        say!("{}", Green.paint(match matches.contains_id("files") {
+            true => "All selected files are intact.",
+            false => "All files of the torrent are intact.",
+        }));
+        return Ok(ExitCode::SUCCESS);
+    }
+    if !matches.get_flag("delete-corrupt") || corrupt.is_empty() {
+        return Ok(ExitCode::from(EXIT_DECLINED));
+    }
+
+    // Removed so that the client downloads them again
+    let files = corrupt.iter().map(|e| e.path.clone()).collect::<Vec<PathBuf>>();
+    let size = corrupt.iter().map(|e| e.size).sum::<u64>();
+    say!();
+    let question = format!("Delete the {} corrupt files ({})?", files.len(), BinaryBytes(size));
+    if !matches.get_flag("no-confirm") && !confirm(matches, &question, false) {
+        return Ok(ExitCode::from(EXIT_DECLINED));
+    }
+    start_removing();
+    let progress = ProgressMode::from_matches(matches).bar(ProgressBar::new(files.len() as u64));
+    progress.set_style(ProgressStyle::default_bar()
+        .template("{prefix} [{wide_bar:.cyan/blue}] {pos}/{len} ({percent}%)\n{msg}")?);
+    progress.set_prefix("Processing");
+    let paths = log_paths(matches, &dir);
+    let removal = remove_files(&files, None, &paths, None, None, RemoveOptions::default(),
+                               &progress)?;
+    let count = files.len() - removal.failed.len();
+    record.removed = count;
+    record.removed_size = size - removal.failed.iter()
+        .filter_map(|(path, _)| corrupt.iter().find(|e| e.path == *path))
+        .map(|e| e.size).sum::<u64>();
+    record.failures = removal.failed.len();
+    save_record(matches, &record);
+    progress.set_prefix("Done");
+    finish(&progress, format!("{} corrupt files removed.", count));
+    if let Some(err) = removal.error(&paths) {
+        say!("Failed to remove ({}):", removal.failed.len());
+        for (path, error) in limited(&removal.failed, &listing) {
+            say!("    {}  {}", path_colored(path, &listing), error);
+        }
+        print_more(removal.failed.len(), &listing);
+        return Err(err.into());
+    }
+    if interrupted() {
+        return Err(CliError::new(ErrorCode::Interrupted, format!(
+            "Interrupted: {} corrupt files removed; verify again for the rest", count)).into());
+    }
+    Ok(ExitCode::from(EXIT_DECLINED))
+}
+
+fn run_info(matches: &ArgMatches) -> anyhow::Result<()> {
+    let metainfo = load_metainfo(matches)?;
+    let torrent = &metainfo.meta;
+    let expected = expected(matches, torrent)?;
+    let info = &torrent.info;
+    if let Some(name) = &info.name {
+        say!("Name: {}", name);
+    }
+    say!("Info hash: {}", torrent.info_hash.as_string());
+    say!("Files: {}", expected.file_count());
+    say!("Total size: {}", BinaryBytes(expected.total_size()));
+    say!("Pieces: {} x {}", piece_count(info), BinaryBytes(info.piece_length as u64));
+    for tracker in torrent.iter_announce() {
+        say!("Tracker: {}", tracker);
+    }
+    if let Some(comment) = &torrent.comment {
+        say!("Comment: {}", comment);
+    }
+    if let Some(created_by) = &torrent.created_by {
+        say!("Created by: {}", created_by);
+    }
+    print_layers(matches, &metainfo)
+}
+
+fn run_purge(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let dir = target_dir(matches)?;
+    let older_than = matches.get_one::<Duration>("older-than");
+    let runs = staged_runs(&dir)?.into_iter()
+        .filter(|e| older_than.is_none_or(|d| e.age() > *d))
+        .collect::<Vec<_>>();
+    if runs.is_empty() {
+        say!("No matching staged runs found.");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    say!("Staged runs found:");
+    let listing = listing(matches, &dir);
+    let mut size = 0;
+    for run in &runs {
+        let run_size = run.size();
+        size += run_size;
+        say!("{}  {} ({})", Red.paint("-d"), path_colored(&run.path, &listing), BinaryBytes(run_size));
+    }
+    say!();
+    say!("Purge runs: {} ({})", Red.paint(BinaryBytes(size)), runs.len());
+
+    let question = format!("Permanently delete the above {} runs?", runs.len());
+    if !matches.get_flag("no-confirm") && !confirm(matches, &question, false) {
+        return Ok(ExitCode::from(EXIT_DECLINED));
+    }
+    for run in &runs {
+        run.purge()?;
+    }
+    say!("{} runs purged.", runs.len());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_restore(matches: &ArgMatches) -> anyhow::Result<()> {
+    let dir = target_dir(matches)?;
+    let runs = staged_runs(&dir)?;
+    let run = match matches.get_one::<String>("run") {
+        Some(name) => runs.iter().find(|e| e.name() == *name)
+            .ok_or_else(|| anyhow!("No staged run named {} in {}", name, dir.display()))?,
+        None => runs.last().ok_or_else(|| anyhow!("No staged runs in {}", dir.display()))?,
+    };
+    let mut include = Vec::new();
+    for pattern in matches.get_many::<String>("include").into_iter().flatten() {
+        include.push(GlobBuilder::new(pattern.trim_matches('/')).literal_separator(true).build()
+            .with_context(|| format!("Invalid --include pattern: {}", pattern))?
+            .compile_matcher());
+    }
+    let files = run.files().into_iter()
+        .filter(|rel| include.is_empty() || include.iter().any(|e| e.is_match(rel)
+            || (!e.glob().glob().contains('/') && rel.file_name().is_some_and(|n| e.is_match(n)))))
+        .collect::<Vec<PathBuf>>();
+    if files.is_empty() {
+        say!("No matching files in {}.", run.name());
+        return Ok(());
+    }
+
+    let listing = listing(matches, &dir);
+    let overwrite = matches.get_flag("overwrite");
+    let (mut restored, mut skipped, mut no_space, mut failed) = (0, 0, 0, 0);
+    say!("Restoring from {}:", run.name());
+    for rel in &files {
+        let target = dir.join(rel);
+        match run.restore(rel, &dir, overwrite, move_options(matches), &ProgressBar::hidden()) {
+            Ok(true) => {
+                restored += 1;
+                say!("{}   {}", Green.paint("+"), path_colored(&target, &listing));
+            }
+            Ok(false) => {
+                skipped += 1;
+                say!("{}   {} (exists)", Yellow.paint("="), path_colored(&target, &listing));
+            }
+            Err(err) if is_no_space(&err) => {
+                no_space += 1;
+                say!("{}   {} (insufficient space at destination)", Red.paint("!"),
+                     path_colored(&target, &listing));
+            }
+            Err(err) => {
+                failed += 1;
+                eprintln!("{} {:#}", Red.paint("Error:"), err);
+            }
+        }
+    }
+    say!();
+    say!("{} restored, {} skipped as existing, {} skipped for lack of space, {} failed.", restored,
+         skipped, no_space, failed);
+    if run.files().is_empty() {
+        run.purge()?;
+        say!("Removed the now empty run {}.", run.name());
+    }
+    match (failed, no_space) {
+        (0, 0) => Ok(()),
+        (0, n) => Err(CliError::new(ErrorCode::InsufficientSpace,
+            format!("{} files were left staged: insufficient space at destination", n)).into()),
+        (n, _) => Err(anyhow!("Failed to restore {} files", n)),
+    }
+}
+
+fn run_history(matches: &ArgMatches) -> anyhow::Result<()> {
+    let file = history_file()?;
+    let dir = matches.get_one::<PathBuf>("dir").map(absolute_path).transpose()?;
+    let torrent = matches.get_one::<String>("torrent").map(|e| e.to_lowercase());
+    let records = load_history(&file)?.into_iter()
+        .filter(|e| dir.as_ref().is_none_or(|d| e.dir == *d))
+        .filter(|e| torrent.as_ref()
+            .is_none_or(|t| e.info_hash.starts_with(t.as_str()) || e.torrent.to_lowercase() == *t))
+        .collect::<Vec<RunRecord>>();
+
+    if matches.get_one::<String>("output").is_some_and(|e| e == "json") {
+        say!("{}", serde_json::to_string(&records)?);
+        return Ok(());
+    }
+    if records.is_empty() {
+        say!("No runs recorded in {}.", file.display());
+        return Ok(());
+    }
+    for record in &records {
+        say!("{}  {:<5}  {}  {}", record.time(), record.command, record.torrent,
+                 record.dir.display());
+        say!("    extras: {} ({}), missing: {}, removed: {} ({}), failures: {}",
+                 record.extras, BinaryBytes(record.extras_size), record.missing, record.removed,
+                 BinaryBytes(record.removed_size), record.failures);
+    }
+    Ok(())
+}
+
+/// Plans like a dry run of diff and records the plan with the options behind it.
+fn take_snapshot(matches: &ArgMatches) -> anyhow::Result<(Snapshot, PathBuf)> {
+    let dir = target_dir(matches)?;
+    let torrent = load_torrent(matches)?;
+    let mut expected = expected(matches, &torrent)?;
+    apply_renames(matches, &mut expected)?;
+    apply_folding(matches, &mut expected);
+    let mut record = new_record("snapshot", &torrent, &dir);
+    let mut sources = Sources { names: vec![record.torrent.clone()], ..Sources::default() };
+    let dir = detect_root(matches, dir, &expected, &mut record, &mut sources)?;
+    let scan = scan(matches, &dir, &expected)?;
+    let snapshot = Snapshot::new(torrent.info_hash.as_string(), &expected, &dir, &scan,
+                                 PlanOptions::from_matches(matches));
+    Ok((snapshot, dir))
+}
+
+fn run_snapshot(matches: &ArgMatches) -> anyhow::Result<()> {
+    let (mut snapshot, dir) = take_snapshot(matches)?;
+    if matches.get_flag("include-paths") {
+        let input = matches.get_one::<PathBuf>("file").expect("required");
+        let torrent = match is_local_path(input) {
+            true => absolute_path(input)?,
+            false => input.clone(),
+        };
+        snapshot = snapshot.with_paths(torrent, &dir);
+    }
+    let file = matches.get_one::<PathBuf>("out").expect("required");
+    snapshot.write(file)?;
+    say!("Snapshot of {} planned removals and {} skipped entries written to {}.",
+         snapshot.plan.files.len() + snapshot.plan.dirs.len(), snapshot.plan.skipped.len(),
+         file.display());
+    Ok(())
+}
+
+fn run_compare_snapshot(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let file = matches.get_one::<PathBuf>("snapshot").expect("required");
+    let snapshot = Snapshot::load(file)?;
+    let (current, _) = take_snapshot(matches)?;
+    let differences = snapshot.differences(&current);
+    if differences.is_empty() {
+        say!("{}", Green.paint("The plan matches the snapshot."));
+        return Ok(ExitCode::SUCCESS);
+    }
+    say!("The plan differs from the snapshot taken with torrent-cleaner {}:",
+         snapshot.tool_version);
+    for line in &differences {
+        say!("  {}", line);
+    }
+    say!();
+    say!("{}", Yellow.paint(format!("{} differences.", differences.len())));
+    Ok(ExitCode::FAILURE)
+}
+
+/// Plans like a dry run of clean and writes the entries it would remove to a plan file.
+fn run_plan(matches: &ArgMatches) -> anyhow::Result<()> {
+    let dir = target_dir(matches)?;
+    let torrent = load_torrent(matches)?;
+    let mut expected = expected(matches, &torrent)?;
+    apply_renames(matches, &mut expected)?;
+    apply_folding(matches, &mut expected);
+    let scan = scan(matches, &dir, &expected)?;
+    let name = torrent.info.name.as_ref().map(|e| e.to_string()).unwrap_or_default();
+    let plan = SavedPlan::new(torrent.info_hash.as_string(), name, &dir, &scan,
+                              PlanOptions::from_matches(matches));
+    let file = matches.get_one::<PathBuf>("out").expect("required");
+    plan.write(file)?;
+    print_skipped(matches, &scan);
+    say!("Plan to remove {} files ({}) and {} directories written to {}; carry it out with \
+          `apply`.", plan.files.len(), BinaryBytes(plan.size), plan.dirs.len(), file.display());
+    Ok(())
+}
+
+/// Removes the entries of a plan file that are still as planned, reporting the others.
+fn run_apply(matches: &ArgMatches) -> anyhow::Result<ExitCode> {
+    let file = matches.get_one::<PathBuf>("plan").expect("required");
+    let plan = SavedPlan::load(file)?;
+    let dir = target_dir(matches)?;
+    let torrent = load_torrent(matches)?;
+    plan.check(file, &torrent.info_hash.as_string(), &dir)?;
+    let mut expected = expected(matches, &torrent)?;
+    check_paths(matches, &expected)?;
+    apply_renames(matches, &mut expected)?;
+    apply_folding(matches, &mut expected);
+    let (mut scan, drifted) = plan.scan(&expected);
+    let listing = torrent_listing(matches, &dir, expected.is_flat());
+    if !drifted.is_empty() {
+        say!("Changed since the plan, left alone ({}):", drifted.len());
+        for (path, drift) in limited(&drifted, &listing) {
+            say!("    {}  ({})", path_colored(path, &listing), drift.describe());
+        }
+        print_more(drifted.len(), &listing);
+        say!();
+    }
+    if scan.removal_count() == 0 {
+        say!("Nothing in the plan is left to remove.");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut entries = scan.old_files.clone();
+    entries.extend(scan.empty_dirs.iter().chain(&scan.emptied_dirs).cloned());
+    say!("Planned entries to remove:");
+    print_entries(&entries, &listing);
+    say!();
+    say!("Remove files: {} ({})", Red.paint(BinaryBytes(scan.rm_size)), scan.old_files.len());
+    say!();
+    let question = format!("Delete the above {} entries?", entries.len());
+    if !matches.get_flag("no-confirm") && !confirm(matches, &question, true) {
+        return Ok(ExitCode::from(EXIT_DECLINED));
+    }
+    start_removing();
+    let progress = ProgressMode::from_matches(matches)
+        .bar(ProgressBar::new(scan.old_files.len() as u64));
+    progress.set_style(ProgressStyle::default_bar()
+        .template("{prefix} [{wide_bar:.cyan/blue}] {pos}/{len} ({percent}%)\n{msg}")?);
+    progress.set_prefix("Processing");
+    let paths = log_paths(matches, &dir);
+    let removal = remove_files(&scan.old_files, Some(&scan), &paths, None, None,
+                               RemoveOptions::default(), &progress)?;
+    let changed_size = removal.changed.iter().filter_map(|e| scan.stamps.get(e))
+        .map(|e| e.size).sum::<u64>();
+    let failed_size = removal.failed.iter().filter_map(|(e, _)| scan.stamps.get(e))
+        .map(|e| e.size).sum::<u64>();
+    let mut count = scan.old_files.len() - removal.changed.len() - removal.failed.len();
+    let rm_size = scan.rm_size - changed_size - failed_size;
+    scan.exclude(&removal.changed.iter().cloned().collect(), SkipReason::Changed);
+    let mut dir_error = None;
+    if !interrupted() {
+        progress.set_prefix("Clearing dirs");
+        let dirs = remove_empty_dirs(&scan, &paths, None, RemoveOptions::default(), &progress);
+        count += dirs.removed;
+        dir_error = dirs.error(&paths);
+        scan.kept_dirs.extend(dirs.kept);
+    }
+    let mut record = new_record("clean", &torrent, &dir);
+    record.extras = plan.files.len() + plan.dirs.len();
+    record.extras_size = plan.size;
+    record.removed = count;
+    record.removed_size = rm_size;
+    record.failures = removal.failed.len() + usize::from(dir_error.is_some());
+    save_record(matches, &record);
+    if interrupted() {
+        progress.finish_and_clear();
+        return Err(CliError::new(ErrorCode::Interrupted, format!(
+            "Interrupted: {} entries removed ({}); apply the plan again for the rest", count,
+            BinaryBytes(rm_size))).into());
+    }
+
+    progress.set_prefix("Done");
+    finish(&progress, format!("{} entries removed.", count));
+    print_kept_dirs(&scan.kept_dirs, &listing);
+    print_skipped(matches, &scan);
+    if let Some(err) = removal.error(&paths) {
+        say!("Failed to remove ({}):", removal.failed.len());
+        for (path, error) in limited(&removal.failed, &listing) {
+            say!("    {}  {}", path_colored(path, &listing), error);
+        }
+        print_more(removal.failed.len(), &listing);
+        return Err(err.into());
+    }
+    if let Some(err) = dir_error {
+        return Err(err.into());
+    }
+    say!("Operation completed successfully.");
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_list(matches: &ArgMatches) -> anyhow::Result<()> {
+    let output = matches.get_one::<String>("output").expect("default");
+    set_to_stderr(output != "human");
+    let expected = expected(matches, &load_torrent(matches)?)?;
+    match output.as_str() {
+        "json" => {
+            println!("{}", serde_json::to_string(&file_list(&expected))?);
+            return Ok(());
+        }
+        "tsv" => {
+            print!("{}", file_list_tsv(&expected));
+            return Ok(());
+        }
+        _ => {}
+    }
+    let mut files = expected.files().collect::<Vec<(&Path, u64)>>();
+    files.sort();
+    for (path, size) in files {
+        say!("{:>12}  {}", BinaryBytes(size).to_string(), path.display());
+    }
+    say!();
+    say!("Total: {} ({})", BinaryBytes(expected.total_size()), expected.file_count());
+    Ok(())
+}
+
+/// Source: https://stackoverflow.com/a/54817755
+pub fn absolute_path(path: impl AsRef<Path>) -> io::Result<PathBuf> {
+    let path = path.as_ref();
+
+    let absolute_path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    }.clean();
+
+    Ok(extended_length(absolute_path))
+}
+
+/// `path` in the `\\?\` form on Windows, which lifts the 260 characters of `MAX_PATH` off the
+/// files beneath it and takes names such as `aux` literally; other systems need nothing of it.
+#[cfg(windows)]
+fn extended_length(path: PathBuf) -> PathBuf {
+    use std::ffi::{OsStr, OsString};
+    use std::path::{Component, Prefix};
+
+    let Some(Component::Prefix(prefix)) = path.components().next() else { return path };
+    let mut extended = OsString::from(r"\\?\");
+    match prefix.kind() {
+        Prefix::Disk(_) => extended.push(path.as_os_str()),
+        Prefix::UNC(server, share) => {
+            for part in [OsStr::new(r"UNC\"), server, OsStr::new(r"\"), share] {
+                extended.push(part);
+            }
+            // A verbatim path is not normalized, so each name is joined with `\` here
+            for name in path.components().filter_map(|e| match e {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            }) {
+                extended.push(r"\");
+                extended.push(name);
+            }
+        }
+        // Verbatim and device paths are taken as they are already
+        _ => return path,
+    }
+    PathBuf::from(extended)
+}
+
+#[cfg(not(windows))]
+fn extended_length(path: PathBuf) -> PathBuf {
+    path
 }
 
-fn truncate_message(message: String) -> String {
+pub fn truncate_message(message: String) -> String {
     if let Some((width, _)) = term_size::dimensions() {
         return format!("{}...", message.unicode_truncate(width.saturating_sub(10)).0)
     }
@@ -0,0 +1,73 @@
+use crate::truncate_message;
+use anyhow::Context;
+use indicatif::ProgressBar;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+struct ManifestLine {
+    /// Path relative to the content directory, `/`-separated.
+    path: String,
+    size: u64,
+    mtime: Option<String>,
+    /// `null` when the file is larger than the hashing limit or could not be read.
+    blake3: Option<String>,
+}
+
+/// Record of deleted files written by `clean --backup-manifest`, one JSON line per file.
+pub struct BackupManifest {
+    file: File,
+    path: PathBuf,
+    root: PathBuf,
+    hash_max_size: Option<u64>,
+}
+
+fn blake3_hex(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buf = vec![0; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hasher.finalize().to_hex().to_string());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+impl BackupManifest {
+    /// Opens `path` for appending; entries are recorded relative to `root`.
+    pub fn open(path: &Path, root: &Path, hash_max_size: Option<u64>) -> anyhow::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .with_context(|| format!("Failed to open backup manifest {}", path.display()))?;
+        Ok(Self { file, path: path.to_path_buf(), root: root.to_path_buf(), hash_max_size })
+    }
+
+    /// Hashes `entry` and appends its line, flushed so that it survives an interrupted run.
+    pub fn record(&mut self, entry: &Path, progress: &ProgressBar) -> anyhow::Result<()> {
+        let meta = fs::symlink_metadata(entry)
+            .with_context(|| format!("Failed to read metadata of {}", entry.display()))?;
+        let hash = match self.hash_max_size {
+            Some(max) if meta.len() > max => None,
+            _ if !meta.is_file() => None,
+            _ => {
+                progress.set_message(truncate_message(format!("Hashing: {}", entry.display())));
+                Some(blake3_hex(entry)
+                    .with_context(|| format!("Failed to hash {}", entry.display()))?)
+            }
+        };
+        let rel = entry.strip_prefix(&self.root).unwrap_or(entry);
+        let line = ManifestLine {
+            path: rel.iter().map(|e| e.to_string_lossy()).collect::<Vec<_>>().join("/"),
+            size: meta.len(),
+            mtime: meta.modified().ok()
+                .map(|e| humantime::format_rfc3339_seconds(e).to_string()),
+            blake3: hash,
+        };
+        writeln!(self.file, "{}", serde_json::to_string(&line)?)
+            .and_then(|_| self.file.flush())
+            .with_context(|| format!("Failed to write backup manifest {}", self.path.display()))
+    }
+}
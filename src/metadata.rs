@@ -0,0 +1,58 @@
+use crate::error::{CliError, ErrorCode};
+use crate::peers::{self, Magnet};
+use crate::torrent::{parse_metainfo, Metainfo};
+use anyhow::anyhow;
+use directories::ProjectDirs;
+use indicatif::ProgressBar;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Directory holding the info dicts of torrents parsed before, one `<info hash>.info` per torrent.
+pub fn cache_dir() -> anyhow::Result<PathBuf> {
+    let dirs = ProjectDirs::from("", "", "torrent-cleaner")
+        .ok_or(anyhow!("No home directory available for the metadata cache"))?;
+    Ok(dirs.cache_dir().join("metadata"))
+}
+
+/// Keeps the raw info dict of a parsed torrent so that it can later be found by info hash alone.
+/// The cache is only an optimization, so failures are ignored.
+pub fn store(info_hash: &str, info_bytes: &[u8]) {
+    let Ok(dir) = cache_dir() else { return };
+    let file = dir.join(format!("{}.info", info_hash));
+    if !file.exists() && fs::create_dir_all(&dir).is_ok() {
+        let _ = fs::write(file, info_bytes);
+    }
+}
+
+/// Loads the cached metadata of `info_hash`, checking that the stored info dict still hashes to
+/// it, or fetches it from the peers `input`, the magnet URI or info hash naming the torrent, leads
+/// to and caches it.
+pub fn load(progress: &ProgressBar, info_hash: &str, input: &str) -> anyhow::Result<Metainfo> {
+    let file = cache_dir()?.join(format!("{}.info", info_hash));
+    let info = match fs::read(&file) {
+        Ok(info) => info,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let info = peers::fetch(progress, &Magnet::new(info_hash, input))?;
+            progress.set_message("Parsing...");
+            store(info_hash, &info);
+            info
+        }
+        Err(e) => return Err(CliError::new(ErrorCode::TorrentUnreadable,
+            format!("Failed to read cached metadata: {}", e)).with_path(&file).into()),
+    };
+    let mut buf = b"d4:info".to_vec();
+    buf.extend_from_slice(&info);
+    buf.push(b'e');
+    let parsed = parse_metainfo(&buf).map_err(|e| match e.downcast::<CliError>() {
+        Ok(e) => e.with_path(&file),
+        Err(e) => CliError::new(ErrorCode::TorrentInvalid,
+            format!("Failed to parse cached metadata: {:#}", e)).with_path(&file),
+    })?;
+    if parsed.meta.info_hash.as_string() != info_hash {
+        return Err(CliError::new(ErrorCode::TorrentInvalid, format!("Cached metadata for {} does \
+            not match its info hash; delete it and pass the .torrent file again", info_hash))
+            .with_path(&file).into());
+    }
+    Ok(parsed)
+}
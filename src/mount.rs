@@ -0,0 +1,141 @@
+use crate::droppings::is_in_progress;
+use crate::scan::{ExpectedSet, STAGE_DIR};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// A torrent with a file or partial download modified this recently is taken to be downloading.
+pub const ACTIVE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Returns why `dir` looks like a mount point whose file system is not mounted, if it does.
+pub fn looks_unmounted(dir: &Path, expected: &impl ExpectedSet) -> Option<String> {
+    #[cfg(unix)]
+    if let Some(mount_point) = unmounted_mount_point(dir) {
+        return Some(format!("{} is listed in /etc/fstab but nothing is mounted there",
+                            mount_point.display()));
+    }
+
+    if expected.total_size() == 0 {
+        return None;
+    }
+    let is_empty = fs::read_dir(dir).is_ok_and(|entries| entries.flatten()
+        .all(|e| e.file_name() == STAGE_DIR));
+    if is_empty {
+        return Some(format!("{} is empty but the torrent expects {} files", dir.display(),
+                            expected.file_count()));
+    }
+    let (missing, _) = expected.missing(dir);
+    if missing.len() == expected.file_count() {
+        return Some(format!("none of the torrent's {} files exist in {}", expected.file_count(),
+                            dir.display()));
+    }
+    None
+}
+
+/// Returns why the torrent looks like it is still downloading into `dir`, if it does: files of
+/// it, or the partial files clients download them into, modified within [`ACTIVE_WINDOW`]. Only
+/// the directories holding files of the torrent are read.
+pub fn looks_active(dir: &Path, expected: &impl ExpectedSet) -> Option<String> {
+    let files = expected.files().map(|(path, _)| dir.join(path)).collect::<Vec<PathBuf>>();
+    let folders = files.iter().filter_map(|e| e.parent()).collect::<BTreeSet<&Path>>();
+    let partial = folders.iter()
+        .filter_map(|e| fs::read_dir(e).ok())
+        .flat_map(|entries| entries.flatten().map(|e| e.path()))
+        .filter(|e| e.strip_prefix(dir).is_ok_and(|e| is_in_progress(e, expected)))
+        .collect::<Vec<PathBuf>>();
+    let now = SystemTime::now();
+    // A time ahead of the clock, as on a share with a skewed one, is only as recent as it is ahead
+    let recent = |path: &PathBuf| fs::symlink_metadata(path).and_then(|e| e.modified())
+        .is_ok_and(|e| now.duration_since(e).unwrap_or_else(|e| e.duration()) < ACTIVE_WINDOW);
+    let modified = files.iter().filter(|e| recent(e)).count();
+    let downloading = partial.iter().filter(|e| recent(e)).count();
+    let mut what = Vec::new();
+    match (modified, files.len()) {
+        (0, _) => (),
+        (_, 1) => what.push("its file".to_string()),
+        (m, n) => what.push(format!("{} of its {} files", m, n)),
+    }
+    match downloading {
+        0 => (),
+        1 => what.push("a partial download".to_string()),
+        n => what.push(format!("{} partial downloads", n)),
+    }
+    let verb = match what.len() {
+        0 => return None,
+        1 if modified == 1 || downloading == 1 => "was",
+        _ => "were",
+    };
+    let idle = match partial.len() - downloading {
+        0 => String::new(),
+        1 => ", and a partial download is there".to_string(),
+        n => format!(", and {} partial downloads are there", n),
+    };
+    Some(format!("{} {} modified in the last {}{}", what.join(" and "), verb,
+                 humantime::format_duration(ACTIVE_WINDOW), idle))
+}
+
+/// The top-level directories of `dir` outside the torrent's scope, sorted, when the torrent
+/// accounts for at most one of its top-level entries; empty otherwise. Such directories are
+/// likely other torrents' content next to this one's, with `dir` a library rather than the
+/// content directory.
+pub fn sibling_trees(dir: &Path, expected: &impl ExpectedSet) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let (mut inside, mut trees) = (0, Vec::new());
+    for entry in entries.flatten().filter(|e| e.file_name() != STAGE_DIR) {
+        if expected.is_surface(&entry.file_name()) {
+            inside += 1;
+        } else if entry.file_type().is_ok_and(|e| e.is_dir()) {
+            trees.push(entry.path());
+        }
+    }
+    if inside > 1 {
+        return Vec::new();
+    }
+    trees.sort();
+    trees
+}
+
+/// The first ancestor of `dir` that `/etc/fstab` declares as a mount point but that is still on
+/// the same device as its parent.
+#[cfg(unix)]
+fn unmounted_mount_point(dir: &Path) -> Option<&Path> {
+    use std::os::unix::fs::MetadataExt;
+
+    let fstab = fs::read_to_string("/etc/fstab").ok()?;
+    let mount_points = fstab.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .filter(|e| *e != "/" && e.starts_with('/'))
+        .map(unescape_fstab)
+        .collect::<Vec<String>>();
+    dir.ancestors().find(|path| {
+        let Some(parent) = path.parent() else { return false };
+        mount_points.iter().any(|e| Path::new(e) == *path)
+            && matches!((fs::metadata(path), fs::metadata(parent)),
+                (Ok(a), Ok(b)) if a.dev() == b.dev())
+    })
+}
+
+/// fstab encodes spaces and other special characters as octal escapes such as `\040`.
+#[cfg(unix)]
+fn unescape_fstab(field: &str) -> String {
+    let mut out = String::new();
+    let mut rest = field;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        let code = rest.get(i + 1..i + 4).and_then(|e| u8::from_str_radix(e, 8).ok());
+        match code {
+            Some(c) => {
+                out.push(c as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,156 @@
+//! Async variants of [`Scanner::scan`] and [`Plan::execute`] for tokio applications, with the
+//! same results as the blocking ones:
+//!
+//! ```
+//! # use librqbit_buffers::ByteBufOwned;
+//! # use librqbit_core::torrent_metainfo::torrent_from_bytes_ext;
+//! # use std::fs;
+//! # use std::path::{Path, PathBuf};
+//! # use std::sync::Arc;
+//! use torrent_cleaner::nonblocking::{execute_plan, scan_async};
+//! use torrent_cleaner::scan::ScanOptions;
+//! use torrent_cleaner::{Scanner, TorrentFileSet};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! # let bytes = ByteBufOwned::from(b"d4:infod5:filesld6:lengthi3e4:pathl7:Season17:ep1.mkveee\
+//! #     4:name4:Show12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee".to_vec());
+//! # let torrent = torrent_from_bytes_ext::<ByteBufOwned>(bytes.as_ref())?.meta;
+//! # let fixture = |name: &str| -> std::io::Result<PathBuf> {
+//! #     let dir = std::env::temp_dir()
+//! #         .join(format!("torrent-cleaner-{}-{}", name, std::process::id()));
+//! #     fs::create_dir_all(dir.join("Season1/Samples"))?;
+//! #     fs::write(dir.join("Season1/ep1.mkv"), "abc")?;
+//! #     fs::write(dir.join("Season1/ep1.nfo"), "info")?;
+//! #     fs::write(dir.join("Season1/Samples/s.mkv"), "sample")?;
+//! #     Ok(dir)
+//! # };
+//! # let relative = |paths: &[PathBuf], dir: &Path| paths.iter()
+//! #     .map(|e| e.strip_prefix(dir).expect("inside").to_path_buf()).collect::<Vec<PathBuf>>();
+//! let files = Arc::new(TorrentFileSet::from_metainfo(&torrent)?);
+//! let options = ScanOptions { include_empty_dir: true, ..ScanOptions::default() };
+//! let (blocking_dir, async_dir) = (fixture("blocking")?, fixture("async")?);
+//! let expected = Scanner::new(&blocking_dir).with_options(options.clone()).scan(&*files)?.plan();
+//!
+//! let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+//! let scanner = Scanner::new(&async_dir).with_options(options);
+//! let plan = runtime.block_on(scan_async(&scanner, files))?.plan();
+//! assert_eq!(relative(&plan.files, &async_dir), relative(&expected.files, &blocking_dir));
+//! assert_eq!(relative(&plan.dirs, &async_dir), relative(&expected.dirs, &blocking_dir));
+//! assert_eq!(plan.size, expected.size);
+//! assert_eq!(runtime.block_on(execute_plan(&plan, 4))?, expected.execute()?);
+//! # fs::remove_dir_all(blocking_dir)?;
+//! # fs::remove_dir_all(async_dir)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::scan::{ExpectedSet, Progress};
+use crate::{remove_error, Plan, ScanReport, Scanner};
+use anyhow::anyhow;
+use futures_core::Stream;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+/// What [`scan_stream`] reports while it runs.
+pub enum ScanEvent {
+    /// An extra file, in the order found; the report sorts them if the options say so.
+    Extra(PathBuf),
+    /// The scan ended; always the last event.
+    Finished(anyhow::Result<ScanReport>),
+}
+
+struct Sender(mpsc::UnboundedSender<ScanEvent>);
+
+impl Progress for Sender {
+    fn inc(&self, _: u64) {}
+
+    fn found(&self, extra: &Path, _: u64) {
+        // A caller that stopped listening does not stop the scan
+        let _ = self.0.send(ScanEvent::Extra(extra.to_path_buf()));
+    }
+}
+
+/// The events of one scan, returned by [`scan_stream`].
+pub struct ScanStream {
+    events: mpsc::UnboundedReceiver<ScanEvent>,
+}
+
+impl ScanStream {
+    /// The next event, or `None` after [`ScanEvent::Finished`].
+    pub async fn next(&mut self) -> Option<ScanEvent> {
+        self.events.recv().await
+    }
+}
+
+impl Stream for ScanStream {
+    type Item = ScanEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ScanEvent>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+/// Runs [`Scanner::scan`] on tokio's blocking pool, reporting each extra as it is found. Must be
+/// called within a tokio runtime.
+pub fn scan_stream<S>(scanner: &Scanner, files: Arc<S>) -> ScanStream
+        where S: ExpectedSet + Send + Sync + 'static {
+    let (sender, events) = mpsc::unbounded_channel();
+    let scanner = scanner.clone();
+    tokio::task::spawn_blocking(move || {
+        let sender = Sender(sender);
+        let report = scanner.scan_with(&*files, &sender);
+        let _ = sender.0.send(ScanEvent::Finished(report));
+    });
+    ScanStream { events }
+}
+
+/// [`Scanner::scan`] without blocking the runtime.
+pub async fn scan_async<S>(scanner: &Scanner, files: Arc<S>) -> anyhow::Result<ScanReport>
+        where S: ExpectedSet + Send + Sync + 'static {
+    let mut stream = scan_stream(scanner, files);
+    while let Some(event) = stream.next().await {
+        if let ScanEvent::Finished(report) = event {
+            return report;
+        }
+    }
+    Err(anyhow!("The scan ended without a result"))
+}
+
+/// [`Plan::execute`] deleting up to `concurrency` files at a time. After a failure no further
+/// deletions start, and the first failure is returned once those in flight are done.
+pub async fn execute_plan(plan: &Plan, concurrency: usize) -> anyhow::Result<usize> {
+    let mut tasks = JoinSet::new();
+    let mut files = plan.files.iter();
+    let mut failure = None;
+    loop {
+        while failure.is_none() && tasks.len() < concurrency.max(1) {
+            let Some(file) = files.next().cloned() else { break };
+            tasks.spawn(async move {
+                match tokio::fs::symlink_metadata(&file).await.is_ok_and(|e| e.is_dir()) {
+                    true => tokio::fs::remove_dir_all(&file).await,
+                    false => tokio::fs::remove_file(&file).await,
+                }.map_err(|e| remove_error("file", &file, e))
+            });
+        }
+        let Some(result) = tasks.join_next().await else { break };
+        if let Err(err) = result? {
+            failure.get_or_insert(err);
+        }
+    }
+    if let Some(err) = failure {
+        return Err(err.into());
+    }
+    // Children come before their parents, so these stay sequential
+    let mut kept = 0;
+    for dir in &plan.dirs {
+        match tokio::fs::remove_dir(dir).await {
+            Err(e) if e.kind() == std::io::ErrorKind::DirectoryNotEmpty => kept += 1,
+            result => result.map_err(|e| remove_error("directory", dir, e))?,
+        }
+    }
+    Ok(plan.files.len() + plan.dirs.len() - kept)
+}
@@ -0,0 +1,132 @@
+use anyhow::Context;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(true);
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+static LOG: Mutex<Option<File>> = Mutex::new(None);
+
+fn env_set(name: &str) -> bool {
+    env::var_os(name).is_some_and(|e| !e.is_empty() && e != "0")
+}
+
+/// Colors the output as `--color` says: `always`, `never`, or with `auto` when stdout is a
+/// terminal, unless `NO_COLOR` is set; `CLICOLOR_FORCE` colors it regardless. Call it before
+/// anything is colored, as term-painter picks the terminal up on first use.
+pub fn set_color(when: &str) {
+    let color = match when {
+        "always" => true,
+        "never" => false,
+        _ if env_set("CLICOLOR_FORCE") => true,
+        _ => !env_set("NO_COLOR") && io::stdout().is_terminal(),
+    };
+    if color {
+        // term-painter writes nothing for a terminal without colors, such as cron's
+        if env::var_os("TERM").is_none_or(|e| e.is_empty() || e == "dumb") {
+            env::set_var("TERM", "xterm");
+        }
+    } else {
+        env::set_var("TERM", "dumb");
+    }
+    console::set_colors_enabled(color);
+    console::set_colors_enabled_stderr(color);
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Whether the output is colored; otherwise listings tag entries with their type.
+pub fn color_enabled() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Suppresses the human-readable output printed through [`say!`], e.g. when stdout carries a
+/// JSON document instead.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Sends the output printed through [`say!`] to stderr, keeping stdout for a JSON document
+/// without losing the human-readable output. Call it before anything is colored: it also turns
+/// colors off, since term-painter writes its escape codes to stdout whichever stream the text
+/// goes to.
+pub fn set_to_stderr(to_stderr: bool) {
+    if to_stderr {
+        env::set_var("TERM", "dumb");
+        COLOR.store(false, Ordering::Relaxed);
+    }
+    TO_STDERR.store(to_stderr, Ordering::Relaxed);
+}
+
+pub fn is_to_stderr() -> bool {
+    TO_STDERR.load(Ordering::Relaxed)
+}
+
+/// `println!` for human-readable output, silent while [`set_quiet`] is in effect and on stderr
+/// while [`set_to_stderr`] is.
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            if $crate::output::is_to_stderr() {
+                eprintln!($($arg)*);
+            } else {
+                println!($($arg)*);
+            }
+        }
+    };
+}
+
+/// Prints the messages of [`verbose!`] up to `level` on stderr: 1 with `-v`, 2 with `-vv`.
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/// Appends every message of [`verbose!`] to the file at `path` from now on, whatever its level,
+/// each line starting with the time.
+pub fn open_log(path: &Path) -> anyhow::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)
+        .with_context(|| format!("Failed to open log file {}", path.display()))?;
+    *LOG.lock().expect("log lock") = Some(file);
+    Ok(())
+}
+
+/// Whether a [`verbose!`] message of `level` goes anywhere, so it is only put together then.
+pub fn logging(level: u8) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= level || LOG.lock().expect("log lock").is_some()
+}
+
+/// Writes `message` to the log file, and to stderr if the verbosity reaches `level`. A log file
+/// that cannot be written is left alone with a warning.
+pub fn note(level: u8, message: &str) {
+    if VERBOSITY.load(Ordering::Relaxed) >= level {
+        eprintln!("{}", message);
+    }
+    let mut log = LOG.lock().expect("log lock");
+    let Some(file) = log.as_mut() else { return };
+    let time = humantime::format_rfc3339_millis(SystemTime::now());
+    if let Err(e) = writeln!(file, "{} {}", time, message) {
+        eprintln!("Warning: failed to write the log file, which is no longer written: {}", e);
+        *log = None;
+    }
+}
+
+/// A message for `-v` (level 1) or `-vv` (level 2) and the `--log-file`, such as an entry
+/// removed or why the scan left one alone; never colored.
+#[macro_export]
+macro_rules! verbose {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::output::logging($level) {
+            $crate::output::note($level, &format!($($arg)*));
+        }
+    };
+}
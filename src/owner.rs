@@ -0,0 +1,46 @@
+use anyhow::anyhow;
+use std::fs::Metadata;
+
+/// The effective user id of this process.
+#[cfg(unix)]
+pub fn current_uid() -> anyhow::Result<u32> {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    Ok(unsafe { libc::geteuid() })
+}
+
+/// Resolves a user name or numeric id to a user id.
+#[cfg(unix)]
+pub fn lookup_user(user: &str) -> anyhow::Result<u32> {
+    if let Ok(uid) = user.parse::<u32>() {
+        return Ok(uid);
+    }
+    let name = std::ffi::CString::new(user).map_err(|_| anyhow!("Invalid user name: {}", user))?;
+    // SAFETY: the name is NUL-terminated and the returned record is only read before the next
+    // getpw* call, which this single-threaded startup path does not make
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    match passwd.is_null() {
+        true => Err(anyhow!("No such user: {}", user)),
+        false => Ok(unsafe { (*passwd).pw_uid }),
+    }
+}
+
+#[cfg(unix)]
+pub fn owner_of(meta: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.uid())
+}
+
+#[cfg(not(unix))]
+pub fn current_uid() -> anyhow::Result<u32> {
+    Err(anyhow!("--only-owned is only supported on Unix"))
+}
+
+#[cfg(not(unix))]
+pub fn lookup_user(_: &str) -> anyhow::Result<u32> {
+    Err(anyhow!("--owner is only supported on Unix"))
+}
+
+#[cfg(not(unix))]
+pub fn owner_of(_: &Metadata) -> Option<u32> {
+    None
+}
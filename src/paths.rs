@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathStyle {
+    /// Relative to the scanned directory.
+    Relative,
+    Absolute,
+}
+
+impl PathStyle {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "absolute" => PathStyle::Absolute,
+            _ => PathStyle::Relative,
+        }
+    }
+}
+
+/// Renders paths found under a scanned directory; every listing and message goes through this
+/// so the chosen `--paths` style applies everywhere.
+#[derive(Clone, Debug)]
+pub struct PathRenderer {
+    root: PathBuf,
+    style: PathStyle,
+    alias: Option<String>,
+}
+
+impl PathRenderer {
+    pub fn new(root: &Path, style: PathStyle) -> Self {
+        Self { root: root.to_path_buf(), style, alias: None }
+    }
+
+    /// Prefixes relative paths with `alias:`, for output mixing several scanned directories.
+    pub fn with_alias(mut self, alias: impl Into<String>) -> Self {
+        self.alias = Some(alias.into());
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn render(&self, path: &Path) -> String {
+        if self.style == PathStyle::Absolute {
+            return path.to_string_lossy().into_owned();
+        }
+        // Paths outside the root stay absolute rather than growing `..` components
+        let Ok(rel) = path.strip_prefix(&self.root) else {
+            return path.to_string_lossy().into_owned();
+        };
+        let rel = match rel.as_os_str().is_empty() {
+            true => ".".into(),
+            false => rel.to_string_lossy(),
+        };
+        match &self.alias {
+            Some(alias) => format!("{}:{}", alias, rel),
+            None => rel.into_owned(),
+        }
+    }
+}
+
+/// Names each of `dirs` for relative paths, which would be ambiguous across them: by its file
+/// name, numbered where two share one.
+pub fn aliases(dirs: &[&Path]) -> Vec<String> {
+    let names = dirs.iter()
+        .map(|e| e.file_name().unwrap_or_default().to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
+    names.iter().enumerate()
+        .map(|(i, name)| match names.iter().filter(|e| *e == name).count() {
+            1 => name.clone(),
+            _ => format!("{}#{}", name, i + 1),
+        })
+        .collect()
+}
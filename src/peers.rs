@@ -0,0 +1,499 @@
+//! Fetching the metadata of a torrent known only by its info hash from peers, with the extension
+//! protocol (BEP 10) and `ut_metadata` (BEP 9). Peers are found through the DHT (BEP 5), the
+//! HTTP and UDP (BEP 15) trackers of the magnet URI and its `x.pe` peers.
+
+use crate::error::{CliError, ErrorCode};
+use crate::interrupt::interrupted;
+use crate::torrent::Bencode;
+use crate::verbose;
+use indicatif::ProgressBar;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Nodes a DHT lookup starts from.
+const BOOTSTRAP: [&str; 4] = ["router.bittorrent.com:6881", "dht.transmissionbt.com:6881",
+                              "router.utorrent.com:6881", "dht.libtorrent.org:25401"];
+/// Nodes queried per round of a DHT lookup, and at most in all.
+const ROUND: usize = 8;
+const MAX_QUERIES: usize = 2000;
+/// Metadata is sent in pieces of this size, of which only the last may be shorter.
+const PIECE_SIZE: usize = 16 * 1024;
+/// Info dicts larger than this are refused rather than allocated on a peer's word.
+const MAX_METADATA: usize = 64 * 1024 * 1024;
+/// Peer messages larger than this are refused; a bitfield of a million pieces still fits.
+const MAX_MESSAGE: usize = 1024 * 1024;
+/// Peers asked for the metadata at the same time.
+const CONNECTIONS: usize = 8;
+/// How long a peer or a tracker may take to answer.
+const PEER_TIMEOUT: Duration = Duration::from_secs(10);
+/// The id our end of the extension protocol gives `ut_metadata`, which peers send its messages
+/// with.
+const UT_METADATA: u8 = 1;
+/// What the port announced to trackers says; nothing listens on it, as nothing is shared.
+const PORT: u16 = 6881;
+
+static TIMEOUT_SECS: AtomicU64 = AtomicU64::new(60);
+static NO_DHT: AtomicBool = AtomicBool::new(false);
+
+/// How long fetching metadata may take, as given by `--metadata-timeout`; zero fetches nothing.
+pub fn set_timeout(timeout: Duration) {
+    TIMEOUT_SECS.store(timeout.as_secs(), Ordering::Relaxed);
+}
+
+/// Finds peers through the magnet URI's trackers and peers alone, as `--no-dht` asks.
+pub fn set_no_dht(no_dht: bool) {
+    NO_DHT.store(no_dht, Ordering::Relaxed);
+}
+
+/// What a magnet URI tells about where to find the peers of its torrent.
+pub struct Magnet {
+    info_hash: [u8; 20],
+    trackers: Vec<String>,
+    peers: Vec<SocketAddr>,
+}
+
+impl Magnet {
+    /// The torrent of `info_hash`, in hex, with the trackers (`tr`) and peers (`x.pe`) of `input`
+    /// if it is a magnet URI rather than a bare info hash.
+    pub fn new(info_hash: &str, input: &str) -> Self {
+        let mut hash = [0; 20];
+        for (byte, digits) in hash.iter_mut().zip(info_hash.as_bytes().chunks(2)) {
+            *byte = std::str::from_utf8(digits).ok()
+                .and_then(|e| u8::from_str_radix(e, 16).ok()).unwrap_or_default();
+        }
+        let params = input.strip_prefix("magnet:?").into_iter().flat_map(|e| e.split('&'))
+            .filter_map(|e| e.split_once('='))
+            .map(|(key, value)| (key, percent_decode(value)));
+        let (mut trackers, mut peers) = (Vec::new(), Vec::new());
+        for (key, value) in params {
+            match key {
+                "tr" => trackers.push(value),
+                "x.pe" => peers.extend(value.to_socket_addrs().into_iter().flatten()),
+                _ => {}
+            }
+        }
+        Self { info_hash: hash, trackers, peers }
+    }
+
+    fn hex(&self) -> String {
+        self.info_hash.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Decodes the `%XX` escapes and `+` of a URI query value, keeping malformed escapes as they are.
+fn percent_decode(value: &str) -> String {
+    let (bytes, mut decoded) = (value.as_bytes(), Vec::new());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3).and_then(|e| std::str::from_utf8(e).ok())
+            .and_then(|e| u8::from_str_radix(e, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// What the threads finding peers and asking them report back.
+enum Event {
+    Peers(Vec<SocketAddr>),
+    /// A DHT lookup or a tracker announce is over.
+    Done,
+    Metadata(SocketAddr, io::Result<Vec<u8>>),
+}
+
+/// Fetches the info dict of `magnet` from the first peer that has it, checked against its info
+/// hash, showing on `progress` how far it got.
+pub fn fetch(progress: &ProgressBar, magnet: &Magnet) -> anyhow::Result<Vec<u8>> {
+    let timeout = Duration::from_secs(TIMEOUT_SECS.load(Ordering::Relaxed));
+    let dht = !NO_DHT.load(Ordering::Relaxed);
+    let hex = magnet.hex();
+    let error = |message: String| CliError::new(ErrorCode::TorrentUnreadable, message);
+    if timeout.is_zero() {
+        return Err(error(format!("No cached metadata for {}, and --metadata-timeout 0 fetches none \
+            from peers; pass its .torrent file once to cache it", hex)).into());
+    }
+    if !dht && magnet.trackers.is_empty() && magnet.peers.is_empty() {
+        return Err(error(format!("No cached metadata for {}, and nowhere to fetch it from: \
+            --no-dht is given and no trackers or peers are named; pass its .torrent file once to \
+            cache it", hex)).into());
+    }
+    let deadline = Instant::now() + timeout;
+    let stop = Arc::new(AtomicBool::new(false));
+    let peer_id = peer_id();
+    let (sender, events) = mpsc::channel();
+    let mut finders = 0;
+    if dht {
+        let (sender, stop, info_hash) = (sender.clone(), stop.clone(), magnet.info_hash);
+        thread::spawn(move || {
+            if let Err(e) = lookup(&info_hash, &sender, &stop, deadline) {
+                verbose!(2, "DHT lookup failed: {}", e);
+            }
+            let _ = sender.send(Event::Done);
+        });
+        finders += 1;
+    }
+    for tracker in &magnet.trackers {
+        let (sender, tracker, info_hash) = (sender.clone(), tracker.clone(), magnet.info_hash);
+        thread::spawn(move || {
+            match announce(&tracker, &info_hash, &peer_id) {
+                Ok(peers) => {
+                    let _ = sender.send(Event::Peers(peers));
+                }
+                Err(e) => verbose!(2, "Announce to {} failed: {}", tracker, e),
+            }
+            let _ = sender.send(Event::Done);
+        });
+        finders += 1;
+    }
+
+    let (mut seen, mut queue, mut asking, mut asked) = (HashSet::new(), VecDeque::new(), 0, 0);
+    queue.extend(magnet.peers.iter().copied().filter(|e| seen.insert(*e)));
+    let result = loop {
+        while asking < CONNECTIONS {
+            let Some(peer) = queue.pop_front() else { break };
+            let (sender, info_hash) = (sender.clone(), magnet.info_hash);
+            thread::spawn(move || {
+                let _ = sender.send(Event::Metadata(peer, ask(peer, &info_hash, &peer_id)));
+            });
+            (asking, asked) = (asking + 1, asked + 1);
+        }
+        progress.set_message(match asking {
+            0 => format!("Finding peers for {}... {} found", hex, seen.len()),
+            _ => format!("Fetching metadata of {}... {} peers found, {} asked", hex, seen.len(),
+                         asked),
+        });
+        if interrupted() {
+            break Err(CliError::new(ErrorCode::Interrupted, "Interrupted fetching metadata from \
+                peers"));
+        }
+        if finders == 0 && asking == 0 && queue.is_empty() {
+            let found = match seen.len() {
+                0 => "no peers were found".to_string(),
+                n => format!("none of the {} peers found shared it", n),
+            };
+            break Err(error(format!("Failed to fetch metadata for {}: {}; pass its .torrent file \
+                instead", hex, found)));
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        match events.recv_timeout(left.min(Duration::from_millis(100))) {
+            Ok(Event::Peers(peers)) => {
+                queue.extend(peers.into_iter().filter(|e| seen.insert(*e)));
+            }
+            Ok(Event::Done) => finders -= 1,
+            Ok(Event::Metadata(peer, Ok(info))) => {
+                progress.println(format!("Metadata of {} fetched from {}", hex, peer));
+                break Ok(info);
+            }
+            Ok(Event::Metadata(peer, Err(e))) => {
+                verbose!(2, "Peer {} has no metadata to share: {}", peer, e);
+                asking -= 1;
+            }
+            Err(RecvTimeoutError::Timeout) if left.is_zero() => {
+                break Err(error(format!("Timed out after {}s fetching metadata for {} ({} peers \
+                    found, {} asked); raise it with --metadata-timeout or pass its .torrent file",
+                    timeout.as_secs(), hex, seen.len(), asked)));
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => unreachable!("a sender is kept"),
+        }
+    };
+    // The threads still running give up at their next answer or timeout
+    stop.store(true, Ordering::Relaxed);
+    Ok(result?)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Random bytes good enough for ids and transaction numbers, which need not be secret.
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    for chunk in bytes.chunks_mut(8) {
+        let value = RandomState::new().build_hasher().finish().to_le_bytes();
+        chunk.copy_from_slice(&value[..chunk.len()]);
+    }
+    bytes
+}
+
+/// An Azureus-style peer id, `-TC0013-` for version 0.1.3, followed by random bytes.
+fn peer_id() -> [u8; 20] {
+    let mut id = random_bytes::<20>();
+    let version = env!("CARGO_PKG_VERSION").replace('.', "");
+    let prefix = format!("-TC{:0>4.4}-", version);
+    id[..8].copy_from_slice(prefix.as_bytes());
+    id
+}
+
+/// The IPv4 peers of a compact peer list, six bytes each.
+fn compact_peers(bytes: &[u8]) -> Vec<SocketAddr> {
+    bytes.chunks_exact(6).map(|e| SocketAddr::V4(SocketAddrV4::new(
+        Ipv4Addr::new(e[0], e[1], e[2], e[3]), u16::from_be_bytes([e[4], e[5]])))).collect()
+}
+
+/// Looks the peers of `info_hash` up in the DHT, walking towards the nodes closest to it until
+/// `stop` is set, the deadline passes or no closer node is left to ask.
+fn lookup(info_hash: &[u8; 20], events: &Sender<Event>, stop: &AtomicBool, deadline: Instant)
+          -> io::Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+    let mut query = b"d1:ad2:id20:".to_vec();
+    query.extend_from_slice(&random_bytes::<20>());
+    query.extend_from_slice(b"9:info_hash20:");
+    query.extend_from_slice(info_hash);
+    query.extend_from_slice(b"e1:q9:get_peers1:t2:gp1:y1:qe");
+    let distance = |id: &[u8; 20]| -> [u8; 20] {
+        std::array::from_fn(|i| id[i] ^ info_hash[i])
+    };
+    // The bootstrap nodes, whose ids are not known, are asked first
+    let mut nodes: Vec<([u8; 20], SocketAddr)> = BOOTSTRAP.iter()
+        .flat_map(|e| e.to_socket_addrs().into_iter().flatten())
+        .filter(SocketAddr::is_ipv4)
+        .map(|e| (*info_hash, e))
+        .collect();
+    if nodes.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no bootstrap node could be resolved"));
+    }
+    let (mut known, mut queried) = (HashSet::new(), HashSet::new());
+    known.extend(nodes.iter().map(|e| e.1));
+    let mut buf = [0; 2048];
+    while !stop.load(Ordering::Relaxed) && Instant::now() < deadline
+        && queried.len() < MAX_QUERIES {
+        nodes.sort_by_key(|(id, _)| distance(id));
+        let round: Vec<SocketAddr> = nodes.iter().map(|e| e.1).filter(|e| !queried.contains(e))
+            .take(ROUND).collect();
+        if round.is_empty() {
+            break;
+        }
+        for node in round {
+            queried.insert(node);
+            let _ = socket.send_to(&query, node);
+        }
+        let answered = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < answered.min(deadline) {
+            let len = match socket.recv_from(&mut buf) {
+                Ok((len, _)) => len,
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+                    => continue,
+                Err(e) => return Err(e),
+            };
+            let Some((message, _)) = Bencode::decode(&buf[..len], 0) else { continue };
+            let Some(reply) = message.get("r") else { continue };
+            if let Some(Bencode::List(values)) = reply.get("values") {
+                let peers = values.iter().flat_map(|e| match e {
+                    Bencode::Bytes(bytes) => compact_peers(bytes),
+                    _ => Vec::new(),
+                });
+                let _ = events.send(Event::Peers(peers.collect()));
+            }
+            if let Some(Bencode::Bytes(compact)) = reply.get("nodes") {
+                for node in compact.chunks_exact(26) {
+                    let id = node[..20].try_into().expect("20 bytes");
+                    let addr = compact_peers(&node[20..])[0];
+                    if known.insert(addr) {
+                        nodes.push((id, addr));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Announces to `tracker` that we want the torrent of `info_hash`, returning the peers it gave.
+fn announce(tracker: &str, info_hash: &[u8; 20], peer_id: &[u8; 20])
+            -> io::Result<Vec<SocketAddr>> {
+    match tracker.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("udp") => {
+            let host = rest.split('/').next().unwrap_or(rest);
+            announce_udp(host, info_hash, peer_id)
+        }
+        Some((scheme, _)) if ["http", "https"].iter().any(|e| scheme.eq_ignore_ascii_case(e)) => {
+            announce_http(tracker, info_hash, peer_id)
+        }
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, "not an HTTP or UDP tracker")),
+    }
+}
+
+fn announce_http(tracker: &str, info_hash: &[u8; 20], peer_id: &[u8; 20])
+                 -> io::Result<Vec<SocketAddr>> {
+    let escape = |bytes: &[u8]| bytes.iter().map(|b| format!("%{:02X}", b)).collect::<String>();
+    let url = format!("{}{}info_hash={}&peer_id={}&port={}&uploaded=0&downloaded=0&left=0\
+        &compact=1&numwant=50", tracker, if tracker.contains('?') { '&' } else { '?' },
+        escape(info_hash), escape(peer_id), PORT);
+    let agent = ureq::AgentBuilder::new()
+        .timeout(PEER_TIMEOUT)
+        .user_agent(concat!("torrent-cleaner/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let response = agent.get(&url).call().map_err(|e| io::Error::other(e.to_string()))?;
+    let mut body = Vec::new();
+    response.into_reader().take(MAX_MESSAGE as u64).read_to_end(&mut body)?;
+    let (reply, _) = Bencode::decode(&body, 0).ok_or_else(|| invalid("not a tracker response"))?;
+    if let Some(Bencode::Bytes(reason)) = reply.get("failure reason") {
+        return Err(io::Error::other(String::from_utf8_lossy(reason).into_owned()));
+    }
+    Ok(match reply.get("peers") {
+        Some(Bencode::Bytes(compact)) => compact_peers(compact),
+        Some(Bencode::List(peers)) => peers.iter().filter_map(|peer| {
+            let Some(Bencode::Bytes(ip)) = peer.get("ip") else { return None };
+            let port = u16::try_from(peer.get("port")?.int()?).ok()?;
+            Some(SocketAddr::new(std::str::from_utf8(ip).ok()?.parse().ok()?, port))
+        }).collect(),
+        _ => Vec::new(),
+    })
+}
+
+fn announce_udp(host: &str, info_hash: &[u8; 20], peer_id: &[u8; 20])
+                -> io::Result<Vec<SocketAddr>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect(host)?;
+    socket.set_read_timeout(Some(PEER_TIMEOUT))?;
+    let transaction = random_bytes::<4>();
+    let mut connect = 0x41727101980u64.to_be_bytes().to_vec();
+    connect.extend_from_slice(&0u32.to_be_bytes());
+    connect.extend_from_slice(&transaction);
+    socket.send(&connect)?;
+    let mut buf = [0; 1500];
+    let len = socket.recv(&mut buf)?;
+    if len < 16 || buf[..4] != [0, 0, 0, 0] || buf[4..8] != transaction {
+        return Err(invalid("not a connect response"));
+    }
+    let mut announce = buf[8..16].to_vec();
+    announce.extend_from_slice(&1u32.to_be_bytes());
+    announce.extend_from_slice(&transaction);
+    announce.extend_from_slice(info_hash);
+    announce.extend_from_slice(peer_id);
+    // Downloaded, left and uploaded, then no event, our address, a key and as many peers as the
+    // tracker gives
+    announce.extend_from_slice(&[0; 24 + 4 + 4]);
+    announce.extend_from_slice(&random_bytes::<4>());
+    announce.extend_from_slice(&(-1i32).to_be_bytes());
+    announce.extend_from_slice(&PORT.to_be_bytes());
+    socket.send(&announce)?;
+    let len = socket.recv(&mut buf)?;
+    if len < 20 || buf[..4] != [0, 0, 0, 1] || buf[4..8] != transaction {
+        return Err(invalid("not an announce response"));
+    }
+    Ok(compact_peers(&buf[20..len]))
+}
+
+/// Sends an extension protocol message with the extended message id `id`.
+fn send_extended(stream: &mut TcpStream, id: u8, payload: &[u8]) -> io::Result<()> {
+    let mut message = (payload.len() as u32 + 2).to_be_bytes().to_vec();
+    message.extend_from_slice(&[20, id]);
+    message.extend_from_slice(payload);
+    stream.write_all(&message)
+}
+
+/// Reads a length-prefixed peer message, empty for a keep-alive.
+fn read_message(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE {
+        return Err(invalid("the peer sent an oversized message"));
+    }
+    let mut message = vec![0; len];
+    stream.read_exact(&mut message)?;
+    Ok(message)
+}
+
+/// Whether `info` is the info dict of `info_hash`: a v1 one hashes to it with SHA-1, and a v2 one
+/// with SHA-256 truncated to 20 bytes.
+fn is_info_of(info: &[u8], info_hash: &[u8; 20]) -> bool {
+    crypto_hash::digest(crypto_hash::Algorithm::SHA1, info) == info_hash
+        || crypto_hash::digest(crypto_hash::Algorithm::SHA256, info)[..20] == info_hash[..]
+}
+
+/// Asks `peer` for the info dict of `info_hash`, piece by piece.
+fn ask(peer: SocketAddr, info_hash: &[u8; 20], peer_id: &[u8; 20]) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect_timeout(&peer, PEER_TIMEOUT)?;
+    stream.set_read_timeout(Some(PEER_TIMEOUT))?;
+    stream.set_write_timeout(Some(PEER_TIMEOUT))?;
+    let mut handshake = b"\x13BitTorrent protocol".to_vec();
+    // The reserved bit of the extension protocol
+    handshake.extend_from_slice(&[0, 0, 0, 0, 0, 0x10, 0, 0]);
+    handshake.extend_from_slice(info_hash);
+    handshake.extend_from_slice(peer_id);
+    stream.write_all(&handshake)?;
+    let mut reply = [0; 68];
+    stream.read_exact(&mut reply)?;
+    if reply[..20] != handshake[..20] {
+        return Err(invalid("not a BitTorrent peer"));
+    }
+    if reply[28..48] != info_hash[..] {
+        return Err(invalid("the peer has another torrent"));
+    }
+    if reply[25] & 0x10 == 0 {
+        return Err(invalid("the peer does not support the extension protocol"));
+    }
+    send_extended(&mut stream, 0, format!("d1:md11:ut_metadatai{}ee1:v{}:{}e",
+        UT_METADATA, "torrent-cleaner".len(), "torrent-cleaner").as_bytes())?;
+    // The info dict so far and which of its pieces were received
+    let mut metadata: Option<(Vec<u8>, Vec<bool>)> = None;
+    loop {
+        let message = read_message(&mut stream)?;
+        let [20, id, payload @ ..] = &message[..] else { continue };
+        let (dict, data) = Bencode::decode(payload, 0)
+            .ok_or_else(|| invalid("the peer sent a malformed extension message"))?;
+        match *id {
+            0 if metadata.is_none() => {
+                let theirs = dict.get("m").and_then(|e| e.get("ut_metadata"))
+                    .and_then(Bencode::int).and_then(|e| u8::try_from(e).ok()).filter(|e| *e > 0)
+                    .ok_or_else(|| invalid("the peer does not share metadata"))?;
+                let size = dict.get("metadata_size").and_then(Bencode::int)
+                    .and_then(|e| usize::try_from(e).ok()).filter(|e| (1..=MAX_METADATA).contains(e))
+                    .ok_or_else(|| invalid("the peer did not tell the size of the metadata"))?;
+                let pieces = size.div_ceil(PIECE_SIZE);
+                for piece in 0..pieces {
+                    send_extended(&mut stream, theirs,
+                                  format!("d8:msg_typei0e5:piecei{}ee", piece).as_bytes())?;
+                }
+                metadata = Some((vec![0; size], vec![false; pieces]));
+            }
+            UT_METADATA => {
+                let Some((info, received)) = metadata.as_mut() else { continue };
+                match dict.get("msg_type").and_then(Bencode::int) {
+                    Some(1) => {
+                        let piece = dict.get("piece").and_then(Bencode::int)
+                            .and_then(|e| usize::try_from(e).ok())
+                            .filter(|e| *e < received.len())
+                            .ok_or_else(|| invalid("the peer sent a piece that does not exist"))?;
+                        let start = piece * PIECE_SIZE;
+                        let end = info.len().min(start + PIECE_SIZE);
+                        if data.len() != end - start {
+                            return Err(invalid("the peer sent a piece of the wrong size"));
+                        }
+                        info[start..end].copy_from_slice(data);
+                        received[piece] = true;
+                        if received.iter().all(|e| *e) {
+                            return match is_info_of(info, info_hash) {
+                                true => Ok(std::mem::take(info)),
+                                false => Err(invalid("the peer sent metadata of another hash")),
+                            };
+                        }
+                    }
+                    Some(2) => return Err(invalid("the peer rejected the request")),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::scan::{ExpectedSet, Scan, Stamp};
+use crate::snapshot::PlanOptions;
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Version of the plan format; plans of other versions are refused.
+pub const PLAN_VERSION: u32 = 1;
+
+/// A file the plan removes, with the size and modification time it was planned with.
+#[derive(Serialize, Deserialize)]
+pub struct PlannedFile {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// A directory below `--max-depth`, removed as a whole.
+    #[serde(default)]
+    pub dir: bool,
+    /// Of such a directory, the files and links inside it, each checked again before removal.
+    #[serde(default)]
+    pub inside: Vec<PlannedFile>,
+}
+
+/// Everything a run of clean would remove, written by `plan` for review and carried out by
+/// `apply`. Paths are relative to the directory and `/`-separated; files are sorted and
+/// directories come children first.
+#[derive(Serialize, Deserialize)]
+pub struct SavedPlan {
+    pub version: u32,
+    /// Version of torrent-cleaner that made the plan.
+    pub tool_version: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub info_hash: String,
+    pub torrent: String,
+    /// The directory scanned, absolute.
+    pub dir: PathBuf,
+    pub options: PlanOptions,
+    /// Total size of the files, each counted once however many paths lead to it.
+    pub size: u64,
+    pub files: Vec<PlannedFile>,
+    /// Directories removed once empty.
+    pub dirs: Vec<String>,
+}
+
+/// How a planned entry changed since the plan, which has `apply` leave it alone.
+pub enum Drift {
+    Gone,
+    /// It is now one of the torrent's files or directories.
+    InTorrent,
+    /// A file that is now a directory, or the other way around.
+    Replaced,
+    Resized { planned: u64, now: u64 },
+}
+
+impl Drift {
+    pub fn describe(&self) -> String {
+        match self {
+            Drift::Gone => "no longer exists".to_string(),
+            Drift::InTorrent => "now part of the torrent".to_string(),
+            Drift::Replaced => "replaced by another kind of entry".to_string(),
+            Drift::Resized { planned, now } => {
+                format!("{} bytes where the plan has {}", now, planned)
+            }
+        }
+    }
+}
+
+fn relative(dir: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(dir).unwrap_or(path);
+    rel.iter().map(|e| e.to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+impl SavedPlan {
+    pub fn new(info_hash: String, torrent: String, dir: &Path, scan: &Scan,
+               options: PlanOptions) -> Self {
+        let planned = |path: &Path, stamp: Option<&Stamp>, inside| PlannedFile {
+            path: relative(dir, path),
+            size: stamp.map_or(0, |e| e.size),
+            modified: stamp.and_then(|e| e.modified),
+            dir: path.is_dir() && !path.is_symlink(),
+            inside,
+        };
+        let mut files = scan.old_files.iter()
+            .map(|e| {
+                let mut inside = match e.is_dir() && !e.is_symlink() {
+                    true => scan.stamps.iter().filter(|(path, _)| path.starts_with(e) && *path != e)
+                        .map(|(path, stamp)| planned(path, Some(stamp), Vec::new()))
+                        .collect::<Vec<PlannedFile>>(),
+                    false => Vec::new(),
+                };
+                inside.sort_by(|a, b| a.path.cmp(&b.path));
+                planned(e, scan.stamps.get(e), inside)
+            })
+            .collect::<Vec<PlannedFile>>();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Self {
+            version: PLAN_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            info_hash,
+            torrent,
+            dir: dir.to_path_buf(),
+            options,
+            size: scan.rm_size,
+            files,
+            dirs: scan.dir_cascade().into_iter().map(|(path, _)| relative(dir, path)).collect(),
+        }
+    }
+
+    pub fn write(&self, file: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(file, content + "\n")
+            .with_context(|| format!("Failed to write plan {}", file.display()))
+    }
+
+    pub fn load(file: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read plan {}", file.display()))?;
+        let plan = serde_json::from_str::<Self>(&content)
+            .with_context(|| format!("Invalid plan {}", file.display()))?;
+        if plan.version != PLAN_VERSION {
+            return Err(anyhow!("Unsupported plan version {} in {}; this version of \
+                torrent-cleaner reads version {}", plan.version, file.display(), PLAN_VERSION));
+        }
+        Ok(plan)
+    }
+
+    /// Refuses to apply the plan in `file` to another torrent or directory than it was made for.
+    pub fn check(&self, file: &Path, info_hash: &str, dir: &Path) -> anyhow::Result<()> {
+        if self.info_hash != info_hash {
+            return Err(anyhow!("Plan {} was made for the torrent {}, not {}", file.display(),
+                               self.info_hash, info_hash));
+        }
+        if self.dir != dir {
+            return Err(anyhow!("Plan {} was made for {}, not {}", file.display(),
+                               self.dir.display(), dir.display()));
+        }
+        Ok(())
+    }
+
+    fn entry(&self, path: &str) -> PathBuf {
+        path.split('/').fold(self.dir.clone(), |e, part| e.join(part))
+    }
+
+    /// The planned entries that are still as planned and still not part of `expected`, as a scan
+    /// to remove them with, and the others with how they changed. Files are checked against
+    /// their planned size and modification time again right before removal.
+    pub fn scan(&self, expected: &impl ExpectedSet) -> (Scan, Vec<(PathBuf, Drift)>) {
+        let mut scan = Scan {
+            old_files: Vec::new(),
+            empty_dirs: Vec::new(),
+            emptied_dirs: Vec::new(),
+            kept_dirs: Vec::new(),
+            rm_size: 0,
+            skipped: Vec::new(),
+            aliases: Vec::new(),
+            stamps: HashMap::new(),
+            unreadable: Vec::new(),
+        };
+        let mut drifted = Vec::new();
+        for file in &self.files {
+            let entry = self.entry(&file.path);
+            let drift = match fs::symlink_metadata(&entry) {
+                Err(_) => Drift::Gone,
+                Ok(_) if expected.file_size(Path::new(&file.path)).is_some()
+                    || expected.contains_dir(Path::new(&file.path)) => Drift::InTorrent,
+                Ok(meta) if meta.is_dir() != file.dir => Drift::Replaced,
+                Ok(_) if Stamp::read(&entry).is_some_and(|e| e.size != file.size) => {
+                    let now = Stamp::read(&entry).map_or(0, |e| e.size);
+                    Drift::Resized { planned: file.size, now }
+                }
+                Ok(_) => {
+                    scan.stamps.extend(file.inside.iter().map(|e| (self.entry(&e.path),
+                        Stamp { size: e.size, modified: e.modified })));
+                    scan.stamps.insert(entry.clone(),
+                                       Stamp { size: file.size, modified: file.modified });
+                    scan.old_files.push(entry);
+                    scan.rm_size += file.size;
+                    continue;
+                }
+            };
+            drifted.push((entry, drift));
+        }
+        for dir in &self.dirs {
+            let entry = self.entry(dir);
+            let drift = match fs::symlink_metadata(&entry) {
+                Err(_) => Drift::Gone,
+                Ok(_) if expected.contains_dir(Path::new(dir)) => Drift::InTorrent,
+                Ok(meta) if !meta.is_dir() => Drift::Replaced,
+                Ok(_) => {
+                    match fs::read_dir(&entry).is_ok_and(|mut e| e.next().is_none()) {
+                        true => scan.empty_dirs.push(entry),
+                        false => scan.emptied_dirs.push(entry),
+                    }
+                    continue;
+                }
+            };
+            drifted.push((entry, drift));
+        }
+        (scan, drifted)
+    }
+}
@@ -0,0 +1,57 @@
+use std::io;
+
+/// Lowers the CPU priority of the process and, on Linux, moves its I/O to the idle scheduling
+/// class. Threads spawned afterwards inherit both. Returns a message for each adjustment that
+/// failed.
+pub fn lower_priority() -> Vec<String> {
+    let mut errors = Vec::new();
+    if let Err(e) = lower_cpu_priority() {
+        errors.push(format!("Failed to lower CPU priority: {}", e));
+    }
+    #[cfg(target_os = "linux")]
+    if let Err(e) = set_idle_io() {
+        errors.push(format!("Failed to set idle I/O priority: {}", e));
+    }
+    errors
+}
+
+#[cfg(unix)]
+fn lower_cpu_priority() -> io::Result<()> {
+    // SAFETY: plain system call on the calling process, no pointers involved
+    match unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(windows)]
+fn lower_cpu_priority() -> io::Result<()> {
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, SetPriorityClass,
+                                                BELOW_NORMAL_PRIORITY_CLASS};
+
+    // SAFETY: the pseudo handle of the current process is always valid
+    match unsafe { SetPriorityClass(GetCurrentProcess(), BELOW_NORMAL_PRIORITY_CLASS) } {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn lower_cpu_priority() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "not supported on this platform"))
+}
+
+#[cfg(target_os = "linux")]
+fn set_idle_io() -> io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+    // SAFETY: ioprio_set takes integers only; 0 means the calling process
+    let result = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0,
+                                        IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT) };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
@@ -0,0 +1,91 @@
+use crate::output::is_quiet;
+use crate::say;
+use clap::ArgMatches;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget};
+use std::io::{self, IsTerminal};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time between plain status lines when stderr is not a terminal.
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How progress is reported: animated bars on a terminal, occasional plain status lines when
+/// stderr is redirected, or not at all with `--no-progress`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressMode {
+    Bars,
+    Plain,
+    Off,
+}
+
+impl ProgressMode {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        if matches.get_flag("no-progress") || is_quiet() {
+            ProgressMode::Off
+        } else if io::stderr().is_terminal() {
+            ProgressMode::Bars
+        } else {
+            ProgressMode::Plain
+        }
+    }
+
+    /// Applies the mode to a newly created bar.
+    pub fn bar(self, bar: ProgressBar) -> ProgressBar {
+        if self != ProgressMode::Bars {
+            bar.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        self.track(bar)
+    }
+
+    pub fn multi(self) -> MultiProgress {
+        match self {
+            ProgressMode::Bars => MultiProgress::new(),
+            _ => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+        }
+    }
+
+    /// Adds a bar to `multi`, which already carries the mode's draw target.
+    pub fn add(self, multi: &MultiProgress, bar: ProgressBar) -> ProgressBar {
+        self.track(multi.add(bar))
+    }
+
+    fn track(self, bar: ProgressBar) -> ProgressBar {
+        if self == ProgressMode::Plain {
+            let status = bar.clone();
+            thread::spawn(move || print_status(status));
+        }
+        bar
+    }
+}
+
+/// Prints `prefix pos/len message` to stderr every [`STATUS_INTERVAL`] until the bar finishes.
+fn print_status(bar: ProgressBar) {
+    let mut last = Instant::now();
+    while !bar.is_finished() {
+        thread::sleep(Duration::from_millis(250));
+        if last.elapsed() < STATUS_INTERVAL || bar.is_finished() {
+            continue;
+        }
+        last = Instant::now();
+        let count = match bar.length() {
+            Some(len) => format!("{}/{}", bar.position(), len),
+            None if bar.position() > 0 => format!("{} entries", bar.position()),
+            None => String::new(),
+        };
+        let line = [bar.prefix(), count, bar.message()].into_iter()
+            .filter(|e| !e.is_empty())
+            .collect::<Vec<String>>();
+        eprintln!("{}", line.join(" "));
+    }
+}
+
+/// Finishes `bar` with `message`, printing the message directly if the bar is not drawn.
+pub fn finish(bar: &ProgressBar, message: String) {
+    if bar.is_hidden() {
+        bar.finish_and_clear();
+        say!("{}", message);
+    } else {
+        bar.set_message(message);
+        bar.finish();
+    }
+}
@@ -0,0 +1,93 @@
+use crate::say;
+use clap::ArgMatches;
+use inquire::list_option::ListOption;
+use inquire::{Confirm, MultiSelect};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Exit code of a run that removed nothing because its confirmation was declined, went
+/// unanswered, was answered by `--assume-no` or could not be asked.
+pub const EXIT_DECLINED: u8 = 1;
+
+/// Whether a prompt can be both seen and answered: stdin and stdout are terminals, which they
+/// are not under cron, in CI or when the output is piped.
+pub fn interactive() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Asks `question` before anything is removed, telling why when the answer is no. `--assume-no`
+/// answers it, and so does the lack of a terminal rather than waiting for input that never
+/// comes; `--prompt-timeout` takes no answer in time for no.
+pub fn confirm(matches: &ArgMatches, question: &str, default: bool) -> bool {
+    if matches.get_flag("assume-no") {
+        say!("{} No (--assume-no).", question);
+        return false;
+    }
+    if !interactive() {
+        let stdin = matches.try_get_one::<PathBuf>("file").ok().flatten()
+            .is_some_and(|e| e == Path::new("-"));
+        say!("Aborted: {}there is no terminal to confirm on; pass --no-confirm to go ahead without \
+              asking.", if stdin { "the torrent was read from stdin and " } else { "" });
+        return false;
+    }
+    let answer = match matches.get_one::<Duration>("prompt-timeout") {
+        Some(timeout) => ask_within(question, default, *timeout),
+        None => Confirm::new(question).with_default(default).prompt().ok(),
+    };
+    match answer {
+        Some(true) => {
+            say!("Confirmed.");
+            true
+        }
+        _ => {
+            say!("Aborted.");
+            false
+        }
+    }
+}
+
+/// Asks `question` on a line of its own, read on another thread so that no answer within
+/// `timeout` can be taken for none. The terminal stays in line mode, so one left waiting has
+/// nothing to restore.
+fn ask_within(question: &str, default: bool, timeout: Duration) -> Option<bool> {
+    eprint!("{} [{}] (no in {}) ", question, if default { "Y/n" } else { "y/N" },
+            humantime::format_duration(timeout));
+    io::stderr().flush().ok()?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut line = String::new();
+        let read = io::stdin().lock().read_line(&mut line);
+        sender.send(read.map(|n| (n > 0).then_some(line))).ok();
+    });
+    let Ok(read) = receiver.recv_timeout(timeout) else {
+        eprintln!();
+        say!("No answer within {}.", humantime::format_duration(timeout));
+        return None;
+    };
+    match read.ok()??.trim().to_ascii_lowercase().as_str() {
+        "" => Some(default),
+        "y" | "yes" => Some(true),
+        _ => Some(false),
+    }
+}
+
+/// Asks which of `options` to go ahead with in a list that scrolls to fit the terminal, all of
+/// them selected to begin with. Returns the indices left selected, or `None` if it was
+/// cancelled.
+pub fn select(question: &str, options: Vec<String>) -> Option<Vec<usize>> {
+    let total = options.len();
+    let rows = term_size::dimensions().map_or(0, |(_, rows)| rows);
+    let formatter = move |selected: &[ListOption<&String>]| {
+        format!("{} of {} selected", selected.len(), total)
+    };
+    MultiSelect::new(question, options)
+        .with_all_selected_by_default()
+        .with_page_size(rows.saturating_sub(4).max(7))
+        .with_help_message("space to toggle, → all, ← none, type to filter, enter to confirm")
+        .with_formatter(&formatter)
+        .raw_prompt().ok()
+        .map(|selected| selected.into_iter().map(|e| e.index).collect())
+}
@@ -0,0 +1,813 @@
+//! `diff` and `clean` of a directory on another machine, given as
+//! `sftp://[user@]host[:port]/path`. The torrent is read here; the listing, the metadata and the
+//! removals go over SFTP, authenticated by the SSH agent or the default keys, to a host already
+//! in `~/.ssh/known_hosts`.
+
+use crate::breakdown::print_breakdown;
+use crate::cli::build_cli;
+use crate::download::timeout;
+use crate::error::{CliError, ErrorCode};
+use crate::interrupt::{interrupted, start_removing};
+use crate::kind::count_kinds;
+use crate::progress::{finish, ProgressMode};
+use crate::prompt::{confirm, EXIT_DECLINED};
+use crate::remove::FileRemoval;
+use crate::{asked_entries, check_threshold, check_torrent, delete_ratio, limited,
+            load_expected, log_paths, path_colored, print_empty_dirs, print_kept, print_kept_dirs,
+            print_more, print_skipped, print_top, save_record, say, scan_options, sort_order,
+            tags, top, torrent_listing, truncate_message, unlisted_dirs, verbose, Listing,
+            CHECK_EXTRAS, CHECK_INCOMPLETE, CHECK_OUT_OF_SCOPE};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use indicatif::{BinaryBytes, HumanCount, ProgressBar, ProgressStyle};
+use ssh2::{CheckResult, FileStat, KnownHostFileKind, Session, Sftp};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Read};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use term_painter::Color::{Green, Red, Yellow};
+use term_painter::ToStyle;
+use torrent_cleaner::droppings;
+use torrent_cleaner::scan::{snapshot_area, sort_deepest_first, sort_paths, DirKeptReason,
+                            ExpectedSet, KeptDir, Mismatch, Scan, ScanOptions, SkipReason, Skipped,
+                            SortOrder, Stamp, Symlinks, IGNORE_FILE, STAGE_DIR};
+
+/// The options of diff and clean that need the files at hand, or a command or report that has not
+/// learnt remote paths, refused with a remote directory rather than silently ignored.
+const LOCAL_ONLY: [&str; 41] = [
+    "verify", "delete-corrupt", "verify-renames", "verify-duplicates", "dedupe-across-roots",
+    "stage", "move-to", "link-to", "trash", "backup-manifest", "journal", "resume",
+    "fix-renames", "relink", "interactive", "confirm-each", "only-duplicates-of-kept",
+    "watch", "tree", "group-dirs", "output", "summary-json", "report", "map-report",
+    "client", "recheck-after", "skip-label", "skip-category", "only-label", "pre-delete-hook",
+    "filter-hook", "owner", "only-owned", "one-file-system", "max-depth", "skip-hardlinked",
+    "skip-errors", "ignore-case", "normalize-unicode", "torrents-dir", "transmission-config",
+];
+
+/// A directory on another machine, as `sftp://[user@]host[:port]/path` gives it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteDir {
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+    /// Absolute on the remote machine.
+    pub path: PathBuf,
+}
+
+impl RemoteDir {
+    /// The remote directory `input` names if it is an `sftp://` URL; `None` for a local path. The
+    /// user defaults to the local one and the port to 22.
+    pub fn parse(input: &Path) -> Option<Result<Self, CliError>> {
+        let url = input.to_str()?.strip_prefix("sftp://")?;
+        Some(Self::parse_url(url).ok_or_else(|| CliError::new(ErrorCode::DirNotFound, format!(
+            "Invalid remote directory {}: expected sftp://[user@]host[:port]/path",
+            input.display())).with_path(input)))
+    }
+
+    fn parse_url(url: &str) -> Option<Self> {
+        let (authority, path) = url.split_once('/')?;
+        let (user, address) = match authority.rsplit_once('@') {
+            Some((user, address)) => (user.to_string(), address),
+            None => (std::env::var("USER").or_else(|_| std::env::var("LOGNAME")).ok()?, authority),
+        };
+        // An IPv6 address is bracketed, its colons being no port
+        let (host, port) = match address.strip_prefix('[') {
+            Some(rest) => {
+                let (host, port) = rest.split_once(']')?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match address.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (address, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port.parse().ok()?,
+            None => 22,
+        };
+        let path = Path::new("/").join(path.trim_end_matches('/'));
+        if user.is_empty() || host.is_empty()
+            || !path.components().all(|e| matches!(e, Component::RootDir | Component::Normal(_))) {
+            return None;
+        }
+        Some(Self { user, host: host.to_string(), port, path })
+    }
+}
+
+impl fmt::Display for RemoteDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let host = match self.host.contains(':') {
+            true => format!("[{}]", self.host),
+            false => self.host.clone(),
+        };
+        match self.port {
+            22 => write!(f, "sftp://{}@{}{}", self.user, host, self.path.display()),
+            port => write!(f, "sftp://{}@{}:{}{}", self.user, host, port, self.path.display()),
+        }
+    }
+}
+
+/// The remote directory diff or clean is given, if it is one; there can only be the one.
+pub fn remote_dir(matches: &ArgMatches) -> anyhow::Result<Option<RemoteDir>> {
+    let mut dirs = matches.get_many::<PathBuf>("dir").into_iter().flatten().collect::<Vec<_>>();
+    if matches.contains_id("manifest") {
+        dirs.extend(matches.get_one::<PathBuf>("file"));
+    }
+    let Some(remote) = dirs.iter().find_map(|e| RemoteDir::parse(e)) else { return Ok(None) };
+    if dirs.len() > 1 {
+        build_cli().error(clap::error::ErrorKind::ArgumentConflict,
+            "a remote directory is cleaned or compared on its own, not with other directories")
+            .exit();
+    }
+    Ok(Some(remote?))
+}
+
+/// Refuses the [`LOCAL_ONLY`] options given, and `--symlinks follow`, before connecting.
+fn check_options(matches: &ArgMatches) {
+    let given = |id: &str| matches.ids().any(|e| e == id)
+        && matches.value_source(id) != Some(ValueSource::DefaultValue);
+    let option = LOCAL_ONLY.into_iter().find(|e| given(e)).map(|e| format!("--{}", e))
+        .or_else(|| (matches.get_one::<String>("symlinks").is_some_and(|e| e == "follow"))
+            .then(|| "--symlinks follow".to_string()));
+    if let Some(option) = option {
+        build_cli().error(clap::error::ErrorKind::ArgumentConflict, format!(
+            "{} is not supported for a remote directory", option)).exit();
+    }
+}
+
+/// An open SFTP session to the host of a remote directory.
+pub struct Remote {
+    pub dir: RemoteDir,
+    sftp: Sftp,
+    // Dropped after the SFTP channel it carries
+    _session: Session,
+}
+
+impl Remote {
+    /// Connects within `--timeout`, checks the host key against `~/.ssh/known_hosts` and logs in
+    /// with the agent's keys or `~/.ssh/id_ed25519`, `id_ecdsa` or `id_rsa`, in that order.
+    pub fn connect(dir: RemoteDir) -> Result<Self, CliError> {
+        let failed = |message: String| CliError::new(ErrorCode::RemoteFailed, format!(
+            "Failed to connect to {}: {}", dir, message)).with_path(dir.to_string());
+        let address = (dir.host.as_str(), dir.port).to_socket_addrs()
+            .map_err(|e| failed(e.to_string()))?
+            .next().ok_or_else(|| failed("the host name has no address".to_string()))?;
+        let stream = TcpStream::connect_timeout(&address, timeout())
+            .map_err(|e| failed(e.to_string()))?;
+        let mut session = Session::new().map_err(|e| failed(e.message().to_string()))?;
+        session.set_timeout(timeout().as_millis().min(u32::MAX as u128) as u32);
+        session.set_tcp_stream(stream);
+        session.handshake().map_err(|e| failed(e.message().to_string()))?;
+        check_host_key(&session, &dir).map_err(failed)?;
+        authenticate(&session, &dir).map_err(failed)?;
+        let sftp = session.sftp().map_err(|e| failed(e.message().to_string()))?;
+        verbose!(1, "Connected to {}", dir);
+        Ok(Self { dir, sftp, _session: session })
+    }
+
+    /// Fails for an operation on `path` that failed with `err`: the directory not being there,
+    /// the connection being lost or the server refusing it.
+    fn failed(&self, err: ssh2::Error, path: &Path, doing: &str) -> CliError {
+        let code = if lost(&err) {
+            ErrorCode::RemoteFailed
+        } else if path == self.dir.path {
+            ErrorCode::DirNotFound
+        } else {
+            ErrorCode::ScanFailed
+        };
+        CliError::new(code, format!("Failed to {} {}:{}: {}", doing, self.dir.host,
+                                    path.display(), err.message())).with_path(path)
+    }
+
+    /// The patterns of the [`IGNORE_FILE`] at the root of the directory, if there is one.
+    fn ignore_file(&self) -> Result<Option<String>, CliError> {
+        let path = self.dir.path.join(IGNORE_FILE);
+        let failed = |e: io::Error| CliError::new(ErrorCode::RemoteFailed, format!(
+            "Failed to read {}:{}: {}", self.dir.host, path.display(), e)).with_path(&path);
+        let mut file = match self.sftp.open(&path) {
+            Ok(file) => file,
+            Err(err) => match io::Error::from(err) {
+                err if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                err => return Err(failed(err)),
+            },
+        };
+        let mut text = String::new();
+        file.read_to_string(&mut text).map_err(failed)?;
+        Ok(Some(text))
+    }
+
+    /// Lists the directory as [`scan_dir`](torrent_cleaner::scan::scan_dir) walks a local one,
+    /// one request per directory: each listing carries the metadata of its entries, so none is
+    /// asked for file by file.
+    fn list(&self, expected: &impl ExpectedSet, options: &ScanOptions, progress: &ProgressBar)
+            -> Result<Listed, CliError> {
+        let root = &self.dir.path;
+        let mut listed = Listed::default();
+        let mut kept = HashMap::<PathBuf, (PathBuf, DirKeptReason)>::new();
+        let mut keep = |path: &Path, reason| {
+            for parent in path.ancestors().skip(1) {
+                if parent == root || kept.contains_key(parent) {
+                    break;
+                }
+                kept.insert(parent.to_owned(), (path.to_owned(), reason));
+            }
+        };
+        let skip = |listed: &mut Listed, path: &Path, reason, size| {
+            listed.scan.skipped.push(Skipped { path: path.to_owned(), reason, size });
+        };
+        let mut removable_dirs = Vec::new();
+        let mut dir_stamps = HashMap::new();
+        let mut pending = vec![root.clone()];
+        while let Some(dir) = pending.pop() {
+            let rel = dir.strip_prefix(root).unwrap_or(&dir);
+            progress.set_message(truncate_message(rel.display().to_string()));
+            let entries = self.sftp.readdir(&dir).map_err(|e| self.failed(e, &dir, "list"))?;
+            progress.inc(entries.len() as u64);
+            for (path, stat) in entries {
+                let rel = path.strip_prefix(root).unwrap_or(&path);
+                let (is_dir, link) = (stat.is_dir(), stat.file_type().is_symlink());
+                let size = if stat.is_file() { stat.size.unwrap_or(0) } else { 0 };
+                if rel == Path::new(STAGE_DIR) {
+                    continue;
+                }
+                if !options.include_surface
+                    && !rel.components().next().is_some_and(|e| expected.is_surface(e.as_os_str())) {
+                    skip(&mut listed, &path, SkipReason::OutOfScope, size);
+                    // Counted all the same, as a local scan does
+                    if is_dir && !link {
+                        pending.push(path);
+                    }
+                    continue;
+                }
+                if link && options.symlinks == Symlinks::Keep {
+                    keep(&path, DirKeptReason::Skipped(SkipReason::Symlink));
+                    skip(&mut listed, &path, SkipReason::Symlink, 0);
+                    continue;
+                }
+                if is_dir && options.prune.matches(rel) {
+                    keep(&path, DirKeptReason::Skipped(SkipReason::Pruned));
+                    skip(&mut listed, &path, SkipReason::Pruned, 0);
+                    continue;
+                }
+                if is_dir && !options.include_snapshots && snapshot_area(rel) == Some(rel)
+                    && !expected.contains_dir(rel) {
+                    keep(&path, DirKeptReason::Skipped(SkipReason::Snapshot));
+                    skip(&mut listed, &path, SkipReason::Snapshot, 0);
+                    continue;
+                }
+                if let Some(expected_size) = expected.file_size(rel) {
+                    // A link at the torrent's path stands for the file it points to
+                    let found = match link {
+                        true => self.sftp.stat(&path).ok().and_then(|e| e.size).unwrap_or(0),
+                        false => size,
+                    };
+                    if !is_dir {
+                        listed.found.insert(rel.to_owned(), found);
+                    }
+                    if !(options.remove_mismatched && stat.is_file() && found != expected_size) {
+                        keep(&path, DirKeptReason::TorrentEntry);
+                        if is_dir {
+                            pending.push(path);
+                        }
+                        continue;
+                    }
+                }
+                let protected = match is_dir && expected.contains_dir(rel) {
+                    true => None,
+                    false => options.protection(rel, is_dir),
+                };
+                if protected == Some(SkipReason::NotJunk) && is_dir {
+                    keep(&path, DirKeptReason::Skipped(SkipReason::NotJunk));
+                    pending.push(path);
+                    continue;
+                }
+                if let Some(reason) = protected {
+                    keep(&path, DirKeptReason::Skipped(reason));
+                    skip(&mut listed, &path, reason, size);
+                    continue;
+                }
+                if is_dir {
+                    match expected.contains_dir(rel) {
+                        true => keep(&path, DirKeptReason::TorrentEntry),
+                        false => removable_dirs.push(path.clone()),
+                    }
+                    dir_stamps.insert(path.clone(), Stamp { size: 0, modified: modified(&stat) });
+                    pending.push(path);
+                    continue;
+                }
+                let reason = match () {
+                    _ if !options.remove_incomplete && droppings::is_in_progress(rel, expected) => {
+                        Some(SkipReason::InProgress)
+                    }
+                    _ if !options.clean_droppings && droppings::is_orphaned(rel, expected) => {
+                        Some(SkipReason::Dropping)
+                    }
+                    _ => None,
+                };
+                if let Some(reason) = reason.filter(|_| stat.is_file()) {
+                    keep(&path, DirKeptReason::Skipped(reason));
+                    skip(&mut listed, &path, reason, size);
+                    continue;
+                }
+                if link {
+                    listed.links.insert(path.clone());
+                }
+                listed.scan.rm_size += size;
+                listed.scan.stamps.insert(path.clone(), Stamp { size, modified: modified(&stat) });
+                listed.scan.old_files.push(path);
+            }
+        }
+        let scan = &mut listed.scan;
+        if options.sorted {
+            options.order.sort_paths(&mut scan.old_files, &scan.stamps);
+        }
+        // As for a local scan: a directory neither kept nor holding an extra holds nothing but
+        // directories as empty as itself
+        let holding = scan.old_files.iter()
+            .flat_map(|e| e.ancestors().skip(1).take_while(|e| *e != root.as_path()))
+            .map(Path::to_path_buf)
+            .collect::<HashSet<PathBuf>>();
+        if options.include_empty_dir {
+            for path in removable_dirs {
+                match kept.remove(&path) {
+                    Some((blocker, reason)) => scan.kept_dirs.push(KeptDir {
+                        path, reason, blocker: Some(blocker), error: None,
+                    }),
+                    None if holding.contains(&path) => scan.emptied_dirs.push(path),
+                    None => scan.empty_dirs.push(path),
+                }
+            }
+        }
+        if options.sorted {
+            let stamp = |e: &PathBuf| dir_stamps.get(e).copied();
+            options.order.sort(&mut scan.empty_dirs, |e| e, stamp);
+            options.order.sort(&mut scan.emptied_dirs, |e| e, stamp);
+            SortOrder::default().sort(&mut scan.kept_dirs, |e| &e.path, |_| None);
+            SortOrder::default().sort(&mut scan.skipped, |e| &e.path, |_| None);
+        }
+        // No removal is a directory as a whole, without --max-depth
+        scan.filter(options, |_| false);
+        Ok(listed)
+    }
+
+    /// Removes the extras of `scan` deepest first and then its directories, children before
+    /// their parents, each file first checked against its stamp unless `revalidate` is off. The
+    /// files are checked a directory at a time, by listing it again, so that each costs the one
+    /// request removing it. The server refusing an entry leaves it in place and goes on; the
+    /// connection failing stops the run with the entries not removed, for a run of the same
+    /// command to remove them.
+    fn remove(&self, scan: &Scan, links: &HashSet<PathBuf>, revalidate: bool,
+              progress: &ProgressBar) -> Result<Removal, CliError> {
+        let mut removal = Removal::default();
+        let mut files = scan.old_files.clone();
+        sort_deepest_first(&mut files);
+        let mut listings = HashMap::<PathBuf, HashMap<PathBuf, FileStat>>::new();
+        for (i, file) in files.iter().enumerate() {
+            if interrupted() {
+                removal.interrupted = true;
+                return Ok(removal);
+            }
+            progress.inc(1);
+            let parent = file.parent().unwrap_or(&self.dir.path);
+            progress.set_message(truncate_message(format!("Removing {}",
+                file.strip_prefix(&self.dir.path).unwrap_or(file).display())));
+            if revalidate && !listings.contains_key(parent) {
+                let listing = self.sftp.readdir(parent);
+                let listing = match listing {
+                    Err(err) if lost(&err) => return Err(self.connection_lost(err, &removal, &files[i..], scan)),
+                    result => result.unwrap_or_default(),
+                };
+                listings.insert(parent.to_owned(), listing.into_iter().collect());
+            }
+            let now = listings.get(parent).and_then(|e| e.get(file));
+            let stamp = now.map(|e| Stamp { size: if e.is_file() { e.size.unwrap_or(0) } else { 0 },
+                                             modified: modified(e) });
+            // A link is what it points to changing no more than its own metadata
+            let same_kind = now.is_some_and(|e| e.file_type().is_symlink() == links.contains(file));
+            if revalidate && (!same_kind || stamp.as_ref() != scan.stamps.get(file)) {
+                removal.changed.push(file.clone());
+                continue;
+            }
+            match self.sftp.unlink(file) {
+                Err(err) if lost(&err) => {
+                    return Err(self.connection_lost(err, &removal, &files[i..], scan));
+                }
+                Err(err) => removal.failed.push((file.clone(), err.message().to_string())),
+                Ok(()) => {
+                    removal.size += scan.stamps.get(file).map_or(0, |e| e.size);
+                    removal.removed.insert(file.clone());
+                    verbose!(1, "Removed file: {}:{}", self.dir.host, file.display());
+                }
+            }
+        }
+        let cascade = scan.dir_cascade();
+        for (i, (dir, inside)) in cascade.iter().enumerate() {
+            if interrupted() {
+                removal.interrupted = true;
+                return Ok(removal);
+            }
+            progress.inc(1);
+            if let Some(blocker) = inside.iter().find(|e| !removal.removed.contains(**e)) {
+                removal.kept.push(KeptDir { path: dir.to_path_buf(),
+                                            reason: DirKeptReason::NotRemoved,
+                                            blocker: Some(blocker.to_path_buf()), error: None });
+                continue;
+            }
+            match self.sftp.rmdir(dir) {
+                Err(err) if lost(&err) => {
+                    let left = cascade[i..].iter().map(|(e, _)| e.to_path_buf()).collect::<Vec<_>>();
+                    return Err(self.connection_lost(err, &removal, &left, scan));
+                }
+                Err(err) => removal.kept.push(KeptDir {
+                    path: dir.to_path_buf(), reason: DirKeptReason::RemoveFailed, blocker: None,
+                    error: Some(err.message().to_string()),
+                }),
+                Ok(()) => {
+                    removal.removed.insert(dir.to_path_buf());
+                    verbose!(1, "Removed directory: {}:{}", self.dir.host, dir.display());
+                }
+            }
+        }
+        Ok(removal)
+    }
+
+    /// The error for the connection lost with the entries `left` still to remove, listing them
+    /// and the directories not removed yet.
+    fn connection_lost(&self, err: ssh2::Error, removal: &Removal, left: &[PathBuf], scan: &Scan)
+                       -> CliError {
+        let mut left = left.to_vec();
+        // The directories come after the files, and are left as well when a file is
+        let dirs = scan.empty_dirs.iter().chain(&scan.emptied_dirs)
+            .filter(|e| !removal.removed.contains(*e) && !left.contains(e))
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        left.extend(dirs);
+        let message = format!("Lost the connection to {} while removing {}, after {} of {} \
+            entries ({}); nothing else was touched, so running the same command again removes the \
+            rest", self.dir.host, left.first().map_or(String::new(), |e| e.display().to_string()),
+            removal.removed.len(), scan.removal_count(), err.message());
+        left.iter().fold(CliError::new(ErrorCode::RemoteFailed, message)
+            .with_path(self.dir.to_string()), |e, path| e.with_entry(path, "not removed"))
+    }
+}
+
+/// Whether `err` is the connection failing, or timing out, rather than the server refusing one
+/// operation.
+fn lost(err: &ssh2::Error) -> bool {
+    matches!(err.code(), ssh2::ErrorCode::Session(_))
+}
+
+fn modified(stat: &FileStat) -> Option<SystemTime> {
+    stat.mtime.map(|e| UNIX_EPOCH + Duration::from_secs(e))
+}
+
+/// Checks the key `session` was offered against the user's known hosts, without ever adding one:
+/// a host is trusted by connecting to it with `ssh` first.
+fn check_host_key(session: &Session, dir: &RemoteDir) -> Result<(), String> {
+    let (key, _) = session.host_key().ok_or("the server sent no host key")?;
+    let mut known = session.known_hosts().map_err(|e| e.message().to_string())?;
+    let file = directories::BaseDirs::new().map(|e| e.home_dir().join(".ssh/known_hosts"));
+    if let Some(file) = file.filter(|e| e.is_file()) {
+        known.read_file(&file, KnownHostFileKind::OpenSSH)
+            .map_err(|e| format!("cannot read {}: {}", file.display(), e.message()))?;
+    }
+    match known.check_port(&dir.host, dir.port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => Err(format!("{} is not a known host; connect to it once with \
+            ssh to check and record its key", dir.host)),
+        CheckResult::Mismatch => Err(format!("the host key of {} differs from the one in \
+            ~/.ssh/known_hosts, which may mean someone is in between", dir.host)),
+        CheckResult::Failure => Err("the host key could not be checked".to_string()),
+    }
+}
+
+/// Logs in with a key of the SSH agent, or else with the first default key that is accepted;
+/// keys with a passphrase are only used through the agent.
+fn authenticate(session: &Session, dir: &RemoteDir) -> Result<(), String> {
+    if session.userauth_agent(&dir.user).is_ok() {
+        return Ok(());
+    }
+    let ssh = directories::BaseDirs::new().map(|e| e.home_dir().join(".ssh"));
+    for key in ["id_ed25519", "id_ecdsa", "id_rsa"].iter().filter_map(|e| Some(ssh.as_ref()?.join(e))) {
+        if key.is_file() && session.userauth_pubkey_file(&dir.user, None, &key, None).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(format!("{} was not let in with the keys of the SSH agent or the default ones in ~/.ssh",
+                dir.user))
+}
+
+/// What listing a remote directory found.
+#[derive(Default)]
+struct Listed {
+    scan: Scan,
+    /// The files of the torrent found, by their path in the directory, with their size.
+    found: HashMap<PathBuf, u64>,
+    /// The extras that are symbolic links, removed as links.
+    links: HashSet<PathBuf>,
+}
+
+impl Listed {
+    /// The files of `expected` not found, by path with their total size, and those of another
+    /// size, in the `--sort` order.
+    fn changes(&self, matches: &ArgMatches, root: &Path, expected: &impl ExpectedSet)
+               -> (Vec<PathBuf>, u64, Vec<Mismatch>) {
+        let (mut missing, mut missing_size, mut mismatched) = (Vec::new(), 0, Vec::new());
+        let mut sizes = HashMap::new();
+        for (path, size) in expected.files() {
+            match self.found.get(path) {
+                None => {
+                    missing.push(root.join(path));
+                    missing_size += size;
+                    sizes.insert(root.join(path), size);
+                }
+                Some(&found) if found != size => mismatched.push(Mismatch {
+                    path: root.join(path), size: found, expected: size,
+                }),
+                Some(_) => {}
+            }
+        }
+        sort_paths(&mut missing);
+        mismatched.sort_by(|a, b| a.path.cmp(&b.path));
+        let order = sort_order(matches);
+        order.sort(&mut missing, |e| e, |e| sizes.get(e).map(|&size| Stamp { size, modified: None }));
+        order.sort(&mut mismatched, |e| &e.path, |e| Some(Stamp { size: e.size, modified: None }));
+        (missing, missing_size, mismatched)
+    }
+
+    /// The files of `expected` found with their size, and their total size.
+    fn matching(&self, expected: &impl ExpectedSet) -> (usize, u64) {
+        expected.files().filter(|(path, size)| self.found.get(*path) == Some(size))
+            .fold((0, 0), |(count, total), (_, size)| (count + 1, total + size))
+    }
+
+    fn mark(&self, entry: &Path) -> &'static str {
+        match self.links.contains(entry) {
+            true => "-l",
+            false => "-f",
+        }
+    }
+
+    /// Prints the torrent's totals next to what was found, as for a local directory.
+    fn print_reconciliation(&self, expected: &impl ExpectedSet) {
+        let (matching, matching_size) = self.matching(expected);
+        let present = expected.files().filter(|(path, _)| self.found.contains_key(*path)).count();
+        let extras = self.scan.old_files.len();
+        say!("Torrent expects:  {} in {} files", BinaryBytes(expected.total_size()),
+             expected.file_count());
+        say!("Found matching:   {} in {} files", BinaryBytes(matching_size), matching);
+        say!("Flagged as extra: {} in {} entries", BinaryBytes(self.scan.rm_size),
+             self.scan.removal_count());
+        say!("Delete ratio:     {:.3} ({} of {} files found)", delete_ratio(extras, present),
+             extras, extras + present);
+    }
+
+    /// Lists the extras, then the empty directories and the files of the torrent missing or of
+    /// another size; returns `false` without printing if there is nothing to report.
+    fn print_changes(&self, missing: &[PathBuf], missing_size: u64, mismatched: &[Mismatch],
+                     listing: &Listing) -> bool {
+        let scan = &self.scan;
+        if scan.removal_count() == 0 && missing.is_empty() && mismatched.is_empty()
+            && !scan.skipped.iter().any(|e| matches!(e.reason, SkipReason::Dropping
+                                                     | SkipReason::InProgress | SkipReason::Junk)) {
+            return false;
+        }
+        say!("File changes:");
+        self.print_extras(listing);
+        print_empty_dirs(scan, listing);
+        for entry in limited(missing, listing) {
+            say!("{}   {}", Green.paint("+"), path_colored(entry, listing));
+        }
+        print_more(missing.len(), listing);
+        for entry in limited(mismatched, listing) {
+            say!("{}   {} (got {}, expected {})", Yellow.paint("~"),
+                 path_colored(&entry.path, listing), BinaryBytes(entry.size),
+                 BinaryBytes(entry.expected));
+        }
+        print_more(mismatched.len(), listing);
+        print_kept(scan, listing);
+        say!();
+        say!("New files: {} ({})", Green.paint(BinaryBytes(missing_size)), missing.len());
+        if !mismatched.is_empty() {
+            let delta = mismatched.iter().map(Mismatch::delta).sum::<i64>();
+            say!("Wrong size: {} files, {}{} against the torrent", mismatched.len(),
+                 if delta < 0 { "-" } else { "+" }, Yellow.paint(BinaryBytes(delta.unsigned_abs())));
+        }
+        say!("Remove entries: {} ({})", Red.paint(BinaryBytes(scan.rm_size)), scan.removal_count());
+        if !scan.old_files.is_empty() {
+            say!("By type: {}", count_kinds(&scan.old_files));
+        }
+        true
+    }
+
+    fn print_extras(&self, listing: &Listing) {
+        for entry in limited(&self.scan.old_files, listing) {
+            say!("{}  {}{}", Red.paint(self.mark(entry)), path_colored(entry, listing),
+                 tags(entry, listing));
+        }
+        print_more(self.scan.old_files.len(), listing);
+    }
+
+    /// Prints the `--top` largest extras and, with `--summary`, where they are and of what type.
+    fn print_largest(&self, matches: &ArgMatches, root: &Path, listing: &Listing) {
+        let top = top(matches);
+        if top > 0 && !self.scan.old_files.is_empty() {
+            let mut largest = self.scan.old_files.iter()
+                .filter_map(|e| Some((e, *self.scan.stamps.get(e)?)))
+                .collect::<Vec<_>>();
+            largest.sort_by_key(|(_, stamp)| std::cmp::Reverse(stamp.size));
+            largest.truncate(top);
+            print_top(&largest, listing);
+        }
+        if matches.get_flag("summary") {
+            print_breakdown(root, &self.scan, top, listing);
+        }
+    }
+
+    /// Says how a directory with nothing to remove compares to the torrent; returns whether all
+    /// of its files are there with the expected size.
+    fn print_nothing_to_remove(&self, expected: &impl ExpectedSet) -> bool {
+        let (matching, matching_size) = self.matching(expected);
+        let total = expected.file_count();
+        if matching == total {
+            say!("{}", Green.paint(format!("Directory is clean: all {} files match ({})",
+                                           HumanCount(total as u64), BinaryBytes(matching_size))));
+            return true;
+        }
+        let missing = expected.files().filter(|(path, _)| !self.found.contains_key(*path)).count();
+        say!("{}", Yellow.paint(format!("Nothing to remove, but the directory does not match the \
+            torrent: {} of {} files missing, {} with another size.", HumanCount(missing as u64),
+            HumanCount(total as u64), HumanCount((total - matching - missing) as u64))));
+        false
+    }
+}
+
+/// What [`Remote::remove`] did.
+#[derive(Default)]
+struct Removal {
+    removed: HashSet<PathBuf>,
+    /// The size of the files removed.
+    size: u64,
+    changed: Vec<PathBuf>,
+    failed: Vec<(PathBuf, String)>,
+    kept: Vec<KeptDir>,
+    interrupted: bool,
+}
+
+/// Runs `command`, `diff` or `clean`, against the remote directory `dir`.
+pub fn run(matches: &ArgMatches, command: &str, dir: RemoteDir) -> anyhow::Result<ExitCode> {
+    check_options(matches);
+    let clean = command == "clean";
+    let (expected, mut record, sources) = load_expected(matches, command, &dir.path)?;
+    record.dir = PathBuf::from(dir.to_string());
+    check_torrent(matches, &expected, clean)?;
+    let names = sources.names.iter().filter(|e| !e.is_empty()).collect::<Vec<_>>();
+    let named = dir.path.file_name().is_some_and(|name| names.iter().any(|e| name == e.as_str()));
+    if clean && matches.get_flag("surface") && !matches.get_flag("force")
+        && !names.is_empty() && !named {
+        return Err(anyhow::anyhow!("{} is not named after the torrent ({}); add --force to clean \
+            it with --surface", dir, names[0]));
+    }
+    let remote = Remote::connect(dir)?;
+    let root = remote.dir.path.clone();
+    let mut options = scan_options(matches)?;
+    if std::mem::take(&mut options.ignore_file) {
+        if let Some(text) = remote.ignore_file()? {
+            options.exclude = std::mem::take(&mut options.exclude).with_ignore_file(&text)?;
+        }
+    }
+
+    let spinner = ProgressMode::from_matches(matches).bar(ProgressBar::new_spinner());
+    spinner.set_style(ProgressStyle::default_spinner()
+        .tick_chars("|/-\\")
+        .template("{spinner:.green} [{elapsed_precise}] Listing {prefix}... {pos} entries\n{msg}")?);
+    spinner.set_prefix(remote.dir.host.clone());
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let listed = remote.list(&expected, &options, &spinner);
+    spinner.finish_and_clear();
+    let mut listed = listed?;
+    let (missing, missing_size, mismatched) = listed.changes(matches, &root, &expected);
+    record.extras = listed.scan.removal_count();
+    record.extras_size = listed.scan.rm_size;
+    record.missing = missing.len();
+    let listing = torrent_listing(matches, &root, expected.is_flat());
+
+    if !clean {
+        listed.print_reconciliation(&expected);
+        say!();
+        let mut consistent = false;
+        if listed.print_changes(&missing, missing_size, &mismatched, &listing) {
+            listed.print_largest(matches, &root, &listing);
+            if listed.scan.removal_count() == 0 {
+                say!();
+                consistent = listed.print_nothing_to_remove(&expected);
+            }
+        } else {
+            consistent = listed.print_nothing_to_remove(&expected);
+        }
+        print_kept_dirs(&listed.scan.kept_dirs, &listing);
+        save_record(matches, &record);
+        print_skipped(matches, &listed.scan);
+        say!("Operation completed successfully.");
+        let scan = &listed.scan;
+        return Ok(ExitCode::from(match matches.get_flag("check") {
+            true if scan.removal_count() > 0 => CHECK_EXTRAS,
+            true if !consistent => CHECK_INCOMPLETE,
+            true if scan.skipped(SkipReason::OutOfScope).next().is_some() => CHECK_OUT_OF_SCOPE,
+            _ => 0,
+        }));
+    }
+
+    let scan = &listed.scan;
+    let (files, dirs) = (scan.old_files.len(), scan.empty_dirs.len() + scan.emptied_dirs.len());
+    if files + dirs == 0 {
+        listed.print_nothing_to_remove(&expected);
+        print_kept(scan, &listing);
+        save_record(matches, &record);
+        print_skipped(matches, scan);
+        say!("Operation completed successfully.");
+        return Ok(ExitCode::SUCCESS);
+    }
+    say!("Existed files found:");
+    listed.print_extras(&listing);
+    print_empty_dirs(scan, &listing);
+    print_kept(scan, &listing);
+    say!();
+    say!("Remove files: {} ({})", Red.paint(BinaryBytes(scan.rm_size)), files);
+    if dirs > 0 {
+        say!("Remove directories: {}", Red.paint(dirs));
+    }
+    if files > 0 {
+        say!("By type: {}", count_kinds(&scan.old_files));
+    }
+    say!();
+    listed.print_reconciliation(&expected);
+    listed.print_largest(matches, &root, &listing);
+    let present = expected.files().filter(|(path, _)| listed.found.contains_key(*path)).count();
+    check_threshold(matches, scan, present)?;
+    if !matches.get_flag("no-confirm") {
+        let question = format!("Delete {}?", asked_entries(files, dirs,
+            listing.unlisted(files) + unlisted_dirs(scan, &listing)));
+        if !confirm(matches, &question, true) {
+            return Ok(ExitCode::from(EXIT_DECLINED));
+        }
+    }
+    start_removing();
+
+    let progress = ProgressMode::from_matches(matches).bar(ProgressBar::new((files + dirs) as u64));
+    progress.set_style(ProgressStyle::default_bar()
+        .template("{prefix} [{wide_bar:.cyan/blue}] {pos}/{len} ({percent}%, eta {eta})\n{msg}")?);
+    progress.set_prefix("Processing");
+    let result = remote.remove(scan, &listed.links, !matches.get_flag("no-revalidate"), &progress);
+    let removal = match result {
+        Ok(removal) => removal,
+        Err(err) => {
+            progress.finish_and_clear();
+            record.removed = scan.removal_count() - err.entries.len();
+            record.failures = err.entries.len();
+            save_record(matches, &record);
+            return Err(err.into());
+        }
+    };
+    record.removed = removal.removed.len();
+    record.removed_size = removal.size;
+    record.failures = removal.failed.len();
+    save_record(matches, &record);
+    if removal.interrupted {
+        progress.finish_and_clear();
+        return Err(CliError::new(ErrorCode::Interrupted, format!(
+            "Interrupted: {} entries removed ({}), {} left; run clean again for the rest",
+            removal.removed.len(), BinaryBytes(removal.size),
+            scan.removal_count() - removal.removed.len())).into());
+    }
+    progress.set_prefix("Done");
+    finish(&progress, format!("{} entries removed.", removal.removed.len()));
+    let mut kept_dirs = removal.kept;
+    kept_dirs.extend(scan.kept_dirs.iter().cloned());
+    print_kept_dirs(&kept_dirs, &listing);
+    listed.scan.skipped.extend(removal.changed.iter().map(|path| Skipped {
+        path: path.clone(), reason: SkipReason::Changed,
+        size: listed.scan.stamps.get(path).map_or(0, |e| e.size),
+    }));
+    print_skipped(matches, &listed.scan);
+    let paths = log_paths(matches, &root);
+    let failed = FileRemoval { changed: removal.changed, failed: removal.failed };
+    if let Some(err) = failed.error(&paths) {
+        say!("Failed to remove ({}):", failed.failed.len());
+        for (path, error) in limited(&failed.failed, &listing) {
+            say!("    {}  {}", path_colored(path, &listing), error);
+        }
+        print_more(failed.failed.len(), &listing);
+        return Err(err.into());
+    }
+    if let Some(err) = kept_dirs.iter().find(|e| e.reason == DirKeptReason::RemoveFailed) {
+        return Err(CliError::new(ErrorCode::RemoveFailed, format!("Failed to remove directory: \
+            {}", paths.render(&err.path))).with_path(&err.path)
+            .with_entry(&err.path, err.error.as_deref().unwrap_or_default()).into());
+    }
+    say!("Operation completed successfully.");
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,249 @@
+use crate::error::{CliError, ErrorCode};
+use crate::interrupt::interrupted;
+use crate::journal::Journal;
+use crate::manifest::BackupManifest;
+use crate::output::logging;
+use crate::paths::PathRenderer;
+use crate::scan::{snapshot_area, sort_deepest_first, DirKeptReason, KeptDir, Scan};
+use crate::{truncate_message, verbose};
+use indicatif::ProgressBar;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// How [`remove_files`] and [`remove_empty_dirs`] get rid of entries.
+#[derive(Clone, Copy, Default)]
+pub struct RemoveOptions {
+    /// Clear the read-only attribute of a file Windows refuses to remove and try once more.
+    pub force: bool,
+    /// Move entries to the system trash, where they can be restored from, instead of deleting
+    /// them. Where there is no trash the entry fails rather than being deleted.
+    pub trash: bool,
+}
+
+impl RemoveOptions {
+    /// What happened to an entry that is gone, for the log.
+    fn done(self) -> &'static str {
+        if self.trash { "Trashed" } else { "Removed" }
+    }
+}
+
+/// What [`remove_files`] did besides removing files.
+pub struct FileRemoval {
+    /// Files left alone because they changed since the scan.
+    pub changed: Vec<PathBuf>,
+    /// Files that could not be removed, with the error.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl FileRemoval {
+    /// The files that could not be removed, as one error.
+    pub fn error(&self, paths: &PathRenderer) -> Option<CliError> {
+        let (first, _) = self.failed.first()?;
+        let message = match self.failed.len() {
+            1 => format!("Failed to remove file: {}", paths.render(first)),
+            n => format!("Failed to remove {} files", n),
+        };
+        Some(self.failed.iter().fold(CliError::new(ErrorCode::RemoveFailed, message)
+            .with_path(first), |err, (path, error)| err.with_entry(path, error.as_str())))
+    }
+}
+
+/// Removes `files` deepest first whatever order they are listed in, first recording each in
+/// `manifest` if one is given and each outcome in `journal`, going on past those that fail and
+/// stopping after Ctrl-C. With `revalidate`, files that changed since that scan are left alone.
+pub fn remove_files(files: &[PathBuf], revalidate: Option<&Scan>, paths: &PathRenderer,
+                    mut manifest: Option<&mut BackupManifest>, mut journal: Option<&mut Journal>,
+                    options: RemoveOptions, progress: &ProgressBar)
+                    -> anyhow::Result<FileRemoval> {
+    let mut removal = FileRemoval { changed: Vec::new(), failed: Vec::new() };
+    let mut files = files.to_vec();
+    sort_deepest_first(&mut files);
+    for entry in &files {
+        if interrupted() {
+            break;
+        }
+        progress.inc(1);
+        // A directory below `--max-depth` is an extra as a whole
+        let tree = fs::symlink_metadata(entry).is_ok_and(|e| e.is_dir());
+        let changed = match revalidate {
+            Some(scan) if scan.changed(entry) => vec![entry.clone()],
+            Some(scan) if tree => scan.changed_inside(entry),
+            _ => Vec::new(),
+        };
+        if !changed.is_empty() {
+            for path in &changed {
+                progress.suspend(|| verbose!(0, "Skipped: {} (changed since plan)",
+                                             paths.render(path)));
+            }
+            removal.changed.extend(changed);
+            continue;
+        }
+        if let Some(manifest) = manifest.as_deref_mut() {
+            manifest.record(entry, progress)?;
+        }
+        let removed = match tree {
+            true => remove_tree(entry, options),
+            false => remove_file(entry, options),
+        };
+        if let Err(e) = removed {
+            progress.suspend(|| verbose!(0, "Failed to remove file: {} ({})", paths.render(entry),
+                                         e));
+            if let Some(journal) = journal.as_deref_mut() {
+                journal.failed(entry, &e.to_string());
+            }
+            removal.failed.push((entry.clone(), e.to_string()));
+            continue;
+        }
+        if let Some(journal) = journal.as_deref_mut() {
+            journal.removed(entry);
+        }
+        if logging(1) {
+            progress.suspend(|| verbose!(1, "{} file: {}", options.done(), paths.render(entry)));
+        }
+        progress.set_message(truncate_message(
+            format!("{} file: {}", options.done(), paths.render(entry))));
+    }
+    Ok(removal)
+}
+
+/// Moves `path` to the system trash, failing where there is none, such as on many network file
+/// systems, rather than deleting it.
+fn trash_entry(path: &Path) -> io::Result<()> {
+    trash::delete(path).map_err(|e| io::Error::other(format!("cannot be moved to the trash, so \
+        it was left in place ({}); run without --trash to delete it for good", e)))
+}
+
+/// Refuses to remove anything from a snapshot or recycle area, whose copies are read-only and
+/// whose recycled files are only there to be restored, whatever matched them.
+fn check_snapshot(path: &Path) -> io::Result<()> {
+    match snapshot_area(path) {
+        Some(area) => Err(io::Error::other(format!("inside the snapshot or recycle area {}, from \
+            which nothing is removed", area.display()))),
+        None => Ok(()),
+    }
+}
+
+fn remove_file(file: &Path, options: RemoveOptions) -> io::Result<()> {
+    check_snapshot(file)?;
+    if options.trash {
+        return trash_entry(file);
+    }
+    let result = fs::remove_file(file);
+    #[cfg(windows)]
+    if options.force
+        && result.as_ref().is_err_and(|e| e.kind() == io::ErrorKind::PermissionDenied) {
+        let mut permissions = fs::symlink_metadata(file)?.permissions();
+        permissions.set_readonly(false);
+        fs::set_permissions(file, permissions)?;
+        return fs::remove_file(file);
+    }
+    result
+}
+
+/// Removes `dir`, a directory below `--max-depth` removed as a whole, from the bottom up: each
+/// file and link inside it, then each directory once empty, so that whatever shows up in it
+/// meanwhile stops the removal rather than going with it. The trash takes it whole.
+fn remove_tree(dir: &Path, options: RemoveOptions) -> io::Result<()> {
+    check_snapshot(dir)?;
+    if options.trash {
+        return trash_entry(dir);
+    }
+    for entry in WalkDir::new(dir).contents_first(true) {
+        let entry = entry.map_err(io::Error::from)?;
+        match entry.file_type().is_dir() {
+            true => fs::remove_dir(entry.path())?,
+            false => remove_file(entry.path(), options)?,
+        }
+    }
+    Ok(())
+}
+
+/// What [`remove_empty_dirs`] did.
+pub struct DirRemoval {
+    pub removed: usize,
+    /// The planned directories still there, and why.
+    pub kept: Vec<KeptDir>,
+}
+
+impl DirRemoval {
+    /// The directories that could not be removed, as one error.
+    pub fn error(&self, paths: &PathRenderer) -> Option<CliError> {
+        let failed = self.kept.iter().filter(|e| e.reason == DirKeptReason::RemoveFailed)
+            .collect::<Vec<&KeptDir>>();
+        let first = failed.first()?;
+        let message = match failed.len() {
+            1 => format!("Failed to remove directory: {}", paths.render(&first.path)),
+            n => format!("Failed to remove {} directories", n),
+        };
+        Some(failed.iter().fold(CliError::new(ErrorCode::RemoveFailed, message)
+            .with_path(&first.path), |err, e| err.with_entry(&e.path,
+                e.error.as_deref().unwrap_or_default())))
+    }
+}
+
+/// Removes the planned directories in [`Scan::dir_cascade`] order, each once the removals it
+/// depends on are done. One still holding a kept entry or something added since the scan, or
+/// failing to be removed, is left alone and returned with the reason. Each removal is recorded in
+/// `journal` if one is given.
+pub fn remove_empty_dirs(scan: &Scan, paths: &PathRenderer, mut journal: Option<&mut Journal>,
+                         options: RemoveOptions, progress: &ProgressBar) -> DirRemoval {
+    let mut removal = DirRemoval { removed: 0, kept: Vec::new() };
+    for (dir, after) in scan.dir_cascade() {
+        if interrupted() {
+            break;
+        }
+        let kept_dir = |reason, blocker: Option<&Path>, error| KeptDir {
+            path: dir.to_path_buf(), reason, blocker: blocker.map(Path::to_path_buf), error,
+        };
+        if let Some(blocker) = after.iter().find(|e| fs::symlink_metadata(e).is_ok()) {
+            // A directory kept by a kept child is kept for the same reason
+            let reason = removal.kept.iter().find(|e| e.path == *blocker)
+                .map_or(DirKeptReason::NotRemoved, |e| e.reason);
+            removal.kept.push(kept_dir(reason, Some(blocker), None));
+            continue;
+        }
+        // The trash takes a directory with whatever is in it
+        let removed = check_snapshot(dir).and_then(|()| match options.trash {
+            true => match fs::read_dir(dir).map(|mut e| e.next().is_some()) {
+                Ok(true) => Err(io::Error::from(io::ErrorKind::DirectoryNotEmpty)),
+                Ok(false) => trash_entry(dir),
+                Err(e) => Err(e),
+            },
+            false => fs::remove_dir(dir),
+        });
+        match removed {
+            Ok(()) => {
+                if let Some(journal) = journal.as_deref_mut() {
+                    journal.removed(dir);
+                }
+                if logging(1) {
+                    progress.suspend(|| verbose!(1, "{} directory: {}", options.done(),
+                                                 paths.render(dir)));
+                }
+                removal.removed += 1;
+            }
+            Err(e) if e.kind() == io::ErrorKind::DirectoryNotEmpty => {
+                progress.suspend(|| verbose!(0, "Skipped: {} (no longer empty)",
+                                             paths.render(dir)));
+                let added = fs::read_dir(dir).ok()
+                    .and_then(|mut e| e.find_map(|e| e.ok()).map(|e| e.path()));
+                removal.kept.push(kept_dir(DirKeptReason::NotEmpty, added.as_deref(), None));
+                continue;
+            }
+            Err(e) => {
+                progress.suspend(|| verbose!(0, "Failed to remove directory: {} ({})",
+                                              paths.render(dir), e));
+                if let Some(journal) = journal.as_deref_mut() {
+                    journal.failed(dir, &e.to_string());
+                }
+                removal.kept.push(kept_dir(DirKeptReason::RemoveFailed, None, Some(e.to_string())));
+                continue;
+            }
+        }
+        progress.set_message(truncate_message(
+            format!("{} directory: {}", options.done(), paths.render(dir))));
+    }
+    removal
+}
@@ -0,0 +1,243 @@
+use crate::scan::{is_padding, plain_path, ExpectedSet, Scan};
+use crate::torrent::is_v2_only;
+use librqbit_buffers::ByteBufOwned;
+use librqbit_core::torrent_metainfo::TorrentMetaV1Info;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// The v1 piece hashes of a torrent with where each of its files starts in the pieces, to tell
+/// a moved file by its content or `verify` the files.
+pub struct Pieces {
+    length: u64,
+    total: u64,
+    hashes: Vec<u8>,
+    /// Offsets by path relative to the content directory, padding files included.
+    offsets: HashMap<PathBuf, u64>,
+    /// In the order of the torrent, which is that of the pieces.
+    files: Vec<PieceFile>,
+}
+
+/// A file of the torrent where it lies in the pieces.
+pub struct PieceFile {
+    /// Relative to the content directory; as the torrent spells it if it could lead outside of
+    /// it, see [`crate::scan::unsafe_paths`].
+    pub path: PathBuf,
+    pub offset: u64,
+    pub length: u64,
+    /// A BEP 47 padding file, which is zeros whether or not it is on disk.
+    pub padding: bool,
+}
+
+impl Pieces {
+    /// `None` for pure v2 torrents, whose piece hashes are not in the info dict.
+    pub fn of(info: &TorrentMetaV1Info<ByteBufOwned>) -> Option<Self> {
+        if is_v2_only(info) || info.piece_length == 0 {
+            return None;
+        }
+        let mut offsets = HashMap::new();
+        let mut offset: u64 = 0;
+        let Some(files) = &info.files else {
+            // The one file of a single-file torrent is named after it
+            let name = PathBuf::from(info.name.as_ref()?.to_string());
+            let total = info.length?;
+            offsets.insert(name.clone(), 0);
+            let files = vec![PieceFile { path: name, offset: 0, length: total, padding: false }];
+            return Some(Self { length: info.piece_length as u64, total,
+                               hashes: info.pieces.as_ref().to_vec(), offsets, files });
+        };
+        let mut pieces = Vec::new();
+        for file in files {
+            let path = plain_path(file)
+                .unwrap_or_else(|| file.path.iter().map(|e| e.to_string()).collect());
+            offsets.insert(path.clone(), offset);
+            pieces.push(PieceFile { path, offset, length: file.length, padding: is_padding(file) });
+            offset = offset.checked_add(file.length)?;
+        }
+        Some(Self { length: info.piece_length as u64, total: offset,
+                    hashes: info.pieces.as_ref().to_vec(), offsets, files: pieces })
+    }
+
+    /// Follows `--map` renaming the top-level folders, as [`crate::scan::Expected`] does.
+    pub fn rename_top_level(&mut self, renames: &[(String, String)]) {
+        let rename = |path: &Path| {
+            let mut parts = path.iter();
+            parts.next()
+                .and_then(|first| renames.iter().find(|(old, _)| first == old.as_str()))
+                .map_or_else(|| path.to_path_buf(), |(_, new)| Path::new(new).join(parts.as_path()))
+        };
+        self.offsets = self.offsets.drain().map(|(path, offset)| (rename(&path), offset)).collect();
+        for file in &mut self.files {
+            file.path = rename(&file.path);
+        }
+    }
+
+    pub fn piece_length(&self) -> u64 {
+        self.length
+    }
+
+    /// The size of the torrent's payload, padding files included.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total.div_ceil(self.length)
+    }
+
+    pub fn files(&self) -> &[PieceFile] {
+        &self.files
+    }
+
+    /// The SHA-1 hash the torrent has for `piece`.
+    pub fn hash(&self, piece: u64) -> Option<&[u8]> {
+        self.hashes.get(piece as usize * 20..piece as usize * 20 + 20)
+    }
+
+    /// Whether the pieces lying wholly inside the torrent's file at `path` hash the same when
+    /// read from `candidate`. `None` when there is no such piece or the candidate cannot be read.
+    fn matches(&self, path: &Path, size: u64, candidate: &Path) -> Option<bool> {
+        let start = *self.offsets.get(path)?;
+        let end = start + size;
+        let mut file = File::open(candidate).ok()?;
+        let mut piece = start.div_ceil(self.length);
+        let mut checked = false;
+        loop {
+            let from = piece * self.length;
+            let to = (from + self.length).min(self.total);
+            if from >= end || to > end {
+                return checked.then_some(true);
+            }
+            let hash = self.hash(piece)?;
+            let mut buf = vec![0; (to - from) as usize];
+            file.seek(SeekFrom::Start(from - start)).ok()?;
+            file.read_exact(&mut buf).ok()?;
+            if crypto_hash::digest(crypto_hash::Algorithm::SHA1, &buf) != hash {
+                return Some(false);
+            }
+            checked = true;
+            piece += 1;
+        }
+    }
+}
+
+/// An extra that is probably a missing file of the torrent under another path.
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub size: u64,
+    /// Its pieces were hashed and match the torrent's.
+    pub verified: bool,
+}
+
+/// A missing file of the torrent that several extras could be, which is never fixed.
+pub struct Ambiguous {
+    pub to: PathBuf,
+    pub candidates: Vec<PathBuf>,
+}
+
+#[derive(Default)]
+pub struct Renames {
+    pub found: Vec<Rename>,
+    pub ambiguous: Vec<Ambiguous>,
+}
+
+/// Pairs each missing non-empty file of the torrent with the extras of the same size, keeping
+/// with `pieces` only those whose content matches where it can be checked. A pair is a probable
+/// rename when neither side has another candidate.
+pub fn find_renames(dir: &Path, expected: &impl ExpectedSet, scan: &Scan, pieces: Option<&Pieces>)
+                    -> Renames {
+    let mut extras = HashMap::<u64, Vec<&PathBuf>>::new();
+    for file in &scan.old_files {
+        let rel = file.strip_prefix(dir).unwrap_or(file);
+        if expected.file_size(rel).is_some() || file.is_symlink() || file.is_dir() {
+            continue;
+        }
+        let size = scan.stamps.get(file).map(|e| e.size)
+            .or_else(|| fs::metadata(file).ok().map(|e| e.len()));
+        if let Some(size) = size.filter(|e| *e > 0) {
+            extras.entry(size).or_default().push(file);
+        }
+    }
+    let mut missing = expected.files()
+        .filter(|(_, size)| extras.contains_key(size))
+        .map(|(path, size)| (path, expected.locate(dir, path), size))
+        .filter(|(_, disk, _)| fs::symlink_metadata(disk).is_err())
+        .collect::<Vec<(&Path, PathBuf, u64)>>();
+    missing.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let mut candidates = missing.iter().map(|(path, disk, size)| {
+        let mut found = Vec::new();
+        for &extra in &extras[size] {
+            match pieces.map(|e| e.matches(path, *size, extra)) {
+                Some(Some(false)) => continue,
+                Some(Some(true)) => found.push((extra, true)),
+                _ => found.push((extra, false)),
+            }
+        }
+        (disk, *size, found)
+    }).collect::<Vec<_>>();
+    candidates.retain(|(_, _, found)| !found.is_empty());
+
+    let mut claims = HashMap::<&Path, usize>::new();
+    for (_, _, found) in &candidates {
+        for (extra, _) in found {
+            *claims.entry(extra.as_path()).or_default() += 1;
+        }
+    }
+    let mut renames = Renames::default();
+    for (disk, size, found) in candidates {
+        match &found[..] {
+            [(extra, verified)] if claims[extra.as_path()] == 1 => renames.found.push(Rename {
+                from: extra.to_path_buf(), to: disk.clone(), size, verified: *verified,
+            }),
+            _ => renames.ambiguous.push(Ambiguous {
+                to: disk.clone(),
+                candidates: found.into_iter().map(|(e, _)| e.to_path_buf()).collect(),
+            }),
+        }
+    }
+    renames
+}
+
+/// Makes way for a file at `to`, refusing to replace anything that appeared there since the scan.
+fn prepare(to: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(to).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "destination exists"));
+    }
+    match to.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// Moves the file of `rename` back to where the torrent has it, creating the directories on the
+/// way. Nothing is replaced should something have appeared there since the scan.
+pub fn move_back(rename: &Rename) -> io::Result<()> {
+    prepare(&rename.to)?;
+    fs::rename(&rename.from, &rename.to)
+}
+
+/// Hardlinks the file of `rename` where the torrent has it, or with `take` moves it there. With
+/// `copy` the file is copied instead when that fails, e.g. across devices, the original then
+/// being removed with `take`.
+pub fn relink(rename: &Rename, take: bool, copy: bool) -> io::Result<()> {
+    prepare(&rename.to)?;
+    let linked = match take {
+        true => fs::rename(&rename.from, &rename.to),
+        false => fs::hard_link(&rename.from, &rename.to),
+    };
+    match linked {
+        Err(_) if copy => {
+            fs::copy(&rename.from, &rename.to).inspect_err(|_| {
+                let _ = fs::remove_file(&rename.to);
+            })?;
+            match take {
+                true => fs::remove_file(&rename.from),
+                false => Ok(()),
+            }
+        }
+        linked => linked,
+    }
+}
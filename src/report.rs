@@ -0,0 +1,450 @@
+use crate::dedup::Duplicate;
+use crate::scan::{ExpectedSet, Scan, SkipReason};
+use crate::summary::Summary;
+use crate::warnings::{warnings, Warning};
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::fs::{self, File, Metadata};
+use std::io::{BufWriter, Write as _};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MapStatus {
+    Present,
+    Missing,
+    SizeMismatch,
+}
+
+#[derive(Serialize)]
+pub struct MappedFile {
+    /// Path inside the torrent, `/`-separated.
+    pub path: String,
+    pub size: u64,
+    pub status: MapStatus,
+    /// Absolute path of the file on disk; `null` when missing.
+    pub disk_path: Option<String>,
+    pub disk_size: Option<u64>,
+    /// Hard link count of the file on disk, where the platform reports it.
+    pub links: Option<u64>,
+}
+
+/// Which on-disk file each of a torrent's files maps to, written by `diff --map-report`.
+#[derive(Serialize)]
+pub struct MapReport {
+    pub info_hash: String,
+    pub name: String,
+    pub dir: String,
+    pub files: Vec<MappedFile>,
+}
+
+#[cfg(unix)]
+fn link_count(meta: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.nlink())
+}
+
+#[cfg(not(unix))]
+fn link_count(_: &Metadata) -> Option<u64> {
+    None
+}
+
+impl MapReport {
+    pub fn new(info_hash: String, name: String, dir: &Path, expected: &impl ExpectedSet) -> Self {
+        let mut files = expected.files()
+            .map(|(path, size)| {
+                let disk = expected.locate(dir, path);
+                let meta = fs::metadata(&disk).ok().filter(|m| m.is_file());
+                let status = match &meta {
+                    None => MapStatus::Missing,
+                    Some(m) if m.len() != size => MapStatus::SizeMismatch,
+                    Some(_) => MapStatus::Present,
+                };
+                MappedFile {
+                    path: path.iter().map(|e| e.to_string_lossy()).collect::<Vec<_>>().join("/"),
+                    size,
+                    status,
+                    disk_path: meta.as_ref().map(|_| disk.to_string_lossy().into_owned()),
+                    disk_size: meta.as_ref().map(|m| m.len()),
+                    links: meta.as_ref().and_then(link_count),
+                }
+            })
+            .collect::<Vec<MappedFile>>();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Self { info_hash, name, dir: dir.to_string_lossy().into_owned(), files }
+    }
+
+    pub fn write(&self, file: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(file, content + "\n")
+            .with_context(|| format!("Failed to write map report {}", file.display()))
+    }
+}
+
+/// Hex digits kept of an entry's id.
+const ID_LEN: usize = 16;
+
+/// The id of the entry at `rel`, relative to the directory, for the torrent with `info_hash`:
+/// the first 16 hex digits of the BLAKE3 hash of the info hash in lowercase hex, a NUL byte and
+/// `rel` with its components joined by `/`, as UTF-8 with invalid bytes replaced by U+FFFD. It
+/// stays the same from one run to the next for as long as the entry keeps its path.
+pub fn entry_id(info_hash: &str, rel: &Path) -> String {
+    let rel = rel.iter().map(|e| e.to_string_lossy()).collect::<Vec<_>>().join("/");
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(info_hash.to_ascii_lowercase().as_bytes());
+    hasher.update(&[0]);
+    hasher.update(rel.as_bytes());
+    let mut id = hasher.finalize().to_hex().to_string();
+    id.truncate(ID_LEN);
+    id
+}
+
+/// `path` as text, plus its bytes in hex when it is not valid Unicode and the text had to
+/// replace some of them. The bytes are those of [`std::ffi::OsStr::as_encoded_bytes`]: the raw
+/// bytes on Unix, WTF-8 on Windows.
+fn encode(path: &Path) -> (String, Option<String>) {
+    match path.to_str() {
+        Some(text) => (text.to_string(), None),
+        None => (path.to_string_lossy().into_owned(), Some(path.as_os_str().as_encoded_bytes()
+            .iter().fold(String::new(), |mut hex, e| {
+                let _ = write!(hex, "{:02x}", e);
+                hex
+            }))),
+    }
+}
+
+#[derive(Serialize)]
+pub struct DiffEntry {
+    /// See [`entry_id`].
+    pub id: String,
+    /// Path inside the directory, `/`-separated.
+    pub path: String,
+    pub absolute_path: String,
+    /// Hex bytes of the absolute path when `absolute_path` could not hold it exactly.
+    pub absolute_path_bytes: Option<String>,
+    /// Bytes on disk, or those the torrent declares for missing files; 0 for directories.
+    pub size: u64,
+}
+
+impl DiffEntry {
+    fn new(info_hash: &str, dir: &Path, path: &Path, size: u64) -> Self {
+        let rel = path.strip_prefix(dir).unwrap_or(path);
+        let (absolute_path, absolute_path_bytes) = encode(path);
+        Self {
+            id: entry_id(info_hash, rel),
+            path: rel.iter().map(|e| e.to_string_lossy()).collect::<Vec<_>>().join("/"),
+            absolute_path,
+            absolute_path_bytes,
+            size,
+        }
+    }
+}
+
+/// What `blocked_by` names for the entries `--deletable-ext` holds back.
+const DELETABLE_EXT: &str = "deletable-ext";
+
+/// An extra left for manual review.
+#[derive(Serialize)]
+pub struct ReviewEntry {
+    #[serde(flatten)]
+    pub entry: DiffEntry,
+    pub blocked_by: &'static str,
+}
+
+/// An extra identical to a present file of the torrent, with `--verify-duplicates`.
+#[derive(Serialize)]
+pub struct DuplicateEntry {
+    #[serde(flatten)]
+    pub entry: DiffEntry,
+    /// The file of the torrent it duplicates, inside the directory and `/`-separated.
+    pub duplicate_of: String,
+}
+
+/// A file of the torrent with another size than it declares.
+#[derive(Serialize)]
+pub struct MismatchedEntry {
+    #[serde(flatten)]
+    pub entry: DiffEntry,
+    pub expected_size: u64,
+}
+
+/// An entry `--skip-errors` went past.
+#[derive(Serialize)]
+pub struct UnreadableEntry {
+    #[serde(flatten)]
+    pub entry: DiffEntry,
+    pub error: String,
+}
+
+/// The document printed by `diff --output json`.
+#[derive(Serialize)]
+pub struct DiffReport {
+    pub info_hash: String,
+    pub name: String,
+    pub dir: String,
+    pub dir_bytes: Option<String>,
+    pub missing: Vec<DiffEntry>,
+    pub mismatched: Vec<MismatchedEntry>,
+    pub extra_files: Vec<DiffEntry>,
+    pub extra_dirs: Vec<DiffEntry>,
+    /// Directories that only become empty once the extras are removed.
+    pub emptied_dirs: Vec<DiffEntry>,
+    pub unreadable: Vec<UnreadableEntry>,
+    /// Extras `--deletable-ext` holds back.
+    pub review: Vec<ReviewEntry>,
+    /// Extras `--verify-duplicates` found identical to a present file of the torrent.
+    pub duplicates: Vec<DuplicateEntry>,
+    /// Whether `--limit-json` left out entries of the lists above.
+    pub truncated: bool,
+    pub summary: Summary,
+}
+
+impl DiffReport {
+    pub fn new(info_hash: String, name: String, dir: &Path, expected: &impl ExpectedSet,
+               scan: &Scan, missing: &[PathBuf], summary: Summary) -> Self {
+        let (dir_text, dir_bytes) = encode(dir);
+        let dirs = |dirs: &[PathBuf]| dirs.iter()
+            .map(|e| DiffEntry::new(&info_hash, dir, e, 0)).collect();
+        Self {
+            name,
+            dir: dir_text,
+            dir_bytes,
+            missing: missing.iter()
+                .map(|e| DiffEntry::new(&info_hash, dir, e, e.strip_prefix(dir).ok()
+                    .and_then(|rel| expected.file_size(rel)).unwrap_or(0)))
+                .collect(),
+            mismatched: expected.mismatched(dir).into_iter()
+                .map(|e| MismatchedEntry {
+                    entry: DiffEntry::new(&info_hash, dir, &e.path, e.size),
+                    expected_size: e.expected,
+                })
+                .collect(),
+            extra_files: scan.old_files.iter()
+                .map(|e| DiffEntry::new(&info_hash, dir, e,
+                                        fs::symlink_metadata(e).map_or(0, |m| m.len())))
+                .collect(),
+            extra_dirs: dirs(&scan.empty_dirs),
+            emptied_dirs: dirs(&scan.emptied_dirs),
+            unreadable: scan.unreadable.iter()
+                .map(|e| UnreadableEntry { entry: DiffEntry::new(&info_hash, dir, &e.path, 0),
+                                           error: e.error.clone() })
+                .collect(),
+            review: scan.skipped(SkipReason::NotDeletable)
+                .map(|e| ReviewEntry { entry: DiffEntry::new(&info_hash, dir, &e.path, e.size),
+                                       blocked_by: DELETABLE_EXT })
+                .collect(),
+            duplicates: Vec::new(),
+            truncated: false,
+            summary,
+            // Last, as the ids above are derived from it
+            info_hash,
+        }
+    }
+
+    /// Lists the `duplicates` of `--verify-duplicates` found under `dir`.
+    pub fn with_duplicates(mut self, dir: &Path, duplicates: &[Duplicate]) -> Self {
+        self.duplicates = duplicates.iter()
+            .map(|e| DuplicateEntry {
+                entry: DiffEntry::new(&self.info_hash, dir, &e.extra, e.size),
+                duplicate_of: DiffEntry::new(&self.info_hash, dir, &e.kept, e.size).path,
+            })
+            .collect();
+        self
+    }
+
+    /// Cuts each list to its first `limit` entries, 0 keeping them all, as `--limit-json` does;
+    /// the summary still counts every entry.
+    pub fn limited(mut self, limit: usize) -> Self {
+        fn cut<T>(entries: &mut Vec<T>, limit: usize) -> bool {
+            let cut = limit > 0 && entries.len() > limit;
+            if cut {
+                entries.truncate(limit);
+            }
+            cut
+        }
+        self.truncated = [cut(&mut self.missing, limit), cut(&mut self.mismatched, limit),
+                          cut(&mut self.extra_files, limit), cut(&mut self.extra_dirs, limit),
+                          cut(&mut self.emptied_dirs, limit), cut(&mut self.unreadable, limit),
+                          cut(&mut self.review, limit), cut(&mut self.duplicates, limit)]
+            .contains(&true);
+        self
+    }
+}
+
+/// What became, or would become, of an entry in a `--report-format ndjson` report.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReportAction {
+    /// An extra that `diff`, or `clean` before removing anything, plans to remove.
+    WouldRemove,
+    /// A file of the torrent that is not in the directory.
+    Missing,
+    Removed,
+    /// Staged, or moved by `--move-to`.
+    Moved,
+    /// Planned but left in place: declined, changed since the scan, or not reached.
+    Skipped,
+    Failed,
+    /// An extra that `--deletable-ext` holds back for manual review.
+    Review,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    File,
+    Dir,
+}
+
+/// Why `clean` removes an entry.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovalReason {
+    /// Not part of the torrent.
+    Extra,
+    /// A file of the torrent that `--verify` found corrupt, for the client to download again.
+    Corrupt,
+}
+
+/// A line of a `--report-format ndjson` report.
+#[derive(Serialize)]
+pub struct ReportRow {
+    pub action: ReportAction,
+    /// Relative to the directory.
+    pub path: String,
+    pub size: u64,
+    pub kind: EntryKind,
+    /// Left out for missing files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<RemovalReason>,
+    /// The option holding a `review` entry back.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked_by: Option<&'static str>,
+}
+
+/// A row as written, with the id of its entry.
+#[derive(Serialize)]
+struct ReportLine<'a> {
+    /// See [`entry_id`].
+    id: String,
+    #[serde(flatten)]
+    row: &'a ReportRow,
+}
+
+/// The last line of a `--report-format ndjson` report.
+#[derive(Serialize)]
+struct ReportTotals<'a> {
+    info_hash: &'a str,
+    entries: usize,
+    size: u64,
+    /// How many entries there are of each action.
+    totals: BTreeMap<ReportAction, usize>,
+    /// Those printed up to the report being written.
+    warnings: Vec<Warning>,
+}
+
+impl ReportRow {
+    fn new(action: ReportAction, dir: &Path, path: &Path, size: u64, is_dir: bool) -> Self {
+        let rel = path.strip_prefix(dir).unwrap_or(path).to_string_lossy().into_owned();
+        Self { action, path: rel, size, kind: if is_dir { EntryKind::Dir } else { EntryKind::File },
+               reason: None, blocked_by: None }
+    }
+
+    fn removal(action: ReportAction, dir: &Path, path: &Path, size: u64, is_dir: bool,
+               corrupt: &HashSet<PathBuf>) -> Self {
+        Self { reason: Some(match corrupt.contains(path) {
+            true => RemovalReason::Corrupt,
+            false => RemovalReason::Extra,
+        }), ..Self::new(action, dir, path, size, is_dir) }
+    }
+
+    /// The entries `scan` removes, with `action`: the files, then the directories in the order
+    /// they are removed. Files changed since the scan count as skipped. Those in `corrupt` are
+    /// files of the torrent removed for being corrupt, the others extras.
+    pub fn planned<'a>(dir: &'a Path, scan: &'a Scan, corrupt: &'a HashSet<PathBuf>,
+                       action: ReportAction) -> impl Iterator<Item = Self> + 'a {
+        let size = |path: &PathBuf| scan.stamps.get(path).map_or(0, |e| e.size);
+        let files = scan.old_files.iter()
+            .map(move |e| Self::removal(action, dir, e, size(e), false, corrupt));
+        let changed = scan.skipped(SkipReason::Changed).map(move |e| {
+            Self::removal(ReportAction::Skipped, dir, &e.path, e.size, false, corrupt)
+        });
+        let dirs = scan.empty_dirs.iter().chain(&scan.emptied_dirs)
+            .map(move |e| Self::removal(action, dir, e, 0, true, corrupt));
+        files.chain(changed).chain(dirs)
+    }
+
+    /// The entries `--deletable-ext` holds back from the removal.
+    pub fn held_back<'a>(dir: &'a Path, scan: &'a Scan) -> impl Iterator<Item = Self> + 'a {
+        scan.skipped(SkipReason::NotDeletable).map(move |e| Self {
+            blocked_by: Some(DELETABLE_EXT),
+            ..Self::new(ReportAction::Review, dir, &e.path, e.size, e.path.is_dir())
+        })
+    }
+
+    /// The files of the torrent missing from `dir`.
+    pub fn missing(dir: &Path, expected: &impl ExpectedSet, missing: &[PathBuf]) -> Vec<Self> {
+        missing.iter()
+            .map(|e| Self::new(ReportAction::Missing, dir, e, e.strip_prefix(dir).ok()
+                .and_then(|rel| expected.file_size(rel)).unwrap_or(0), false))
+            .collect()
+    }
+
+    /// What became of the entries `scan` planned to remove once `clean` is done with them: gone,
+    /// as removed or `moved`, failed with the error, or still there.
+    pub fn outcome<'a>(dir: &'a Path, scan: &'a Scan, corrupt: &'a HashSet<PathBuf>,
+                       failed: &'a [(PathBuf, String)], moved: bool)
+                       -> impl Iterator<Item = Self> + 'a {
+        let failed = failed.iter().map(|(path, _)| path).collect::<HashSet<&PathBuf>>();
+        let paths = scan.old_files.iter()
+            .chain(scan.skipped(SkipReason::Changed).map(|e| &e.path))
+            .chain(&scan.empty_dirs).chain(&scan.emptied_dirs);
+        Self::planned(dir, scan, corrupt, ReportAction::Removed).zip(paths).enumerate()
+            .map(move |(i, (mut row, path))| {
+                row.action = match () {
+                    _ if row.action == ReportAction::Skipped => ReportAction::Skipped,
+                    _ if failed.contains(path) => ReportAction::Failed,
+                    _ if fs::symlink_metadata(path).is_ok() => ReportAction::Skipped,
+                    // Moving takes the extras only; the directories are deleted either way
+                    _ if moved && i < scan.old_files.len() => ReportAction::Moved,
+                    _ => ReportAction::Removed,
+                };
+                row
+            })
+    }
+}
+
+/// Writes `rows` to `file` for `--report-format ndjson`, one JSON object per line with the id of
+/// its entry, followed by one with their totals and the torrent's info hash. Each row is written
+/// as it comes, so a removal of any size takes no more memory to report.
+pub fn write_report(file: &Path, rows: impl IntoIterator<Item = ReportRow>, info_hash: &str)
+                    -> anyhow::Result<()> {
+    let context = || format!("Failed to write report {}", file.display());
+    let mut out = BufWriter::new(File::create(file).with_context(context)?);
+    let mut totals = ReportTotals { info_hash, entries: 0, size: 0, totals: BTreeMap::new(),
+                                    warnings: warnings() };
+    for row in rows {
+        let line = ReportLine { id: entry_id(info_hash, Path::new(&row.path)), row: &row };
+        serde_json::to_writer(&mut out, &line).with_context(context)?;
+        writeln!(out).with_context(context)?;
+        totals.entries += 1;
+        totals.size += row.size;
+        *totals.totals.entry(row.action).or_default() += 1;
+    }
+    serde_json::to_writer(&mut out, &totals).with_context(context)?;
+    writeln!(out).and_then(|()| out.flush()).with_context(context)
+}
+
+/// Writes every entry `scan` removes to `file` for `--report`, one path relative to `dir` per
+/// line: the files, then the directories in the order they are removed.
+pub fn write_removal_list(file: &Path, dir: &Path, scan: &Scan) -> anyhow::Result<()> {
+    let context = || format!("Failed to write report {}", file.display());
+    let mut out = BufWriter::new(File::create(file).with_context(context)?);
+    for entry in scan.old_files.iter().chain(&scan.empty_dirs).chain(&scan.emptied_dirs) {
+        writeln!(out, "{}", entry.strip_prefix(dir).unwrap_or(entry).to_string_lossy())
+            .with_context(context)?;
+    }
+    out.flush().with_context(context)
+}
@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Context};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+#[derive(Default)]
+pub struct LinkSummary {
+    pub created: usize,
+    pub refreshed: usize,
+    pub unchanged: usize,
+    pub pruned: usize,
+}
+
+impl LinkSummary {
+    pub fn total(&self) -> usize {
+        self.created + self.refreshed + self.unchanged
+    }
+}
+
+/// Mirrors `entries` (absolute paths under `root`) as symlinks inside `link_dir`, keeping their
+/// paths relative to `root`. Links into `root` are taken for those of earlier reviews: those
+/// pointing at another entry are refreshed, and those no longer corresponding to any entry are
+/// pruned along with the directories pruning empties. Anything else in `link_dir`, links
+/// elsewhere included, is not the tool's and is left alone.
+pub fn link_extras(root: &Path, link_dir: &Path, entries: &[PathBuf]) -> anyhow::Result<LinkSummary> {
+    let mut summary = LinkSummary::default();
+    let mut wanted = HashSet::new();
+    fs::create_dir_all(link_dir)
+        .with_context(|| format!("Failed to create review directory {}", link_dir.display()))?;
+
+    let mut entries = entries.iter().collect::<Vec<&PathBuf>>();
+    entries.sort();
+    let mut linked_dir: Option<&Path> = None;
+    for entry in entries {
+        // Contents of a linked directory are already reachable through its link
+        if linked_dir.is_some_and(|d| entry.starts_with(d)) {
+            continue;
+        }
+        if entry.is_dir() {
+            linked_dir = Some(entry);
+        }
+        let rel = entry.strip_prefix(root).with_context(||
+            format!("Failed to strip directory contents of {:?}", root))?;
+        let link = link_dir.join(rel);
+        wanted.insert(link.clone());
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+
+        match fs::symlink_metadata(&link) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                let target = fs::read_link(&link)?;
+                if target == *entry {
+                    summary.unchanged += 1;
+                    continue;
+                }
+                if !target.starts_with(root) {
+                    return Err(anyhow!("Refusing to replace a link not made by a review of {} in \
+                        review directory: {}", root.display(), link.display()));
+                }
+                remove_link(&link)?;
+                summary.refreshed += 1;
+            }
+            Ok(_) => return Err(anyhow!("Refusing to replace non-link entry in review directory: {}",
+                link.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => summary.created += 1,
+            Err(e) => return Err(e.into()),
+        }
+        create_link(entry, &link)
+            .with_context(|| format!("Failed to create link {}", link.display()))?;
+    }
+
+    // Prune links of earlier runs, then the directories that held only them
+    let mut emptied = HashSet::new();
+    for entry in WalkDir::new(link_dir).min_depth(1) {
+        let entry = entry.context("Failed to read review directory contents")?;
+        let path = entry.path();
+        if entry.path_is_symlink() && !wanted.contains(path)
+            && fs::read_link(path).is_ok_and(|e| e.starts_with(root)) {
+            remove_link(path)?;
+            summary.pruned += 1;
+            emptied.extend(path.ancestors().skip(1).take_while(|e| *e != link_dir)
+                .map(Path::to_path_buf));
+        }
+    }
+    let mut dirs = emptied.into_iter().collect::<Vec<PathBuf>>();
+    dirs.sort();
+    for dir in dirs.iter().rev() {
+        let _ = fs::remove_dir(dir); // only succeeds if empty
+    }
+
+    Ok(summary)
+}
+
+#[cfg(unix)]
+fn create_link(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_link(target: &Path, link: &Path) -> io::Result<()> {
+    if target.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(unix)]
+fn remove_link(link: &Path) -> io::Result<()> {
+    fs::remove_file(link)
+}
+
+#[cfg(windows)]
+fn remove_link(link: &Path) -> io::Result<()> {
+    // Directory symlinks have to be removed as directories on Windows
+    fs::remove_file(link).or_else(|_| fs::remove_dir(link))
+}
@@ -0,0 +1,361 @@
+use crate::cli::build_cli;
+use crate::error::{CliError, ErrorCode};
+use crate::history::RunRecord;
+use crate::interrupt::{interrupted, start_removing};
+use crate::output::set_quiet;
+use crate::paths::aliases;
+use crate::progress::ProgressMode;
+use crate::prompt::{confirm, EXIT_DECLINED};
+use crate::remove::{remove_empty_dirs, remove_files};
+use crate::say;
+use crate::scan::{ExpectedSet, Mismatch, Scan, SkipReason, Stamp};
+use crate::{asked_entries, check_content, check_scope, check_threshold, check_torrent, held_back,
+            limited, load_expected, log_paths, print_aliases, print_breakdown, print_changes,
+            print_empty_dirs, print_entries, print_kept, print_kind_counts, print_largest,
+            print_more, print_skipped, print_space_freed, remove_options, save_record,
+            scan_options, sort_changes, sort_order, top, torrent_listing, unlisted_dirs, Listing};
+use anyhow::anyhow;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use indicatif::{BinaryBytes, ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use term_painter::Color::{Green, Red, Yellow};
+use term_painter::ToStyle;
+
+/// Options acting on one directory, which cannot be given along with several.
+const SINGLE_DIR: [&str; 25] = [
+    "link-to", "summary-json", "only-duplicates-of-kept", "confirm-each", "backup-manifest",
+    "journal", "resume", "stage", "move-to", "pre-delete-hook", "filter-hook", "fix-renames",
+    "relink", "report", "verify-renames", "since-last", "map-report", "tree", "group-dirs",
+    "watch", "torrents-dir", "transmission-config", "verify", "interactive", "verify-duplicates",
+];
+
+/// `diff --check` exit codes, as for one directory.
+const CHECK_EXTRAS: u8 = 8;
+const CHECK_OUT_OF_SCOPE: u8 = 9;
+const CHECK_INCOMPLETE: u8 = 10;
+
+/// Where the files of the torrent are across the directories, by their paths in the torrent.
+struct Presence {
+    /// In none of the directories, with their size.
+    missing: Vec<(PathBuf, u64)>,
+    /// Files only found with another size.
+    mismatched: usize,
+    /// Files with the expected size in more than one directory, with the indices of those.
+    copies: Vec<(PathBuf, u64, Vec<usize>)>,
+    /// How many files of the torrent each directory holds with the expected size.
+    present: Vec<usize>,
+}
+
+impl Presence {
+    fn find(dirs: &[PathBuf], expected: &impl ExpectedSet) -> Self {
+        let mut presence = Self { missing: Vec::new(), mismatched: 0, copies: Vec::new(),
+                                  present: vec![0; dirs.len()] };
+        for (path, size) in expected.files() {
+            let metas = dirs.iter()
+                .map(|dir| fs::metadata(expected.locate(dir, path)).ok().filter(|m| m.is_file()))
+                .collect::<Vec<Option<fs::Metadata>>>();
+            let holding = metas.iter().enumerate()
+                .filter(|(_, meta)| meta.as_ref().is_some_and(|m| m.len() == size))
+                .map(|(i, _)| i)
+                .collect::<Vec<usize>>();
+            for &i in &holding {
+                presence.present[i] += 1;
+            }
+            match holding.len() {
+                0 if metas.iter().any(Option::is_some) => presence.mismatched += 1,
+                0 => presence.missing.push((path.to_path_buf(), size)),
+                1 => {}
+                _ => presence.copies.push((path.to_path_buf(), size, holding)),
+            }
+        }
+        presence.missing.sort();
+        presence.copies.sort();
+        presence
+    }
+}
+
+/// A directory of the run as it is planned.
+struct Root {
+    dir: PathBuf,
+    scan: Scan,
+    mismatched: Vec<Mismatch>,
+    listing: Listing,
+}
+
+/// Refuses the options that only make sense for one directory, and the JSON documents, which
+/// describe one.
+fn check_options(matches: &ArgMatches) {
+    let given = SINGLE_DIR.iter().find(|id| matches.try_contains_id(id).is_ok_and(|e| e)
+        && matches.value_source(id).is_some_and(|e| e != ValueSource::DefaultValue));
+    let option = match given {
+        Some(id) => format!("--{}", id),
+        None if matches.get_one::<String>("output").is_some_and(|e| e == "json") => {
+            "--output json".to_string()
+        }
+        None => return,
+    };
+    build_cli().error(ErrorKind::ArgumentConflict,
+        format!("{} takes a single directory; give one directory at a time", option)).exit()
+}
+
+/// Runs diff or clean over several directories holding the torrent's files between them: a file
+/// is present if any of them holds it with the expected size, and the extras are those of each
+/// directory, listed and removed per directory after one confirmation.
+pub fn run_roots(matches: &ArgMatches, dirs: &[PathBuf], command: &str)
+                 -> anyhow::Result<ExitCode> {
+    check_options(matches);
+    let clean = command == "clean";
+    let mut seen = HashMap::new();
+    for (i, dir) in dirs.iter().enumerate() {
+        let canonical = dir.canonicalize().unwrap_or_else(|_| dir.clone());
+        if let Some(first) = seen.insert(canonical, i) {
+            return Err(anyhow!("Directories {} and {} are both {}; give each once", first + 1,
+                               i + 1, dir.display()));
+        }
+    }
+    let (expected, record, sources) = load_expected(matches, command, &dirs[0])?;
+    for dir in dirs {
+        for warning in check_content(matches, dir, &expected, clean)? {
+            say!("{}", warning);
+        }
+        check_scope(matches, dir, &expected, &sources.names, clean)?;
+    }
+    check_torrent(matches, &expected, clean)?;
+    let held = match clean {
+        true => held_back(matches, &record)?,
+        false => None,
+    };
+
+    let mut presence = Presence::find(dirs, &expected);
+    sort_order(matches).sort(&mut presence.missing, |e| &e.0,
+                             |e| Some(Stamp { size: e.1, modified: None }));
+    let aliases = aliases(&dirs.iter().map(PathBuf::as_path).collect::<Vec<&Path>>());
+    let mut roots = Vec::new();
+    for (i, dir) in dirs.iter().enumerate() {
+        let mut listing = torrent_listing(matches, dir, expected.is_flat());
+        listing.paths = listing.paths.with_alias(&aliases[i]);
+        let mut mismatched = expected.mismatched(dir);
+        sort_changes(matches, dir, &expected, &mut [], &mut mismatched);
+        roots.push(Root { dir: dir.clone(), scan: crate::scan(matches, dir, &expected)?,
+                          mismatched, listing });
+    }
+    let dedupe = matches.get_flag("dedupe-across-roots");
+    if dedupe {
+        for (path, _, holding) in &presence.copies {
+            for &i in &holding[1..] {
+                let scan = &mut roots[i].scan;
+                let copy = expected.locate(&dirs[i], path);
+                let Some(stamp) = Stamp::read(&copy) else { continue };
+                scan.rm_size += stamp.size;
+                scan.stamps.insert(copy.clone(), stamp);
+                scan.old_files.push(copy);
+            }
+        }
+        for root in &mut roots {
+            sort_order(matches).sort_paths(&mut root.scan.old_files, &root.scan.stamps);
+        }
+    }
+    let actionable = roots.iter()
+        .any(|e| !e.scan.old_files.is_empty() || !e.scan.empty_dirs.is_empty());
+    if actionable && !clean {
+        set_quiet(false);
+    }
+
+    let top = top(matches);
+    let total = roots.len();
+    // Entries the listings left out, which the confirmation covers all the same
+    let mut unlisted = 0;
+    for (i, root) in roots.iter().enumerate() {
+        let (scan, listing) = (&root.scan, &root.listing);
+        say!("[{}/{}] {}: {}", i + 1, total, aliases[i], root.dir.display());
+        if clean {
+            if held.is_none() {
+                check_threshold(matches, scan, presence.present[i])
+                    .map_err(|e| e.with_path(&root.dir))?;
+            }
+            if scan.removal_count() == 0 {
+                say!("No matching entries found.");
+            } else {
+                print_entries(&scan.old_files, listing);
+                print_empty_dirs(scan, listing);
+                unlisted += listing.unlisted(scan.old_files.len()) + unlisted_dirs(scan, listing);
+                print_aliases(scan, listing);
+                print_kept(scan, listing);
+                say!("Remove files: {} ({})", Red.paint(BinaryBytes(scan.rm_size)),
+                     scan.old_files.len());
+                print_space_freed(&scan.disk_usage());
+                print_kind_counts(&scan.old_files);
+                print_largest(matches, &scan.old_files, top, listing)?;
+            }
+        } else if print_changes(scan, &[], 0, &root.mismatched, listing) {
+            print_largest(matches, &scan.old_files, top, listing)?;
+        } else {
+            say!("No matching entries found.");
+        }
+        if matches.get_flag("summary") {
+            print_breakdown(&root.dir, scan, top, listing);
+        }
+        print_skipped(matches, scan);
+        say!();
+    }
+    print_presence(&presence, &aliases, dedupe, &roots[0].listing);
+
+    let count = roots.iter().map(|e| e.scan.old_files.len()).sum::<usize>();
+    let dirs_count = roots.iter().map(|e| e.scan.empty_dirs.len() + e.scan.emptied_dirs.len())
+        .sum::<usize>();
+    let size = roots.iter().map(|e| e.scan.rm_size).sum::<u64>();
+    for (root, alias) in roots.iter().zip(&aliases) {
+        say!("Remove entries in {}: {} ({})", alias, Red.paint(BinaryBytes(root.scan.rm_size)),
+             root.scan.removal_count());
+    }
+    say!("Remove entries in total: {} ({}) across {} directories", Red.paint(BinaryBytes(size)),
+         count + dirs_count, total);
+
+    if !clean {
+        for root in &roots {
+            save_record(matches, &root_record(&record, root, &presence));
+        }
+        say!("Operation completed successfully.");
+        let consistent = presence.missing.is_empty() && presence.mismatched == 0;
+        let out_of_scope = roots.iter()
+            .any(|e| e.scan.skipped(SkipReason::OutOfScope).next().is_some());
+        return Ok(ExitCode::from(match matches.get_flag("check") {
+            true if actionable => CHECK_EXTRAS,
+            true if !consistent => CHECK_INCOMPLETE,
+            true if out_of_scope => CHECK_OUT_OF_SCOPE,
+            _ => 0,
+        }));
+    }
+
+    if let Some(reason) = &held {
+        for root in &roots {
+            save_record(matches, &root_record(&record, root, &presence));
+        }
+        say!("Skipped: {} is not cleaned, as {}.", record.torrent, reason);
+        return Ok(ExitCode::SUCCESS);
+    }
+    let include_empty_dir = scan_options(matches)?.include_empty_dir;
+    let pending = roots.iter()
+        .filter(|e| !e.scan.old_files.is_empty() || include_empty_dir && e.scan.removal_count() > 0)
+        .count();
+    if pending == 0 {
+        say!("Aborted.");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let question = format!("Delete {}?", asked_entries(count, dirs_count, unlisted));
+    if !matches.get_flag("no-confirm") && !confirm(matches, &question, true) {
+        return Ok(ExitCode::from(EXIT_DECLINED));
+    }
+    start_removing();
+
+    let revalidate = !matches.get_flag("no-revalidate");
+    let mut failures = Vec::new();
+    let mut removed = 0;
+    for (root, alias) in roots.iter().zip(&aliases) {
+        if interrupted() {
+            break;
+        }
+        let scan = &root.scan;
+        let mut record = root_record(&record, root, &presence);
+        let progress = ProgressMode::from_matches(matches)
+            .bar(ProgressBar::new(scan.old_files.len() as u64));
+        progress.set_style(ProgressStyle::default_bar()
+            .template("{prefix} [{wide_bar:.cyan/blue}] {pos}/{len} ({percent}%)\n{msg}")?);
+        progress.set_prefix(alias.clone());
+        let paths = log_paths(matches, &root.dir).with_alias(alias);
+        let files = remove_files(&scan.old_files, revalidate.then_some(scan), &paths, None, None,
+                                 remove_options(matches), &progress)?;
+        let gone = scan.old_files.iter().filter(|e| fs::symlink_metadata(e).is_err())
+            .collect::<Vec<&PathBuf>>();
+        let mut count = gone.len();
+        let mut error = files.error(&paths);
+        if include_empty_dir && !interrupted() {
+            let removal = remove_empty_dirs(scan, &paths, None, remove_options(matches),
+                                            &progress);
+            error = error.or_else(|| removal.error(&paths));
+            count += removal.removed;
+        }
+        progress.finish_and_clear();
+        record.removed = count;
+        record.removed_size = gone.iter().filter_map(|e| scan.stamps.get(*e)).map(|e| e.size)
+            .sum();
+        record.failures = files.failed.len();
+        save_record(matches, &record);
+        removed += count;
+        match error {
+            Some(err) => {
+                say!("[{}] {} {:#}", alias, Red.paint("Failed:"), err);
+                failures.push((root.dir.clone(), err));
+            }
+            None => say!("[{}] {} entries {}.", alias, count, match matches.get_flag("trash") {
+                true => "moved to the trash",
+                false => "removed",
+            }),
+        }
+    }
+    if interrupted() {
+        return Err(CliError::new(ErrorCode::Interrupted, format!(
+            "Interrupted: {} entries removed; run clean again for the rest", removed)).into());
+    }
+    if !failures.is_empty() {
+        let mut error = CliError::new(ErrorCode::BatchFailed,
+            format!("{} of {} directories failed", failures.len(), total));
+        for (dir, err) in &failures {
+            error = error.with_entry(dir, format!("{:#}", err));
+        }
+        return Err(error.into());
+    }
+    say!("Operation completed successfully.");
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The record of the run in `root`, which is missing the files none of the directories hold.
+fn root_record(record: &RunRecord, root: &Root, presence: &Presence) -> RunRecord {
+    let mut record = record.clone();
+    record.dir = root.dir.clone();
+    record.extras = root.scan.removal_count();
+    record.extras_size = root.scan.rm_size;
+    record.missing = presence.missing.len();
+    record
+}
+
+/// Lists the files of the torrent none of the directories hold, and those more than one does,
+/// by their paths in the torrent.
+fn print_presence(presence: &Presence, aliases: &[String], dedupe: bool, listing: &Listing) {
+    let missing = &presence.missing;
+    if !missing.is_empty() {
+        say!("Missing from every directory:");
+        for (path, _) in limited(missing, listing) {
+            say!("{}   {}", Green.paint("+"), path.display());
+        }
+        print_more(missing.len(), listing);
+        say!("New files: {} ({})",
+             Green.paint(BinaryBytes(missing.iter().map(|(_, size)| size).sum::<u64>())),
+             missing.len());
+        say!();
+    }
+    let copies = &presence.copies;
+    if !copies.is_empty() {
+        say!("In several directories:");
+        for (path, _, holding) in limited(copies, listing) {
+            let names = holding.iter().map(|&i| aliases[i].as_str()).collect::<Vec<&str>>();
+            match dedupe {
+                true => say!("{}   {}  (kept in {}, removed from {})", Yellow.paint("="),
+                             path.display(), names[0], names[1..].join(", ")),
+                false => say!("{}   {}  (in {})", Yellow.paint("="), path.display(),
+                              names.join(", ")),
+            }
+        }
+        print_more(copies.len(), listing);
+        let size = copies.iter().map(|(_, size, holding)| size * (holding.len() - 1) as u64)
+            .sum::<u64>();
+        say!("Duplicate copies: {} ({}){}", Yellow.paint(BinaryBytes(size)), copies.len(),
+             if dedupe { "" } else { "; --dedupe-across-roots removes all but the first" });
+        say!();
+    }
+}
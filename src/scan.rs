@@ -0,0 +1,2708 @@
+use crate::droppings;
+use crate::error::{CliError, ErrorCode};
+use crate::junk::{Junk, JunkMode};
+use crate::owner::owner_of;
+use anyhow::{anyhow, Context};
+use globset::{GlobBuilder, GlobMatcher};
+use librqbit_buffers::ByteBufOwned;
+use librqbit_core::torrent_metainfo::{TorrentMetaV1, TorrentMetaV1File};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime};
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+use walkdir::WalkDir;
+
+/// Holding area of `clean --stage` inside the scanned directory; never scanned or pruned itself.
+pub const STAGE_DIR: &str = ".torrent-cleaner-trash";
+
+/// Patterns kept at the root of the scanned directory of entries to leave alone, in addition to
+/// `--exclude`; see [`Exclude::with_ignore_file`]. The file itself is never removed.
+pub const IGNORE_FILE: &str = ".torrentcleanerignore";
+
+/// Directories in which snapshotting file systems and file servers keep read-only copies of
+/// earlier states or deleted files: ZFS, btrfs (snapper), NetApp and Synology snapshots, and the
+/// recycle bins of Samba, Synology and QNAP shares. Never walked into unless
+/// [`ScanOptions::include_snapshots`], and nothing inside them is ever removed.
+pub const SNAPSHOT_DIRS: [&str; 7] =
+    [".zfs", ".snapshots", ".snapshot", "#snapshot", ".recycle", "#recycle", "@Recycle"];
+
+/// The snapshot or recycle area `path` is or lies in, if any: the outermost of its ancestors
+/// named like one of [`SNAPSHOT_DIRS`], in any case.
+///
+/// ```
+/// use std::path::Path;
+/// use torrent_cleaner::scan::snapshot_area;
+///
+/// assert_eq!(snapshot_area(Path::new("/tank/.zfs/snapshot/daily/Show/ep1.mkv")),
+///            Some(Path::new("/tank/.zfs")));
+/// assert_eq!(snapshot_area(Path::new("Show/#Recycle")), Some(Path::new("Show/#Recycle")));
+/// assert_eq!(snapshot_area(Path::new("Show/recycle/ep1.mkv")), None);
+/// ```
+pub fn snapshot_area(path: &Path) -> Option<&Path> {
+    path.ancestors().filter(|e| e.file_name().is_some_and(is_snapshot_name)).last()
+}
+
+fn is_snapshot_name(name: &OsStr) -> bool {
+    SNAPSHOT_DIRS.iter().any(|e| name.eq_ignore_ascii_case(e))
+}
+
+/// Receives a tick for every entry [`scan_dir`] visits; `()` ignores them.
+pub trait Progress: Sync {
+    fn inc(&self, delta: u64);
+
+    /// Called with each extra file and its size as soon as it is found, before sorting.
+    fn found(&self, _extra: &Path, _size: u64) {}
+
+    /// Called with each directory as the walk reads it, from any of its threads.
+    fn entering(&self, _dir: &Path) {}
+}
+
+impl Progress for () {
+    fn inc(&self, _: u64) {}
+}
+
+#[cfg(feature = "cli")]
+impl Progress for indicatif::ProgressBar {
+    fn inc(&self, delta: u64) {
+        indicatif::ProgressBar::inc(self, delta)
+    }
+}
+
+/// The files, directories and top-level names described by a torrent.
+pub struct Expected {
+    /// The files with their sizes, sorted by path so that those inside a directory are next to
+    /// each other; a directory is expected when a file path starts with it.
+    files: Vec<(Box<Path>, u64)>,
+    pub surface_files: HashSet<OsString>,
+    folded: Option<Folded>,
+    /// Files of the torrent left out for a path leading outside of the directory.
+    unsafe_paths: Vec<UnsafePath>,
+}
+
+/// Where `path` is, or would go, among `paths` sorted by path.
+fn position<P: AsRef<Path>, T>(paths: &[(P, T)], path: &Path) -> usize {
+    paths.partition_point(|(e, _)| e.as_ref() < path)
+}
+
+/// The entry of `paths`, sorted by path, at exactly `path`.
+fn find<'a, P: AsRef<Path>, T>(paths: &'a [(P, T)], path: &Path) -> Option<&'a T> {
+    paths.get(position(paths, path)).filter(|(e, _)| e.as_ref() == path).map(|(_, e)| e)
+}
+
+/// Whether `path` is one of `paths`, sorted by path, or a directory holding one.
+fn holds<P: AsRef<Path>, T>(paths: &[(P, T)], path: &Path) -> bool {
+    !path.as_os_str().is_empty()
+        && paths.get(position(paths, path)).is_some_and(|(e, _)| e.as_ref().starts_with(path))
+}
+
+/// The lookups of an [`Expected`] matched by [`Folding::fold`]ed path.
+struct Folded {
+    folding: Folding,
+    /// The folded paths, sorted, with the index of the file each stands for.
+    files: Vec<(PathBuf, usize)>,
+    surface: HashSet<OsString>,
+    /// The directory listings read to find expected files on disk: the name of each entry by its
+    /// folded one, the first in byte order where several fold alike.
+    listings: Mutex<HashMap<PathBuf, HashMap<OsString, OsString>>>,
+}
+
+impl Folded {
+    fn fold_name(&self, name: &OsStr) -> OsString {
+        self.folding.fold(Path::new(name)).into_os_string()
+    }
+
+    fn locate(&self, dir: &Path, path: &Path) -> Option<PathBuf> {
+        let mut listings = self.listings.lock().unwrap_or_else(|e| e.into_inner());
+        let mut found = dir.to_path_buf();
+        for name in path.iter() {
+            let listing = listings.entry(found.clone()).or_insert_with(|| {
+                let mut names = fs::read_dir(&found).into_iter().flatten().flatten()
+                    .map(|e| e.file_name()).collect::<Vec<OsString>>();
+                names.sort_by(|a, b| a.as_encoded_bytes().cmp(b.as_encoded_bytes()));
+                let mut listing = HashMap::new();
+                for name in names {
+                    listing.entry(self.fold_name(&name)).or_insert(name);
+                }
+                listing
+            });
+            found.push(listing.get(&self.fold_name(name))?);
+        }
+        Some(found)
+    }
+}
+
+/// How the paths found on disk are matched against the expected ones, where not byte for byte.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Folding {
+    /// Ignoring case, as NTFS and APFS volumes do.
+    pub case: bool,
+    /// Composing decomposed characters to their NFC form, since macOS volumes store names
+    /// decomposed while torrents mostly carry them precomposed.
+    pub unicode: bool,
+}
+
+impl Folding {
+    /// `path` with each component composed and lowercased as enabled, Unicode-aware; components
+    /// that are not valid UTF-8 are kept as they are.
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use torrent_cleaner::scan::Folding;
+    ///
+    /// let folding = Folding { case: true, unicode: true };
+    /// assert_eq!(folding.fold(Path::new("Season1/E\u{301}PISODE 1.MKV")),
+    ///            Path::new("season1/\u{e9}pisode 1.mkv"));
+    /// let folding = Folding { case: false, unicode: true };
+    /// assert_eq!(folding.fold(Path::new("Cafe\u{301}")), Path::new("Caf\u{e9}"));
+    /// assert_eq!(folding.fold(Path::new("Vie\u{323}\u{302}t")), Path::new("Vi\u{1ec7}t"));
+    /// assert_eq!(folding.fold(Path::new("Vie\u{302}\u{323}t")), Path::new("Vi\u{1ec7}t"));
+    /// assert_eq!(folding.fold(Path::new("\u{1100}\u{1161}\u{11a8}")), Path::new("\u{ac01}"));
+    /// ```
+    pub fn fold(&self, path: &Path) -> PathBuf {
+        path.iter().map(|e| match e.to_str() {
+            Some(name) => {
+                // Names already in NFC, as most are, are kept without a copy
+                let composed = self.unicode && is_nfc_quick(name.chars()) != IsNormalized::Yes;
+                let name = match composed {
+                    true => Cow::Owned(name.nfc().collect::<String>()),
+                    false => Cow::Borrowed(name),
+                };
+                match self.case {
+                    true => OsString::from(name.to_lowercase()),
+                    false => OsString::from(name.as_ref()),
+                }
+            }
+            None => e.to_os_string(),
+        }).collect()
+    }
+}
+
+/// The torrent's name, which a client gives the file of a single-file torrent; a plain name, so it
+/// cannot lead outside of the directory.
+fn plain_name(torrent: &TorrentMetaV1<ByteBufOwned>) -> anyhow::Result<String> {
+    let name = torrent.info.name.as_ref().map(|e| e.to_string()).unwrap_or_default();
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(CliError::new(ErrorCode::TorrentInvalid,
+            format!("Torrent name {:?} is not a plain file name", name)).into());
+    }
+    Ok(name)
+}
+
+/// Whether a file of the torrent is a BEP 47 padding file: one with `p` in its attributes, or
+/// named `.pad/<size>` as that BEP recommends.
+///
+/// ```
+/// use librqbit_buffers::ByteBufOwned;
+/// use librqbit_core::torrent_metainfo::TorrentMetaV1File;
+/// use torrent_cleaner::scan::is_padding;
+///
+/// let file = |path: &[&str], attr: Option<&str>| TorrentMetaV1File {
+///     length: 100,
+///     path: path.iter().map(|e| ByteBufOwned::from(e.as_bytes())).collect(),
+///     attr: attr.map(|e| ByteBufOwned::from(e.as_bytes())),
+///     sha1: None,
+///     symlink_path: None,
+/// };
+/// assert!(is_padding(&file(&[".pad", "100"], Some("p"))));
+/// assert!(is_padding(&file(&["padding"], Some("hp"))));
+/// assert!(is_padding(&file(&[".pad", "100"], None)));
+/// assert!(!is_padding(&file(&[".pad", "notes.txt"], None)));
+/// assert!(!is_padding(&file(&["Season1", "ep1.mkv"], Some("x"))));
+/// ```
+pub fn is_padding(file: &TorrentMetaV1File<ByteBufOwned>) -> bool {
+    let named = match &file.path[..] {
+        [dir, size] => dir.as_ref() == b".pad" && size.as_ref().iter().all(u8::is_ascii_digit),
+        _ => false,
+    };
+    named || file.attr.as_ref().is_some_and(|e| e.as_ref().contains(&b'p'))
+}
+
+/// The path of `file` as the torrent spells it, a segment per name.
+fn torrent_segments(file: &TorrentMetaV1File<ByteBufOwned>) -> Vec<String> {
+    file.path.iter().map(|e| e.to_string()).collect()
+}
+
+/// Names Windows reserves for devices, in any case and with any extension.
+const RESERVED_NAMES: [&str; 22] = ["CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4",
+    "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7",
+    "LPT8", "LPT9"];
+
+/// Why a file of the torrent at `path` cannot exist on Windows, if it cannot: a component that is
+/// a reserved device name, ends with a space or dot, or holds a character Windows forbids in
+/// names. Clients write such files renamed, if at all, so they are never found as expected.
+///
+/// ```
+/// use std::path::Path;
+/// use torrent_cleaner::scan::windows_conflict;
+///
+/// assert_eq!(windows_conflict(Path::new("Show/aux.nfo")).as_deref(),
+///            Some("aux.nfo is a reserved device name"));
+/// assert_eq!(windows_conflict(Path::new("Show./ep1.mkv")).as_deref(), Some("Show. ends with a dot"));
+/// assert_eq!(windows_conflict(Path::new("Show/ep1: pilot.mkv")).as_deref(),
+///            Some("ep1: pilot.mkv contains ':'"));
+/// assert_eq!(windows_conflict(Path::new("Show/auxiliary.nfo")), None);
+/// ```
+pub fn windows_conflict(path: &Path) -> Option<String> {
+    path.iter().find_map(|name| {
+        let name = name.to_string_lossy();
+        let stem = name.split('.').next().unwrap_or_default().trim_end();
+        if RESERVED_NAMES.iter().any(|e| stem.eq_ignore_ascii_case(e)) {
+            return Some(format!("{} is a reserved device name", name));
+        }
+        if let Some(c) = name.chars().find(|e| "<>:\"|?*\\".contains(*e) || e.is_ascii_control()) {
+            return Some(format!("{} contains {:?}", name, c));
+        }
+        match name.chars().next_back() {
+            Some(' ') => Some(format!("{} ends with a space", name)),
+            Some('.') => Some(format!("{} ends with a dot", name)),
+            _ => None,
+        }
+    })
+}
+
+/// `path` as a case-insensitive file system compares it once a client has written it on Windows:
+/// each name lowercased, with the characters Windows forbids replaced by `_` and trailing spaces
+/// and dots dropped, as clients commonly do.
+///
+/// ```
+/// use std::path::Path;
+/// use torrent_cleaner::scan::disk_key;
+///
+/// assert_eq!(disk_key(Path::new("Show/README.txt")), disk_key(Path::new("show/readme.TXT")));
+/// assert_eq!(disk_key(Path::new("Show./ep1: pilot.mkv")), Path::new("show/ep1_ pilot.mkv"));
+/// ```
+pub fn disk_key(path: &Path) -> PathBuf {
+    path.iter().map(|name| {
+        let name = name.to_string_lossy().chars()
+            .map(|c| if "<>:\"|?*\\".contains(c) || c.is_ascii_control() { '_' } else { c })
+            .collect::<String>();
+        OsString::from(name.trim_end_matches([' ', '.']).to_lowercase())
+    }).collect()
+}
+
+/// Files of a torrent that cannot all exist on disk as it spells them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Collision {
+    /// Paths with the same [`disk_key`], sorted: one file on a case-insensitive file system or
+    /// once written on Windows.
+    Alike(Vec<PathBuf>),
+    /// A file that other files of the torrent are inside of, e.g. `foo` and `foo/bar`, which only
+    /// malformed metainfo declares; `inside` is the first of them.
+    FileAsDir { file: PathBuf, inside: PathBuf },
+}
+
+impl Collision {
+    /// The [`disk_key`] of the entry the paths contend for.
+    fn key(&self) -> PathBuf {
+        match self {
+            Collision::Alike(paths) => disk_key(&paths[0]),
+            Collision::FileAsDir { file, .. } => disk_key(file),
+        }
+    }
+
+    /// The files of the torrent involved.
+    pub fn paths(&self) -> Vec<&Path> {
+        match self {
+            Collision::Alike(paths) => paths.iter().map(PathBuf::as_path).collect(),
+            Collision::FileAsDir { file, inside } => vec![file, inside],
+        }
+    }
+}
+
+/// The collisions among the files of `expected`, checked whatever the file system, since a
+/// torrent is often moved between them.
+///
+/// ```
+/// use std::path::PathBuf;
+/// use torrent_cleaner::scan::{collisions, Collision, Expected};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let expected = Expected::from_files([
+///     (vec!["README.txt".into()], 1),
+///     (vec!["readme.txt".into()], 2),
+///     (vec!["Extras".into()], 3),
+///     (vec!["extras".into(), "trailer.mkv".into()], 4),
+///     (vec!["ep1.mkv".into()], 5),
+/// ])?;
+/// assert_eq!(collisions(&expected), [
+///     Collision::Alike(vec![PathBuf::from("README.txt"), PathBuf::from("readme.txt")]),
+///     Collision::FileAsDir { file: "Extras".into(), inside: "extras/trailer.mkv".into() },
+/// ]);
+/// # Ok(())
+/// # }
+/// ```
+pub fn collisions(expected: &impl ExpectedSet) -> Vec<Collision> {
+    let mut keys = BTreeMap::<PathBuf, Vec<PathBuf>>::new();
+    for (path, _) in expected.files() {
+        keys.entry(disk_key(path)).or_default().push(path.to_path_buf());
+    }
+    let mut alike = Vec::new();
+    let mut files = BTreeMap::new();
+    for paths in keys.values_mut() {
+        paths.sort_by_cached_key(|e| path_key(e));
+        if paths.len() > 1 {
+            alike.push(Collision::Alike(paths.clone()));
+        }
+    }
+    for (key, paths) in &keys {
+        if let Some(file) = key.ancestors().skip(1).find_map(|e| keys.get(e)) {
+            files.entry(file[0].clone()).or_insert_with(|| paths[0].clone());
+        }
+    }
+    alike.extend(files.into_iter().map(|(file, inside)| Collision::FileAsDir { file, inside }));
+    alike
+}
+
+/// A file of the torrent whose path could lead outside of the directory it is expected in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsafePath {
+    /// Where it is in the torrent's list of files, from 0.
+    pub index: usize,
+    /// As the torrent spells it, `/`-separated.
+    pub path: String,
+    pub problem: &'static str,
+}
+
+impl fmt::Display for UnsafePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "file {} of the torrent, {:?}, {}", self.index, self.path, self.problem)
+    }
+}
+
+/// A path of the torrent as it is expected on disk: `.` and empty segments are dropped, as
+/// clients do, while a `..`, a segment holding a separator or one that is a root or a drive is
+/// refused with what is wrong with it.
+fn plain_segments(segs: Vec<String>) -> Result<Vec<String>, &'static str> {
+    let mut plain = Vec::with_capacity(segs.len());
+    for seg in segs {
+        let mut components = Path::new(&seg).components();
+        match (components.next(), components.next()) {
+            _ if seg.is_empty() || seg == "." => continue,
+            (Some(Component::ParentDir), None) => return Err("goes up out of its directory"),
+            (Some(Component::RootDir | Component::Prefix(_)), _) => return Err("is absolute"),
+            (Some(Component::Normal(_)), None) if !seg.contains(['/', '\\']) => plain.push(seg),
+            _ => return Err("has a separator inside a name"),
+        }
+    }
+    match plain.is_empty() {
+        true => Err("is empty"),
+        false => Ok(plain),
+    }
+}
+
+/// The files of `torrent` whose paths [`Expected`] leaves out, which could otherwise have it
+/// compare, remove or create entries outside of the directory:
+///
+/// ```
+/// use librqbit_buffers::ByteBufOwned;
+/// use librqbit_core::torrent_metainfo::torrent_from_bytes_ext;
+/// use std::path::Path;
+/// use torrent_cleaner::scan::{unsafe_paths, Expected, ExpectedSet};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let crafted = ByteBufOwned::from(b"d4:infod5:filesld6:lengthi1e4:pathl2:..4:evileed6:length\
+///     i2e4:pathl1:a0:1:.1:beed6:lengthi3e4:pathl4:/etc6:passwdeed6:lengthi4e4:pathl2:okeee\
+///     4:name4:Show12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee".to_vec());
+/// let torrent = torrent_from_bytes_ext::<ByteBufOwned>(crafted.as_ref())?.meta;
+/// let found = unsafe_paths(&torrent);
+/// assert_eq!(found.iter().map(|e| (e.index, e.problem)).collect::<Vec<_>>(),
+///            [(0, "goes up out of its directory"), (2, "is absolute")]);
+/// assert_eq!(found[0].path, "../evil");
+///
+/// let expected = Expected::from_torrent(&torrent)?;
+/// assert_eq!(expected.files().collect::<Vec<_>>(), [(Path::new("a/b"), 2), (Path::new("ok"), 4)]);
+/// assert_eq!(expected.unsafe_paths(), found);
+/// let expected = Expected::from_torrent_in_parent(&torrent, false)?;
+/// assert_eq!(expected.files().map(|e| e.0).collect::<Vec<_>>(), ["Show/a/b", "Show/ok"]);
+/// # Ok(())
+/// # }
+/// ```
+pub fn unsafe_paths(torrent: &TorrentMetaV1<ByteBufOwned>) -> Vec<UnsafePath> {
+    torrent.info.files.iter().flatten().enumerate().filter_map(|(index, file)| {
+        let segs = torrent_segments(file);
+        let path = segs.join("/");
+        plain_segments(segs).err().map(|problem| UnsafePath { index, path, problem })
+    }).collect()
+}
+
+/// Where `file` is expected relative to the directory, or `None` for a path that could lead
+/// outside of it; see [`unsafe_paths`].
+pub fn plain_path(file: &TorrentMetaV1File<ByteBufOwned>) -> Option<PathBuf> {
+    plain_segments(torrent_segments(file)).ok().map(PathBuf::from_iter)
+}
+
+impl Expected {
+    /// Leaves out padding files; see [`Expected::from_torrent_with`].
+    pub fn from_torrent(torrent: &TorrentMetaV1<ByteBufOwned>) -> anyhow::Result<Self> {
+        Self::from_torrent_with(torrent, false)
+    }
+
+    /// With `keep_padding`, BEP 47 padding files are expected like any other file, for clients
+    /// that write them to disk. A single-file torrent expects the one file of its name, in the
+    /// directory it was downloaded to. Torrents with an empty `files` list, or only padding in it,
+    /// are read as expecting no files at all:
+    ///
+    /// ```
+    /// use librqbit_buffers::ByteBufOwned;
+    /// use librqbit_core::torrent_metainfo::torrent_from_bytes_ext;
+    /// use torrent_cleaner::scan::{Expected, ExpectedSet};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let padding_only = ByteBufOwned::from(b"d4:infod5:filesld4:attr1:p6:lengthi100e4:pathl4:\
+    ///     .pad3:100eee4:name4:Show12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee".to_vec());
+    /// let torrent = torrent_from_bytes_ext::<ByteBufOwned>(padding_only.as_ref())?.meta;
+    /// assert_eq!(Expected::from_torrent_with(&torrent, false)?.file_count(), 0);
+    /// assert_eq!(Expected::from_torrent_with(&torrent, true)?.file_count(), 1);
+    ///
+    /// let no_files = ByteBufOwned::from(b"d4:infod5:filesle4:name4:Show12:piece lengthi16384e\
+    ///     6:pieces0:ee".to_vec());
+    /// let torrent = torrent_from_bytes_ext::<ByteBufOwned>(no_files.as_ref())?.meta;
+    /// assert_eq!(Expected::from_torrent(&torrent)?.file_count(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_torrent_with(torrent: &TorrentMetaV1<ByteBufOwned>, keep_padding: bool)
+                             -> anyhow::Result<Self> {
+        let Some(vec) = &torrent.info.files else {
+            // The one file of a single-file torrent is named after it
+            return Self::from_files([(vec![plain_name(torrent)?],
+                                      torrent.info.length.unwrap_or_default())]);
+        };
+        let mut expected = Self::from_files(vec.iter().filter(|e| keep_padding || !is_padding(e))
+            .filter_map(|f| Some((plain_segments(torrent_segments(f)).ok()?, f.length))))?;
+        expected.unsafe_paths = unsafe_paths(torrent);
+        Ok(expected)
+    }
+
+    /// The torrent's files as a client downloads them into a shared directory, under the torrent's
+    /// name; a single-file torrent is the one file of that name.
+    pub fn from_torrent_in_parent(torrent: &TorrentMetaV1<ByteBufOwned>, keep_padding: bool)
+                                  -> anyhow::Result<Self> {
+        let name = plain_name(torrent)?;
+        let Some(vec) = &torrent.info.files else {
+            return Self::from_files([(vec![name], torrent.info.length.unwrap_or_default())]);
+        };
+        let mut expected = Self::from_files(vec.iter().filter(|e| keep_padding || !is_padding(e))
+            .filter_map(|f| {
+                let segs = plain_segments(torrent_segments(f)).ok()?;
+                Some((std::iter::once(name.clone()).chain(segs).collect(), f.length))
+            }))?;
+        expected.unsafe_paths = unsafe_paths(torrent);
+        Ok(expected)
+    }
+
+    /// The torrent's files as [`Expected::from_torrent_in_parent`] has them, but under the paths a
+    /// client renamed them to: `name` in place of the torrent's name, and `renamed` with a path
+    /// for each file of the torrent in its order, relative to the shared directory and
+    /// `/`-separated, or empty for a file kept under its own. `renamed` lists the padding files
+    /// too, or none of them, or is empty when no file was renamed. Fails if it lists another
+    /// number of files, or a path or name leading outside of the directory.
+    pub fn from_torrent_renamed(torrent: &TorrentMetaV1<ByteBufOwned>, keep_padding: bool,
+                                name: Option<&str>, renamed: &[String]) -> anyhow::Result<Self> {
+        let name = match name {
+            Some(name) if name.is_empty() || name == "." || name == ".."
+                || name.contains(['/', '\\']) => {
+                return Err(anyhow!("the name {:?} is not a plain file name", name));
+            }
+            Some(name) => name.to_string(),
+            None => plain_name(torrent)?,
+        };
+        // Those of an unsafe path are kept for the count, to be left out below
+        let files = match &torrent.info.files {
+            None => vec![(Some(vec![name]), torrent.info.length.unwrap_or_default(), false)],
+            Some(vec) => vec.iter().map(|f| {
+                let segs = plain_segments(torrent_segments(f)).ok()
+                    .map(|e| std::iter::once(name.clone()).chain(e).collect::<Vec<String>>());
+                (segs, f.length, is_padding(f))
+            }).collect(),
+        };
+        let with_padding = renamed.len() == files.len();
+        let unpadded = files.iter().filter(|e| !e.2).count();
+        if !renamed.is_empty() && !with_padding && renamed.len() != unpadded {
+            return Err(anyhow!("{} file paths are given for the {} files of the torrent",
+                               renamed.len(), unpadded));
+        }
+        let mut renamed = renamed.iter();
+        let mut expected = Vec::new();
+        for (segs, size, padding) in files {
+            let path = match padding && !with_padding {
+                true => None,
+                false => renamed.next().filter(|e| !e.is_empty()),
+            };
+            let segs = match path {
+                Some(path) => {
+                    let segs = path.split('/').map(str::to_string).collect::<Vec<String>>();
+                    let plain = |e: &String| !e.is_empty() && e != "." && e != ".."
+                        && !e.contains('\\');
+                    if !segs.iter().all(plain) {
+                        return Err(anyhow!("the file path {:?} is not a plain relative path",
+                                           path));
+                    }
+                    segs
+                }
+                None => match segs {
+                    Some(segs) => segs,
+                    None => continue,
+                },
+            };
+            if keep_padding || !padding {
+                expected.push((segs, size));
+            }
+        }
+        let mut expected = Self::from_files(expected)?;
+        expected.unsafe_paths = unsafe_paths(torrent);
+        Ok(expected)
+    }
+
+    /// The files given by their path segments and size, such as those of [`parse_file_list`],
+    /// expected just as a torrent's would be; the first segments are the top-level names.
+    pub fn from_files(files: impl IntoIterator<Item = (Vec<String>, u64)>) -> anyhow::Result<Self> {
+        let mut expected = Self { files: Vec::new(), surface_files: HashSet::new(), folded: None,
+                                  unsafe_paths: Vec::new() };
+        for (segs, size) in files {
+            let first = segs.first()
+                .ok_or(CliError::new(ErrorCode::TorrentInvalid, "Empty path"))?;
+            if !expected.surface_files.contains(OsStr::new(first)) {
+                expected.surface_files.insert(OsString::from(first));
+            }
+            expected.files.push((PathBuf::from_iter(&segs).into_boxed_path(), size));
+        }
+        expected.sort();
+        Ok(expected)
+    }
+
+    /// Sorts the files by path; of a path given twice, the size given last is kept.
+    fn sort(&mut self) {
+        self.files.sort_by(|a, b| a.0.cmp(&b.0));
+        self.files.dedup_by(|later, kept| {
+            let same = later.0 == kept.0;
+            if same {
+                kept.1 = later.1;
+            }
+            same
+        });
+        self.files.shrink_to_fit();
+    }
+
+    /// Adds the files of `other`, such as those of a torrent cross-seeded from the same
+    /// directory; a file only has to be expected by one of them. A file both expect keeps the size
+    /// it has here; those whose sizes disagree are returned with the other size.
+    pub fn merge(&mut self, other: Expected) -> Vec<(PathBuf, u64)> {
+        let mut conflicts = Vec::new();
+        let mut added = Vec::new();
+        for (path, size) in other.files {
+            match find(&self.files, &path) {
+                Some(kept) if *kept != size => conflicts.push((path.into_path_buf(), size)),
+                Some(_) => {}
+                None => added.push((path, size)),
+            }
+        }
+        if !added.is_empty() {
+            self.files.extend(added);
+            self.sort();
+        }
+        self.surface_files.extend(other.surface_files);
+        self.unsafe_paths.extend(other.unsafe_paths);
+        conflicts
+    }
+
+    /// The files of the torrents left out for their paths; see [`unsafe_paths`].
+    pub fn unsafe_paths(&self) -> &[UnsafePath] {
+        &self.unsafe_paths
+    }
+
+    /// Expects the files under each top-level name of `renames` under the new name instead, for
+    /// folders renamed on disk; call it before [`Expected::fold`]. Returns the old names that are
+    /// not top-level names.
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use torrent_cleaner::scan::{Expected, ExpectedSet};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut expected = Expected::from_files([
+    ///     (vec!["Some.Release.2023".into(), "Extras".into(), "trailer.mkv".into()], 3),
+    ///     (vec!["readme.txt".into()], 1),
+    /// ])?;
+    /// let renames = [("Some.Release.2023".into(), "Some Release (2023)".into()),
+    ///                ("Other".into(), "Another".into())];
+    /// assert_eq!(expected.rename_top_level(&renames), ["Other"]);
+    /// assert_eq!(expected.file_size(Path::new("Some Release (2023)/Extras/trailer.mkv")), Some(3));
+    /// assert!(expected.contains_dir(Path::new("Some Release (2023)/Extras")));
+    /// assert!(!expected.contains_dir(Path::new("Some.Release.2023")));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn rename_top_level(&mut self, renames: &[(String, String)]) -> Vec<String> {
+        let unknown = renames.iter()
+            .filter(|(old, _)| !self.surface_files.contains(OsStr::new(old)))
+            .map(|(old, _)| old.clone())
+            .collect();
+        let files = std::mem::take(&mut self.files).into_iter().map(|(path, size)| {
+            let mut segs = path.iter().map(|e| e.to_string_lossy().into_owned())
+                .collect::<Vec<String>>();
+            if let Some((_, new)) = renames.iter().find(|(old, _)| *old == segs[0]) {
+                segs[0] = new.clone();
+            }
+            (segs, size)
+        });
+        let unsafe_paths = std::mem::take(&mut self.unsafe_paths);
+        *self = Self::from_files(files).expect("expected paths are not empty");
+        self.unsafe_paths = unsafe_paths;
+        unknown
+    }
+
+    /// Matches the paths found on disk by their [`Folding::fold`]ed form from now on, while the
+    /// expected files keep their spelling; call it after any [`Expected::merge`]. Returns the
+    /// expected files that fold like an earlier one, in byte order, with that one; both are the
+    /// same file on disk.
+    ///
+    /// ```
+    /// # use std::fs;
+    /// use std::path::Path;
+    /// use torrent_cleaner::scan::{Expected, ExpectedSet, Folding};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join(format!("torrent-cleaner-case-{}", std::process::id()));
+    /// # fs::create_dir_all(dir.join("show"))?;
+    /// fs::write(dir.join("show/readme.txt"), "abc")?;
+    /// fs::write(dir.join("show/Cafe\u{301}.txt"), "de")?;
+    /// let mut expected = Expected::from_files([
+    ///     (vec!["Show".into(), "Readme.TXT".into()], 3),
+    ///     (vec!["Show".into(), "README.txt".into()], 3),
+    ///     (vec!["Show".into(), "Caf\u{e9}.txt".into()], 2),
+    /// ])?;
+    /// let collisions = expected.fold(Folding { case: true, unicode: true });
+    /// assert_eq!(collisions, [(Path::new("Show/README.txt").into(), Path::new("Show/Readme.TXT").into())]);
+    /// assert_eq!(expected.file_size(Path::new("show/readme.txt")), Some(3));
+    /// assert_eq!(expected.locate(&dir, Path::new("Show/README.txt")), dir.join("show/readme.txt"));
+    /// assert_eq!(expected.locate(&dir, Path::new("Show/Caf\u{e9}.txt")), dir.join("show/Cafe\u{301}.txt"));
+    /// assert_eq!(expected.matching(&dir), (3, 8));
+    /// # fs::remove_dir_all(dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fold(&mut self, folding: Folding) -> Vec<(PathBuf, PathBuf)> {
+        if folding == Folding::default() {
+            self.folded = None;
+            return Vec::new();
+        }
+        // In byte order, so the first of the files folding alike is kept
+        let mut order = (0..self.files.len()).collect::<Vec<usize>>();
+        order.sort_by_cached_key(|&i| path_key(&self.files[i].0));
+        let mut files = order.into_iter().map(|i| (folding.fold(&self.files[i].0), i))
+            .collect::<Vec<(PathBuf, usize)>>();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut collisions = Vec::new();
+        files.dedup_by(|later, kept| {
+            let same = later.0 == kept.0;
+            if same {
+                collisions.push((self.files[kept.1].0.to_path_buf(),
+                                 self.files[later.1].0.to_path_buf()));
+            }
+            same
+        });
+        collisions.sort_by_cached_key(|(_, e)| path_key(e));
+        self.folded = Some(Folded {
+            folding,
+            files,
+            surface: self.surface_files.iter().map(|e| folding.fold(Path::new(e)).into_os_string())
+                .collect(),
+            listings: Mutex::default(),
+        });
+        collisions
+    }
+}
+
+/// What a content directory should contain. [`Expected`] derives it from a torrent; any other
+/// source answering these lookups is scanned the same way:
+///
+/// ```
+/// # use librqbit_buffers::ByteBufOwned;
+/// # use librqbit_core::torrent_metainfo::torrent_from_bytes_ext;
+/// # use std::fs;
+/// use std::ffi::OsStr;
+/// use std::path::{Path, PathBuf};
+/// use torrent_cleaner::scan::{ExpectedSet, ScanOptions};
+/// use torrent_cleaner::{Scanner, TorrentFileSet};
+///
+/// /// A plain list of files, e.g. from a client's API.
+/// struct Listed(Vec<(PathBuf, u64)>);
+///
+/// impl ExpectedSet for Listed {
+///     fn file_size(&self, path: &Path) -> Option<u64> {
+///         self.0.iter().find(|(file, _)| file == path).map(|(_, size)| *size)
+///     }
+///
+///     fn contains_dir(&self, path: &Path) -> bool {
+///         self.0.iter().any(|(file, _)| file.ancestors().skip(1).any(|e| e == path))
+///     }
+///
+///     fn is_surface(&self, name: &OsStr) -> bool {
+///         self.0.iter().any(|(file, _)| file.iter().next() == Some(name))
+///     }
+///
+///     fn files(&self) -> impl Iterator<Item = (&Path, u64)> {
+///         self.0.iter().map(|(file, size)| (file.as_path(), *size))
+///     }
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # let bytes = ByteBufOwned::from(b"d4:infod5:filesld6:lengthi3e4:pathl7:Season17:ep1.mkveee\
+/// #     4:name4:Show12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee".to_vec());
+/// # let torrent = torrent_from_bytes_ext::<ByteBufOwned>(bytes.as_ref())?.meta;
+/// # let dir = std::env::temp_dir().join(format!("torrent-cleaner-listed-{}", std::process::id()));
+/// # fs::create_dir_all(dir.join("Season1/Samples"))?;
+/// # fs::write(dir.join("Season1/ep1.mkv"), "abc")?;
+/// # fs::write(dir.join("Season1/ep1.nfo"), "info")?;
+/// # fs::write(dir.join("Season1/Samples/s.mkv"), "sample")?;
+/// let listed = Listed(vec![(PathBuf::from("Season1/ep1.mkv"), 3)]);
+/// let options = ScanOptions { include_empty_dir: true, ..ScanOptions::default() };
+/// let scanner = Scanner::new(&dir).with_options(options);
+/// let (from_list, from_torrent) = (scanner.scan(&listed)?,
+///                                  scanner.scan(&TorrentFileSet::from_metainfo(&torrent)?)?);
+/// assert_eq!(from_list.extras(), from_torrent.extras());
+/// assert_eq!(from_list.plan().dirs, from_torrent.plan().dirs);
+/// assert_eq!(from_list.missing(), from_torrent.missing());
+/// # fs::remove_dir_all(dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait ExpectedSet: Sync {
+    /// Size of the expected file at `path`, relative to the content directory.
+    fn file_size(&self, path: &Path) -> Option<u64>;
+
+    /// Whether the directory at `path`, relative to the content directory, holds expected files.
+    fn contains_dir(&self, path: &Path) -> bool;
+
+    /// Whether `name` is one of the top-level names of the content.
+    fn is_surface(&self, name: &OsStr) -> bool;
+
+    /// The expected files, relative to the content directory, in no particular order.
+    fn files(&self) -> impl Iterator<Item = (&Path, u64)>;
+
+    fn file_count(&self) -> usize {
+        self.files().count()
+    }
+
+    /// Whether all files sit at the top level, without directories of their own.
+    fn is_flat(&self) -> bool {
+        self.files().all(|(path, _)| path.components().count() == 1)
+    }
+
+    fn total_size(&self) -> u64 {
+        self.files().map(|(_, size)| size).sum()
+    }
+
+    /// Where the expected file at `path` is looked for under `dir`; unless matched ignoring case,
+    /// the two joined.
+    fn locate(&self, dir: &Path, path: &Path) -> PathBuf {
+        dir.join(path)
+    }
+
+    /// Expected files that do not exist under `dir`, together with their total size.
+    fn missing(&self, dir: &Path) -> (Vec<PathBuf>, u64) {
+        let mut new_files = Vec::new();
+        let mut new_size: u64 = 0;
+        for (path, size) in self.files() {
+            let path = self.locate(dir, path);
+            if !path.exists() {
+                new_files.push(path);
+                new_size += size;
+            }
+        }
+        sort_paths(&mut new_files);
+        (new_files, new_size)
+    }
+
+    /// Expected files found under `dir` with another size, sorted by path. A file expected to be
+    /// empty only mismatches if it is not.
+    ///
+    /// ```
+    /// # use std::fs;
+    /// use torrent_cleaner::scan::{Expected, ExpectedSet};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// # let dir = std::env::temp_dir().join(format!("torrent-cleaner-size-{}", std::process::id()));
+    /// # fs::create_dir_all(dir.join("Season1"))?;
+    /// fs::write(dir.join("Season1/ep1.mkv"), "ab")?;
+    /// fs::write(dir.join("Season1/empty.txt"), "")?;
+    /// let expected = Expected::from_files([
+    ///     (vec!["Season1".into(), "ep1.mkv".into()], 5),
+    ///     (vec!["Season1".into(), "empty.txt".into()], 0),
+    /// ])?;
+    /// let mismatched = expected.mismatched(&dir);
+    /// assert_eq!(mismatched.len(), 1);
+    /// assert_eq!((mismatched[0].size, mismatched[0].expected), (2, 5));
+    /// assert_eq!(mismatched[0].delta(), -3);
+    /// # fs::remove_dir_all(dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn mismatched(&self, dir: &Path) -> Vec<Mismatch> {
+        let mut files = self.files()
+            .filter_map(|(path, expected)| {
+                let path = self.locate(dir, path);
+                let meta = fs::metadata(&path).ok().filter(|m| m.is_file() && m.len() != expected)?;
+                Some(Mismatch { path, size: meta.len(), expected })
+            })
+            .collect::<Vec<Mismatch>>();
+        files.sort_by_cached_key(|e| path_key(&e.path));
+        files
+    }
+
+    /// Counts the files found under `dir` with the expected size, and their size.
+    fn matching(&self, dir: &Path) -> (usize, u64) {
+        self.files()
+            .filter(|(path, size)| fs::metadata(self.locate(dir, path))
+                .is_ok_and(|m| m.is_file() && m.len() == *size))
+            .fold((0, 0), |(count, total), (_, size)| (count + 1, total + size))
+    }
+}
+
+impl ExpectedSet for Expected {
+    fn file_size(&self, path: &Path) -> Option<u64> {
+        match &self.folded {
+            Some(folded) => find(&folded.files, &folded.folding.fold(path))
+                .map(|&i| self.files[i].1),
+            None => find(&self.files, path).copied(),
+        }
+    }
+
+    /// Also true for the path of a file, so a directory in its place is kept.
+    fn contains_dir(&self, path: &Path) -> bool {
+        match &self.folded {
+            Some(folded) => holds(&folded.files, &folded.folding.fold(path)),
+            None => holds(&self.files, path),
+        }
+    }
+
+    fn is_surface(&self, name: &OsStr) -> bool {
+        match &self.folded {
+            Some(folded) => folded.surface.contains(&folded.fold_name(name)),
+            None => self.surface_files.contains(name),
+        }
+    }
+
+    /// Ignoring case, the file as spelled on disk if there is one.
+    fn locate(&self, dir: &Path, path: &Path) -> PathBuf {
+        self.folded.as_ref().and_then(|e| e.locate(dir, path)).unwrap_or_else(|| dir.join(path))
+    }
+
+    fn files(&self) -> impl Iterator<Item = (&Path, u64)> {
+        self.files.iter().map(|(path, size)| (&**path, *size))
+    }
+
+    fn file_count(&self) -> usize {
+        self.files.len()
+    }
+}
+
+/// A file of the torrent found with another size than it should have, e.g. truncated or only
+/// partly downloaded.
+pub struct Mismatch {
+    pub path: PathBuf,
+    /// Bytes on disk.
+    pub size: u64,
+    pub expected: u64,
+}
+
+impl Mismatch {
+    /// Bytes on disk less those expected; negative for short files.
+    pub fn delta(&self) -> i64 {
+        self.size as i64 - self.expected as i64
+    }
+}
+
+/// An entry of the JSON file list of `list --output json`.
+#[derive(Serialize, Deserialize)]
+pub struct ListedFile {
+    /// Path inside the content directory, `/`-separated.
+    pub path: String,
+    pub size: u64,
+}
+
+fn segments(path: &Path) -> Vec<String> {
+    path.iter().map(|e| e.to_string_lossy().into_owned()).collect()
+}
+
+/// The expected files sorted by path, for `list --output json`.
+pub fn file_list(expected: &impl ExpectedSet) -> Vec<ListedFile> {
+    let mut files = expected.files().collect::<Vec<(&Path, u64)>>();
+    files.sort_by_cached_key(|(path, _)| path_key(path));
+    files.into_iter().map(|(path, size)| ListedFile { path: segments(path).join("/"), size })
+        .collect()
+}
+
+/// The expected files sorted by path, one per line: the `/`-separated path, a tab and the size.
+/// Backslashes, tabs and line breaks in the path are escaped as `\\`, `\t`, `\n` and `\r`.
+pub fn file_list_tsv(expected: &impl ExpectedSet) -> String {
+    let mut tsv = String::new();
+    for file in file_list(expected) {
+        for c in file.path.chars() {
+            match c {
+                '\\' => tsv.push_str("\\\\"),
+                '\t' => tsv.push_str("\\t"),
+                '\n' => tsv.push_str("\\n"),
+                '\r' => tsv.push_str("\\r"),
+                c => tsv.push(c),
+            }
+        }
+        tsv.push('\t');
+        tsv.push_str(&file.size.to_string());
+        tsv.push('\n');
+    }
+    tsv
+}
+
+fn invalid_list(message: String) -> anyhow::Error {
+    CliError::new(ErrorCode::TorrentInvalid, message).into()
+}
+
+fn unescape(path: &str) -> Option<String> {
+    let mut text = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        text.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(text)
+}
+
+/// Reads a file list written by `list --output tsv` or `list --output json` into path segments
+/// and sizes for [`Expected::from_files`]. Paths must be relative, without `.`, `..` or empty
+/// segments; anything else fails with [`ErrorCode::TorrentInvalid`]. Read back, a torrent's
+/// list plans exactly like the torrent:
+///
+/// ```
+/// # use librqbit_buffers::ByteBufOwned;
+/// # use librqbit_core::torrent_metainfo::torrent_from_bytes_ext;
+/// # use std::fs;
+/// use torrent_cleaner::scan::{file_list_tsv, parse_file_list, Expected, ScanOptions};
+/// use torrent_cleaner::Scanner;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # let bytes = ByteBufOwned::from(b"d4:infod5:filesld6:lengthi3e4:pathl7:Season17:ep1.mkveee\
+/// #     4:name4:Show12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee".to_vec());
+/// # let torrent = torrent_from_bytes_ext::<ByteBufOwned>(bytes.as_ref())?.meta;
+/// # let dir = std::env::temp_dir().join(format!("torrent-cleaner-list-{}", std::process::id()));
+/// # fs::create_dir_all(dir.join("Season1/Samples"))?;
+/// # fs::create_dir_all(dir.join("Extras"))?;
+/// # fs::write(dir.join("Season1/ep1.mkv"), "abc")?;
+/// # fs::write(dir.join("Season1/Samples/s.mkv"), "sample")?;
+/// let from_torrent = Expected::from_torrent(&torrent)?;
+/// let tsv = file_list_tsv(&from_torrent);
+/// assert_eq!(tsv, "Season1/ep1.mkv\t3\n");
+/// let from_list = Expected::from_files(parse_file_list(&tsv)?)?;
+///
+/// let options = ScanOptions { include_surface: true, include_empty_dir: true,
+///                             ..ScanOptions::default() };
+/// let scanner = Scanner::new(&dir).with_options(options);
+/// let (listed, torrent) = (scanner.scan(&from_list)?, scanner.scan(&from_torrent)?);
+/// assert_eq!(listed.extras(), torrent.extras());
+/// assert_eq!(listed.plan().dirs, torrent.plan().dirs);
+/// assert_eq!(listed.missing(), torrent.missing());
+/// # fs::remove_dir_all(dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn parse_file_list(content: &str) -> anyhow::Result<Vec<(Vec<String>, u64)>> {
+    let files = match content.trim_start().starts_with('[') {
+        true => serde_json::from_str::<Vec<ListedFile>>(content)
+            .map_err(|e| invalid_list(format!("Invalid JSON file list: {}", e)))?
+            .into_iter().map(|e| (e.path, e.size)).collect::<Vec<(String, u64)>>(),
+        false => content.lines().enumerate()
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(i, line)| line.rsplit_once('\t')
+                .and_then(|(path, size)| Some((unescape(path)?, size.parse().ok()?)))
+                .ok_or_else(|| invalid_list(format!("Invalid file list line {}: expected a \
+                    path, a tab and a size", i + 1))))
+            .collect::<anyhow::Result<Vec<(String, u64)>>>()?,
+    };
+    files.into_iter().map(|(path, size)| {
+        let segs = path.split('/').map(str::to_string).collect::<Vec<String>>();
+        let normal = |seg: &String| matches!(Path::new(seg).components().collect::<Vec<_>>()[..],
+                                              [Component::Normal(_)]);
+        match segs.iter().all(normal) {
+            true => Ok((segs, size)),
+            false => Err(invalid_list(format!("Invalid path in file list: {}", path))),
+        }
+    }).collect()
+}
+
+#[derive(Clone)]
+pub struct ScanOptions {
+    pub include_surface: bool,
+    pub include_empty_dir: bool,
+    pub prune: Prune,
+    pub exclude: Exclude,
+    /// Paths relative to the scanned directory that are never removed, nor anything inside them.
+    pub keep: HashSet<PathBuf>,
+    /// Sort the entries found; otherwise they keep the order they were discovered in.
+    pub sorted: bool,
+    /// The order of the extra files and empty directories when `sorted`; the others are sorted
+    /// by path.
+    pub order: SortOrder,
+    /// Only entries owned by this user id are candidates for removal.
+    pub owner: Option<u32>,
+    /// Remove orphaned client droppings like other extras instead of keeping them.
+    pub clean_droppings: bool,
+    /// Remove the downloads in progress of files of the torrent like other extras instead of
+    /// keeping them.
+    pub remove_incomplete: bool,
+    /// Walk into the snapshot and recycle areas of [`SNAPSHOT_DIRS`] like other directories,
+    /// rather than leaving them alone unread.
+    pub include_snapshots: bool,
+    /// Entries never removed as the private tracker of the torrent may require them, matched
+    /// like `exclude`; see [`SkipReason::Private`].
+    pub private: Exclude,
+    /// Treat files of the torrent that have another size as extras, so the client downloads
+    /// them again.
+    pub remove_mismatched: bool,
+    /// The only extras removed, if given; see [`SkipReason::NotDeletable`].
+    pub deletable: Option<Deletable>,
+    /// Walk the directory with this many threads; 1 walks it on the calling thread. With more,
+    /// the entries are only found in a deterministic order if `sorted`.
+    pub threads: usize,
+    pub symlinks: Symlinks,
+    /// Record the entries that cannot be read in [`Scan::unreadable`] and go on, rather than
+    /// failing the scan. Directories that could not be listed are never planned for removal.
+    pub skip_errors: bool,
+    /// Which extras count as junk and whether they are kept, removed, or the only ones removed.
+    pub junk: Junk,
+    /// Keep the extra files that have links outside the removal, since removing them frees
+    /// nothing.
+    pub skip_hardlinked: bool,
+    /// Walk no deeper than this below the scanned directory. A directory this deep that is not
+    /// part of the torrent is an extra as a whole, with the size of everything inside it; one of
+    /// the torrent's is left unexamined.
+    pub max_depth: Option<usize>,
+    /// Stay on the file system of the scanned directory: directories on another one, such as
+    /// mount points, are left alone unread.
+    pub one_file_system: bool,
+    /// Keep the extra files smaller than this many bytes.
+    pub min_size: Option<u64>,
+    /// Keep the extra files larger than this many bytes.
+    pub max_size: Option<u64>,
+    /// Keep the extra files modified more recently than this long ago.
+    pub older_than: Option<Duration>,
+    /// Keep the extra files modified longer than this ago.
+    pub newer_than: Option<Duration>,
+    /// Files never removed, nor the directories holding them: those the run reads or writes.
+    /// Absolute or relative to the working directory, they are matched however the scanned
+    /// directory is reached through links, and need not exist yet.
+    pub protected: HashSet<PathBuf>,
+    /// Read the [`IGNORE_FILE`] of the scanned directory into `exclude` before scanning it; see
+    /// [`ScanOptions::load_ignore_file`].
+    pub ignore_file: bool,
+}
+
+/// What becomes of the symbolic links found in the directory. A link is only ever removed
+/// itself, never what it points to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Never removed, nor are the directories holding them.
+    Keep,
+    /// Removed like other extras unless at a path of the torrent; linked directories are not
+    /// walked into.
+    #[default]
+    Remove,
+    /// Taken for what they point to: linked directories inside the scanned one are walked into,
+    /// and what links at paths of the torrent point to is kept. Entries found through a linked
+    /// directory are never removed through it, nor are links leading out of the directory; an
+    /// extra also found through one is listed once, with the other path as an alias.
+    Follow,
+}
+
+/// What the entries of a scan are ordered by, with [`ScanOptions::sorted`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// Byte-wise with `/` as the separator, as [`sort_paths`] does.
+    #[default]
+    Path,
+    /// Smallest first, by the size [`Scan::rm_size`] counts.
+    Size,
+    /// Oldest first by modification time, those without one first.
+    Mtime,
+}
+
+/// How the entries of a scan are listed; entries alike by the key are by path, so the order is
+/// the same from one run to the next. Removals go deepest first whatever the order.
+///
+/// ```
+/// use std::fs;
+/// use torrent_cleaner::scan::{Expected, ScanOptions, SortKey, SortOrder};
+/// use torrent_cleaner::Scanner;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let dir = std::env::temp_dir().join(format!("torrent-cleaner-sort-{}", std::process::id()));
+/// fs::create_dir_all(dir.join("Show/Extras"))?;
+/// fs::write(dir.join("Show/ep1.mkv"), b"video")?;
+/// fs::write(dir.join("Show/b.txt"), b"bb")?;
+/// fs::write(dir.join("Show/c.txt"), b"c")?;
+/// fs::write(dir.join("Show/Extras/a.txt"), b"aa")?;
+///
+/// let expected = Expected::from_files([(vec!["Show".into(), "ep1.mkv".into()], 5)])?;
+/// let extras = |key, reverse| -> anyhow::Result<Vec<_>> {
+///     let options = ScanOptions { order: SortOrder { key, reverse }, ..ScanOptions::default() };
+///     let report = Scanner::new(&dir).with_options(options).scan(&expected)?;
+///     Ok(report.extras().iter().map(|e| e.strip_prefix(&dir).unwrap().to_path_buf()).collect())
+/// };
+/// assert_eq!(extras(SortKey::Path, false)?,
+///            ["Show/Extras/a.txt", "Show/b.txt", "Show/c.txt"].map(std::path::PathBuf::from));
+/// assert_eq!(extras(SortKey::Size, false)?,
+///            ["Show/c.txt", "Show/Extras/a.txt", "Show/b.txt"].map(std::path::PathBuf::from));
+/// assert_eq!(extras(SortKey::Size, true)?,
+///            ["Show/b.txt", "Show/Extras/a.txt", "Show/c.txt"].map(std::path::PathBuf::from));
+/// # fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SortOrder {
+    pub key: SortKey,
+    /// Turn the whole order around, ties included.
+    pub reverse: bool,
+}
+
+impl SortOrder {
+    /// Sorts `entries` by the key of their `path` and, for [`SortKey::Size`] and
+    /// [`SortKey::Mtime`], their `stamp`.
+    pub fn sort<T>(self, entries: &mut [T], path: impl Fn(&T) -> &Path,
+                   stamp: impl Fn(&T) -> Option<Stamp>) {
+        entries.sort_by_cached_key(|entry| {
+            let (size, modified) = match self.key {
+                SortKey::Path => (None, None),
+                SortKey::Size => (stamp(entry).map(|e| e.size), None),
+                SortKey::Mtime => (None, stamp(entry).and_then(|e| e.modified)),
+            };
+            (size, modified, path_key(path(entry)))
+        });
+        if self.reverse {
+            entries.reverse();
+        }
+    }
+
+    /// Sorts the extras at `paths` by their stamps of the scan; those without one have none.
+    pub fn sort_paths(self, paths: &mut [PathBuf], stamps: &HashMap<PathBuf, Stamp>) {
+        self.sort(paths, |e| e, |e| stamps.get(e).copied());
+    }
+}
+
+/// Only the extras inside the torrent's top-level names, sorted, without empty directories,
+/// walking on the calling thread.
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self { include_surface: false, include_empty_dir: false, prune: Prune::default(),
+               exclude: Exclude::default(), keep: HashSet::new(), sorted: true,
+               order: SortOrder::default(), owner: None, clean_droppings: false,
+               include_snapshots: false, private: Exclude::default(), remove_incomplete: false,
+               remove_mismatched: false, deletable: None, threads: 1, symlinks: Symlinks::Remove,
+               skip_errors: false, junk: Junk::default(), skip_hardlinked: false, max_depth: None,
+               one_file_system: false, min_size: None, max_size: None, older_than: None,
+               newer_than: None, protected: HashSet::new(), ignore_file: false }
+    }
+}
+
+impl ScanOptions {
+    /// Adds the patterns of the [`IGNORE_FILE`] at the root of `dir`, if there is one, to
+    /// `exclude` with `ignore_file`, which is then cleared so they are not added twice.
+    pub fn load_ignore_file(&mut self, dir: &Path) -> anyhow::Result<()> {
+        if !std::mem::take(&mut self.ignore_file) {
+            return Ok(());
+        }
+        let file = dir.join(IGNORE_FILE);
+        let text = match fs::read_to_string(&file) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            result => result.with_context(|| format!("Failed to read {}", file.display()))?,
+        };
+        self.exclude = std::mem::take(&mut self.exclude).with_ignore_file(&text)?;
+        Ok(())
+    }
+
+    /// Why the entry at `path` (relative to the scanned directory), a directory if `is_dir`, is
+    /// left alone whatever else it is, if it is: the ignore file itself, `exclude`, `private`,
+    /// `keep` and `junk`.
+    pub fn protection(&self, path: &Path, is_dir: bool) -> Option<SkipReason> {
+        match () {
+            _ if path == Path::new(IGNORE_FILE) => Some(SkipReason::Excluded),
+            _ if self.exclude.matches(path, is_dir) => Some(SkipReason::Excluded),
+            _ if self.private.matches(path, is_dir) => Some(SkipReason::Private),
+            _ if path.ancestors().any(|e| self.keep.contains(e)) => Some(SkipReason::KeepList),
+            _ => match self.junk.mode {
+                JunkMode::Keep if self.junk.matches(path) => Some(SkipReason::Junk),
+                JunkMode::Only if !self.junk.matches(path) => Some(SkipReason::NotJunk),
+                _ => None,
+            },
+        }
+    }
+
+    /// Whether an entry with this metadata may be removed under the `owner` restriction.
+    pub fn owns(&self, meta: &fs::Metadata) -> bool {
+        self.owner.is_none_or(|uid| owner_of(meta) == Some(uid))
+    }
+}
+
+/// `--prune-dir` patterns: directories matching them are not descended into at all.
+#[derive(Clone, Default)]
+pub struct Prune {
+    /// Patterns without a `/`, matched against the directory name.
+    names: Vec<GlobMatcher>,
+    /// Patterns with a `/`, matched against the path relative to the scanned directory.
+    paths: Vec<GlobMatcher>,
+}
+
+impl Prune {
+    pub fn new<'a>(patterns: impl IntoIterator<Item = &'a String>) -> anyhow::Result<Self> {
+        let mut prune = Self::default();
+        for pattern in patterns {
+            let glob = GlobBuilder::new(pattern.trim_matches('/')).literal_separator(true).build()
+                .with_context(|| format!("Invalid --prune-dir pattern: {}", pattern))?
+                .compile_matcher();
+            match pattern.trim_matches('/').contains('/') {
+                true => prune.paths.push(glob),
+                false => prune.names.push(glob),
+            }
+        }
+        Ok(prune)
+    }
+
+    /// Whether the directory at `path` (relative to the scanned directory) is pruned.
+    pub fn matches(&self, path: &Path) -> bool {
+        path.file_name().is_some_and(|name| self.names.iter().any(|e| e.is_match(name)))
+            || self.paths.iter().any(|e| e.is_match(path))
+    }
+
+    fn matches_under(&self, root: &Path, path: &Path) -> bool {
+        path.strip_prefix(root).is_ok_and(|rel| self.matches(rel))
+    }
+}
+
+/// An `--exclude` pattern or one of an [`IGNORE_FILE`].
+#[derive(Clone)]
+struct Pattern {
+    matcher: GlobMatcher,
+    /// Ends with a `/`.
+    dir_only: bool,
+    /// Holds a `/` other than a trailing one, so it matches the full path.
+    anchored: bool,
+    /// Starts with a `!`, in an ignore file.
+    negated: bool,
+    /// Where it comes from and how it is spelled, to tell which one excluded an entry.
+    source: String,
+}
+
+impl Pattern {
+    fn new(pattern: &str, source: String) -> anyhow::Result<Self> {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, pattern.strip_prefix('\\').unwrap_or(pattern)),
+        };
+        let glob = pattern.trim_matches('/');
+        let matcher = GlobBuilder::new(glob).literal_separator(true).build()
+            .with_context(|| format!("Invalid pattern {}", source))?
+            .compile_matcher();
+        Ok(Self { matcher, dir_only: pattern.ends_with('/'),
+                  anchored: pattern.starts_with('/') || glob.contains('/'), negated, source })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && match self.anchored {
+            true => self.matcher.is_match(path),
+            false => path.file_name().is_some_and(|name| self.matcher.is_match(name)),
+        }
+    }
+}
+
+/// `--exclude` patterns, gitignore-style: without a `/` they match the name at any depth, with one
+/// the path relative to the scanned directory, and a trailing `/` only matches directories.
+/// Everything inside a matching directory is excluded along with it. Those of an [`IGNORE_FILE`]
+/// add to them; see [`Exclude::with_ignore_file`].
+#[derive(Clone, Default)]
+pub struct Exclude {
+    patterns: Vec<Pattern>,
+    /// Those of an ignore file, of which the last one matching an entry decides.
+    ignored: Vec<Pattern>,
+}
+
+impl Exclude {
+    pub fn new<'a>(patterns: impl IntoIterator<Item = &'a String>) -> anyhow::Result<Self> {
+        let mut exclude = Self::default();
+        for pattern in patterns {
+            if pattern.starts_with('!') {
+                return Err(anyhow!("Negated --exclude patterns are not supported: {}", pattern));
+            }
+            exclude.patterns.push(Pattern::new(pattern, format!("--exclude {}", pattern))?);
+        }
+        Ok(exclude)
+    }
+
+    /// Adds the patterns of an [`IGNORE_FILE`] holding `text`, one per line, with blank lines and
+    /// those starting with `#` left out and trailing spaces ignored. They are spelled like those
+    /// of `--exclude`, and as in a `.gitignore` a pattern starting with `!` takes back what the
+    /// patterns above it excluded, `\` escaping a leading `!` or `#`. An entry inside an excluded
+    /// directory cannot be taken back, and nor can one `--exclude` matches: an entry either of
+    /// them excludes is excluded, and `--exclude` patterns come first, so that they are the ones
+    /// [`Exclude::matched`] names when both match.
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use torrent_cleaner::scan::Exclude;
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let exclude = Exclude::default().with_ignore_file("# edited by hand\n*.nfo\n!Show.nfo\n\
+    ///     screens/\n!screens/cover.jpg\n/Extras/*.srt\n")?;
+    /// assert!(exclude.matches(Path::new("Season1/ep1.nfo"), false));
+    /// assert!(!exclude.matches(Path::new("Season1/Show.nfo"), false));
+    /// assert!(exclude.matches(Path::new("screens"), true));
+    /// assert!(!exclude.matches(Path::new("Season1/screens"), false));
+    /// assert!(exclude.matches(Path::new("screens/cover.jpg"), false));
+    /// assert!(exclude.matches(Path::new("Extras/en.srt"), false));
+    /// assert!(!exclude.matches(Path::new("Season1/Extras/en.srt"), false));
+    /// assert_eq!(exclude.matched(Path::new("a.nfo"), false),
+    ///            Some(".torrentcleanerignore:2 *.nfo"));
+    ///
+    /// // Along with `--exclude`, a `!` line takes back neither what it matches nor what lies
+    /// // inside a directory it matches
+    /// let cli = ["keep.txt", "*.srt", "samples/"].map(String::from);
+    /// let exclude = Exclude::new(&cli)?
+    ///     .with_ignore_file("*.jpg\n!keep.txt\n!en.srt\n!samples/s.mkv\n!*.nfo\n")?;
+    /// assert_eq!(exclude.matched(Path::new("keep.txt"), false), Some("--exclude keep.txt"));
+    /// assert_eq!(exclude.matched(Path::new("Extras/en.srt"), false), Some("--exclude *.srt"));
+    /// assert_eq!(exclude.matched(Path::new("samples/s.mkv"), false), Some("--exclude samples/"));
+    /// assert_eq!(exclude.matched(Path::new("cover.jpg"), false),
+    ///            Some(".torrentcleanerignore:1 *.jpg"));
+    /// assert!(!exclude.matches(Path::new("Show.nfo"), false));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_ignore_file(mut self, text: &str) -> anyhow::Result<Self> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r').trim_end_matches(' ');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let source = format!("{}:{} {}", IGNORE_FILE, i + 1, line);
+            self.ignored.push(Pattern::new(line, source)?);
+        }
+        Ok(self)
+    }
+
+    /// The pattern excluding the entry itself, if any: the first `--exclude` one matching it, or
+    /// the last one of the ignore file unless that one is negated.
+    fn matched_entry(&self, path: &Path, is_dir: bool) -> Option<&Pattern> {
+        self.patterns.iter().find(|e| e.matches(path, is_dir))
+            .or_else(|| self.ignored.iter().rev().find(|e| e.matches(path, is_dir))
+                .filter(|e| !e.negated))
+    }
+
+    /// Whether the entry at `path` (relative to the scanned directory), a directory if `is_dir`,
+    /// matches a pattern or lies inside a directory that does.
+    pub fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        self.matched(path, is_dir).is_some()
+    }
+
+    /// Where the pattern excluding the entry at `path` comes from and how it is spelled, such as
+    /// `--exclude *.nfo` or `.torrentcleanerignore:3 screens/`; see [`Exclude::matches`].
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<&str> {
+        if self.patterns.is_empty() && self.ignored.is_empty() {
+            return None;
+        }
+        self.matched_entry(path, is_dir)
+            .or_else(|| path.ancestors().skip(1).take_while(|e| !e.as_os_str().is_empty())
+                .find_map(|e| self.matched_entry(e, true)))
+            .map(|e| e.source.as_str())
+    }
+}
+
+/// `--deletable-ext` extensions: the only extra files removed are those whose name ends, ignoring
+/// case, with a dot and one of them. An extension may have dots of its own, so `sample.mkv`
+/// allows `Show.sample.mkv` but not `Show.mkv`. Directories are never removed as a whole under
+/// it, only once the files inside them are.
+///
+/// ```
+/// use std::path::Path;
+/// use torrent_cleaner::scan::Deletable;
+///
+/// let exts = ["rar", ".NFO", "sample.mkv"].map(String::from);
+/// let deletable = Deletable::new(&exts);
+/// assert!(deletable.allows(Path::new("Season1/ep1.rar")));
+/// assert!(deletable.allows(Path::new("Show.nfo")));
+/// assert!(deletable.allows(Path::new("Season1/Show.SAMPLE.mkv")));
+/// assert!(!deletable.allows(Path::new("Season1/ep1.mkv")));
+/// assert!(!deletable.allows(Path::new("Season1/rar")));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Deletable {
+    /// The extensions in lowercase, each with its leading dot.
+    suffixes: Vec<String>,
+}
+
+impl Deletable {
+    pub fn new<'a>(exts: impl IntoIterator<Item = &'a String>) -> Self {
+        Self { suffixes: exts.into_iter()
+            .map(|e| format!(".{}", e.trim_start_matches('.').to_lowercase()))
+            .collect() }
+    }
+
+    /// Whether the file at `path` may be removed.
+    pub fn allows(&self, path: &Path) -> bool {
+        path.file_name().map(|e| e.to_string_lossy().to_lowercase())
+            .is_some_and(|name| self.suffixes.iter().any(|e| name.ends_with(e.as_str())))
+    }
+}
+
+/// Reads a `--keep-from` list: one path relative to the scanned directory per line, with blank
+/// lines and lines starting with `#` ignored. `.` segments are dropped; a path with `..` or an
+/// absolute one is an error.
+///
+/// ```
+/// use std::path::Path;
+/// use torrent_cleaner::scan::parse_keep_list;
+///
+/// let keep = parse_keep_list("# kept by hand\nextras/commentary.mkv\n\n./screens/\n").unwrap();
+/// assert!(keep.contains(Path::new("extras/commentary.mkv")));
+/// assert!(keep.contains(Path::new("screens")));
+/// assert!(parse_keep_list("../other/file.mkv").is_err());
+/// ```
+pub fn parse_keep_list(content: &str) -> anyhow::Result<HashSet<PathBuf>> {
+    let mut keep = HashSet::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut path = PathBuf::new();
+        for component in Path::new(line).components() {
+            match component {
+                Component::Normal(name) => path.push(name),
+                Component::CurDir => {}
+                _ => return Err(anyhow!("Line {}: {} is not inside the scanned directory", i + 1,
+                                        line)),
+            }
+        }
+        if !path.as_os_str().is_empty() {
+            keep.insert(path);
+        }
+    }
+    Ok(keep)
+}
+
+/// Why an entry that is not part of the torrent is left alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Outside the torrent's top-level names, without `--surface`.
+    OutOfScope,
+    /// A directory matching `--prune-dir`; its contents are never read.
+    Pruned,
+    /// Matching `--exclude`, or inside a directory that does.
+    Excluded,
+    /// Listed in the `--keep-from` file, or inside a directory that is.
+    KeepList,
+    /// Owned by a user other than the one `--only-owned` or `--owner` selects.
+    NotOwned,
+    /// A torrent client's partial or bookkeeping file for a file no longer in the torrent, without
+    /// `--clean-droppings`.
+    Dropping,
+    /// Vetoed by `--filter-hook`.
+    Hook,
+    /// Replaced or modified after the scan, found when about to remove it.
+    Changed,
+    /// A symbolic link with `--symlinks keep`, or one `--symlinks follow` leaves alone.
+    Symlink,
+    /// Could not be read, with `--skip-errors`.
+    Unreadable,
+    /// Junk, or inside a junk directory, with `--junk keep`.
+    Junk,
+    /// Not junk, with `--junk only`.
+    NotJunk,
+    /// A torrent client's partial file for a file of the torrent, or one in qBittorrent's
+    /// `.unwanted` folder, without `--remove-incomplete`.
+    InProgress,
+    /// A file with links outside the removal, with `--skip-hardlinked`.
+    Hardlinked,
+    /// A directory at `--max-depth` that is part of the torrent, whose contents are never read,
+    /// or one holding an entry left alone for another reason, which is then kept whole.
+    TooDeep,
+    /// A directory on another file system than the scanned one, with `--one-file-system`; its
+    /// contents are never read.
+    OtherFileSystem,
+    /// A file smaller than `--min-size`.
+    BelowMinSize,
+    /// A file larger than `--max-size`.
+    AboveMaxSize,
+    /// A file modified more recently than `--older-than`.
+    TooNew,
+    /// A file modified longer ago than `--newer-than`.
+    TooOld,
+    /// A file whose modification time cannot be read, with `--older-than` or `--newer-than`.
+    UnknownAge,
+    /// A file the run itself reads or writes, such as the torrent file or a report, or a
+    /// directory holding one; see [`ScanOptions::protected`].
+    InUse,
+    /// A missing file of the torrent under another path, with `--fix-renames` or `--relink`.
+    Renamed,
+    /// Hardlinked or copied to a missing file of the torrent by `--relink`.
+    Relinked,
+    /// Possibly a file of the torrent under another spelling, for files of it that collide on
+    /// disk; see [`Scan::colliding`].
+    Colliding,
+    /// A snapshot or recycle area of [`SNAPSHOT_DIRS`], without `--include-snapshots`; its
+    /// contents are never read.
+    Snapshot,
+    /// Matching what is left alone on a private torrent, such as its nfo file, or inside a
+    /// directory that does; see [`ScanOptions::private`].
+    Private,
+    /// Unselected in the list `clean --interactive` asks about.
+    Deselected,
+    /// An extra without an extension of `--deletable-ext`, or a directory of them, left for
+    /// manual review; see [`ScanOptions::deletable`].
+    NotDeletable,
+}
+
+impl SkipReason {
+    pub fn describe(self) -> &'static str {
+        match self {
+            SkipReason::OutOfScope => "outside scope",
+            SkipReason::Pruned => "pruned (contents unknown)",
+            SkipReason::Excluded => "excluded",
+            SkipReason::KeepList => "on keep list",
+            SkipReason::NotOwned => "owned by others",
+            SkipReason::Dropping => "client droppings",
+            SkipReason::Hook => "skipped by hook",
+            SkipReason::Changed => "changed since plan",
+            SkipReason::Symlink => "symbolic links",
+            SkipReason::Unreadable => "unreadable",
+            SkipReason::Junk => "junk",
+            SkipReason::NotJunk => "not junk",
+            SkipReason::InProgress => "downloads in progress",
+            SkipReason::Hardlinked => "hardlinked elsewhere",
+            SkipReason::TooDeep => "beyond --max-depth",
+            SkipReason::OtherFileSystem => "on another file system",
+            SkipReason::BelowMinSize => "below --min-size",
+            SkipReason::AboveMaxSize => "above --max-size",
+            SkipReason::TooNew => "newer than --older-than",
+            SkipReason::TooOld => "older than --newer-than",
+            SkipReason::UnknownAge => "modification time unknown",
+            SkipReason::InUse => "used by this run",
+            SkipReason::Renamed => "renamed torrent files",
+            SkipReason::Relinked => "linked into the torrent",
+            SkipReason::Colliding => "colliding torrent paths",
+            SkipReason::Snapshot => "snapshot or recycle area",
+            SkipReason::Private => "kept for private tracker",
+            SkipReason::Deselected => "kept by selection",
+            SkipReason::NotDeletable => "requires manual review",
+        }
+    }
+}
+
+/// Why a directory that is not part of the torrent survives `--empty-dir`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirKeptReason {
+    /// It holds a file or directory of the torrent.
+    TorrentEntry,
+    /// A planned removal inside it was not carried out, e.g. declined or left for lack of space.
+    NotRemoved,
+    /// Something was added to it since the scan.
+    NotEmpty,
+    /// Removing it failed with an OS error.
+    RemoveFailed,
+    /// It holds an entry left alone for this reason; serialized as the reason itself.
+    #[serde(untagged)]
+    Skipped(SkipReason),
+}
+
+impl DirKeptReason {
+    pub fn describe(self) -> &'static str {
+        match self {
+            DirKeptReason::TorrentEntry => "holds part of the torrent",
+            DirKeptReason::NotRemoved => "holds an entry that was not removed",
+            DirKeptReason::NotEmpty => "holds an entry added since the scan",
+            DirKeptReason::RemoveFailed => "could not be removed",
+            DirKeptReason::Skipped(reason) => match reason {
+                SkipReason::OutOfScope => "holds an entry outside scope",
+                SkipReason::Pruned => "holds a pruned directory",
+                SkipReason::Excluded => "holds an excluded entry",
+                SkipReason::KeepList => "holds an entry on the keep list",
+                SkipReason::NotOwned => "holds an entry owned by others",
+                SkipReason::Dropping => "holds a client dropping",
+                SkipReason::Hook => "holds a file skipped by hook",
+                SkipReason::Changed => "holds a file changed since plan",
+                SkipReason::Symlink => "holds a symbolic link",
+                SkipReason::Unreadable => "holds an unreadable entry",
+                SkipReason::Junk => "holds junk",
+                SkipReason::NotJunk => "holds an entry that is not junk",
+                SkipReason::InProgress => "holds a download in progress",
+                SkipReason::Hardlinked => "holds a file hardlinked elsewhere",
+                SkipReason::TooDeep => "holds a directory too deep to examine",
+                SkipReason::OtherFileSystem => "holds a mount point",
+                SkipReason::BelowMinSize => "holds a file below --min-size",
+                SkipReason::AboveMaxSize => "holds a file above --max-size",
+                SkipReason::TooNew => "holds a file too recent to remove",
+                SkipReason::TooOld => "holds a file too old to remove",
+                SkipReason::UnknownAge => "holds a file of unknown age",
+                SkipReason::InUse => "holds a file used by this run",
+                SkipReason::Renamed => "holds a renamed torrent file",
+                SkipReason::Relinked => "holds a file linked into the torrent",
+                SkipReason::Colliding => "holds a file of colliding torrent paths",
+                SkipReason::Snapshot => "holds a snapshot or recycle area",
+                SkipReason::Private => "holds an entry kept for the private tracker",
+                SkipReason::Deselected => "holds a file kept by selection",
+                SkipReason::NotDeletable => "holds an entry requiring manual review",
+            },
+        }
+    }
+}
+
+/// A directory `--empty-dir` considered but kept, with the first entry found that keeps it.
+#[derive(Clone)]
+pub struct KeptDir {
+    pub path: PathBuf,
+    pub reason: DirKeptReason,
+    pub blocker: Option<PathBuf>,
+    /// The error removing it failed with, for [`DirKeptReason::RemoveFailed`].
+    pub error: Option<String>,
+}
+
+pub struct Skipped {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+    /// Size of a skipped file; 0 for directories.
+    pub size: u64,
+}
+
+/// What an extra file looked like when it was scanned.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    pub fn of(meta: &fs::Metadata) -> Self {
+        Self { size: meta.len(), modified: meta.modified().ok() }
+    }
+
+    /// The stamp of the extra at `path` as it is now; a directory that is an extra as a whole is
+    /// stamped with the size of everything inside it.
+    pub fn read(path: &Path) -> Option<Self> {
+        let meta = fs::symlink_metadata(path).ok()?;
+        match meta.is_dir() {
+            true => Some(Self { size: tree_size(path), modified: meta.modified().ok() }),
+            false => Some(Self::of(&meta)),
+        }
+    }
+}
+
+/// Total size of the files and links inside the directory at `path`, without following links.
+pub fn tree_size(path: &Path) -> u64 {
+    WalkDir::new(path).into_iter().flatten()
+        .filter(|e| !e.file_type().is_dir())
+        .filter_map(|e| e.metadata().ok())
+        .map(|e| e.len())
+        .sum()
+}
+
+/// Entries found in the directory that are not part of the torrent.
+#[derive(Default)]
+pub struct Scan {
+    /// The extra files, and with [`ScanOptions::max_depth`] the directories that are extras as a
+    /// whole.
+    pub old_files: Vec<PathBuf>,
+    pub empty_dirs: Vec<PathBuf>,
+    /// Directories holding only extras, which become empty once those are removed; only
+    /// collected with `--empty-dir`.
+    pub emptied_dirs: Vec<PathBuf>,
+    /// Directories outside the torrent that `--empty-dir` keeps, and why.
+    pub kept_dirs: Vec<KeptDir>,
+    pub rm_size: u64,
+    /// Entries left alone, with the reason.
+    pub skipped: Vec<Skipped>,
+    /// Extras that are another path to a file listed before them, and with `Symlinks::Follow`
+    /// the paths through links to an extra, each with that first path; their size is not counted
+    /// again.
+    pub aliases: Vec<(PathBuf, PathBuf)>,
+    /// Each extra file as scanned, to tell whether it changed before it is removed.
+    pub stamps: HashMap<PathBuf, Stamp>,
+    /// Entries that could not be read, with [`ScanOptions::skip_errors`].
+    pub unreadable: Vec<Unreadable>,
+}
+
+/// An entry the scan could not read, and why.
+#[derive(Clone, Debug)]
+pub struct Unreadable {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Identifies the file behind `path` if it has several links; `None` for singly linked files.
+fn file_id(path: &Path, meta: &fs::Metadata) -> Option<(u64, u64)> {
+    file_links(path, meta).filter(|(_, links)| *links > 1).map(|(id, _)| id)
+}
+
+/// Identifies the file behind `path`, together with how many links it has.
+#[cfg(unix)]
+fn file_links(_: &Path, meta: &fs::Metadata) -> Option<((u64, u64), u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some(((meta.dev(), meta.ino()), meta.nlink()))
+}
+
+#[cfg(windows)]
+fn file_links(path: &Path, _: &fs::Metadata) -> Option<((u64, u64), u64)> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{GetFileInformationByHandle,
+                                                  BY_HANDLE_FILE_INFORMATION};
+    let file = fs::File::open(path).ok()?;
+    // SAFETY: the handle stays open for the duration of the call and `info` is plain data
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle(), &mut info) } == 0 {
+        return None;
+    }
+    Some(((info.dwVolumeSerialNumber as u64,
+           (info.nFileIndexHigh as u64) << 32 | info.nFileIndexLow as u64),
+          info.nNumberOfLinks as u64))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn file_links(_: &Path, _: &fs::Metadata) -> Option<((u64, u64), u64)> {
+    None
+}
+
+/// Identifies the file system `path` is on, following links.
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|e| e.dev())
+}
+
+/// The serial number of the volume, which for a folder a volume is mounted on is the mounted one.
+#[cfg(windows)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{GetFileInformationByHandle,
+                                                  BY_HANDLE_FILE_INFORMATION,
+                                                  FILE_FLAG_BACKUP_SEMANTICS};
+    // Directories can only be opened with backup semantics
+    let file = fs::OpenOptions::new().read(true).custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path).ok()?;
+    // SAFETY: the handle stays open for the duration of the call and `info` is plain data
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle(), &mut info) } == 0 {
+        return None;
+    }
+    Some(info.dwVolumeSerialNumber as u64)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn device_id(_: &Path) -> Option<u64> {
+    None
+}
+
+/// Bytes the file takes on disk, which is less than its length when sparse or compressed.
+#[cfg(unix)]
+fn allocated_size(_: &Path, meta: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.blocks() * 512
+}
+
+#[cfg(windows)]
+fn allocated_size(path: &Path, meta: &fs::Metadata) -> u64 {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{GetCompressedFileSizeW, INVALID_FILE_SIZE};
+    let wide = path.as_os_str().encode_wide().chain([0]).collect::<Vec<u16>>();
+    let mut high = 0;
+    // SAFETY: `wide` is NUL-terminated and outlives the call
+    let low = unsafe { GetCompressedFileSizeW(wide.as_ptr(), &mut high) };
+    // A size whose low half is all ones is read as a failure too; the length is close enough
+    match low {
+        INVALID_FILE_SIZE => meta.len(),
+        _ => (high as u64) << 32 | low as u64,
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn allocated_size(_: &Path, meta: &fs::Metadata) -> u64 {
+    meta.len()
+}
+
+/// What removing the extra files takes off the disk, as opposed to their apparent size in
+/// [`Scan::rm_size`].
+pub struct DiskUsage {
+    /// Bytes allocated to the extra files, each file counted once however many paths lead to it.
+    pub allocated: u64,
+    /// Of those, the bytes removing them frees; files with links outside the removal free none.
+    pub freed: u64,
+    /// The extra files with links outside the removal.
+    pub hardlinked: HashSet<PathBuf>,
+}
+
+impl Scan {
+    /// Entries that removing the extras would delete, counting the directories they leave empty.
+    pub fn removal_count(&self) -> usize {
+        self.old_files.len() + self.empty_dirs.len() + self.emptied_dirs.len()
+    }
+
+    /// The skipped entries of one reason, in listing order.
+    pub fn skipped(&self, reason: SkipReason) -> impl Iterator<Item = &Skipped> {
+        self.skipped.iter().filter(move |e| e.reason == reason)
+    }
+
+    /// Whether the extra file at `path` is gone or no longer has the size and modification time
+    /// it was scanned with.
+    pub fn changed(&self, path: &Path) -> bool {
+        self.stamps.get(path).is_some_and(|e| Stamp::read(path) != Some(*e))
+    }
+
+    /// The entries inside `dir`, a directory below [`ScanOptions::max_depth`] removed as a
+    /// whole, that are not as the scan stamped them: files and links changed or added since,
+    /// and whatever cannot be read.
+    pub fn changed_inside(&self, dir: &Path) -> Vec<PathBuf> {
+        WalkDir::new(dir).min_depth(1).into_iter()
+            .filter_map(|entry| match entry {
+                Ok(e) if e.file_type().is_dir() => None,
+                Ok(e) => (Stamp::read(e.path()) != self.stamps.get(e.path()).copied())
+                    .then(|| e.into_path()),
+                Err(e) => Some(e.path().unwrap_or(dir).to_owned()),
+            })
+            .collect()
+    }
+
+    /// Reads what the extra files take on disk and which of them have links outside the removal,
+    /// whose data stays on disk after removing them.
+    pub fn disk_usage(&self) -> DiskUsage {
+        let mut files = HashMap::<(u64, u64), (Vec<&PathBuf>, u64, u64)>::new();
+        let mut usage = DiskUsage { allocated: 0, freed: 0, hardlinked: HashSet::new() };
+        for file in &self.old_files {
+            let Ok(meta) = fs::symlink_metadata(file) else { continue };
+            let size = match meta.is_dir() {
+                true => self.stamps.get(file).map_or(0, |e| e.size),
+                false => allocated_size(file, &meta),
+            };
+            match file_links(file, &meta).filter(|_| !meta.is_dir()) {
+                Some((id, links)) if links > 1 => {
+                    files.entry(id).or_insert((Vec::new(), links, size)).0.push(file);
+                }
+                _ => {
+                    usage.allocated += size;
+                    usage.freed += size;
+                }
+            }
+        }
+        for (paths, links, size) in files.into_values() {
+            usage.allocated += size;
+            match paths.len() as u64 >= links {
+                true => usage.freed += size,
+                false => usage.hardlinked.extend(paths.into_iter().cloned()),
+            }
+        }
+        usage
+    }
+
+    /// Takes the extra files in `paths` out of the removal, keeping the directories holding them.
+    /// The extras under `dir` whose being extra depends on how `collisions` are resolved: another
+    /// spelling of paths that collide, or at or inside a file that others are inside of.
+    pub fn colliding(&self, dir: &Path, collisions: &[Collision]) -> HashSet<PathBuf> {
+        let keys = collisions.iter().map(Collision::key).collect::<Vec<PathBuf>>();
+        self.old_files.iter()
+            .filter(|e| {
+                let key = disk_key(e.strip_prefix(dir).unwrap_or(e));
+                keys.iter().any(|e| key.starts_with(e) || e.starts_with(&key))
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn exclude(&mut self, paths: &HashSet<PathBuf>, reason: SkipReason) {
+        self.take_out(paths, reason, true);
+    }
+
+    /// Takes the extra files in `paths`, since moved elsewhere, out of the removal; the
+    /// directories they leave empty are still removed.
+    pub fn moved_away(&mut self, paths: &HashSet<PathBuf>, reason: SkipReason) {
+        self.take_out(paths, reason, false);
+    }
+
+    fn take_out(&mut self, paths: &HashSet<PathBuf>, reason: SkipReason, keep_dirs: bool) {
+        let (excluded, kept) = self.old_files.drain(..).partition(|e| paths.contains(e));
+        self.old_files = kept;
+        for path in excluded {
+            // The scanned size is the one counted in `rm_size`, even if the file changed since
+            let size = self.stamps.get(&path).map_or(0, |e| e.size);
+            if !self.aliases.iter().any(|(alias, _)| *alias == path) {
+                self.rm_size -= size;
+            }
+            if keep_dirs {
+                let (kept, emptied) = self.emptied_dirs.drain(..)
+                    .partition(|e| path.starts_with(e));
+                self.emptied_dirs = emptied;
+                self.kept_dirs.extend(kept.into_iter().map(|dir| KeptDir {
+                    path: dir, reason: DirKeptReason::Skipped(reason), blocker: Some(path.clone()),
+                    error: None,
+                }));
+            }
+            self.skipped.push(Skipped { path, reason, size });
+        }
+        self.aliases.retain(|(alias, _)| !paths.contains(alias));
+        // A path through a followed link is only an alias of a file that is removed
+        let (through, aliases): (Vec<_>, _) = self.aliases.drain(..)
+            .partition(|(alias, first)| paths.contains(first) && !self.old_files.contains(alias));
+        self.aliases = aliases;
+        self.skipped.extend(through.into_iter()
+            .map(|(path, _)| Skipped { path, reason: SkipReason::Symlink, size: 0 }));
+    }
+
+    /// The planned directories, children before their parents, each with the planned removals
+    /// directly inside it. Once those are gone the directory is empty, which may in turn leave
+    /// its parent empty.
+    pub fn dir_cascade(&self) -> Vec<(&Path, Vec<&Path>)> {
+        let mut inside = HashMap::<&Path, Vec<&Path>>::new();
+        for entry in self.old_files.iter().chain(&self.empty_dirs).chain(&self.emptied_dirs) {
+            if let Some(parent) = entry.parent() {
+                inside.entry(parent).or_default().push(entry);
+            }
+        }
+        let mut dirs = self.empty_dirs.iter().chain(&self.emptied_dirs)
+            .map(PathBuf::as_path).collect::<Vec<&Path>>();
+        dirs.sort_by(|a, b| b.cmp(a));
+        dirs.into_iter().map(|dir| (dir, inside.remove(dir).unwrap_or_default())).collect()
+    }
+
+    /// Keeps the extras that `deletable`, the size bounds and the age bounds of `options` leave
+    /// alone, going by the stamps of the scan. Those of the entries `is_dir` tells are
+    /// directories removed as a whole are not filtered, but `deletable` keeps them.
+    pub fn filter(&mut self, options: &ScanOptions, is_dir: impl Fn(&Path) -> bool) {
+        if let Some(deletable) = &options.deletable {
+            let blocked = self.old_files.iter()
+                .filter(|e| is_dir(e) || !deletable.allows(e))
+                .cloned()
+                .collect();
+            self.exclude(&blocked, SkipReason::NotDeletable);
+        }
+        for (bound, reason) in [(options.min_size, SkipReason::BelowMinSize),
+                                (options.max_size, SkipReason::AboveMaxSize)] {
+            let Some(bound) = bound else { continue };
+            let outside = self.old_files.iter()
+                .filter(|e| !is_dir(e) && self.stamps.get(*e).is_some_and(|e| match reason {
+                    SkipReason::BelowMinSize => e.size < bound,
+                    _ => e.size > bound,
+                }))
+                .cloned()
+                .collect();
+            self.exclude(&outside, reason);
+        }
+        if options.older_than.is_some() || options.newer_than.is_some() {
+            let now = SystemTime::now();
+            let mut outside = BTreeMap::<SkipReason, HashSet<PathBuf>>::new();
+            for file in self.old_files.iter().filter(|e| !is_dir(e)) {
+                let modified = self.stamps.get(file).and_then(|e| e.modified);
+                // A time in the future is taken for now
+                let age = modified.map(|e| now.duration_since(e).unwrap_or_default());
+                let reason = match age {
+                    None => SkipReason::UnknownAge,
+                    Some(age) if options.older_than.is_some_and(|e| age < e) => SkipReason::TooNew,
+                    Some(age) if options.newer_than.is_some_and(|e| age > e) => SkipReason::TooOld,
+                    Some(_) => continue,
+                };
+                outside.entry(reason).or_default().insert(file.clone());
+            }
+            for (reason, paths) in outside {
+                self.exclude(&paths, reason);
+            }
+        }
+    }
+
+    /// Count and total size of the skipped entries per reason.
+    pub fn skip_totals(&self) -> BTreeMap<SkipReason, (usize, u64)> {
+        let mut totals = BTreeMap::<SkipReason, (usize, u64)>::new();
+        for entry in &self.skipped {
+            let total = totals.entry(entry.reason).or_default();
+            total.0 += 1;
+            total.1 += entry.size;
+        }
+        totals
+    }
+}
+
+/// What one walker gathered; the workers of a parallel scan each fill their own.
+#[derive(Default)]
+struct Collected {
+    old_files: Vec<PathBuf>,
+    empty_dirs: Vec<PathBuf>,
+    rm_size: u64,
+    pruned: Vec<PathBuf>,
+    /// Directories on another file system, with `--one-file-system`.
+    mounts: Vec<PathBuf>,
+    /// Snapshot and recycle areas, without `--include-snapshots`.
+    snapshots: Vec<PathBuf>,
+    linked: HashMap<PathBuf, ((u64, u64), u64)>,
+    skipped: Vec<Skipped>,
+    stamps: HashMap<PathBuf, Stamp>,
+    /// Directories that keep something after the extras are removed, with the first entry found
+    /// that keeps them, and those that may not
+    kept: HashMap<PathBuf, (PathBuf, DirKeptReason)>,
+    removable_dirs: Vec<PathBuf>,
+    /// Directories that would be removable if they were owned, with `--owner`.
+    unowned_dirs: Vec<PathBuf>,
+    /// With `Symlinks::Follow`, where the torrent's files found through links really are.
+    link_targets: HashSet<PathBuf>,
+    /// With `Symlinks::Follow`, the other files found through links, with the file behind each.
+    reached: Vec<(PathBuf, (u64, u64))>,
+    unreadable: Vec<Unreadable>,
+}
+
+impl Collected {
+    fn keep(&mut self, dir: &Path, path: &Path, reason: DirKeptReason) {
+        for parent in path.ancestors().skip(1) {
+            if parent == dir || self.kept.contains_key(parent) {
+                break;
+            }
+            self.kept.insert(parent.to_owned(), (path.to_owned(), reason));
+        }
+    }
+
+    /// Adds what another worker gathered. A directory both found kept goes by the blocker first
+    /// in path order, so the result does not depend on which worker got there first.
+    /// Records the entry at `path` as unreadable, keeping it and the directories holding it.
+    fn unreadable(&mut self, dir: &Path, path: &Path, error: String) {
+        let reason = DirKeptReason::Skipped(SkipReason::Unreadable);
+        // A directory that could not be listed may hold anything
+        if path != dir {
+            self.kept.entry(path.to_owned()).or_insert_with(|| (path.to_owned(), reason));
+        }
+        self.keep(dir, path, reason);
+        self.skipped.push(Skipped { path: path.to_owned(), reason: SkipReason::Unreadable, size: 0 });
+        self.unreadable.push(Unreadable { path: path.to_owned(), error });
+    }
+
+    fn merge(&mut self, other: Collected) {
+        self.old_files.extend(other.old_files);
+        self.empty_dirs.extend(other.empty_dirs);
+        self.rm_size += other.rm_size;
+        self.pruned.extend(other.pruned);
+        self.mounts.extend(other.mounts);
+        self.snapshots.extend(other.snapshots);
+        self.linked.extend(other.linked);
+        self.skipped.extend(other.skipped);
+        self.stamps.extend(other.stamps);
+        self.removable_dirs.extend(other.removable_dirs);
+        self.unowned_dirs.extend(other.unowned_dirs);
+        self.link_targets.extend(other.link_targets);
+        self.reached.extend(other.reached);
+        self.unreadable.extend(other.unreadable);
+        for (path, kept) in other.kept {
+            match self.kept.get(&path) {
+                Some((blocker, _)) if path_key(blocker) <= path_key(&kept.0) => {}
+                _ => {
+                    self.kept.insert(path, kept);
+                }
+            }
+        }
+    }
+}
+
+/// The OS error behind `err`, without the path it is reported with.
+fn io_message(err: &walkdir::Error) -> String {
+    err.io_error().map_or_else(|| err.to_string(), |e| e.to_string())
+}
+
+/// Subtrees below this depth are never split between the workers of a parallel scan.
+const SPLIT_DEPTH: usize = 3;
+
+/// A scan of `dir`, shared by the workers of a parallel one.
+struct Walk<'a, E, P> {
+    dir: &'a Path,
+    /// The directory with links resolved, when following them.
+    real_dir: Option<PathBuf>,
+    expected: &'a E,
+    options: &'a ScanOptions,
+    progress: &'a P,
+    /// The file system of the scanned directory, with `--one-file-system`.
+    device: Option<u64>,
+}
+
+impl<E: ExpectedSet, P: Progress> Walk<'_, E, P> {
+    /// Visits `root`, at `depth` below the scanned directory, and unless `itself_only` everything
+    /// inside it.
+    fn walk(&self, into: &mut Collected, root: &Path, depth: usize, itself_only: bool)
+            -> anyhow::Result<()> {
+        let pruned = RefCell::new(Vec::new());
+        let mounts = RefCell::new(Vec::new());
+        let snapshots = RefCell::new(Vec::new());
+        // The linked directories walked into, and the links that are not followed
+        let followed = RefCell::new(Vec::new());
+        let unfollowed = RefCell::new(Vec::new());
+        let follow = self.real_dir.is_some();
+        // A subtree handed to a worker of a parallel scan may be a link itself
+        let mut walker = WalkDir::new(root).follow_root_links(follow && depth > 0)
+            .follow_links(follow);
+        if itself_only {
+            walker = walker.max_depth(0);
+        } else if let Some(max) = self.options.max_depth {
+            walker = walker.max_depth(max.saturating_sub(depth));
+        }
+        let walker = walker.into_iter()
+            .filter_entry(|e| {
+                let depth = depth + e.depth();
+                if depth == 1 && e.file_name() == STAGE_DIR {
+                    return false;
+                }
+                let prune = depth > 0 && e.file_type().is_dir()
+                    && self.options.prune.matches_under(self.dir, e.path());
+                if prune {
+                    pruned.borrow_mut().push(e.path().to_owned());
+                } else if depth > 0 && e.file_type().is_dir() && self.snapshot(e.path()) {
+                    snapshots.borrow_mut().push(e.path().to_owned());
+                    return false;
+                } else if depth > 0 && e.file_type().is_dir() && self.foreign(e.path()) {
+                    mounts.borrow_mut().push(e.path().to_owned());
+                    return false;
+                } else if e.path_is_symlink() && e.file_type().is_dir() {
+                    match self.followable(e.path()) {
+                        true => followed.borrow_mut().push(e.path().to_owned()),
+                        false => {
+                            unfollowed.borrow_mut().push(e.path().to_owned());
+                            return false;
+                        }
+                    }
+                }
+                !prune
+            });
+        for entry in walker {
+            let entry = match entry {
+                // Links that dangle, or loop in ways not caught before, cannot be followed
+                Err(e) if follow && e.path().is_some_and(Path::is_symlink) => {
+                    unfollowed.borrow_mut().push(e.path().expect("checked").to_owned());
+                    continue;
+                }
+                Err(e) if self.options.skip_errors && e.path().is_some_and(|e| e != self.dir) => {
+                    into.unreadable(self.dir, e.path().expect("checked"), io_message(&e));
+                    continue;
+                }
+                entry => entry.map_err(|e| {
+                    let path = e.path().unwrap_or(self.dir).to_owned();
+                    CliError::new(ErrorCode::ScanFailed,
+                                  format!("Failed to read directory contents: {}", e)).with_path(path)
+                })?,
+            };
+            if depth + entry.depth() == 0 { continue; } // skip root
+            match self.visit(into, &entry, &followed.borrow()) {
+                Err(e) if self.options.skip_errors => {
+                    into.unreadable(self.dir, entry.path(), format!("{:#}", e));
+                }
+                result => result?,
+            }
+        }
+        for path in unfollowed.into_inner() {
+            self.keep_link(into, &path);
+        }
+        into.pruned.extend(pruned.into_inner());
+        into.mounts.extend(mounts.into_inner());
+        into.snapshots.extend(snapshots.into_inner());
+        Ok(())
+    }
+
+    /// Whether the directory at `path` is a snapshot or recycle area left alone, one the torrent
+    /// has a directory at being its own.
+    fn snapshot(&self, path: &Path) -> bool {
+        !self.options.include_snapshots && path.file_name().is_some_and(is_snapshot_name)
+            && path.strip_prefix(self.dir).is_ok_and(|e| !self.expected.contains_dir(e))
+    }
+
+    /// Whether the directory at `path` is on another file system than the scanned one.
+    fn foreign(&self, path: &Path) -> bool {
+        self.device.is_some_and(|device| device_id(path).is_some_and(|e| e != device))
+    }
+
+    /// Whether the linked directory at `path` can be walked into: it resolves to somewhere inside
+    /// the scanned directory, and not to one the link is in, which would loop.
+    fn followable(&self, path: &Path) -> bool {
+        let (Some(dir), Ok(target)) = (&self.real_dir, fs::canonicalize(path)) else { return false };
+        let parent = path.parent().and_then(|e| fs::canonicalize(e).ok());
+        target.starts_with(dir) && !parent.is_some_and(|e| e.starts_with(&target))
+    }
+
+    /// Leaves the link at `path` alone, as part of the torrent if it is at one of its paths.
+    fn keep_link(&self, into: &mut Collected, path: &Path) {
+        let rel = path.strip_prefix(self.dir).unwrap_or(path);
+        if self.expected.file_size(rel).is_some() || self.expected.contains_dir(rel) {
+            into.keep(self.dir, path, DirKeptReason::TorrentEntry);
+            return;
+        }
+        into.keep(self.dir, path, DirKeptReason::Skipped(SkipReason::Symlink));
+        into.skipped.push(Skipped { path: path.to_owned(), reason: SkipReason::Symlink, size: 0 });
+    }
+
+    /// Walks `root`, a directory below `--max-depth` that is not part of the torrent, for the
+    /// entries inside it the walk would have left alone, and stamps the files and links of the
+    /// others so that each is checked again before it is removed. What cannot be read is left
+    /// alone, as nothing is known of what it holds.
+    fn tree(&self, root: &Path) -> (Vec<Skipped>, HashMap<PathBuf, Stamp>) {
+        let options = self.options;
+        let (mut left, mut stamps) = (Vec::new(), HashMap::new());
+        let mut walker = WalkDir::new(root).min_depth(1).into_iter();
+        while let Some(entry) = walker.next() {
+            let unreadable = |path: Option<&Path>| Skipped {
+                path: path.unwrap_or(root).to_owned(), reason: SkipReason::Unreadable, size: 0,
+            };
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    left.push(unreadable(e.path()));
+                    continue;
+                }
+            };
+            let Ok(meta) = entry.metadata() else {
+                left.push(unreadable(Some(entry.path())));
+                continue;
+            };
+            let (full, is_dir) = (entry.path(), meta.is_dir());
+            let path = full.strip_prefix(self.dir).unwrap_or(full);
+            let link_kept = options.symlinks == Symlinks::Keep
+                || fs::metadata(full).is_ok_and(|e| e.is_dir());
+            let reason = match () {
+                _ if is_dir && options.prune.matches_under(self.dir, full) => {
+                    Some(SkipReason::Pruned)
+                }
+                _ if is_dir && self.snapshot(full) => Some(SkipReason::Snapshot),
+                _ if is_dir && self.foreign(full) => Some(SkipReason::OtherFileSystem),
+                _ if entry.path_is_symlink() && link_kept => Some(SkipReason::Symlink),
+                _ if !options.owns(&meta) => Some(SkipReason::NotOwned),
+                _ if meta.is_file() && !options.remove_incomplete
+                    && droppings::is_in_progress(path, self.expected) => {
+                    Some(SkipReason::InProgress)
+                }
+                _ if meta.is_file() && !options.clean_droppings
+                    && droppings::is_orphaned(path, self.expected) => Some(SkipReason::Dropping),
+                _ => options.protection(path, is_dir)
+                    .filter(|e| !(is_dir && *e == SkipReason::NotJunk)),
+            };
+            match reason {
+                Some(reason) => {
+                    if is_dir {
+                        walker.skip_current_dir();
+                    }
+                    let size = if meta.is_file() { meta.len() } else { 0 };
+                    left.push(Skipped { path: full.to_owned(), reason, size });
+                }
+                None if !is_dir => {
+                    stamps.insert(full.to_owned(), Stamp::of(&meta));
+                }
+                None => {}
+            }
+        }
+        (left, stamps)
+    }
+
+    fn visit(&self, into: &mut Collected, entry: &walkdir::DirEntry, followed: &[PathBuf])
+             -> anyhow::Result<()> {
+        let (dir, expected, options) = (self.dir, self.expected, self.options);
+        self.progress.inc(1);
+        if entry.file_type().is_dir() {
+            self.progress.entering(entry.path());
+        }
+        let path = entry.path().strip_prefix(dir).with_context(||
+            format!("Failed to strip directory contents of {:?}", dir))?;
+        // Which the comparison and the removal rely on, whatever names the torrent holds
+        if !path.components().all(|e| matches!(e, Component::Normal(_))) {
+            return Err(anyhow!("Entry {} does not lie below {}", entry.path().display(),
+                               dir.display()));
+        }
+        if !options.include_surface
+            && !expected.is_surface(path.components().next().expect("Not empty").as_os_str()) {
+            let size = match entry.file_type().is_file() {
+                true => entry.metadata().map_or(0, |m| m.len()),
+                false => 0,
+            };
+            into.skipped.push(Skipped { path: entry.path().to_owned(),
+                                        reason: SkipReason::OutOfScope, size });
+            return Ok(());
+        }
+        let link = entry.path_is_symlink();
+        let follow = self.real_dir.is_some();
+        // Entries found through a linked directory are only matched, never removed through it
+        let through_link = followed.iter().any(|e| entry.path().starts_with(e) && entry.path() != e);
+        if through_link || (link && (options.symlinks == Symlinks::Keep
+                                     || entry.file_type().is_dir())) {
+            if through_link && entry.file_type().is_file() {
+                match expected.file_size(path) {
+                    Some(_) => into.link_targets.extend(fs::canonicalize(entry.path())),
+                    None => into.reached.extend(entry.metadata().ok()
+                        .and_then(|meta| file_links(entry.path(), &meta))
+                        .map(|(id, _)| (entry.path().to_owned(), id))),
+                }
+            }
+            self.keep_link(into, entry.path());
+            return Ok(());
+        }
+        let mismatched = options.remove_mismatched && entry.file_type().is_file()
+            && expected.file_size(path)
+                .is_some_and(|size| entry.metadata().is_ok_and(|m| m.len() != size));
+        if expected.file_size(path).is_some() && !mismatched {
+            into.keep(dir, entry.path(), DirKeptReason::TorrentEntry);
+            if link && follow {
+                into.link_targets.extend(fs::canonicalize(entry.path()));
+            }
+            return Ok(());
+        }
+        // A link to a file is removed as the link, which is what its metadata describes
+        let meta = match link && follow {
+            true => fs::symlink_metadata(entry.path())?,
+            false => entry.metadata()?,
+        };
+        let protected = match meta.is_dir() && expected.contains_dir(path) {
+            true => None,
+            false => options.protection(path, meta.is_dir()),
+        };
+        // Directories are only walked into with `--junk only`, not listed for what they are not
+        if protected == Some(SkipReason::NotJunk) && meta.is_dir() {
+            into.keep(dir, entry.path(), DirKeptReason::Skipped(SkipReason::NotJunk));
+            return Ok(());
+        }
+        if let Some(reason) = protected {
+            into.keep(dir, entry.path(), DirKeptReason::Skipped(reason));
+            into.skipped.push(Skipped { path: entry.path().to_owned(), reason,
+                                        size: if meta.is_file() { meta.len() } else { 0 } });
+            return Ok(());
+        }
+        let owned = options.owns(&meta);
+        let too_deep = meta.is_dir() && options.max_depth == Some(path.components().count())
+            && fs::read_dir(entry.path()).is_ok_and(|mut e| e.next().is_some());
+        // Only removed as a whole if the walk would have removed everything inside it
+        let (left, stamps) = match too_deep && owned && !expected.contains_dir(path) {
+            true => self.tree(entry.path()),
+            false => (Vec::new(), HashMap::new()),
+        };
+        if too_deep && (expected.contains_dir(path) || !left.is_empty()) {
+            into.keep(dir, entry.path(), DirKeptReason::Skipped(SkipReason::TooDeep));
+            into.skipped.push(Skipped { path: entry.path().to_owned(), reason: SkipReason::TooDeep,
+                                        size: 0 });
+            into.skipped.extend(left);
+            return Ok(());
+        }
+        if too_deep && owned {
+            let stamp = Stamp::read(entry.path()).unwrap_or(Stamp::of(&meta));
+            self.progress.found(entry.path(), stamp.size);
+            into.rm_size += stamp.size;
+            into.stamps.insert(entry.path().to_owned(), stamp);
+            into.stamps.extend(stamps);
+            into.old_files.push(entry.path().to_owned());
+            return Ok(());
+        }
+        if meta.is_dir() && expected.contains_dir(path) {
+            into.keep(dir, entry.path(), DirKeptReason::TorrentEntry);
+        } else if !owned {
+            into.keep(dir, entry.path(), DirKeptReason::Skipped(SkipReason::NotOwned));
+        } else if meta.is_dir() {
+            into.removable_dirs.push(entry.path().to_owned());
+        }
+        if !owned && !meta.is_dir() {
+            into.skipped.push(Skipped { path: entry.path().to_owned(),
+                                        reason: SkipReason::NotOwned, size: meta.len() });
+            return Ok(());
+        }
+        if meta.is_file() && !options.remove_incomplete
+            && droppings::is_in_progress(path, expected) {
+            into.keep(dir, entry.path(), DirKeptReason::Skipped(SkipReason::InProgress));
+            into.skipped.push(Skipped { path: entry.path().to_owned(),
+                                        reason: SkipReason::InProgress, size: meta.len() });
+            return Ok(());
+        }
+        if meta.is_file() && !options.clean_droppings && droppings::is_orphaned(path, expected) {
+            into.keep(dir, entry.path(), DirKeptReason::Skipped(SkipReason::Dropping));
+            into.skipped.push(Skipped { path: entry.path().to_owned(),
+                                        reason: SkipReason::Dropping, size: meta.len() });
+            return Ok(());
+        }
+        if meta.is_file() {
+            into.rm_size += meta.len();
+            // Any file may also be found through a followed link, not only one with several
+            let id = match follow {
+                true => file_links(entry.path(), &meta).map(|(id, _)| id),
+                false => file_id(entry.path(), &meta),
+            };
+            if let Some(id) = id {
+                into.linked.insert(entry.path().to_owned(), (id, meta.len()));
+            }
+        }
+
+        if meta.is_dir() {
+            // Whether it is empty is only known once everything inside it is visited
+            if !owned && options.include_empty_dir && !expected.contains_dir(path) {
+                into.unowned_dirs.push(entry.path().to_owned());
+            }
+        } else {
+            self.progress.found(entry.path(), meta.len());
+            into.stamps.insert(entry.path().to_owned(), Stamp::of(&meta));
+            into.old_files.push(entry.path().to_owned());
+        }
+        Ok(())
+    }
+
+    /// Splits the directory into subtrees for `threads` workers, as `(root, depth, itself_only)`:
+    /// directories near the top are visited by themselves and their entries handed out instead,
+    /// until there are a few subtrees per worker. The stage directory, pruned ones and snapshot
+    /// areas are left for the walk to skip as usual.
+    fn split(&self, threads: usize) -> anyhow::Result<Vec<(PathBuf, usize, bool)>> {
+        let failed = |e: std::io::Error, path: &Path| CliError::new(ErrorCode::ScanFailed,
+            format!("Failed to read directory contents: {}", e)).with_path(path);
+        let mut units = Vec::new();
+        let mut frontier = vec![self.dir.to_path_buf()];
+        let mut depth = 0;
+        while depth < SPLIT_DEPTH.min(self.options.max_depth.unwrap_or(SPLIT_DEPTH)) {
+            depth += 1;
+            let mut next = Vec::new();
+            for parent in frontier {
+                if depth > 1 {
+                    units.push((parent.clone(), depth - 1, true));
+                }
+                let listed = fs::read_dir(&parent).and_then(|entries| entries
+                    .map(|entry| entry.and_then(|e| Ok((e.path(), e.file_type()?.is_dir()))))
+                    .collect::<std::io::Result<Vec<(PathBuf, bool)>>>());
+                let listed = match listed {
+                    Ok(listed) => listed,
+                    // Walked as a whole instead, which records what cannot be read
+                    Err(_) if self.options.skip_errors && depth > 1 => {
+                        units.pop();
+                        units.push((parent, depth - 1, false));
+                        continue;
+                    }
+                    Err(e) => return Err(failed(e, &parent).into()),
+                };
+                for (path, is_dir) in listed {
+                    let split = is_dir
+                        && !(depth == 1 && path.file_name() == Some(OsStr::new(STAGE_DIR))
+                             || self.options.prune.matches_under(self.dir, &path)
+                             || self.snapshot(&path) || self.foreign(&path));
+                    match split {
+                        true => next.push(path),
+                        false => units.push((path, depth, false)),
+                    }
+                }
+            }
+            frontier = next;
+            if units.len() + frontier.len() >= threads * 4 {
+                break;
+            }
+        }
+        units.extend(frontier.into_iter().map(|e| (e, depth, false)));
+        Ok(units)
+    }
+}
+
+pub fn scan_dir(dir: &Path, expected: &impl ExpectedSet, options: &ScanOptions,
+                progress: &impl Progress) -> anyhow::Result<Scan> {
+    if options.ignore_file {
+        let mut options = options.clone();
+        options.load_ignore_file(dir)?;
+        return scan_dir(dir, expected, &options, progress);
+    }
+    let real_dir = match options.symlinks {
+        Symlinks::Follow => Some(fs::canonicalize(dir).with_context(||
+            format!("Failed to resolve {}", dir.display()))?),
+        _ => None,
+    };
+    let device = options.one_file_system.then(|| device_id(dir)).flatten();
+    let walk = Walk { dir, real_dir, expected, options, progress, device };
+    let mut collected = Collected::default();
+    if options.threads <= 1 {
+        walk.walk(&mut collected, dir, 0, false)?;
+    } else {
+        let units = walk.split(options.threads)?;
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let workers = thread::scope(|scope| {
+            let workers = (0..options.threads.min(units.len())).map(|_| scope.spawn(|| {
+                let mut collected = Collected::default();
+                while let Some((root, depth, itself_only)) = units.get(next.fetch_add(1, Relaxed)) {
+                    if failed.load(Relaxed) {
+                        break;
+                    }
+                    if let Err(err) = walk.walk(&mut collected, root, *depth, *itself_only) {
+                        failed.store(true, Relaxed);
+                        return Err(err);
+                    }
+                }
+                Ok(collected)
+            })).collect::<Vec<_>>();
+            workers.into_iter().map(|e| e.join().expect("scan worker panicked"))
+                .collect::<anyhow::Result<Vec<Collected>>>()
+        })?;
+        for worker in workers {
+            collected.merge(worker);
+        }
+    }
+    let Collected { mut old_files, mut rm_size, pruned, mounts, snapshots, linked, mut skipped,
+                    stamps, kept, removable_dirs, unowned_dirs, link_targets, reached,
+                    mut unreadable, .. } = collected;
+    let mut collected = Collected { kept, ..Collected::default() };
+    let mut keep = |path: &Path, reason| collected.keep(dir, path, reason);
+
+    if options.sorted {
+        options.order.sort_paths(&mut old_files, &stamps);
+    }
+    for path in &pruned {
+        keep(path, DirKeptReason::Skipped(SkipReason::Pruned));
+    }
+    for path in &mounts {
+        keep(path, DirKeptReason::Skipped(SkipReason::OtherFileSystem));
+    }
+    for path in &snapshots {
+        keep(path, DirKeptReason::Skipped(SkipReason::Snapshot));
+    }
+    let mut kept = collected.kept;
+    // Everything inside a directory is either kept, which keeps the directory, or removed, so one
+    // neither kept nor holding a removal holds nothing but directories as empty as itself
+    let holding = old_files.iter()
+        .flat_map(|e| e.ancestors().skip(1).take_while(|e| *e != dir))
+        .collect::<HashSet<&Path>>();
+    let empty = |path: &PathBuf| !kept.contains_key(path) && !holding.contains(path.as_path());
+    let mut empty_dirs = Vec::new();
+    if options.include_empty_dir {
+        empty_dirs.extend(removable_dirs.iter().filter(|e| empty(e)).cloned());
+        skipped.extend(unowned_dirs.iter().filter(|e| empty(e))
+            .map(|path| Skipped { path: path.clone(), reason: SkipReason::NotOwned, size: 0 }));
+    }
+    // Directories take up nothing the summary counts, and are only ever their own size
+    let dir_stamp = |path: &PathBuf| fs::symlink_metadata(path).ok()
+        .map(|e| Stamp { size: 0, modified: e.modified().ok() });
+    if options.sorted {
+        options.order.sort(&mut empty_dirs, |e| e, dir_stamp);
+    }
+    // Directories that only become empty once the extras in them are removed, and the others
+    let mut emptied_dirs = Vec::new();
+    let mut kept_dirs = Vec::new();
+    if options.include_empty_dir {
+        let empty = empty_dirs.iter().collect::<HashSet<&PathBuf>>();
+        for path in removable_dirs.into_iter().filter(|e| !empty.contains(e)) {
+            match kept.remove(&path) {
+                Some((blocker, reason)) => kept_dirs.push(KeptDir {
+                    path, reason, blocker: Some(blocker), error: None,
+                }),
+                None => emptied_dirs.push(path),
+            }
+        }
+    }
+    if options.sorted {
+        options.order.sort(&mut emptied_dirs, |e| e, dir_stamp);
+        kept_dirs.sort_by_cached_key(|e| path_key(&e.path));
+    }
+    skipped.extend(pruned.into_iter()
+        .map(|path| Skipped { path, reason: SkipReason::Pruned, size: 0 }));
+    skipped.extend(mounts.into_iter()
+        .map(|path| Skipped { path, reason: SkipReason::OtherFileSystem, size: 0 }));
+    skipped.extend(snapshots.into_iter()
+        .map(|path| Skipped { path, reason: SkipReason::Snapshot, size: 0 }));
+    if options.sorted {
+        skipped.sort_by_cached_key(|e| path_key(&e.path));
+        unreadable.sort_by_cached_key(|e| path_key(&e.path));
+    }
+    // Paths to the same file are all removed, but its size only counts once
+    let mut aliases = Vec::new();
+    let mut first = HashMap::<(u64, u64), &PathBuf>::new();
+    for file in &old_files {
+        let Some((id, size)) = linked.get(file) else { continue };
+        match first.get(id) {
+            Some(path) => {
+                aliases.push((file.clone(), PathBuf::clone(path)));
+                rm_size -= size;
+            }
+            None => {
+                first.insert(*id, file);
+            }
+        }
+    }
+    // Those also found through a followed link are listed once, with that path as a further one
+    let mut reached = reached.into_iter()
+        .filter_map(|(path, id)| first.get(&id).map(|first| (path, PathBuf::clone(first))))
+        .collect::<Vec<_>>();
+    if options.sorted {
+        reached.sort_by_cached_key(|(path, _)| path_key(path));
+    }
+    skipped.retain(|e| !reached.iter().any(|(path, _)| *path == e.path));
+    aliases.extend(reached);
+    let mut scan = Scan { old_files, empty_dirs, emptied_dirs, kept_dirs, rm_size, skipped, aliases,
+                          stamps, unreadable };
+    // After the protections, so that what they keep is counted as theirs
+    scan.filter(options, |e| e.is_dir());
+    if !options.protected.is_empty() {
+        let protected = paths_within(dir, &options.protected);
+        let in_use = scan.old_files.iter()
+            .filter(|e| protected.iter().any(|path| path.starts_with(e)))
+            .cloned()
+            .collect();
+        scan.exclude(&in_use, SkipReason::InUse);
+    }
+    if options.skip_hardlinked {
+        let hardlinked = scan.disk_usage().hardlinked;
+        scan.exclude(&hardlinked, SkipReason::Hardlinked);
+    }
+    // What links at the torrent's paths point to is part of the torrent as well
+    if !link_targets.is_empty() {
+        let targets = scan.old_files.iter()
+            .filter(|e| !e.is_symlink() && fs::canonicalize(e).is_ok_and(|e| link_targets.contains(&e)))
+            .cloned()
+            .collect();
+        scan.exclude(&targets, SkipReason::Symlink);
+    }
+    Ok(scan)
+}
+
+/// Where the `paths` inside `dir` are found below it, both for the path itself and for what it
+/// links to. A file not written yet is found through the directory it is to go in.
+fn paths_within(dir: &Path, paths: &HashSet<PathBuf>) -> Vec<PathBuf> {
+    let Ok(real_dir) = fs::canonicalize(dir) else { return Vec::new() };
+    let mut within = Vec::new();
+    for path in paths {
+        let parent = path.parent().filter(|e| !e.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let itself = fs::canonicalize(parent).ok()
+            .and_then(|parent| Some(parent.join(path.file_name()?)));
+        for real in itself.into_iter().chain(fs::canonicalize(path)) {
+            if let Ok(rel) = real.strip_prefix(&real_dir) {
+                within.push(dir.join(rel));
+            }
+        }
+    }
+    within
+}
+
+/// Sorts paths byte-wise with `/` as the separator, so the order is the same on every platform.
+pub fn sort_paths(paths: &mut [PathBuf]) {
+    paths.sort_by_cached_key(|path| path_key(path));
+}
+
+/// Sorts paths deepest first, and those as deep as [`sort_paths`] does, so that nothing is
+/// removed before what is inside it.
+pub fn sort_deepest_first(paths: &mut [PathBuf]) {
+    paths.sort_by_cached_key(|path| (std::cmp::Reverse(path.components().count()), path_key(path)));
+}
+
+fn path_key(path: &Path) -> Vec<u8> {
+    let mut key = Vec::new();
+    for (i, component) in path.components().enumerate() {
+        if i > 0 {
+            key.push(b'/');
+        }
+        key.extend_from_slice(component.as_os_str().as_encoded_bytes());
+    }
+    key
+}
@@ -0,0 +1,373 @@
+use crate::error::ErrorCode;
+use crate::kind::FileKind;
+use crate::plan::PLAN_VERSION;
+use crate::report::MapStatus;
+use crate::scan::{DirKeptReason, SkipReason};
+use crate::snapshot::SNAPSHOT_VERSION;
+use crate::summary::RunStatus;
+use crate::warnings::WarningCode;
+use serde_json::{json, Value};
+
+/// Version of the JSON documents described by [`schema`]. Bump it whenever a field is removed,
+/// renamed or changes type; new fields are compatible and keep it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const BASE_ID: &str = "https://github.com/AnvilloyDevStudio/torrent-cleaner/schema";
+
+const SKIP_REASONS: [SkipReason; 29] = [SkipReason::OutOfScope, SkipReason::Pruned,
+    SkipReason::Excluded, SkipReason::KeepList, SkipReason::NotOwned, SkipReason::Dropping, SkipReason::Hook, SkipReason::Changed,
+    SkipReason::Symlink, SkipReason::Unreadable, SkipReason::Junk, SkipReason::NotJunk,
+    SkipReason::InProgress, SkipReason::Hardlinked, SkipReason::TooDeep,
+    SkipReason::OtherFileSystem, SkipReason::BelowMinSize, SkipReason::AboveMaxSize,
+    SkipReason::TooNew, SkipReason::TooOld, SkipReason::UnknownAge, SkipReason::InUse,
+    SkipReason::Renamed, SkipReason::Relinked, SkipReason::Colliding, SkipReason::Snapshot,
+    SkipReason::Private, SkipReason::Deselected, SkipReason::NotDeletable];
+
+const WARNING_CODES: [WarningCode; 25] = [WarningCode::Deprecated, WarningCode::Priority,
+    WarningCode::TorrentMetadata, WarningCode::Geometry, WarningCode::UnsafePath,
+    WarningCode::Unmounted,
+    WarningCode::Active, WarningCode::SiblingTrees, WarningCode::Unreadable,
+    WarningCode::Colliding, WarningCode::UnknownAge, WarningCode::SizeConflict,
+    WarningCode::TorrentSkipped, WarningCode::FoldedAlike, WarningCode::Hook,
+    WarningCode::History, WarningCode::Report, WarningCode::PieceLayers, WarningCode::Resume,
+    WarningCode::Client, WarningCode::Journal, WarningCode::Map, WarningCode::Rename,
+    WarningCode::Relink, WarningCode::Watch];
+
+fn id(name: &str) -> String {
+    format!("{}/v{}/{}", BASE_ID, SCHEMA_VERSION, name)
+}
+
+fn count() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+fn nullable(ty: &str) -> Value {
+    json!({ "type": [ty, "null"] })
+}
+
+/// The serialized names of `values`, so the schema spells them exactly as the output does.
+fn names(values: &[impl serde::Serialize]) -> Value {
+    json!({ "enum": values.iter().map(|e| serde_json::to_value(e).expect("serializable"))
+        .collect::<Vec<Value>>() })
+}
+
+/// An object with all of `properties` required. Other properties are allowed, since new fields
+/// do not change the version.
+fn object(properties: Value) -> Value {
+    let required = properties.as_object().map_or(Vec::new(), |e| e.keys().cloned().collect());
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+fn document(name: &str, description: &str, mut schema: Value) -> Value {
+    schema["$id"] = id(name).into();
+    schema["description"] = description.into();
+    schema
+}
+
+fn kind_total() -> Value {
+    object(json!({ "count": count(), "size": count() }))
+}
+
+fn run_status() -> Value {
+    names(&[RunStatus::Ok, RunStatus::Aborted, RunStatus::Failed, RunStatus::Incomplete])
+}
+
+fn error_document() -> Value {
+    let code = names(&[
+        ErrorCode::TorrentUnreadable, ErrorCode::TorrentInvalid, ErrorCode::TorrentUnsupported,
+        ErrorCode::UnsafePaths,
+        ErrorCode::DirNotFound, ErrorCode::TargetUnmounted, ErrorCode::TargetActive,
+        ErrorCode::ScanFailed,
+        ErrorCode::RemoveFailed, ErrorCode::BatchFailed, ErrorCode::HookRejected,
+        ErrorCode::InsufficientSpace, ErrorCode::EmptyExpected, ErrorCode::ThresholdExceeded,
+        ErrorCode::Interrupted, ErrorCode::ClientFailed, ErrorCode::RemoteFailed,
+        ErrorCode::Internal,
+    ]);
+    let entry = object(json!({ "path": { "type": "string" }, "message": { "type": "string" } }));
+    let mut error = object(json!({ "code": code, "message": { "type": "string" } }));
+    error["properties"]["path"] = json!({ "type": "string" });
+    error["properties"]["errors"] = json!({ "type": "array", "items": entry });
+    document("error", "The last line on stdout of a run that failed with --output json.",
+             object(json!({ "error": error })))
+}
+
+fn warning() -> Value {
+    object(json!({
+        "code": names(&WARNING_CODES),
+        "message": { "type": "string" },
+        "path": nullable("string"),
+    }))
+}
+
+fn summary_object() -> Value {
+    let kinds = [FileKind::Video, FileKind::Audio, FileKind::Archive, FileKind::Subtitle,
+        FileKind::Image, FileKind::Junk, FileKind::Other].map(FileKind::name);
+    let kept_reasons = [DirKeptReason::TorrentEntry, DirKeptReason::NotRemoved,
+        DirKeptReason::NotEmpty, DirKeptReason::RemoveFailed].into_iter()
+        .chain(SKIP_REASONS.map(DirKeptReason::Skipped))
+        .collect::<Vec<DirKeptReason>>();
+    let kept_dir = object(json!({
+        "path": { "type": "string" },
+        "reason": names(&kept_reasons),
+        "blocker": nullable("string"),
+        "error": nullable("string"),
+    }));
+    object(json!({
+        "command": { "enum": ["diff", "clean"] },
+        "status": run_status(),
+        "exit_code": { "type": "integer" },
+        "expected_files": count(),
+        "expected_size": count(),
+        "present_files": count(),
+        "present_size": count(),
+        "matching_files": count(),
+        "matching_size": count(),
+        "missing_files": count(),
+        "missing_size": count(),
+        "mismatched_files": count(),
+        "mismatched_delta": { "type": "integer" },
+        "extra_files": count(),
+        "extra_dirs": count(),
+        "emptied_dirs": count(),
+        "dirs_kept": { "type": "array", "items": kept_dir },
+        "extra_size": count(),
+        "allocated_size": count(),
+        "freed_size": count(),
+        "hardlinked_files": count(),
+        "extras_by_type": { "type": "object", "propertyNames": names(&kinds),
+                            "additionalProperties": kind_total() },
+        "skipped": { "type": "object", "propertyNames": names(&SKIP_REASONS),
+                     "additionalProperties": kind_total() },
+        "removed": count(),
+        "removed_size": count(),
+        "no_space": kind_total(),
+        "relinked": kind_total(),
+        "corrupt": kind_total(),
+        "held_back": nullable("string"),
+        "overlap_percent": { "type": "number", "minimum": 0, "maximum": 100 },
+        "delete_ratio": { "type": "number", "minimum": 0, "maximum": 1 },
+        "clean": { "type": "boolean" },
+        "warnings": { "type": "array", "items": warning() },
+        "duration_secs": { "type": "number", "minimum": 0 },
+    }))
+}
+
+fn summary() -> Value {
+    document("summary", "The line printed by diff --summary-json and clean --summary-json.",
+             summary_object())
+}
+
+fn diff_report() -> Value {
+    let entry = json!({
+        "id": { "type": "string" },
+        "path": { "type": "string" },
+        "absolute_path": { "type": "string" },
+        "absolute_path_bytes": nullable("string"),
+        "size": count(),
+    });
+    let entries = json!({ "type": "array", "items": object(entry.clone()) });
+    let mut mismatched = entry.clone();
+    mismatched["expected_size"] = count();
+    let mut unreadable = entry.clone();
+    unreadable["error"] = json!({ "type": "string" });
+    let mut review = entry.clone();
+    review["blocked_by"] = json!({ "const": "deletable-ext" });
+    let mut duplicate = entry;
+    duplicate["duplicate_of"] = json!({ "type": "string" });
+    document("diff", "The document printed by diff with --output json.", object(json!({
+        "info_hash": { "type": "string" },
+        "name": { "type": "string" },
+        "dir": { "type": "string" },
+        "dir_bytes": nullable("string"),
+        "missing": entries,
+        "mismatched": { "type": "array", "items": object(mismatched) },
+        "extra_files": entries,
+        "extra_dirs": entries,
+        "emptied_dirs": entries,
+        "unreadable": { "type": "array", "items": object(unreadable) },
+        "review": { "type": "array", "items": object(review) },
+        "duplicates": { "type": "array", "items": object(duplicate) },
+        "truncated": { "type": "boolean" },
+        "summary": summary_object(),
+    })))
+}
+
+fn batch_report() -> Value {
+    let entry = object(json!({
+        "torrent": { "type": "string" },
+        "dir": { "type": "string" },
+        "status": { "enum": ["ok", "failed", "skipped"] },
+        "error": nullable("string"),
+        "held_back": nullable("string"),
+        "extras": count(),
+        "extras_size": count(),
+        "missing": count(),
+        "removed": count(),
+        "removed_size": count(),
+    }));
+    document("batch", "The document printed by batch with --output json.", object(json!({
+        "command": { "enum": ["diff", "clean"] },
+        "status": run_status(),
+        "exit_code": { "type": "integer" },
+        "entries": { "type": "array", "items": entry },
+    })))
+}
+
+fn history() -> Value {
+    let record = object(json!({
+        "timestamp": count(),
+        "command": { "enum": ["diff", "clean"] },
+        "info_hash": { "type": "string" },
+        "torrent": { "type": "string" },
+        "dir": { "type": "string" },
+        "extras": count(),
+        "extras_size": count(),
+        "missing": count(),
+        "removed": count(),
+        "removed_size": count(),
+        "failures": count(),
+    }));
+    document("history", "The document printed by history with --output json.",
+             json!({ "type": "array", "items": record }))
+}
+
+fn file_list() -> Value {
+    let file = object(json!({ "path": { "type": "string" }, "size": count() }));
+    document("list", "The document printed by list with --output json, read back by --manifest.",
+             json!({ "type": "array", "items": file }))
+}
+
+fn stats() -> Value {
+    document("stats", "The document printed by stats with --output json.", object(json!({
+        "expected_files": count(),
+        "expected_size": count(),
+        "matching_files": count(),
+        "matching_size": count(),
+        "mismatched_files": count(),
+        "mismatched_size": count(),
+        "missing_files": count(),
+        "missing_size": count(),
+        "extra_files": count(),
+        "extra_dirs": count(),
+        "extra_size": count(),
+        "colliding_files": count(),
+        "completion_percent": { "type": "number", "minimum": 0, "maximum": 100 },
+    })))
+}
+
+fn map_report() -> Value {
+    let file = object(json!({
+        "path": { "type": "string" },
+        "size": count(),
+        "status": names(&[MapStatus::Present, MapStatus::Missing, MapStatus::SizeMismatch]),
+        "disk_path": nullable("string"),
+        "disk_size": { "type": ["integer", "null"], "minimum": 0 },
+        "links": { "type": ["integer", "null"], "minimum": 0 },
+    }));
+    document("map-report", "The file written by diff --map-report.", object(json!({
+        "info_hash": { "type": "string" },
+        "name": { "type": "string" },
+        "dir": { "type": "string" },
+        "files": { "type": "array", "items": file },
+    })))
+}
+
+/// The options of a snapshot or plan file.
+fn plan_options() -> Value {
+    let paths = json!({ "type": "array", "items": { "type": "string" } });
+    object(json!({
+        "surface": { "type": "boolean" },
+        "empty_dir": { "type": "boolean" },
+        "prune_dir": paths,
+        "owner": nullable("string"),
+        "only_owned": { "type": "boolean" },
+        "clean_droppings": { "type": "boolean" },
+        "remove_incomplete": { "type": "boolean" },
+        "keep_padding": { "type": "boolean" },
+        "remove_mismatched": { "type": "boolean" },
+        "exclude": paths,
+        "map": paths,
+        "deletable_ext": paths,
+        "ignore_case": { "type": "boolean" },
+        "normalize_unicode": { "type": "boolean" },
+        "symlinks": { "enum": ["keep", "remove", "follow"] },
+        "junk": { "enum": ["keep", "remove", "only"] },
+        "junk_pattern": paths,
+        "skip_hardlinked": { "type": "boolean" },
+        "max_depth": { "type": ["integer", "null"], "minimum": 1 },
+        "one_file_system": { "type": "boolean" },
+        "min_size": { "type": ["integer", "null"], "minimum": 0 },
+        "max_size": { "type": ["integer", "null"], "minimum": 0 },
+        "older_than": nullable("string"),
+        "newer_than": nullable("string"),
+    }))
+}
+
+fn snapshot() -> Value {
+    let paths = json!({ "type": "array", "items": { "type": "string" } });
+    let mut root = object(json!({
+        "version": { "const": SNAPSHOT_VERSION },
+        "tool_version": { "type": "string" },
+        "info_hash": { "type": "string" },
+        "expected_digest": { "type": "string" },
+        "options": plan_options(),
+        "plan": object(json!({
+            "files": paths,
+            "dirs": { "type": "array", "items": object(json!({
+                "path": { "type": "string" },
+                "becomes_empty_after": paths,
+            })) },
+            "skipped": { "type": "array", "items": object(json!({
+                "path": { "type": "string" },
+                "reason": names(&SKIP_REASONS),
+            })) },
+        })),
+    }));
+    root["properties"]["torrent"] = json!({ "type": "string" });
+    root["properties"]["dir"] = json!({ "type": "string" });
+    document("snapshot", "The file written by snapshot.", root)
+}
+
+fn plan_file() -> Value {
+    let modified = json!({ "type": ["object", "null"], "properties": {
+        "secs_since_epoch": count(),
+        "nanos_since_epoch": count(),
+    } });
+    let mut file = object(json!({ "path": { "type": "string" }, "size": count(),
+                                  "modified": modified, "dir": { "type": "boolean" } }));
+    file["properties"]["inside"] = json!({ "type": "array", "items": file.clone() });
+    file["required"].as_array_mut().expect("required list").push("inside".into());
+    document("plan", "The file written by plan and carried out by apply.", object(json!({
+        "version": { "const": PLAN_VERSION },
+        "tool_version": { "type": "string" },
+        "timestamp": count(),
+        "info_hash": { "type": "string" },
+        "torrent": { "type": "string" },
+        "dir": { "type": "string" },
+        "options": plan_options(),
+        "size": count(),
+        "files": { "type": "array", "items": file },
+        "dirs": { "type": "array", "items": { "type": "string" } },
+    })))
+}
+
+/// A JSON Schema (draft 2020-12) with one definition per JSON document the tool emits.
+pub fn schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": id("schema"),
+        "title": "torrent-cleaner JSON output",
+        "$defs": {
+            "error": error_document(),
+            "summary": summary(),
+            "diff": diff_report(),
+            "batch": batch_report(),
+            "history": history(),
+            "list": file_list(),
+            "stats": stats(),
+            "map_report": map_report(),
+            "snapshot": snapshot(),
+            "plan": plan_file(),
+        },
+    })
+}
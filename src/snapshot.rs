@@ -0,0 +1,261 @@
+use crate::folding;
+use crate::scan::{ExpectedSet, Scan, SkipReason};
+use anyhow::{anyhow, Context};
+use clap::ArgMatches;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use xxhash_rust::xxh3::Xxh3;
+
+/// Version of the snapshot format; snapshots of other versions are refused.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The options that decide what a scan plans to remove, as given on the command line.
+#[derive(Serialize, Deserialize)]
+pub struct PlanOptions {
+    pub surface: bool,
+    pub empty_dir: bool,
+    pub prune_dir: Vec<String>,
+    pub owner: Option<String>,
+    pub only_owned: bool,
+    pub clean_droppings: bool,
+    #[serde(default)]
+    pub remove_incomplete: bool,
+    #[serde(default)]
+    pub keep_padding: bool,
+    #[serde(default)]
+    pub remove_mismatched: bool,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub map: Vec<String>,
+    #[serde(default)]
+    pub deletable_ext: Vec<String>,
+    #[serde(default)]
+    pub ignore_case: bool,
+    #[serde(default)]
+    pub normalize_unicode: bool,
+    #[serde(default = "default_symlinks")]
+    pub symlinks: String,
+    #[serde(default = "default_junk")]
+    pub junk: String,
+    #[serde(default)]
+    pub junk_pattern: Vec<String>,
+    #[serde(default)]
+    pub skip_hardlinked: bool,
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    #[serde(default)]
+    pub one_file_system: bool,
+    #[serde(default)]
+    pub min_size: Option<u64>,
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// `--older-than` and `--newer-than`, as humantime durations.
+    #[serde(default)]
+    pub older_than: Option<String>,
+    #[serde(default)]
+    pub newer_than: Option<String>,
+}
+
+fn default_symlinks() -> String {
+    "remove".to_string()
+}
+
+fn default_junk() -> String {
+    "remove".to_string()
+}
+
+impl PlanOptions {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            surface: matches.get_flag("surface"),
+            empty_dir: matches.get_flag("empty-dir"),
+            prune_dir: matches.get_many::<String>("prune-dir")
+                .map_or(Vec::new(), |e| e.cloned().collect()),
+            owner: matches.get_one::<String>("owner").cloned(),
+            only_owned: matches.get_flag("only-owned"),
+            clean_droppings: matches.get_flag("clean-droppings"),
+            remove_incomplete: matches.get_flag("remove-incomplete"),
+            keep_padding: matches.get_flag("keep-padding"),
+            remove_mismatched: matches.get_flag("remove-mismatched"),
+            exclude: matches.get_many::<String>("exclude")
+                .map_or(Vec::new(), |e| e.cloned().collect()),
+            map: matches.get_many::<(String, String)>("map")
+                .map_or(Vec::new(), |e| e.map(|(old, new)| format!("{}={}", old, new)).collect()),
+            deletable_ext: matches.get_many::<String>("deletable-ext")
+                .map_or(Vec::new(), |e| e.cloned().collect()),
+            ignore_case: matches.get_flag("ignore-case"),
+            normalize_unicode: folding(matches).unicode,
+            symlinks: matches.get_one::<String>("symlinks").expect("default").clone(),
+            junk: matches.get_one::<String>("junk").expect("default").clone(),
+            junk_pattern: matches.get_many::<String>("junk-pattern")
+                .map_or(Vec::new(), |e| e.cloned().collect()),
+            skip_hardlinked: matches.get_flag("skip-hardlinked"),
+            max_depth: matches.get_one::<u16>("max-depth").map(|e| *e as usize),
+            one_file_system: matches.get_flag("one-file-system"),
+            min_size: matches.get_one::<u64>("min-size").copied(),
+            max_size: matches.get_one::<u64>("max-size").copied(),
+            older_than: matches.get_one::<Duration>("older-than")
+                .map(|e| humantime::format_duration(*e).to_string()),
+            newer_than: matches.get_one::<Duration>("newer-than")
+                .map(|e| humantime::format_duration(*e).to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SkippedEntry {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+/// A directory that is empty, or left empty by the removals, in the order they are removed.
+#[derive(Serialize, Deserialize)]
+pub struct PlannedDir {
+    pub path: String,
+    /// The planned removals directly inside it, files and directories alike.
+    pub becomes_empty_after: Vec<String>,
+}
+
+/// What a scan plans to remove and leaves alone, with paths relative to the directory and
+/// `/`-separated. Files and skipped entries are sorted; directories come children first.
+#[derive(Serialize, Deserialize)]
+pub struct PlannedEntries {
+    pub files: Vec<String>,
+    pub dirs: Vec<PlannedDir>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+/// The plan of a dry run with everything that went into it, written by `snapshot` and checked
+/// by `compare-snapshot`.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Version of torrent-cleaner that took the snapshot.
+    pub tool_version: String,
+    pub info_hash: String,
+    /// XXH3 of the torrent's file paths and sizes.
+    pub expected_digest: String,
+    pub options: PlanOptions,
+    pub plan: PlannedEntries,
+    /// Absolute paths, only recorded with `--include-paths`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub torrent: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+}
+
+fn relative(dir: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(dir).unwrap_or(path);
+    rel.iter().map(|e| e.to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+fn sorted(dir: &Path, paths: &[PathBuf]) -> Vec<String> {
+    let mut paths = paths.iter().map(|e| relative(dir, e)).collect::<Vec<String>>();
+    paths.sort();
+    paths
+}
+
+/// Digest of the expected files, independent of the order they are listed in.
+pub fn expected_digest(expected: &impl ExpectedSet) -> String {
+    let mut files = expected.files()
+        .map(|(path, size)| (relative(Path::new(""), path), size))
+        .collect::<Vec<(String, u64)>>();
+    files.sort();
+    let mut hasher = Xxh3::new();
+    for (path, size) in files {
+        hasher.update(path.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(&size.to_le_bytes());
+    }
+    format!("{:016x}", hasher.digest())
+}
+
+impl Snapshot {
+    pub fn new(info_hash: String, expected: &impl ExpectedSet, dir: &Path, scan: &Scan,
+               options: PlanOptions) -> Self {
+        let mut skipped = scan.skipped.iter()
+            .map(|e| SkippedEntry { path: relative(dir, &e.path), reason: e.reason })
+            .collect::<Vec<SkippedEntry>>();
+        skipped.sort();
+        let dirs = scan.dir_cascade().into_iter()
+            .map(|(path, after)| {
+                let mut after = after.into_iter().map(|e| relative(dir, e)).collect::<Vec<String>>();
+                after.sort();
+                PlannedDir { path: relative(dir, path), becomes_empty_after: after }
+            })
+            .collect();
+        Self {
+            version: SNAPSHOT_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            info_hash,
+            expected_digest: expected_digest(expected),
+            options,
+            plan: PlannedEntries { files: sorted(dir, &scan.old_files), dirs, skipped },
+            torrent: None,
+            dir: None,
+        }
+    }
+
+    pub fn with_paths(self, torrent: PathBuf, dir: &Path) -> Self {
+        Self { torrent: Some(torrent), dir: Some(dir.to_path_buf()), ..self }
+    }
+
+    pub fn write(&self, file: &Path) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(file, content + "\n")
+            .with_context(|| format!("Failed to write snapshot {}", file.display()))
+    }
+
+    pub fn load(file: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read snapshot {}", file.display()))?;
+        let snapshot = serde_json::from_str::<Self>(&content)
+            .with_context(|| format!("Invalid snapshot {}", file.display()))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(anyhow!("Unsupported snapshot version {} in {}; this version of \
+                torrent-cleaner reads version {}", snapshot.version, file.display(), SNAPSHOT_VERSION));
+        }
+        Ok(snapshot)
+    }
+
+    /// One line per difference between this snapshot and `current`, a snapshot of the same
+    /// setup taken now: the torrent, the options, then the entries that entered (`+`) or left
+    /// (`-`) the plan.
+    pub fn differences(&self, current: &Snapshot) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.info_hash != current.info_hash {
+            lines.push(format!("Info hash: {} in the snapshot, {} now", self.info_hash,
+                               current.info_hash));
+        } else if self.expected_digest != current.expected_digest {
+            lines.push("The torrent's files or sizes changed".to_string());
+        }
+        let (Value::Object(old), Value::Object(new)) = (serde_json::to_value(&self.options)
+            .expect("serializable"), serde_json::to_value(&current.options).expect("serializable"))
+        else { unreachable!("options serialize to an object") };
+        for (name, value) in &old {
+            if new.get(name) != Some(value) {
+                lines.push(format!("Option --{}: {} in the snapshot, {} now",
+                    name.replace('_', "-"), value, new.get(name).unwrap_or(&Value::Null)));
+            }
+        }
+        fn changes<T: Ord>(old: &[T], new: &[T], show: impl Fn(&T) -> String) -> Vec<String> {
+            let old = old.iter().collect::<BTreeSet<&T>>();
+            let new = new.iter().collect::<BTreeSet<&T>>();
+            let added = new.difference(&old).map(|e| format!("+ {}", show(e)));
+            let removed = old.difference(&new).map(|e| format!("- {}", show(e)));
+            added.chain(removed).collect()
+        }
+        lines.extend(changes(&self.plan.files, &current.plan.files, |e| format!("file {}", e)));
+        let dirs = |plan: &PlannedEntries| plan.dirs.iter().map(|e| e.path.clone())
+            .collect::<Vec<String>>();
+        lines.extend(changes(&dirs(&self.plan), &dirs(&current.plan), |e| format!("dir {}/", e)));
+        lines.extend(changes(&self.plan.skipped, &current.plan.skipped,
+            |e| format!("skipped {} ({})", e.path, e.reason.describe())));
+        lines
+    }
+}
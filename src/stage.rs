@@ -0,0 +1,436 @@
+use crate::interrupt::interrupted;
+use crate::paths::PathRenderer;
+use crate::scan::{Scan, STAGE_DIR};
+use crate::truncate_message;
+use anyhow::{anyhow, Context};
+use indicatif::{BinaryBytes, ProgressBar};
+use serde::Serialize;
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use walkdir::WalkDir;
+
+/// Room left on the destination file system beyond the file itself before a copy is started.
+const SPACE_MARGIN: u64 = 64 * 1024 * 1024;
+/// Bytes copied at a time across file systems; also how much of a partial copy is compared
+/// before resuming it.
+const COPY_CHUNK: usize = 1024 * 1024;
+/// Suffix of a copy across file systems until it is complete.
+pub const PARTIAL_SUFFIX: &str = ".tc-partial";
+
+#[derive(Serialize)]
+struct ManifestEntry<'a> {
+    path: &'a Path,
+    size: u64,
+    /// False when `--touch-moved` gave the staged file a new modification time.
+    mtime_kept: bool,
+}
+
+/// A staged run: `<dir>/.torrent-cleaner-trash/<timestamp>/` plus its `<timestamp>.jsonl`
+/// manifest.
+pub struct StagedRun {
+    pub path: PathBuf,
+    pub manifest: PathBuf,
+    pub time: SystemTime,
+}
+
+pub fn run_name(time: SystemTime) -> String {
+    // Colons are not allowed in Windows file names
+    humantime::format_rfc3339_seconds(time).to_string().replace(':', "-")
+}
+
+fn parse_run_name(name: &str) -> Option<SystemTime> {
+    let stamp = &name[..name.find('Z')? + 1];
+    let (date, time) = stamp.split_once('T')?;
+    humantime::parse_rfc3339(&format!("{}T{}", date, time.replace('-', ":"))).ok()
+}
+
+/// The outcome of [`stage_files`] and [`move_files_to`].
+pub struct Staged {
+    /// The staging run, or the directory the files were moved into.
+    pub run: PathBuf,
+    /// Bytes of the files that were moved.
+    pub size: u64,
+    /// Files left in place because they did not fit on the file system of the run.
+    pub no_space: Vec<PathBuf>,
+    /// Files left in place because they changed since the scan passed as `revalidate`.
+    pub changed: Vec<PathBuf>,
+}
+
+/// What became of one file handed to [`transfer`].
+enum Transferred {
+    Moved { size: u64, mtime_kept: bool },
+    NoSpace,
+    Changed,
+}
+
+/// Moves `entry` to `target`, creating the directories in between, unless it changed since
+/// `revalidate` or does not fit; those are reported and left in place.
+fn transfer(entry: &Path, target: &Path, revalidate: Option<&Scan>, paths: &PathRenderer,
+            options: MoveOptions, progress: &ProgressBar) -> anyhow::Result<Transferred> {
+    if revalidate.is_some_and(|scan| scan.changed(entry)) {
+        progress.suspend(|| eprintln!("Skipped: {} (changed since plan)", paths.render(entry)));
+        return Ok(Transferred::Changed);
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    let size = fs::symlink_metadata(entry)?.len();
+    match move_file(entry, target, options, progress) {
+        Err(e) if e.kind() == io::ErrorKind::StorageFull => {
+            progress.suspend(|| eprintln!("Skipped: {} (insufficient space at destination)",
+                                          paths.render(entry)));
+            Ok(Transferred::NoSpace)
+        }
+        result => Ok(Transferred::Moved { size, mtime_kept: result
+            .with_context(|| format!("Failed to move file: {}", paths.render(entry)))? }),
+    }
+}
+
+/// Moves `files` (absolute paths under `dir`) into a new staging run, preserving their relative
+/// paths.
+pub fn stage_files(dir: &Path, files: &[PathBuf], revalidate: Option<&Scan>, paths: &PathRenderer,
+                   options: MoveOptions, progress: &ProgressBar) -> anyhow::Result<Staged> {
+    let stage = dir.join(STAGE_DIR);
+    fs::create_dir_all(&stage)
+        .with_context(|| format!("Failed to create staging directory {}", stage.display()))?;
+    let name = run_name(SystemTime::now());
+    let mut run = stage.join(&name);
+    let mut n = 0;
+    while let Err(e) = fs::create_dir(&run) {
+        if e.kind() != io::ErrorKind::AlreadyExists {
+            return Err(e).with_context(|| format!("Failed to create {}", run.display()));
+        }
+        n += 1;
+        run = stage.join(format!("{}_{}", name, n));
+    }
+
+    let manifest_path = run.with_extension("jsonl");
+    let mut manifest = OpenOptions::new().create(true).append(true).open(&manifest_path)
+        .with_context(|| format!("Failed to create manifest {}", manifest_path.display()))?;
+    let mut staged = Staged { run, size: 0, no_space: Vec::new(), changed: Vec::new() };
+    for entry in files {
+        if interrupted() {
+            break;
+        }
+        let rel = entry.strip_prefix(dir).with_context(||
+            format!("Failed to strip directory contents of {:?}", dir))?;
+        match transfer(entry, &staged.run.join(rel), revalidate, paths, options, progress)? {
+            Transferred::Moved { size, mtime_kept } => {
+                writeln!(manifest, "{}",
+                         serde_json::to_string(&ManifestEntry { path: rel, size, mtime_kept })?)?;
+                staged.size += size;
+                progress.set_message(truncate_message(
+                    format!("Staged file: {}", paths.render(entry))));
+            }
+            Transferred::NoSpace => staged.no_space.push(entry.clone()),
+            Transferred::Changed => staged.changed.push(entry.clone()),
+        }
+        progress.inc(1);
+    }
+    Ok(staged)
+}
+
+/// `path`, or the first of `path.1`, `path.2`… that does not exist yet.
+fn unused_path(path: PathBuf) -> PathBuf {
+    let mut candidate = path.clone();
+    let mut n = 0;
+    while candidate.symlink_metadata().is_ok() {
+        n += 1;
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}", n));
+        candidate = path.with_file_name(name);
+    }
+    candidate
+}
+
+/// Moves `files` (absolute paths under `dir`) into `to`, under the name of `dir` and their
+/// relative paths, so `<dir>/Extras/sample.mkv` becomes `<to>/<dir name>/Extras/sample.mkv`.
+/// Files already there are kept; the moved file gets a `.1`, `.2`… suffix instead.
+pub fn move_files_to(dir: &Path, files: &[PathBuf], to: &Path, revalidate: Option<&Scan>,
+                     paths: &PathRenderer, options: MoveOptions, progress: &ProgressBar)
+                     -> anyhow::Result<Staged> {
+    let run = to.join(dir.file_name().unwrap_or_default());
+    let mut moved = Staged { run, size: 0, no_space: Vec::new(), changed: Vec::new() };
+    for entry in files {
+        if interrupted() {
+            break;
+        }
+        let rel = entry.strip_prefix(dir).with_context(||
+            format!("Failed to strip directory contents of {:?}", dir))?;
+        let target = unused_path(moved.run.join(rel));
+        match transfer(entry, &target, revalidate, paths, options, progress)? {
+            Transferred::Moved { size, .. } => {
+                moved.size += size;
+                progress.set_message(truncate_message(
+                    format!("Moved file: {}", paths.render(entry))));
+            }
+            Transferred::NoSpace => moved.no_space.push(entry.clone()),
+            Transferred::Changed => moved.changed.push(entry.clone()),
+        }
+        progress.inc(1);
+    }
+    Ok(moved)
+}
+
+/// Whether `err` comes from a move that did not fit on the destination file system.
+pub fn is_no_space(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| e.downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::StorageFull))
+}
+
+/// Bytes available to unprivileged users on the file system holding `dir`, if known.
+#[cfg(unix)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read after a successful call
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+    let path = dir.as_os_str().encode_wide().chain([0]).collect::<Vec<u16>>();
+    let mut available = 0;
+    // SAFETY: `path` is NUL-terminated and the totals are optional
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(path.as_ptr(), &mut available, std::ptr::null_mut(),
+                            std::ptr::null_mut())
+    };
+    (ok != 0).then_some(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available_space(_: &Path) -> Option<u64> {
+    None
+}
+
+/// How [`move_file`] treats files it has to copy to another file system.
+#[derive(Clone, Copy, Default)]
+pub struct MoveOptions {
+    /// Give moved files the current modification time instead of keeping theirs.
+    pub touch: bool,
+    /// Bytes per second a copy may write, for slow or shared destinations.
+    pub bwlimit: Option<u64>,
+}
+
+/// Opens `path` just far enough to change its times, which also works for read-only files.
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::FILE_WRITE_ATTRIBUTES;
+    OpenOptions::new().access_mode(FILE_WRITE_ATTRIBUTES).open(path)
+}
+
+#[cfg(not(windows))]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+/// The name a copy has until it is complete; an interrupted copy is resumed from it.
+fn partial_path(to: &Path) -> PathBuf {
+    let mut name = to.file_name().unwrap_or_default().to_os_string();
+    name.push(PARTIAL_SUFFIX);
+    to.with_file_name(name)
+}
+
+fn read_at(file: &mut File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// How much of `source` an earlier, interrupted copy at `partial` already holds: its length, if
+/// it is no longer than `source` and its last chunk matches, and 0 otherwise.
+fn resumable(source: &mut File, partial: &Path, len: u64) -> io::Result<u64> {
+    let done = fs::metadata(partial).map_or(0, |m| m.len());
+    if done == 0 || done > len {
+        return Ok(0);
+    }
+    let tail = done.min(COPY_CHUNK as u64);
+    let matches = read_at(source, done - tail, tail as usize)?
+        == read_at(&mut File::open(partial)?, done - tail, tail as usize)?;
+    Ok(if matches { done } else { 0 })
+}
+
+/// Appends `source` from `done` on to `partial` in chunks, at most `bwlimit` bytes per second,
+/// and syncs it to disk.
+fn copy_chunks(source: &mut File, partial: &Path, done: u64, len: u64, options: MoveOptions,
+               progress: &ProgressBar) -> io::Result<()> {
+    let mut out = OpenOptions::new().create(true).write(true).truncate(false).open(partial)?;
+    out.set_len(done)?;
+    out.seek(SeekFrom::Start(done))?;
+    source.seek(SeekFrom::Start(done))?;
+    let name = partial.file_name().unwrap_or_default().to_string_lossy()
+        .trim_end_matches(PARTIAL_SUFFIX).to_string();
+    let mut buf = vec![0; COPY_CHUNK];
+    let (start, mut copied) = (Instant::now(), 0);
+    loop {
+        let n = source.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        out.write_all(&buf[..n])?;
+        copied += n as u64;
+        progress.set_message(truncate_message(format!("Copying {}: {} of {}", name,
+            BinaryBytes(done + copied), BinaryBytes(len))));
+        if let Some(limit) = options.bwlimit.filter(|e| *e > 0) {
+            let due = Duration::from_secs_f64(copied as f64 / limit as f64);
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+    }
+    out.sync_all()
+}
+
+/// Copies `from` to `to` with its permissions and times, unless `to`'s file system lacks the
+/// room for it. The copy is written next to `to` under a temporary name and only renamed once it
+/// is complete and synced; an interrupted copy is continued by the next attempt, while one that
+/// ran out of space is removed.
+fn copy_file(from: &Path, to: &Path, options: MoveOptions, progress: &ProgressBar)
+             -> io::Result<()> {
+    let meta = fs::metadata(from)?;
+    let partial = partial_path(to);
+    let mut source = File::open(from)?;
+    let done = resumable(&mut source, &partial, meta.len())?;
+    let available = to.parent().and_then(available_space);
+    if available.is_some_and(|e| e < (meta.len() - done).saturating_add(SPACE_MARGIN)) {
+        return Err(io::Error::new(io::ErrorKind::StorageFull,
+                                  "insufficient space at destination"));
+    }
+    copy_chunks(&mut source, &partial, done, meta.len(), options, progress).inspect_err(|e| {
+        if e.kind() == io::ErrorKind::StorageFull {
+            let _ = fs::remove_file(&partial);
+        }
+    })?;
+    let mut times = FileTimes::new()
+        .set_modified(if options.touch { SystemTime::now() } else { meta.modified()? });
+    if let Ok(accessed) = meta.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    open_for_times(&partial)?.set_times(times)?;
+    fs::set_permissions(&partial, meta.permissions())?;
+    fs::rename(&partial, to)
+}
+
+/// Renames `from` to `to`, falling back to copy and delete across file systems. Permissions and
+/// access and modification times are kept either way, unless [`MoveOptions::touch`] sets the
+/// modification time to now; returns whether it was kept. Fails with [`io::ErrorKind::StorageFull`], leaving `from`
+/// alone and no partial `to`, when the copy does not fit.
+pub fn move_file(from: &Path, to: &Path, options: MoveOptions, progress: &ProgressBar)
+                 -> io::Result<bool> {
+    match fs::rename(from, to) {
+        // A directory that is an extra as a whole is moved entry by entry
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices
+            && fs::symlink_metadata(from)?.is_dir() => {
+            fs::create_dir_all(to)?;
+            for entry in fs::read_dir(from)? {
+                let entry = entry?;
+                move_file(&entry.path(), &to.join(entry.file_name()), options, progress)?;
+            }
+            fs::remove_dir(from)?;
+        }
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_file(from, to, options, progress)?;
+            fs::remove_file(from)?;
+        }
+        Err(e) => return Err(e),
+        Ok(()) if options.touch => open_for_times(to)?.set_modified(SystemTime::now())?,
+        Ok(()) => {}
+    }
+    Ok(!options.touch)
+}
+
+/// Lists the staged runs of `dir`, oldest first.
+pub fn staged_runs(dir: &Path) -> anyhow::Result<Vec<StagedRun>> {
+    let stage = dir.join(STAGE_DIR);
+    let entries = match fs::read_dir(&stage) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", stage.display())),
+    };
+    let mut runs = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let Some(time) = path.file_name().and_then(|e| parse_run_name(&e.to_string_lossy())) else {
+            continue;
+        };
+        runs.push(StagedRun { manifest: path.with_extension("jsonl"), path, time });
+    }
+    runs.sort_by_key(|e| e.time);
+    Ok(runs)
+}
+
+impl StagedRun {
+    pub fn age(&self) -> Duration {
+        SystemTime::now().duration_since(self.time).unwrap_or_default()
+    }
+
+    pub fn size(&self) -> u64 {
+        WalkDir::new(&self.path).into_iter().flatten()
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| e.metadata().ok())
+            .map(|e| e.len())
+            .sum()
+    }
+
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or(String::new(), |e| e.to_string_lossy().into_owned())
+    }
+
+    /// The staged files, relative to the run directory and sorted; unfinished copies are left out.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files = WalkDir::new(&self.path).into_iter().flatten()
+            .filter(|e| !e.file_type().is_dir())
+            .filter(|e| !e.file_name().to_string_lossy().ends_with(PARTIAL_SUFFIX))
+            .filter_map(|e| e.path().strip_prefix(&self.path).ok().map(Path::to_path_buf))
+            .collect::<Vec<PathBuf>>();
+        files.sort();
+        files
+    }
+
+    /// Moves the staged file `rel` back to the same place under `dir`. An existing file there is
+    /// only replaced with `overwrite`; returns whether the file was restored.
+    pub fn restore(&self, rel: &Path, dir: &Path, overwrite: bool, options: MoveOptions,
+                   progress: &ProgressBar) -> anyhow::Result<bool> {
+        let target = dir.join(rel);
+        if !overwrite && fs::symlink_metadata(&target).is_ok() {
+            return Ok(false);
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        move_file(&self.path.join(rel), &target, options, progress)
+            .with_context(|| format!("Failed to restore {}", target.display()))?;
+        Ok(true)
+    }
+
+    pub fn purge(&self) -> anyhow::Result<()> {
+        fs::remove_dir_all(&self.path)
+            .with_context(|| format!("Failed to purge {}", self.path.display()))?;
+        match fs::remove_file(&self.manifest) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(anyhow!(e)
+                .context(format!("Failed to remove manifest {}", self.manifest.display()))),
+            _ => Ok(()),
+        }
+    }
+}
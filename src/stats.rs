@@ -0,0 +1,77 @@
+use crate::say;
+use crate::scan::{collisions, Collision, ExpectedSet, Scan};
+use indicatif::BinaryBytes;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// How much of a torrent the directory holds and what else lies there, printed by `stats`. The
+/// matching, mismatched and missing files together are the torrent's, with its sizes.
+#[derive(Clone, Debug, Serialize)]
+pub struct Stats {
+    pub expected_files: usize,
+    pub expected_size: u64,
+    /// Present files with the size the torrent declares.
+    pub matching_files: usize,
+    pub matching_size: u64,
+    /// Present files with another size, counted at the size declared for them.
+    pub mismatched_files: usize,
+    pub mismatched_size: u64,
+    pub missing_files: usize,
+    pub missing_size: u64,
+    pub extra_files: usize,
+    /// Empty directories, and those only holding extras.
+    pub extra_dirs: usize,
+    pub extra_size: u64,
+    /// Files of the torrent that cannot all exist on disk as spelled, e.g. differing only by case.
+    pub colliding_files: usize,
+    /// Share of the torrent's bytes in matching files.
+    pub completion_percent: f64,
+}
+
+impl Stats {
+    pub fn new(dir: &Path, expected: &impl ExpectedSet, scan: &Scan) -> Self {
+        let expected_size = expected.total_size();
+        let (matching_files, matching_size) = expected.matching(dir);
+        let mismatched = expected.mismatched(dir);
+        let (missing, missing_size) = expected.missing(dir);
+        let collisions = collisions(expected);
+        Self {
+            expected_files: expected.file_count(),
+            expected_size,
+            matching_files,
+            matching_size,
+            mismatched_files: mismatched.len(),
+            mismatched_size: mismatched.iter().map(|e| e.expected).sum(),
+            missing_files: missing.len(),
+            missing_size,
+            extra_files: scan.old_files.len(),
+            extra_dirs: scan.empty_dirs.len() + scan.emptied_dirs.len(),
+            extra_size: scan.rm_size,
+            colliding_files: collisions.iter().flat_map(Collision::paths)
+                .collect::<HashSet<&Path>>().len(),
+            // Rounded down, so that a directory lacking a few bytes never reads as 100%
+            completion_percent: match expected_size {
+                0 => 100.0,
+                size => (matching_size as f64 * 1000.0 / size as f64).floor() / 10.0,
+            },
+        }
+    }
+
+    pub fn print(&self) {
+        say!("Torrent:      {:>10} in {} files", BinaryBytes(self.expected_size).to_string(),
+             self.expected_files);
+        say!("Present:      {:>10} in {} files", BinaryBytes(self.matching_size).to_string(),
+             self.matching_files);
+        say!("Another size: {:>10} in {} files", BinaryBytes(self.mismatched_size).to_string(),
+             self.mismatched_files);
+        say!("Missing:      {:>10} in {} files", BinaryBytes(self.missing_size).to_string(),
+             self.missing_files);
+        say!("Extra:        {:>10} in {} files and {} directories",
+             BinaryBytes(self.extra_size).to_string(), self.extra_files, self.extra_dirs);
+        if self.colliding_files > 0 {
+            say!("Colliding:    {:>10} files", self.colliding_files);
+        }
+        say!("Completion:   {:>9.1}%", self.completion_percent);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::delete_ratio;
+use crate::error::EXIT_FAILED;
+use crate::kind::FileKind;
+use crate::scan::{DirKeptReason, ExpectedSet, KeptDir, Mismatch, Scan, SkipReason, Stamp};
+use crate::warnings::{denied, warnings, Warning, EXIT_WARNED};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Ok,
+    Aborted,
+    Failed,
+    /// Some files could not be moved for lack of space; everything else was done.
+    Incomplete,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct KindTotal {
+    pub count: usize,
+    pub size: u64,
+}
+
+/// A directory `--empty-dir` kept, with paths inside the scanned directory, `/`-separated.
+#[derive(Clone, Debug, Serialize)]
+pub struct KeptDirEntry {
+    pub path: String,
+    pub reason: DirKeptReason,
+    /// The first entry found that keeps it.
+    pub blocker: Option<String>,
+    /// The error removing it failed with.
+    pub error: Option<String>,
+}
+
+fn relative(dir: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(dir).unwrap_or(path);
+    rel.iter().map(|e| e.to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+impl KeptDirEntry {
+    pub fn list(dir: &Path, kept: &[KeptDir]) -> Vec<Self> {
+        kept.iter().map(|e| Self {
+            path: relative(dir, &e.path),
+            reason: e.reason,
+            blocker: e.blocker.as_ref().map(|b| relative(dir, b)),
+            error: e.error.clone(),
+        }).collect()
+    }
+}
+
+/// Totals of a diff or clean run, printed by `--summary-json`. This is the `summary` object of
+/// the full JSON document, so it must only ever gain fields both can report.
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+    pub command: String,
+    pub status: RunStatus,
+    pub exit_code: i32,
+    pub expected_files: usize,
+    pub expected_size: u64,
+    pub present_files: usize,
+    pub present_size: u64,
+    /// Present files that also have the size the torrent declares.
+    pub matching_files: usize,
+    pub matching_size: u64,
+    pub missing_files: usize,
+    pub missing_size: u64,
+    /// Present files with another size than the torrent declares.
+    pub mismatched_files: usize,
+    /// Bytes on disk less those declared, summed over the mismatched files.
+    pub mismatched_delta: i64,
+    pub extra_files: usize,
+    pub extra_dirs: usize,
+    /// Directories that only become empty once the extras are removed.
+    pub emptied_dirs: usize,
+    /// Directories `--empty-dir` kept, and why; after the removal for clean.
+    pub dirs_kept: Vec<KeptDirEntry>,
+    pub extra_size: u64,
+    /// Bytes allocated on disk to the extra files, less than `extra_size` for sparse files.
+    pub allocated_size: u64,
+    /// Of those, the bytes removing the extras frees.
+    pub freed_size: u64,
+    /// Extra files with links outside the removal, which free nothing.
+    pub hardlinked_files: usize,
+    /// Extra files per coarse file type.
+    pub extras_by_type: BTreeMap<&'static str, KindTotal>,
+    /// Entries left alone per reason.
+    pub skipped: BTreeMap<SkipReason, KindTotal>,
+    pub removed: usize,
+    pub removed_size: u64,
+    /// Files `--stage` left in place because they did not fit at the destination.
+    pub no_space: KindTotal,
+    /// Missing files of the torrent `--relink` supplied from extras, sparing their download.
+    pub relinked: KindTotal,
+    /// Files of the torrent `clean --verify` found corrupt, which `--delete-corrupt` removes with
+    /// the extras.
+    pub corrupt: KindTotal,
+    /// Why the labels or category of the torrent in the `--client` kept it from being cleaned.
+    pub held_back: Option<String>,
+    /// Share of the torrent's bytes present on disk.
+    pub overlap_percent: f64,
+    /// Share of the files found that are extra, which `--max-delete-ratio` limits.
+    pub delete_ratio: f64,
+    /// Nothing to remove, and every file of the torrent present with its size.
+    pub clean: bool,
+    /// Those printed up to the end of the run.
+    pub warnings: Vec<Warning>,
+    pub duration_secs: f64,
+}
+
+impl Summary {
+    pub fn new(command: &str, dir: &Path, expected: &impl ExpectedSet, scan: &Scan,
+               missing: &[PathBuf], missing_size: u64, start: Instant) -> Self {
+        let expected_size = expected.total_size();
+        let present_size = expected_size - missing_size;
+        let (matching_files, matching_size) = expected.matching(dir);
+        let mismatched = expected.mismatched(dir);
+        let mut extras_by_type = BTreeMap::<&'static str, KindTotal>::new();
+        for file in &scan.old_files {
+            let total = extras_by_type.entry(FileKind::of(file).name()).or_default();
+            total.count += 1;
+            if !scan.aliases.iter().any(|(alias, _)| alias == file) {
+                total.size += Stamp::read(file).map_or(0, |e| e.size);
+            }
+        }
+        let usage = scan.disk_usage();
+        let skipped = scan.skip_totals().into_iter()
+            .map(|(reason, (count, size))| (reason, KindTotal { count, size }))
+            .collect();
+        Self {
+            command: command.to_string(),
+            status: RunStatus::Ok,
+            exit_code: 0,
+            expected_files: expected.file_count(),
+            expected_size,
+            present_files: expected.file_count() - missing.len(),
+            present_size,
+            matching_files,
+            matching_size,
+            missing_files: missing.len(),
+            missing_size,
+            mismatched_files: mismatched.len(),
+            mismatched_delta: mismatched.iter().map(Mismatch::delta).sum(),
+            extra_files: scan.old_files.len(),
+            extra_dirs: scan.empty_dirs.len(),
+            emptied_dirs: scan.emptied_dirs.len(),
+            dirs_kept: KeptDirEntry::list(dir, &scan.kept_dirs),
+            extra_size: scan.rm_size,
+            allocated_size: usage.allocated,
+            freed_size: usage.freed,
+            hardlinked_files: usage.hardlinked.len(),
+            extras_by_type,
+            skipped,
+            removed: 0,
+            removed_size: 0,
+            no_space: KindTotal::default(),
+            relinked: KindTotal::default(),
+            corrupt: KindTotal::default(),
+            held_back: None,
+            overlap_percent: match expected_size {
+                0 => 100.0,
+                _ => present_size as f64 * 100.0 / expected_size as f64,
+            },
+            delete_ratio: delete_ratio(scan.old_files.len(), expected.file_count() - missing.len()),
+            clean: scan.removal_count() == 0 && matching_files == expected.file_count(),
+            warnings: Vec::new(),
+            duration_secs: start.elapsed().as_secs_f64(),
+        }
+    }
+
+    /// The summary with the final status, the warnings so far and the duration.
+    pub fn finished(&self, status: RunStatus, start: Instant) -> Self {
+        Self {
+            status,
+            exit_code: match status {
+                RunStatus::Failed | RunStatus::Incomplete => EXIT_FAILED.into(),
+                _ if denied() => EXIT_WARNED.into(),
+                _ => 0,
+            },
+            warnings: warnings(),
+            duration_secs: start.elapsed().as_secs_f64(),
+            ..self.clone()
+        }
+    }
+
+    /// Prints the summary as one line of JSON with the final status and duration.
+    pub fn print(&self, status: RunStatus, start: Instant) {
+        println!("{}", serde_json::to_string(&self.finished(status, start)).expect("serializable"));
+    }
+}
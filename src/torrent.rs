@@ -1,15 +1,655 @@
+use crate::download::{download, torrent_url};
+use crate::error::{CliError, ErrorCode};
+use crate::metadata;
+use crate::scan::is_padding;
+use crate::warnings::{Warning, WarningCode};
+use anyhow::anyhow;
 use librqbit_buffers::ByteBufOwned;
-use librqbit_core::torrent_metainfo::{torrent_from_bytes_ext, TorrentMetaV1};
-use std::fs::File;
-use std::io::Read;
+use librqbit_core::torrent_metainfo::{torrent_from_bytes_ext, TorrentMetaV1, TorrentMetaV1File,
+                                      TorrentMetaV1Info};
+use librqbit_core::Id20;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Read};
 use std::path::Path;
-use indicatif::ProgressBar;
+use std::sync::atomic::{AtomicBool, Ordering};
+use indicatif::{BinaryBytes, HumanCount, ProgressBar};
 
-pub fn parse_torrent(progress: &ProgressBar, file: impl AsRef<Path>) -> anyhow::Result<TorrentMetaV1<ByteBufOwned>> {
-    let file = file.as_ref().canonicalize()?;
-    let mut buf = Vec::new();
+/// Files smaller than this are read in one go without reporting progress.
+const PROGRESS_THRESHOLD: u64 = 16 * 1024 * 1024;
+const CHUNK_SIZE: usize = 1024 * 1024;
+/// Piece lengths outside this range are unusual enough to hint at hand-made metadata.
+const PIECE_LENGTH_RANGE: (u32, u32) = (16 * 1024, 128 * 1024 * 1024);
+/// More pieces than this means the piece length is far too small for the payload.
+const MAX_PIECES: u64 = 1 << 20;
+/// The torrent argument reading the .torrent file from stdin.
+const STDIN: &str = "-";
+/// The newest `meta version` understood; BEP 52 defines 2, and torrents without one are version 1.
+const META_VERSION: i64 = 2;
+const ISSUES_URL: &str = "https://github.com/AnvilloyDevStudio/torrent-cleaner/issues";
+
+static V1_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Reads torrents of a newer `meta version` than understood by their v1 part alone, if they have
+/// one, instead of refusing them.
+pub fn set_v1_only(v1_only: bool) {
+    V1_ONLY.store(v1_only, Ordering::Relaxed);
+}
+
+/// Loads a .torrent file, one piped in on stdin as `-` or downloaded from an HTTP(S) URL, or the
+/// metadata of a magnet URI, a `.magnet` file holding one, or a bare info hash, from the cache or
+/// else from peers.
+pub fn parse_torrent(progress: &ProgressBar, file: impl AsRef<Path>) -> anyhow::Result<Metainfo> {
+    if file.as_ref() == Path::new(STDIN) {
+        if io::stdin().is_terminal() {
+            return Err(CliError::new(ErrorCode::TorrentUnreadable, "The torrent is read from \
+                stdin with -, but stdin is a terminal; pipe the .torrent file in").into());
+        }
+        let mut buf = Vec::new();
+        io::stdin().read_to_end(&mut buf).map_err(|e| CliError::new(ErrorCode::TorrentUnreadable,
+            format!("Failed to read the torrent from stdin: {}", e)))?;
+        return parse_torrent_bytes(progress, &buf, file.as_ref());
+    }
+    if let Some(info_hash) = named_info_hash(file.as_ref()) {
+        let input = file.as_ref().to_string_lossy();
+        return metadata::load(progress, &info_hash, &input);
+    }
+    if let Some(url) = torrent_url(file.as_ref()) {
+        progress.println(format!("Torrent URL: {}", url));
+        progress.set_message("Downloading...");
+        let buf = download(url)?;
+        progress.set_message("Parsing...");
+        return parse_torrent_bytes(progress, &buf, file.as_ref());
+    }
+    let unreadable = |e: std::io::Error| CliError::new(ErrorCode::TorrentUnreadable,
+        format!("Failed to read torrent file: {}", e)).with_path(file.as_ref());
+    let file = file.as_ref().canonicalize().map_err(unreadable)?;
     progress.println(format!("Torrent file: {}", file.display()));
-    File::open(&file)?.read_to_end(&mut buf)?;
-    let buf = ByteBufOwned::from(buf);
-    Ok(torrent_from_bytes_ext(buf.as_ref())?.meta)
+    if file.extension().is_some_and(|e| e.eq_ignore_ascii_case("magnet")) {
+        let uri = fs::read_to_string(&file).map_err(unreadable)?;
+        let info_hash = info_hash_of(uri.trim()).ok_or_else(|| CliError::new(
+            ErrorCode::TorrentInvalid, "No magnet URI with an info hash in the file")
+            .with_path(&file))?;
+        return metadata::load(progress, &info_hash, uri.trim());
+    }
+    let buf = read_file(progress, &file).map_err(unreadable)?;
+    parse_torrent_bytes(progress, &buf, &file)
+}
+
+/// Parses a .torrent file already read into memory, e.g. a downloaded one; `origin` is where it
+/// came from, for the errors.
+pub fn parse_torrent_bytes(progress: &ProgressBar, buf: &[u8], origin: &Path)
+                           -> anyhow::Result<Metainfo> {
+    let parsed = parse_metainfo(buf).map_err(|e| match e.downcast::<CliError>() {
+        Ok(e) => e.with_path(origin),
+        Err(e) => CliError::new(ErrorCode::TorrentInvalid,
+            format!("Failed to parse torrent file: {:#}", e)).with_path(origin),
+    })?;
+    for warning in &parsed.warnings {
+        let warning = Warning::new(WarningCode::TorrentMetadata, warning.clone()).at(origin)
+            .record();
+        progress.suspend(|| eprintln!("{}", warning));
+    }
+    metadata::store(&parsed.meta.info_hash.as_string(), &parsed.info_bytes);
+    Ok(parsed)
+}
+
+/// A decoded bencode value, borrowing its strings from the buffer.
+pub enum Bencode<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(Vec<Bencode<'a>>),
+    /// The entries in their encoded order, and the raw bytes of the whole dictionary.
+    Dict(Vec<(&'a [u8], Bencode<'a>)>, &'a [u8]),
+}
+
+/// Nesting deeper than this is refused rather than risking the stack on a crafted file.
+const MAX_DEPTH: usize = 256;
+
+impl<'a> Bencode<'a> {
+    /// Decodes the value at the start of `buf`, returning it with the rest of the buffer.
+    pub fn decode(buf: &'a [u8], depth: usize) -> Option<(Self, &'a [u8])> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        match *buf.first()? {
+            b'i' => {
+                let end = buf.iter().position(|b| *b == b'e')?;
+                let value = std::str::from_utf8(&buf[1..end]).ok()?.parse().ok()?;
+                Some((Bencode::Int(value), &buf[end + 1..]))
+            }
+            b'l' => {
+                let (mut items, mut rest) = (Vec::new(), &buf[1..]);
+                while *rest.first()? != b'e' {
+                    let (item, next) = Self::decode(rest, depth + 1)?;
+                    items.push(item);
+                    rest = next;
+                }
+                Some((Bencode::List(items), &rest[1..]))
+            }
+            b'd' => {
+                let (mut entries, mut rest) = (Vec::new(), &buf[1..]);
+                while *rest.first()? != b'e' {
+                    let (Bencode::Bytes(key), next) = Self::decode(rest, depth + 1)? else {
+                        return None;
+                    };
+                    let (value, next) = Self::decode(next, depth + 1)?;
+                    entries.push((key, value));
+                    rest = next;
+                }
+                let raw = &buf[..buf.len() - rest.len() + 1];
+                Some((Bencode::Dict(entries, raw), &rest[1..]))
+            }
+            b'0'..=b'9' => {
+                let colon = buf.iter().position(|b| *b == b':')?;
+                let len = std::str::from_utf8(&buf[..colon]).ok()?.parse::<usize>().ok()?;
+                let end = (colon + 1).checked_add(len)?;
+                Some((Bencode::Bytes(buf.get(colon + 1..end)?), &buf[end..]))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Bencode<'a>> {
+        match self {
+            Bencode::Dict(entries, _) => entries.iter()
+                .find_map(|(k, v)| (*k == key.as_bytes()).then_some(v)),
+            _ => None,
+        }
+    }
+
+    pub fn int(&self) -> Option<i64> {
+        match self {
+            Bencode::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn bytes(&self) -> Option<ByteBufOwned> {
+        match self {
+            Bencode::Bytes(bytes) => Some(ByteBufOwned::from(*bytes)),
+            _ => None,
+        }
+    }
+}
+
+/// A file of a v2 or hybrid torrent; empty files have no pieces root.
+pub struct V2File {
+    pub path: Vec<Vec<u8>>,
+    pub length: u64,
+    pub pieces_root: Option<Vec<u8>>,
+}
+
+impl V2File {
+    pub fn name(&self) -> String {
+        self.path.iter().map(|e| String::from_utf8_lossy(e)).collect::<Vec<_>>().join("/")
+    }
+}
+
+/// Collects the files of a BEP 52 `file tree`; a file is the node holding an empty key.
+fn tree_files(node: &Bencode, prefix: &mut Vec<Vec<u8>>, files: &mut Vec<V2File>) -> Option<()> {
+    let Bencode::Dict(entries, _) = node else { return None };
+    for (name, child) in entries {
+        if name.is_empty() {
+            let pieces_root = child.get("pieces root").and_then(|e| match e {
+                Bencode::Bytes(root) => Some(root.to_vec()),
+                _ => None,
+            });
+            let length = child.get("length")?.int()?.try_into().ok()?;
+            files.push(V2File { path: prefix.clone(), length, pieces_root });
+            continue;
+        }
+        prefix.push(name.to_vec());
+        tree_files(child, prefix, files)?;
+        prefix.pop();
+    }
+    Some(())
+}
+
+/// The layers of a `piece layers` dictionary, by pieces root.
+fn layers_of(dict: &Bencode) -> Option<HashMap<Vec<u8>, Vec<u8>>> {
+    let Bencode::Dict(entries, _) = dict else { return None };
+    entries.iter().map(|(root, layer)| match layer {
+        Bencode::Bytes(layer) => Some((root.to_vec(), layer.to_vec())),
+        _ => None,
+    }).collect()
+}
+
+fn v1_file(path: &[Vec<u8>], length: u64) -> TorrentMetaV1File<ByteBufOwned> {
+    TorrentMetaV1File {
+        length,
+        path: path.iter().map(|e| ByteBufOwned::from(e.as_slice())).collect(),
+        attr: None,
+        sha1: None,
+        symlink_path: None,
+    }
+}
+
+/// Pure v2 torrents keep their piece hashes in `piece layers`, outside the info dict, so
+/// [`parse_metainfo`] leaves `pieces` empty for them.
+pub fn is_v2_only(info: &TorrentMetaV1Info<ByteBufOwned>) -> bool {
+    info.pieces.as_ref().is_empty()
+}
+
+/// A parsed .torrent file with its raw info dict and what was odd about it.
+pub struct Metainfo {
+    pub meta: TorrentMetaV1<ByteBufOwned>,
+    pub info_bytes: Vec<u8>,
+    pub warnings: Vec<String>,
+    /// The files of the v2 `file tree`, in its order; empty for v1 torrents.
+    pub v2_files: Vec<V2File>,
+    /// The `piece layers` of the .torrent file, by pieces root, if it has them.
+    pub piece_layers: Option<HashMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl Metainfo {
+    /// Whether the info dict sets `private` to 1, as torrents of private trackers do (BEP 27).
+    pub fn is_private(&self) -> bool {
+        Bencode::decode(&self.info_bytes, 0)
+            .and_then(|(info, _)| info.get("private").and_then(Bencode::int)) == Some(1)
+    }
+
+    /// The host of the torrent's first tracker, lowercased and without port or credentials.
+    pub fn announce_host(&self) -> Option<String> {
+        let url = self.meta.iter_announce().next()?;
+        let url = String::from_utf8_lossy(url.as_ref());
+        let authority = url.split_once("://").map_or(&*url, |(_, rest)| rest)
+            .split(['/', '?', '#']).next()?;
+        let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+        let host = match host.strip_prefix('[') {
+            Some(ipv6) => ipv6.split(']').next()?,
+            None => host.split(':').next()?,
+        };
+        (!host.is_empty()).then(|| host.to_ascii_lowercase())
+    }
+}
+
+/// Parses a .torrent file of any BitTorrent version into the v1 form the rest of the tool reads.
+/// The file list of v2 and hybrid torrents comes from the v2 `file tree`; for hybrid ones it is
+/// checked against the v1 list, leaving out padding files. Pure v2 torrents have no v1 info hash,
+/// so they are identified by their SHA-256 one truncated to 20 bytes, as trackers and the DHT do.
+///
+/// A newer `meta version` than [`META_VERSION`] fails with [`ErrorCode::TorrentUnsupported`],
+/// unless [`set_v1_only`] allows reading the v1 part such a torrent still has.
+pub fn parse_metainfo(buf: &[u8]) -> anyhow::Result<Metainfo> {
+    let (root, _) = Bencode::decode(buf, 0).ok_or_else(|| anyhow!("malformed bencode"))?;
+    let info = root.get("info").ok_or_else(|| anyhow!("missing info dictionary"))?;
+    if let Some(version) = info.get("meta version").and_then(Bencode::int)
+        .filter(|e| *e > META_VERSION) {
+        let has_v1 = info.get("pieces").is_some()
+            && (info.get("files").is_some() || info.get("length").is_some());
+        if !has_v1 || !V1_ONLY.load(Ordering::Relaxed) {
+            let hint = match has_v1 {
+                true => "; its v1 part can still be read with --v1-only",
+                false => ", and it has no v1 part to fall back to",
+            };
+            return Err(CliError::new(ErrorCode::TorrentUnsupported, format!("The torrent uses \
+                meta version {}, newer than this version of torrent-cleaner supports (up to {}){}. \
+                See {} for support of newer versions", version, META_VERSION, hint, ISSUES_URL))
+                .into());
+        }
+        let parsed = torrent_from_bytes_ext::<ByteBufOwned>(buf)?;
+        let info_bytes = parsed.info_bytes.as_ref().to_vec();
+        let warning = format!("the torrent uses meta version {}, which is not supported; only its \
+            v1 part is read (--v1-only), so anything specific to the newer version is ignored",
+            version);
+        return Ok(Metainfo { meta: parsed.meta, info_bytes, warnings: vec![warning],
+                             v2_files: Vec::new(), piece_layers: None });
+    }
+    let (Some(2), Some(tree)) = (info.get("meta version").and_then(Bencode::int),
+                                 info.get("file tree")) else {
+        // The decoded tree is as large as the parsed torrent again for one of many files
+        drop(root);
+        let parsed = torrent_from_bytes_ext::<ByteBufOwned>(buf)?;
+        let info_bytes = parsed.info_bytes.as_ref().to_vec();
+        return Ok(Metainfo { meta: parsed.meta, info_bytes, warnings: Vec::new(),
+                             v2_files: Vec::new(), piece_layers: None });
+    };
+    let mut files = Vec::new();
+    tree_files(tree, &mut Vec::new(), &mut files).ok_or_else(|| anyhow!("malformed file tree"))?;
+    let Bencode::Dict(_, info_bytes) = info else { return Err(anyhow!("malformed info dictionary")) };
+    let name = info.get("name").and_then(Bencode::bytes);
+    // A single file is a tree of one file named after the torrent
+    let single = match &files[..] {
+        [file] if file.path.len() == 1
+            && name.as_ref().is_some_and(|e| *e.as_ref() == *file.path[0]) => Some(file.length),
+        _ => None,
+    };
+
+    let mut warnings = Vec::new();
+    let mut meta = if info.get("pieces").is_some() {
+        let meta = torrent_from_bytes_ext::<ByteBufOwned>(buf)?.meta;
+        let mut v1 = meta.info.files.iter().flatten().filter(|e| !is_padding(e))
+            .map(|e| (e.path.iter().map(|s| s.as_ref().to_vec()).collect(), e.length))
+            .collect::<Vec<(Vec<Vec<u8>>, u64)>>();
+        let mut v2 = files.iter().map(|e| (e.path.clone(), e.length))
+            .collect::<Vec<(Vec<Vec<u8>>, u64)>>();
+        v1.sort();
+        v2.sort();
+        let consistent = match single {
+            Some(length) => meta.info.length == Some(length),
+            None => v1 == v2,
+        };
+        if !consistent {
+            warnings.push("the v1 and v2 file lists of this hybrid torrent disagree; using the \
+                v2 file tree".to_string());
+        }
+        meta
+    } else {
+        let digest = crypto_hash::digest(crypto_hash::Algorithm::SHA256, info_bytes);
+        let mut info_hash = [0; 20];
+        info_hash.copy_from_slice(&digest[..20]);
+        let top = |key| root.get(key).and_then(Bencode::bytes);
+        TorrentMetaV1 {
+            announce: top("announce"),
+            announce_list: match root.get("announce-list") {
+                Some(Bencode::List(tiers)) => tiers.iter().map(|tier| match tier {
+                    Bencode::List(urls) => urls.iter().filter_map(Bencode::bytes).collect(),
+                    _ => Vec::new(),
+                }).collect(),
+                _ => Vec::new(),
+            },
+            info: TorrentMetaV1Info {
+                name,
+                pieces: ByteBufOwned::from(&[][..]),
+                piece_length: info.get("piece length").and_then(Bencode::int)
+                    .and_then(|e| e.try_into().ok())
+                    .ok_or_else(|| anyhow!("missing or invalid piece length"))?,
+                length: single,
+                attr: None,
+                sha1: None,
+                symlink_path: None,
+                md5sum: None,
+                files: None,
+            },
+            comment: top("comment"),
+            created_by: top("created by"),
+            encoding: top("encoding"),
+            publisher: top("publisher"),
+            publisher_url: top("publisher-url"),
+            creation_date: root.get("creation date").and_then(Bencode::int)
+                .and_then(|e| e.try_into().ok()),
+            info_hash: Id20::new(info_hash),
+        }
+    };
+    meta.info.length = single;
+    meta.info.files = single.is_none()
+        .then(|| files.iter().map(|e| v1_file(&e.path, e.length)).collect());
+    let piece_layers = root.get("piece layers").map(|e| layers_of(e)
+        .ok_or_else(|| anyhow!("malformed piece layers"))).transpose()?;
+    Ok(Metainfo { meta, info_bytes: info_bytes.to_vec(), warnings, v2_files: files, piece_layers })
+}
+
+/// Size of the blocks the merkle trees of v2 torrents are built from.
+const BLOCK_SIZE: u64 = 16 * 1024;
+
+fn sha256(parts: &[&[u8]]) -> Vec<u8> {
+    crypto_hash::digest(crypto_hash::Algorithm::SHA256, &parts.concat())
+}
+
+/// Merkle root over a piece layer: a 32-byte hash per piece, padded to a power of two with the
+/// hash of a piece of zero blocks, as BEP 52 defines it.
+fn layer_root(layer: &[u8], piece_length: u64) -> Option<Vec<u8>> {
+    if layer.is_empty() || !layer.len().is_multiple_of(32) {
+        return None;
+    }
+    let mut pad = vec![0; 32];
+    let mut covered = BLOCK_SIZE;
+    while covered < piece_length {
+        pad = sha256(&[&pad, &pad]);
+        covered *= 2;
+    }
+    let mut level = layer.chunks(32).map(<[u8]>::to_vec).collect::<Vec<Vec<u8>>>();
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            level.push(pad.clone());
+        }
+        level = level.chunks(2).map(|e| sha256(&[&e[0], &e[1]])).collect();
+        pad = sha256(&[&pad, &pad]);
+    }
+    level.pop()
+}
+
+/// How finely a file of a v2 torrent can be verified.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LayerCheck {
+    /// Piece by piece, with a piece layer that hashes to its pieces root.
+    ByPiece,
+    /// The file fits in one piece, so its pieces root is the hash of that piece.
+    SinglePiece,
+    /// Only as a whole, since its piece layer is missing.
+    Missing,
+    /// Only as a whole, since its piece layer does not hash to its pieces root.
+    Mismatch,
+}
+
+/// Checks the piece layer of each non-empty file against its pieces root.
+pub fn check_layers<'a>(files: &'a [V2File], piece_length: u64,
+                        layers: &HashMap<Vec<u8>, Vec<u8>>) -> Vec<(&'a V2File, LayerCheck)> {
+    files.iter().filter_map(|file| {
+        let root = file.pieces_root.as_ref()?;
+        let pieces = file.length.div_ceil(piece_length.max(1));
+        let check = match layers.get(root) {
+            _ if pieces <= 1 => LayerCheck::SinglePiece,
+            None => LayerCheck::Missing,
+            Some(layer) if layer.len() as u64 == pieces * 32
+                && layer_root(layer, piece_length).as_ref() == Some(root) => LayerCheck::ByPiece,
+            Some(_) => LayerCheck::Mismatch,
+        };
+        Some((file, check))
+    }).collect()
+}
+
+/// The renames Transmission records in the `.resume` file of a torrent: the torrent's `name` and
+/// the `files` paths relative to its download directory, empty for a file kept under its own.
+pub struct TransmissionResume {
+    pub name: Option<String>,
+    /// In the order of the torrent's files; empty if the file does not list them.
+    pub files: Vec<String>,
+}
+
+/// Reads a Transmission `.resume` file, a bencoded dictionary; `files` only needs to be a list
+/// and other keys, such as `priority`, are ignored.
+pub fn read_transmission_resume(file: &Path) -> anyhow::Result<TransmissionResume> {
+    let buf = fs::read(file).map_err(|e| anyhow!("failed to read {}: {}", file.display(), e))?;
+    let invalid = || anyhow!("{} is not a bencoded resume dictionary", file.display());
+    let root = match Bencode::decode(&buf, 0) {
+        Some((root @ Bencode::Dict(..), _)) => root,
+        _ => return Err(invalid()),
+    };
+    let text = |value: &Bencode| match value {
+        Bencode::Bytes(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+        _ => None,
+    };
+    let files = match root.get("files") {
+        None => Vec::new(),
+        Some(Bencode::List(items)) => items.iter().map(text).collect::<Option<Vec<String>>>()
+            .ok_or_else(invalid)?,
+        Some(_) => return Err(invalid()),
+    };
+    Ok(TransmissionResume { name: root.get("name").and_then(text), files })
+}
+
+/// Reads piece layers distributed apart from their torrent: a bencoded dictionary of layers by
+/// pieces root, on its own or as the `piece layers` of a dictionary holding it.
+pub fn load_piece_layers(file: &Path) -> anyhow::Result<HashMap<Vec<u8>, Vec<u8>>> {
+    let buf = fs::read(file).map_err(|e| CliError::new(ErrorCode::TorrentUnreadable,
+        format!("Failed to read piece layers: {}", e)).with_path(file))?;
+    let invalid = || CliError::new(ErrorCode::TorrentInvalid, "Not a bencoded piece layers \
+        dictionary").with_path(file);
+    let (root, _) = Bencode::decode(&buf, 0).ok_or_else(invalid)?;
+    Ok(layers_of(root.get("piece layers").unwrap_or(&root)).ok_or_else(invalid)?)
+}
+
+/// Whether `input` is the path of a local file, rather than `-` for stdin, a URL, a magnet URI or
+/// an info hash, which are kept as given instead of being made absolute.
+pub fn is_local_path(input: &Path) -> bool {
+    input != Path::new(STDIN) && named_info_hash(input).is_none() && torrent_url(input).is_none()
+}
+
+/// The info hash `input` names if it is a magnet URI or an info hash rather than an existing file.
+pub fn named_info_hash(input: &Path) -> Option<String> {
+    input.to_str().and_then(info_hash_of).filter(|_| !input.exists())
+}
+
+/// The lowercase hex info hash named by a v1 or v2 magnet URI or given directly in hex or
+/// base32; `None` for anything else, such as a file path.
+fn info_hash_of(input: &str) -> Option<String> {
+    let hash = match input.strip_prefix("magnet:?") {
+        Some(query) => {
+            let topics = query.split('&').filter_map(|e| e.strip_prefix("xt=urn:"));
+            // A v2 info hash, a multihash of `1220` and a SHA-256 digest, is known by its first
+            // 20 bytes; hybrid torrents go by their v1 one
+            topics.clone().find_map(|e| e.strip_prefix("btih:")).or_else(|| topics
+                .filter_map(|e| e.strip_prefix("btmh:1220")).find(|e| e.len() == 64)
+                .map(|e| &e[..40]))?
+        }
+        None => input,
+    };
+    match hash.len() {
+        40 if hash.bytes().all(|b| b.is_ascii_hexdigit()) => Some(hash.to_ascii_lowercase()),
+        32 => decode_base32(hash).map(|bytes| bytes.iter().map(|b| format!("{:02x}", b)).collect()),
+        _ => None,
+    }
+}
+
+/// Decodes RFC 4648 base32 without padding, as used by older magnet links.
+fn decode_base32(input: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in input.bytes() {
+        let value = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        buffer = buffer << 5 | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Reads the whole file, reporting the bytes read so far for large files.
+fn read_file(progress: &ProgressBar, file: &Path) -> std::io::Result<Vec<u8>> {
+    let mut f = File::open(file)?;
+    let size = f.metadata()?.len();
+    let mut buf = Vec::with_capacity(size as usize);
+    if size < PROGRESS_THRESHOLD {
+        f.read_to_end(&mut buf)?;
+        return Ok(buf);
+    }
+
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let n = f.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        progress.set_message(format!("Parsing... {}/{} read", BinaryBytes(buf.len() as u64),
+                                     BinaryBytes(size)));
+    }
+    progress.set_message("Decoding...");
+    Ok(buf)
+}
+
+/// The `--strict-torrent` error for the problems found by [`check_geometry`].
+pub fn geometry_error(problems: &[String]) -> CliError {
+    CliError::new(ErrorCode::TorrentInvalid,
+        format!("Inconsistent torrent geometry: {}", problems.join("; ")))
+}
+
+/// Sum of the file lengths, or `None` when it does not fit in 64 bits.
+fn total_length(info: &TorrentMetaV1Info<ByteBufOwned>) -> Option<u64> {
+    info.files.iter().flatten().try_fold(info.length.unwrap_or(0), |total, e| total.checked_add(e.length))
+}
+
+/// Pieces needed when every file starts a new piece, as in v2 torrents and the v1 pieces of
+/// hybrid ones, whose padding files are not in the v2 file tree.
+fn aligned_pieces(info: &TorrentMetaV1Info<ByteBufOwned>) -> u64 {
+    let piece_length = (info.piece_length as u64).max(1);
+    info.files.iter().flatten().map(|e| e.length).chain(info.length)
+        .map(|e| e.div_ceil(piece_length)).sum()
+}
+
+/// Pieces of pure v2 torrents are counted from their files, since their hashes are not in the
+/// info dict.
+pub fn piece_count(info: &TorrentMetaV1Info<ByteBufOwned>) -> u64 {
+    match is_v2_only(info) {
+        true => aligned_pieces(info),
+        false => info.pieces.as_ref().len() as u64 / 20,
+    }
+}
+
+/// One-line overview of the payload, e.g. `Torrent: 1,044 files, 930.40 GiB, 14,218 pieces of
+/// 64.00 MiB`, to confirm the right .torrent was given.
+pub fn overview(info: &TorrentMetaV1Info<ByteBufOwned>) -> String {
+    let files = info.files.as_ref().map_or(1, |e| e.len());
+    let total = match total_length(info) {
+        Some(total) => BinaryBytes(total).to_string(),
+        None => "an overflowing size".to_string(),
+    };
+    format!("Torrent: {} file{}, {}, {} pieces of {}", HumanCount(files as u64),
+            if files == 1 { "" } else { "s" }, total, HumanCount(piece_count(info)),
+            BinaryBytes(info.piece_length as u64))
+}
+
+/// Describes every inconsistency between the piece length, the piece hashes and the total size
+/// of the files; an empty list means the geometry is sound. The hashes of pure v2 torrents are not
+/// in the info dict, so only their piece length is checked.
+pub fn check_geometry(info: &TorrentMetaV1Info<ByteBufOwned>) -> Vec<String> {
+    let mut problems = Vec::new();
+    let piece_length = info.piece_length as u64;
+    let Some(total) = total_length(info) else {
+        problems.push("file lengths add up to more than 16 EiB".to_string());
+        return problems;
+    };
+    let hashes = info.pieces.as_ref().len();
+    if piece_length == 0 {
+        problems.push("piece length is 0".to_string());
+        return problems;
+    }
+    let covered = (hashes as u64 / 20).saturating_mul(piece_length);
+    let oversized = info.files.iter().flatten()
+        .filter(|e| e.length > covered && !is_v2_only(info)).collect::<Vec<_>>();
+    if let Some(file) = oversized.first() {
+        let name = file.path.iter().map(|e| String::from_utf8_lossy(e.as_ref()))
+            .collect::<Vec<_>>().join("/");
+        let more = match oversized.len() {
+            1 => String::new(),
+            n => format!(" (and {} more files)", n - 1),
+        };
+        problems.push(format!("{} is {}, more than the {} the piece data covers{}", name,
+                              BinaryBytes(file.length), BinaryBytes(covered), more));
+    }
+    if !info.piece_length.is_power_of_two() {
+        problems.push(format!("piece length {} is not a power of two", BinaryBytes(piece_length)));
+    }
+    let (min, max) = PIECE_LENGTH_RANGE;
+    if info.piece_length < min || info.piece_length > max {
+        problems.push(format!("piece length {} is outside {} to {}", BinaryBytes(piece_length),
+                              BinaryBytes(min as u64), BinaryBytes(max as u64)));
+    }
+    let pieces = match is_v2_only(info) {
+        true => aligned_pieces(info),
+        false => total.div_ceil(piece_length),
+    };
+    if pieces > MAX_PIECES {
+        problems.push(format!("{} pieces of {} for {} is unusually many", pieces,
+                              BinaryBytes(piece_length), BinaryBytes(total)));
+    }
+    if is_v2_only(info) {
+        return problems;
+    }
+    if hashes % 20 != 0 {
+        problems.push(format!("pieces field is {} bytes, not a multiple of 20", hashes));
+    } else if hashes as u64 / 20 != pieces && hashes as u64 / 20 != aligned_pieces(info) {
+        problems.push(format!("{} piece hashes, but {} of {} are needed to cover {}", hashes / 20,
+                              pieces, BinaryBytes(piece_length), BinaryBytes(total)));
+    }
+    problems
 }
@@ -0,0 +1,70 @@
+use crate::scan::ExpectedSet;
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// What `touch` creates: the files of the torrent missing under the directory, and the
+/// directories that are not there to hold them.
+pub struct Placeholders {
+    /// Sorted by path, so that each comes after the directory holding it.
+    pub dirs: Vec<PathBuf>,
+    /// With the size the torrent declares, sorted by path.
+    pub files: Vec<(PathBuf, u64)>,
+}
+
+/// How many placeholders were created, and those that could not be with why.
+pub struct Creation {
+    pub dirs: usize,
+    pub files: usize,
+    pub size: u64,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl Placeholders {
+    pub fn find(dir: &Path, expected: &impl ExpectedSet) -> Self {
+        let (missing, _) = expected.missing(dir);
+        let missing = missing.into_iter().collect::<BTreeSet<PathBuf>>();
+        let mut files = expected.files()
+            .map(|(path, size)| (expected.locate(dir, path), size))
+            .filter(|(path, _)| missing.contains(path))
+            .collect::<Vec<(PathBuf, u64)>>();
+        files.sort();
+        files.dedup_by(|a, b| a.0 == b.0);
+        let dirs = files.iter()
+            .flat_map(|(path, _)| path.ancestors().skip(1).take_while(|e| *e != dir))
+            .filter(|e| fs::symlink_metadata(e).is_err())
+            .map(Path::to_path_buf)
+            .collect::<BTreeSet<PathBuf>>();
+        Self { dirs: dirs.into_iter().collect(), files }
+    }
+
+    /// The total size the torrent declares for the files.
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|(_, size)| size).sum()
+    }
+
+    /// Creates the directories and then the files, empty or, with `allocate`, of their declared
+    /// length, which most file systems leave sparse. A file is only ever created anew, so one
+    /// that appeared since is left as it is and reported as failed.
+    pub fn create(&self, allocate: bool) -> Creation {
+        let mut creation = Creation { dirs: 0, files: 0, size: 0, failed: Vec::new() };
+        for dir in &self.dirs {
+            match fs::create_dir(dir) {
+                Ok(()) => creation.dirs += 1,
+                Err(err) => creation.failed.push((dir.clone(), err.to_string())),
+            }
+        }
+        for (path, size) in &self.files {
+            let created = OpenOptions::new().write(true).create_new(true).open(path)
+                .and_then(|file| if allocate { file.set_len(*size) } else { Ok(()) });
+            match created {
+                Ok(()) => {
+                    creation.files += 1;
+                    creation.size += if allocate { *size } else { 0 };
+                }
+                Err(err) => creation.failed.push((path.clone(), err.to_string())),
+            }
+        }
+        creation
+    }
+}
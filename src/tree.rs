@@ -0,0 +1,288 @@
+use crate::group::holds_only;
+use crate::kind::KindPath;
+use crate::output::color_enabled;
+use crate::scan::{Mismatch, Scan};
+use crate::{say, tags, Listing};
+use indicatif::BinaryBytes;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::{env, fmt, fs};
+use term_painter::Color::{Blue, Green, Red, Yellow};
+use term_painter::{Painted, ToStyle};
+
+/// The connectors `diff --tree` is drawn with.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Glyphs {
+    Unicode,
+    Ascii,
+}
+
+impl Glyphs {
+    /// Box-drawing characters, unless the locale says the terminal does not take UTF-8. The
+    /// Windows console always does.
+    pub fn detect() -> Self {
+        if cfg!(windows) {
+            return Glyphs::Unicode;
+        }
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"].into_iter()
+            .filter_map(|e| env::var(e).ok())
+            .find(|e| !e.is_empty())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match locale.contains("utf-8") || locale.contains("utf8") {
+            true => Glyphs::Unicode,
+            false => Glyphs::Ascii,
+        }
+    }
+
+    fn branch(self, last: bool) -> &'static str {
+        match (self, last) {
+            (Glyphs::Unicode, false) => "├── ",
+            (Glyphs::Unicode, true) => "└── ",
+            (Glyphs::Ascii, false) => "|-- ",
+            (Glyphs::Ascii, true) => "`-- ",
+        }
+    }
+
+    fn indent(self, last: bool) -> &'static str {
+        match (self, last) {
+            (Glyphs::Unicode, false) => "│   ",
+            (Glyphs::Ascii, false) => "|   ",
+            (_, true) => "    ",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Missing,
+    Extra,
+    EmptyDir,
+    Mismatched,
+}
+
+impl Change {
+    fn marker(self) -> Painted<&'static str> {
+        match self {
+            Change::Missing => Green.paint("+"),
+            Change::Extra => Red.paint("-"),
+            Change::EmptyDir => Blue.paint("-"),
+            Change::Mismatched => Yellow.paint("~"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    change: Option<Change>,
+    size: Option<u64>,
+    children: BTreeMap<OsString, Node>,
+}
+
+/// How many changes of each sort a directory holds, itself included; removals count extras and
+/// empty directories alike.
+#[derive(Default)]
+struct Counts {
+    missing: usize,
+    removed: usize,
+    empty_dirs: usize,
+    mismatched: usize,
+    size: u64,
+}
+
+impl Node {
+    fn insert(&mut self, rel: &Path, change: Change, size: Option<u64>) {
+        let node = rel.iter().fold(self, |node, part| node.children.entry(part.to_owned())
+            .or_default());
+        node.change = Some(change);
+        node.size = size;
+    }
+
+    fn count(&self, counts: &mut Counts) {
+        match self.change {
+            Some(Change::Missing) => counts.missing += 1,
+            Some(Change::Extra) => counts.removed += 1,
+            Some(Change::EmptyDir) => {
+                counts.removed += 1;
+                counts.empty_dirs += 1;
+            }
+            Some(Change::Mismatched) => counts.mismatched += 1,
+            None => {}
+        }
+        counts.size += self.size.unwrap_or(0);
+        for child in self.children.values() {
+            child.count(counts);
+        }
+    }
+}
+
+/// What a tree is drawn from, with the directories found to hold nothing but removals or files
+/// of another size.
+struct Tree<'a> {
+    listing: &'a Listing,
+    glyphs: Glyphs,
+    removed: HashSet<&'a PathBuf>,
+    mismatched: HashSet<&'a PathBuf>,
+    /// The torrent's size of each file of another size.
+    expected: HashMap<&'a PathBuf, u64>,
+    known: HashMap<PathBuf, bool>,
+    lines: Vec<Line>,
+}
+
+enum Name {
+    Dir(String),
+    Entry(PathBuf, String),
+}
+
+struct Line {
+    prefix: String,
+    change: Option<Change>,
+    name: Name,
+    /// Tags and counts after the name.
+    note: String,
+    size: Option<u64>,
+}
+
+impl Line {
+    /// Columns taken before the size, as printed.
+    fn width(&self, listing: &Listing) -> usize {
+        let name = match &self.name {
+            Name::Dir(name) => name.chars().count(),
+            Name::Entry(_, shown) if color_enabled() => shown.chars().count(),
+            // The type tag is printed instead of colors
+            Name::Entry(path, shown) => KindPath { path, shown: shown.clone(),
+                                                   highlight: &listing.highlight }
+                .to_string().chars().count(),
+        };
+        self.prefix.chars().count() + self.change.map_or(0, |_| 2) + name + self.note.chars().count()
+    }
+}
+
+struct Shown<'a>(&'a Name, &'a Listing);
+
+impl fmt::Display for Shown<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Name::Dir(name) => Blue.paint(name).fmt(f),
+            Name::Entry(path, shown) => KindPath { path, shown: shown.clone(),
+                                                   highlight: &self.1.highlight }.fmt(f),
+        }
+    }
+}
+
+impl Tree<'_> {
+    /// The marker and note of a directory holding two changes or more, all of one sort, and
+    /// nothing else, which is then shown as a single line.
+    fn collapsed(&mut self, node: &Node, path: &Path) -> Option<(Change, String, u64)> {
+        if node.children.is_empty() {
+            return None;
+        }
+        let mut counts = Counts::default();
+        node.count(&mut counts);
+        let sorts = [counts.missing, counts.removed, counts.mismatched];
+        if sorts.iter().filter(|e| **e > 0).count() != 1 || sorts.iter().sum::<usize>() < 2 {
+            return None;
+        }
+        let (change, note) = match counts {
+            Counts { missing: n @ 1.., .. } if fs::symlink_metadata(path).is_err() => {
+                (Change::Missing, format!("{} missing", n))
+            }
+            Counts { removed: n @ 1.., empty_dirs, .. }
+                if holds_only(path, &self.removed, &mut self.known) => match n == empty_dirs {
+                true => (Change::EmptyDir, format!("{} empty directories", n)),
+                false => (Change::Extra, format!("{} to remove", n)),
+            },
+            Counts { mismatched: n @ 1.., .. }
+                if holds_only(path, &self.mismatched, &mut self.known) => {
+                (Change::Mismatched, format!("{} of another size", n))
+            }
+            _ => return None,
+        };
+        Some((change, note, counts.size))
+    }
+
+    fn draw(&mut self, node: &Node, path: &Path, prefix: &str) {
+        let last = node.children.len().saturating_sub(1);
+        for (i, (name, child)) in node.children.iter().enumerate() {
+            let path = path.join(name);
+            let branch = format!("{}{}", prefix, self.glyphs.branch(i == last));
+            let name = name.to_string_lossy();
+            let dir = !child.children.is_empty() || path.is_dir() && !path.is_symlink();
+            let shown = match dir {
+                true => Name::Dir(format!("{}{}", name, MAIN_SEPARATOR)),
+                false => Name::Entry(path.clone(), name.to_string()),
+            };
+            if let Some((change, note, size)) = self.collapsed(child, &path) {
+                self.lines.push(Line { prefix: branch, change: Some(change), name: shown,
+                                       note: format!("  ({})", note), size: Some(size) });
+                continue;
+            }
+            let note = match child.change {
+                Some(Change::Extra | Change::EmptyDir) => tags(&path, self.listing),
+                Some(Change::Mismatched) => self.mismatched_note(&path),
+                _ => String::new(),
+            };
+            self.lines.push(Line { prefix: branch, change: child.change, name: shown, note,
+                                   size: child.size });
+            let indent = format!("{}{}", prefix, self.glyphs.indent(i == last));
+            self.draw(child, &path, &indent);
+        }
+    }
+
+    fn mismatched_note(&self, path: &Path) -> String {
+        match self.expected.get(&path.to_path_buf()) {
+            Some(size) => format!("  (expected {})", BinaryBytes(*size)),
+            None => String::new(),
+        }
+    }
+}
+
+/// Prints the missing files, extras, empty directories and files of another size as a tree
+/// under the scanned directory, with their sizes aligned on the right. A directory holding
+/// nothing but changes of one sort is folded into one line with their count.
+pub fn print_tree(scan: &Scan, new_files: &[PathBuf], mismatched: &[Mismatch],
+                  listing: &Listing, glyphs: Glyphs) {
+    let root = listing.paths.root();
+    let mut node = Node::default();
+    let mut add = |path: &Path, change, size| {
+        node.insert(path.strip_prefix(root).unwrap_or(path), change, size);
+    };
+    for entry in &scan.old_files {
+        add(entry, Change::Extra, Some(scan.stamps.get(entry).map_or(0, |e| e.size)));
+    }
+    for entry in scan.empty_dirs.iter().chain(&scan.emptied_dirs) {
+        add(entry, Change::EmptyDir, None);
+    }
+    for entry in new_files {
+        add(entry, Change::Missing, listing.missing_sizes.get(entry).copied());
+    }
+    for entry in mismatched {
+        add(&entry.path, Change::Mismatched, Some(entry.size));
+    }
+
+    let mut tree = Tree {
+        listing,
+        glyphs,
+        removed: scan.old_files.iter().chain(&scan.empty_dirs).chain(&scan.emptied_dirs).collect(),
+        mismatched: mismatched.iter().map(|e| &e.path).collect(),
+        expected: mismatched.iter().map(|e| (&e.path, e.expected)).collect(),
+        known: HashMap::new(),
+        lines: Vec::new(),
+    };
+    tree.draw(&node, root, "");
+    say!("{}", Blue.paint(root.display()));
+    let width = tree.lines.iter().map(|e| e.width(listing)).max().unwrap_or(0);
+    for line in &tree.lines {
+        let size = match line.size {
+            Some(size) => format!("{}  {:>10}", " ".repeat(width - line.width(listing)),
+                                  BinaryBytes(size).to_string()),
+            None => String::new(),
+        };
+        let name = Shown(&line.name, listing);
+        match line.change {
+            Some(change) => say!("{}{} {}{}{}", line.prefix, change.marker(), name, line.note, size),
+            None => say!("{}{}{}{}", line.prefix, name, line.note, size),
+        }
+    }
+}
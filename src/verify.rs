@@ -0,0 +1,226 @@
+use crate::rename::Pieces;
+use indicatif::ProgressBar;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+/// What `verify` found of a file of the torrent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileCheck {
+    /// Every piece it has a part in matches.
+    Intact,
+    /// A piece it has a part in does not match; one spanning several files counts for each.
+    Corrupt,
+    Missing,
+    /// On disk with another size, so its pieces are not read.
+    SizeDiffers(u64),
+    /// Reading it failed, with the error.
+    Unreadable(String),
+    /// All its pieces also hold part of a file that is missing, differs in size or could not be
+    /// read, so none could be hashed.
+    Unchecked,
+}
+
+/// How the pieces a file has a part in fared.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PieceCounts {
+    pub verified: u64,
+    pub failed: u64,
+    /// Not hashed, as a part of them is missing, of another size or unreadable.
+    pub unverifiable: u64,
+}
+
+/// A file of the torrent, read where the directory has it.
+pub struct VerifiedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub check: FileCheck,
+    pub pieces: PieceCounts,
+    /// The other files that kept pieces of it from being hashed, with what is wrong with them.
+    pub blockers: Vec<(PathBuf, FileCheck)>,
+}
+
+/// The outcome of hashing the files of a torrent against its pieces.
+pub struct Verification {
+    /// In the order of the torrent, padding files and those not selected left out.
+    pub files: Vec<VerifiedFile>,
+    /// Those hashed or meant to be: every piece, or those of the selected files.
+    pub pieces: u64,
+    pub bad_pieces: u64,
+    /// Pieces that could not be hashed, for a part of them is missing or unreadable.
+    pub unchecked_pieces: u64,
+    /// The bytes of the pieces that match, out of `total`.
+    pub verified: u64,
+    pub total: u64,
+}
+
+/// How far a file of the torrent got while its pieces were hashed.
+struct State {
+    /// Why its pieces cannot be read: missing, of another size or unreadable.
+    check: Option<FileCheck>,
+    pieces: PieceCounts,
+    /// The files that kept its pieces from being hashed, by their index.
+    blockers: Vec<usize>,
+}
+
+/// Which pieces hold part of the files at `only`, indices into [`Pieces::files`], or all of them.
+fn wanted(pieces: &Pieces, only: Option<&HashSet<usize>>) -> Vec<bool> {
+    let Some(only) = only else {
+        return vec![true; pieces.count() as usize];
+    };
+    let mut wanted = vec![false; pieces.count() as usize];
+    for file in only.iter().filter_map(|&i| pieces.files().get(i)).filter(|e| e.length > 0) {
+        let first = file.offset / pieces.piece_length();
+        let last = (file.offset + file.length - 1) / pieces.piece_length();
+        wanted[first as usize..=last as usize].iter_mut().for_each(|e| *e = true);
+    }
+    wanted
+}
+
+/// The bytes [`verify`] reads for `only`, those of the pieces it hashes.
+pub fn bytes_to_hash(pieces: &Pieces, only: Option<&HashSet<usize>>) -> u64 {
+    wanted(pieces, only).iter().enumerate().filter(|(_, wanted)| **wanted)
+        .map(|(piece, _)| {
+            let from = piece as u64 * pieces.piece_length();
+            (from + pieces.piece_length()).min(pieces.total()) - from
+        })
+        .sum()
+}
+
+/// Hashes the files of the torrent under `dir` piece by piece in the torrent's order, advancing
+/// `progress` by the bytes of each piece; see [`FileCheck`]. Padding files are taken for zeros.
+/// With `only`, indices into [`Pieces::files`], just the pieces those files have a part in are
+/// hashed, reading what the files around them hold of the pieces at their ends.
+pub fn verify(dir: &Path, pieces: &Pieces, only: Option<&HashSet<usize>>, progress: &ProgressBar)
+              -> Verification {
+    let files = pieces.files();
+    let wanted = wanted(pieces, only);
+    let disk = files.iter()
+        .map(|e| (!e.padding).then(|| dir.join(&e.path)))
+        .collect::<Vec<Option<PathBuf>>>();
+    let mut states = files.iter().zip(&disk).map(|(file, disk)| {
+        // A path that could lead outside of the directory is never read
+        let outside = file.path.components().any(|e| !matches!(e, Component::Normal(_)));
+        let check = match disk.as_ref().map(fs::metadata) {
+            None => None,
+            Some(_) if outside => Some(FileCheck::Missing),
+            Some(Err(_)) => Some(FileCheck::Missing),
+            Some(Ok(meta)) if meta.len() != file.length => Some(FileCheck::SizeDiffers(meta.len())),
+            Some(Ok(_)) => None,
+        };
+        State { check, pieces: PieceCounts::default(), blockers: Vec::new() }
+    }).collect::<Vec<State>>();
+
+    let mut verification = Verification { files: Vec::new(),
+                                          pieces: wanted.iter().filter(|e| **e).count() as u64,
+                                          bad_pieces: 0, unchecked_pieces: 0, verified: 0,
+                                          total: bytes_to_hash(pieces, only) };
+    let mut open: Option<(usize, File)> = None;
+    let mut buf = Vec::with_capacity(pieces.piece_length() as usize);
+    // The first file that may still have a part in the next piece
+    let mut first = 0;
+    for piece in (0..pieces.count()).filter(|e| wanted[*e as usize]) {
+        let from = piece * pieces.piece_length();
+        let to = (from + pieces.piece_length()).min(pieces.total());
+        progress.inc(to - from);
+        while first < files.len() && files[first].offset + files[first].length <= from {
+            first += 1;
+        }
+        let spanned = (first..files.len())
+            .take_while(|&i| files[i].offset < to)
+            .filter(|&i| files[i].length > 0)
+            .collect::<Vec<usize>>();
+        buf.clear();
+        let blocked = spanned.iter().any(|&i| states[i].check.is_some());
+        let mut readable = !blocked;
+        for &i in spanned.iter().filter(|_| !blocked) {
+            let file = &files[i];
+            let start = from.max(file.offset);
+            let end = to.min(file.offset + file.length);
+            let len = (end - start) as usize;
+            let Some(path) = &disk[i] else {
+                buf.resize(buf.len() + len, 0);
+                continue;
+            };
+            if open.as_ref().is_none_or(|(j, _)| *j != i) {
+                match File::open(path) {
+                    Ok(handle) => open = Some((i, handle)),
+                    Err(err) => {
+                        states[i].check = Some(FileCheck::Unreadable(err.to_string()));
+                        readable = false;
+                        break;
+                    }
+                }
+            }
+            let (_, handle) = open.as_mut().expect("opened above");
+            let at = buf.len();
+            buf.resize(at + len, 0);
+            let read = handle.seek(SeekFrom::Start(start - file.offset))
+                .and_then(|_| handle.read_exact(&mut buf[at..]));
+            if let Err(err) = read {
+                states[i].check = Some(FileCheck::Unreadable(err.to_string()));
+                readable = false;
+                break;
+            }
+        }
+        if !readable {
+            verification.unchecked_pieces += 1;
+            let blockers = spanned.iter().copied().filter(|&i| states[i].check.is_some())
+                .collect::<Vec<usize>>();
+            for &i in &spanned {
+                states[i].pieces.unverifiable += 1;
+                for &blocker in blockers.iter().filter(|&&e| e != i) {
+                    if !states[i].blockers.contains(&blocker) {
+                        states[i].blockers.push(blocker);
+                    }
+                }
+            }
+            continue;
+        }
+        let matches = pieces.hash(piece)
+            .is_some_and(|hash| crypto_hash::digest(crypto_hash::Algorithm::SHA1, &buf) == hash);
+        for &i in &spanned {
+            match matches {
+                true => states[i].pieces.verified += 1,
+                false => states[i].pieces.failed += 1,
+            }
+        }
+        match matches {
+            true => verification.verified += to - from,
+            false => verification.bad_pieces += 1,
+        }
+    }
+
+    let blocker = |i: usize| Some((disk[i].clone()?, states[i].check.clone()?));
+    verification.files = files.iter().zip(&disk).zip(&states).enumerate()
+        .filter(|(i, _)| only.is_none_or(|e| e.contains(i)))
+        .filter_map(|(_, ((file, disk), state))| Some(VerifiedFile {
+            path: disk.clone()?,
+            size: file.length,
+            check: match &state.check {
+                Some(check) => check.clone(),
+                None if state.pieces.failed > 0 => FileCheck::Corrupt,
+                None if state.pieces.verified > 0 || file.length == 0 => FileCheck::Intact,
+                None => FileCheck::Unchecked,
+            },
+            pieces: state.pieces,
+            blockers: state.blockers.iter().filter_map(|&i| blocker(i)).collect(),
+        }))
+        .collect();
+    verification
+}
+
+impl Verification {
+    pub fn with(&self, check: impl Fn(&FileCheck) -> bool) -> Vec<&VerifiedFile> {
+        self.files.iter().filter(|e| check(&e.check)).collect()
+    }
+
+    /// The share of the payload in pieces that match, as a percentage.
+    pub fn percent(&self) -> f64 {
+        match self.total {
+            0 => 100.0,
+            total => self.verified as f64 * 100.0 / total as f64,
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::say;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use term_painter::Color::Red;
+use term_painter::ToStyle;
+
+/// Exit code of a run that went through but warned, with `--deny-warnings`.
+pub const EXIT_WARNED: u8 = 3;
+
+/// What a warning is about, for scripts to tell warnings apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningCode {
+    /// The invocation is deprecated.
+    Deprecated,
+    /// `--background` could not lower the priority of the run.
+    Priority,
+    /// The torrent parses, but something in it is off.
+    TorrentMetadata,
+    /// The piece length or count is suspicious; see `--strict-torrent`.
+    Geometry,
+    /// A file of the torrent has a path that could lead outside of the directory, and is left out.
+    UnsafePath,
+    /// The directory looks like a mount point with nothing mounted.
+    Unmounted,
+    /// The torrent looks like it is still downloading into the directory.
+    Active,
+    /// The directory holds top-level directories outside the torrent.
+    SiblingTrees,
+    /// An entry `--skip-errors` went past.
+    Unreadable,
+    /// Paths of the torrent that cannot all exist on disk as spelled, or extras left alone for
+    /// possibly being one of them.
+    Colliding,
+    /// The modification time of an extra cannot be read, with `--older-than` or `--newer-than`.
+    UnknownAge,
+    /// Combined torrents declare a file with different sizes.
+    SizeConflict,
+    /// A torrent of `--torrents-dir` could not be loaded.
+    TorrentSkipped,
+    /// Files of the torrent that are the same file on disk once folded.
+    FoldedAlike,
+    /// A `--filter-hook` is slow to run over so many files, or timed out on one.
+    Hook,
+    /// The run history could not be written.
+    History,
+    /// The report could not be written again once done.
+    Report,
+    /// A file of a v2 torrent lacks a valid piece layer, or `--piece-layers` does not apply.
+    PieceLayers,
+    /// A Transmission `.resume` file of `--transmission-config` is missing, unreadable or does
+    /// not fit its torrent.
+    Resume,
+    /// The `--client` could not be reached, or did not do what it was asked.
+    Client,
+    /// The journal of a clean could not be created, written or deleted.
+    Journal,
+    /// A `--map` names a top-level entry the torrent does not have.
+    Map,
+    /// A probable rename could not be moved back by `--fix-renames`, or `--verify-renames` has
+    /// no piece hashes to check it with.
+    Rename,
+    /// A probable rename could not be linked, moved or copied into the torrent by `--relink`.
+    Relink,
+    /// A rescan of `--watch` failed; the previous result is kept.
+    Watch,
+}
+
+/// A warning of the run, printed as it happens and kept for the outputs of the run.
+#[derive(Clone, Debug, Serialize)]
+pub struct Warning {
+    pub code: WarningCode,
+    pub message: String,
+    /// The entry it is about, if it is about one.
+    pub path: Option<String>,
+}
+
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+static DENY: AtomicBool = AtomicBool::new(false);
+
+impl Warning {
+    pub fn new(code: WarningCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), path: None }
+    }
+
+    pub fn at(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_string_lossy().into_owned());
+        self
+    }
+
+    /// Keeps the warning for the outputs of the run, and returns it to be printed.
+    pub fn record(self) -> Self {
+        WARNINGS.lock().expect("warnings lock").push(self.clone());
+        self
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", Red.paint("Warning:"), self.message)
+    }
+}
+
+/// Records a warning not about a particular entry; see [`Warning::record`].
+pub fn warn(code: WarningCode, message: impl Into<String>) -> Warning {
+    Warning::new(code, message).record()
+}
+
+/// The warnings of the run so far, in the order they were raised.
+pub fn warnings() -> Vec<Warning> {
+    WARNINGS.lock().expect("warnings lock").clone()
+}
+
+/// Makes warnings fail the run, for `--deny-warnings`.
+pub fn set_deny(deny: bool) {
+    DENY.store(deny, Ordering::Relaxed);
+}
+
+/// Whether the run warned and `--deny-warnings` has that fail it.
+pub fn denied() -> bool {
+    DENY.load(Ordering::Relaxed) && !WARNINGS.lock().expect("warnings lock").is_empty()
+}
+
+/// Ends the run with how many warnings of each kind it printed, since those found early may
+/// have scrolled out of view.
+pub fn print_warnings() {
+    let warnings = warnings();
+    if warnings.is_empty() {
+        return;
+    }
+    let mut counts = BTreeMap::<WarningCode, usize>::new();
+    for warning in &warnings {
+        *counts.entry(warning.code).or_default() += 1;
+    }
+    say!();
+    say!("Warnings ({}):", warnings.len());
+    for (code, count) in counts {
+        let name = serde_json::to_value(code).expect("serializable");
+        say!("    {:<20} {}", name.as_str().unwrap_or_default(), count);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::interrupt::{interrupted, start_watching};
+use crate::warnings::{warn, WarningCode};
+use crate::{limited, path_colored, print_more, say, scan_options, Listing};
+use clap::ArgMatches;
+use indicatif::BinaryBytes;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use term_painter::Color::{Blue, Green, Red, Yellow};
+use term_painter::ToStyle;
+use torrent_cleaner::scan::{scan_dir, ExpectedSet, Scan};
+
+/// One comparison of `diff --watch`: the scan, and the torrent's files missing with their size.
+pub struct Watched {
+    pub scan: Scan,
+    pub missing: Vec<PathBuf>,
+    pub missing_size: u64,
+    mismatched: usize,
+}
+
+impl Watched {
+    pub fn new(scan: Scan, missing: Vec<PathBuf>, missing_size: u64, mismatched: usize) -> Self {
+        Self { scan, missing, missing_size, mismatched }
+    }
+
+    fn removals(&self) -> HashSet<&PathBuf> {
+        self.scan.old_files.iter().chain(&self.scan.empty_dirs).chain(&self.scan.emptied_dirs)
+            .collect()
+    }
+
+    fn print_counts(&self) {
+        say!("[{}] {} entries to remove ({}), {} files missing ({}), {} of another size",
+             humantime::format_rfc3339_seconds(SystemTime::now()), self.scan.removal_count(),
+             BinaryBytes(self.scan.rm_size), self.missing.len(), BinaryBytes(self.missing_size),
+             self.mismatched);
+    }
+}
+
+/// How an entry changed between two scans of `diff --watch`.
+#[derive(Clone, Copy)]
+enum Change {
+    /// A missing file of the torrent that is there now.
+    Arrived,
+    /// A file of the torrent that went missing.
+    Missing,
+    /// A new entry to remove.
+    Extra,
+    /// An entry to remove that no longer is.
+    Gone,
+}
+
+impl Change {
+    fn print(self, path: &Path, listing: &Listing) {
+        let (color, label) = match self {
+            Change::Arrived => (Green, "arrived"),
+            Change::Missing => (Yellow, "missing"),
+            Change::Extra => (Red, "extra"),
+            Change::Gone => (Blue, "gone"),
+        };
+        say!("{}  {}", color.paint(format!("{:<7}", label)), path_colored(path, listing));
+    }
+}
+
+/// Waits `interval`, or less if Ctrl-C is pressed; returns whether it was.
+fn wait(interval: Duration) -> bool {
+    let start = Instant::now();
+    while !interrupted() && start.elapsed() < interval {
+        thread::sleep(Duration::from_millis(100).min(interval));
+    }
+    interrupted()
+}
+
+/// Scans `dir` again every `interval` until Ctrl-C, printing the counts and the entries that
+/// changed whenever something did, and returns the last comparison. Polling stands in for file
+/// system notifications, so each round rescans the whole directory; one that fails is warned
+/// about and the previous result kept.
+pub fn watch(matches: &ArgMatches, dir: &Path, expected: &impl ExpectedSet, listing: &Listing,
+             interval: Duration, mut last: Watched) -> anyhow::Result<Watched> {
+    let options = scan_options(matches)?;
+    start_watching();
+    say!();
+    say!("Watching for changes every {}; press Ctrl-C to stop.",
+         humantime::format_duration(interval));
+    last.print_counts();
+    while !wait(interval) {
+        let scan = match scan_dir(dir, expected, &options, &()) {
+            Ok(scan) => scan,
+            Err(err) => {
+                eprintln!("{}", warn(WarningCode::Watch, format!(
+                    "scan failed, keeping the previous result: {:#}", err)));
+                continue;
+            }
+        };
+        let (missing, missing_size) = expected.missing(dir);
+        let now = Watched::new(scan, missing, missing_size, expected.mismatched(dir).len());
+
+        let (was_missing, is_missing) = (last.missing.iter().collect::<HashSet<&PathBuf>>(),
+                                         now.missing.iter().collect::<HashSet<&PathBuf>>());
+        let (removals, was_removal) = (now.removals(), last.removals());
+        let mut changes = Vec::new();
+        changes.extend(was_missing.difference(&is_missing).map(|e| (Change::Arrived, *e)));
+        changes.extend(is_missing.difference(&was_missing).map(|e| (Change::Missing, *e)));
+        changes.extend(removals.difference(&was_removal).map(|e| (Change::Extra, *e)));
+        changes.extend(was_removal.difference(&removals).map(|e| (Change::Gone, *e)));
+        changes.sort_by(|a, b| a.1.cmp(b.1));
+        let unchanged = changes.is_empty() && now.scan.rm_size == last.scan.rm_size
+            && now.missing_size == last.missing_size && now.mismatched == last.mismatched;
+        if !unchanged {
+            say!();
+            now.print_counts();
+            for (change, path) in limited(&changes, listing) {
+                change.print(path, listing);
+            }
+            print_more(changes.len(), listing);
+        }
+        last = now;
+    }
+    say!();
+    say!("Stopped watching.");
+    last.print_counts();
+    Ok(last)
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::{stderr, stdout, Bencode, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 3] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789"),
+                                   ("sub/c.bin", b"abc")];
+
+/// What the run printed to stdout and stderr, where the refusal lands.
+fn printed(output: &std::process::Output) -> String {
+    stdout(output) + &stderr(output)
+}
+
+#[test]
+fn single_file_torrent_names_its_file() {
+    let fixture = Fixture::new(&FILES);
+    let info = Bencode::dict([
+        ("name", Bencode::str("movie.mkv")),
+        ("piece length", Bencode::Int(common::PIECE_LENGTH)),
+        ("pieces", Bencode::Bytes(vec![0; 20])),
+        ("length", Bencode::Int(5)),
+    ]);
+    fs::write(fixture.path("movie.torrent"), Bencode::dict([("info", info)]).encode())
+        .expect("torrent written");
+    fs::create_dir(fixture.path("Downloads")).expect("directory created");
+    fs::write(fixture.path("Downloads/movie.mkv"), b"movie").expect("file written");
+
+    let output = fixture.run(&["clean", "-f", "movie.torrent", "Downloads"]);
+    assert!(!output.status.success());
+    let printed = printed(&output);
+    assert!(printed.contains("its file was modified in the last 10m"), "{}", printed);
+    assert!(!printed.contains("of its"), "{}", printed);
+}
+
+#[test]
+fn multi_file_torrent_counts_its_files_and_partial_downloads() {
+    let fixture = Fixture::new(&FILES);
+    fs::write(fixture.content().join("a.txt"), b"hello").expect("file written");
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(printed(&output).contains("1 of its 3 files was modified in the last 10m"),
+            "{}", printed(&output));
+
+    fs::write(fixture.content().join("sub/c.bin.part"), b"a").expect("partial written");
+    fixture.write("sub/b.bin.!qB", b"0123");
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(printed(&output).contains("1 of its 3 files and a partial download were modified in \
+                                       the last 10m, and a partial download is there"),
+            "{}", printed(&output));
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{age, stderr, stdout, Fixture};
+use serde_json::Value;
+use std::fs;
+use std::time::Duration;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+fn manifest(fixture: &Fixture, lines: &[&str]) {
+    fs::write(fixture.path("batch.tsv"), lines.join("\n")).unwrap();
+}
+
+#[test]
+fn batch_clean_refuses_a_torrent_still_downloading() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    age(&fixture.content().join("a.txt"), Duration::ZERO);
+    manifest(&fixture, &["show.torrent\tShow"]);
+
+    let output = fixture.run(&["batch", "--clean", "-f", "batch.tsv"]);
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("still downloading"), "{}", stdout(&output));
+    assert!(extra.exists());
+
+    let output = fixture.run(&["batch", "--clean", "-f", "--force", "batch.tsv"]);
+    assert!(output.status.success(), "{}{}", stdout(&output), stderr(&output));
+    assert!(!extra.exists());
+}
+
+#[test]
+fn batch_diff_warns_of_a_torrent_still_downloading() {
+    let fixture = Fixture::new(&FILES);
+    age(&fixture.content().join("a.txt"), Duration::ZERO);
+    manifest(&fixture, &["show.torrent\tShow"]);
+
+    let output = fixture.run(&["batch", "batch.tsv"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("looks like it is still downloading"), "{}", stdout(&output));
+}
+
+#[test]
+fn batch_json_reports_each_entry_status() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    fs::write(fixture.path("broken.torrent"), b"not bencode").unwrap();
+    manifest(&fixture, &["# comment", "broken.torrent\tShow", "show.torrent\tShow"]);
+
+    let output = fixture.run(&["batch", "--output", "json", "batch.tsv"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["status"], "failed");
+    let statuses = report["entries"].as_array().unwrap().iter()
+        .map(|e| e["status"].as_str().unwrap()).collect::<Vec<&str>>();
+    assert_eq!(statuses, ["failed", "ok"]);
+    assert_eq!(report["entries"][1]["extras"], 1);
+}
+
+#[test]
+fn batch_on_error_abort_skips_the_rest() {
+    let fixture = Fixture::new(&FILES);
+    fs::write(fixture.path("broken.torrent"), b"not bencode").unwrap();
+    manifest(&fixture, &["broken.torrent\tShow", "show.torrent\tShow"]);
+
+    let output = fixture.run(&["batch", "--on-error", "abort", "--output", "json", "batch.tsv"]);
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let statuses = report["entries"].as_array().unwrap().iter()
+        .map(|e| e["status"].as_str().unwrap()).collect::<Vec<&str>>();
+    assert_eq!(statuses, ["failed", "skipped"]);
+}
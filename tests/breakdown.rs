@@ -0,0 +1,86 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The lines of the table titled `title` in `stdout`, whose sizes are aligned right.
+fn table<'a>(stdout: &'a str, title: &str) -> Vec<&'a str> {
+    stdout.lines().skip_while(|e| !e.starts_with(title)).skip(1)
+        .take_while(|e| e.starts_with(' '))
+        .map(str::trim)
+        .collect()
+}
+
+#[test]
+fn summary_prints_nothing_more_without_it() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["diff", "--surface", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(!stdout.contains("directories by extras"), "{}", stdout);
+    assert!(!stdout.contains("Extras by extension"), "{}", stdout);
+}
+
+#[test]
+fn extensions_are_read_from_the_last_dot() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("notes.tar.gz", &[0; 300]);
+    fixture.write("sub/old.GZ", &[0; 200]);
+    fixture.write(".hidden", &[0; 100]);
+    fixture.write("sub/.hidden", &[0; 50]);
+    fixture.write("README", &[0; 20]);
+    fixture.write("sub/Makefile", &[0; 10]);
+    let output = fixture.run(&["diff", "--summary", "--surface", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    // A multi-dot name counts as its last extension, lowercased; a dotfile is its own
+    // extension; a name without a dot has none
+    assert_eq!(table(&stdout, "Extras by extension:"), [
+        "500 B  (2)  .gz",
+        "150 B  (2)  .hidden",
+        "30 B  (2)  (no extension)",
+    ], "{}", stdout);
+}
+
+#[test]
+fn directories_count_everything_beneath_them() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("top.nfo", &[0; 1000]);
+    fixture.write("sub/a.nfo", &[0; 100]);
+    fixture.write("sub/deep/b.nfo", &[0; 200]);
+    fixture.write("sub/deep/c.nfo", &[0; 300]);
+    fixture.write("other/d.nfo", &[0; 50]);
+    let output = fixture.run(&["diff", "--summary", "--surface", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    // The scanned directory itself is not listed, and files at its top count in none
+    let dirs = table(&stdout, "Top 3 directories by extras:");
+    assert_eq!(dirs.len(), 3, "{}", stdout);
+    assert!(dirs[0].starts_with("600 B  (3)") && dirs[0].ends_with("sub"), "{}", stdout);
+    assert!(dirs[1].starts_with("500 B  (2)") && dirs[1].ends_with("deep"), "{}", stdout);
+    assert!(dirs[2].starts_with("50 B  (1)") && dirs[2].ends_with("other"), "{}", stdout);
+}
+
+#[test]
+fn top_limits_the_tables_and_adds_up_the_other_extensions() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("a.nfo", &[0; 400]);
+    fixture.write("b.srt", &[0; 300]);
+    fixture.write("c/d.jpg", &[0; 200]);
+    fixture.write("e/f.png", &[0; 100]);
+    let output = fixture.run(&["diff", "--summary", "--top", "2", "--surface",
+                               "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert_eq!(table(&stdout, "Extras by extension:"), [
+        "400 B  (1)  .nfo",
+        "300 B  (1)  .srt",
+        "300 B  (2)  2 more extensions",
+    ], "{}", stdout);
+    // Directories nest, so those left out are not added up
+    let dirs = table(&stdout, "Top 2 directories by extras:");
+    assert_eq!(dirs.len(), 2, "{}", stdout);
+    assert!(dirs[0].ends_with('c') && dirs[1].ends_with('e'), "{}", stdout);
+}
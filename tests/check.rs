@@ -0,0 +1,66 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+fn check(fixture: &Fixture) -> std::process::Output {
+    fixture.run(&["diff", "--check", "--quiet", "show.torrent", "Show"])
+}
+
+#[test]
+fn complete_directory_exits_0_silently() {
+    let fixture = Fixture::new(&FILES);
+    let output = check(&fixture);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn extras_exit_8_and_are_listed() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let output = check(&fixture);
+    assert_eq!(output.status.code(), Some(8), "{}", stderr(&output));
+    assert!(stdout(&output).contains("extra.nfo"), "{}", stdout(&output));
+}
+
+#[test]
+fn only_out_of_scope_entries_exit_9() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("extra.nfo", b"x");
+    let output = check(&fixture);
+    assert_eq!(output.status.code(), Some(9), "{}", stderr(&output));
+}
+
+#[test]
+fn missing_files_exit_10() {
+    let fixture = Fixture::new(&FILES);
+    fs::remove_file(fixture.content().join("a.txt")).expect("file removed");
+    let output = check(&fixture);
+    assert_eq!(output.status.code(), Some(10), "{}", stderr(&output));
+}
+
+#[test]
+fn extras_outrank_missing_files() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    fs::remove_file(fixture.content().join("a.txt")).expect("file removed");
+    assert_eq!(check(&fixture).status.code(), Some(8));
+}
+
+#[test]
+fn failure_exits_2() {
+    let fixture = Fixture::new(&FILES);
+    let output = fixture.run(&["diff", "--check", "--quiet", "show.torrent", "Elsewhere"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+}
+
+#[test]
+fn without_check_differences_exit_0() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::{stderr, stdout, Answer, Fixture, MockServer, Request};
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// qBittorrent listing the torrent and taking any recheck, without a login.
+fn qbittorrent(request: &Request) -> Answer {
+    match request.target.split('?').next().unwrap_or_default() {
+        "/api/v2/torrents/info" => (200, Vec::new(), r#"[{"name":"Show"}]"#.to_string()),
+        "/api/v2/torrents/recheck" => (200, Vec::new(), String::new()),
+        _ => (404, Vec::new(), String::new()),
+    }
+}
+
+#[test]
+fn recheck_after_needs_a_client() {
+    let fixture = Fixture::new(&FILES);
+    let output = fixture.run(&["clean", "-f", "--recheck-after", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("--recheck-after needs the --client"), "{}", stderr(&output));
+}
+
+#[test]
+fn recheck_after_asks_qbittorrent() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let server = MockServer::start(qbittorrent);
+    let output = fixture.run(&["clean", "-f", "--client", "qbittorrent", "--client-url", &server.url,
+                               "--recheck-after", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fixture.content().join("sub/extra.nfo").exists(), "{}", stdout(&output));
+    assert!(stdout(&output).contains("Recheck of Show requested from qBittorrent: HTTP 200"),
+            "{}", stdout(&output));
+    let requests = server.requests();
+    let recheck = requests.iter().find(|e| e.target == "/api/v2/torrents/recheck")
+        .expect("recheck requested");
+    assert_eq!(recheck.method, "POST");
+    assert!(recheck.body.starts_with("hashes="), "{}", recheck.body);
+}
+
+#[test]
+fn declined_clean_does_not_recheck() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let server = MockServer::start(qbittorrent);
+    let output = fixture.run(&["clean", "--assume-no", "--client", "qbittorrent", "--client-url",
+                               &server.url, "--recheck-after", "show.torrent", "Show"]);
+    assert!(fixture.content().join("sub/extra.nfo").exists(), "{}", stderr(&output));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn recheck_after_takes_the_transmission_session_id() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let server = MockServer::start(|request| match request.header("x-transmission-session-id") {
+        Some("abc") if request.body.contains("torrent-get") => (200, Vec::new(),
+            r#"{"result":"success","arguments":{"torrents":[{"name":"Show"}]}}"#.to_string()),
+        Some("abc") => (200, Vec::new(), r#"{"result":"success","arguments":{}}"#.to_string()),
+        _ => (409, vec![("X-Transmission-Session-Id", "abc".to_string())], String::new()),
+    });
+    let output = fixture.run(&["clean", "-f", "--client", "transmission", "--client-url",
+                               &server.url, "--recheck-after", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("requested from Transmission: result: success"),
+            "{}", stdout(&output));
+    assert!(server.requests().iter().any(|e| e.body.contains("\"torrent-verify\"")));
+}
+
+#[test]
+fn unknown_torrent_only_warns() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let server = MockServer::start(|_| (200, Vec::new(), "[]".to_string()));
+    let output = fixture.run(&["clean", "-f", "--client", "qbittorrent", "--client-url", &server.url,
+                               "--recheck-after", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("has no torrent with info hash"), "{}", stderr(&output));
+    assert!(!server.requests().iter().any(|e| e.target == "/api/v2/torrents/recheck"));
+}
+
+#[test]
+fn recheck_after_logs_in_to_deluge() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let server = MockServer::start(|request| {
+        let reply = |result: &str| format!(r#"{{"result":{},"error":null,"id":1}}"#, result);
+        match () {
+            _ if request.body.contains("auth.login") => (200,
+                vec![("Set-Cookie", "_session_id=s1; Path=/json".to_string())], reply("true")),
+            _ if request.header("cookie") != Some("_session_id=s1") => (200, Vec::new(),
+                r#"{"result":null,"error":{"message":"Not authenticated","code":1},"id":1}"#
+                    .to_string()),
+            _ if request.body.contains("core.get_torrent_status") => {
+                (200, Vec::new(), reply(r#"{"name":"Show"}"#))
+            }
+            _ => (200, Vec::new(), reply("null")),
+        }
+    });
+    let output = fixture.run(&["clean", "-f", "--client", "deluge", "--client-url", &server.url,
+                               "--client-password", "secret", "--recheck-after", "show.torrent",
+                               "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("requested from Deluge: result: null"), "{}",
+            stdout(&output));
+    let requests = server.requests();
+    assert!(requests[0].body.contains(r#"["secret"]"#), "{}", requests[0].body);
+    assert!(requests.iter().any(|e| e.body.contains("core.force_recheck")));
+}
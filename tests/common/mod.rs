@@ -0,0 +1,275 @@
+//! Runs the binary against a torrent and its content written to a temporary directory.
+
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+/// A bencoded value, enough of the format to write metainfo.
+pub enum Bencode {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    /// Keyed by raw bytes, as the keys of `piece layers` are hashes.
+    Dict(BTreeMap<Vec<u8>, Bencode>),
+}
+
+impl Bencode {
+    pub fn str(text: &str) -> Self {
+        Self::Bytes(text.as_bytes().to_vec())
+    }
+
+    pub fn dict<'a>(entries: impl IntoIterator<Item = (&'a str, Bencode)>) -> Self {
+        Self::Dict(entries.into_iter().map(|(key, value)| (key.as_bytes().to_vec(), value))
+            .collect())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Int(int) => out.extend(format!("i{}e", int).bytes()),
+            Self::Bytes(bytes) => {
+                out.extend(format!("{}:", bytes.len()).bytes());
+                out.extend(bytes);
+            }
+            Self::List(list) => {
+                out.push(b'l');
+                list.iter().for_each(|e| e.write(out));
+                out.push(b'e');
+            }
+            // BTreeMap iterates its keys sorted, as bencode requires
+            Self::Dict(dict) => {
+                out.push(b'd');
+                for (key, value) in dict {
+                    Self::Bytes(key.clone()).write(out);
+                    value.write(out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+}
+
+/// The piece length of the torrents written, larger than any content of the tests.
+pub const PIECE_LENGTH: i64 = 16384;
+
+/// Metainfo of a multi-file torrent `name` of `files`, by `/`-separated path. The pieces are
+/// placeholders, so the torrent is fit for anything but verifying.
+pub fn torrent(name: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+    let total = files.iter().map(|e| e.1.len() as i64).sum::<i64>();
+    let pieces = (total + PIECE_LENGTH - 1) / PIECE_LENGTH;
+    metainfo(name, files, PIECE_LENGTH, pieces.max(1) as usize * 20)
+}
+
+/// [`torrent`] with any `piece_length` and `hashes` bytes of placeholder piece hashes, sound or
+/// not.
+pub fn metainfo(name: &str, files: &[(&str, &[u8])], piece_length: i64, hashes: usize) -> Vec<u8> {
+    let info = Bencode::dict([
+        ("name", Bencode::str(name)),
+        ("piece length", Bencode::Int(piece_length)),
+        ("pieces", Bencode::Bytes(vec![0; hashes])),
+        ("files", v1_files(files)),
+    ]);
+    Bencode::dict([("info", info)]).encode()
+}
+
+/// The v1 `files` list of `files`, by `/`-separated path.
+pub fn v1_files(files: &[(&str, &[u8])]) -> Bencode {
+    Bencode::List(files.iter().map(|(path, content)| Bencode::dict([
+        ("length", Bencode::Int(content.len() as i64)),
+        ("path", Bencode::List(path.split('/').map(Bencode::str).collect())),
+    ])).collect())
+}
+
+/// Metainfo of a BEP 52 torrent `name` with the `file tree` of `files`, and with the v1 list
+/// of `v1` as well for a hybrid torrent. The pieces roots are placeholders, and the v1 list has
+/// no padding files.
+pub fn torrent_v2(name: &str, files: &[(&str, &[u8])], v1: Option<&[(&str, &[u8])]>) -> Vec<u8> {
+    let mut tree = BTreeMap::new();
+    for (path, content) in files {
+        let mut node = &mut tree;
+        for part in path.split('/') {
+            let Bencode::Dict(children) = node.entry(part.as_bytes().to_vec())
+                .or_insert_with(|| Bencode::Dict(BTreeMap::new())) else { unreachable!() };
+            node = children;
+        }
+        let mut file = vec![("length", Bencode::Int(content.len() as i64))];
+        if !content.is_empty() {
+            file.push(("pieces root", Bencode::Bytes(vec![1; 32])));
+        }
+        node.insert(Vec::new(), Bencode::dict(file));
+    }
+    let mut info = vec![
+        ("name", Bencode::str(name)),
+        ("piece length", Bencode::Int(PIECE_LENGTH)),
+        ("meta version", Bencode::Int(2)),
+        ("file tree", Bencode::Dict(tree)),
+    ];
+    if let Some(v1) = v1 {
+        info.push(("files", v1_files(v1)));
+        info.push(("pieces", Bencode::Bytes(vec![0; 20 * v1.len().max(1)])));
+    }
+    Bencode::dict([("info", Bencode::dict(info))]).encode()
+}
+
+/// A temporary directory with `show.torrent`, its content under `Show/`, and the config, cache
+/// and data directories of the runs, so that they leave the user's own alone.
+pub struct Fixture {
+    pub root: TempDir,
+}
+
+impl Fixture {
+    /// The torrent `Show` of `files`, all of them present and last modified a day ago, so that
+    /// it does not look like it is still downloading.
+    pub fn new(files: &[(&str, &[u8])]) -> Self {
+        let fixture = Self { root: TempDir::new().expect("temporary directory") };
+        fs::write(fixture.torrent(), torrent("Show", files)).expect("torrent written");
+        fs::create_dir(fixture.content()).expect("content directory");
+        for (path, content) in files {
+            fixture.write(path, content);
+        }
+        fixture
+    }
+
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root.path().join(path)
+    }
+
+    pub fn torrent(&self) -> PathBuf {
+        self.path("show.torrent")
+    }
+
+    pub fn content(&self) -> PathBuf {
+        self.path("Show")
+    }
+
+    /// Writes `content` to `path` under the content directory, a day old.
+    pub fn write(&self, path: &str, content: &[u8]) -> PathBuf {
+        let path = self.content().join(path);
+        fs::create_dir_all(path.parent().expect("in the content")).expect("parent created");
+        fs::write(&path, content).expect("file written");
+        age(&path, Duration::from_secs(24 * 3600));
+        path
+    }
+
+    /// The binary with `args`, run in the fixture with its own user directories.
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_torrent-cleaner"));
+        command.args(args)
+            .current_dir(self.root.path())
+            .env("HOME", self.path("home"))
+            .env("XDG_CONFIG_HOME", self.path("home/config"))
+            .env("XDG_CACHE_HOME", self.path("home/cache"))
+            .env("XDG_DATA_HOME", self.path("home/data"))
+            .env("NO_COLOR", "1")
+            .env_remove("CLICOLOR_FORCE")
+            .env_remove("RUST_BACKTRACE");
+        command
+    }
+
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().expect("binary runs")
+    }
+}
+
+/// Sets the modification time of `path` `ago` back.
+pub fn age(path: &Path, ago: Duration) {
+    File::options().write(true).open(path).expect("file opened")
+        .set_modified(SystemTime::now() - ago).expect("modification time set");
+}
+
+/// A request the mock server got.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    /// The path and query.
+    pub target: String,
+    /// The headers, with their names lowercased.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|e| e.0 == name).map(|e| e.1.as_str())
+    }
+}
+
+/// An answer of the mock server: status, headers and body.
+pub type Answer = (u16, Vec<(&'static str, String)>, String);
+
+/// An HTTP server on localhost standing in for the Web API of a client, answering each request
+/// with what its handler makes of it and keeping the requests.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    pub fn start(handler: impl Fn(&Request) -> Answer + Send + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("listening");
+        let url = format!("http://{}", listener.local_addr().expect("bound"));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let kept = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().expect("stream cloned"));
+                let mut line = String::new();
+                reader.read_line(&mut line).expect("request line");
+                let mut parts = line.split_whitespace().map(str::to_string);
+                let (method, target) = (parts.next().unwrap_or_default(),
+                                        parts.next().unwrap_or_default());
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).expect("header line");
+                    let Some((name, value)) = line.trim_end().split_once(':') else { break };
+                    headers.push((name.to_lowercase(), value.trim().to_string()));
+                }
+                let length = headers.iter().find(|e| e.0 == "content-length")
+                    .map_or(0, |e| e.1.parse().expect("content length"));
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).expect("body");
+                let request = Request { method, target, headers,
+                                        body: String::from_utf8_lossy(&body).into_owned() };
+                let (status, headers, body) = handler(&request);
+                kept.lock().expect("not poisoned").push(request);
+                let mut response = format!("HTTP/1.1 {} Mock\r\nContent-Length: {}\r\n\
+                                            Connection: close\r\n", status, body.len());
+                for (name, value) in headers {
+                    response += &format!("{}: {}\r\n", name, value);
+                }
+                response += "\r\n";
+                response += &body;
+                stream.write_all(response.as_bytes()).ok();
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().expect("not poisoned").clone()
+    }
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// `args` run with `config` as the config file.
+fn with_config(fixture: &Fixture, config: &str, args: &[&str]) -> std::process::Output {
+    fs::write(fixture.path("config.toml"), config).expect("config written");
+    let mut all = vec!["--config", "config.toml"];
+    all.extend(args);
+    fixture.run(&all)
+}
+
+#[test]
+fn settings_are_read_from_toml() {
+    let fixture = Fixture::new(&FILES);
+    let config = "# Defaults\n\
+                  max_delete_ratio = 0.3\n\
+                  exclude = [\n  \"*.nfo\",\n  'sam\\ple/', # literal\n]\n\
+                  threads = 0x4\n\
+                  \n\
+                  [clean]\n\
+                  no-confirm = true\n";
+    let output = with_config(&fixture, config, &["clean", "--print-config"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let printed = stdout(&output);
+    for line in ["exclude = [\"*.nfo\", \"sam\\\\ple/\"]  # config file",
+                 "max-delete-ratio = 0.3  # config file",
+                 "threads = 4  # config file",
+                 "no-confirm = true  # config file"] {
+        assert!(printed.contains(line), "{}: {}", line, printed);
+    }
+    let output = with_config(&fixture, config, &["diff", "--print-config"]);
+    assert!(!stdout(&output).contains("no-confirm = true"), "{}", stdout(&output));
+}
+
+#[test]
+fn command_line_wins_over_the_file() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let output = with_config(&fixture, "exclude = [\"*.nfo\"]\n[clean]\nno-confirm = true\n",
+                             &["clean", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(extra.exists());
+
+    let output = with_config(&fixture, "exclude = [\"*.nfo\"]\n[clean]\nno-confirm = true\n",
+                             &["clean", "--exclude", "*.srt", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!extra.exists());
+}
+
+#[test]
+fn errors_name_their_line() {
+    let fixture = Fixture::new(&FILES);
+    for (config, error) in [
+        ("exclude = \"x\"\nexclude = \"y\"\n", "line 2: duplicate key"),
+        ("\n[clean]\nbogus = 1\n", "line 3: unknown key `bogus` in [clean]"),
+        ("\n\nexclude = 1979-05-27\n", "line 3: expected a value, found a date"),
+        ("[clean.sub]\nx = 1\n", "line 1: [clean] cannot hold the table sub"),
+        ("no_confirm = true\nno-confirm = true\n", "line 2: `no-confirm` is set twice"),
+        ("threads = \"many\"\n", "line 1"),
+        ("exclude = \"unterminated\n", "line 1"),
+    ] {
+        let output = with_config(&fixture, config, &["diff", "show.torrent", "Show"]);
+        assert_eq!(output.status.code(), Some(2), "{}", config);
+        assert!(stderr(&output).contains(&format!("Invalid config file config.toml: {}", error)),
+                "{}: {}", config, stderr(&output));
+    }
+}
+
+#[test]
+fn private_tables_take_protect_lists() {
+    let fixture = Fixture::new(&FILES);
+    let output = with_config(&fixture, "[private]\nprotect = [\"*.sfv\"]\n\n\
+                                        [private.\"tracker.example\"]\nprotect = \"proof/\"\n",
+                             &["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = with_config(&fixture, "[private]\nkeep = [\"*.sfv\"]\n",
+                             &["diff", "show.torrent", "Show"]);
+    assert!(stderr(&output).contains("unknown key `keep` in [private]"), "{}", stderr(&output));
+}
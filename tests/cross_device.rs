@@ -0,0 +1,103 @@
+//! Moves to another file system, which copy the files in chunks. They need a second file system
+//! to copy to, `/dev/shm`, and are skipped where it is not one.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The size of the chunks copies are written and checked in.
+const CHUNK: usize = 1024 * 1024;
+
+/// A directory on another file system than the fixture's, if there is one.
+fn elsewhere(fixture: &Fixture) -> Option<TempDir> {
+    let device = |path: &Path| fs::metadata(path).map(|e| e.dev()).ok();
+    let dir = TempDir::new_in("/dev/shm").ok()?;
+    match device(dir.path()) != device(fixture.root.path()) {
+        true => Some(dir),
+        false => {
+            eprintln!("skipped: /dev/shm is on the same file system");
+            None
+        }
+    }
+}
+
+/// Three chunks that differ from each other.
+fn content() -> Vec<u8> {
+    (0..3 * CHUNK).map(|e| (e / CHUNK + e % 251) as u8).collect()
+}
+
+fn move_to(fixture: &Fixture, to: &Path, args: &[&str]) -> std::process::Output {
+    let to = to.to_string_lossy();
+    let mut all = vec!["clean", "-f", "--move-to", &to];
+    all.extend(args);
+    all.extend(["show.torrent", "Show"]);
+    fixture.run(&all)
+}
+
+#[test]
+fn copy_keeps_content_and_times() {
+    let fixture = Fixture::new(&FILES);
+    let Some(to) = elsewhere(&fixture) else { return };
+    let extra = fixture.write("sub/big.bin", &content());
+    let modified = fs::metadata(&extra).and_then(|e| e.modified()).expect("modification time");
+    let output = move_to(&fixture, to.path(), &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!extra.exists(), "{}", stdout(&output));
+    let moved = to.path().join("Show/sub/big.bin");
+    assert_eq!(fs::read(&moved).expect("moved"), content());
+    assert_eq!(fs::metadata(&moved).and_then(|e| e.modified()).expect("time"), modified);
+    assert!(!to.path().join("Show/sub/big.bin.tc-partial").exists());
+}
+
+#[test]
+fn partial_copy_is_continued() {
+    let fixture = Fixture::new(&FILES);
+    let Some(to) = elsewhere(&fixture) else { return };
+    fixture.write("sub/big.bin", &content());
+    // Two chunks copied before, the first of which no longer matches: only the last chunk of a
+    // partial copy is checked, so what is kept of it shows that the copy was continued
+    let mut partial = content()[..2 * CHUNK].to_vec();
+    partial[..CHUNK].fill(0);
+    fs::create_dir_all(to.path().join("Show/sub")).expect("directory created");
+    fs::write(to.path().join("Show/sub/big.bin.tc-partial"), &partial).expect("partial written");
+    let output = move_to(&fixture, to.path(), &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let moved = fs::read(to.path().join("Show/sub/big.bin")).expect("moved");
+    assert_eq!(moved[..2 * CHUNK], partial[..]);
+    assert_eq!(moved[2 * CHUNK..], content()[2 * CHUNK..]);
+}
+
+#[test]
+fn mismatched_partial_copy_starts_over() {
+    let fixture = Fixture::new(&FILES);
+    let Some(to) = elsewhere(&fixture) else { return };
+    fixture.write("sub/big.bin", &content());
+    fs::create_dir_all(to.path().join("Show/sub")).expect("directory created");
+    fs::write(to.path().join("Show/sub/big.bin.tc-partial"), vec![0; CHUNK + 1])
+        .expect("partial written");
+    let output = move_to(&fixture, to.path(), &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fs::read(to.path().join("Show/sub/big.bin")).expect("moved"), content());
+}
+
+#[test]
+fn bwlimit_slows_the_copy() {
+    let fixture = Fixture::new(&FILES);
+    let Some(to) = elsewhere(&fixture) else { return };
+    fixture.write("sub/big.bin", &content());
+    let start = Instant::now();
+    let output = move_to(&fixture, to.path(), &["--bwlimit", "6M"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    // 3 MiB at 6 MiB per second
+    assert!(start.elapsed() >= Duration::from_millis(450), "{:?}", start.elapsed());
+    assert_eq!(fs::read(to.path().join("Show/sub/big.bin")).expect("moved"), content());
+}
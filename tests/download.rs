@@ -0,0 +1,55 @@
+mod common;
+
+use common::{stderr, stdout, torrent, Fixture, MockServer};
+use std::thread;
+use std::time::Duration;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The torrent of the fixture, whose placeholder pieces keep it valid UTF-8 for the mock server.
+fn served() -> String {
+    String::from_utf8(torrent("Show", &FILES)).expect("torrent of text")
+}
+
+#[test]
+fn torrent_url_is_downloaded_following_redirects() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let server = MockServer::start(|request| match request.target.as_str() {
+        "/download?id=1" => (302, vec![("Location", "/files/show.torrent".to_string())],
+                             String::new()),
+        "/files/show.torrent" => (200, Vec::new(), served()),
+        _ => (404, Vec::new(), String::new()),
+    });
+    let url = format!("{}/download?id=1", server.url);
+    let output = fixture.run(&["diff", &url, "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("extra.nfo"), "{}", stdout(&output));
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].header("user-agent").is_some_and(|e| e.starts_with("torrent-cleaner/")));
+}
+
+#[test]
+fn http_error_names_the_status() {
+    let fixture = Fixture::new(&FILES);
+    let server = MockServer::start(|_| (403, Vec::new(), "Forbidden".to_string()));
+    let url = format!("{}/show.torrent", server.url);
+    let output = fixture.run(&["diff", &url, "Show"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("the server answered with HTTP status 403"),
+            "{}", stderr(&output));
+}
+
+#[test]
+fn slow_server_times_out() {
+    let fixture = Fixture::new(&FILES);
+    let server = MockServer::start(|_| {
+        thread::sleep(Duration::from_secs(3));
+        (200, Vec::new(), served())
+    });
+    let url = format!("{}/show.torrent", server.url);
+    let output = fixture.run(&["diff", "--timeout", "1s", &url, "Show"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Timed out after 1s downloading"), "{}", stderr(&output));
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+#[test]
+fn clean_droppings_removes_what_clients_left_of_files_no_longer_in_the_torrent() {
+    let fixture = Fixture::new(&FILES);
+    let droppings = ["sub/old.bin.part", "sub/old.bin.resume", "sub/old.bin.!qB",
+                     "Show.torrent.libtorrent_resume"];
+    for path in droppings {
+        fixture.write(path, b"x");
+    }
+    // The download in progress of a file of the torrent is no dropping
+    fixture.write("sub/b.bin.!qB", b"x");
+    let output = fixture.run(&["clean", "-f", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).lines().any(|e| e.trim_start().starts_with("client droppings")),
+            "{}", stdout(&output));
+    for path in droppings {
+        assert!(fixture.content().join(path).exists(), "{}", path);
+    }
+
+    let output = fixture.run(&["clean", "-f", "--clean-droppings", "--surface",
+                               "--force-threshold", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    for path in droppings {
+        assert!(!fixture.content().join(path).exists(), "{}", path);
+    }
+    assert!(fixture.content().join("sub/b.bin.!qB").exists());
+}
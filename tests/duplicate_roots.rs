@@ -0,0 +1,60 @@
+mod common;
+
+use common::{age, stderr, stdout, Fixture};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// `Show` lacking `sub/b.bin`, next to a `Show (1)` copy holding only that file.
+fn fixture() -> (Fixture, PathBuf) {
+    let fixture = Fixture::new(&FILES);
+    fs::remove_file(fixture.content().join("sub/b.bin")).unwrap();
+    let copy = fixture.path("Show (1)");
+    fs::create_dir_all(copy.join("sub")).unwrap();
+    fs::write(copy.join("sub/b.bin"), b"0123456789").unwrap();
+    age(&copy.join("sub/b.bin"), Duration::from_secs(24 * 3600));
+    (fixture, copy)
+}
+
+#[test]
+fn diff_reports_duplicate_roots() {
+    let (fixture, copy) = fixture();
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    let out = stdout(&output);
+    assert!(out.contains("Probable duplicate root:"), "{}", out);
+    assert!(out.contains("Show (1) (10 B, 1/2 expected files;"), "{}", out);
+    assert!(out.contains("Show has 1/2)"), "{}", out);
+    assert!(copy.exists());
+}
+
+#[test]
+fn clean_merges_a_duplicate_root() {
+    let (fixture, copy) = fixture();
+    let output = fixture.run(&["clean", "-f", "--surface", "--duplicate-roots", "merge",
+                               "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}{}", stdout(&output), stderr(&output));
+    assert!(stdout(&output).contains("Moved 1 files into"), "{}", stdout(&output));
+    assert_eq!(fs::read(fixture.content().join("sub/b.bin")).unwrap(), b"0123456789");
+    assert!(!copy.exists());
+}
+
+#[test]
+fn clean_deletes_a_duplicate_root() {
+    let (fixture, copy) = fixture();
+    let output = fixture.run(&["clean", "-f", "--surface", "--duplicate-roots", "delete",
+                               "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}{}", stdout(&output), stderr(&output));
+    assert!(!fixture.content().join("sub/b.bin").exists());
+    assert!(!copy.exists());
+}
+
+#[test]
+fn clean_keeps_duplicate_roots_without_asking() {
+    let (fixture, copy) = fixture();
+    let output = fixture.run(&["clean", "-f", "--surface", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("left alone with --no-confirm"), "{}", stdout(&output));
+    assert!(copy.join("sub/b.bin").exists());
+}
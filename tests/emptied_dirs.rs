@@ -0,0 +1,51 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use serde_json::Value;
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The fixture with `sub/extras` holding nothing but an extra, and `sub/empty` already empty.
+fn fixture() -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extras/only.nfo", b"x");
+    fs::create_dir(fixture.content().join("sub/empty")).expect("directory created");
+    fixture
+}
+
+#[test]
+fn diff_marks_the_directories_clean_would_empty() {
+    let fixture = fixture();
+    let output = fixture.run(&["diff", "--empty-dir", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.lines().any(|e| e.contains("-D") && e.contains("sub/extras")), "{}", stdout);
+    assert!(stdout.lines().any(|e| e.contains("-d") && e.contains("sub/empty")), "{}", stdout);
+    assert!(stdout.contains("Remove entries: 1 B (3)"), "{}", stdout);
+}
+
+#[test]
+fn diff_json_counts_the_emptied_directories() {
+    let fixture = fixture();
+    let output = fixture.run(&["diff", "--empty-dir", "--output", "json", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let report: Value = serde_json::from_str(&stdout(&output)).expect("JSON report");
+    let paths = |list: &str| report[list].as_array().expect("list").iter()
+        .map(|e| e["path"].as_str().expect("path").to_string()).collect::<Vec<String>>();
+    assert_eq!(paths("emptied_dirs"), ["sub/extras"]);
+    assert_eq!(paths("extra_dirs"), ["sub/empty"]);
+    assert_eq!(report["summary"]["emptied_dirs"], 1);
+    assert_eq!(report["summary"]["extra_dirs"], 1);
+}
+
+#[test]
+fn clean_removes_what_diff_showed() {
+    let fixture = fixture();
+    let output = fixture.run(&["clean", "-f", "--empty-dir", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("3 entries removed."), "{}", stdout(&output));
+    assert!(!fixture.content().join("sub/extras").exists());
+    assert!(!fixture.content().join("sub/empty").exists());
+    assert!(fixture.content().join("sub/b.bin").exists());
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/deep/b.bin", b"0123456789")];
+
+#[test]
+fn directories_of_the_torrent_are_kept_while_empty() {
+    let fixture = Fixture::new(&FILES);
+    fs::remove_file(fixture.content().join("sub/deep/b.bin")).expect("file removed");
+    fs::create_dir_all(fixture.content().join("sub/stray/empty")).expect("directory created");
+    let output = fixture.run(&["clean", "-f", "--empty-dir", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(fixture.content().join("sub/deep").is_dir(), "{}", stdout(&output));
+    assert!(!fixture.content().join("sub/stray").exists(), "{}", stdout(&output));
+}
+
+#[test]
+fn empty_directories_outside_the_scope_need_surface() {
+    let fixture = Fixture::new(&FILES);
+    fs::create_dir(fixture.content().join("stray")).expect("directory created");
+    let output = fixture.run(&["clean", "-f", "--empty-dir", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(fixture.content().join("stray").is_dir(), "{}", stdout(&output));
+
+    let output = fixture.run(&["clean", "-f", "--empty-dir", "--surface", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fixture.content().join("stray").exists(), "{}", stdout(&output));
+}
+
+#[test]
+fn without_empty_dir_directories_stay() {
+    let fixture = Fixture::new(&FILES);
+    fs::create_dir_all(fixture.content().join("sub/stray")).expect("directory created");
+    let output = fixture.run(&["clean", "-f", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(fixture.content().join("sub/stray").is_dir());
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::{metainfo, stderr, stdout, Bencode, Fixture};
+use serde_json::Value;
+use std::fs;
+
+/// The fixture with `show.torrent` replaced by one whose `files` list is empty, and two entries
+/// in its directory.
+fn empty() -> Fixture {
+    let fixture = Fixture::new(&[("a.txt", b"hello"), ("sub/b.bin", b"0123456789")]);
+    fs::write(fixture.torrent(), metainfo("Show", &[], common::PIECE_LENGTH, 20))
+        .expect("torrent written");
+    fixture
+}
+
+/// The last line of stdout, where the JSON document is.
+fn last_json(output: &std::process::Output) -> Value {
+    let stdout = stdout(output);
+    serde_json::from_str(stdout.lines().last().unwrap_or_default()).expect("JSON document")
+}
+
+#[test]
+fn clean_refuses_an_empty_torrent() {
+    let fixture = empty();
+    let output = fixture.run(&["clean", "-f", "--output", "json", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(11));
+    assert_eq!(last_json(&output)["error"]["code"], "empty_expected");
+    assert!(fixture.content().join("a.txt").exists());
+    assert!(fixture.content().join("sub/b.bin").exists());
+}
+
+#[test]
+fn padding_only_torrent_is_empty() {
+    let fixture = empty();
+    let padding = Bencode::dict([
+        ("attr", Bencode::str("p")),
+        ("length", Bencode::Int(16)),
+        ("path", Bencode::List(vec![Bencode::str(".pad"), Bencode::str("16")])),
+    ]);
+    let info = Bencode::dict([
+        ("name", Bencode::str("Show")),
+        ("piece length", Bencode::Int(common::PIECE_LENGTH)),
+        ("pieces", Bencode::Bytes(vec![0; 20])),
+        ("files", Bencode::List(vec![padding])),
+    ]);
+    fs::write(fixture.torrent(), Bencode::dict([("info", info)]).encode()).expect("torrent written");
+    let output = fixture.run(&["clean", "-f", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(11));
+    assert!(stderr(&output).contains("Torrent describes no payload files"), "{}", stderr(&output));
+}
+
+#[test]
+fn diff_lists_the_directory_before_failing() {
+    let fixture = empty();
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(11));
+    let stdout = stdout(&output);
+    let contents = stdout.find("Directory contents: 15 B in 2 entries").expect(&stdout);
+    let listed = stdout.find("sub/b.bin").expect(&stdout);
+    assert!(contents < listed, "{}", stdout);
+    assert!(stdout.contains("a.txt"), "{}", stdout);
+    assert!(stderr(&output).contains("--force-empty-expected"), "{}", stderr(&output));
+}
+
+#[test]
+fn force_empty_expected_plans_everything_as_extra() {
+    let fixture = empty();
+    let output = fixture.run(&["diff", "--force-empty-expected", "--output", "json",
+                               "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(last_json(&output)["extra_files"], serde_json::json!([]));
+
+    let output = fixture.run(&["diff", "--force-empty-expected", "--surface", "--output", "json",
+                               "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let report = last_json(&output);
+    let extras = report["extra_files"].as_array().expect("extra files").iter()
+        .map(|e| e["path"].as_str().expect("path").to_string()).collect::<Vec<String>>();
+    assert_eq!(extras, ["a.txt", "sub/b.bin"]);
+
+    let output = fixture.run(&["clean", "-f", "--force-empty-expected", "--surface",
+                               "--force-threshold", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fixture.content().join("a.txt").exists());
+}
+
+#[test]
+fn batch_fails_the_empty_entries() {
+    let fixture = empty();
+    fs::write(fixture.path("batch.tsv"), "show.torrent\tShow\n").expect("manifest written");
+    let output = fixture.run(&["batch", "--clean", "-f", "--output", "json", "batch.tsv"]);
+    assert!(!output.status.success());
+    let report = last_json(&output);
+    let entry = &report["entries"][0];
+    assert_eq!(entry["status"], "failed", "{}", entry);
+    assert!(entry["error"].to_string().contains("no payload files"), "{}", entry);
+    assert!(fixture.content().join("a.txt").exists());
+}
@@ -0,0 +1,83 @@
+//! Lookups in the sorted index of [`Expected`] against a plain map of the same files.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use torrent_cleaner::scan::{Expected, ExpectedSet, Folding};
+
+/// Paths whose byte order differs from their component order, `-` and `.` sorting before `/`.
+const PATHS: [&str; 12] = ["sub/a.bin", "sub-2/b.bin", "sub.d/c.bin", "su/d.bin", "sub/deep/e.bin",
+                           "sub/deep-er/f.bin", "Top.txt", "x/y/z/w.bin", "x/y.bin", "x-y/z.bin",
+                           "Caf\u{e9}/g.bin", "\u{1f600}/h.bin"];
+
+/// Queries for files, directories, and names that only share a prefix with either.
+const QUERIES: [&str; 27] = ["sub", "sub/a.bin", "sub-2", "sub.d", "su", "s", "sub/deep",
+                             "sub/deep-er", "sub/dee", "sub/deep/e.bin", "sub/a", "sub-", "Top.txt",
+                             "Top", "x", "x/y", "x/y.bin", "x/y/z", "x-y", "x/y/z/w.bin/v",
+                             "Caf\u{e9}", "Cafe\u{301}", "\u{1f600}", "sub/deep-er/f.bin",
+                             "SUB/A.BIN", "missing", "sub/a.bin/more"];
+
+fn expected(paths: &[String]) -> Expected {
+    let files = paths.iter().enumerate()
+        .map(|(i, e)| (e.split('/').map(String::from).collect(), i as u64));
+    Expected::from_files(files).expect("files")
+}
+
+/// The reference: each path with its size, looked up the obvious way.
+fn reference(paths: &[String]) -> BTreeMap<PathBuf, u64> {
+    paths.iter().enumerate().map(|(i, e)| (PathBuf::from(e), i as u64)).collect()
+}
+
+fn assert_same(expected: &Expected, reference: &BTreeMap<PathBuf, u64>, query: &str) {
+    let path = Path::new(query);
+    assert_eq!(expected.file_size(path), reference.get(path).copied(), "file_size {}", query);
+    assert_eq!(expected.contains_dir(path), reference.keys().any(|e| e.starts_with(path)),
+               "contains_dir {}", query);
+    let first = path.iter().next().expect("a name");
+    assert_eq!(expected.is_surface(first),
+               reference.keys().any(|e| e.iter().next() == Some(first)), "is_surface {}", query);
+}
+
+#[test]
+fn lookups_match_the_plain_map() {
+    let paths = PATHS.map(String::from);
+    let (expected, reference) = (expected(&paths), reference(&paths));
+    assert_eq!(expected.file_count(), PATHS.len());
+    for query in QUERIES {
+        assert_same(&expected, &reference, query);
+    }
+    assert!(!expected.is_surface(OsStr::new("Sub")));
+}
+
+#[test]
+fn lookups_match_among_many_files() {
+    let mut paths = Vec::new();
+    for season in 0..50 {
+        for episode in 0..400 {
+            paths.push(format!("Show/Season {}/E{:03}.mkv", season, episode));
+            paths.push(format!("Show/Season {}-extras/E{:03}.srt", season, episode));
+        }
+    }
+    let (expected, reference) = (expected(&paths), reference(&paths));
+    assert_eq!(expected.file_count(), 40_000);
+    for query in ["Show", "Show/Season 1", "Show/Season 1-extras", "Show/Season 10",
+                  "Show/Season 49/E399.mkv", "Show/Season 49/E400.mkv",
+                  "Show/Season 5-extras/E007.srt", "Show/Season 5-extras/E007.mkv", "Show/Season",
+                  "Show/Season 50"] {
+        assert_same(&expected, &reference, query);
+    }
+}
+
+#[test]
+fn ignoring_case_folds_the_lookups_alike() {
+    let paths = PATHS.map(String::from);
+    let mut expected = expected(&paths);
+    let collisions = expected.fold(Folding { case: true, unicode: true });
+    assert!(collisions.is_empty());
+    assert_eq!(expected.file_size(Path::new("SUB/A.BIN")), Some(0));
+    assert_eq!(expected.file_size(Path::new("cafe\u{301}/G.bin")), Some(10));
+    assert!(expected.contains_dir(Path::new("SUB/Deep")));
+    assert!(!expected.contains_dir(Path::new("SUB/Dee")));
+    assert!(expected.is_surface(OsStr::new("TOP.TXT")));
+    assert!(!expected.is_surface(OsStr::new("sub-3")));
+}
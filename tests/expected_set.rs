@@ -0,0 +1,78 @@
+//! Scans through each source of expected files give the same result for the same files.
+
+mod common;
+
+use common::{torrent, Fixture};
+use librqbit_buffers::ByteBufOwned;
+use librqbit_core::torrent_metainfo::torrent_from_bytes_ext;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use torrent_cleaner::scan::{Expected, ExpectedSet, ScanOptions};
+use torrent_cleaner::{ScanReport, Scanner, TorrentFileSet};
+
+const FILES: [(&str, &[u8]); 3] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789"),
+                                   ("sub/deep/c.bin", b"abc")];
+
+/// The plainest source there is: a map of paths to sizes.
+struct PathMap(BTreeMap<PathBuf, u64>);
+
+impl ExpectedSet for PathMap {
+    fn file_size(&self, path: &Path) -> Option<u64> {
+        self.0.get(path).copied()
+    }
+
+    fn contains_dir(&self, path: &Path) -> bool {
+        self.0.keys().any(|e| e.starts_with(path) && e != path)
+    }
+
+    fn is_surface(&self, name: &OsStr) -> bool {
+        self.0.keys().any(|e| e.iter().next() == Some(name))
+    }
+
+    fn files(&self) -> impl Iterator<Item = (&Path, u64)> {
+        self.0.iter().map(|(path, size)| (path.as_path(), *size))
+    }
+}
+
+/// What a scan found, sorted so that the walk order does not matter.
+fn outcome(report: &ScanReport) -> (Vec<PathBuf>, Vec<PathBuf>, u64, Vec<PathBuf>, u64) {
+    let sorted = |paths: &[PathBuf]| {
+        let mut paths = paths.to_vec();
+        paths.sort();
+        paths
+    };
+    let plan = report.plan();
+    (sorted(report.extras()), sorted(report.missing()), report.missing_size(), sorted(&plan.dirs),
+     plan.size)
+}
+
+#[test]
+fn every_source_scans_the_same() -> anyhow::Result<()> {
+    let fixture = Fixture::new(&FILES);
+    fs::remove_file(fixture.content().join("sub/deep/c.bin"))?;
+    fixture.write("extra.nfo", b"x");
+    fixture.write("sub/extra/sample.mkv", b"sample");
+    fs::create_dir(fixture.content().join("sub/empty"))?;
+
+    let bytes = ByteBufOwned::from(torrent("Show", &FILES));
+    let metainfo = torrent_from_bytes_ext::<ByteBufOwned>(bytes.as_ref())?.meta;
+    let from_torrent = TorrentFileSet::from_metainfo(&metainfo)?;
+    let from_files = Expected::from_files(FILES.iter().map(|(path, content)|
+        (path.split('/').map(String::from).collect(), content.len() as u64)))?;
+    let from_map = PathMap(FILES.iter()
+        .map(|(path, content)| (PathBuf::from(path), content.len() as u64)).collect());
+
+    let options = ScanOptions { include_empty_dir: true, include_surface: true,
+                                ..ScanOptions::default() };
+    let scanner = Scanner::new(&fixture.content()).with_options(options);
+    let expected = outcome(&scanner.scan(&from_torrent)?);
+    assert_eq!(expected.0, [fixture.content().join("extra.nfo"),
+                            fixture.content().join("sub/extra/sample.mkv")]);
+    assert_eq!(expected.1, [fixture.content().join("sub/deep/c.bin")]);
+    assert_eq!(expected.2, 3);
+    assert_eq!(outcome(&scanner.scan(&from_files)?), expected);
+    assert_eq!(outcome(&scanner.scan(&from_map)?), expected);
+    Ok(())
+}
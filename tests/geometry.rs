@@ -0,0 +1,60 @@
+mod common;
+
+use common::{metainfo, stderr, stdout, Fixture, PIECE_LENGTH};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The fixture with its torrent rewritten with `piece_length` and `hashes` bytes of hashes.
+fn fixture(piece_length: i64, hashes: usize) -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    fs::write(fixture.torrent(), metainfo("Show", &FILES, piece_length, hashes))
+        .expect("torrent written");
+    fixture
+}
+
+#[test]
+fn sound_geometry_warns_of_nothing() {
+    let output = fixture(PIECE_LENGTH, 20).run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("geometry"), "{}", stderr(&output));
+}
+
+#[test]
+fn odd_piece_length_warns() {
+    let output = fixture(20000, 20).run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let all = stdout(&output) + &stderr(&output);
+    assert!(all.contains("piece length 19.53 KiB is not a power of two"), "{}", all);
+}
+
+#[test]
+fn piece_hashes_must_be_whole() {
+    let output = fixture(PIECE_LENGTH, 30).run(&["diff", "show.torrent", "Show"]);
+    let all = stdout(&output) + &stderr(&output);
+    assert!(all.contains("pieces field is 30 bytes, not a multiple of 20"), "{}", all);
+}
+
+#[test]
+fn piece_count_must_cover_the_files() {
+    let output = fixture(PIECE_LENGTH, 60).run(&["diff", "show.torrent", "Show"]);
+    let all = stdout(&output) + &stderr(&output);
+    assert!(all.contains("3 piece hashes, but 1 of 16.00 KiB are needed to cover 15 B"), "{}", all);
+}
+
+#[test]
+fn tiny_piece_length_is_out_of_range() {
+    let output = fixture(1024, 20).run(&["diff", "show.torrent", "Show"]);
+    let all = stdout(&output) + &stderr(&output);
+    assert!(all.contains("piece length 1.00 KiB is outside 16.00 KiB to 128.00 MiB"), "{}", all);
+}
+
+#[test]
+fn strict_torrent_refuses_to_go_on() {
+    let fixture = fixture(20000, 20);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["clean", "-f", "--strict-torrent", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("not a power of two"), "{}", stderr(&output));
+    assert!(extra.exists());
+}
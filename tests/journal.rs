@@ -0,0 +1,48 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+#[test]
+fn finished_journal_is_deleted_from_the_cache() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["clean", "-f", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!extra.exists());
+    let journals = fixture.path("home/cache/torrent-cleaner/journals");
+    // The directory is left, having held the journal of the run
+    let left = fs::read_dir(&journals).expect("journals directory").count();
+    assert_eq!(left, 0, "{}", journals.display());
+}
+
+#[test]
+fn given_journal_is_kept_finished() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["clean", "-f", "--journal", "run.jsonl", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let journal = fs::read_to_string(fixture.path("run.jsonl")).expect("journal kept");
+    let events = journal.lines()
+        .map(|e| serde_json::from_str::<serde_json::Value>(e).expect("JSON line")["event"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(events, ["start", "planned", "removed", "finished"]);
+}
+
+#[test]
+fn journal_that_cannot_be_kept_is_a_warning() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    // The cache directory cannot be made where a file is
+    fs::create_dir_all(fixture.path("home")).expect("home created");
+    fs::write(fixture.path("home/cache"), b"").expect("file written");
+    let output = fixture.run(&["clean", "-f", "--summary-json", "--deny-warnings",
+                               "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    assert!(!extra.exists());
+    assert!(stderr(&output).contains("Warning: no journal is kept for this run"),
+            "{}", stderr(&output));
+    assert!(stdout(&output).contains("\"code\":\"journal\""), "{}", stdout(&output));
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The line of `stdout` listing `name`.
+fn line<'a>(stdout: &'a str, name: &str) -> &'a str {
+    let line = stdout.lines().find(|e| e.contains(name));
+    line.unwrap_or_else(|| panic!("{} not listed: {}", name, stdout))
+}
+
+#[test]
+fn system_junk_is_told_from_the_junk_type() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/Thumbs.db", b"t");
+    fixture.write("sub/info.nfo", b"n");
+    fixture.write("sub/.stfolder/marker", b"m");
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(line(&stdout, "Thumbs.db").contains("(system)"), "{}", stdout);
+    assert!(line(&stdout, "info.nfo").contains("[junk]"), "{}", stdout);
+    assert!(!line(&stdout, "info.nfo").contains("(system)"), "{}", stdout);
+    assert!(line(&stdout, ".stfolder").contains("(system)"), "{}", stdout);
+}
+
+#[test]
+fn junk_keep_lists_the_system_junk_kept() {
+    let fixture = Fixture::new(&FILES);
+    let thumbs = fixture.write("sub/Thumbs.db", b"t");
+    let nfo = fixture.write("sub/info.nfo", b"n");
+    let output = fixture.run(&["clean", "-f", "--junk", "keep", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("System junk, kept (1, 1 B):"), "{}", stdout(&output));
+    assert!(thumbs.exists());
+    assert!(!nfo.exists());
+}
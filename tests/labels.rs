@@ -0,0 +1,125 @@
+mod common;
+
+use common::{stderr, stdout, Answer, Fixture, MockServer, Request};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// qBittorrent listing the torrent with `tags` in `category`.
+fn qbittorrent(tags: &'static str, category: &'static str) -> impl Fn(&Request) -> Answer {
+    move |request| match request.target.split('?').next().unwrap_or_default() {
+        "/api/v2/torrents/info" => (200, Vec::new(), format!(
+            r#"[{{"name":"Show","tags":"{}","category":"{}"}}]"#, tags, category)),
+        _ => (404, Vec::new(), String::new()),
+    }
+}
+
+/// `clean -f` of the fixture against `server` as qBittorrent, with `args` before the torrent.
+fn clean(fixture: &Fixture, server: &MockServer, args: &[&str]) -> std::process::Output {
+    let mut all = vec!["clean", "-f", "--client", "qbittorrent", "--client-url", &server.url];
+    all.extend(args);
+    all.extend(["show.torrent", "Show"]);
+    fixture.run(&all)
+}
+
+#[test]
+fn label_options_need_a_client() {
+    let fixture = Fixture::new(&FILES);
+    let output = fixture.run(&["clean", "-f", "--skip-label", "keep", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("--skip-label needs the --client"), "{}", stderr(&output));
+}
+
+#[test]
+fn skip_label_reports_without_cleaning() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let server = MockServer::start(qbittorrent("seeding, Keep", ""));
+    let output = clean(&fixture, &server, &["--skip-label", "keep"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(extra.exists());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("extra.nfo"), "{}", stdout);
+    assert!(stdout.contains("Skipped: Show is not cleaned, as it carries the label Keep \
+                             (--skip-label)."), "{}", stdout);
+}
+
+#[test]
+fn skip_category_holds_back_the_summary() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let server = MockServer::start(qbittorrent("", "movies"));
+    let output = clean(&fixture, &server, &["--skip-category", "tv", "--skip-category", "movies",
+                                            "--summary-json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(extra.exists());
+    let summary: serde_json::Value = serde_json::from_str(&stdout(&output)).expect("JSON summary");
+    assert_eq!(summary["status"], "ok");
+    assert_eq!(summary["removed"], 0);
+    assert_eq!(summary["held_back"], "it is in the category movies (--skip-category)");
+}
+
+#[test]
+fn only_label_cleans_the_torrents_carrying_one() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let server = MockServer::start(qbittorrent("done", ""));
+    let output = clean(&fixture, &server, &["--only-label", "finished"]);
+    assert!(extra.exists(), "{}", stdout(&output));
+    assert!(stdout(&output).contains("it carries none of the labels finished (--only-label)"),
+            "{}", stdout(&output));
+
+    let output = clean(&fixture, &server, &["--only-label", "finished", "--only-label", "done"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!extra.exists(), "{}", stdout(&output));
+}
+
+#[test]
+fn transmission_labels_are_read() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let server = MockServer::start(|request| match request.header("x-transmission-session-id") {
+        Some("abc") => (200, Vec::new(), r#"{"result":"success","arguments":{"torrents":
+            [{"name":"Show","labels":["private","keep"]}]}}"#.to_string()),
+        _ => (409, vec![("X-Transmission-Session-Id", "abc".to_string())], String::new()),
+    });
+    let output = fixture.run(&["clean", "-f", "--client", "transmission", "--client-url",
+                               &server.url, "--skip-label", "keep", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(extra.exists());
+    assert!(server.requests().iter().any(|e| e.body.contains("\"labels\"")));
+}
+
+#[test]
+fn unreachable_client_fails_the_clean() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["clean", "-f", "--client", "qbittorrent", "--client-url",
+                               "http://127.0.0.1:1", "--skip-label", "keep", "--output", "json",
+                               "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(extra.exists());
+    // The overview of the torrent printed before the client was asked comes first
+    let stdout = stdout(&output);
+    let error = stdout.lines().last().unwrap_or_default();
+    let error: serde_json::Value = serde_json::from_str(error).expect("JSON error");
+    assert_eq!(error["error"]["code"], "client_failed");
+}
+
+#[test]
+fn batch_skips_the_torrents_held_back() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    fs::write(fixture.path("batch.tsv"), "show.torrent\tShow\n").expect("manifest written");
+    let server = MockServer::start(qbittorrent("keep", ""));
+    let output = fixture.run(&["batch", "--clean", "-f", "--client", "qbittorrent",
+                               "--client-url", &server.url, "--skip-label", "keep",
+                               "--output", "json", "batch.tsv"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(extra.exists());
+    let report: serde_json::Value = serde_json::from_str(&stdout(&output)).expect("JSON report");
+    let entry = &report["entries"][0];
+    assert_eq!(entry["status"], "skipped");
+    assert_eq!(entry["held_back"], "it carries the label keep (--skip-label)");
+    assert_eq!(entry["extras"], 1);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+#[test]
+fn the_torrents_directory_inside_the_one_given_is_scanned() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["diff", "show.torrent", "."]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains(&format!("holds none of the torrent's top-level entries; scanning {} \
+        instead (--flat to scan it as given)", fixture.content().display())), "{}", stdout);
+    assert!(stdout.contains("Found matching:   15 B in 2 files"), "{}", stdout);
+    assert!(stdout.contains("sub/extra.nfo"), "{}", stdout);
+}
+
+#[test]
+fn the_directory_around_the_one_given_is_scanned() {
+    // The torrent's paths start with its name, so the content is Show/Show/...
+    let fixture = Fixture::new(&[("Show/a.txt", b"hello"), ("Show/sub/b.bin", b"0123456789")]);
+    fixture.write("Show/sub/extra.nfo", b"x");
+    let output = fixture.run(&["diff", "show.torrent", "Show/Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains(&format!("scanning {} instead", fixture.content().display())),
+            "{}", stdout);
+    assert!(stdout.contains("Found matching:   15 B in 2 files"), "{}", stdout);
+    assert!(stdout.contains("Show/sub/extra.nfo"), "{}", stdout);
+}
+
+#[test]
+fn flat_scans_the_directory_as_given() {
+    let fixture = Fixture::new(&FILES);
+    for flag in ["--flat", "--no-autodetect"] {
+        let output = fixture.run(&["diff", flag, "show.torrent", "."]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        let stdout = stdout(&output);
+        assert!(!stdout.contains("scanning"), "{}", stdout);
+        assert!(stdout.contains("Found matching:   0 B in 0 files"), "{}", stdout);
+    }
+}
+
+#[test]
+fn files_both_in_the_directory_and_in_its_namesake_are_ambiguous() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("Show/a.txt", b"hello");
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains(&format!("Files of the torrent are both directly in {} and \
+        in {}, so the layout is ambiguous; pass --flat", fixture.content().display(),
+        fixture.content().join("Show").display())), "{}", stderr(&output));
+    assert!(fixture.run(&["clean", "-f", "show.torrent", "Show"]).status.code() == Some(2));
+    assert!(fixture.content().join("Show/a.txt").exists());
+
+    let output = fixture.run(&["diff", "--flat", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Found matching:   15 B in 2 files"), "{}", stdout(&output));
+    // Once the inner copy is gone there is nothing to choose between
+    fs::remove_dir_all(fixture.content().join("Show")).expect("inner copy removed");
+    assert!(fixture.run(&["diff", "show.torrent", "Show"]).status.success());
+}
@@ -0,0 +1,209 @@
+//! The scan and compare logic through the library API, against temporary directories.
+
+mod common;
+
+use librqbit_core::torrent_metainfo::torrent_from_bytes_ext;
+use librqbit_buffers::ByteBufOwned;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use torrent_cleaner::scan::{ExpectedSet, ScanOptions, SkipReason, Symlinks};
+use torrent_cleaner::{Scanner, TorrentFileSet};
+
+const FILES: [(&str, &[u8]); 3] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789"),
+                                   ("sub/deep/c.bin", b"abc")];
+
+/// The file set of the fixture torrent, read from its metainfo as a library user would.
+fn file_set() -> TorrentFileSet {
+    let buf = common::torrent("Show", &FILES);
+    let torrent = torrent_from_bytes_ext::<ByteBufOwned>(&buf).expect("torrent parsed");
+    TorrentFileSet::from_metainfo(&torrent.meta).expect("file set")
+}
+
+/// A temporary content directory with the torrent's files and `extra` ones.
+fn content(extra: &[(&str, &[u8])]) -> TempDir {
+    let dir = TempDir::new().expect("temporary directory");
+    for (path, bytes) in FILES.iter().chain(extra) {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().expect("in the directory")).expect("parent created");
+        fs::write(path, bytes).expect("file written");
+    }
+    dir
+}
+
+fn joined(dir: &Path, paths: &[&str]) -> Vec<PathBuf> {
+    paths.iter().map(|e| dir.join(e)).collect()
+}
+
+#[test]
+fn file_set_reads_the_torrent() {
+    let files = file_set();
+    assert_eq!(files.file_count(), 3);
+    assert_eq!(files.file_size(Path::new("sub/b.bin")), Some(10));
+    assert!(files.contains_dir(Path::new("sub/deep")));
+    assert!(!files.is_flat());
+}
+
+#[test]
+fn complete_directory_has_nothing_to_do() {
+    let dir = content(&[]);
+    let report = Scanner::new(dir.path()).scan(&file_set()).expect("scanned");
+    assert!(report.extras().is_empty());
+    assert!(report.missing().is_empty());
+    assert_eq!(report.plan().size, 0);
+}
+
+#[test]
+fn extras_and_missing_files_are_found() {
+    let dir = content(&[("sub/extra.nfo", b"x"), ("sub/deep/sample.mkv", b"sample")]);
+    fs::remove_file(dir.path().join("sub/deep/c.bin")).expect("file removed");
+    let options = ScanOptions { sorted: true, ..ScanOptions::default() };
+    let report = Scanner::new(dir.path()).with_options(options).scan(&file_set()).expect("scanned");
+    assert_eq!(report.extras(), joined(dir.path(), &["sub/deep/sample.mkv", "sub/extra.nfo"]));
+    assert_eq!(report.missing(), joined(dir.path(), &["sub/deep/c.bin"]));
+    assert_eq!(report.missing_size(), 3);
+    assert_eq!(report.plan().size, 7);
+}
+
+#[test]
+fn top_level_extras_need_surface() {
+    let dir = content(&[("stray.txt", b"stray"), ("Other/x.mkv", b"other")]);
+    let report = Scanner::new(dir.path()).scan(&file_set()).expect("scanned");
+    assert!(report.extras().is_empty());
+    let skipped = &report.details().skipped;
+    assert!(skipped.iter().all(|e| matches!(e.reason, SkipReason::OutOfScope)));
+    let mut skipped = skipped.iter().map(|e| e.path.clone()).collect::<Vec<PathBuf>>();
+    skipped.sort();
+    assert_eq!(skipped, joined(dir.path(), &["Other", "Other/x.mkv", "stray.txt"]));
+
+    let options = ScanOptions { include_surface: true, sorted: true, ..ScanOptions::default() };
+    let report = Scanner::new(dir.path()).with_options(options).scan(&file_set()).expect("scanned");
+    assert_eq!(report.extras(), joined(dir.path(), &["Other/x.mkv", "stray.txt"]));
+}
+
+#[test]
+fn file_of_another_size_is_not_an_extra() {
+    let dir = content(&[]);
+    fs::write(dir.path().join("sub/b.bin"), b"0123").expect("file rewritten");
+    let report = Scanner::new(dir.path()).scan(&file_set()).expect("scanned");
+    assert!(report.extras().is_empty());
+    assert!(report.missing().is_empty());
+}
+
+#[test]
+fn empty_directories_are_planned_children_first() {
+    let dir = content(&[("sub/old/notes.txt", b"notes")]);
+    fs::create_dir_all(dir.path().join("sub/empty/inner")).expect("directories created");
+    let options = ScanOptions { include_empty_dir: true, ..ScanOptions::default() };
+    let report = Scanner::new(dir.path()).with_options(options).scan(&file_set()).expect("scanned");
+    let plan = report.plan();
+    assert_eq!(plan.files, joined(dir.path(), &["sub/old/notes.txt"]));
+    assert_eq!(plan.dirs, joined(dir.path(), &["sub/old", "sub/empty/inner", "sub/empty"]));
+
+    assert_eq!(plan.execute().expect("executed"), 4);
+    assert!(!dir.path().join("sub/old").exists());
+    assert!(!dir.path().join("sub/empty").exists());
+    for (path, _) in FILES {
+        assert!(dir.path().join(path).exists(), "{}", path);
+    }
+}
+
+#[test]
+fn execute_keeps_a_directory_that_gained_an_entry() {
+    let dir = content(&[("sub/old/notes.txt", b"notes")]);
+    let options = ScanOptions { include_empty_dir: true, ..ScanOptions::default() };
+    let report = Scanner::new(dir.path()).with_options(options).scan(&file_set()).expect("scanned");
+    fs::write(dir.path().join("sub/old/new.txt"), b"new").expect("file written");
+    assert_eq!(report.plan().execute().expect("executed"), 1);
+    assert!(!dir.path().join("sub/old/notes.txt").exists());
+    assert!(dir.path().join("sub/old/new.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn hard_linked_extras_count_once() {
+    let dir = content(&[("sub/extra.bin", b"0123456789")]);
+    fs::hard_link(dir.path().join("sub/extra.bin"), dir.path().join("sub/link.bin"))
+        .expect("hard link");
+    let options = ScanOptions { sorted: true, ..ScanOptions::default() };
+    let report = Scanner::new(dir.path()).with_options(options).scan(&file_set()).expect("scanned");
+    assert_eq!(report.extras().len(), 2);
+    assert_eq!(report.plan().size, 10);
+    assert_eq!(report.details().aliases.len(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn extras_found_through_a_followed_link_count_once() {
+    let dir = content(&[("sub/other/extra.bin", b"0123456789")]);
+    std::os::unix::fs::symlink("other", dir.path().join("sub/link")).expect("symbolic link");
+    let options = ScanOptions { symlinks: Symlinks::Follow, sorted: true,
+                                ..ScanOptions::default() };
+    let report = Scanner::new(dir.path()).with_options(options).scan(&file_set()).expect("scanned");
+    assert_eq!(report.extras(), joined(dir.path(), &["sub/other/extra.bin"]));
+    assert_eq!(report.plan().size, 10);
+    assert_eq!(report.details().aliases, [(dir.path().join("sub/link/extra.bin"),
+                                           dir.path().join("sub/other/extra.bin"))]);
+    // The link itself is left alone, but not listed again for what is found through it
+    let skipped = report.details().skipped.iter().map(|e| e.path.clone()).collect::<Vec<_>>();
+    assert_eq!(skipped, joined(dir.path(), &["sub/link"]));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn async_scan_and_removal_match_the_blocking_ones() {
+    use std::sync::Arc;
+    use torrent_cleaner::nonblocking::{execute_plan, scan_async, scan_stream, ScanEvent};
+
+    let extra: [(&str, &[u8]); 3] = [("sub/extra.nfo", b"x"), ("sub/old/notes.txt", b"notes"),
+                                     ("sub/deep/sample.mkv", b"sample")];
+    let (blocking, nonblocking) = (content(&extra), content(&extra));
+    for dir in [&blocking, &nonblocking] {
+        fs::create_dir_all(dir.path().join("sub/empty/inner")).expect("directories created");
+        fs::remove_file(dir.path().join("a.txt")).expect("file removed");
+    }
+    let relative = |paths: &[PathBuf], dir: &TempDir| paths.iter()
+        .map(|e| e.strip_prefix(dir.path()).expect("inside").to_path_buf())
+        .collect::<Vec<PathBuf>>();
+    let options = ScanOptions { include_empty_dir: true, sorted: true, ..ScanOptions::default() };
+    let expected = Scanner::new(blocking.path()).with_options(options.clone()).scan(&file_set())
+        .expect("scanned");
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build().expect("runtime");
+    let scanner = Scanner::new(nonblocking.path()).with_options(options);
+    let files = Arc::new(file_set());
+    let report = runtime.block_on(scan_async(&scanner, files.clone())).expect("scanned");
+    assert_eq!(relative(report.extras(), &nonblocking), relative(expected.extras(), &blocking));
+    assert_eq!(relative(report.missing(), &nonblocking), relative(expected.missing(), &blocking));
+    let (plan, expected_plan) = (report.plan(), expected.plan());
+    assert_eq!(relative(&plan.files, &nonblocking), relative(&expected_plan.files, &blocking));
+    assert_eq!(relative(&plan.dirs, &nonblocking), relative(&expected_plan.dirs, &blocking));
+    assert_eq!(plan.size, expected_plan.size);
+
+    // The stream reports each extra once before the same report
+    let mut found = runtime.block_on(async {
+        let mut stream = scan_stream(&scanner, files);
+        let mut found = Vec::new();
+        while let Some(event) = stream.next().await {
+            match event {
+                ScanEvent::Extra(path) => found.push(path),
+                ScanEvent::Finished(finished) => {
+                    assert_eq!(finished.expect("scanned").extras(), report.extras());
+                }
+            }
+        }
+        found
+    });
+    found.sort();
+    assert_eq!(relative(&found, &nonblocking), relative(expected.extras(), &blocking));
+
+    assert_eq!(runtime.block_on(execute_plan(&plan, 2)).expect("executed"),
+               expected_plan.execute().expect("executed"));
+    for path in ["sub/extra.nfo", "sub/old", "sub/deep/sample.mkv", "sub/empty"] {
+        assert!(!blocking.path().join(path).exists(), "{}", path);
+        assert!(!nonblocking.path().join(path).exists(), "{}", path);
+    }
+    for (path, _) in &FILES[1..] {
+        assert!(nonblocking.path().join(path).exists(), "{}", path);
+    }
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use serde_json::Value;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The `diff --output json` document of the fixture with three extras, run with `args`.
+fn diff(args: &[&str]) -> Value {
+    let fixture = Fixture::new(&FILES);
+    for name in ["x.nfo", "y.nfo", "z.nfo"] {
+        fixture.write(&format!("sub/{}", name), b"x");
+    }
+    let mut all = vec!["diff", "--output", "json", "--limit", "2"];
+    all.extend(args);
+    all.extend(["show.torrent", "Show"]);
+    let output = fixture.run(&all);
+    assert!(output.status.code().is_some_and(|e| e < 2), "{}", stderr(&output));
+    serde_json::from_str(&stdout(&output)).expect("JSON report")
+}
+
+#[test]
+fn json_ignores_the_limit_by_default() {
+    let report = diff(&[]);
+    assert_eq!(report["extra_files"].as_array().map(Vec::len), Some(3));
+    assert_eq!(report["truncated"], false);
+}
+
+#[test]
+fn limit_json_cuts_the_lists_but_not_the_summary() {
+    let report = diff(&["--limit-json"]);
+    let paths = report["extra_files"].as_array().expect("extra files").iter()
+        .map(|e| e["path"].as_str().expect("path")).collect::<Vec<&str>>();
+    assert_eq!(paths, ["sub/x.nfo", "sub/y.nfo"]);
+    assert_eq!(report["truncated"], true);
+    assert_eq!(report["summary"]["extra_files"], 3);
+    assert_eq!(report["summary"]["extra_size"], 3);
+}
+
+#[test]
+fn limit_json_within_the_limit_is_not_truncated() {
+    let report = diff(&["--limit-json", "--show-all"]);
+    assert_eq!(report["extra_files"].as_array().map(Vec::len), Some(3));
+    assert_eq!(report["truncated"], false);
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use common::{stderr, stdout, torrent, Fixture};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The info dict of the fixture's torrent, as the torrent file holds it.
+fn info() -> Vec<u8> {
+    let torrent = torrent("Show", &FILES);
+    torrent[b"d4:info".len()..torrent.len() - 1].to_vec()
+}
+
+fn info_hash(info: &[u8]) -> String {
+    crypto_hash::hex_digest(crypto_hash::Algorithm::SHA1, info)
+}
+
+fn read_message(stream: &mut TcpStream) -> Vec<u8> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).expect("message length");
+    let mut message = vec![0; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut message).expect("message");
+    message
+}
+
+fn send_extended(stream: &mut TcpStream, id: u8, payload: &[u8]) {
+    let mut message = (payload.len() as u32 + 2).to_be_bytes().to_vec();
+    message.extend_from_slice(&[20, id]);
+    message.extend_from_slice(payload);
+    stream.write_all(&message).expect("message sent");
+}
+
+/// A peer on localhost that hands out `metadata` in one piece to whoever asks for it, whatever
+/// the info hash asked for; returns its address.
+fn peer(metadata: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("listening");
+    let addr = listener.local_addr().expect("bound").to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut handshake = [0; 68];
+            stream.read_exact(&mut handshake).expect("handshake");
+            assert_ne!(handshake[25] & 0x10, 0, "extension protocol");
+            stream.write_all(&handshake).expect("handshake answered");
+            let message = read_message(&mut stream);
+            assert_eq!(message[..2], [20, 0], "extension handshake");
+            send_extended(&mut stream, 0, format!("d1:md11:ut_metadatai3ee13:metadata_sizei{}ee",
+                                                  metadata.len()).as_bytes());
+            let request = read_message(&mut stream);
+            assert_eq!(request, b"\x14\x03d8:msg_typei0e5:piecei0ee", "metadata request");
+            let mut piece = format!("d8:msg_typei1e5:piecei0e10:total_sizei{}ee", metadata.len())
+                .into_bytes();
+            piece.extend_from_slice(&metadata);
+            send_extended(&mut stream, 1, &piece);
+        }
+    });
+    addr
+}
+
+#[test]
+fn metadata_is_fetched_from_a_peer_and_cached() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let info = info();
+    let magnet = format!("magnet:?xt=urn:btih:{}&dn=Show&x.pe={}", info_hash(&info),
+                         peer(info.clone()));
+    let output = fixture.run(&["diff", "--no-dht", "--save-torrent", "saved.torrent", &magnet,
+                               "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("extra.nfo"), "{}", stdout(&output));
+    let saved = std::fs::read(fixture.path("saved.torrent")).expect("torrent saved");
+    assert_eq!(saved, torrent("Show", &FILES));
+    // The second run finds it in the cache, without asking anyone
+    let output = fixture.run(&["diff", "--metadata-timeout", "0", &info_hash(&info), "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("extra.nfo"), "{}", stdout(&output));
+}
+
+#[test]
+fn metadata_of_another_hash_is_refused() {
+    let fixture = Fixture::new(&FILES);
+    let info = info();
+    let other = torrent("Other", &FILES);
+    let magnet = format!("magnet:?xt=urn:btih:{}&x.pe={}", info_hash(&info),
+                         peer(other[7..other.len() - 1].to_vec()));
+    let output = fixture.run(&["diff", "--no-dht", &magnet, "Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("none of the 1 peers found shared it"),
+            "{}", stderr(&output));
+}
+
+#[test]
+fn fetching_gives_up_after_the_timeout() {
+    let fixture = Fixture::new(&FILES);
+    // A peer that takes the connection but never answers the handshake
+    let listener = TcpListener::bind("127.0.0.1:0").expect("listening");
+    let magnet = format!("magnet:?xt=urn:btih:{}&x.pe={}", info_hash(&info()),
+                         listener.local_addr().expect("bound"));
+    let output = fixture.run(&["diff", "--no-dht", "--metadata-timeout", "1s", &magnet, "Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Timed out after 1s fetching metadata"),
+            "{}", stderr(&output));
+}
+
+#[test]
+fn nothing_is_fetched_without_a_place_to_fetch_it_from() {
+    let fixture = Fixture::new(&FILES);
+    let hash = info_hash(&info());
+    let output = fixture.run(&["diff", "--no-dht", &hash, "Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("nowhere to fetch it from"), "{}", stderr(&output));
+    let output = fixture.run(&["diff", "--metadata-timeout", "0", &hash, "Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("--metadata-timeout 0 fetches none"), "{}", stderr(&output));
+}
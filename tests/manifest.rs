@@ -0,0 +1,56 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use serde_json::Value;
+use std::fs;
+
+const FILES: [(&str, &[u8]); 4] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789"),
+                                   ("sub/dé jà.mkv", b"video"), ("sub/missing.srt", b"subs")];
+
+/// The JSON diff of the fixture against `source`, the torrent or `--manifest` with its path.
+fn diff(fixture: &Fixture, source: &[&str]) -> Value {
+    let mut args = vec!["diff", "--output", "json"];
+    args.extend(source);
+    args.push("Show");
+    let output = fixture.run(&args);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    let line = stdout.lines().find(|e| e.starts_with('{')).expect("JSON report");
+    serde_json::from_str(line).expect("JSON report")
+}
+
+/// The paths and sizes of the entries of a diff list, without the ids, which hash the source.
+fn entries(report: &Value, list: &str) -> Vec<(String, Value)> {
+    report[list].as_array().expect("list").iter()
+        .map(|e| (e["path"].as_str().expect("path").to_string(), e["size"].clone()))
+        .collect()
+}
+
+#[test]
+fn listed_manifest_plans_like_the_torrent() {
+    let fixture = Fixture::new(&FILES);
+    fs::remove_file(fixture.content().join("sub/missing.srt")).expect("file removed");
+    fixture.write("a.txt", b"hello, world");
+    fixture.write("sub/extra.nfo", b"x");
+    fixture.write("sub/old/sample.mkv", b"sample");
+
+    let output = fixture.run(&["list", "--output", "tsv", "show.torrent"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let listing = stdout(&output);
+    let listing: String = listing.lines().filter(|e| e.contains('\t'))
+        .map(|e| format!("{}\n", e)).collect();
+    assert_eq!(listing.lines().count(), FILES.len(), "{}", listing);
+    fs::write(fixture.path("show.tsv"), listing).expect("manifest written");
+
+    let torrent = diff(&fixture, &["show.torrent"]);
+    let manifest = diff(&fixture, &["--manifest", "show.tsv"]);
+    for list in ["missing", "mismatched", "extra_files", "extra_dirs", "emptied_dirs"] {
+        assert_eq!(entries(&torrent, list), entries(&manifest, list), "{}", list);
+    }
+    assert_eq!(entries(&torrent, "missing").len(), 1);
+    assert_eq!(entries(&torrent, "mismatched").len(), 1);
+    assert_eq!(entries(&torrent, "extra_files").len(), 2);
+    for field in ["expected_files", "expected_size", "matching_files", "extra_size", "freed_size"] {
+        assert_eq!(torrent["summary"][field], manifest["summary"][field], "{}", field);
+    }
+}
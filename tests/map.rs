@@ -0,0 +1,27 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+#[test]
+fn a_renamed_top_level_folder_is_expected_under_its_new_name() {
+    let fixture = Fixture::new(&FILES);
+    fs::rename(fixture.content().join("sub"), fixture.content().join("Renamed"))
+        .expect("folder renamed");
+    let output = fixture.run(&["diff", "--surface", "--map", "sub=Renamed", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Directory is clean: all 2 files match"),
+            "{}", stdout(&output));
+}
+
+#[test]
+fn a_folder_the_torrent_does_not_have_is_a_warning() {
+    let fixture = Fixture::new(&FILES);
+    let output = fixture.run(&["diff", "--deny-warnings", "--map", "nope=Other", "show.torrent",
+                               "Show"]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Warning: --map nope: the torrent has no top-level entry of \
+                                      that name"), "{}", stderr(&output));
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+#[test]
+fn directory_below_the_cutoff_is_removed_as_a_whole() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("extra/deeper/notes.txt", b"notes");
+    fixture.write("extra/cover.jpg", b"jpg");
+    let output = fixture.run(&["clean", "-s", "-f", "--max-depth", "1", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fixture.content().join("extra").exists(), "{}", stdout(&output));
+    assert!(fixture.content().join("sub/b.bin").exists());
+}
+
+#[test]
+fn excluded_file_below_the_cutoff_keeps_its_directory() {
+    let fixture = Fixture::new(&FILES);
+    let notes = fixture.write("extra/notes.nfo", b"notes");
+    let cover = fixture.write("extra/cover.jpg", b"jpg");
+    let output = fixture.run(&["clean", "-s", "-f", "--max-depth", "1", "--exclude", "*.nfo",
+                               "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(notes.exists(), "{}", stdout(&output));
+    // The directory is kept whole rather than emptied around the excluded file
+    assert!(cover.exists(), "{}", stdout(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("beyond --max-depth"), "{}", stdout);
+    assert!(stdout.contains("excluded"), "{}", stdout);
+}
+
+#[test]
+fn keep_list_below_the_cutoff_keeps_its_directory() {
+    let fixture = Fixture::new(&FILES);
+    let kept = fixture.write("extra/deeper/kept.txt", b"kept");
+    std::fs::write(fixture.path("keep.txt"), "extra/deeper/kept.txt\n").expect("keep list");
+    let output = fixture.run(&["clean", "-s", "-f", "--max-depth", "1", "--keep-from",
+                               "keep.txt", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(kept.exists(), "{}", stdout(&output));
+}
+
+#[test]
+fn file_added_below_the_cutoff_after_the_plan_stops_its_removal() {
+    let fixture = Fixture::new(&FILES);
+    let cover = fixture.write("extra/deeper/cover.jpg", b"jpg");
+    let output = fixture.run(&["plan", "--max-depth", "1", "--surface", "--out", "plan.json",
+                               "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    // Empty and one level down, so neither the size nor the time of the directory changes
+    let added = fixture.write("extra/deeper/added.txt", b"");
+    let output = fixture.run(&["apply", "-f", "plan.json", "show.torrent", "Show"]);
+    assert!(added.exists() && cover.exists(), "{}{}", stdout(&output), stderr(&output));
+    assert!(stderr(&output).contains("changed since plan"), "{}", stderr(&output));
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{stderr, stdout, v1_files, Bencode, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The fixture with `show.torrent` replaced by one of meta version 3, with the v1 file list and
+/// pieces of the fixture's if `v1`, and a made-up tree of the newer version either way.
+fn version_3(v1: bool) -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    let mut info = vec![
+        ("name", Bencode::str("Show")),
+        ("piece length", Bencode::Int(common::PIECE_LENGTH)),
+        ("meta version", Bencode::Int(3)),
+        ("file tree v3", Bencode::dict([("a.txt", Bencode::Int(5))])),
+    ];
+    if v1 {
+        info.push(("pieces", Bencode::Bytes(vec![0; 20])));
+        info.push(("files", v1_files(&FILES)));
+    }
+    fs::write(fixture.torrent(), Bencode::dict([("info", Bencode::dict(info))]).encode())
+        .expect("torrent written");
+    fixture
+}
+
+#[test]
+fn hybrid_of_a_newer_version_points_to_v1_only() {
+    let fixture = version_3(true);
+    fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["diff", "--output", "json", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stdout = stdout(&output);
+    let error: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap_or_default())
+        .expect("JSON error");
+    assert_eq!(error["error"]["code"], "torrent_unsupported");
+    let message = error["error"]["message"].as_str().expect("message");
+    assert!(message.contains("meta version 3"), "{}", message);
+    assert!(message.contains("--v1-only"), "{}", message);
+}
+
+#[test]
+fn v1_only_reads_the_v1_part() {
+    let fixture = version_3(true);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["clean", "-f", "--v1-only", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let printed = stdout(&output) + &stderr(&output);
+    assert!(printed.contains("uses meta version 3, which is not supported; only its v1 part is \
+                              read"), "{}", printed);
+    assert!(!extra.exists());
+    assert!(fixture.content().join("sub/b.bin").exists());
+}
+
+#[test]
+fn newer_version_without_v1_fails_even_with_v1_only() {
+    let fixture = version_3(false);
+    let output = fixture.run(&["diff", "--v1-only", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("it has no v1 part to fall back to"), "{}", stderr(&output));
+}
@@ -0,0 +1,99 @@
+//! `--only-owned` and `--owner`. Entries of another user are made by changing their owner, which
+//! takes root; the tests that need one are skipped otherwise.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+use std::os::unix::fs::{chown, MetadataExt};
+use std::process::Output;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// A user id no test runs as.
+const OTHER: u32 = 4242;
+
+/// The user the fixture's files belong to, the one running the tests.
+fn uid(fixture: &Fixture) -> u32 {
+    fs::metadata(fixture.root.path()).expect("fixture exists").uid()
+}
+
+/// Gives `path` under the content directory to [`OTHER`], if running as root.
+fn give_away(fixture: &Fixture, path: &str) -> bool {
+    match chown(fixture.content().join(path), Some(OTHER), None) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("skipped: cannot change the owner of a file ({})", e);
+            false
+        }
+    }
+}
+
+/// Whether the Skipped table of `output` has a row for `reason`.
+fn skipped_for(output: &Output, reason: &str) -> bool {
+    stdout(output).lines().skip_while(|e| *e != "Skipped:")
+        .any(|e| e.trim_start().starts_with(reason))
+}
+
+#[test]
+fn owner_lists_the_extras_of_other_users_as_skipped() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let other = (uid(&fixture) + 1).to_string();
+    let output = fixture.run(&["diff", "--owner", &other, "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let listing = stdout(&output);
+    assert!(listing.contains("Owned by others, skipped (1):\n    sub/extra.nfo"), "{}", listing);
+    assert!(listing.contains("Remove entries: 0 B (0)"), "{}", listing);
+    let output = fixture.run(&["clean", "-f", "--owner", &other, "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(skipped_for(&output, "owned by others"), "{}", stdout(&output));
+    assert!(fixture.content().join("sub/extra.nfo").exists());
+}
+
+#[test]
+fn owner_by_uid_removes_the_extras_of_that_user() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["clean", "-f", "--owner", &uid(&fixture).to_string(),
+                               "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fixture.content().join("sub/extra.nfo").exists());
+    assert!(!skipped_for(&output, "owned by others"), "{}", stdout(&output));
+}
+
+#[test]
+fn owner_must_be_a_known_user() {
+    let fixture = Fixture::new(&FILES);
+    let output = fixture.run(&["diff", "--owner", "no-such-user-here", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("No such user: no-such-user-here"), "{}", stderr(&output));
+}
+
+#[test]
+fn only_owned_conflicts_with_owner() {
+    let fixture = Fixture::new(&FILES);
+    let output = fixture.run(&["diff", "--only-owned", "--owner", "0", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("cannot be used with"), "{}", stderr(&output));
+}
+
+#[test]
+fn only_owned_leaves_the_entries_of_others_and_their_directories() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/mine.nfo", b"x");
+    fixture.write("extras/theirs.nfo", b"y");
+    if !give_away(&fixture, "extras/theirs.nfo") {
+        return;
+    }
+    let output = fixture.run(&["clean", "-f", "--only-owned", "--surface", "--empty-dir",
+                               "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fixture.content().join("sub/mine.nfo").exists());
+    assert!(fixture.content().join("extras/theirs.nfo").exists());
+    assert!(skipped_for(&output, "owned by others"), "{}", stdout(&output));
+    // The directory stays for the entry it holds, and says so
+    assert!(stdout(&output).contains("holds an entry owned by others"), "{}", stdout(&output));
+}
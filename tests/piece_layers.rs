@@ -0,0 +1,105 @@
+//! `info` on v2 torrents with and without their piece layers.
+
+mod common;
+
+use common::{stderr, stdout, Bencode, Fixture, PIECE_LENGTH};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Three pieces, so that the file needs a piece layer to be verified piece by piece.
+const BIG: i64 = 2 * PIECE_LENGTH + 100;
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    crypto_hash::digest(crypto_hash::Algorithm::SHA256, data)
+}
+
+/// The piece layer of the big file: a made-up hash per piece.
+fn layer() -> Vec<u8> {
+    (0..3u8).flat_map(|e| sha256(&[e])).collect()
+}
+
+/// The Merkle root of [`layer`]: its three hashes padded to four with the hash of an empty
+/// piece, which with pieces of one block is 32 zero bytes.
+fn pieces_root() -> Vec<u8> {
+    let hashes = layer().chunks(32).map(<[u8]>::to_vec).collect::<Vec<Vec<u8>>>();
+    let pair = |a: &[u8], b: &[u8]| sha256(&[a, b].concat());
+    pair(&pair(&hashes[0], &hashes[1]), &pair(&hashes[2], &[0; 32]))
+}
+
+/// `piece layers`, holding `layer` for the big file.
+fn piece_layers(layer: Vec<u8>) -> Bencode {
+    Bencode::Dict(BTreeMap::from([(pieces_root(), Bencode::Bytes(layer))]))
+}
+
+/// A v2 torrent of a big file and a small one, with `layers` embedded.
+fn torrent(layers: Option<Vec<u8>>) -> Vec<u8> {
+    let file = |length: i64, root: Vec<u8>| Bencode::dict([("", Bencode::dict([
+        ("length", Bencode::Int(length)),
+        ("pieces root", Bencode::Bytes(root)),
+    ]))]);
+    let info = Bencode::dict([
+        ("name", Bencode::str("Show")),
+        ("piece length", Bencode::Int(PIECE_LENGTH)),
+        ("meta version", Bencode::Int(2)),
+        ("file tree", Bencode::dict([("big.bin", file(BIG, pieces_root())),
+                                     ("small.txt", file(5, sha256(b"small")))])),
+    ]);
+    let mut root = vec![("info", info)];
+    if let Some(layer) = layers {
+        root.push(("piece layers", piece_layers(layer)));
+    }
+    Bencode::dict(root).encode()
+}
+
+/// `info` of the fixture with its torrent replaced by [`torrent`] of `layers`, with `args`.
+fn info(layers: Option<Vec<u8>>, args: &[&str]) -> (String, String) {
+    let fixture = Fixture::new(&[("small.txt", b"small")]);
+    fs::write(fixture.torrent(), torrent(layers)).expect("torrent written");
+    let mut all = vec!["info"];
+    all.extend(args);
+    all.push("show.torrent");
+    let output = fixture.run(&all);
+    assert!(output.status.success(), "{}", stderr(&output));
+    (stdout(&output), stderr(&output))
+}
+
+#[test]
+fn embedded_layers_verify_by_piece() {
+    let (stdout, stderr) = info(Some(layer()), &[]);
+    assert!(stdout.contains("Piece layers: embedded"), "{}", stdout);
+    assert!(stdout.contains("Verifiable: 1 files by piece, 1 in a single piece, 0 only as a whole"),
+            "{}", stdout);
+    assert!(!stderr.contains("piece layer"), "{}", stderr);
+}
+
+#[test]
+fn missing_layers_degrade_to_the_whole_file() {
+    let (stdout, stderr) = info(None, &[]);
+    assert!(stdout.contains("Piece layers: none"), "{}", stdout);
+    assert!(stdout.contains("Verifiable: 0 files by piece, 1 in a single piece, 1 only as a whole"),
+            "{}", stdout);
+    assert!(stderr.contains("big.bin has no piece layer; it can only be verified as a whole"),
+            "{}", stderr);
+}
+
+#[test]
+fn external_layers_fill_in() {
+    let fixture = Fixture::new(&[]);
+    let layers = fixture.path("layers.bencode");
+    fs::write(&layers, Bencode::dict([("piece layers", piece_layers(layer()))]).encode())
+        .expect("layers written");
+    let (stdout, _) = info(None, &["--piece-layers", &layers.to_string_lossy()]);
+    assert!(stdout.contains("Piece layers: from "), "{}", stdout);
+    assert!(stdout.contains("1 files by piece"), "{}", stdout);
+}
+
+#[test]
+fn mismatched_layer_is_reported() {
+    let mut wrong = layer();
+    wrong[0] ^= 1;
+    let (stdout, stderr) = info(Some(wrong), &[]);
+    assert!(stdout.contains("0 files by piece, 1 in a single piece, 1 only as a whole"), "{}",
+            stdout);
+    assert!(stderr.contains("big.bin has a piece layer that does not match its pieces root"),
+            "{}", stderr);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use common::{stderr, Fixture};
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+#[test]
+fn a_remote_directory_without_a_path_is_refused() {
+    let fixture = Fixture::new(&FILES);
+    let output = fixture.run(&["diff", "show.torrent", "sftp://user@example.com"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Invalid remote directory sftp://user@example.com"),
+            "{}", stderr(&output));
+}
+
+#[test]
+fn a_refused_connection_is_reported() {
+    let fixture = Fixture::new(&FILES);
+    let output = fixture.run(&["diff", "show.torrent", "sftp://user@127.0.0.1:1/srv/Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Failed to connect to sftp://user@127.0.0.1:1/srv/Show"),
+            "{}", stderr(&output));
+}
+
+#[test]
+fn local_only_options_are_refused_before_connecting() {
+    let fixture = Fixture::new(&FILES);
+    // Port 1 is never listened on, so getting past the options would fail to connect instead
+    let output = fixture.run(&["clean", "--trash", "show.torrent",
+                               "sftp://user@127.0.0.1:1/srv/Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("--trash is not supported for a remote directory"),
+            "{}", stderr(&output));
+    let output = fixture.run(&["diff", "--symlinks", "follow", "show.torrent",
+                               "sftp://user@127.0.0.1:1/srv/Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("Failed to connect"), "{}", stderr(&output));
+}
+
+#[test]
+fn other_commands_refuse_a_remote_directory() {
+    let fixture = Fixture::new(&FILES);
+    let output = fixture.run(&["verify", "show.torrent", "sftp://user@127.0.0.1:1/srv/Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("only diff and clean support"), "{}", stderr(&output));
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The fixture with `sub/b.bin` moved to `moved.bin`, at the top where only `--surface` looks.
+fn moved() -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    fs::rename(fixture.content().join("sub/b.bin"), fixture.content().join("moved.bin"))
+        .expect("file moved");
+    fixture
+}
+
+#[test]
+fn probable_renames_are_listed_and_fixed() {
+    let fixture = moved();
+    let output = fixture.run(&["diff", "--surface", "show.torrent", "Show"]);
+    assert!(stdout(&output).contains("moved.bin"), "{}", stdout(&output));
+    let output = fixture.run(&["clean", "-f", "--surface", "--fix-renames", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Moved 1 files back to where the torrent has them."),
+            "{}", stdout(&output));
+    assert_eq!(fs::read(fixture.content().join("sub/b.bin")).expect("moved back"), b"0123456789");
+    assert!(!fixture.content().join("moved.bin").exists());
+}
+
+#[test]
+fn a_rename_that_cannot_be_moved_back_is_a_warning() {
+    let fixture = moved();
+    // Its directory cannot be made where a file is
+    fs::remove_dir(fixture.content().join("sub")).expect("directory removed");
+    fs::write(fixture.content().join("sub"), b"").expect("file written");
+    let output = fixture.run(&["clean", "-f", "--surface", "--fix-renames", "--deny-warnings",
+                               "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Warning: failed to move moved.bin back to sub/b.bin"),
+            "{}", stderr(&output));
+    assert!(fixture.content().join("moved.bin").exists());
+}
+
+#[test]
+fn relink_links_the_rename_into_the_torrent_and_keeps_it() {
+    let fixture = moved();
+    let output = fixture.run(&["clean", "-f", "--surface", "--relink", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Relinked 1 files into the torrent"), "{}", stdout(&output));
+    assert_eq!(fs::read(fixture.content().join("sub/b.bin")).expect("linked"), b"0123456789");
+    assert!(fixture.content().join("moved.bin").exists());
+}
+
+#[test]
+fn a_rename_that_cannot_be_linked_is_a_warning() {
+    let fixture = moved();
+    fs::remove_dir(fixture.content().join("sub")).expect("directory removed");
+    fs::write(fixture.content().join("sub"), b"").expect("file written");
+    let output = fixture.run(&["clean", "-f", "--surface", "--relink", "--deny-warnings",
+                               "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Warning: failed to link moved.bin to sub/b.bin"),
+            "{}", stderr(&output));
+    assert!(fixture.content().join("moved.bin").exists());
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path).and_then(|e| e.modified()).expect("modification time")
+}
+
+/// The fixture with the extras `sub/x.nfo` and `sub/y.nfo` staged by `clean --stage`.
+fn staged() -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/x.nfo", b"x");
+    fixture.write("sub/y.nfo", b"y");
+    let output = fixture.run(&["clean", "-f", "--stage", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fixture.content().join("sub/x.nfo").exists(), "{}", stdout(&output));
+    fixture
+}
+
+#[test]
+fn restore_undoes_a_staged_clean() {
+    let fixture = Fixture::new(&FILES);
+    let extras = [fixture.write("sub/x.nfo", b"x"), fixture.write("sub/y.nfo", b"y")];
+    let times = extras.each_ref().map(|e| modified(e));
+    let output = fixture.run(&["clean", "-f", "--stage", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(extras.iter().all(|e| !e.exists()));
+
+    let output = fixture.run(&["restore", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("2 restored, 0 skipped as existing"), "{}", stdout);
+    assert!(stdout.contains("Removed the now empty run"), "{}", stdout);
+    assert_eq!(fs::read(&extras[0]).expect("restored"), b"x");
+    assert_eq!(fs::read(&extras[1]).expect("restored"), b"y");
+    assert_eq!(extras.each_ref().map(|e| modified(e)), times);
+}
+
+#[test]
+fn include_restores_part_of_a_run() {
+    let fixture = staged();
+    let output = fixture.run(&["restore", "--include", "x.nfo", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(fixture.content().join("sub/x.nfo").exists());
+    assert!(!fixture.content().join("sub/y.nfo").exists());
+
+    let output = fixture.run(&["restore", "--include", "sub/*.nfo", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(fixture.content().join("sub/y.nfo").exists(), "{}", stdout(&output));
+}
+
+#[test]
+fn existing_files_need_overwrite() {
+    let fixture = staged();
+    let x = fixture.write("sub/x.nfo", b"new");
+    let output = fixture.run(&["restore", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("1 restored, 1 skipped as existing"), "{}", stdout(&output));
+    assert_eq!(fs::read(&x).expect("kept"), b"new");
+
+    let output = fixture.run(&["restore", "--overwrite", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fs::read(&x).expect("restored"), b"x");
+}
+
+#[test]
+fn unknown_run_is_an_error() {
+    let fixture = staged();
+    let output = fixture.run(&["restore", "--run", "nonexistent", "Show"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("No staged run named nonexistent"), "{}", stderr(&output));
+}
@@ -0,0 +1,50 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{stderr, Fixture};
+use std::fs;
+use std::os::unix::fs::symlink;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+#[test]
+fn link_to_prunes_only_its_own_links() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let review = fixture.path("review");
+    // What the user keeps in the review directory: a link elsewhere and an empty directory
+    fs::create_dir_all(review.join("mine/empty")).unwrap();
+    fs::write(fixture.path("notes.txt"), b"notes").unwrap();
+    symlink(fixture.path("notes.txt"), review.join("mine/notes")).unwrap();
+
+    let output = fixture.run(&["clean", "--link-to", "review", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let link = review.join("sub/extra.nfo");
+    assert_eq!(fs::read_link(&link).unwrap().file_name(), extra.file_name());
+
+    // Once the extra is gone its link is stale
+    fs::remove_file(&extra).unwrap();
+    let output = fixture.run(&["clean", "--link-to", "review", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(fs::symlink_metadata(&link).is_err());
+    assert!(!review.join("sub").exists());
+    assert!(fs::symlink_metadata(review.join("mine/notes")).is_ok());
+    assert!(review.join("mine/empty").is_dir());
+}
+
+#[test]
+fn link_to_refuses_to_replace_a_foreign_link() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let review = fixture.path("review");
+    fs::create_dir_all(review.join("sub")).unwrap();
+    fs::write(fixture.path("notes.txt"), b"notes").unwrap();
+    symlink(fixture.path("notes.txt"), review.join("sub/extra.nfo")).unwrap();
+
+    let output = fixture.run(&["clean", "--link-to", "review", "show.torrent", "Show"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Refusing to replace a link not made by a review"),
+            "{}", stderr(&output));
+    assert!(fs::read_link(review.join("sub/extra.nfo")).unwrap().ends_with("notes.txt"));
+}
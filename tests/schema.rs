@@ -0,0 +1,99 @@
+//! The JSON the binary prints validates against the schema of `--schema`, which names every
+//! field it prints.
+
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use serde_json::Value;
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The definition of `name` in the schema, with objects closed to properties it does not name,
+/// so that a field missing from the schema fails too.
+fn definition(fixture: &Fixture, name: &str) -> Value {
+    fn close(value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                if object.contains_key("properties") && !object.contains_key("additionalProperties") {
+                    object.insert("additionalProperties".to_string(), Value::Bool(false));
+                }
+                object.values_mut().for_each(close);
+            }
+            Value::Array(array) => array.iter_mut().for_each(close),
+            _ => {}
+        }
+    }
+    let output = fixture.run(&["--schema"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let schema: Value = serde_json::from_str(&stdout(&output)).expect("JSON schema");
+    let mut definition = schema["$defs"][name].clone();
+    assert!(definition.is_object(), "no definition of {}", name);
+    close(&mut definition);
+    definition
+}
+
+/// Asserts that the last line `args` print validates against the definition of `name`.
+fn assert_valid(fixture: &Fixture, name: &str, args: &[&str]) {
+    let output = fixture.run(args);
+    let stdout = stdout(&output);
+    let document: Value = serde_json::from_str(stdout.lines().last().unwrap_or_default())
+        .unwrap_or_else(|e| panic!("{:?} printed no JSON ({}): {}{}", args, e, stdout,
+                                   stderr(&output)));
+    let validator = jsonschema::validator_for(&definition(fixture, name)).expect("valid schema");
+    let errors = validator.iter_errors(&document)
+        .map(|e| format!("{} at {}", e, e.instance_path())).collect::<Vec<String>>();
+    assert!(errors.is_empty(), "{:?} against {}: {:#?}", args, name, errors);
+}
+
+/// The fixture with an extra, an empty directory and a missing file, so that the lists are not
+/// empty.
+fn fixture() -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    fs::create_dir(fixture.content().join("sub/empty")).expect("directory created");
+    fs::remove_file(fixture.content().join("a.txt")).expect("file removed");
+    fixture
+}
+
+#[test]
+fn diff_output_is_valid() {
+    assert_valid(&fixture(), "diff",
+                 &["diff", "--empty-dir", "--output", "json", "show.torrent", "Show"]);
+}
+
+#[test]
+fn summaries_are_valid() {
+    let fixture = fixture();
+    assert_valid(&fixture, "summary", &["diff", "--summary-json", "show.torrent", "Show"]);
+    assert_valid(&fixture, "summary",
+                 &["clean", "-f", "--empty-dir", "--summary-json", "show.torrent", "Show"]);
+}
+
+#[test]
+fn errors_are_valid() {
+    assert_valid(&fixture(), "error", &["diff", "--output", "json", "show.torrent", "Elsewhere"]);
+}
+
+#[test]
+fn batch_output_is_valid() {
+    let fixture = fixture();
+    fs::write(fixture.path("batch.tsv"), "show.torrent\tShow\nshow.torrent\tElsewhere\n")
+        .expect("manifest written");
+    assert_valid(&fixture, "batch", &["batch", "--output", "json", "batch.tsv"]);
+}
+
+#[test]
+fn history_is_valid() {
+    let fixture = fixture();
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_valid(&fixture, "history", &["history", "--output", "json"]);
+}
+
+#[test]
+fn list_and_stats_are_valid() {
+    let fixture = fixture();
+    assert_valid(&fixture, "list", &["list", "--output", "json", "show.torrent"]);
+    assert_valid(&fixture, "stats", &["stats", "--output", "json", "show.torrent", "Show"]);
+}
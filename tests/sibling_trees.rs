@@ -0,0 +1,86 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("sub/a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The fixture with the torrent's content moved from `Show` into `Library` among the content
+/// of two other torrents, and with `Library/Show` made if `named`.
+fn library(named: bool) -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    fs::create_dir(fixture.path("Library")).expect("library created");
+    fs::rename(fixture.content().join("sub"), fixture.path("Library/sub")).expect("content moved");
+    fs::remove_dir(fixture.content()).expect("content directory removed");
+    for (path, content) in [("Library/Other/x.mkv", b"other"), ("Library/Another/y.mkv", b"again")] {
+        let path = fixture.path(path);
+        fs::create_dir_all(path.parent().expect("in the library")).expect("tree created");
+        fs::write(path, content).expect("file written");
+    }
+    if named {
+        fs::create_dir(fixture.path("Library/Show")).expect("named directory created");
+    }
+    fixture
+}
+
+#[test]
+fn library_warns_about_its_other_trees() {
+    let fixture = library(false);
+    let output = fixture.run(&["diff", "show.torrent", "Library"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("holds 2 top-level directories outside the torrent, e.g. Another; \
+                             --surface would remove them with everything inside."), "{}", stdout);
+    assert!(stdout.contains("Pass the torrent's content directory, usually named Show."),
+            "{}", stdout);
+}
+
+#[test]
+fn library_suggests_the_directory_named_after_the_torrent() {
+    let fixture = library(true);
+    let output = fixture.run(&["diff", "--output", "json", "show.torrent", "Library"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let suggested = fixture.path("Library/Show");
+    assert!(stderr(&output).contains(&format!("Did you mean {}?", suggested.display())),
+            "{}", stderr(&output));
+    let stdout = stdout(&output);
+    let report = stdout.lines().find(|e| e.starts_with('{')).expect("JSON report");
+    let report: serde_json::Value = serde_json::from_str(report).expect("JSON report");
+    let warnings = report["summary"]["warnings"].as_array().expect("warnings");
+    assert!(warnings.iter().any(|e| e["code"] == "sibling_trees"), "{:?}", warnings);
+}
+
+#[test]
+fn named_directory_does_not_warn() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("Other/x.mkv", b"other");
+    fixture.write("Another/y.mkv", b"again");
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stdout(&output).contains("outside the torrent, e.g."), "{}", stdout(&output));
+}
+
+#[test]
+fn surface_in_a_library_needs_force() {
+    let fixture = library(false);
+    let output = fixture.run(&["clean", "-f", "--surface", "show.torrent", "Library"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("is not named after the torrent (Show); add --force to clean \
+                                      it with --surface"), "{}", stderr(&output));
+    assert!(fixture.path("Library/Other/x.mkv").exists());
+
+    let output = fixture.run(&["clean", "-f", "--surface", "--force", "show.torrent", "Library"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fixture.path("Library/Other/x.mkv").exists(), "{}", stdout(&output));
+    assert!(!fixture.path("Library/Another/y.mkv").exists(), "{}", stdout(&output));
+    assert!(fixture.path("Library/sub/a.txt").exists());
+}
+
+#[test]
+fn clean_without_surface_leaves_the_library_alone() {
+    let fixture = library(false);
+    let output = fixture.run(&["clean", "-f", "show.torrent", "Library"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(fixture.path("Library/Other/x.mkv").exists());
+    assert!(fixture.path("Library/Another/y.mkv").exists());
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use common::{age, stderr, stdout, Fixture};
+use serde_json::Value;
+use std::time::Duration;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+/// Extras in an order neither creation nor case-insensitive sorting would give.
+const EXTRAS: [&str; 6] = ["sub/é.nfo", "sub/b.nfo", "sub/z/x", "sub/B.nfo", "sub/a.nfo",
+                           "sub/a b.nfo"];
+/// The same, by the bytes of their paths.
+const SORTED: [&str; 6] = ["sub/B.nfo", "sub/a b.nfo", "sub/a.nfo", "sub/b.nfo", "sub/z/x",
+                           "sub/é.nfo"];
+
+/// The extra files `diff` lists, in its order.
+fn listed(output: &std::process::Output) -> Vec<String> {
+    stdout(output).lines()
+        .filter_map(|e| e.strip_prefix("-f  "))
+        .map(|e| e.rsplit_once(" [").map_or(e, |e| e.0).to_string())
+        .collect()
+}
+
+/// Extras of sizes and ages that order them differently, with ties to be broken by path.
+fn sized_fixture() -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    for (path, size, days) in [("sub/c.nfo", 3, 2), ("sub/a.nfo", 1, 3), ("sub/z/b.nfo", 3, 5),
+                               ("sub/d.nfo", 2, 3)] {
+        let path = fixture.write(path, &vec![b'x'; size]);
+        age(&path, Duration::from_secs(days * 24 * 3600));
+    }
+    fixture
+}
+
+fn fixture() -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    for extra in EXTRAS {
+        fixture.write(extra, b"x");
+    }
+    fixture
+}
+
+#[test]
+fn diff_output_is_identical_across_runs() {
+    let fixture = fixture();
+    let first = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(first.status.success(), "{}", stderr(&first));
+    let second = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert_eq!(stdout(&first), stdout(&second));
+}
+
+#[test]
+fn listings_are_sorted_byte_wise() {
+    let fixture = fixture();
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert_eq!(listed(&output), SORTED);
+}
+
+#[test]
+fn json_arrays_are_sorted_byte_wise() {
+    let fixture = fixture();
+    let output = fixture.run(&["diff", "--output", "json", "show.torrent", "Show"]);
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let paths = report["extra_files"].as_array().unwrap().iter()
+        .map(|e| e["path"].as_str().unwrap())
+        .collect::<Vec<&str>>();
+    assert_eq!(paths, SORTED);
+}
+
+#[test]
+fn unsorted_lists_the_same_entries() {
+    let fixture = fixture();
+    let output = fixture.run(&["diff", "--unsorted", "--output", "json", "show.torrent", "Show"]);
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut paths = report["extra_files"].as_array().unwrap().iter()
+        .map(|e| e["path"].as_str().unwrap())
+        .collect::<Vec<&str>>();
+    paths.sort();
+    assert_eq!(paths, SORTED);
+}
+
+#[test]
+fn sort_size_lists_the_smallest_first_and_ties_by_path() {
+    let fixture = sized_fixture();
+    let output = fixture.run(&["diff", "--sort", "size", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(listed(&output), ["sub/a.nfo", "sub/d.nfo", "sub/c.nfo", "sub/z/b.nfo"]);
+}
+
+#[test]
+fn sort_mtime_lists_the_oldest_first_and_ties_by_path() {
+    let fixture = sized_fixture();
+    let output = fixture.run(&["diff", "--sort", "mtime", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(listed(&output), ["sub/z/b.nfo", "sub/a.nfo", "sub/d.nfo", "sub/c.nfo"]);
+}
+
+#[test]
+fn reverse_turns_the_whole_order_around() {
+    let fixture = sized_fixture();
+    for (key, order) in [("path", ["sub/z/b.nfo", "sub/d.nfo", "sub/c.nfo", "sub/a.nfo"]),
+                         ("size", ["sub/z/b.nfo", "sub/c.nfo", "sub/d.nfo", "sub/a.nfo"]),
+                         ("mtime", ["sub/c.nfo", "sub/d.nfo", "sub/a.nfo", "sub/z/b.nfo"])] {
+        let output = fixture.run(&["diff", "--sort", key, "--reverse", "show.torrent", "Show"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(listed(&output), order, "--sort {}", key);
+    }
+}
+
+#[test]
+fn sorted_listings_are_identical_across_runs() {
+    let fixture = sized_fixture();
+    for key in ["size", "mtime"] {
+        let first = fixture.run(&["diff", "--sort", key, "show.torrent", "Show"]);
+        let second = fixture.run(&["diff", "--sort", key, "show.torrent", "Show"]);
+        assert_eq!(stdout(&first), stdout(&second), "--sort {}", key);
+    }
+}
+
+#[test]
+fn removal_goes_deepest_first_whatever_the_display_order() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/big.nfo", b"12345678");
+    fixture.write("sub/z/mid.nfo", b"1234");
+    fixture.write("sub/z/deep/x", b"x");
+    let output = fixture.run(&["clean", "-f", "-v", "--empty-dir", "--force-threshold",
+                               "--sort", "size", "--reverse", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(listed(&output), ["sub/big.nfo", "sub/z/mid.nfo", "sub/z/deep/x"]);
+    let all = stdout(&output) + &stderr(&output);
+    let removed = all.lines()
+        .filter_map(|e| e.strip_prefix("Removed file: ").or(e.strip_prefix("Removed directory: ")))
+        .map(|e| e.strip_prefix(&*fixture.content().to_string_lossy()).unwrap_or(e).to_string())
+        .collect::<Vec<String>>();
+    assert_eq!(removed, ["/sub/z/deep/x", "/sub/z/mid.nfo", "/sub/big.nfo", "/sub/z/deep",
+                         "/sub/z"], "{}", all);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use serde_json::Value;
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The error code of a `--output json` run, from the last line of its stdout.
+fn error_code(output: &std::process::Output) -> Value {
+    let stdout = stdout(output);
+    let error: Value = serde_json::from_str(stdout.lines().last().unwrap_or_default())
+        .expect("JSON error");
+    error["error"]["code"].clone()
+}
+
+#[test]
+fn corrupt_files_count_towards_the_threshold() {
+    // The placeholder piece hashes of the fixture make every file of the torrent corrupt
+    let fixture = Fixture::new(&FILES);
+    let output = fixture.run(&["clean", "-f", "--max-delete-ratio", "0.4", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = fixture.run(&["clean", "-f", "--verify", "--delete-corrupt", "--max-delete-ratio",
+                               "0.4", "--output", "json", "show.torrent", "Show"]);
+    assert_eq!(error_code(&output), "threshold_exceeded");
+    assert!(fixture.content().join("a.txt").exists());
+    assert!(fixture.content().join("sub/b.bin").exists());
+}
+
+#[test]
+fn empty_directories_alone_are_refused_without_the_torrent() {
+    let fixture = Fixture::new(&FILES);
+    fs::remove_dir_all(fixture.content()).expect("content removed");
+    fs::create_dir_all(fixture.content().join("sub/empty")).expect("empty directory created");
+    let output = fixture.run(&["clean", "-f", "--empty-dir", "--allow-empty-target", "--output",
+                               "json", "show.torrent", "Show"]);
+    assert_eq!(error_code(&output), "threshold_exceeded");
+    assert!(fixture.content().join("sub/empty").exists());
+
+    let output = fixture.run(&["clean", "-f", "--empty-dir", "--allow-empty-target",
+                               "--force-threshold", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fixture.content().join("sub/empty").exists());
+}
+
+#[test]
+fn nothing_to_remove_is_not_refused() {
+    let fixture = Fixture::new(&FILES);
+    fs::remove_dir_all(fixture.content()).expect("content removed");
+    fs::create_dir(fixture.content()).expect("directory created");
+    let output = fixture.run(&["clean", "-f", "--allow-empty-target", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::{stderr, stdout, Fixture};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path).and_then(|e| e.modified()).expect("modification time")
+}
+
+/// Finds the single file named `name` below `dir`.
+fn find(dir: &Path, name: &str) -> std::path::PathBuf {
+    let mut found = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).expect("directory read").flatten() {
+            match entry.file_type().expect("file type").is_dir() {
+                true => dirs.push(entry.path()),
+                false if entry.file_name() == name => found.push(entry.path()),
+                false => {}
+            }
+        }
+    }
+    assert_eq!(found.len(), 1, "{:?}", found);
+    found.remove(0)
+}
+
+#[test]
+fn move_to_keeps_the_modification_time() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let before = modified(&extra);
+    let output = fixture.run(&["clean", "-f", "--move-to", "moved", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let moved = find(&fixture.path("moved"), "extra.nfo");
+    assert_eq!(modified(&moved), before, "{}", stdout(&output));
+}
+
+#[test]
+fn touch_moved_gives_the_current_time() {
+    let fixture = Fixture::new(&FILES);
+    fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["clean", "-f", "--move-to", "moved", "--touch-moved",
+                               "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let moved = find(&fixture.path("moved"), "extra.nfo");
+    let age = SystemTime::now().duration_since(modified(&moved)).unwrap_or_default();
+    assert!(age < Duration::from_secs(3600), "{:?}", age);
+}
+
+#[test]
+fn stage_and_restore_keep_the_modification_time() {
+    let fixture = Fixture::new(&FILES);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let before = modified(&extra);
+    let output = fixture.run(&["clean", "-f", "--stage", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(modified(&find(&fixture.content(), "extra.nfo")), before);
+
+    let output = fixture.run(&["restore", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(modified(&extra), before);
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::{stderr, stdout, torrent, Bencode, Fixture, PIECE_LENGTH};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// Metainfo of `Show` whose files have the path segments and lengths of `files`, for segments
+/// [`torrent`] cannot spell, such as absolute ones.
+fn crafted(files: &[(&[&str], i64)]) -> Vec<u8> {
+    let files = files.iter().map(|(segs, length)| Bencode::dict([
+        ("length", Bencode::Int(*length)),
+        ("path", Bencode::List(segs.iter().copied().map(Bencode::str).collect())),
+    ]));
+    let info = Bencode::dict([
+        ("name", Bencode::str("Show")),
+        ("piece length", Bencode::Int(PIECE_LENGTH)),
+        ("pieces", Bencode::Bytes(vec![0; 20])),
+        ("files", Bencode::List(files.collect())),
+    ]);
+    Bencode::dict([("info", info)]).encode()
+}
+
+/// The fixture with its torrent replaced by one with a file going up out of the directory and an
+/// absolute one, besides its own two.
+fn fixture() -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    let files: [(&[&str], i64); 4] = [(&["a.txt"], 5), (&["..", "evil"], 10),
+                                      (&["sub", "b.bin"], 10), (&["/etc", "passwd"], 1)];
+    fs::write(fixture.torrent(), crafted(&files)).expect("torrent written");
+    fs::write(fixture.path("evil"), b"0123456789").expect("file written");
+    fixture.write("sub/extra.nfo", b"x");
+    fixture
+}
+
+#[test]
+fn unsafe_paths_are_reported_by_index_and_left_out() {
+    let fixture = fixture();
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("file 1 of the torrent, \"../evil\", goes up out of its directory; \
+                             leaving it out"), "{}", stderr);
+    assert!(stderr.contains("file 3 of the torrent, \"/etc/passwd\", is absolute; leaving it out"),
+            "{}", stderr);
+    assert!(!stderr.contains("file 0 ") && !stderr.contains("file 2 "), "{}", stderr);
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Torrent expects:  15 B in 2 files"), "{}", stdout);
+    assert!(stdout.contains("sub/extra.nfo"), "{}", stdout);
+}
+
+#[test]
+fn dot_and_empty_segments_are_dropped() {
+    let fixture = Fixture::new(&FILES);
+    fs::write(fixture.torrent(), torrent("Show", &[("./a.txt", b"hello"),
+                                                   ("sub//b.bin", b"0123456789")]))
+        .expect("torrent written");
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("leaving it out"), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Directory is clean: all 2 files match"),
+            "{}", stdout(&output));
+}
+
+#[test]
+fn a_path_of_nothing_but_dots_is_left_out() {
+    let fixture = Fixture::new(&FILES);
+    fs::write(fixture.torrent(), torrent("Show", &[("a.txt", b"hello"), ("./", b"x")]))
+        .expect("torrent written");
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("file 1 of the torrent, \"./\", is empty"),
+            "{}", stderr(&output));
+}
+
+#[test]
+fn clean_is_refused_without_the_override() {
+    let fixture = fixture();
+    let output = fixture.run(&["clean", "-f", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Torrent has a path leading outside of the directory: file 1 \
+        of the torrent, \"../evil\", goes up out of its directory (and 1 more); pass \
+        --allow-unsafe-paths"), "{}", stderr(&output));
+    assert!(fixture.content().join("sub/extra.nfo").exists());
+
+    let output = fixture.run(&["--output", "json", "clean", "-f", "show.torrent", "Show"]);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stdout(&output).contains("\"code\":\"unsafe_paths\""), "{}", stdout(&output));
+}
+
+#[test]
+fn clean_goes_on_without_those_files_with_the_override() {
+    let fixture = fixture();
+    let output = fixture.run(&["clean", "-f", "--allow-unsafe-paths", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!fixture.content().join("sub/extra.nfo").exists());
+    assert!(fixture.content().join("a.txt").exists());
+    assert!(fixture.path("evil").exists());
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{stderr, stdout, torrent_v2, Fixture};
+use std::fs;
+
+const FILES: [(&str, &[u8]); 2] = [("a.txt", b"hello"), ("sub/b.bin", b"0123456789")];
+
+/// The fixture with its torrent rewritten as a v2 one of `files`, hybrid with `v1`.
+fn fixture(files: &[(&str, &[u8])], v1: Option<&[(&str, &[u8])]>) -> Fixture {
+    let fixture = Fixture::new(&FILES);
+    fs::write(fixture.torrent(), torrent_v2("Show", files, v1)).expect("torrent written");
+    fixture
+}
+
+#[test]
+fn v2_torrent_is_read_from_its_file_tree() {
+    let fixture = fixture(&FILES, None);
+    let extra = fixture.write("sub/extra.nfo", b"x");
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Found matching:   15 B in 2 files"), "{}", stdout);
+    assert!(stdout.contains("-f  sub/extra.nfo"), "{}", stdout);
+
+    let output = fixture.run(&["clean", "-f", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!extra.exists());
+    assert!(fixture.content().join("sub/b.bin").exists());
+}
+
+#[test]
+fn v2_torrent_reports_missing_files() {
+    let fixture = fixture(&[("a.txt", b"hello"), ("sub/b.bin", b"0123456789"),
+                            ("sub/c.bin", b"abc")], None);
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("+   sub/c.bin"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("1 of 3 files missing"), "{}", stdout(&output));
+}
+
+#[test]
+fn consistent_hybrid_torrent_does_not_warn() {
+    let fixture = fixture(&FILES, Some(&FILES));
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("disagree"), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Found matching:   15 B in 2 files"), "{}", stdout(&output));
+}
+
+#[test]
+fn disagreeing_hybrid_torrent_goes_by_the_file_tree() {
+    let fixture = fixture(&FILES, Some(&[("a.txt", b"hello"), ("sub/old.bin", b"0123456789")]));
+    fixture.write("sub/old.bin", b"0123456789");
+    let output = fixture.run(&["diff", "show.torrent", "Show"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let all = stdout(&output) + &stderr(&output);
+    assert!(all.contains("the v1 and v2 file lists of this hybrid torrent disagree; using the v2 \
+                          file tree"), "{}", all);
+    assert!(all.contains("-f  sub/old.bin"), "{}", all);
+}